@@ -0,0 +1,250 @@
+//! Indicators of compromise seen over a single day of audit logs, for sharing with threat
+//! intelligence platforms.
+
+use std::{collections::BTreeMap, net::IpAddr};
+
+use pisshoff_types::audit::{AuditLog, AuditLogAction};
+use time::{Date, OffsetDateTime, UtcOffset};
+
+#[derive(Debug)]
+pub struct Indicators {
+    /// The UTC day indicators are collected from.
+    pub date: Date,
+    /// Every address that connected, other than those in private ranges.
+    pub addresses: BTreeMap<IpAddr, Sighting>,
+    /// URLs clients tried to download from with `wget`, `curl` and the like.
+    pub urls: BTreeMap<Box<str>, Sighting>,
+    /// The SHA-256 of each file written or executed.
+    pub files: BTreeMap<Box<str>, File>,
+    /// Public keys added to `authorized_keys` for persistence, by their fingerprint.
+    pub ssh_keys: BTreeMap<Box<str>, SshKey>,
+}
+
+/// When an indicator was first seen on the day, and how many times it was seen.
+#[derive(Debug)]
+pub struct Sighting {
+    pub first_seen: OffsetDateTime,
+    pub count: u64,
+}
+
+impl Sighting {
+    fn new(ts: OffsetDateTime) -> Self {
+        Self {
+            first_seen: ts,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, ts: OffsetDateTime) {
+        self.first_seen = self.first_seen.min(ts);
+        self.count += 1;
+    }
+}
+
+#[derive(Debug)]
+pub struct File {
+    pub sighting: Sighting,
+    /// The path it was first written to or run from.
+    pub path: Box<str>,
+    pub size: u64,
+}
+
+#[derive(Debug)]
+pub struct SshKey {
+    pub sighting: Sighting,
+    /// The type of the key, ie. `ssh-ed25519`.
+    pub key_type: Box<str>,
+    /// The comment trailing the key, often naming the botnet that added it.
+    pub comment: Option<Box<str>>,
+}
+
+impl Indicators {
+    pub fn new(date: Date) -> Self {
+        Self {
+            date,
+            addresses: BTreeMap::new(),
+            urls: BTreeMap::new(),
+            files: BTreeMap::new(),
+            ssh_keys: BTreeMap::new(),
+        }
+    }
+
+    /// Collects the indicators from `log` if its connection started on the day being exported.
+    pub fn add(&mut self, log: &AuditLog) {
+        let ts = log.ts.to_offset(UtcOffset::UTC);
+
+        if ts.date() != self.date {
+            return;
+        }
+
+        if let Some(address) = log
+            .peer_address
+            .map(|v| v.ip().to_canonical())
+            .filter(|v| is_public(*v))
+        {
+            self.addresses
+                .entry(address)
+                .or_insert_with(|| Sighting::new(ts))
+                .add(ts);
+        }
+
+        for event in &log.events {
+            let ts = ts + event.start_offset;
+
+            match &event.action {
+                AuditLogAction::DownloadAttempt(event) => {
+                    self.urls
+                        .entry(event.url.clone())
+                        .or_insert_with(|| Sighting::new(ts))
+                        .add(ts);
+                }
+                AuditLogAction::WriteFile(event) => {
+                    if let Some(sha256) = &event.sha256 {
+                        self.file(sha256, &event.path, event.size, ts);
+                    }
+                }
+                AuditLogAction::BinaryExecution(event) => {
+                    self.file(&event.sha256, &event.path, event.size, ts);
+                }
+                AuditLogAction::AuthorizedKeyAdded(event) => {
+                    // keys that couldn't be decoded have no fingerprint to match them on
+                    let Some(fingerprint) = &event.fingerprint else {
+                        continue;
+                    };
+
+                    self.ssh_keys
+                        .entry(fingerprint.clone())
+                        .or_insert_with(|| SshKey {
+                            sighting: Sighting::new(ts),
+                            key_type: event.key_type.clone(),
+                            comment: event.comment.clone(),
+                        })
+                        .sighting
+                        .add(ts);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn file(&mut self, sha256: &str, path: &str, size: u64, ts: OffsetDateTime) {
+        self.files
+            .entry(sha256.into())
+            .or_insert_with(|| File {
+                sighting: Sighting::new(ts),
+                path: path.into(),
+                size,
+            })
+            .sighting
+            .add(ts);
+    }
+}
+
+/// Whether `address` is worth sharing, rather than one in a private range that could only have
+/// been a test.
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(v) => !(v.is_private() || v.is_loopback() || v.is_link_local()),
+        IpAddr::V6(v) => {
+            let unique_local = v.segments()[0] & 0xfe00 == 0xfc00;
+            let link_local = v.segments()[0] & 0xffc0 == 0xfe80;
+            !(v.is_loopback() || unique_local || link_local)
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use pisshoff_types::audit::{
+        AuditLogAction, AuthorizedKeyAddedEvent, BinaryExecutionEvent, DownloadAttemptEvent,
+    };
+    use time::macros::datetime;
+
+    use crate::{
+        indicators::Indicators,
+        summary::test::{log, login, write},
+    };
+
+    pub fn download(url: &str) -> AuditLogAction {
+        AuditLogAction::DownloadAttempt(DownloadAttemptEvent {
+            command: "wget".into(),
+            url: url.into(),
+            destination: None,
+            user_agent_flag: None,
+        })
+    }
+
+    pub fn indicators() -> Indicators {
+        let mut indicators = Indicators::new(datetime!(2024-05-02 0:00 UTC).date());
+
+        // another day, so left out
+        indicators.add(&log(
+            1,
+            datetime!(2024-05-01 23:59 UTC),
+            "192.0.2.1:1000",
+            vec![download("http://198.51.100.1/old.sh")],
+        ));
+
+        indicators.add(&log(
+            2,
+            datetime!(2024-05-02 01:00 UTC),
+            "192.0.2.2:1000",
+            vec![
+                login("root", "root"),
+                download("http://198.51.100.1/x.sh?a='b'"),
+                write("/tmp/xmrig", "bbbb"),
+                AuditLogAction::BinaryExecution(BinaryExecutionEvent {
+                    path: "/tmp/xmrig".into(),
+                    sha256: "bbbb".into(),
+                    size: 7,
+                    args: Box::default(),
+                }),
+                AuditLogAction::AuthorizedKeyAdded(AuthorizedKeyAddedEvent {
+                    path: "/root/.ssh/authorized_keys".into(),
+                    key_type: "ssh-rsa".into(),
+                    key: "AAAAB3NzaC1yc2E".into(),
+                    fingerprint: Some("SHA256:mVPwvezndPv/ARoIadVY98vAC0g+P/5633yTC4d/wXE".into()),
+                    comment: Some("mdrfckr".into()),
+                }),
+            ],
+        ));
+
+        indicators.add(&log(
+            3,
+            datetime!(2024-05-02 02:00 UTC),
+            "192.0.2.2:1001",
+            vec![download("http://198.51.100.1/x.sh?a='b'")],
+        ));
+
+        indicators.add(&log(
+            4,
+            datetime!(2024-05-02 03:00 UTC),
+            "10.0.0.1:1000",
+            vec![],
+        ));
+
+        indicators
+    }
+
+    #[test]
+    fn collects_the_day() {
+        let indicators = indicators();
+
+        let addresses: Vec<_> = indicators
+            .addresses
+            .iter()
+            .map(|(address, sighting)| (address.to_string(), sighting.count))
+            .collect();
+        assert_eq!(addresses, [("192.0.2.2".to_string(), 2)]);
+
+        let urls: Vec<_> = indicators
+            .urls
+            .iter()
+            .map(|(url, sighting)| (&**url, sighting.count))
+            .collect();
+        assert_eq!(urls, [("http://198.51.100.1/x.sh?a='b'", 2)]);
+
+        assert_eq!(indicators.files["bbbb"].sighting.count, 2);
+        assert_eq!(indicators.ssh_keys.len(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+#![deny(clippy::pedantic)]
+#![allow(clippy::module_name_repetitions)]
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
+use flate2::read::MultiGzDecoder;
+use pisshoff_types::{audit::AuditLog, upgrade};
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+use crate::{
+    indicators::Indicators,
+    render::{render, Markup},
+    summary::Summary,
+};
+
+mod indicators;
+mod misp;
+mod render;
+mod stix;
+mod summary;
+
+/// Summarises a day of pisshoff audit logs as Markdown or HTML, for emailing or posting to a chat
+/// webhook, or exports the indicators seen that day as a STIX bundle or MISP event.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Audit log files to read, gzip compressed or not, or `-` for stdin. Include the days before
+    /// the one being reported on so payloads seen on them aren't reported as new.
+    #[arg(required = true, value_name = "FILE")]
+    inputs: Vec<PathBuf>,
+    /// The UTC day to report on, as `YYYY-MM-DD`. Defaults to yesterday.
+    #[arg(short, long, value_parser = parse_date)]
+    date: Option<Date>,
+    #[arg(short, long, value_enum, default_value_t)]
+    format: Format,
+    /// The number of addresses, credentials and sessions to list in Markdown and HTML reports.
+    #[arg(short, long, default_value_t = 10)]
+    top: usize,
+    /// Writes the report to a file rather than stdout.
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Adds the MISP event to the instance at this URL through its API, rather than writing it
+    /// out.
+    #[arg(long, requires = "misp_key", value_name = "URL")]
+    misp_url: Option<String>,
+    /// API key for `--misp-url`.
+    #[arg(long, env, hide_env_values = true, value_name = "KEY")]
+    misp_key: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Format {
+    #[default]
+    Markdown,
+    Html,
+    /// A STIX 2.1 bundle of the indicators seen.
+    Stix,
+    /// A MISP event with the indicators seen as its attributes.
+    Misp,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Failed to run {}: {e}", env!("CARGO_CRATE_NAME"));
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    anyhow::ensure!(
+        args.misp_url.is_none() || args.format == Format::Misp,
+        "--misp-url can only be used with --format misp"
+    );
+
+    let date = args
+        .date
+        .unwrap_or_else(|| (OffsetDateTime::now_utc() - Duration::DAY).date());
+    let mut summary = Summary::new(date);
+    let mut indicators = Indicators::new(date);
+
+    for path in &args.inputs {
+        let invalid = read(path, |log| {
+            summary.add(log);
+            indicators.add(log);
+        })?;
+
+        if invalid > 0 {
+            eprintln!("Skipped {invalid} invalid lines in {}", path.display());
+        }
+    }
+
+    let report = match args.format {
+        Format::Markdown => render(&summary, args.top, Markup::Markdown),
+        Format::Html => render(&summary, args.top, Markup::Html),
+        Format::Stix => {
+            serde_json::to_string_pretty(&stix::bundle(&indicators, OffsetDateTime::now_utc()))?
+        }
+        Format::Misp => {
+            let event = misp::event(&indicators);
+
+            if let (Some(url), Some(key)) = (&args.misp_url, &args.misp_key) {
+                return tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?
+                    .block_on(misp::push(url, key, &event));
+            }
+
+            serde_json::to_string_pretty(&event)?
+        }
+    };
+
+    match &args.output {
+        Some(path) => std::fs::write(path, report)?,
+        None => std::io::stdout().write_all(report.as_bytes())?,
+    }
+
+    Ok(())
+}
+
+/// Passes every log in the file at `path` to `add`, returning the number of lines that couldn't be
+/// parsed.
+fn read(path: &Path, mut add: impl FnMut(&AuditLog)) -> anyhow::Result<u64> {
+    let input: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(File::open(path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?)
+    };
+
+    let mut input = BufReader::new(input);
+
+    // rotated logs are compressed, but so might the active one be if the server is compressing
+    // as it writes
+    let input: Box<dyn BufRead> = match input.fill_buf()? {
+        [0x1f, 0x8b, ..] => Box::new(BufReader::new(MultiGzDecoder::new(input))),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => anyhow::bail!(
+            "{} is zstd compressed, decompress it with `zstdcat` and pass it in on stdin",
+            path.display()
+        ),
+        _ => Box::new(input),
+    };
+
+    let mut invalid = 0;
+
+    for line in input.lines() {
+        let line = line?;
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match upgrade::parse(&line) {
+            Ok(log) => add(&log),
+            Err(_) => invalid += 1,
+        }
+    }
+
+    Ok(invalid)
+}
+
+fn parse_date(value: &str) -> Result<Date, time::error::Parse> {
+    Date::parse(value, format_description!("[year]-[month]-[day]"))
+}
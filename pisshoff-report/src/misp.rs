@@ -0,0 +1,156 @@
+//! Exports [`Indicators`] as a MISP event, with an attribute for each address, URL, file and SSH
+//! key, and adds it to a MISP instance through its API.
+
+use std::time::Duration;
+
+use pisshoff_http as http;
+use serde_json::{json, Value};
+
+use crate::indicators::Indicators;
+
+/// How long MISP has to accept the event before it's given up on.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn event(indicators: &Indicators) -> Value {
+    let mut attributes = Vec::new();
+
+    let mut attribute = |ty: &str, category: &str, value: &str, comment: String| {
+        attributes.push(json!({
+            "type": ty,
+            "category": category,
+            "value": value,
+            "comment": comment,
+            "to_ids": true,
+        }));
+    };
+
+    for (address, sighting) in &indicators.addresses {
+        attribute(
+            "ip-src",
+            "Network activity",
+            &address.to_string(),
+            format!("Connected {} times", sighting.count),
+        );
+    }
+
+    for (url, sighting) in &indicators.urls {
+        attribute(
+            "url",
+            "Network activity",
+            url,
+            format!("Downloaded {} times", sighting.count),
+        );
+    }
+
+    for (sha256, file) in &indicators.files {
+        attribute(
+            "sha256",
+            "Payload delivery",
+            sha256,
+            format!("{} bytes, written to or run from {}", file.size, file.path),
+        );
+    }
+
+    for (fingerprint, key) in &indicators.ssh_keys {
+        attribute(
+            "ssh-fingerprint",
+            "Persistence mechanism",
+            fingerprint,
+            format!(
+                "{} key added to authorized_keys{}",
+                key.key_type,
+                key.comment
+                    .as_deref()
+                    .map(|v| format!(", commented {v}"))
+                    .unwrap_or_default()
+            ),
+        );
+    }
+
+    json!({
+        "Event": {
+            "info": format!("pisshoff SSH honeypot indicators for {}", indicators.date),
+            "date": indicators.date.to_string(),
+            // undefined threat level, completed analysis, this organisation only
+            "threat_level_id": "4",
+            "analysis": "2",
+            "distribution": "0",
+            "Tag": [{ "name": "pisshoff" }],
+            "Attribute": attributes,
+        }
+    })
+}
+
+/// Adds `event` to the MISP instance at `url` through its API.
+pub async fn push(url: &str, key: &str, event: &Value) -> anyhow::Result<()> {
+    let url = format!("{}/events/add", url.trim_end_matches('/'));
+
+    let status = tokio::time::timeout(
+        TIMEOUT,
+        http::Client::shared().request(
+            "POST",
+            &url,
+            &[
+                ("Authorization", key),
+                ("Accept", "application/json"),
+                ("Content-Type", "application/json"),
+            ],
+            &event.to_string(),
+        ),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("timed out waiting for MISP"))??;
+
+    anyhow::ensure!(http::is_success(&status), "MISP refused event: {status}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use crate::{
+        indicators::test::indicators,
+        misp::{event, push},
+    };
+
+    #[test]
+    fn exports_event() {
+        insta::assert_snapshot!(serde_json::to_string_pretty(&event(&indicators())).unwrap());
+    }
+
+    #[tokio::test]
+    async fn pushes_event() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/misp/", listener.local_addr().unwrap());
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"}}") {
+                let mut buf = [0; 1024];
+                let len = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        push(&url, "secret", &event(&indicators())).await.unwrap();
+        let request = server.join().unwrap();
+
+        assert!(
+            request.starts_with("POST /misp/events/add HTTP/1.1\r\n"),
+            "{request}"
+        );
+        assert!(
+            request.contains("\r\nAuthorization: secret\r\n"),
+            "{request}"
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Renders a [`Summary`] as Markdown or HTML. Usernames, passwords and paths are chosen by
+//! whoever connected, so everything taken from the logs is escaped before it's written out.
+
+use std::fmt::Write;
+
+use time::macros::format_description;
+
+use crate::summary::Summary;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Markup {
+    Markdown,
+    Html,
+}
+
+/// A rendered section of the report, as a heading and a table of already escaped cells.
+struct Table {
+    heading: &'static str,
+    columns: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+pub fn render(summary: &Summary, top: usize, format: Markup) -> String {
+    let escape = match format {
+        Markup::Markdown => markdown_escape,
+        Markup::Html => html_escape,
+    };
+    let code = |value: &str| match format {
+        Markup::Markdown => markdown_code(value),
+        Markup::Html => format!("<code>{}</code>", html_escape(value)),
+    };
+
+    let title = format!("pisshoff report for {}", summary.date);
+    let totals = [
+        (
+            "Connections",
+            format!(
+                "{} from {} addresses",
+                summary.connections,
+                summary.unique_addresses()
+            ),
+        ),
+        ("Logged in", summary.logged_in.to_string()),
+        ("Login attempts", summary.login_attempts.to_string()),
+        ("Commands executed", summary.commands.to_string()),
+        ("Files written", summary.files_written.to_string()),
+    ];
+
+    let tables = [
+        Table {
+            heading: "Top addresses",
+            columns: &["Address", "Country", "Connections"],
+            rows: summary
+                .top_addresses(top)
+                .into_iter()
+                .map(|(ip, address)| {
+                    vec![
+                        ip.to_string(),
+                        address.country.as_deref().map(escape).unwrap_or_default(),
+                        address.connections.to_string(),
+                    ]
+                })
+                .collect(),
+        },
+        Table {
+            heading: "Top credentials",
+            columns: &["Username", "Password", "Attempts"],
+            rows: summary
+                .top_credentials(top)
+                .into_iter()
+                .map(|(username, password, count)| {
+                    vec![code(username), code(password), count.to_string()]
+                })
+                .collect(),
+        },
+        Table {
+            heading: "New payloads",
+            columns: &["SHA-256", "Path", "Size", "Connections"],
+            rows: summary
+                .new_payloads()
+                .into_iter()
+                .map(|(sha256, payload)| {
+                    vec![
+                        code(sha256),
+                        code(&payload.path),
+                        payload.size.to_string(),
+                        payload.connections.len().to_string(),
+                    ]
+                })
+                .collect(),
+        },
+        Table {
+            heading: "Notable sessions",
+            columns: &["Started", "Address", "Connection", "Commands", "Tags"],
+            rows: summary
+                .notable_sessions(top)
+                .into_iter()
+                .map(|session| {
+                    vec![
+                        session
+                            .ts
+                            .format(format_description!("[hour]:[minute]:[second]"))
+                            .unwrap(),
+                        session.address.map(|v| v.to_string()).unwrap_or_default(),
+                        code(&session.connection_id.to_string()),
+                        session.commands.to_string(),
+                        session
+                            .tags
+                            .iter()
+                            .map(|v| escape(v))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    ]
+                })
+                .collect(),
+        },
+    ];
+
+    match format {
+        Markup::Markdown => markdown(&title, &totals, &tables),
+        Markup::Html => html(&title, &totals, &tables),
+    }
+}
+
+fn markdown(title: &str, totals: &[(&str, String)], tables: &[Table]) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "# {title}\n").unwrap();
+
+    for (name, value) in totals {
+        writeln!(out, "- **{name}:** {value}").unwrap();
+    }
+
+    for table in tables {
+        writeln!(out, "\n## {}\n", table.heading).unwrap();
+
+        if table.rows.is_empty() {
+            writeln!(out, "None.").unwrap();
+            continue;
+        }
+
+        writeln!(out, "| {} |", table.columns.join(" | ")).unwrap();
+        writeln!(out, "|{}", "---|".repeat(table.columns.len())).unwrap();
+
+        for row in &table.rows {
+            writeln!(out, "| {} |", row.join(" | ")).unwrap();
+        }
+    }
+
+    out
+}
+
+fn html(title: &str, totals: &[(&str, String)], tables: &[Table]) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n<ul>"
+    )
+    .unwrap();
+
+    for (name, value) in totals {
+        writeln!(out, "<li><strong>{name}:</strong> {value}</li>").unwrap();
+    }
+
+    writeln!(out, "</ul>").unwrap();
+
+    for table in tables {
+        writeln!(out, "<h2>{}</h2>", table.heading).unwrap();
+
+        if table.rows.is_empty() {
+            writeln!(out, "<p>None.</p>").unwrap();
+            continue;
+        }
+
+        writeln!(out, "<table>\n<tr>").unwrap();
+        for column in table.columns {
+            writeln!(out, "<th>{column}</th>").unwrap();
+        }
+        writeln!(out, "</tr>").unwrap();
+
+        for row in &table.rows {
+            writeln!(out, "<tr>").unwrap();
+            for cell in row {
+                writeln!(out, "<td>{cell}</td>").unwrap();
+            }
+            writeln!(out, "</tr>").unwrap();
+        }
+
+        writeln!(out, "</table>").unwrap();
+    }
+
+    writeln!(out, "</body>\n</html>").unwrap();
+
+    out
+}
+
+/// Escapes anything that would otherwise be interpreted as Markdown, or break out of a table
+/// cell.
+fn markdown_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' | '!' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_control() => out.extend(c.escape_default()),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Wraps `value` in a code span fenced with more backticks than it contains, so it's shown exactly
+/// as it was sent.
+fn markdown_code(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '|' => escaped.push_str("\\|"),
+            c if c.is_control() => escaped.extend(c.escape_default()),
+            c => escaped.push(c),
+        }
+    }
+
+    // an empty code span isn't one, so the password is shown as a space instead
+    if escaped.is_empty() {
+        return "` `".to_string();
+    }
+
+    let longest = escaped
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest + 1);
+    let pad = if escaped.starts_with('`') || escaped.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+
+    format!("{fence}{pad}{escaped}{pad}{fence}")
+}
+
+fn html_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        render::{markdown_code, markdown_escape, render, Markup},
+        summary::test::summary,
+    };
+
+    #[test]
+    fn markdown() {
+        insta::assert_snapshot!(render(&summary(), 10, Markup::Markdown));
+    }
+
+    #[test]
+    fn html() {
+        insta::assert_snapshot!(render(&summary(), 10, Markup::Html));
+    }
+
+    #[test]
+    fn escapes_markdown() {
+        assert_eq!(markdown_escape("a_b|*c*\n"), "a\\_b\\|\\*c\\*\\n");
+        assert_eq!(markdown_code("x|y"), "`x\\|y`");
+        assert_eq!(markdown_code("a`b"), "``a`b``");
+        assert_eq!(markdown_code("`a``"), "``` `a`` ```");
+        assert_eq!(markdown_code(""), "` `");
+    }
+}
@@ -0,0 +1,138 @@
+//! Exports [`Indicators`] as a STIX 2.1 bundle, an indicator for each address, URL, file and SSH
+//! key, all created by an identity for the honeypot.
+
+use std::net::IpAddr;
+
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::indicators::{Indicators, Sighting};
+
+/// The namespace STIX 2.1 derives deterministic identifiers from.
+const STIX_NAMESPACE: Uuid = Uuid::from_u128(0x00ab_edb4_aa42_466c_9c01_fed2_3315_a9b7);
+
+pub fn bundle(indicators: &Indicators, now: OffsetDateTime) -> Value {
+    let now = timestamp(now);
+
+    let identity = json!({
+        "type": "identity",
+        "spec_version": "2.1",
+        "id": id("identity", "pisshoff"),
+        "created": now,
+        "modified": now,
+        "name": "pisshoff",
+        "description": "SSH honeypot",
+        "identity_class": "system",
+    });
+
+    let indicator = |pattern: String, name: String, description: String, sighting: &Sighting| {
+        // identifiers are derived from the pattern, so exporting the same indicator again
+        // updates it rather than duplicating it
+        json!({
+            "type": "indicator",
+            "spec_version": "2.1",
+            "id": id("indicator", &pattern),
+            "created_by_ref": identity["id"],
+            "created": now,
+            "modified": now,
+            "name": name,
+            "description": description,
+            "indicator_types": ["malicious-activity"],
+            "pattern": pattern,
+            "pattern_type": "stix",
+            "valid_from": timestamp(sighting.first_seen),
+            "labels": ["honeypot"],
+        })
+    };
+
+    let mut objects = vec![identity.clone()];
+
+    for (address, sighting) in &indicators.addresses {
+        let object = match address {
+            IpAddr::V4(_) => "ipv4-addr",
+            IpAddr::V6(_) => "ipv6-addr",
+        };
+
+        objects.push(indicator(
+            format!("[{object}:value = '{address}']"),
+            format!("SSH honeypot client {address}"),
+            format!("Connected to the honeypot {} times", sighting.count),
+            sighting,
+        ));
+    }
+
+    for (url, sighting) in &indicators.urls {
+        objects.push(indicator(
+            format!("[url:value = '{}']", escape(url)),
+            format!("Download from {url}"),
+            format!("Downloaded by honeypot clients {} times", sighting.count),
+            sighting,
+        ));
+    }
+
+    for (sha256, file) in &indicators.files {
+        objects.push(indicator(
+            format!("[file:hashes.'SHA-256' = '{}']", escape(sha256)),
+            format!("File {sha256}"),
+            format!(
+                "{} byte file written to or run from {} on the honeypot",
+                file.size, file.path
+            ),
+            &file.sighting,
+        ));
+    }
+
+    // STIX has no object for SSH keys, so they're matched with a custom one
+    for (fingerprint, key) in &indicators.ssh_keys {
+        objects.push(indicator(
+            format!("[x-ssh-key:fingerprint = '{}']", escape(fingerprint)),
+            format!("SSH key {fingerprint}"),
+            format!(
+                "{} key{} added to authorized_keys on the honeypot",
+                key.key_type,
+                key.comment
+                    .as_deref()
+                    .map(|v| format!(" ({v})"))
+                    .unwrap_or_default()
+            ),
+            &key.sighting,
+        ));
+    }
+
+    json!({
+        "type": "bundle",
+        "id": format!("bundle--{}", Uuid::new_v4()),
+        "objects": objects,
+    })
+}
+
+fn id(ty: &str, value: &str) -> String {
+    format!("{ty}--{}", Uuid::new_v5(&STIX_NAMESPACE, value.as_bytes()))
+}
+
+fn timestamp(ts: OffsetDateTime) -> String {
+    ts.format(&Rfc3339).unwrap_or_default()
+}
+
+/// Escapes a string for a quoted value in a STIX pattern.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use crate::{indicators::test::indicators, stix::bundle};
+
+    #[test]
+    fn exports_bundle() {
+        let mut bundle = bundle(&indicators(), datetime!(2024-05-03 06:00 UTC));
+
+        assert!(bundle["id"].as_str().unwrap().starts_with("bundle--"));
+        bundle["id"] = "bundle--random".into();
+
+        insta::assert_snapshot!(serde_json::to_string_pretty(&bundle).unwrap());
+    }
+}
@@ -0,0 +1,396 @@
+//! Totals for a single day of audit logs, built up one log at a time.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::IpAddr,
+};
+
+use pisshoff_types::audit::{AuditLog, AuditLogAction, LoginAttemptEvent};
+use time::{Date, OffsetDateTime, UtcOffset};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct Summary {
+    /// The UTC day being summarised.
+    pub date: Date,
+    pub connections: u64,
+    /// Connections that got past authentication.
+    pub logged_in: u64,
+    pub login_attempts: u64,
+    pub commands: u64,
+    pub files_written: u64,
+    addresses: HashMap<IpAddr, Address>,
+    credentials: HashMap<(Box<str>, Box<str>), u64>,
+    payloads: HashMap<Box<str>, Payload>,
+    sessions: Vec<Session>,
+}
+
+#[derive(Debug, Default)]
+pub struct Address {
+    pub connections: u64,
+    /// Where the address is registered, as looked up by the server.
+    pub country: Option<Box<str>>,
+}
+
+/// A file uploaded to, or executed on, the honeypot, keyed by its SHA-256.
+#[derive(Debug)]
+pub struct Payload {
+    pub first_seen: OffsetDateTime,
+    /// The path it was first written to or run from.
+    pub path: Box<str>,
+    pub size: u64,
+    /// Connections on the day being summarised that wrote or ran the payload.
+    pub connections: BTreeSet<Uuid>,
+}
+
+/// A connection worth a closer look, having been tagged by the server as doing something
+/// interesting, ie. running a miner or touching a honeytoken.
+#[derive(Debug)]
+pub struct Session {
+    pub connection_id: Uuid,
+    pub ts: OffsetDateTime,
+    pub address: Option<IpAddr>,
+    pub commands: u64,
+    pub tags: Vec<Box<str>>,
+}
+
+impl Summary {
+    pub fn new(date: Date) -> Self {
+        Self {
+            date,
+            connections: 0,
+            logged_in: 0,
+            login_attempts: 0,
+            commands: 0,
+            files_written: 0,
+            addresses: HashMap::new(),
+            credentials: HashMap::new(),
+            payloads: HashMap::new(),
+            sessions: Vec::new(),
+        }
+    }
+
+    /// Counts `log` towards the summary if its connection started on the day being summarised.
+    /// Logs from any other day are only used to tell which payloads have been seen before, so
+    /// previous days' logs should be passed in too for "new" to mean anything.
+    pub fn add(&mut self, log: &AuditLog) {
+        let ts = log.ts.to_offset(UtcOffset::UTC);
+        let today = ts.date() == self.date;
+
+        for event in &log.events {
+            let payload = match &event.action {
+                AuditLogAction::WriteFile(event) => event
+                    .sha256
+                    .as_deref()
+                    .map(|sha256| (sha256, &event.path, event.size)),
+                AuditLogAction::BinaryExecution(event) => {
+                    Some((&*event.sha256, &event.path, event.size))
+                }
+                _ => None,
+            };
+
+            if let Some((sha256, path, size)) = payload {
+                let payload = self
+                    .payloads
+                    .entry(sha256.into())
+                    .or_insert_with(|| Payload {
+                        first_seen: ts,
+                        path: path.clone(),
+                        size,
+                        connections: BTreeSet::new(),
+                    });
+
+                if ts < payload.first_seen {
+                    payload.first_seen = ts;
+                    payload.path = path.clone();
+                }
+
+                if today {
+                    payload.connections.insert(log.connection_id);
+                }
+            }
+        }
+
+        if !today {
+            return;
+        }
+
+        self.connections += 1;
+
+        if let Some(peer) = log.peer_address {
+            let address = self.addresses.entry(peer.ip()).or_default();
+            address.connections += 1;
+
+            if let Some(location) = &log.location {
+                address.country = Some(location.country.clone());
+            }
+        }
+
+        let mut commands = 0;
+        let mut logged_in = false;
+
+        for event in &log.events {
+            match &event.action {
+                AuditLogAction::LoginAttempt(attempt) => {
+                    self.login_attempts += 1;
+
+                    if let LoginAttemptEvent::UsernamePassword { username, password } = attempt {
+                        *self
+                            .credentials
+                            .entry((username.clone(), password.clone()))
+                            .or_default() += 1;
+                    }
+                }
+                AuditLogAction::AuthCheck(check) if check.accepted => logged_in = true,
+                AuditLogAction::ExecCommand(_) => commands += 1,
+                AuditLogAction::WriteFile(_) => self.files_written += 1,
+                _ => {}
+            }
+        }
+
+        // sampling may have left commands out of the events, in which case the summary's count
+        // is the one to go by
+        let commands = log.summary.commands.max(commands);
+        self.commands += commands;
+        self.logged_in += u64::from(logged_in);
+
+        let mut tags: Vec<Box<str>> = log.tags.keys().map(|tag| (**tag).into()).collect();
+
+        if let Some(limit) = log.summary.limit_exceeded {
+            tags.push(format!("{}_exceeded", <&str>::from(limit)).into());
+        }
+
+        if !tags.is_empty() {
+            self.sessions.push(Session {
+                connection_id: log.connection_id,
+                ts,
+                address: log.peer_address.map(|v| v.ip()),
+                commands,
+                tags,
+            });
+        }
+    }
+
+    /// Addresses that connected the most, at most `n` of them.
+    pub fn top_addresses(&self, n: usize) -> Vec<(&IpAddr, &Address)> {
+        top(self.addresses.iter(), n, |address| address.connections)
+    }
+
+    /// Username and password pairs tried the most, at most `n` of them.
+    pub fn top_credentials(&self, n: usize) -> Vec<(&str, &str, u64)> {
+        top(self.credentials.iter(), n, |count| *count)
+            .into_iter()
+            .map(|((username, password), count)| (&**username, &**password, *count))
+            .collect()
+    }
+
+    /// Payloads seen for the first time on the day being summarised, oldest first.
+    pub fn new_payloads(&self) -> Vec<(&str, &Payload)> {
+        let mut payloads: Vec<_> = self
+            .payloads
+            .iter()
+            .filter(|(_, payload)| payload.first_seen.date() == self.date)
+            .map(|(sha256, payload)| (&**sha256, payload))
+            .collect();
+        payloads.sort_by(|a, b| (a.1.first_seen, a.0).cmp(&(b.1.first_seen, b.0)));
+        payloads
+    }
+
+    /// The most interesting tagged sessions, those with the most tags then the most commands
+    /// first, at most `n` of them.
+    pub fn notable_sessions(&self, n: usize) -> Vec<&Session> {
+        let mut sessions: Vec<_> = self.sessions.iter().collect();
+        sessions.sort_by(|a, b| {
+            (b.tags.len(), b.commands)
+                .cmp(&(a.tags.len(), a.commands))
+                .then((a.ts, a.connection_id).cmp(&(b.ts, b.connection_id)))
+        });
+        sessions.truncate(n);
+        sessions
+    }
+
+    pub fn unique_addresses(&self) -> usize {
+        self.addresses.len()
+    }
+}
+
+/// The `n` entries with the highest `count`, ties broken by key so reports are stable.
+fn top<'a, K: Ord, V>(
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+    n: usize,
+    count: impl Fn(&V) -> u64,
+) -> Vec<(&'a K, &'a V)> {
+    let mut entries: Vec<_> = entries.collect();
+    entries.sort_by(|a, b| count(b.1).cmp(&count(a.1)).then(a.0.cmp(b.0)));
+    entries.truncate(n);
+    entries
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{borrow::Cow, time::Duration};
+
+    use pisshoff_types::audit::{
+        AuditLog, AuditLogAction, AuditLogEvent, AuthCheckEvent, BinaryExecutionEvent, GeoLocation,
+        LoginAttemptEvent, WriteFileEvent,
+    };
+    use time::{macros::datetime, OffsetDateTime};
+    use uuid::Uuid;
+
+    use crate::summary::Summary;
+
+    pub fn log(id: u128, ts: OffsetDateTime, peer: &str, actions: Vec<AuditLogAction>) -> AuditLog {
+        AuditLog {
+            connection_id: Uuid::from_u128(id),
+            ts,
+            peer_address: Some(peer.parse().unwrap()),
+            events: actions
+                .into_iter()
+                .map(|action| AuditLogEvent {
+                    sequence: None,
+                    start_offset: Duration::ZERO,
+                    action,
+                })
+                .collect(),
+            ..AuditLog::default()
+        }
+    }
+
+    pub fn login(username: &str, password: &str) -> AuditLogAction {
+        AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword {
+            username: username.into(),
+            password: password.into(),
+        })
+    }
+
+    pub fn accepted() -> AuditLogAction {
+        AuditLogAction::AuthCheck(AuthCheckEvent {
+            method: "password".into(),
+            username: "root".into(),
+            accepted: true,
+            attempt: 1,
+            methods_tried: vec![Cow::Borrowed("password")],
+            methods_advertised: vec![Cow::Borrowed("password")],
+            rule: None,
+        })
+    }
+
+    pub fn write(path: &str, sha256: &str) -> AuditLogAction {
+        AuditLogAction::WriteFile(WriteFileEvent {
+            path: path.into(),
+            content: "payload".into(),
+            size: 7,
+            sha256: Some(sha256.into()),
+        })
+    }
+
+    pub fn summary() -> Summary {
+        let mut summary = Summary::new(datetime!(2024-05-02 0:00 UTC).date());
+
+        // seen the day before, so not new
+        summary.add(&log(
+            1,
+            datetime!(2024-05-01 23:59 UTC),
+            "192.0.2.1:1000",
+            vec![login("root", "root"), write("/tmp/old", "aaaa")],
+        ));
+
+        let mut miner = log(
+            2,
+            datetime!(2024-05-02 01:00 UTC),
+            "192.0.2.2:1000",
+            vec![
+                login("root", "root"),
+                login("admin", "admin"),
+                accepted(),
+                write("/tmp/old", "aaaa"),
+                write("/tmp/xmrig", "bbbb"),
+                AuditLogAction::BinaryExecution(BinaryExecutionEvent {
+                    path: "/tmp/xmrig".into(),
+                    sha256: "bbbb".into(),
+                    size: 7,
+                    args: Box::default(),
+                }),
+            ],
+        );
+        miner.tags.insert("miner".into(), "1".into());
+        miner.location = Some(GeoLocation {
+            country: "NL".into(),
+            asn: 14061,
+            as_name: "DIGITALOCEAN-ASN".into(),
+        });
+        miner.summary.commands = 4;
+        summary.add(&miner);
+
+        summary.add(&log(
+            3,
+            datetime!(2024-05-02 02:00 UTC),
+            "192.0.2.2:1001",
+            vec![login("root", "root")],
+        ));
+
+        summary.add(&log(
+            4,
+            datetime!(2024-05-02 03:00 UTC),
+            "[2001:db8::1]:1000",
+            vec![login("admin", "admin|<b>"), write("/root/.x", "cccc")],
+        ));
+
+        summary
+    }
+
+    #[test]
+    fn counts_only_the_day() {
+        let summary = summary();
+
+        assert_eq!(summary.connections, 3);
+        assert_eq!(summary.unique_addresses(), 2);
+        assert_eq!(summary.logged_in, 1);
+        assert_eq!(summary.login_attempts, 4);
+        assert_eq!(summary.commands, 4);
+        assert_eq!(summary.files_written, 3);
+    }
+
+    #[test]
+    fn orders_top_entries() {
+        let summary = summary();
+
+        let addresses: Vec<_> = summary
+            .top_addresses(10)
+            .into_iter()
+            .map(|(ip, address)| (ip.to_string(), address.connections))
+            .collect();
+        assert_eq!(
+            addresses,
+            [("192.0.2.2".to_string(), 2), ("2001:db8::1".to_string(), 1)]
+        );
+
+        let credentials = summary.top_credentials(2);
+        assert_eq!(credentials, [("root", "root", 2), ("admin", "admin", 1)]);
+    }
+
+    #[test]
+    fn only_new_payloads() {
+        let summary = summary();
+
+        let payloads: Vec<_> = summary
+            .new_payloads()
+            .into_iter()
+            .map(|(sha256, payload)| (sha256, &*payload.path, payload.connections.len()))
+            .collect();
+        assert_eq!(
+            payloads,
+            [("bbbb", "/tmp/xmrig", 1), ("cccc", "/root/.x", 1)]
+        );
+    }
+
+    #[test]
+    fn notable_sessions_are_tagged() {
+        let summary = summary();
+
+        let sessions = summary.notable_sessions(10);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].connection_id, Uuid::from_u128(2));
+        assert_eq!(sessions[0].tags, ["miner".into()]);
+    }
+}
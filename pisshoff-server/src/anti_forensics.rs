@@ -0,0 +1,65 @@
+//! Recognises clients trying to cover their tracks, so the attempt can be flagged even though the
+//! server goes along with it.
+
+use std::path::Path;
+
+use crate::audit::AntiForensicsTechnique;
+
+/// What removing or overwriting the file at the given absolute path would hide, if anything.
+pub fn file_technique(path: &Path) -> Option<AntiForensicsTechnique> {
+    let name = path.file_name()?.to_str()?;
+
+    if path.starts_with("/var/log") {
+        Some(AntiForensicsTechnique::TamperLogs)
+    } else if name.starts_with('.') && name.ends_with("_history") {
+        Some(AntiForensicsTechnique::DeleteHistory)
+    } else {
+        None
+    }
+}
+
+/// Returns true if setting the variable to `value`, or unsetting it if `None`, stops the shell's
+/// history from being saved.
+pub fn disables_history(name: &[u8], value: Option<&[u8]>) -> bool {
+    match name {
+        b"HISTFILE" => matches!(value, None | Some(b"" | b"/dev/null")),
+        b"HISTSIZE" | b"HISTFILESIZE" => value == Some(b"0"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{
+        anti_forensics::{disables_history, file_technique},
+        audit::AntiForensicsTechnique,
+    };
+
+    #[test_case("/root/.bash_history", Some(AntiForensicsTechnique::DeleteHistory); "bash history")]
+    #[test_case("/home/admin/.zsh_history", Some(AntiForensicsTechnique::DeleteHistory); "zsh history")]
+    #[test_case("/var/log/wtmp", Some(AntiForensicsTechnique::TamperLogs); "wtmp")]
+    #[test_case("/var/log/nginx/access.log", Some(AntiForensicsTechnique::TamperLogs); "nested log")]
+    #[test_case("/var/log", Some(AntiForensicsTechnique::TamperLogs); "log directory")]
+    #[test_case("/var/lib/dpkg/status", None; "not a log")]
+    #[test_case("/root/history", None; "not history")]
+    fn classifies_files(path: &str, expected: Option<AntiForensicsTechnique>) {
+        assert_eq!(file_technique(Path::new(path)), expected);
+    }
+
+    #[test_case("HISTFILE", None, true; "unset histfile")]
+    #[test_case("HISTFILE", Some("/dev/null"), true; "histfile to null")]
+    #[test_case("HISTFILE", Some("/root/.bash_history"), false; "histfile elsewhere")]
+    #[test_case("HISTSIZE", Some("0"), true; "zero histsize")]
+    #[test_case("HISTSIZE", None, false; "unset histsize")]
+    #[test_case("PATH", None, false; "other variable")]
+    fn classifies_variables(name: &str, value: Option<&str>, expected: bool) {
+        assert_eq!(
+            disables_history(name.as_bytes(), value.map(str::as_bytes)),
+            expected
+        );
+    }
+}
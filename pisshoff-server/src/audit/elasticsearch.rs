@@ -0,0 +1,760 @@
+//! Bulk indexes audit events into Elasticsearch or OpenSearch, each event as its own document in
+//! a daily index. Documents use the same field names as Cowrie's for the events both honeypots
+//! log, so dashboards built for Cowrie work with little change, with everything else pisshoff
+//! logs under `pisshoff`.
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use pisshoff_http as http;
+use pisshoff_types::audit::{AuditLog, AuditLogAction, LoginAttemptEvent};
+use serde_json::{json, Map, Value};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime, UtcOffset,
+};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{Elasticsearch, SearchDistribution};
+
+/// The most connections waiting to be turned into documents.
+const MAX_BUFFERED_LOGS: usize = 1024;
+
+/// How long to wait before the first retry once the cluster is unavailable, doubled after each
+/// failure.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest to wait between retries.
+const MAX_BACKOFF: Duration = Duration::from_mins(1);
+
+/// How long the cluster has to respond to each request before it's given up on.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long buffered events are given to be indexed once the server is shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Strings longer than this are stored but not indexed, as Lucene can't index terms over 32KiB.
+const IGNORE_ABOVE: usize = 8191;
+
+/// Indexes documents from a background task, which buffers them while the cluster is unavailable.
+pub struct ElasticsearchSink {
+    index: String,
+    send: mpsc::Sender<Vec<String>>,
+    handle: JoinHandle<()>,
+}
+
+impl ElasticsearchSink {
+    pub fn start(config: Elasticsearch) -> Self {
+        let (send, recv) = mpsc::channel(MAX_BUFFERED_LOGS);
+        let index = config.index.clone();
+        let handle = tokio::spawn(run(config, recv));
+
+        Self {
+            index,
+            send,
+            handle,
+        }
+    }
+
+    /// Queues a document for each of the events in `log` to be indexed.
+    pub fn write(&mut self, log: &AuditLog) {
+        if let Err(TrySendError::Full(_)) = self.send.try_send(documents(&self.index, log)) {
+            warn!("Elasticsearch sink is falling behind, dropping audit events");
+        }
+    }
+
+    /// Waits a short while for buffered events to be indexed, giving up on any that can't be.
+    pub async fn close(self) {
+        drop(self.send);
+
+        let abort = self.handle.abort_handle();
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.handle)
+            .await
+            .is_err()
+        {
+            warn!("Timed out indexing buffered audit events into Elasticsearch");
+            abort.abort();
+        }
+    }
+}
+
+/// Indexes documents in batches of `batch-size`, or every `flush-interval` seconds if the batch
+/// hasn't filled by then. Returns once every sender has been dropped, after indexing whatever is
+/// still buffered.
+async fn run(config: Elasticsearch, mut recv: mpsc::Receiver<Vec<String>>) {
+    let http = match &config.ca_file {
+        Some(path) => match http::Client::with_ca_file(path) {
+            Ok(http) => http,
+            Err(error) => {
+                error!(
+                    %error,
+                    path = %path.display(),
+                    "Failed to load Elasticsearch CA file, not indexing audit events"
+                );
+                return;
+            }
+        },
+        None => http::Client::shared().clone(),
+    };
+
+    let mut client = Client {
+        installed: !config.manage_template,
+        config,
+        http,
+    };
+    let batch_size = client.config.batch_size.max(1);
+
+    let mut buffered = Vec::new();
+    let mut dropping = false;
+    let mut backoff = MIN_BACKOFF;
+    let mut retry_at = Instant::now();
+
+    let period = Duration::from_secs(client.config.flush_interval.max(1));
+    let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let ready = tokio::select! {
+            documents = recv.recv() => match documents {
+                Some(documents) => {
+                    let space = client.config.max_buffered.saturating_sub(buffered.len());
+
+                    if documents.len() > space {
+                        if !dropping {
+                            warn!("Elasticsearch buffer is full, dropping audit events until it's available");
+                        }
+
+                        dropping = true;
+                    } else {
+                        dropping = false;
+                    }
+
+                    buffered.extend(documents.into_iter().take(space));
+                    buffered.len() >= batch_size
+                }
+                None => break,
+            },
+            _ = interval.tick() => !buffered.is_empty(),
+        };
+
+        if !ready || Instant::now() < retry_at {
+            continue;
+        }
+
+        if client.flush(&mut buffered, batch_size).await {
+            backoff = MIN_BACKOFF;
+        } else {
+            retry_at = Instant::now() + backoff;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    client.flush(&mut buffered, batch_size).await;
+}
+
+struct Client {
+    config: Elasticsearch,
+    http: http::Client,
+    /// Whether the index template and lifecycle policy are in place, so indices created by bulk
+    /// requests are created with them.
+    installed: bool,
+}
+
+impl Client {
+    /// Indexes every buffered document, returning false if the cluster couldn't be reached.
+    async fn flush(&mut self, buffered: &mut Vec<String>, batch_size: usize) -> bool {
+        while !buffered.is_empty() {
+            if !self.send(buffered, batch_size).await {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Indexes up to `batch_size` of the `buffered` documents, removing them from the buffer
+    /// unless they should be retried. Returns false if the cluster couldn't be reached.
+    async fn send(&mut self, buffered: &mut Vec<String>, batch_size: usize) -> bool {
+        if !self.installed {
+            if !self.install().await {
+                return false;
+            }
+
+            self.installed = true;
+        }
+
+        let len = buffered.len().min(batch_size);
+        let body = buffered[..len].concat();
+
+        let res = self
+            .request(
+                "POST",
+                "_bulk?filter_path=errors,items.*.error.reason",
+                "application/x-ndjson",
+                &body,
+            )
+            .await;
+
+        match res {
+            Some(res) if http::is_success(&res.status) => {
+                if res.body.contains("\"errors\":true") {
+                    // documents the cluster refused would only be refused again
+                    warn!(response = res.body, "Elasticsearch rejected audit events");
+                }
+
+                debug!("Indexed {len} audit events into Elasticsearch");
+                buffered.drain(..len);
+                true
+            }
+            Some(res) if matches!(http::status(&res.status), Some(429 | 500..)) => {
+                warn!(
+                    status = res.status,
+                    "Elasticsearch is unavailable, retrying"
+                );
+                false
+            }
+            Some(res) => {
+                warn!(
+                    status = res.status,
+                    response = res.body,
+                    "Elasticsearch refused bulk request, dropping audit events"
+                );
+                buffered.drain(..len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Creates or updates the lifecycle policy and index template, returning false if they
+    /// couldn't be.
+    async fn install(&self) -> bool {
+        let index = &self.config.index;
+        let retention_days = self.config.retention_days;
+
+        let policy = match self.config.distribution {
+            _ if retention_days == 0 => None,
+            SearchDistribution::Elasticsearch => {
+                Some((format!("_ilm/policy/{index}"), ilm_policy(retention_days)))
+            }
+            SearchDistribution::Opensearch => Some((
+                format!("_plugins/_ism/policies/{index}"),
+                ism_policy(index, retention_days),
+            )),
+        };
+
+        if let Some((path, policy)) = policy {
+            match self
+                .request("PUT", &path, "application/json", &policy.to_string())
+                .await
+            {
+                Some(res) if http::is_success(&res.status) => {}
+                // ISM policies can't be replaced without their sequence number, so an existing
+                // one is left as it is
+                Some(res) if http::status(&res.status) == Some(409) => {
+                    info!("Elasticsearch lifecycle policy {index} already exists, leaving as is");
+                }
+                Some(res) => {
+                    warn!(
+                        status = res.status,
+                        response = res.body,
+                        "Failed to create Elasticsearch lifecycle policy"
+                    );
+                    return false;
+                }
+                None => return false,
+            }
+        }
+
+        let template = index_template(
+            index,
+            self.config.distribution == SearchDistribution::Elasticsearch && retention_days > 0,
+        );
+
+        match self
+            .request(
+                "PUT",
+                &format!("_index_template/{index}"),
+                "application/json",
+                &template.to_string(),
+            )
+            .await
+        {
+            Some(res) if http::is_success(&res.status) => {
+                info!("Installed Elasticsearch index template {index}");
+                true
+            }
+            Some(res) => {
+                warn!(
+                    status = res.status,
+                    response = res.body,
+                    "Failed to create Elasticsearch index template"
+                );
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Sends a request to the cluster, returning `None` if it couldn't be reached.
+    async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        content_type: &str,
+        body: &str,
+    ) -> Option<http::Response> {
+        let url = format!("{}/{path}", self.config.url.trim_end_matches('/'));
+
+        let authorization = match (&self.config.api_key, &self.config.username) {
+            (Some(key), _) => Some(format!("ApiKey {key}")),
+            (None, Some(username)) => Some(format!(
+                "Basic {}",
+                BASE64.encode(format!(
+                    "{username}:{}",
+                    self.config.password.as_deref().unwrap_or_default()
+                ))
+            )),
+            (None, None) => None,
+        };
+
+        let mut headers = vec![("Content-Type", content_type)];
+        if let Some(authorization) = &authorization {
+            headers.push(("Authorization", authorization));
+        }
+
+        match tokio::time::timeout(
+            REQUEST_TIMEOUT,
+            self.http.fetch(method, &url, &headers, body),
+        )
+        .await
+        {
+            Ok(Ok(res)) => Some(res),
+            Ok(Err(error)) => {
+                warn!(%error, "Failed to connect to Elasticsearch");
+                None
+            }
+            Err(_) => {
+                warn!("Timed out waiting for Elasticsearch");
+                None
+            }
+        }
+    }
+}
+
+/// The bulk request lines indexing each event in `log`, along with one for the connection itself.
+/// Documents are given IDs derived from the connection, so a batch that's retried after the
+/// cluster has already indexed part of it doesn't duplicate anything.
+fn documents(index: &str, log: &AuditLog) -> Vec<String> {
+    let mut common = Map::new();
+    common.insert("session".into(), log.connection_id.to_string().into());
+    common.insert("sensor".into(), log.host.as_ref().into());
+
+    if let Some(peer_address) = log.peer_address {
+        common.insert(
+            "src_ip".into(),
+            peer_address.ip().to_canonical().to_string().into(),
+        );
+        common.insert("src_port".into(), peer_address.port().into());
+    }
+
+    if let Some(location) = &log.location {
+        common.insert(
+            "geoip".into(),
+            json!({
+                "country_code2": location.country,
+                "asn": location.asn,
+                "as_org": location.as_name,
+            }),
+        );
+    }
+
+    let mut documents = Vec::with_capacity(log.events.len() + 1);
+
+    let mut document = |id: usize, ts: OffsetDateTime, fields: Map<String, Value>| {
+        let ts = ts.to_offset(UtcOffset::UTC);
+        let formatted = ts.format(&Rfc3339).unwrap_or_default();
+
+        let mut source = common.clone();
+        source.insert("@timestamp".into(), formatted.clone().into());
+        source.insert("timestamp".into(), formatted.into());
+        source.extend(fields);
+
+        let action = json!({
+            "index": {
+                "_index": format!(
+                    "{index}-{}",
+                    ts.format(format_description!("[year].[month].[day]"))
+                        .unwrap_or_default()
+                ),
+                "_id": format!("{}-{id}", log.connection_id),
+            }
+        });
+
+        documents.push(format!("{action}\n{}\n", Value::Object(source)));
+    };
+
+    let mut fields = Map::new();
+    fields.insert("eventid".into(), "cowrie.session.connect".into());
+    if let Some(version) = &log.client_version {
+        fields.insert("version".into(), version.as_ref().into());
+    }
+    if let Some(hassh) = &log.hassh {
+        fields.insert("hassh".into(), hassh.as_ref().into());
+    }
+    fields.insert(
+        "pisshoff".into(),
+        json!({
+            "type": "connection",
+            "connection": {
+                "algorithms": log.algorithms,
+                "tags": log.tags,
+                "summary": log.summary,
+            },
+        }),
+    );
+    document(0, log.ts, fields);
+
+    for (i, event) in log.events.iter().enumerate() {
+        let ty = <&'static str>::from(&event.action);
+        let mut fields = cowrie_fields(&event.action, || {
+            // the outcome of a login is logged in the check following the attempt
+            log.events[i + 1..]
+                .iter()
+                .find_map(|event| match &event.action {
+                    AuditLogAction::AuthCheck(check) => Some(check.accepted),
+                    _ => None,
+                })
+        });
+
+        // nested under the event's type so fields of the same name in different events can't
+        // conflict in the mapping
+        let mut details = match serde_json::to_value(&event.action) {
+            Ok(Value::Object(details)) => details,
+            _ => Map::new(),
+        };
+        details.remove("type");
+
+        fields
+            .entry("eventid")
+            .or_insert_with(|| format!("pisshoff.{ty}").into());
+        fields.insert(
+            "pisshoff".into(),
+            json!({ "type": ty, ty: details, "sequence": event.sequence }),
+        );
+
+        document(i + 1, log.ts + event.start_offset, fields);
+    }
+
+    documents
+}
+
+/// The fields Cowrie logs for events it has an equivalent of, `accepted` finding whether a login
+/// attempt went on to be accepted.
+fn cowrie_fields(
+    action: &AuditLogAction,
+    accepted: impl FnOnce() -> Option<bool>,
+) -> Map<String, Value> {
+    let fields = match action {
+        AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword {
+            username,
+            password,
+        }) => {
+            json!({
+                "eventid": if accepted() == Some(true) {
+                    "cowrie.login.success"
+                } else {
+                    "cowrie.login.failed"
+                },
+                "username": username,
+                "password": password,
+            })
+        }
+        AuditLogAction::ExecCommand(event) => json!({
+            "eventid": "cowrie.command.input",
+            "input": event.command,
+        }),
+        AuditLogAction::DownloadAttempt(event) => json!({
+            "eventid": "cowrie.session.file_download",
+            "url": event.url,
+            "outfile": event.destination,
+        }),
+        AuditLogAction::WriteFile(event) => json!({
+            "eventid": "cowrie.session.file_upload",
+            "filename": event.path,
+            "shasum": event.sha256,
+        }),
+        _ => return Map::new(),
+    };
+
+    match fields {
+        Value::Object(fields) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Deletes indices `retention_days` after they were created.
+fn ilm_policy(retention_days: u32) -> Value {
+    json!({
+        "policy": {
+            "_meta": { "description": "Audit events from pisshoff" },
+            "phases": {
+                "hot": { "min_age": "0ms", "actions": {} },
+                "delete": {
+                    "min_age": format!("{retention_days}d"),
+                    "actions": { "delete": {} },
+                },
+            },
+        }
+    })
+}
+
+/// Deletes indices `retention_days` after they were created, applied to new indices by the
+/// policy's own template since OpenSearch has no setting to name it in the index template.
+fn ism_policy(index: &str, retention_days: u32) -> Value {
+    json!({
+        "policy": {
+            "description": "Audit events from pisshoff",
+            "default_state": "hot",
+            "states": [
+                {
+                    "name": "hot",
+                    "actions": [],
+                    "transitions": [{
+                        "state_name": "delete",
+                        "conditions": { "min_index_age": format!("{retention_days}d") },
+                    }],
+                },
+                {
+                    "name": "delete",
+                    "actions": [{ "delete": {} }],
+                    "transitions": [],
+                },
+            ],
+            "ism_template": [{ "index_patterns": [format!("{index}-*")], "priority": 100 }],
+        }
+    })
+}
+
+/// Maps the fields shared by every document, and any other strings as keywords rather than
+/// analysed text since they're mostly identifiers, paths and hashes.
+fn index_template(index: &str, ilm: bool) -> Value {
+    let keyword = json!({ "type": "keyword", "ignore_above": IGNORE_ABOVE });
+
+    let mut settings = json!({
+        // each event type's fields are mapped separately, which adds up to more than the default
+        // limit of 1000
+        "index.mapping.total_fields.limit": 5000,
+    });
+    if ilm {
+        settings["index.lifecycle.name"] = index.into();
+    }
+
+    json!({
+        "index_patterns": [format!("{index}-*")],
+        "priority": 100,
+        "_meta": { "description": "Audit events from pisshoff" },
+        "template": {
+            "settings": settings,
+            "mappings": {
+                "dynamic_templates": [{
+                    "strings": { "match_mapping_type": "string", "mapping": keyword },
+                }],
+                "properties": {
+                    "@timestamp": { "type": "date" },
+                    "timestamp": { "type": "date" },
+                    "eventid": { "type": "keyword" },
+                    "session": { "type": "keyword" },
+                    "sensor": { "type": "keyword" },
+                    "src_ip": { "type": "ip" },
+                    "src_port": { "type": "integer" },
+                    "username": keyword,
+                    "password": keyword,
+                    "input": {
+                        "type": "text",
+                        "fields": { "keyword": keyword },
+                    },
+                    "url": keyword,
+                    "shasum": keyword,
+                    "geoip": {
+                        "properties": {
+                            "country_code2": { "type": "keyword" },
+                            "asn": { "type": "long" },
+                            "as_org": { "type": "keyword" },
+                        }
+                    },
+                    "pisshoff": {
+                        "properties": {
+                            "type": { "type": "keyword" },
+                            "sequence": { "type": "long" },
+                        }
+                    },
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use pisshoff_types::audit::{
+        AuditLog, AuditLogAction, AuditLogEvent, AuthCheckEvent, ExecCommandEvent, GeoLocation,
+        LoginAttemptEvent,
+    };
+    use time::macros::datetime;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use uuid::Uuid;
+
+    use crate::{
+        audit::elasticsearch::{documents, ElasticsearchSink},
+        config::Config,
+    };
+
+    fn log() -> AuditLog {
+        let mut log = AuditLog {
+            connection_id: Uuid::nil(),
+            ts: datetime!(2024-05-02 23:59:59 UTC),
+            peer_address: Some("[::ffff:192.0.2.1]:51234".parse().unwrap()),
+            host: "honeypot-1".into(),
+            client_version: Some("SSH-2.0-Go".into()),
+            location: Some(GeoLocation {
+                country: "NL".into(),
+                asn: 14061,
+                as_name: "DIGITALOCEAN-ASN".into(),
+            }),
+            ..AuditLog::default()
+        };
+
+        for (offset, action) in [
+            (
+                0,
+                AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword {
+                    username: "root".into(),
+                    password: "hunter2".into(),
+                }),
+            ),
+            (
+                0,
+                AuditLogAction::AuthCheck(AuthCheckEvent {
+                    method: "password".into(),
+                    username: "root".into(),
+                    accepted: true,
+                    attempt: 1,
+                    methods_tried: vec!["password".into()],
+                    methods_advertised: vec!["password".into()],
+                    rule: None,
+                }),
+            ),
+            (1, AuditLogAction::ShellRequested),
+            (
+                2,
+                AuditLogAction::ExecCommand(ExecCommandEvent {
+                    command: "uname -a".into(),
+                    args: None,
+                    interactive: true,
+                    pty: true,
+                }),
+            ),
+        ] {
+            log.events.push(AuditLogEvent {
+                sequence: Some(log.events.len() as u64),
+                start_offset: Duration::from_secs(offset),
+                action,
+            });
+        }
+
+        log
+    }
+
+    #[test]
+    fn renders_documents() {
+        insta::assert_snapshot!(documents("pisshoff", &log()).concat());
+    }
+
+    /// Reads a request with a `Content-Length` from `stream`, returning its request line and body.
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> (String, String) {
+        let mut request = Vec::new();
+
+        loop {
+            let mut buf = [0; 4096];
+            let len = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..len]);
+
+            let request = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = request.split_once("\r\n\r\n") {
+                let length: usize = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+
+                if body.len() >= length {
+                    let line = head.lines().next().unwrap().to_string();
+                    return (line, body.to_string());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn installs_template_and_indexes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+
+            for _ in 0..3 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                requests.push(read_request(&mut stream).await);
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                          10\r\n{\"errors\":false}\r\n0\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            requests
+        });
+
+        let config: Config = toml::from_str(&format!(
+            "[elasticsearch]\n\
+             url = \"{url}/\"\n\
+             api-key = \"a2V5\""
+        ))
+        .unwrap();
+
+        let mut sink = ElasticsearchSink::start(config.elasticsearch.unwrap());
+        sink.write(&log());
+        sink.close().await;
+
+        let requests = server.await.unwrap();
+        let lines: Vec<_> = requests.iter().map(|(line, _)| line.as_str()).collect();
+        assert_eq!(
+            lines,
+            [
+                "PUT /_ilm/policy/pisshoff HTTP/1.1",
+                "PUT /_index_template/pisshoff HTTP/1.1",
+                "POST /_bulk?filter_path=errors,items.*.error.reason HTTP/1.1",
+            ]
+        );
+
+        assert!(requests[0].1.contains("\"min_age\":\"90d\""));
+        assert!(requests[1]
+            .1
+            .contains("\"index.lifecycle.name\":\"pisshoff\""));
+        assert_eq!(requests[2].1, documents("pisshoff", &log()).concat());
+    }
+}
@@ -0,0 +1,324 @@
+//! The files audit logs are written to, optionally compressed as they're written and rotated
+//! once they grow too large or old, for deployments without logrotate to keep them from filling
+//! the disk.
+
+use std::{
+    ffi::OsString,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWrite, AsyncWriteExt, BufWriter},
+};
+use tracing::info;
+
+use crate::config::{AuditRotation, LogCompression};
+
+type Writer = BufWriter<Box<dyn AsyncWrite + Send + Unpin>>;
+
+/// An append-only log file, rolled over once it exceeds the limits given by `rotation`.
+pub struct LogFile {
+    path: PathBuf,
+    rotation: Option<AuditRotation>,
+    compression: LogCompression,
+    writer: Writer,
+    /// Set when data has been written that hasn't been flushed through to the file yet.
+    dirty: bool,
+    /// Number of bytes in the current file on disk, after compression.
+    size: Arc<AtomicU64>,
+    /// When the current file was started.
+    started: SystemTime,
+}
+
+impl LogFile {
+    pub async fn open(
+        path: &Path,
+        rotation: Option<AuditRotation>,
+        compression: LogCompression,
+    ) -> std::io::Result<Self> {
+        let (writer, size, started) = Self::open_writer(path, compression).await?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            rotation,
+            compression,
+            writer,
+            dirty: false,
+            size,
+            started,
+        })
+    }
+
+    async fn open_writer(
+        path: &Path,
+        compression: LogCompression,
+    ) -> std::io::Result<(Writer, Arc<AtomicU64>, SystemTime)> {
+        let file = OpenOptions::default()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        let metadata = file.metadata().await?;
+        let started = metadata.created().unwrap_or_else(|_| SystemTime::now());
+        let size = Arc::new(AtomicU64::new(metadata.len()));
+
+        let file = Counted {
+            file,
+            written: size.clone(),
+        };
+
+        Ok((BufWriter::new(encoder(file, compression)), size, started))
+    }
+
+    /// Closes the log and opens it again, so it's recreated if something else moved it away.
+    pub async fn reopen(&mut self) -> std::io::Result<()> {
+        self.close().await?;
+        (self.writer, self.size, self.started) =
+            Self::open_writer(&self.path, self.compression).await?;
+        Ok(())
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(data).await?;
+        self.dirty = true;
+
+        if self.should_rotate() {
+            self.rotate().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes everything buffered so far out to the file, including anything held by the
+    /// compressor, so the file can be read up to this point.
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush().await?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Flushes the log and finishes the compressed stream, nothing more can be written until the
+    /// log is reopened.
+    pub async fn close(&mut self) -> std::io::Result<()> {
+        self.writer.shutdown().await?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn has_buffered(&self) -> bool {
+        self.dirty
+    }
+
+    fn should_rotate(&self) -> bool {
+        let Some(rotation) = &self.rotation else {
+            return false;
+        };
+
+        // anything still held by the compressor isn't counted until it's written out
+        let size = self.size.load(Ordering::Relaxed) + self.writer.buffer().len() as u64;
+        let too_large = rotation.max_size.is_some_and(|max| size >= max);
+        let too_old = rotation.max_age.is_some_and(|max| {
+            self.started
+                .elapsed()
+                .is_ok_and(|age| age >= Duration::from_secs(max))
+        });
+
+        too_large || too_old
+    }
+
+    /// Moves each rotated log along by one, compresses the current log into the first slot and
+    /// starts a new one. Logs that were already compressed as they were written are moved as-is.
+    async fn rotate(&mut self) -> std::io::Result<()> {
+        let Some(rotation) = self.rotation.clone() else {
+            return Ok(());
+        };
+
+        info!(path = %self.path.display(), "Rotating log file");
+        self.close().await?;
+
+        let compression = if self.compression == LogCompression::None {
+            rotation.compression
+        } else {
+            LogCompression::None
+        };
+
+        let archive = |n: usize| {
+            let mut name = OsString::from(self.path.as_os_str());
+            name.push(format!(".{n}{}", compression.extension()));
+            PathBuf::from(name)
+        };
+
+        ignore_missing(tokio::fs::remove_file(archive(rotation.keep)).await)?;
+
+        for n in (1..rotation.keep).rev() {
+            ignore_missing(tokio::fs::rename(archive(n), archive(n + 1)).await)?;
+        }
+
+        if rotation.keep == 0 {
+            tokio::fs::remove_file(&self.path).await?;
+        } else if compression == LogCompression::None {
+            tokio::fs::rename(&self.path, archive(1)).await?;
+        } else {
+            compress(&self.path, &archive(1), compression).await?;
+            tokio::fs::remove_file(&self.path).await?;
+        }
+
+        (self.writer, self.size, self.started) =
+            Self::open_writer(&self.path, self.compression).await?;
+
+        Ok(())
+    }
+}
+
+/// Wraps `writer` in an encoder for the given compression.
+fn encoder<W: AsyncWrite + Send + Unpin + 'static>(
+    writer: W,
+    compression: LogCompression,
+) -> Box<dyn AsyncWrite + Send + Unpin> {
+    match compression {
+        LogCompression::None => Box::new(writer),
+        LogCompression::Gzip => Box::new(GzipEncoder::new(writer)),
+        LogCompression::Zstd => Box::new(ZstdEncoder::new(writer)),
+    }
+}
+
+async fn compress(
+    source: &Path,
+    destination: &Path,
+    compression: LogCompression,
+) -> std::io::Result<()> {
+    let mut input = File::open(source).await?;
+    let mut output = encoder(File::create(destination).await?, compression);
+
+    tokio::io::copy(&mut input, &mut output).await?;
+    output.shutdown().await
+}
+
+fn ignore_missing(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+/// Counts the bytes written through to the file, so the size of a compressed log is known
+/// without flushing the compressor.
+struct Counted {
+    file: File,
+    written: Arc<AtomicU64>,
+}
+
+impl AsyncWrite for Counted {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let res = Pin::new(&mut self.file).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = res {
+            self.written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use flate2::read::{GzDecoder, MultiGzDecoder};
+    use uuid::Uuid;
+
+    use crate::{
+        audit::log_file::LogFile,
+        config::{AuditRotation, LogCompression},
+    };
+
+    #[tokio::test]
+    async fn rotates_by_size() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let path = directory.join("audit.jsonl");
+
+        let rotation = AuditRotation {
+            max_size: Some(10),
+            max_age: None,
+            keep: 2,
+            compression: LogCompression::Gzip,
+        };
+
+        let mut log = LogFile::open(&path, Some(rotation), LogCompression::None)
+            .await
+            .unwrap();
+
+        for line in ["first line\n", "second line\n", "third line\n", "tail\n"] {
+            log.write(line.as_bytes()).await.unwrap();
+        }
+        log.flush().await.unwrap();
+
+        let read_archive = |n: usize| {
+            let file = std::fs::File::open(directory.join(format!("audit.jsonl.{n}.gz"))).unwrap();
+            let mut out = String::new();
+            GzDecoder::new(file).read_to_string(&mut out).unwrap();
+            out
+        };
+
+        let current = tokio::fs::read_to_string(&path).await.unwrap();
+        let newest = read_archive(1);
+        let oldest = read_archive(2);
+        let entries = std::fs::read_dir(&directory).unwrap().count();
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+
+        assert_eq!(current, "tail\n");
+        assert_eq!(newest, "third line\n");
+        assert_eq!(oldest, "second line\n");
+        assert_eq!(entries, 3);
+    }
+
+    #[tokio::test]
+    async fn compresses_across_reopen() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let path = directory.join("audit.jsonl.gz");
+
+        let mut log = LogFile::open(&path, None, LogCompression::Gzip)
+            .await
+            .unwrap();
+
+        log.write(b"first line\n").await.unwrap();
+        log.reopen().await.unwrap();
+        log.write(b"second line\n").await.unwrap();
+        log.close().await.unwrap();
+
+        let mut content = String::new();
+        MultiGzDecoder::new(std::fs::File::open(&path).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+
+        assert_eq!(content, "first line\nsecond line\n");
+    }
+}
@@ -0,0 +1,174 @@
+//! Hashes or removes sensitive fields from audit logs before they're written, as configured by
+//! `redaction`.
+
+use bytes::Bytes;
+use pisshoff_types::audit::{AuditLog, AuditLogAction, LoginAttemptEvent};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{Redaction, RedactionMode},
+    payload,
+};
+
+/// Written in place of a password when passwords are redacted.
+const REDACTED: &str = "[redacted]";
+
+/// Rewrites every password and uploaded file in `log` as `config` asks.
+pub fn redact(log: &mut AuditLog, config: &Redaction) {
+    if config.passwords == RedactionMode::Raw && config.file_contents == RedactionMode::Raw {
+        return;
+    }
+
+    for event in &mut log.events {
+        redact_action(&mut event.action, config);
+    }
+}
+
+/// Rewrites the passwords and uploaded file in a single event as `config` asks, for events
+/// leaving the server before their connection's log is written.
+pub fn redact_action(action: &mut AuditLogAction, config: &Redaction) {
+    match action {
+        AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword { password, .. }) => {
+            password_field(password, config);
+        }
+        AuditLogAction::LoginAttempt(LoginAttemptEvent::KeyboardInteractive {
+            responses, ..
+        }) => {
+            for response in responses {
+                password_field(&mut response.response, config);
+            }
+        }
+        AuditLogAction::SudoPassword(event) => password_field(&mut event.password, config),
+        AuditLogAction::SwitchUser(event) => {
+            if let Some(password) = &mut event.password {
+                password_field(password, config);
+            }
+        }
+        AuditLogAction::SocksConnect(event) => {
+            if let Some(password) = &mut event.password {
+                password_field(password, config);
+            }
+        }
+        AuditLogAction::WriteFile(event) => {
+            file_contents(&mut event.content, &mut event.sha256, config.file_contents);
+        }
+        AuditLogAction::PartialUpload(event) => {
+            if let Some(content) = &mut event.content {
+                file_contents(content, &mut event.sha256, config.file_contents);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn password_field(password: &mut Box<str>, config: &Redaction) {
+    match config.passwords {
+        RedactionMode::Raw => {}
+        RedactionMode::Hash => {
+            let salted = [config.salt.as_bytes(), password.as_bytes()].concat();
+            *password = format!("sha256:{:x}", Sha256::digest(salted)).into();
+        }
+        RedactionMode::Redact => *password = REDACTED.into(),
+    }
+}
+
+/// Empties `content`, making sure `sha256` is filled in first if it's being hashed. Where content
+/// was truncated the hash will already be of the whole file.
+fn file_contents(content: &mut Bytes, sha256: &mut Option<Box<str>>, mode: RedactionMode) {
+    match mode {
+        RedactionMode::Raw => return,
+        RedactionMode::Hash if sha256.is_none() => *sha256 = Some(payload::sha256(content)),
+        RedactionMode::Hash | RedactionMode::Redact => {}
+    }
+
+    *content = Bytes::new();
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use pisshoff_types::audit::{
+        AuditLog, AuditLogAction, KeyboardInteractiveResponse, LoginAttemptEvent,
+        PartialUploadEvent, SudoPasswordEvent, WriteFileEvent,
+    };
+
+    use crate::{audit::redact::redact, config::Config};
+
+    fn log() -> AuditLog {
+        let mut log = AuditLog::default();
+
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: "root".into(),
+                password: "hunter2".into(),
+            },
+        ));
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::KeyboardInteractive {
+                username: "root".into(),
+                submethods: "".into(),
+                responses: vec![KeyboardInteractiveResponse {
+                    prompt: "Password: ".into(),
+                    response: "hunter2".into(),
+                }],
+            },
+        ));
+        log.push_action(AuditLogAction::SudoPassword(SudoPasswordEvent {
+            username: "root".into(),
+            password: "hunter2".into(),
+            command: Box::default(),
+        }));
+        log.push_action(AuditLogAction::WriteFile(WriteFileEvent {
+            path: "/tmp/x".into(),
+            content: Bytes::from_static(b"test"),
+            size: 4,
+            sha256: Some("full".into()),
+        }));
+        log.push_action(AuditLogAction::PartialUpload(PartialUploadEvent {
+            protocol: "scp".into(),
+            path: "/tmp/y".into(),
+            bytes_received: 4,
+            expected_bytes: Some(8),
+            content: Some(Bytes::from_static(b"test")),
+            sha256: None,
+        }));
+
+        log
+    }
+
+    fn redacted(config: &str) -> String {
+        let config: Config = toml::from_str(config).unwrap();
+
+        let mut log = log();
+        redact(&mut log, &config.redaction);
+
+        serde_json::to_value(&log.events)
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["action"].to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn raw_by_default() {
+        assert_eq!(redacted(""), redacted("[redaction]\npasswords = \"raw\""));
+        assert!(redacted("").contains("hunter2"));
+    }
+
+    #[test]
+    fn hashes() {
+        insta::assert_snapshot!(redacted(
+            "[redaction]\npasswords = \"hash\"\nfile-contents = \"hash\"\nsalt = \"pepper\""
+        ));
+    }
+
+    #[test]
+    fn redacts() {
+        insta::assert_snapshot!(redacted(
+            "[redaction]\npasswords = \"redact\"\nfile-contents = \"redact\""
+        ));
+    }
+}
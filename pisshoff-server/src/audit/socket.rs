@@ -0,0 +1,174 @@
+//! Sends audit logs to a Unix socket, such as the one the exporter listens on, so logs can be
+//! ingested as they're written without anything following the log file.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::UnixStream,
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+/// How long to wait before the first attempt at reconnecting, doubled after each failure.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest to wait between attempts at reconnecting.
+const MAX_BACKOFF: Duration = Duration::from_mins(1);
+
+/// How long buffered logs are given to be sent once the server is shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the socket, maintained by a background task that buffers logs while the
+/// socket is unavailable.
+pub struct SocketSink {
+    send: mpsc::Sender<Vec<u8>>,
+    handle: JoinHandle<()>,
+    /// Whether logs have been dropped since the buffer was last full, so the warning is only
+    /// logged once each time.
+    dropping: bool,
+}
+
+impl SocketSink {
+    pub fn start(path: PathBuf, max_buffered: usize) -> Self {
+        let (send, recv) = mpsc::channel(max_buffered.max(1));
+        let handle = tokio::spawn(run(path, recv));
+
+        Self {
+            send,
+            handle,
+            dropping: false,
+        }
+    }
+
+    /// Queues a newline-terminated log to be sent, dropping it if the buffer is full.
+    pub fn write(&mut self, log: Vec<u8>) {
+        match self.send.try_send(log) {
+            Ok(()) => self.dropping = false,
+            Err(TrySendError::Full(_)) => {
+                if !self.dropping {
+                    warn!("Audit socket buffer is full, dropping logs until it's reconnected");
+                }
+
+                self.dropping = true;
+            }
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Waits a short while for buffered logs to be sent, giving up on any that can't be.
+    pub async fn close(self) {
+        drop(self.send);
+
+        let abort = self.handle.abort_handle();
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.handle)
+            .await
+            .is_err()
+        {
+            warn!("Timed out sending buffered logs to the audit socket");
+            abort.abort();
+        }
+    }
+}
+
+/// Sends every log received to the socket at `path`, reconnecting whenever a write fails and
+/// retrying the log that failed. Returns once every sender has been dropped.
+async fn run(path: PathBuf, mut recv: mpsc::Receiver<Vec<u8>>) {
+    let mut pending = None;
+
+    loop {
+        let mut stream = connect(&path).await;
+
+        loop {
+            let log = match pending.take() {
+                Some(log) => log,
+                None => match recv.recv().await {
+                    Some(log) => log,
+                    None => return,
+                },
+            };
+
+            if let Err(e) = stream.write_all(&log).await {
+                warn!("Lost connection to audit socket {}: {e}", path.display());
+                pending = Some(log);
+                break;
+            }
+        }
+    }
+}
+
+/// Connects to the socket at `path`, backing off between attempts until it succeeds.
+async fn connect(path: &Path) -> UnixStream {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        match UnixStream::connect(path).await {
+            Ok(stream) => {
+                info!("Connected to audit socket {}", path.display());
+                return stream;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to connect to audit socket {}, retrying in {}s: {e}",
+                    path.display(),
+                    backoff.as_secs()
+                );
+            }
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::UnixListener,
+    };
+
+    use crate::audit::socket::SocketSink;
+
+    #[tokio::test]
+    async fn sends_logs_and_reconnects() {
+        let dir = std::env::temp_dir().join(format!("pisshoff-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("audit.sock");
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut sink = SocketSink::start(path.clone(), 16);
+        sink.write(b"first\n".to_vec());
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("first"));
+
+        // the exporter going away shouldn't lose the logs written while it restarts
+        drop(lines);
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        sink.write(b"second\n".to_vec());
+        sink.write(b"third\n".to_vec());
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        sink.close().await;
+
+        let mut lines = BufReader::new(stream).lines();
+        let mut received = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            received.push(line);
+        }
+
+        // the first write after the connection drops can appear to succeed, so it may be lost
+        assert!(received.ends_with(&["third".to_string()]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
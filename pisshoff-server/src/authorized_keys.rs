@@ -0,0 +1,124 @@
+use std::path::Path;
+
+/// A public key read from an `authorized_keys` file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AuthorizedKey<'a> {
+    pub key_type: &'a str,
+    pub key: &'a str,
+    pub comment: Option<&'a str>,
+}
+
+/// Returns true if the given path is one sshd reads authorized keys from, ie.
+/// `~/.ssh/authorized_keys` or `~/.ssh/authorized_keys2`.
+pub fn is_authorized_keys_file(path: &Path) -> bool {
+    let is_authorized_keys = path
+        .file_name()
+        .and_then(|v| v.to_str())
+        .is_some_and(|v| v.starts_with("authorized_keys"));
+
+    let in_ssh_directory = path
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|v| v == ".ssh");
+
+    is_authorized_keys && in_ssh_directory
+}
+
+/// Parses the keys out of the content of an `authorized_keys` file, each line takes the form
+/// `[options] key-type base64-key [comment]`. Lines that don't contain a key are skipped.
+pub fn parse(content: &str) -> Vec<AuthorizedKey<'_>> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut rest = line;
+
+            // skip over any options preceding the key type
+            let key_type = loop {
+                let (token, remaining) = next_token(rest)?;
+                rest = remaining;
+
+                if is_key_type(token) {
+                    break token;
+                }
+            };
+
+            let (key, comment) = next_token(rest)?;
+
+            Some(AuthorizedKey {
+                key_type,
+                key,
+                comment: (!comment.is_empty()).then_some(comment),
+            })
+        })
+        .collect()
+}
+
+/// Splits the first whitespace-delimited token off of `s`, returning it alongside the rest of
+/// the string.
+fn next_token(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+
+    if s.is_empty() {
+        return None;
+    }
+
+    let (token, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    Some((token, rest.trim()))
+}
+
+fn is_key_type(v: &str) -> bool {
+    v.starts_with("ssh-") || v.starts_with("ecdsa-sha2-") || v.starts_with("sk-")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::authorized_keys::{is_authorized_keys_file, parse, AuthorizedKey};
+
+    #[test_case("/root/.ssh/authorized_keys", true; "absolute")]
+    #[test_case(".ssh/authorized_keys2", true; "relative")]
+    #[test_case("~/.ssh/authorized_keys", true; "tilde")]
+    #[test_case("/root/.ssh/known_hosts", false; "other ssh file")]
+    #[test_case("/tmp/authorized_keys", false; "outside ssh directory")]
+    fn authorized_keys_file(path: &str, expected: bool) {
+        assert_eq!(is_authorized_keys_file(Path::new(path)), expected);
+    }
+
+    #[test]
+    fn parses_keys() {
+        let keys = parse(
+            "# comment\n\
+             ssh-rsa AAAAB3NzaC1yc2E mdrfckr\n\
+             \n\
+             no-pty,command=\"/bin/true\" ssh-ed25519 AAAAC3NzaC1lZDI1NTE5 user@some host\n\
+             ecdsa-sha2-nistp256 AAAAE2VjZHNh\n\
+             garbage\n",
+        );
+
+        assert_eq!(
+            keys,
+            vec![
+                AuthorizedKey {
+                    key_type: "ssh-rsa",
+                    key: "AAAAB3NzaC1yc2E",
+                    comment: Some("mdrfckr"),
+                },
+                AuthorizedKey {
+                    key_type: "ssh-ed25519",
+                    key: "AAAAC3NzaC1lZDI1NTE5",
+                    comment: Some("user@some host"),
+                },
+                AuthorizedKey {
+                    key_type: "ecdsa-sha2-nistp256",
+                    key: "AAAAE2VjZHNh",
+                    comment: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! The time as seen by each connection, which is the system's clock unless the server is given
+//! a fixed one, such as in tests so that output and audit logs containing the time are
+//! deterministic.
+
+use std::{sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+use time::OffsetDateTime;
+
+use crate::audit::AuditLog;
+
+/// Where connections get the time from, passed to [`crate::Builder::clock`].
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    /// Stopped at a point in time, only moving when it's advanced. Clones share the same time.
+    Fixed(Arc<Mutex<OffsetDateTime>>),
+}
+
+impl Clock {
+    /// A clock stopped at `now`.
+    #[must_use]
+    pub fn fixed(now: OffsetDateTime) -> Self {
+        Self::Fixed(Arc::new(Mutex::new(now)))
+    }
+
+    #[must_use]
+    pub fn now(&self) -> OffsetDateTime {
+        match self {
+            Self::System => OffsetDateTime::now_utc(),
+            Self::Fixed(now) => *now.lock(),
+        }
+    }
+
+    /// How long it's been since the connection `log` belongs to was opened.
+    pub(crate) fn elapsed(&self, log: &AuditLog) -> Duration {
+        match self {
+            Self::System => log.start.elapsed(),
+            Self::Fixed(now) => (*now.lock() - log.ts).try_into().unwrap_or_default(),
+        }
+    }
+
+    /// Moves a fixed clock forward, the system clock moves by itself.
+    pub fn advance(&self, by: Duration) {
+        if let Self::Fixed(now) = self {
+            *now.lock() += by;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use pisshoff_types::audit::AuditLogAction;
+    use time::macros::datetime;
+
+    use crate::server::ConnectionState;
+
+    #[test]
+    fn times_audit_events_by_connection_clock() {
+        let mut connection = ConnectionState::mock();
+
+        connection.push_action(AuditLogAction::ShellRequested);
+        connection.clock().advance(Duration::from_secs(42));
+        connection.push_action(AuditLogAction::ShellRequested);
+
+        assert_eq!(connection.now(), datetime!(2024-05-02 13:37:42 UTC));
+
+        let offsets = connection
+            .audit_log()
+            .events
+            .iter()
+            .map(|v| v.start_offset)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [Duration::ZERO, Duration::from_secs(42)]);
+    }
+}
@@ -1,19 +1,71 @@
+//! Commands clients can run, either typed into the shell or sent in an exec request.
+//!
+//! Commands of your own can be added alongside the built-in ones by implementing [`Command`] and
+//! registering it with [`crate::Builder::command`].
+
 mod cat;
+mod cd;
+mod chattr;
+mod chmod;
+mod chown;
+mod container;
+mod crontab;
+mod date;
+mod dmesg;
+mod download;
 mod echo;
+mod editor;
+mod executable;
 mod exit;
+mod find;
+mod firewall;
+mod gzip;
+mod help;
+mod history;
+mod insmod;
+mod interpreter;
+mod journalctl;
 mod ls;
+mod lsmod;
+mod man;
+mod modprobe;
+mod package;
+mod printf;
 mod pwd;
+mod rm;
 mod scp;
+mod service;
+mod ssh;
+mod su;
+mod sudo;
+mod sysinfo;
+mod tar;
+mod test_builtin;
 mod uname;
+mod unzip;
+mod variables;
+mod which;
+mod who;
 mod whoami;
 
-use std::{borrow::Cow, fmt::Debug};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
 
-use async_trait::async_trait;
+pub use async_trait::async_trait;
+use futures::future::BoxFuture;
 use itertools::Either;
-use thrussh::ChannelId;
+pub use thrussh::{ChannelId, CryptoVec};
+use tracing::{debug, info_span, Instrument};
 
-use crate::server::{ConnectionState, ThrusshSession};
+use crate::{audit::DetectionProbe, config::CannedCommand, detection, subsystem::shell::Script};
+pub use crate::{
+    file_system::{FileSystem, LsError},
+    server::{ConnectionState, ThrusshSession},
+};
 
 #[derive(Debug)]
 pub enum CommandResult<T> {
@@ -59,6 +111,10 @@ pub trait Command: Sized {
         data: &[u8],
         session: &mut S,
     ) -> CommandResult<Self>;
+
+    /// Called if the channel the command is running on goes away while the command is still
+    /// waiting on stdin, such as when the client disconnects.
+    fn abort(self, _connection: &mut ConnectionState) {}
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -68,10 +124,17 @@ pub struct PartialCommand<'a> {
 }
 
 impl<'a> PartialCommand<'a> {
+    #[must_use]
     pub fn new(exec: Option<Cow<'a, [u8]>>, params: Vec<Cow<'a, [u8]>>) -> Self {
         Self { exec, params }
     }
 
+    /// Returns the command followed by its parameters as a single list of words.
+    #[must_use]
+    pub fn into_words(self) -> Vec<Cow<'a, [u8]>> {
+        self.exec.into_iter().chain(self.params).collect()
+    }
+
     pub async fn into_concrete_command<S: ThrusshSession + Send>(
         self,
         connection: &mut ConnectionState,
@@ -89,67 +152,342 @@ impl<'a> PartialCommand<'a> {
     }
 }
 
-macro_rules! define_commands {
-    ($($name:ident($ty:ty) = $command:expr),*) => {
-        #[derive(Debug, Clone)]
-        pub enum ConcreteCommand {
-            $($name($ty)),*
+/// Directories searched for commands when the client hasn't set a `PATH`.
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Starts a command, returning it with its type erased so commands can be looked up by name at
+/// runtime.
+pub type CommandFactory = for<'a> fn(
+    &'a mut ConnectionState,
+    &'a [String],
+    ChannelId,
+    &'a mut (dyn ThrusshSession + Send),
+) -> BoxFuture<'a, CommandResult<ConcreteCommand>>;
+
+fn start<'a, T: Command + Debug + Clone + Send + Sync + 'static>(
+    connection: &'a mut ConnectionState,
+    params: &'a [String],
+    channel: ChannelId,
+    mut session: &'a mut (dyn ThrusshSession + Send),
+) -> BoxFuture<'a, CommandResult<ConcreteCommand>> {
+    Box::pin(async move {
+        T::new(connection, params, channel, &mut session)
+            .await
+            .map(ConcreteCommand::wrap)
+    })
+}
+
+/// The commands available to clients by name, shared between every connection.
+pub struct Registry {
+    commands: HashMap<Box<[u8]>, CommandFactory>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut this = Self {
+            commands: HashMap::new(),
+        };
+
+        this.register::<echo::Echo>("echo");
+        this.register::<printf::Printf>("printf");
+        this.register::<date::Date>("date");
+        this.register::<exit::Exit>("exit");
+        this.register::<ls::Ls>("ls");
+        this.register::<pwd::Pwd>("pwd");
+        this.register::<cd::Cd>("cd");
+        this.register::<find::Find>("find");
+        this.register::<which::Which>("which");
+        this.register::<which::Whereis>("whereis");
+        this.register::<rm::Rm>("rm");
+        this.register::<rm::Shred>("shred");
+        this.register::<history::History>("history");
+        this.register::<variables::Export>("export");
+        this.register::<variables::Unset>("unset");
+        this.register::<scp::Scp>("scp");
+        this.register::<ssh::Ssh>("ssh");
+        this.register::<ssh::Telnet>("telnet");
+        this.register::<su::Su>("su");
+        this.register::<sudo::Sudo>("sudo");
+        this.register::<uname::Uname>("uname");
+        this.register::<sysinfo::Df>("df");
+        this.register::<sysinfo::Free>("free");
+        this.register::<sysinfo::Uptime>("uptime");
+        this.register::<sysinfo::Lscpu>("lscpu");
+        this.register::<whoami::Whoami>("whoami");
+        this.register::<who::Who>("who");
+        this.register::<who::W>("w");
+        this.register::<who::Last>("last");
+        this.register::<service::Systemctl>("systemctl");
+        this.register::<service::Service>("service");
+        this.register::<firewall::Iptables>("iptables");
+        this.register::<firewall::Ufw>("ufw");
+        this.register::<container::Docker>("docker");
+        this.register::<container::Kubectl>("kubectl");
+        this.register::<download::Curl>("curl");
+        this.register::<download::Wget>("wget");
+        this.register::<package::AptGet>("apt-get");
+        this.register::<package::AptGet>("apt");
+        this.register::<package::Yum>("yum");
+        this.register::<package::Yum>("dnf");
+        this.register::<package::Apk>("apk");
+        this.register::<cat::Cat>("cat");
+        this.register::<dmesg::Dmesg>("dmesg");
+        this.register::<journalctl::Journalctl>("journalctl");
+        this.register::<lsmod::Lsmod>("lsmod");
+        this.register::<modprobe::Modprobe>("modprobe");
+        this.register::<insmod::Insmod>("insmod");
+        this.register::<test_builtin::Test>("test");
+        this.register::<test_builtin::Bracket>("[");
+        this.register::<crontab::Crontab>("crontab");
+        this.register::<editor::Vi>("vi");
+        this.register::<editor::Vi>("vim");
+        this.register::<editor::Nano>("nano");
+        this.register::<man::Man>("man");
+        this.register::<chmod::Chmod>("chmod");
+        this.register::<chown::Chown>("chown");
+        this.register::<chattr::Chattr>("chattr");
+        this.register::<tar::Tar>("tar");
+        this.register::<gzip::Gzip>("gzip");
+        this.register::<gzip::Gunzip>("gunzip");
+        this.register::<unzip::Unzip>("unzip");
+        this.register::<interpreter::Interpreter<interpreter::Python>>("python");
+        this.register::<interpreter::Interpreter<interpreter::Python3>>("python3");
+        this.register::<interpreter::Interpreter<interpreter::Perl>>("perl");
+        this.register::<interpreter::Interpreter<interpreter::Php>>("php");
+        this.register::<Script>("sh");
+        this.register::<Script>("bash");
+
+        this
+    }
+}
+
+impl Registry {
+    /// Makes `T` available as `name`, replacing any command already registered with that name.
+    pub fn register<T: Command + Debug + Clone + Send + Sync + 'static>(&mut self, name: &str) {
+        self.register_factory(name, start::<T>);
+    }
+
+    pub fn register_factory(&mut self, name: &str, factory: CommandFactory) {
+        self.commands.insert(Box::from(name.as_bytes()), factory);
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &[u8]) -> Option<CommandFactory> {
+        self.commands.get(name).copied()
+    }
+}
+
+/// What a command name given by the client refers to.
+enum Resolved {
+    /// Fixed output defined in the config file.
+    Canned(CannedCommand),
+    /// A command built into the server, along with the name it's registered as.
+    Builtin(Vec<u8>, CommandFactory),
+    /// A file in the client's fake file system.
+    File(PathBuf),
+}
+
+/// Looks up a command the way a shell would. Canned commands from the config take precedence,
+/// followed by the built in commands and then files in each directory of `PATH`. Names
+/// containing a `/` are only looked up in the file system, falling back to the built in commands
+/// if they'd be where the real binary lives, ie. `/bin/ls`.
+fn resolve(connection: &mut ConnectionState, exec: &[u8]) -> Option<Resolved> {
+    let name = String::from_utf8_lossy(exec);
+
+    if name.contains('/') {
+        let path: PathBuf = connection
+            .file_system()
+            .pwd()
+            .join(name.as_ref())
+            .components()
+            .collect();
+
+        if connection.file_system().read(&path).is_ok() {
+            return Some(Resolved::File(path));
         }
 
-        impl ConcreteCommand {
-            pub async fn new<S: ThrusshSession + Send>(
-                connection: &mut ConnectionState,
-                exec: Option<&[u8]>,
-                params: &[String],
-                channel: ChannelId,
-                session: &mut S,
-            ) -> CommandResult<Self> {
-                let Some(command) = exec else {
-                    return CommandResult::Exit(0);
-                };
+        let (dir, command) = name.rsplit_once('/')?;
 
-                match command {
-                    $($command => <$ty as Command>::new(connection, &params, channel, session).await.map(Self::$name),)*
-                    other => {
-                        // TODO: fix stderr displaying out of order
-                        session.data(
-                            channel,
-                            format!("bash: {}: command not found\n", String::from_utf8_lossy(other)).into(),
-                        );
-                        CommandResult::Exit(1)
+        return DEFAULT_PATH
+            .split(':')
+            .any(|v| v == dir)
+            .then(|| connection.server_state().commands.get(command.as_bytes()))
+            .flatten()
+            .map(|factory| Resolved::Builtin(command.as_bytes().to_vec(), factory));
+    }
+
+    if let Some(canned) = connection.config().commands.get(name.as_ref()) {
+        return Some(Resolved::Canned(canned.clone()));
+    }
+
+    if let Some(factory) = connection.server_state().commands.get(exec) {
+        return Some(Resolved::Builtin(exec.to_vec(), factory));
+    }
+
+    let path = connection
+        .environment()
+        .get(b"PATH".as_slice())
+        .map_or_else(
+            || DEFAULT_PATH.to_string(),
+            |v| String::from_utf8_lossy(v).into_owned(),
+        );
+
+    path.split(':')
+        .filter(|v| !v.is_empty())
+        .map(|dir| Path::new(dir).join(name.as_ref()))
+        .find(|path| connection.file_system().read(path).is_ok())
+        .map(Resolved::File)
+}
+
+/// A command that's been started, with its type erased so it can come from the [`Registry`].
+#[derive(Debug)]
+pub struct ConcreteCommand(Box<dyn ErasedCommand>);
+
+impl Clone for ConcreteCommand {
+    fn clone(&self) -> Self {
+        Self(self.0.clone_box())
+    }
+}
+
+impl ConcreteCommand {
+    fn wrap<T: Command + Debug + Clone + Send + Sync + 'static>(command: T) -> Self {
+        Self(Box::new(command))
+    }
+
+    pub async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        exec: Option<&[u8]>,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let Some(exec) = exec else {
+            return CommandResult::Exit(0);
+        };
+
+        let span = info_span!(
+            "command",
+            command = %String::from_utf8_lossy(exec),
+            args = ?params,
+        );
+
+        Self::start(connection, exec, params, channel, session)
+            .instrument(span)
+            .await
+    }
+
+    async fn start<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        exec: &[u8],
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let delay = connection.throttle_command();
+        if !delay.is_zero() {
+            debug!(?delay, "Client is running commands too quickly, throttling");
+            tokio::time::sleep(delay).await;
+        }
+
+        let resolved = resolve(connection, exec);
+        connection
+            .server_state()
+            .command_counts
+            .record(&String::from_utf8_lossy(exec), resolved.is_some());
+
+        match resolved {
+            Some(Resolved::Canned(canned)) => {
+                if !canned.output.is_empty() {
+                    session.data(channel, connection.render_template(&canned.output).into());
+                }
+
+                CommandResult::Exit(canned.exit_code)
+            }
+            Some(Resolved::Builtin(command, factory)) => {
+                if help::requested(params) {
+                    if let Some(help) = help::help(&command, connection.config().personality) {
+                        session.data(channel, help.into());
+                        return CommandResult::Exit(0);
                     }
                 }
+
+                factory(connection, params, channel, session).await
+            }
+            Some(Resolved::File(path)) => {
+                executable::execute(connection, exec, &path, params, channel, session).await
             }
+            None => {
+                let name = String::from_utf8_lossy(exec);
 
-            pub async fn stdin<S: ThrusshSession + Send>(
-                self,
-                connection: &mut ConnectionState,
-                channel: ChannelId,
-                data: &[u8],
-                session: &mut S,
-            ) -> CommandResult<Self> {
-                match self {
-                    $(Self::$name(cmd) => {
-                        cmd
-                            .stdin(connection, channel, data, session)
-                            .await
-                            .map(Self::$name)
-                    }),*
-                }
+                let error = if exec.contains(&b'/') {
+                    "No such file or directory"
+                } else {
+                    if detection::is_nonsense_command(&name) {
+                        connection.record_detection_attempt(DetectionProbe::NonsenseCommand, &name);
+                    }
+
+                    "command not found"
+                };
+
+                session.stderr(channel, format!("bash: {name}: {error}\n").into());
+                CommandResult::Exit(127)
             }
         }
     }
+
+    pub async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        self.0.stdin(connection, channel, data, session).await
+    }
+
+    pub fn abort(self, connection: &mut ConnectionState) {
+        self.0.abort(connection);
+    }
+}
+
+/// Object safe version of [`Command`], implemented for every command so they can be stored in a
+/// [`ConcreteCommand`] while waiting on stdin.
+#[async_trait]
+trait ErasedCommand: Debug + Send + Sync {
+    async fn stdin(
+        self: Box<Self>,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut (dyn ThrusshSession + Send),
+    ) -> CommandResult<ConcreteCommand>;
+
+    fn abort(self: Box<Self>, connection: &mut ConnectionState);
+
+    fn clone_box(&self) -> Box<dyn ErasedCommand>;
 }
 
-define_commands! {
-    Echo(echo::Echo) = b"echo",
-    Exit(exit::Exit) = b"exit",
-    Ls(ls::Ls) = b"ls",
-    Pwd(pwd::Pwd) = b"pwd",
-    Scp(scp::Scp) = b"scp",
-    Uname(uname::Uname) = b"uname",
-    Whoami(whoami::Whoami) = b"whoami",
-    Cat(cat::Cat) = b"cat"
+#[async_trait]
+impl<T: Command + Debug + Clone + Send + Sync + 'static> ErasedCommand for T {
+    async fn stdin(
+        self: Box<Self>,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        mut session: &mut (dyn ThrusshSession + Send),
+    ) -> CommandResult<ConcreteCommand> {
+        Command::stdin(*self, connection, channel, data, &mut session)
+            .await
+            .map(ConcreteCommand::wrap)
+    }
+
+    fn abort(self: Box<Self>, connection: &mut ConnectionState) {
+        Command::abort(*self, connection);
+    }
+
+    fn clone_box(&self) -> Box<dyn ErasedCommand> {
+        Box::new(self.clone())
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -160,29 +498,137 @@ pub enum Arg<'a> {
 }
 
 fn argparse(args: &[String]) -> impl Iterator<Item = Arg<'_>> {
-    args.iter().flat_map(|rest| {
-        if let Some(rest) = rest.strip_prefix("--") {
-            Either::Left(std::iter::once(Arg::Long(rest)))
-        } else if let Some(rest) = rest.strip_prefix('-').filter(|v| !v.is_empty()) {
-            Either::Right(rest.chars().map(Arg::Short))
-        } else {
-            Either::Left(std::iter::once(Arg::Operand(rest)))
-        }
-    })
+    // everything after a bare `--` is an operand, even if it looks like an option
+    let (options, operands) = match args.iter().position(|v| v == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[][..]),
+    };
+
+    options
+        .iter()
+        .flat_map(|rest| {
+            if let Some(rest) = rest.strip_prefix("--") {
+                Either::Left(std::iter::once(Arg::Long(rest)))
+            } else if let Some(rest) = rest.strip_prefix('-').filter(|v| !v.is_empty()) {
+                Either::Right(rest.chars().map(Arg::Short))
+            } else {
+                Either::Left(std::iter::once(Arg::Operand(rest)))
+            }
+        })
+        .chain(operands.iter().map(|v| Arg::Operand(v)))
 }
 
 #[cfg(test)]
 mod test {
+    use mockall::predicate::always;
     use test_case::test_case;
 
-    use super::Arg;
+    use super::{Arg, CommandResult, ConcreteCommand};
+    use crate::{
+        config::{CannedCommand, Config},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
 
     #[test_case("-a", &[Arg::Short('a')]; "single short parameter")]
     #[test_case("-abc", &[Arg::Short('a'), Arg::Short('b'), Arg::Short('c')]; "multiple short parameter")]
     #[test_case("-a --long operand -b -", &[Arg::Short('a'), Arg::Long("long"), Arg::Operand("operand"), Arg::Short('b'), Arg::Operand("-")]; "full hit")]
+    #[test_case("-a -- -b --long", &[Arg::Short('a'), Arg::Operand("-b"), Arg::Operand("--long")]; "end of options")]
     fn argparse(input: &str, expected: &[Arg<'static>]) {
         let input = shlex::split(input).unwrap();
         let output = super::argparse(&input).collect::<Vec<_>>();
         assert_eq!(output, expected);
     }
+
+    #[tokio::test]
+    async fn help() {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(super::help::MANUALS[0].gnu))
+            .returning(|_, _| ());
+
+        let out = ConcreteCommand::new(
+            &mut ConnectionState::mock(),
+            Some(b"cat"),
+            &["-n".to_string(), "--help".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+
+    #[test_case(b"/bin/pwd", "/root\n", 0, false; "builtin by path")]
+    #[test_case(b"/tmp/pwd", "bash: /tmp/pwd: No such file or directory\n", 127, true; "missing path")]
+    #[test_case(b"nproc", "4\n", 0, false; "canned")]
+    #[test_case(b"uname", "Linux\n", 3, false; "canned overrides builtin")]
+    #[test_case(b"whoami", "root\n", 0, false; "canned template")]
+    #[test_case(b"nvidia-smi", "bash: nvidia-smi: command not found\n", 127, true; "not found")]
+    #[tokio::test]
+    async fn resolves(exec: &'static [u8], expected: &'static str, exit_code: u32, stderr: bool) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            commands: [
+                (
+                    "nproc".to_string(),
+                    CannedCommand {
+                        output: "4\n".to_string(),
+                        exit_code: 0,
+                    },
+                ),
+                (
+                    "uname".to_string(),
+                    CannedCommand {
+                        output: "Linux\n".to_string(),
+                        exit_code: 3,
+                    },
+                ),
+                (
+                    "whoami".to_string(),
+                    CannedCommand {
+                        output: "{{username}}\n".to_string(),
+                        exit_code: 0,
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Config::default()
+        });
+
+        if stderr {
+            session
+                .expect_stderr()
+                .once()
+                .with(always(), eq_string(expected))
+                .returning(|_, _| ());
+        } else {
+            session
+                .expect_data()
+                .once()
+                .with(always(), eq_string(expected))
+                .returning(|_, _| ());
+        }
+
+        let out = ConcreteCommand::new(
+            &mut state,
+            Some(exec),
+            &["hello".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(
+            matches!(out, CommandResult::Exit(v) if v == exit_code),
+            "{out:?}"
+        );
+    }
 }
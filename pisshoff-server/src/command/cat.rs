@@ -4,6 +4,7 @@ use async_trait::async_trait;
 use thrussh::ChannelId;
 
 use crate::{
+    audit::HoneytokenAccess,
     command::{Command, CommandResult},
     server::{ConnectionState, ThrusshSession},
 };
@@ -26,15 +27,15 @@ impl Cat {
                 return CommandResult::ReadStdin(self);
             }
 
+            connection.touch_file(Path::new(&param), HoneytokenAccess::Read);
+
             match connection.file_system().read(Path::new(&param)) {
                 Ok(content) => {
                     session.data(channel, content.to_vec().into());
                 }
                 Err(e) => {
                     self.status = 1;
-                    // TODO: stderr
-                    eprintln!("{e}");
-                    session.data(channel, format!("cat: {param}: {e}").into());
+                    session.stderr(channel, format!("cat: {param}: {e}\n").into());
                 }
             }
         }
@@ -127,9 +128,9 @@ mod test {
             .returning(|_, _| ());
 
         session
-            .expect_data()
+            .expect_stderr()
             .once()
-            .with(always(), eq_string("cat: b: No such file or directory"))
+            .with(always(), eq_string("cat: b: No such file or directory\n"))
             .returning(|_, _| ());
 
         session
@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Cd {}
+
+#[async_trait]
+impl Command for Cd {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        if params.len() > 1 {
+            session.stderr(channel, "bash: cd: too many arguments\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        let fs = connection.file_system();
+        let dir = params.first().map(String::as_str);
+
+        let res = match dir.filter(|v| *v != "~") {
+            Some(dir) => match dir.strip_prefix("~/") {
+                Some(rest) => {
+                    let home = fs.home().join(rest);
+                    fs.cd(home.to_str())
+                }
+                None => fs.cd(Some(dir)),
+            },
+            None => fs.cd(None),
+        };
+
+        if let Err(e) = res {
+            session.stderr(
+                channel,
+                format!("bash: cd: {}: {e}\n", dir.unwrap_or_default()).into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        command::{cd::Cd, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case(&[], "/root"; "home")]
+    #[test_case(&[".."], "/"; "parent")]
+    #[test_case(&["../../.."], "/"; "above root")]
+    #[test_case(&["/dev/../root/./.ssh"], "/root/.ssh"; "dots")]
+    #[test_case(&["~/.ssh"], "/root/.ssh"; "tilde")]
+    #[tokio::test]
+    async fn changes_directory(params: &[&str], pwd: &str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall("/root/.ssh".as_ref()).unwrap();
+
+        let params: Vec<_> = params.iter().map(ToString::to_string).collect();
+        let out = Cd::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(state.file_system().pwd().to_str().unwrap(), pwd);
+    }
+
+    #[test_case("missing", "bash: cd: missing: No such file or directory\n"; "missing")]
+    #[test_case("/dev/null", "bash: cd: /dev/null: Not a directory\n"; "not a directory")]
+    #[tokio::test]
+    async fn fails(dir: &str, error: &'static str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string(error))
+            .returning(|_, _| ());
+
+        let out = Cd::new(
+            &mut state,
+            &[dir.to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+        assert_eq!(state.file_system().pwd().to_str().unwrap(), "/root");
+    }
+}
@@ -0,0 +1,238 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, ChattrEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    file_system::LsError,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "Usage: chattr [-pRVf] [-+=aAcCdDeijPsStTuFxm] [-v version] files...\n";
+
+/// Attributes supported by `chattr`.
+const ATTRIBUTES: &str = "aAcCdDeijPsStTuFxm";
+
+/// Attributes that can only be changed by root, as they require `CAP_LINUX_IMMUTABLE`.
+const PRIVILEGED_ATTRIBUTES: &str = "ai";
+
+#[derive(Debug, Clone)]
+pub struct Chattr {}
+
+#[async_trait]
+impl Command for Chattr {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (err, exit_code) = execute(connection, params);
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// Changes to make to the attributes of each file.
+#[derive(Debug, Default)]
+struct Changes {
+    add: String,
+    remove: String,
+    set: Option<String>,
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut changes = Changes::default();
+    let mut silent = false;
+    let mut specs = Vec::new();
+    let mut params = params.iter().map(String::as_str).peekable();
+
+    // attribute changes and options precede the files, `-` is used for both removing
+    // attributes and passing options
+    while let Some(param) = params.next_if(|v| v.starts_with(['-', '+', '=']) && *v != "--") {
+        let (op, rest) = param.split_at(1);
+        let mut removed = String::new();
+
+        for c in rest.chars() {
+            match (op, c) {
+                ("-", 'R' | 'V') => {}
+                ("-", 'f') => silent = true,
+                (_, c) if ATTRIBUTES.contains(c) => match op {
+                    "+" => changes.add.push(c),
+                    "=" => changes.set.get_or_insert_with(String::new).push(c),
+                    _ => {
+                        changes.remove.push(c);
+                        removed.push(c);
+                    }
+                },
+                _ => return (USAGE.to_string(), 1),
+            }
+        }
+
+        if op != "-" || !removed.is_empty() {
+            specs.push(if op == "-" {
+                format!("-{removed}")
+            } else {
+                param.to_string()
+            });
+        }
+    }
+
+    if params.peek() == Some(&"--") {
+        params.next();
+    }
+
+    let files = params.collect::<Vec<_>>();
+
+    if specs.is_empty() {
+        return ("Must use '-v', =, - or +\n".to_string(), 1);
+    }
+
+    if files.is_empty() {
+        return (USAGE.to_string(), 1);
+    }
+
+    let attributes = specs.join(" ");
+    let privileged = changes
+        .add
+        .chars()
+        .chain(changes.remove.chars())
+        .chain(changes.set.iter().flat_map(|v| v.chars()))
+        .any(|c| PRIVILEGED_ATTRIBUTES.contains(c))
+        || changes.set.is_some();
+
+    let username = connection.username().to_string();
+    let mut err = String::new();
+    let mut exit_code = 0;
+
+    for file in files {
+        connection.push_action(AuditLogAction::Chattr(ChattrEvent {
+            path: Box::from(file),
+            attributes: Box::from(attributes.as_str()),
+        }));
+
+        let metadata = match connection.file_system().metadata_mut(Path::new(file)) {
+            Ok(metadata) => metadata,
+            // attributes aren't tracked for directories
+            Err(LsError::IsADirectory) => continue,
+            Err(e) => {
+                if !silent {
+                    writeln!(err, "chattr: {e} while trying to stat {file}").unwrap();
+                }
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        if username != "root" && (privileged || metadata.owner != username) {
+            if !silent {
+                writeln!(
+                    err,
+                    "chattr: Operation not permitted while setting flags on {file}"
+                )
+                .unwrap();
+            }
+            exit_code = 1;
+            continue;
+        }
+
+        if let Some(set) = &changes.set {
+            metadata.attributes = set.chars().collect();
+        }
+
+        metadata.attributes.extend(changes.add.chars());
+
+        for c in changes.remove.chars() {
+            metadata.attributes.remove(&c);
+        }
+    }
+
+    (err, exit_code)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{command::chattr::execute, server::ConnectionState};
+
+    #[test_case("", "Must use '-v', =, - or +\n", 1; "no attributes")]
+    #[test_case("+i", "Usage: chattr [-pRVf] [-+=aAcCdDeijPsStTuFxm] [-v version] files...\n", 1; "no files")]
+    #[test_case("+q payload", "Usage: chattr [-pRVf] [-+=aAcCdDeijPsStTuFxm] [-v version] files...\n", 1; "unknown attribute")]
+    #[test_case("+i missing", "chattr: No such file or directory while trying to stat missing\n", 1; "missing file")]
+    #[test_case("-R +i payload", "", 0; "recursive")]
+    #[test_case("-ia payload", "", 0; "remove")]
+    fn output(input: &str, expected_error: &str, expected_exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("payload"), Box::default())
+            .unwrap();
+
+        let input = shlex::split(input).unwrap();
+        let (err, exit_code) = execute(&mut state, &input);
+
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test]
+    fn immutable() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("payload"), Box::default())
+            .unwrap();
+
+        let input = shlex::split("+i payload").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+
+        assert!(state.file_system().remove(Path::new("payload")).is_err());
+        assert!(state
+            .file_system()
+            .write(Path::new("payload"), Box::default())
+            .is_err());
+
+        let input = shlex::split("-i payload").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+
+        assert!(state.file_system().remove(Path::new("payload")).is_ok());
+    }
+
+    #[test]
+    fn unprivileged() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("payload"), Box::default())
+            .unwrap();
+        state.set_username("ubuntu");
+
+        let input = shlex::split("+i payload").unwrap();
+        let (err, exit_code) = execute(&mut state, &input);
+
+        assert_eq!(
+            err,
+            "chattr: Operation not permitted while setting flags on payload\n"
+        );
+        assert_eq!(exit_code, 1);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, ChmodEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    file_system::LsError,
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Bits that are left untouched by symbolic modes that don't specify who they apply to.
+const UMASK: u32 = 0o022;
+
+#[derive(Debug, Clone)]
+pub struct Chmod {}
+
+#[async_trait]
+impl Command for Chmod {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// Flags controlling what's printed for each file.
+#[derive(Debug, Default)]
+struct Options {
+    verbose: bool,
+    changes: bool,
+    silent: bool,
+}
+
+fn parse_args(params: &[String]) -> Result<(Options, Vec<&str>), String> {
+    let mut options = Options::default();
+    let mut operands = Vec::new();
+    let mut options_ended = false;
+
+    for param in params {
+        if options_ended {
+            operands.push(param.as_str());
+            continue;
+        }
+
+        match param.as_str() {
+            "--" => options_ended = true,
+            "--verbose" => options.verbose = true,
+            "--changes" => options.changes = true,
+            "--silent" | "--quiet" => options.silent = true,
+            "--recursive" | "--preserve-root" | "--no-preserve-root" => {}
+            v if v.starts_with("--") => {
+                return Err(format!(
+                    "chmod: unrecognized option '{v}'\nTry 'chmod --help' for more information.\n"
+                ));
+            }
+            // symbolic modes such as `-x` look like options, so only the known option letters
+            // are treated as such
+            v if v.len() > 1
+                && v.starts_with('-')
+                && v[1..].chars().all(|c| "Rcfv".contains(c)) =>
+            {
+                options.verbose |= v.contains('v');
+                options.changes |= v.contains('c');
+                options.silent |= v.contains('f');
+            }
+            v => operands.push(v),
+        }
+    }
+
+    Ok((options, operands))
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, String, u32) {
+    let (
+        Options {
+            verbose,
+            changes,
+            silent,
+        },
+        operands,
+    ) = match parse_args(params) {
+        Ok(v) => v,
+        Err(e) => return (String::new(), e, 1),
+    };
+
+    let (mode, files) = match operands.as_slice() {
+        [] => {
+            return (
+                String::new(),
+                "chmod: missing operand\nTry 'chmod --help' for more information.\n".to_string(),
+                1,
+            )
+        }
+        [mode] => {
+            return (
+                String::new(),
+                format!(
+                "chmod: missing operand after '{mode}'\nTry 'chmod --help' for more information.\n"
+            ),
+                1,
+            )
+        }
+        [mode, files @ ..] => (*mode, files),
+    };
+
+    if apply_mode(mode, 0).is_none() {
+        return (
+            String::new(),
+            format!("chmod: invalid mode: '{mode}'\nTry 'chmod --help' for more information.\n"),
+            1,
+        );
+    }
+
+    let username = connection.username().to_string();
+    let mut out = String::new();
+    let mut err = String::new();
+    let mut exit_code = 0;
+
+    for file in files {
+        connection.push_action(AuditLogAction::Chmod(ChmodEvent {
+            path: Box::from(*file),
+            mode: Box::from(mode),
+        }));
+
+        let metadata = match connection.file_system().metadata_mut(Path::new(file)) {
+            Ok(metadata) => metadata,
+            // directories don't have any permissions to change
+            Err(LsError::IsADirectory) => continue,
+            Err(e) => {
+                if !silent {
+                    writeln!(err, "chmod: cannot access '{file}': {e}").unwrap();
+                }
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        if metadata.is_immutable() || (username != "root" && metadata.owner != username) {
+            if !silent {
+                writeln!(
+                    err,
+                    "chmod: changing permissions of '{file}': Operation not permitted"
+                )
+                .unwrap();
+            }
+            exit_code = 1;
+            continue;
+        }
+
+        let old = metadata.mode;
+        let new = apply_mode(mode, old).unwrap_or(old);
+        metadata.mode = new;
+
+        if verbose || (changes && old != new) {
+            if old == new {
+                writeln!(
+                    out,
+                    "mode of '{file}' retained as {new:04o} ({})",
+                    render_mode(new)
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    out,
+                    "mode of '{file}' changed from {old:04o} ({}) to {new:04o} ({})",
+                    render_mode(old),
+                    render_mode(new)
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    (out, err, exit_code)
+}
+
+/// Applies a mode in either octal (`755`) or symbolic (`u+x,go-w`) form to the current mode of
+/// a file, returning `None` if the mode is invalid.
+fn apply_mode(mode: &str, current: u32) -> Option<u32> {
+    if !mode.is_empty() && mode.len() <= 4 && mode.bytes().all(|c| (b'0'..=b'7').contains(&c)) {
+        return u32::from_str_radix(mode, 8).ok();
+    }
+
+    let mut current = current;
+
+    for clause in mode.split(',') {
+        let mut chars = clause.chars().peekable();
+
+        // which classes of user the clause applies to, none given means all of them
+        let mut who = 0;
+        while let Some(c) = chars.next_if(|c| "ugoa".contains(*c)) {
+            who |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                _ => 0o7777,
+            };
+        }
+
+        let mask = if who == 0 { 0o7777 & !UMASK } else { who };
+
+        // every clause needs at least one operation
+        chars.peek().filter(|c| "+-=".contains(**c))?;
+
+        while let Some(op) = chars.next_if(|c| "+-=".contains(*c)) {
+            let mut bits = 0;
+
+            // permissions can be copied from another class, ie. `g=u`
+            if let Some(class) = chars.next_if(|c| "ugo".contains(*c)) {
+                let shift = match class {
+                    'u' => 6,
+                    'g' => 3,
+                    _ => 0,
+                };
+                bits = ((current >> shift) & 0o7) * 0o111;
+            } else {
+                while let Some(c) = chars.next_if(|c| "rwxXst".contains(*c)) {
+                    bits |= match c {
+                        'r' => 0o444,
+                        'w' => 0o222,
+                        'x' => 0o111,
+                        // directories aren't tracked, so only files that are already executable match
+                        'X' if current & 0o111 != 0 => 0o111,
+                        's' => 0o6000,
+                        't' => 0o1000,
+                        _ => 0,
+                    };
+                }
+            }
+
+            let bits = bits & mask;
+
+            current = match op {
+                '+' => current | bits,
+                '-' => current & !bits,
+                _ => (current & !mask) | bits,
+            };
+        }
+
+        if chars.next().is_some() {
+            return None;
+        }
+    }
+
+    Some(current)
+}
+
+/// Renders permission bits in the form shown by `ls -l`, ie. `rwxr-xr-x`.
+pub fn render_mode(mode: u32) -> String {
+    let mut out = String::with_capacity(9);
+
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        out.push(if bits & 0o4 == 0 { '-' } else { 'r' });
+        out.push(if bits & 0o2 == 0 { '-' } else { 'w' });
+        out.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{
+        command::chmod::{apply_mode, execute, render_mode},
+        server::ConnectionState,
+    };
+
+    #[test_case("755", 0o644, Some(0o755); "octal")]
+    #[test_case("4755", 0o644, Some(0o4755); "octal setuid")]
+    #[test_case("+x", 0o644, Some(0o755); "add execute")]
+    #[test_case("+w", 0o644, Some(0o644); "umask protects group and other")]
+    #[test_case("a+w", 0o644, Some(0o666); "all add write")]
+    #[test_case("u+x,go-r", 0o644, Some(0o700); "multiple clauses")]
+    #[test_case("u=rwx,g=u,o=", 0o644, Some(0o770); "copy class")]
+    #[test_case("u+s", 0o755, Some(0o4755); "setuid")]
+    #[test_case("+t", 0o755, Some(0o1755); "sticky")]
+    #[test_case("a+X", 0o644, Some(0o644); "conditional execute")]
+    #[test_case("-x", 0o755, Some(0o644); "remove execute")]
+    #[test_case("abc", 0o644, None; "invalid")]
+    #[test_case("u", 0o644, None; "missing operator")]
+    #[test_case("8", 0o644, None; "invalid octal")]
+    fn mode(mode: &str, current: u32, expected: Option<u32>) {
+        assert_eq!(apply_mode(mode, current), expected);
+    }
+
+    #[test_case(0o755, "rwxr-xr-x"; "executable")]
+    #[test_case(0o4755, "rwsr-xr-x"; "setuid")]
+    #[test_case(0o1644, "rw-r--r-T"; "sticky without execute")]
+    fn render(mode: u32, expected: &str) {
+        assert_eq!(render_mode(mode), expected);
+    }
+
+    #[test_case("", "", "chmod: missing operand\nTry 'chmod --help' for more information.\n", 1; "no operands")]
+    #[test_case("+x", "", "chmod: missing operand after '+x'\nTry 'chmod --help' for more information.\n", 1; "no files")]
+    #[test_case("+q payload", "", "chmod: invalid mode: '+q'\nTry 'chmod --help' for more information.\n", 1; "invalid mode")]
+    #[test_case("+x missing", "", "chmod: cannot access 'missing': No such file or directory\n", 1; "missing file")]
+    #[test_case("-f +x missing", "", "", 1; "silent missing file")]
+    #[test_case("-v +x payload", "mode of 'payload' changed from 0644 (rw-r--r--) to 0755 (rwxr-xr-x)\n", "", 0; "verbose")]
+    #[test_case("-c 644 payload", "", "", 0; "changes unchanged")]
+    #[test_case("-x /root", "", "", 0; "directory")]
+    fn output(input: &str, expected_output: &str, expected_error: &str, expected_exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("payload"), b"#!/bin/sh".to_vec().into())
+            .unwrap();
+
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = execute(&mut state, &input);
+
+        assert_eq!(out, expected_output);
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test]
+    fn other_user() {
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("/tmp/payload"), Box::default())
+            .unwrap();
+        state.set_username("ubuntu");
+
+        let input = shlex::split("+x /tmp/payload").unwrap();
+        let (out, err, exit_code) = execute(&mut state, &input);
+
+        assert_eq!(out, "");
+        assert_eq!(
+            err,
+            "chmod: changing permissions of '/tmp/payload': Operation not permitted\n"
+        );
+        assert_eq!(exit_code, 1);
+    }
+}
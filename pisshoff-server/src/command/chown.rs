@@ -0,0 +1,323 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, ChownEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    file_system::LsError,
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Users with an entry in the fake `/etc/passwd`, alongside their uid.
+const USERS: &[(&str, u32)] = &[
+    ("root", 0),
+    ("daemon", 1),
+    ("bin", 2),
+    ("sys", 3),
+    ("sync", 4),
+    ("games", 5),
+    ("man", 6),
+    ("lp", 7),
+    ("mail", 8),
+    ("news", 9),
+    ("uucp", 10),
+    ("proxy", 13),
+    ("www-data", 33),
+    ("backup", 34),
+    ("list", 38),
+    ("irc", 39),
+    ("nobody", 65534),
+    ("systemd-network", 100),
+    ("systemd-resolve", 101),
+    ("messagebus", 102),
+    ("sshd", 105),
+    ("ubuntu", 1000),
+];
+
+/// Groups with an entry in the fake `/etc/group`, in addition to each user's login group.
+const GROUPS: &[(&str, u32)] = &[
+    ("adm", 4),
+    ("tty", 5),
+    ("disk", 6),
+    ("kmem", 15),
+    ("dialout", 20),
+    ("cdrom", 24),
+    ("sudo", 27),
+    ("audio", 29),
+    ("video", 44),
+    ("plugdev", 46),
+    ("staff", 50),
+    ("users", 100),
+    ("nogroup", 65534),
+];
+
+#[derive(Debug, Clone)]
+pub struct Chown {}
+
+#[async_trait]
+impl Command for Chown {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// Flags controlling what's printed for each file.
+#[derive(Debug, Default)]
+struct Options {
+    verbose: bool,
+    changes: bool,
+    silent: bool,
+}
+
+fn parse_args(params: &[String]) -> Result<(Options, Vec<&str>), String> {
+    let mut options = Options::default();
+    let mut operands = Vec::new();
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('v') | Arg::Long("verbose") => options.verbose = true,
+            Arg::Short('c') | Arg::Long("changes") => options.changes = true,
+            Arg::Short('f') | Arg::Long("silent" | "quiet") => options.silent = true,
+            Arg::Short('R' | 'h' | 'H' | 'L' | 'P')
+            | Arg::Long(
+                "recursive" | "no-dereference" | "dereference" | "preserve-root"
+                | "no-preserve-root",
+            ) => {}
+            Arg::Operand(v) => operands.push(v),
+            Arg::Short(c) => {
+                return Err(format!(
+                    "chown: invalid option -- '{c}'\nTry 'chown --help' for more information.\n"
+                ));
+            }
+            Arg::Long(v) => {
+                return Err(format!(
+                    "chown: unrecognized option '--{v}'\nTry 'chown --help' for more information.\n"
+                ));
+            }
+        }
+    }
+
+    Ok((options, operands))
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, String, u32) {
+    let (
+        Options {
+            verbose,
+            changes,
+            silent,
+        },
+        operands,
+    ) = match parse_args(params) {
+        Ok(v) => v,
+        Err(e) => return (String::new(), e, 1),
+    };
+
+    let (spec, files) = match operands.as_slice() {
+        [] => {
+            return (
+                String::new(),
+                "chown: missing operand\nTry 'chown --help' for more information.\n".to_string(),
+                1,
+            )
+        }
+        [spec] => {
+            return (
+                String::new(),
+                format!(
+                "chown: missing operand after '{spec}'\nTry 'chown --help' for more information.\n"
+            ),
+                1,
+            )
+        }
+        [spec, files @ ..] => (*spec, files),
+    };
+
+    let (owner, group) = match parse_spec(spec, connection.username()) {
+        Ok(v) => v,
+        Err(e) => return (String::new(), format!("chown: {e}\n"), 1),
+    };
+
+    let username = connection.username().to_string();
+    let mut out = String::new();
+    let mut err = String::new();
+    let mut exit_code = 0;
+
+    for file in files {
+        connection.push_action(AuditLogAction::Chown(ChownEvent {
+            path: Box::from(*file),
+            owner: owner.as_deref().map(Box::from),
+            group: group.as_deref().map(Box::from),
+        }));
+
+        let metadata = match connection.file_system().metadata_mut(Path::new(file)) {
+            Ok(metadata) => metadata,
+            // directories don't have any ownership to change
+            Err(LsError::IsADirectory) => continue,
+            Err(e) => {
+                if !silent {
+                    writeln!(err, "chown: cannot access '{file}': {e}").unwrap();
+                }
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        // only root can give files away
+        if metadata.is_immutable() || username != "root" {
+            if !silent {
+                writeln!(
+                    err,
+                    "chown: changing ownership of '{file}': Operation not permitted"
+                )
+                .unwrap();
+            }
+            exit_code = 1;
+            continue;
+        }
+
+        let old = format!("{}:{}", metadata.owner, metadata.group);
+
+        if let Some(owner) = &owner {
+            metadata.owner.clone_from(owner);
+        }
+
+        if let Some(group) = &group {
+            metadata.group.clone_from(group);
+        }
+
+        let new = format!("{}:{}", metadata.owner, metadata.group);
+
+        if verbose || (changes && old != new) {
+            if old == new {
+                writeln!(out, "ownership of '{file}' retained as {new}").unwrap();
+            } else {
+                writeln!(out, "changed ownership of '{file}' from {old} to {new}").unwrap();
+            }
+        }
+    }
+
+    (out, err, exit_code)
+}
+
+/// Parses an `[OWNER][:[GROUP]]` specification, resolving numeric ids to names. A trailing
+/// colon without a group changes the group to the owner's login group.
+fn parse_spec(spec: &str, current_user: &str) -> Result<(Option<String>, Option<String>), String> {
+    let (owner, group) = match spec.split_once([':', '.']) {
+        Some((owner, "")) => (owner, Some(owner)),
+        Some((owner, group)) => (owner, Some(group)),
+        None => (spec, None),
+    };
+
+    let owner = if owner.is_empty() {
+        None
+    } else {
+        Some(
+            resolve(owner, &[USERS], current_user)
+                .ok_or_else(|| format!("invalid user: '{spec}'"))?,
+        )
+    };
+
+    let group = match group {
+        None | Some("") => None,
+        Some(group) => Some(
+            resolve(group, &[GROUPS, USERS], current_user)
+                .ok_or_else(|| format!("invalid group: '{spec}'"))?,
+        ),
+    };
+
+    Ok((owner, group))
+}
+
+/// Resolves a name or numeric id against the given databases, the user the client is logged in
+/// as always exists.
+fn resolve(name: &str, databases: &[&[(&str, u32)]], current_user: &str) -> Option<String> {
+    if name == current_user {
+        return Some(name.to_string());
+    }
+
+    let id = name.parse::<u32>().ok();
+    let entry = databases
+        .iter()
+        .flat_map(|v| v.iter())
+        .find(|(v, uid)| *v == name || Some(*uid) == id);
+
+    match (entry, id) {
+        (Some((name, _)), _) => Some((*name).to_string()),
+        // ids without a name are valid, they're just displayed numerically
+        (None, Some(id)) => Some(id.to_string()),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{
+        command::chown::{execute, parse_spec},
+        server::ConnectionState,
+    };
+
+    #[test_case("www-data", Some("www-data"), None; "owner")]
+    #[test_case("www-data:", Some("www-data"), Some("www-data"); "owner login group")]
+    #[test_case(":adm", None, Some("adm"); "group")]
+    #[test_case("0:0", Some("root"), Some("root"); "numeric")]
+    #[test_case("1234.users", Some("1234"), Some("users"); "unnamed id")]
+    fn spec(spec: &str, owner: Option<&str>, group: Option<&str>) {
+        let (actual_owner, actual_group) = parse_spec(spec, "root").unwrap();
+        assert_eq!(actual_owner.as_deref(), owner);
+        assert_eq!(actual_group.as_deref(), group);
+    }
+
+    #[test_case("", "", "chown: missing operand\nTry 'chown --help' for more information.\n", 1; "no operands")]
+    #[test_case("nobody", "", "chown: missing operand after 'nobody'\nTry 'chown --help' for more information.\n", 1; "no files")]
+    #[test_case("hacker payload", "", "chown: invalid user: 'hacker'\n", 1; "unknown user")]
+    #[test_case("root:hacker payload", "", "chown: invalid group: 'root:hacker'\n", 1; "unknown group")]
+    #[test_case("nobody missing", "", "chown: cannot access 'missing': No such file or directory\n", 1; "missing file")]
+    #[test_case("-v nobody:nogroup payload", "changed ownership of 'payload' from root:root to nobody:nogroup\n", "", 0; "verbose")]
+    #[test_case("-c root payload", "", "", 0; "changes unchanged")]
+    fn output(input: &str, expected_output: &str, expected_error: &str, expected_exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("payload"), Box::default())
+            .unwrap();
+
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = execute(&mut state, &input);
+
+        assert_eq!(out, expected_output);
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+}
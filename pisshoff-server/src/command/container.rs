@@ -0,0 +1,508 @@
+//! Container and cluster management CLIs. Nothing is ever run, but every invocation is recorded
+//! in full since they're how clients try to break out of a container or into the cluster and
+//! cloud account the machine belongs to.
+
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    audit::{AuditLogAction, ContainerCommandEvent},
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const DOCKER_VERSION: &str = "Docker version 24.0.5, build ced0996\n";
+
+const DOCKER_USAGE: &str = "
+Usage:  docker [OPTIONS] COMMAND
+
+A self-sufficient runtime for containers
+
+Run 'docker COMMAND --help' for more information on a command.
+
+For more help on how to use Docker, head to https://docs.docker.com/go/guides/
+";
+
+/// Every top level `docker` command, anything else is rejected as a typo.
+const DOCKER_COMMANDS: &[&str] = &[
+    "attach",
+    "build",
+    "builder",
+    "buildx",
+    "commit",
+    "compose",
+    "config",
+    "container",
+    "context",
+    "cp",
+    "create",
+    "diff",
+    "events",
+    "exec",
+    "export",
+    "history",
+    "image",
+    "images",
+    "import",
+    "info",
+    "inspect",
+    "kill",
+    "load",
+    "login",
+    "logout",
+    "logs",
+    "manifest",
+    "network",
+    "node",
+    "pause",
+    "plugin",
+    "port",
+    "ps",
+    "pull",
+    "push",
+    "rename",
+    "restart",
+    "rm",
+    "rmi",
+    "run",
+    "save",
+    "search",
+    "secret",
+    "service",
+    "stack",
+    "start",
+    "stats",
+    "stop",
+    "swarm",
+    "system",
+    "tag",
+    "top",
+    "trust",
+    "unpause",
+    "update",
+    "version",
+    "volume",
+    "wait",
+];
+
+/// Commands taking the name of an existing container, none of which exist.
+const DOCKER_CONTAINER_COMMANDS: &[&str] = &[
+    "attach", "exec", "inspect", "kill", "logs", "restart", "rm", "start", "stop", "top",
+];
+
+/// Options to `docker run` and `docker create` taking a value, so the value isn't mistaken for
+/// the image.
+const DOCKER_RUN_VALUES: &[&str] = &[
+    "-e",
+    "--env",
+    "-h",
+    "--hostname",
+    "-l",
+    "--label",
+    "-p",
+    "--publish",
+    "-u",
+    "--user",
+    "-v",
+    "--volume",
+    "-w",
+    "--workdir",
+    "--cap-add",
+    "--device",
+    "--entrypoint",
+    "--ipc",
+    "--mount",
+    "--name",
+    "--net",
+    "--network",
+    "--pid",
+    "--restart",
+    "--security-opt",
+];
+
+const DOCKER_DAEMON_DOWN: &str =
+    "Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Is the docker daemon running?\n";
+
+const DOCKER_SOCKET_DENIED: &str = "permission denied while trying to connect to the Docker daemon socket at unix:///var/run/docker.sock: Get \"http://%2Fvar%2Frun%2Fdocker.sock/v1.24/containers/json\": dial unix /var/run/docker.sock: connect: permission denied\n";
+
+const DOCKER_REGISTRY_TIMEOUT: &str = "Error response from daemon: Get \"https://registry-1.docker.io/v2/\": net/http: request canceled while waiting for connection (Client.Timeout exceeded while awaiting headers)";
+
+const KUBECTL_USAGE: &str = "kubectl controls the Kubernetes cluster manager.
+
+ Find more information at: https://kubernetes.io/docs/reference/kubectl/
+";
+
+const KUBECTL_VERSION: &str = "Client Version: v1.28.2
+Kustomize Version: v5.0.4-0.20230601165947-6ce0bf390ce3
+";
+
+/// There's no kubeconfig, so `kubectl` falls back to a local API server that isn't there.
+const KUBECTL_REFUSED: &str =
+    "The connection to the server localhost:8080 was refused - did you specify the right host or port?\n";
+
+fn record(connection: &mut ConnectionState, command: &'static str, params: &[String]) {
+    connection.push_action(AuditLogAction::ContainerCommand(ContainerCommandEvent {
+        command: Cow::Borrowed(command),
+        args: params.to_vec().into_boxed_slice(),
+    }));
+}
+
+/// The image passed to `docker run`, `create` or `pull`, with the tag filled in.
+fn image(args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if DOCKER_RUN_VALUES.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return Some(if arg.rsplit('/').next().unwrap_or(arg).contains(':') {
+                arg.clone()
+            } else {
+                format!("{arg}:latest")
+            });
+        }
+    }
+
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct Docker {}
+
+#[async_trait]
+impl Command for Docker {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        record(connection, "docker", params);
+
+        let Some(position) = params.iter().position(|v| !v.starts_with('-')) else {
+            if params.iter().any(|v| v == "-v" || v == "--version") {
+                session.data(channel, DOCKER_VERSION.into());
+            } else {
+                session.data(channel, DOCKER_USAGE.into());
+            }
+
+            return CommandResult::Exit(0);
+        };
+
+        let command = params[position].as_str();
+        let args = &params[position + 1..];
+
+        if !DOCKER_COMMANDS.contains(&command) {
+            session.stderr(
+                channel,
+                format!("docker: '{command}' is not a docker command.\nSee 'docker --help'\n")
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        if connection.profile().is_container() {
+            session.stderr(channel, DOCKER_DAEMON_DOWN.into());
+            return CommandResult::Exit(1);
+        }
+
+        if connection.username() != "root" {
+            session.stderr(channel, DOCKER_SOCKET_DENIED.into());
+            return CommandResult::Exit(1);
+        }
+
+        match command {
+            "ps" => {
+                session.data(
+                    channel,
+                    "CONTAINER ID   IMAGE     COMMAND   CREATED   STATUS    PORTS     NAMES\n"
+                        .into(),
+                );
+            }
+            "images" => {
+                session.data(
+                    channel,
+                    "REPOSITORY   TAG       IMAGE ID   CREATED   SIZE\n".into(),
+                );
+            }
+            "run" | "create" | "pull" => {
+                let Some(image) = image(args) else {
+                    session.stderr(
+                        channel,
+                        format!(
+                            "\"docker {command}\" requires at least 1 argument.\nSee 'docker {command} --help'.\n"
+                        )
+                        .into(),
+                    );
+                    return CommandResult::Exit(1);
+                };
+
+                if command == "pull" {
+                    session.stderr(channel, format!("{DOCKER_REGISTRY_TIMEOUT}\n").into());
+                    return CommandResult::Exit(1);
+                }
+
+                session.stderr(
+                    channel,
+                    format!(
+                        "Unable to find image '{image}' locally\ndocker: {DOCKER_REGISTRY_TIMEOUT}.\nSee 'docker {command} --help'.\n"
+                    )
+                    .into(),
+                );
+                return CommandResult::Exit(125);
+            }
+            command if DOCKER_CONTAINER_COMMANDS.contains(&command) => {
+                let Some(container) = args.iter().find(|v| !v.starts_with('-')) else {
+                    session.stderr(
+                        channel,
+                        format!(
+                            "\"docker {command}\" requires at least 1 argument.\nSee 'docker {command} --help'.\n"
+                        )
+                        .into(),
+                    );
+                    return CommandResult::Exit(1);
+                };
+
+                session.stderr(
+                    channel,
+                    format!("Error response from daemon: No such container: {container}\n").into(),
+                );
+                return CommandResult::Exit(1);
+            }
+            _ => {}
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Kubectl {}
+
+#[async_trait]
+impl Command for Kubectl {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        record(connection, "kubectl", params);
+
+        match params
+            .iter()
+            .find(|v| !v.starts_with('-'))
+            .map(String::as_str)
+        {
+            None => {
+                session.data(channel, KUBECTL_USAGE.into());
+                CommandResult::Exit(0)
+            }
+            Some("version") => {
+                session.data(channel, KUBECTL_VERSION.into());
+
+                if params.iter().any(|v| v == "--client") {
+                    CommandResult::Exit(0)
+                } else {
+                    session.stderr(channel, KUBECTL_REFUSED.into());
+                    CommandResult::Exit(1)
+                }
+            }
+            Some(_) => {
+                session.stderr(channel, KUBECTL_REFUSED.into());
+                CommandResult::Exit(1)
+            }
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, ContainerCommandEvent};
+
+    use crate::{
+        command::{
+            container::{Docker, Kubectl},
+            Command, CommandResult,
+        },
+        config::{Config, SystemProfile},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    fn host() -> ConnectionState {
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            profile: SystemProfile::Ubuntu2204,
+            ..Config::default()
+        });
+        state
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn docker_ps_is_recorded() {
+        let mut session = MockThrusshSession::default();
+        let mut state = host();
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string(
+                    "CONTAINER ID   IMAGE     COMMAND   CREATED   STATUS    PORTS     NAMES\n",
+                ),
+            )
+            .returning(|_, _| ());
+
+        let out = Docker::new(
+            &mut state,
+            &args(&["ps", "-a"]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let AuditLogAction::ContainerCommand(ContainerCommandEvent { command, args }) =
+            &state.audit_log().events[0].action
+        else {
+            panic!("expected container command event");
+        };
+
+        assert_eq!(command, "docker");
+        assert_eq!(&**args, ["ps", "-a"]);
+    }
+
+    #[tokio::test]
+    async fn docker_run_cant_pull() {
+        let mut session = MockThrusshSession::default();
+        let mut state = host();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string(
+                    "Unable to find image 'alpine:latest' locally\ndocker: Error response from daemon: Get \"https://registry-1.docker.io/v2/\": net/http: request canceled while waiting for connection (Client.Timeout exceeded while awaiting headers).\nSee 'docker run --help'.\n",
+                ),
+            )
+            .returning(|_, _| ());
+
+        let out = Docker::new(
+            &mut state,
+            &args(&[
+                "run",
+                "--rm",
+                "-v",
+                "/:/mnt",
+                "--privileged",
+                "alpine",
+                "chroot",
+                "/mnt",
+                "sh",
+            ]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(125)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn docker_requires_root() {
+        let mut session = MockThrusshSession::default();
+        let mut state = host();
+        state.set_username("ubuntu");
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string(super::DOCKER_SOCKET_DENIED))
+            .returning(|_, _| ());
+
+        let out = Docker::new(&mut state, &args(&["ps"]), fake_channel_id(), &mut session).await;
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn docker_has_no_daemon_in_container() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string(super::DOCKER_DAEMON_DOWN))
+            .returning(|_, _| ());
+
+        let out = Docker::new(
+            &mut state,
+            &args(&["images"]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn kubectl_has_no_cluster() {
+        let mut session = MockThrusshSession::default();
+        let mut state = host();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string(super::KUBECTL_REFUSED))
+            .returning(|_, _| ());
+
+        let out = Kubectl::new(
+            &mut state,
+            &args(&["get", "pods", "-A"]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+
+        let AuditLogAction::ContainerCommand(ContainerCommandEvent { command, args }) =
+            &state.audit_log().events[0].action
+        else {
+            panic!("expected container command event");
+        };
+
+        assert_eq!(command, "kubectl");
+        assert_eq!(&**args, ["get", "pods", "-A"]);
+    }
+}
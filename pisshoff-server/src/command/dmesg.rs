@@ -0,0 +1,167 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, Duration, OffsetDateTime};
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Dmesg {}
+
+#[async_trait]
+impl Command for Dmesg {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &ConnectionState, params: &[String]) -> (String, String, u32) {
+    let mut human_timestamps = false;
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('T') | Arg::Long("ctime") => human_timestamps = true,
+            Arg::Short('H' | 'L' | 'x' | 'k') | Arg::Long("human" | "color" | "kernel") => {}
+            Arg::Short('c' | 'C') | Arg::Long("read-clear" | "clear") => {
+                if connection.username() != "root" {
+                    return (
+                        String::new(),
+                        "dmesg: klogctl failed: Operation not permitted\n".to_string(),
+                        1,
+                    );
+                }
+
+                return (String::new(), String::new(), 0);
+            }
+            Arg::Short(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "dmesg: invalid option -- '{s}'\nTry 'dmesg --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "dmesg: unrecognized option '--{s}'\nTry 'dmesg --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Operand(_) => {
+                return (
+                    String::new(),
+                    "dmesg: bad usage\nTry 'dmesg --help' for more information.\n".to_string(),
+                    1,
+                );
+            }
+        }
+    }
+
+    // kernel.dmesg_restrict is enabled by default on most distributions
+    if connection.username() != "root" {
+        return (
+            String::new(),
+            "dmesg: read kernel buffer failed: Operation not permitted\n".to_string(),
+            1,
+        );
+    }
+
+    let boot_time = connection.boot_time();
+    let mut out = String::new();
+
+    for (offset, message) in connection.profile().kernel_log() {
+        if human_timestamps {
+            let ts = boot_time + Duration::microseconds(offset);
+            let ts = ts
+                .format(format_description!(
+                    "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]:[second] [year]"
+                ))
+                .unwrap_or_default();
+
+            writeln!(out, "[{ts}] {message}").unwrap();
+        } else {
+            writeln!(
+                out,
+                "[{:>5}.{:06}] {message}",
+                offset / 1_000_000,
+                offset % 1_000_000
+            )
+            .unwrap();
+        }
+    }
+
+    (out, String::new(), 0)
+}
+
+/// Formats the given time since boot as a wall clock time, in the style used by syslog.
+pub fn syslog_timestamp(boot_time: OffsetDateTime, offset: Duration) -> String {
+    (boot_time + offset)
+        .format(format_description!(
+            "[month repr:short] [day padding:space] [hour]:[minute]:[second]"
+        ))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::dmesg::execute, server::ConnectionState};
+
+    #[test]
+    fn root() {
+        let (out, err, exit_code) = execute(&ConnectionState::mock(), &[]);
+
+        assert_eq!(exit_code, 0);
+        assert_eq!(err, "");
+        assert!(
+            out.starts_with("[    0.000000] Linux version 5.15.49"),
+            "{out}"
+        );
+        assert!(out.contains("[    7.915803] IPv6"), "{out}");
+    }
+
+    #[test_case("-T", 0; "human timestamps")]
+    #[test_case("-z", 1; "unknown short arg")]
+    #[test_case("--fake", 1; "unknown long arg")]
+    #[test_case("oper", 1; "operand")]
+    fn exit_code(input: &str, expected_exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (_out, err, exit_code) = execute(&ConnectionState::mock(), &input);
+        assert_eq!(exit_code, expected_exit_code);
+        assert_eq!(err.is_empty(), expected_exit_code == 0, "{err}");
+    }
+}
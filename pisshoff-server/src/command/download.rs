@@ -0,0 +1,744 @@
+//! HTTP clients. Nothing is ever fetched, every download is recorded and then fails to connect,
+//! apart from requests to the cloud metadata service which are answered with fake credentials
+//! so the client reveals what it does with them.
+
+use std::{borrow::Cow, fmt::Write as _, net::IpAddr, path::Path};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pisshoff_types::audit::{AuditLogAction, DownloadAttemptEvent};
+use thrussh::ChannelId;
+use time::macros::format_description;
+
+use crate::{
+    command::{Command, CommandResult},
+    metadata,
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Short `curl` options taking a value, which may be attached to the flag or the next argument.
+const CURL_SHORT_VALUES: &str = "AbcCdeEFHKmoruTwxX";
+
+/// Long `curl` options taking a value as the next argument.
+const CURL_LONG_VALUES: &[&str] = &[
+    "connect-timeout",
+    "cookie",
+    "cookie-jar",
+    "data",
+    "data-binary",
+    "data-raw",
+    "form",
+    "header",
+    "max-time",
+    "proxy",
+    "range",
+    "referer",
+    "request",
+    "retry",
+    "upload-file",
+    "user",
+    "write-out",
+];
+
+/// Short `wget` options taking a value, which may be attached to the flag or the next argument.
+const WGET_SHORT_VALUES: &str = "aeiOoPtTU";
+
+/// Long `wget` options taking a value as the next argument, if not given with `=`.
+const WGET_LONG_VALUES: &[&str] = &[
+    "append-output",
+    "body-data",
+    "directory-prefix",
+    "execute",
+    "header",
+    "input-file",
+    "method",
+    "output-document",
+    "output-file",
+    "password",
+    "post-data",
+    "timeout",
+    "tries",
+    "user",
+    "user-agent",
+];
+
+/// The parts of a URL needed to pretend to fetch it.
+#[derive(Clone, Copy)]
+struct Url<'a> {
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+impl<'a> Url<'a> {
+    /// Parses `url`, assuming `http` if there's no scheme as both clients do.
+    fn parse(url: &'a str) -> Option<Self> {
+        let (scheme, rest) = url.split_once("://").unwrap_or(("http", url));
+        let (authority, path) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+        let authority = authority.rsplit_once('@').map_or(authority, |(_, v)| v);
+
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => {
+                (&authority[..i], Some(&authority[i + 1..]))
+            }
+            _ => (authority, None),
+        };
+
+        let port = match port {
+            Some(port) => port.parse().ok()?,
+            None if scheme.eq_ignore_ascii_case("https") => 443,
+            None if scheme.eq_ignore_ascii_case("ftp") => 21,
+            None => 80,
+        };
+
+        (!host.is_empty()).then_some(Self {
+            host,
+            port,
+            path: if path.is_empty() { "/" } else { path },
+        })
+    }
+
+    /// The name of the file being fetched, if the path has one.
+    fn file_name(&self) -> Option<&'a str> {
+        let path = self.path.split(['?', '#']).next().unwrap_or_default();
+        path.rsplit('/').next().filter(|v| !v.is_empty())
+    }
+}
+
+/// How a request turned out.
+enum Fetched<'a> {
+    Metadata(Url<'a>, metadata::Response),
+    Refused(Url<'a>),
+    Unresolved(Url<'a>),
+    Invalid,
+}
+
+/// Records the client's attempt to fetch `url`, answering it if it's for the cloud metadata
+/// service.
+fn fetch<'a>(
+    connection: &mut ConnectionState,
+    command: &'static str,
+    url: &'a str,
+    destination: Option<&str>,
+    user_agent: Option<&str>,
+) -> Fetched<'a> {
+    let parsed = Url::parse(url);
+
+    if let Some(parsed) = parsed.filter(|v| metadata::is_metadata_host(v.host)) {
+        let response = connection.request_metadata(command, url, parsed.path);
+        return Fetched::Metadata(parsed, response);
+    }
+
+    connection.push_action(AuditLogAction::DownloadAttempt(DownloadAttemptEvent {
+        command: Cow::Borrowed(command),
+        url: Box::from(url),
+        destination: destination.map(Box::from),
+        user_agent_flag: user_agent.map(Box::from),
+    }));
+
+    match parsed {
+        Some(parsed)
+            if parsed
+                .host
+                .trim_matches(['[', ']'])
+                .parse::<IpAddr>()
+                .is_ok() =>
+        {
+            Fetched::Refused(parsed)
+        }
+        Some(parsed) => Fetched::Unresolved(parsed),
+        None => Fetched::Invalid,
+    }
+}
+
+/// Writes a fetched file to the fake file system, returning false if it couldn't be.
+fn save(connection: &mut ConnectionState, path: &str, content: &[u8]) -> bool {
+    connection.record_file_write(path, Bytes::copy_from_slice(content));
+
+    connection
+        .file_system()
+        .write(Path::new(path), Box::from(content))
+        .is_ok()
+}
+
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct CurlOptions {
+    urls: Vec<String>,
+    output: Option<String>,
+    remote_name: bool,
+    silent: bool,
+    show_error: bool,
+    fail: bool,
+    user_agent: Option<String>,
+}
+
+impl CurlOptions {
+    fn parse(params: &[String]) -> Self {
+        let mut options = Self::default();
+        let mut args = params.iter();
+
+        while let Some(arg) = args.next() {
+            if let Some(long) = arg.strip_prefix("--") {
+                match long {
+                    "output" => options.output = args.next().cloned(),
+                    "remote-name" => options.remote_name = true,
+                    "silent" => options.silent = true,
+                    "show-error" => options.show_error = true,
+                    "fail" => options.fail = true,
+                    "user-agent" => options.user_agent = args.next().cloned(),
+                    "url" => options.urls.extend(args.next().cloned()),
+                    long if CURL_LONG_VALUES.contains(&long) => {
+                        args.next();
+                    }
+                    _ => {}
+                }
+            } else if let Some(short) = arg.strip_prefix('-').filter(|v| !v.is_empty()) {
+                for (i, flag) in short.char_indices() {
+                    if CURL_SHORT_VALUES.contains(flag) {
+                        let value = Some(&short[i + 1..])
+                            .filter(|v| !v.is_empty())
+                            .map(ToString::to_string)
+                            .or_else(|| args.next().cloned());
+
+                        match flag {
+                            'o' => options.output = value,
+                            'A' => options.user_agent = value,
+                            _ => {}
+                        }
+
+                        break;
+                    }
+
+                    match flag {
+                        's' => options.silent = true,
+                        'S' => options.show_error = true,
+                        'f' => options.fail = true,
+                        'O' => options.remote_name = true,
+                        _ => {}
+                    }
+                }
+            } else {
+                options.urls.push(arg.clone());
+            }
+        }
+
+        options
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Curl {}
+
+impl Curl {
+    /// Fetches a single URL, returning the exit status and any error to print.
+    fn fetch<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        options: &CurlOptions,
+        url: &str,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> (u32, Option<String>) {
+        let destination = match (&options.output, options.remote_name) {
+            (Some(output), _) => Some(output.as_str()),
+            (None, true) => match Url::parse(url).and_then(|v| v.file_name()) {
+                Some(name) => Some(name),
+                None => {
+                    return (
+                        23,
+                        Some("curl: Remote file name has no length!".to_string()),
+                    )
+                }
+            },
+            (None, false) => None,
+        };
+
+        match fetch(
+            connection,
+            "curl",
+            url,
+            destination,
+            options.user_agent.as_deref(),
+        ) {
+            Fetched::Metadata(_, response) if options.fail && response.status >= 400 => (
+                22,
+                Some(format!(
+                    "curl: (22) The requested URL returned error: {}",
+                    response.status
+                )),
+            ),
+            Fetched::Metadata(_, response) => match destination {
+                Some(path) if !save(connection, path, response.body.as_bytes()) => (
+                    23,
+                    Some("curl: (23) Failure writing output to destination".to_string()),
+                ),
+                Some(_) => (0, None),
+                None => {
+                    session.data(channel, response.body.into());
+                    (0, None)
+                }
+            },
+            Fetched::Refused(url) => (
+                7,
+                Some(format!(
+                    "curl: (7) Failed to connect to {} port {}: Connection refused",
+                    url.host, url.port
+                )),
+            ),
+            Fetched::Unresolved(url) => (
+                6,
+                Some(format!("curl: (6) Could not resolve host: {}", url.host)),
+            ),
+            Fetched::Invalid => (
+                3,
+                Some("curl: (3) URL using bad/illegal format or missing URL".to_string()),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Curl {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = CurlOptions::parse(params);
+
+        if options.urls.is_empty() {
+            session.stderr(
+                channel,
+                "curl: try 'curl --help' or 'curl --manual' for more information\n".into(),
+            );
+            return CommandResult::Exit(2);
+        }
+
+        let mut status = 0;
+
+        for url in &options.urls {
+            let (exit, error) = Self::fetch(connection, &options, url, channel, session);
+            status = exit;
+
+            if let Some(error) = error.filter(|_| !options.silent || options.show_error) {
+                session.stderr(channel, format!("{error}\n").into());
+            }
+        }
+
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct WgetOptions {
+    urls: Vec<String>,
+    output_document: Option<String>,
+    directory_prefix: Option<String>,
+    quiet: bool,
+    user_agent: Option<String>,
+}
+
+impl WgetOptions {
+    fn parse(params: &[String]) -> Self {
+        let mut options = Self::default();
+        let mut args = params.iter();
+
+        while let Some(arg) = args.next() {
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None if WGET_LONG_VALUES.contains(&long) => (long, args.next().cloned()),
+                    None => (long, None),
+                };
+
+                match name {
+                    "output-document" => options.output_document = value,
+                    "directory-prefix" => options.directory_prefix = value,
+                    "user-agent" => options.user_agent = value,
+                    "quiet" => options.quiet = true,
+                    _ => {}
+                }
+            } else if let Some(short) = arg.strip_prefix('-').filter(|v| !v.is_empty()) {
+                for (i, flag) in short.char_indices() {
+                    if WGET_SHORT_VALUES.contains(flag) {
+                        let value = Some(&short[i + 1..])
+                            .filter(|v| !v.is_empty())
+                            .map(ToString::to_string)
+                            .or_else(|| args.next().cloned());
+
+                        match flag {
+                            'O' => options.output_document = value,
+                            'P' => options.directory_prefix = value,
+                            'U' => options.user_agent = value,
+                            _ => {}
+                        }
+
+                        break;
+                    }
+
+                    if flag == 'q' {
+                        options.quiet = true;
+                    }
+                }
+            } else {
+                options.urls.push(arg.clone());
+            }
+        }
+
+        options
+    }
+
+    /// Where the file fetched from `url` is saved, `-` being stdout.
+    fn destination(&self, url: &str) -> String {
+        if let Some(output) = &self.output_document {
+            return output.clone();
+        }
+
+        let name = Url::parse(url)
+            .and_then(|v| v.file_name())
+            .unwrap_or("index.html");
+
+        match &self.directory_prefix {
+            Some(prefix) => format!("{}/{name}", prefix.trim_end_matches('/')),
+            None => name.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Wget {}
+
+impl Wget {
+    /// Fetches a single URL, returning the exit status and the progress to print.
+    fn fetch<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        options: &WgetOptions,
+        url: &str,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> (u32, String) {
+        let now = connection
+            .now()
+            .format(format_description!(
+                "[year]-[month]-[day] [hour]:[minute]:[second]"
+            ))
+            .unwrap_or_default();
+        let shown = if url.contains("://") {
+            url.to_string()
+        } else {
+            format!("http://{url}")
+        };
+        let destination = options.destination(url);
+
+        let mut out = format!("--{now}--  {shown}\n");
+
+        let status = match fetch(
+            connection,
+            "wget",
+            url,
+            Some(&destination).filter(|v| *v != "-").map(String::as_str),
+            options.user_agent.as_deref(),
+        ) {
+            Fetched::Metadata(parsed, response) => {
+                write!(
+                    out,
+                    "Connecting to {}:{}... connected.\nHTTP request sent, awaiting response... {} {}\n",
+                    parsed.host,
+                    parsed.port,
+                    response.status,
+                    response.reason()
+                )
+                .unwrap();
+
+                if response.status >= 400 {
+                    write!(
+                        out,
+                        "{now} ERROR {}: {}.\n\n",
+                        response.status,
+                        response.reason()
+                    )
+                    .unwrap();
+                    return (8, out);
+                }
+
+                let len = response.body.len();
+                let (name, saved) = if destination == "-" {
+                    session.data(channel, response.body.into());
+                    ("STDOUT", "written to stdout")
+                } else if save(connection, &destination, response.body.as_bytes()) {
+                    (destination.as_str(), "saved")
+                } else {
+                    writeln!(out, "{destination}: Permission denied").unwrap();
+                    return (3, out);
+                };
+
+                write!(
+                    out,
+                    "Length: {len} [{}]\nSaving to: ‘{name}’\n\n{name:<20}100%[===================>] {len:>8}  --.-KB/s    in 0s\n\n{now} (1.21 MB/s) - ‘{name}’ {saved} [{len}/{len}]\n\n",
+                    response.content_type,
+                )
+                .unwrap();
+                0
+            }
+            Fetched::Refused(parsed) => {
+                writeln!(
+                    out,
+                    "Connecting to {}:{}... failed: Connection refused.",
+                    parsed.host, parsed.port
+                )
+                .unwrap();
+                4
+            }
+            Fetched::Unresolved(parsed) => {
+                write!(
+                    out,
+                    "Resolving {host} ({host})... failed: Name or service not known.\nwget: unable to resolve host address ‘{host}’\n",
+                    host = parsed.host
+                )
+                .unwrap();
+                4
+            }
+            Fetched::Invalid => {
+                out = format!("{shown}: Invalid host name.\n");
+                1
+            }
+        };
+
+        (status, out)
+    }
+}
+
+#[async_trait]
+impl Command for Wget {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = WgetOptions::parse(params);
+
+        if options.urls.is_empty() {
+            session.stderr(
+                channel,
+                "wget: missing URL\nUsage: wget [OPTION]... [URL]...\n\nTry `wget --help' for more options.\n"
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        let mut status = 0;
+
+        for url in &options.urls {
+            let (exit, progress) = Self::fetch(connection, &options, url, channel, session);
+            status = exit;
+
+            if !options.quiet {
+                session.stderr(channel, progress.into());
+            }
+        }
+
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, DownloadAttemptEvent, MetadataAccessEvent};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            download::{Curl, Url, Wget},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test_case("http://1.2.3.4/x", "1.2.3.4", 80, "/x"; "http")]
+    #[test_case("https://example.com", "example.com", 443, "/"; "https")]
+    #[test_case("user:pass@1.2.3.4:8080/a?b", "1.2.3.4", 8080, "/a?b"; "no scheme")]
+    #[test_case("http://[fd00:ec2::254]/latest", "[fd00:ec2::254]", 80, "/latest"; "ipv6")]
+    fn parses_urls(url: &str, host: &str, port: u16, path: &str) {
+        let url = Url::parse(url).unwrap();
+        assert_eq!((url.host, url.port, url.path), (host, port, path));
+    }
+
+    #[tokio::test]
+    async fn curl_fetches_metadata_credentials() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .withf(|_, data| String::from_utf8_lossy(data).contains("\"AccessKeyId\" : \"ASIA"))
+            .returning(|_, _| ());
+
+        let out = Curl::new(
+            &mut state,
+            &args(&[
+                "-s",
+                "http://169.254.169.254/latest/meta-data/iam/security-credentials/ec2-app-server",
+            ]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        assert_eq!(
+            state
+                .audit_log()
+                .tags
+                .get("metadata_access")
+                .map(AsRef::as_ref),
+            Some("1")
+        );
+
+        let AuditLogAction::MetadataAccess(MetadataAccessEvent {
+            command,
+            credentials,
+            ..
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected metadata access event");
+        };
+
+        assert_eq!(command, "curl");
+        assert!(credentials);
+    }
+
+    #[tokio::test]
+    async fn curl_fails_to_connect() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string("curl: (7) Failed to connect to 1.2.3.4 port 80: Connection refused\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Curl::new(
+            &mut state,
+            &args(&["-sSLo", "/tmp/x", "-A", "Mozilla/5.0", "http://1.2.3.4/x"]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(7)), "{out:?}");
+
+        let AuditLogAction::DownloadAttempt(DownloadAttemptEvent {
+            command,
+            url,
+            destination,
+            user_agent_flag,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected download attempt event");
+        };
+
+        assert_eq!(command, "curl");
+        assert_eq!(&**url, "http://1.2.3.4/x");
+        assert_eq!(destination.as_deref(), Some("/tmp/x"));
+        assert_eq!(user_agent_flag.as_deref(), Some("Mozilla/5.0"));
+    }
+
+    #[tokio::test]
+    async fn wget_saves_metadata() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+
+        let out = Wget::new(
+            &mut state,
+            &args(&[
+                "-q",
+                "-P",
+                "/tmp",
+                "169.254.169.254/latest/dynamic/instance-identity/document",
+            ]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let document = state
+            .file_system()
+            .read(Path::new("/tmp/document"))
+            .unwrap()
+            .to_vec();
+        assert!(String::from_utf8(document)
+            .unwrap()
+            .contains("\"instanceId\" : \"i-0a3f9c27d5e81b46f\""));
+    }
+
+    #[tokio::test]
+    async fn wget_cant_resolve() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_stderr()
+            .once()
+            .withf(|_, data| {
+                String::from_utf8_lossy(data).ends_with(
+                    "--  http://example.com/x.sh\nResolving example.com (example.com)... failed: Name or service not known.\nwget: unable to resolve host address ‘example.com’\n",
+                )
+            })
+            .returning(|_, _| ());
+
+        let out = Wget::new(
+            &mut state,
+            &args(&["example.com/x.sh"]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(4)), "{out:?}");
+
+        let AuditLogAction::DownloadAttempt(DownloadAttemptEvent { destination, .. }) =
+            &state.audit_log().events[0].action
+        else {
+            panic!("expected download attempt event");
+        };
+
+        assert_eq!(destination.as_deref(), Some("x.sh"));
+    }
+}
@@ -7,6 +7,71 @@ use crate::{
     server::{ConnectionState, ThrusshSession},
 };
 
+/// Interprets the backslash escapes understood by `echo -e` and `printf %b`, returning the bytes to print and
+/// whether `\c` asked for the rest of the output, including the trailing newline, to be dropped.
+pub fn unescape(text: &str) -> (Vec<u8>, bool) {
+    let mut out = Vec::with_capacity(text.len());
+    let mut bytes = text.as_bytes();
+
+    while let Some((&c, rest)) = bytes.split_first() {
+        bytes = rest;
+
+        if c != b'\\' {
+            out.push(c);
+            continue;
+        }
+
+        let Some((&escape, rest)) = bytes.split_first() else {
+            out.push(b'\\');
+            break;
+        };
+        bytes = rest;
+
+        match escape {
+            b'a' => out.push(0x07),
+            b'b' => out.push(0x08),
+            b'c' => return (out, true),
+            b'e' | b'E' => out.push(0x1b),
+            b'f' => out.push(0x0c),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'v' => out.push(0x0b),
+            b'\\' => out.push(b'\\'),
+            b'0' => {
+                let len = bytes
+                    .iter()
+                    .take(3)
+                    .take_while(|v| matches!(v, b'0'..=b'7'))
+                    .count();
+                let value = bytes[..len]
+                    .iter()
+                    .fold(0_u8, |acc, v| acc.wrapping_mul(8).wrapping_add(v - b'0'));
+                out.push(value);
+                bytes = &bytes[len..];
+            }
+            b'x' => {
+                let len = bytes
+                    .iter()
+                    .take(2)
+                    .take_while(|v| v.is_ascii_hexdigit())
+                    .count();
+
+                if len == 0 {
+                    out.extend_from_slice(b"\\x");
+                } else {
+                    let digits = std::str::from_utf8(&bytes[..len]).unwrap_or_default();
+                    out.push(u8::from_str_radix(digits, 16).unwrap_or_default());
+                    bytes = &bytes[len..];
+                }
+            }
+            other => out.extend_from_slice(&[b'\\', other]),
+        }
+    }
+
+    (out, false)
+}
+
 #[derive(Debug, Clone)]
 pub struct Echo {}
 
@@ -18,12 +83,44 @@ impl Command for Echo {
         channel: ChannelId,
         session: &mut S,
     ) -> CommandResult<Self> {
-        let suffix = if session.redirected() { "" } else { "\n" };
+        let mut newline = !session.redirected();
+        let mut escapes = false;
+
+        // options are only recognised before the first operand, and anything else starting with a
+        // dash is printed as is
+        let options = params
+            .iter()
+            .take_while(|v| {
+                v.strip_prefix('-')
+                    .is_some_and(|v| !v.is_empty() && v.chars().all(|c| "neE".contains(c)))
+            })
+            .inspect(|v| {
+                for c in v.chars() {
+                    match c {
+                        'n' => newline = false,
+                        'e' => escapes = true,
+                        'E' => escapes = false,
+                        _ => {}
+                    }
+                }
+            })
+            .count();
+
+        let text = params[options..].iter().join(" ");
+
+        let mut out = if escapes {
+            let (out, stop) = unescape(&text);
+            newline &= !stop;
+            out
+        } else {
+            text.into_bytes()
+        };
+
+        if newline {
+            out.push(b'\n');
+        }
 
-        session.data(
-            channel,
-            format!("{}{suffix}", params.iter().join(" ")).into(),
-        );
+        session.data(channel, out.into());
 
         CommandResult::Exit(0)
     }
@@ -55,6 +152,12 @@ mod test {
     #[test_case(&[], "\n"; "no parameters")]
     #[test_case(&["hello"], "hello\n"; "single parameter")]
     #[test_case(&["hello", "world"], "hello world\n"; "multiple parameters")]
+    #[test_case(&["-n", "hello"], "hello"; "no newline")]
+    #[test_case(&["-e", "\\x6F\\x6B"], "ok\n"; "hex escapes")]
+    #[test_case(&["-en", "\\061\\x33\\0063\\x37"], "1337"; "octal escapes")]
+    #[test_case(&["-e", "a\\tb\\\\c\\q\\cdropped"], "a\tb\\c\\q"; "stops at c")]
+    #[test_case(&["\\x6F\\x6B"], "\\x6F\\x6B\n"; "escapes need e")]
+    #[test_case(&["-E", "-e", "-x", "-n"], "-x -n\n"; "options end at first operand")]
     #[tokio::test]
     async fn test(params: &[&str], output: &'static str) {
         let mut session = MockThrusshSession::default();
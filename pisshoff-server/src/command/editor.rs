@@ -0,0 +1,889 @@
+//! Just enough of `vi` and `nano` for a client to hand-edit a file and save it, so whatever they
+//! wrote to it is audited the same as any other file write. There's no real screen to draw, keys
+//! are applied to the buffer as they arrive.
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    file_system::LsError,
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Switches to the terminal's alternate screen and clears it, as full-screen editors do.
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[H\x1b[2J";
+
+/// Switches back to the screen the shell was drawing on.
+const LEAVE_SCREEN: &str = "\x1b[?1049l";
+
+const NANO_SHORTCUTS: &str = "^G Help      ^O Write Out  ^W Where Is   ^K Cut        ^T Execute\n\
+                              ^X Exit      ^R Read File  ^\\ Replace    ^U Paste      ^J Justify\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Splits the client's input into keys, picking out the escape sequences sent for the arrow
+/// keys so they aren't taken as an escape followed by commands.
+fn keys(data: &[u8]) -> Vec<Key> {
+    let chars = String::from_utf8_lossy(data).chars().collect::<Vec<_>>();
+    let mut keys = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+
+        if c != '\x1b' {
+            keys.push(Key::Char(c));
+            continue;
+        }
+
+        // `\x1b[A` or `\x1bOA`, with any other sequence such as `\x1b[3~` being skipped
+        let sequence = match chars.get(i) {
+            Some('[') => chars[i + 1..]
+                .iter()
+                .position(|c| !c.is_ascii_digit() && *c != ';')
+                .map(|end| (chars[i + 1 + end], end + 2)),
+            Some('O') => chars
+                .get(i + 1)
+                .filter(|c| matches!(c, 'A'..='D'))
+                .map(|c| (*c, 2)),
+            _ => None,
+        };
+
+        let Some((end, len)) = sequence else {
+            keys.push(Key::Escape);
+            continue;
+        };
+
+        i += len;
+        keys.extend(match end {
+            'A' => Some(Key::Up),
+            'B' => Some(Key::Down),
+            'C' => Some(Key::Right),
+            'D' => Some(Key::Left),
+            _ => None,
+        });
+    }
+
+    keys
+}
+
+/// The file being edited, along with where the cursor is in it.
+#[derive(Debug, Clone)]
+struct Buffer {
+    path: Option<String>,
+    lines: Vec<String>,
+    line: usize,
+    /// Byte offset of the cursor into the current line.
+    column: usize,
+    /// Whether the file didn't exist when the editor was opened.
+    new: bool,
+    /// Whether there are changes that haven't been written yet.
+    modified: bool,
+    /// Whether an empty last line is left out when writing, as nano always keeps one at the end
+    /// of the buffer to type into.
+    magic_line: bool,
+}
+
+impl Buffer {
+    fn open(connection: &mut ConnectionState, path: Option<&str>) -> Self {
+        let content = path.and_then(|path| connection.file_system().read(Path::new(path)).ok());
+
+        Self {
+            path: path.map(ToString::to_string),
+            lines: content
+                .map(|v| {
+                    String::from_utf8_lossy(v)
+                        .lines()
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            line: 0,
+            column: 0,
+            new: content.is_none(),
+            modified: false,
+            magic_line: false,
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.lines.iter().map(|v| v.len() + 1).sum()
+    }
+
+    fn content(&self) -> Vec<u8> {
+        let mut content = Vec::with_capacity(self.bytes());
+        let mut lines = self.lines.as_slice();
+
+        if self.magic_line {
+            if let Some((last, rest)) = lines.split_last() {
+                if last.is_empty() {
+                    lines = rest;
+                }
+            }
+        }
+
+        for line in lines {
+            content.extend_from_slice(line.as_bytes());
+            content.push(b'\n');
+        }
+
+        content
+    }
+
+    /// Writes the buffer to `path`, auditing the write even if it fails.
+    fn save(&mut self, connection: &mut ConnectionState, path: &str) -> Result<(), LsError> {
+        let content = self.content();
+        connection.record_file_write(path, Bytes::from(content.clone()));
+        connection
+            .file_system()
+            .write(Path::new(path), content.into_boxed_slice())?;
+
+        self.modified = false;
+        Ok(())
+    }
+
+    fn current(&mut self) -> &mut String {
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+
+        &mut self.lines[self.line]
+    }
+
+    fn insert(&mut self, c: char) {
+        let column = self.column;
+        let current = self.current();
+
+        if c == '\n' {
+            let rest = current.split_off(column);
+            self.line += 1;
+            self.column = 0;
+            self.lines.insert(self.line, rest);
+        } else {
+            current.insert(column, c);
+            self.column += c.len_utf8();
+        }
+
+        self.modified = true;
+    }
+
+    fn backspace(&mut self) {
+        if self.column > 0 {
+            let column = self.column;
+            let current = self.current();
+
+            if let Some(c) = current[..column].chars().next_back() {
+                current.remove(column - c.len_utf8());
+                self.column -= c.len_utf8();
+            }
+        } else if self.line > 0 {
+            let current = self.lines.remove(self.line);
+            self.line -= 1;
+            self.column = self.lines[self.line].len();
+            self.lines[self.line].push_str(&current);
+        } else {
+            return;
+        }
+
+        self.modified = true;
+    }
+
+    /// Moves the cursor to `line`, keeping it as close to the same column as the line allows.
+    fn move_to(&mut self, line: usize) {
+        self.line = line.min(self.lines.len().saturating_sub(1));
+
+        let current = self.lines.get(self.line).map_or("", String::as_str);
+        self.column = self.column.min(current.len());
+        while !current.is_char_boundary(self.column) {
+            self.column -= 1;
+        }
+    }
+
+    fn left(&mut self) {
+        let column = self.column;
+
+        if let Some(c) = self.current()[..column].chars().next_back() {
+            self.column -= c.len_utf8();
+        }
+    }
+
+    fn right(&mut self) {
+        let column = self.column;
+
+        if let Some(c) = self.current()[column..].chars().next() {
+            self.column += c.len_utf8();
+        }
+    }
+
+    /// Adds an empty line below the cursor, or above it, and moves onto it.
+    fn open_line(&mut self, below: bool) {
+        if !self.lines.is_empty() && below {
+            self.line += 1;
+        }
+
+        self.lines.insert(self.line, String::new());
+        self.column = 0;
+        self.modified = true;
+    }
+
+    /// Removes the line the cursor is on, and every line after it if `to_end` is set.
+    fn delete_lines(&mut self, to_end: bool) {
+        if self.lines.is_empty() {
+            return;
+        }
+
+        if to_end {
+            self.lines.truncate(self.line);
+        } else {
+            self.lines.remove(self.line);
+        }
+
+        self.column = 0;
+        self.move_to(self.line);
+        self.modified = true;
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ViMode {
+    /// Waiting on a command, holding the first key of a two key command such as `dd`.
+    Normal(Option<char>),
+    Insert,
+    /// Typing an ex command after `:`.
+    CommandLine(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Vi {
+    buffer: Buffer,
+    mode: ViMode,
+    pty: bool,
+}
+
+#[async_trait]
+impl Command for Vi {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let path = params.iter().find(|v| !v.starts_with(['-', '+']));
+        let buffer = Buffer::open(connection, path.map(String::as_str));
+        let pty = connection.has_pty(channel);
+
+        let status = match &buffer.path {
+            Some(path) if buffer.new => format!("\"{path}\" [New]"),
+            Some(path) => format!("\"{path}\" {}L, {}B", buffer.lines.len(), buffer.bytes()),
+            None => String::new(),
+        };
+
+        if pty {
+            session.data(
+                channel,
+                format!(
+                    "{ENTER_SCREEN}{}{status}\n",
+                    String::from_utf8_lossy(&buffer.content())
+                )
+                .into(),
+            );
+        } else {
+            session.stderr(
+                channel,
+                "Vim: Warning: Output is not to a terminal\nVim: Warning: Input is not from a terminal\n"
+                    .into(),
+            );
+        }
+
+        CommandResult::ReadStdin(Self {
+            buffer,
+            mode: ViMode::Normal(None),
+            pty,
+        })
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        for key in keys(data) {
+            let mode = std::mem::replace(&mut self.mode, ViMode::Normal(None));
+
+            self.mode = match (mode, key) {
+                (ViMode::Insert, Key::Escape) => {
+                    self.buffer.left();
+                    ViMode::Normal(None)
+                }
+                (ViMode::Insert, Key::Char('\r' | '\n')) => {
+                    self.buffer.insert('\n');
+                    ViMode::Insert
+                }
+                (ViMode::Insert, Key::Char('\x7f' | '\x08')) => {
+                    self.buffer.backspace();
+                    ViMode::Insert
+                }
+                (ViMode::Insert, Key::Char(c)) if !c.is_control() || c == '\t' => {
+                    self.buffer.insert(c);
+                    ViMode::Insert
+                }
+                (ViMode::CommandLine(_), Key::Escape) => ViMode::Normal(None),
+                (ViMode::CommandLine(command), Key::Char('\r' | '\n')) => {
+                    match self.ex(connection, &command) {
+                        Ok(true) => return self.quit(channel, session),
+                        Ok(false) => {}
+                        Err(message) => session.stderr(channel, format!("{message}\n").into()),
+                    }
+
+                    ViMode::Normal(None)
+                }
+                (ViMode::CommandLine(mut command), Key::Char('\x7f' | '\x08')) => {
+                    if command.pop().is_none() {
+                        ViMode::Normal(None)
+                    } else {
+                        ViMode::CommandLine(command)
+                    }
+                }
+                (ViMode::CommandLine(mut command), Key::Char(c)) if !c.is_control() => {
+                    command.push(c);
+                    ViMode::CommandLine(command)
+                }
+                (mode, Key::Up) => {
+                    self.buffer.move_to(self.buffer.line.saturating_sub(1));
+                    mode
+                }
+                (mode, Key::Down) => {
+                    self.buffer.move_to(self.buffer.line + 1);
+                    mode
+                }
+                (mode, Key::Left) => {
+                    self.buffer.left();
+                    mode
+                }
+                (mode, Key::Right) => {
+                    self.buffer.right();
+                    mode
+                }
+                (ViMode::Normal(Some('Z')), Key::Char('Z')) if !self.buffer.modified => {
+                    return self.quit(channel, session);
+                }
+                (ViMode::Normal(Some('Z')), Key::Char('Z')) => {
+                    let path = self.buffer.path.clone();
+
+                    match self.write(connection, path.as_deref()) {
+                        Ok(()) => return self.quit(channel, session),
+                        Err(message) => session.stderr(channel, format!("{message}\n").into()),
+                    }
+
+                    ViMode::Normal(None)
+                }
+                (ViMode::Normal(Some('Z')), Key::Char('Q')) => return self.quit(channel, session),
+                (ViMode::Normal(pending), key) => self.normal(pending, key),
+                (mode, _) => mode,
+            };
+        }
+
+        CommandResult::ReadStdin(self)
+    }
+}
+
+impl Vi {
+    /// Handles a key typed outside of insert mode.
+    fn normal(&mut self, pending: Option<char>, key: Key) -> ViMode {
+        let buffer = &mut self.buffer;
+
+        let Key::Char(c) = key else {
+            return ViMode::Normal(None);
+        };
+
+        match (pending, c) {
+            (Some('d'), 'd') => {
+                buffer.delete_lines(false);
+                ViMode::Normal(None)
+            }
+            (Some('d'), 'G') => {
+                buffer.delete_lines(true);
+                ViMode::Normal(None)
+            }
+            (Some('g'), 'g') => {
+                buffer.move_to(0);
+                ViMode::Normal(None)
+            }
+            (_, 'd' | 'g' | 'Z') => ViMode::Normal(Some(c)),
+            (_, 'i') => ViMode::Insert,
+            (_, 'I') => {
+                buffer.column = 0;
+                ViMode::Insert
+            }
+            (_, 'a') => {
+                buffer.right();
+                ViMode::Insert
+            }
+            (_, 'A') => {
+                buffer.column = buffer.current().len();
+                ViMode::Insert
+            }
+            (_, 'o' | 'O') => {
+                buffer.open_line(c == 'o');
+                ViMode::Insert
+            }
+            (_, 'G') => {
+                buffer.move_to(usize::MAX);
+                ViMode::Normal(None)
+            }
+            (_, 'j') => {
+                buffer.move_to(buffer.line + 1);
+                ViMode::Normal(None)
+            }
+            (_, 'k') => {
+                buffer.move_to(buffer.line.saturating_sub(1));
+                ViMode::Normal(None)
+            }
+            (_, 'h') => {
+                buffer.left();
+                ViMode::Normal(None)
+            }
+            (_, 'l') => {
+                buffer.right();
+                ViMode::Normal(None)
+            }
+            (_, ':') => ViMode::CommandLine(String::new()),
+            _ => ViMode::Normal(None),
+        }
+    }
+
+    /// Runs an ex command, returning whether the editor should be closed.
+    fn ex(&mut self, connection: &mut ConnectionState, command: &str) -> Result<bool, String> {
+        let command = command.trim();
+        let (name, arg) = command
+            .split_once(' ')
+            .map_or((command, None), |(name, arg)| (name, Some(arg.trim())));
+
+        match name.trim_end_matches('!') {
+            "" => Ok(false),
+            // `:x` only writes if there's something to write
+            "x" | "xa" if !self.buffer.modified && arg.is_none() => Ok(true),
+            "w" | "wq" | "x" | "wqa" | "xa" => {
+                let path = arg.map(ToString::to_string).or(self.buffer.path.clone());
+                self.write(connection, path.as_deref())?;
+                Ok(name != "w" && name != "w!")
+            }
+            "q" | "qa" if self.buffer.modified && !name.ends_with('!') => {
+                Err("E37: No write since last change (add ! to override)".to_string())
+            }
+            "q" | "qa" => Ok(true),
+            "%d" => {
+                self.buffer.move_to(0);
+                self.buffer.delete_lines(true);
+                Ok(false)
+            }
+            "$" => {
+                self.buffer.move_to(usize::MAX);
+                Ok(false)
+            }
+            line => match line.parse::<usize>() {
+                Ok(line) => {
+                    self.buffer.move_to(line.saturating_sub(1));
+                    Ok(false)
+                }
+                Err(_) => Err(format!("E492: Not an editor command: {command}")),
+            },
+        }
+    }
+
+    fn write(
+        &mut self,
+        connection: &mut ConnectionState,
+        path: Option<&str>,
+    ) -> Result<(), String> {
+        let path = path.ok_or_else(|| "E32: No file name".to_string())?;
+
+        if self.buffer.path.is_none() {
+            self.buffer.path = Some(path.to_string());
+        }
+
+        self.buffer
+            .save(connection, path)
+            .map_err(|_| format!("\"{path}\" E212: Can't open file for writing"))
+    }
+
+    fn quit<S: ThrusshSession + Send>(
+        self,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        if self.pty {
+            session.data(channel, LEAVE_SCREEN.into());
+        }
+
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum NanoMode {
+    Editing,
+    /// Asked whether to save the changes before exiting.
+    SaveChanges,
+    /// Typing the name of the file to write to, exiting once it's written if `exit` is set.
+    FileName {
+        name: String,
+        exit: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Nano {
+    buffer: Buffer,
+    mode: NanoMode,
+    pty: bool,
+}
+
+#[async_trait]
+impl Command for Nano {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let path = params.iter().find(|v| !v.starts_with(['-', '+']));
+        let mut buffer = Buffer::open(connection, path.map(String::as_str));
+        buffer.magic_line = true;
+        let pty = connection.has_pty(channel);
+
+        if pty {
+            let title = buffer.path.as_deref().unwrap_or("New Buffer");
+            let status = match &buffer.path {
+                Some(_) if buffer.new => "[ New File ]".to_string(),
+                Some(_) => format!("[ Read {} lines ]", buffer.lines.len()),
+                None => String::new(),
+            };
+
+            session.data(
+                channel,
+                format!(
+                    "{ENTER_SCREEN}  GNU nano 5.4{title:>40}\n\n{}{status}\n{NANO_SHORTCUTS}",
+                    String::from_utf8_lossy(&buffer.content())
+                )
+                .into(),
+            );
+        }
+
+        CommandResult::ReadStdin(Self {
+            buffer,
+            mode: NanoMode::Editing,
+            pty,
+        })
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        for key in keys(data) {
+            let mode = std::mem::replace(&mut self.mode, NanoMode::Editing);
+
+            self.mode = match (mode, key) {
+                (NanoMode::Editing, Key::Char('\x18')) if !self.buffer.modified => {
+                    return self.quit(channel, session);
+                }
+                (NanoMode::Editing, Key::Char('\x18')) => {
+                    session.data(
+                        channel,
+                        "Save modified buffer?  (Answering \"No\" will DISCARD changes.)\n Y Yes\n N No           ^C Cancel\n"
+                            .into(),
+                    );
+                    NanoMode::SaveChanges
+                }
+                (NanoMode::Editing, Key::Char('\x0f')) => self.prompt(channel, session, false),
+                (NanoMode::Editing, Key::Char('\x0b')) => {
+                    self.buffer.delete_lines(false);
+                    NanoMode::Editing
+                }
+                (NanoMode::Editing, Key::Char('\r' | '\n')) => {
+                    self.buffer.insert('\n');
+                    NanoMode::Editing
+                }
+                (NanoMode::Editing, Key::Char('\x7f' | '\x08')) => {
+                    self.buffer.backspace();
+                    NanoMode::Editing
+                }
+                (NanoMode::Editing, Key::Char(c)) if !c.is_control() || c == '\t' => {
+                    self.buffer.insert(c);
+                    NanoMode::Editing
+                }
+                (NanoMode::Editing, Key::Up) => {
+                    self.buffer.move_to(self.buffer.line.saturating_sub(1));
+                    NanoMode::Editing
+                }
+                (NanoMode::Editing, Key::Down) => {
+                    self.buffer.move_to(self.buffer.line + 1);
+                    NanoMode::Editing
+                }
+                (NanoMode::Editing, Key::Left) => {
+                    self.buffer.left();
+                    NanoMode::Editing
+                }
+                (NanoMode::Editing, Key::Right) => {
+                    self.buffer.right();
+                    NanoMode::Editing
+                }
+                (NanoMode::SaveChanges, Key::Char('y' | 'Y')) => {
+                    self.prompt(channel, session, true)
+                }
+                (NanoMode::SaveChanges, Key::Char('n' | 'N')) => {
+                    return self.quit(channel, session);
+                }
+                (NanoMode::SaveChanges | NanoMode::FileName { .. }, Key::Char('\x03')) => {
+                    session.data(channel, "[ Cancelled ]\n".into());
+                    NanoMode::Editing
+                }
+                (NanoMode::FileName { name, exit }, Key::Char('\r' | '\n')) => {
+                    if name.is_empty() {
+                        session.data(channel, "[ Cancelled ]\n".into());
+                        NanoMode::Editing
+                    } else if let Err(e) = self.buffer.save(connection, &name) {
+                        session.stderr(channel, format!("[ Error writing {name}: {e} ]\n").into());
+                        NanoMode::Editing
+                    } else if exit {
+                        return self.quit(channel, session);
+                    } else {
+                        session.data(
+                            channel,
+                            format!("[ Wrote {} lines ]\n", self.buffer.lines.len()).into(),
+                        );
+                        self.buffer.path = Some(name);
+                        NanoMode::Editing
+                    }
+                }
+                (NanoMode::FileName { mut name, exit }, Key::Char('\x7f' | '\x08')) => {
+                    name.pop();
+                    NanoMode::FileName { name, exit }
+                }
+                (NanoMode::FileName { mut name, exit }, Key::Char(c)) if !c.is_control() => {
+                    name.push(c);
+                    NanoMode::FileName { name, exit }
+                }
+                (mode, _) => mode,
+            };
+        }
+
+        CommandResult::ReadStdin(self)
+    }
+}
+
+impl Nano {
+    fn prompt<S: ThrusshSession + Send>(
+        &self,
+        channel: ChannelId,
+        session: &mut S,
+        exit: bool,
+    ) -> NanoMode {
+        let name = self.buffer.path.clone().unwrap_or_default();
+        session.data(channel, format!("File Name to Write: {name}").into());
+        NanoMode::FileName { name, exit }
+    }
+
+    fn quit<S: ThrusshSession + Send>(
+        self,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        if self.pty {
+            session.data(channel, LEAVE_SCREEN.into());
+        }
+
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            editor::{keys, Key, Nano, Vi},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case(b"ab", &[Key::Char('a'), Key::Char('b')]; "plain")]
+    #[test_case(b"\x1b:wq", &[Key::Escape, Key::Char(':'), Key::Char('w'), Key::Char('q')]; "escape")]
+    #[test_case(b"\x1b[A\x1bOB", &[Key::Up, Key::Down]; "arrows")]
+    #[test_case(b"\x1b[3~x", &[Key::Char('x')]; "unknown sequence")]
+    #[test_case(b"\x1bOx", &[Key::Escape, Key::Char('O'), Key::Char('x')]; "open line")]
+    fn splits_keys(input: &[u8], expected: &[Key]) {
+        assert_eq!(keys(input), expected);
+    }
+
+    fn written(state: &mut ConnectionState, path: &str) -> String {
+        String::from_utf8(state.file_system().read(Path::new(path)).unwrap().to_vec()).unwrap()
+    }
+
+    async fn vi(state: &mut ConnectionState, path: &str, input: &[&[u8]]) -> CommandResult<Vi> {
+        let mut session = MockThrusshSession::default();
+        session.expect_data().returning(|_, _| ());
+        session.expect_stderr().returning(|_, _| ());
+
+        let mut out = Vi::new(state, &[path.to_string()], fake_channel_id(), &mut session).await;
+
+        for input in input {
+            out = out
+                .unwrap_stdin()
+                .stdin(state, fake_channel_id(), input, &mut session)
+                .await;
+        }
+
+        out
+    }
+
+    #[test_case(&[b"ihello\rworld\x1b:wq\r"], "hello\nworld\n"; "new file")]
+    #[test_case(&[b"i", b"hello\r", b"\x1b", b":x\r"], "hello\n\n"; "keystrokes")]
+    #[test_case(&[b"ihellp\x7fo\x1bZZ"], "hello\n"; "backspace")]
+    #[test_case(&[b"ihello\x1b:w\r", b"ZQ"], "hello\n"; "write then quit")]
+    fn vi_new_file(input: &[&[u8]], expected: &str) {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mut state = ConnectionState::mock();
+
+            let out = vi(&mut state, "/etc/rc.local", input).await;
+            assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+            assert_eq!(written(&mut state, "/etc/rc.local"), expected);
+        });
+    }
+
+    #[tokio::test]
+    async fn vi_edits_existing() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(
+                Path::new("/etc/rc.local"),
+                b"#!/bin/sh\nexit 0\n".to_vec().into(),
+            )
+            .unwrap();
+
+        let out = vi(
+            &mut state,
+            "/etc/rc.local",
+            &[b"ggo/tmp/.x/run &\x1bGdd:wq\r"],
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        assert_eq!(
+            written(&mut state, "/etc/rc.local"),
+            "#!/bin/sh\n/tmp/.x/run &\n"
+        );
+
+        assert!(state.audit_log().events.iter().any(|event| matches!(
+            &event.action,
+            AuditLogAction::WriteFile(v) if &*v.path == "/etc/rc.local"
+        )));
+    }
+
+    #[tokio::test]
+    async fn vi_refuses_to_discard_changes() {
+        let mut state = ConnectionState::mock();
+        let mut session = MockThrusshSession::default();
+
+        session.expect_stderr().once().returning(|_, _| ());
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string("E37: No write since last change (add ! to override)\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Vi::new(
+            &mut state,
+            &["x".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin()
+        .stdin(&mut state, fake_channel_id(), b"ihi\x1b:q\r", &mut session)
+        .await
+        .unwrap_stdin()
+        .stdin(&mut state, fake_channel_id(), b":q!\r", &mut session)
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        assert!(state.file_system().read(Path::new("x")).is_err());
+    }
+
+    #[test_case(b"* * * * * /tmp/.x/run\r\x18y\r"; "exit")]
+    #[test_case(b"* * * * * /tmp/.x/run\r\x0f\r\x18"; "write out")]
+    #[test_case(b"* * * * * /tmp/.x/run\r\x0f\x03\x18Y\r"; "cancelled write out")]
+    fn nano(input: &[u8]) {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let mut state = ConnectionState::mock();
+            let mut session = MockThrusshSession::default();
+            session.expect_data().returning(|_, _| ());
+
+            let out = Nano::new(
+                &mut state,
+                &["/etc/crontab".to_string()],
+                fake_channel_id(),
+                &mut session,
+            )
+            .await
+            .unwrap_stdin()
+            .stdin(&mut state, fake_channel_id(), input, &mut session)
+            .await;
+            assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+            assert_eq!(
+                written(&mut state, "/etc/crontab"),
+                "* * * * * /tmp/.x/run\n"
+            );
+        });
+    }
+
+    #[tokio::test]
+    async fn nano_discards_changes() {
+        let mut state = ConnectionState::mock();
+        let mut session = MockThrusshSession::default();
+        session.expect_data().returning(|_, _| ());
+
+        let out = Nano::new(&mut state, &[], fake_channel_id(), &mut session)
+            .await
+            .unwrap_stdin()
+            .stdin(&mut state, fake_channel_id(), b"hello\x18n", &mut session)
+            .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        assert!(state.audit_log().events.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! Running files from the client's fake file system, such as uploaded payloads or binaries
+//! installed by a package manager. Shell scripts are run through the shell so whatever they go on
+//! to do is captured, anything else is only recorded.
+
+use std::{borrow::Cow, path::Path};
+
+use bytes::Bytes;
+use pisshoff_types::{
+    audit::{AuditLogAction, BinaryExecutionEvent, ScriptExecutionEvent},
+    byte_str::ByteStr,
+};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{CommandResult, ConcreteCommand},
+    config::BinaryExecutionMode,
+    payload,
+    server::{ConnectionState, ThrusshSession},
+    subsystem::shell::Script,
+};
+
+/// Shells whose scripts are run through our own shell, rather than just being recorded.
+const SHELLS: &[&str] = &["ash", "bash", "dash", "sh", "zsh"];
+
+/// Returns the name of the interpreter given in a script's shebang, looking through `env`.
+fn interpreter(content: &[u8]) -> Option<String> {
+    let line = content.strip_prefix(b"#!")?.split(|v| *v == b'\n').next()?;
+    let line = String::from_utf8_lossy(line);
+    let mut words = line.split_whitespace();
+
+    let mut program = words.next()?.rsplit('/').next()?;
+
+    if program == "env" {
+        program = words.find(|v| !v.starts_with('-'))?;
+    }
+
+    Some(program.to_string())
+}
+
+pub async fn execute<S: ThrusshSession + Send>(
+    connection: &mut ConnectionState,
+    exec: &[u8],
+    path: &Path,
+    params: &[String],
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<ConcreteCommand> {
+    let name = String::from_utf8_lossy(exec);
+    let fs = connection.file_system();
+
+    let executable = fs.metadata(path).is_ok_and(|v| v.mode & 0o111 != 0);
+    let content = fs
+        .read(path)
+        .map(Bytes::copy_from_slice)
+        .unwrap_or_default();
+
+    if !executable {
+        session.stderr(channel, format!("bash: {name}: Permission denied\n").into());
+        return CommandResult::Exit(126);
+    }
+
+    let path = path.to_string_lossy();
+    let args = params.to_vec().into_boxed_slice();
+    let interpreter = interpreter(&content);
+
+    // anything without a shebang is run by the shell, unless it's a binary
+    let elf = content.starts_with(b"\x7fELF");
+    let binary = elf || content.iter().take(80).any(|v| *v == 0);
+
+    if interpreter.is_none() && binary {
+        connection.push_action(AuditLogAction::BinaryExecution(BinaryExecutionEvent {
+            path: Box::from(path.as_ref()),
+            sha256: payload::sha256(&content),
+            size: content.len() as u64,
+            args,
+        }));
+
+        let installed = Path::new(path.as_ref())
+            .strip_prefix("/usr/bin")
+            .ok()
+            .and_then(Path::to_str)
+            .is_some_and(|v| connection.is_installed(v));
+
+        if installed {
+            return CommandResult::Exit(0);
+        }
+
+        return match &connection.config().binary_execution {
+            BinaryExecutionMode::Segfault if elf => {
+                session.stderr(channel, "Segmentation fault (core dumped)\n".into());
+                CommandResult::Exit(139)
+            }
+            BinaryExecutionMode::Canned(canned) if elf => {
+                session.data(channel, connection.render_template(&canned.output).into());
+                CommandResult::Exit(canned.exit_code)
+            }
+            _ => {
+                session.stderr(
+                    channel,
+                    format!("bash: {name}: cannot execute binary file: Exec format error\n").into(),
+                );
+                CommandResult::Exit(126)
+            }
+        };
+    }
+
+    let interpreter = interpreter.unwrap_or_else(|| "sh".to_string());
+    let shell = SHELLS.contains(&interpreter.as_str());
+
+    connection.push_action(AuditLogAction::ScriptExecution(ScriptExecutionEvent {
+        interpreter: Cow::Owned(interpreter),
+        source: Some(Box::from(path.as_ref())),
+        script: ByteStr::from_utf8_lossy(content.clone()),
+        args,
+    }));
+
+    if !shell {
+        return CommandResult::Exit(0);
+    }
+
+    Script::start(&name, &content, params, connection, channel, session)
+        .await
+        .map(ConcreteCommand::wrap)
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, BinaryExecutionEvent, ScriptExecutionEvent};
+    use test_case::test_case;
+
+    use crate::{
+        command::{executable::interpreter, CommandResult, ConcreteCommand},
+        config::{BinaryExecutionMode, CannedCommand, Config},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case(b"#!/bin/sh\necho hi", Some("sh"); "absolute")]
+    #[test_case(b"#!/usr/bin/env python3\nprint(1)", Some("python3"); "env")]
+    #[test_case(b"#! /usr/bin/env -S perl -w\n", Some("perl"); "env with flags")]
+    #[test_case(b"\x7fELF\x02\x01\x01", None; "binary")]
+    fn parses_shebang(content: &[u8], expected: Option<&str>) {
+        assert_eq!(interpreter(content).as_deref(), expected);
+    }
+
+    #[tokio::test]
+    async fn requires_executable_bit() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        state
+            .file_system()
+            .write("payload".as_ref(), Box::from(*b"#!/bin/sh\npwd\n"))
+            .unwrap();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string("bash: ./payload: Permission denied\n"))
+            .returning(|_, _| ());
+
+        let out = ConcreteCommand::new(
+            &mut state,
+            Some(b"./payload"),
+            &[],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(126)), "{out:?}");
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("/root\n"))
+            .returning(|_, _| ());
+
+        state
+            .file_system()
+            .metadata_mut("payload".as_ref())
+            .unwrap()
+            .mode = 0o755;
+
+        let out = ConcreteCommand::new(
+            &mut state,
+            Some(b"./payload"),
+            &["-x".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let AuditLogAction::ScriptExecution(ScriptExecutionEvent {
+            interpreter,
+            source,
+            script,
+            args,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected script execution event");
+        };
+
+        assert_eq!(interpreter, "sh");
+        assert_eq!(source.as_deref(), Some("/root/payload"));
+        assert_eq!(&**script, "#!/bin/sh\npwd\n");
+        assert_eq!(&**args, ["-x"]);
+    }
+
+    #[tokio::test]
+    async fn resolves_from_path() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.install_command("xmrig");
+
+        let out = ConcreteCommand::new(
+            &mut state,
+            Some(b"xmrig"),
+            &["--donate-level=1".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let AuditLogAction::BinaryExecution(BinaryExecutionEvent { path, args, .. }) =
+            &state.audit_log().events[0].action
+        else {
+            panic!("expected binary execution event");
+        };
+
+        assert_eq!(&**path, "/usr/bin/xmrig");
+        assert_eq!(&**args, ["--donate-level=1"]);
+    }
+
+    #[test_case(BinaryExecutionMode::Segfault, "Segmentation fault (core dumped)\n", true, 139; "segfault")]
+    #[test_case(
+        BinaryExecutionMode::ExecFormatError,
+        "bash: ./kinsing: cannot execute binary file: Exec format error\n",
+        true,
+        126;
+        "exec format error"
+    )]
+    #[test_case(
+        BinaryExecutionMode::Canned(CannedCommand { output: "mining\n".to_string(), exit_code: 0 }),
+        "mining\n",
+        false,
+        0;
+        "canned"
+    )]
+    #[tokio::test]
+    async fn runs_uploaded_binary(
+        mode: BinaryExecutionMode,
+        output: &'static str,
+        stderr: bool,
+        status: u32,
+    ) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            binary_execution: mode,
+            ..Config::default()
+        });
+
+        state
+            .file_system()
+            .write("kinsing".as_ref(), Box::from(*b"\x7fELF\x02\x01\x01\0"))
+            .unwrap();
+        state
+            .file_system()
+            .metadata_mut("kinsing".as_ref())
+            .unwrap()
+            .mode = 0o755;
+
+        if stderr {
+            session
+                .expect_stderr()
+                .once()
+                .with(always(), eq_string(output))
+                .returning(|_, _| ());
+        } else {
+            session
+                .expect_data()
+                .once()
+                .with(always(), eq_string(output))
+                .returning(|_, _| ());
+        }
+
+        let out = ConcreteCommand::new(
+            &mut state,
+            Some(b"./kinsing"),
+            &[],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(
+            matches!(out, CommandResult::Exit(v) if v == status),
+            "{out:?}"
+        );
+
+        let AuditLogAction::BinaryExecution(BinaryExecutionEvent {
+            path, sha256, size, ..
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected binary execution event");
+        };
+
+        assert_eq!(&**path, "/root/kinsing");
+        assert_eq!(
+            &**sha256,
+            "e94466faac02d08efbb3109dbe52d0c13c5a53859e328d50dbeb2e6f0ee89871"
+        );
+        assert_eq!(*size, 8);
+    }
+}
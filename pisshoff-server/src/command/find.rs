@@ -0,0 +1,271 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    file_system::{glob_match, FileSystem, Tree},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Find {}
+
+#[async_trait]
+impl Command for Find {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = find(connection.file_system(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// The tests an entry has to pass to be printed, all of which have to match.
+#[derive(Default)]
+struct Expression<'a> {
+    names: Vec<Name<'a>>,
+    kind: Option<char>,
+    min_depth: usize,
+    max_depth: Option<usize>,
+}
+
+struct Name<'a> {
+    pattern: &'a str,
+    case_insensitive: bool,
+}
+
+impl Expression<'_> {
+    fn matches(&self, name: &str, tree: &Tree, depth: usize) -> bool {
+        let kind = match tree {
+            Tree::Directory(_) => 'd',
+            Tree::File(..) => 'f',
+            Tree::Device(..) => 'c',
+        };
+
+        depth >= self.min_depth
+            && self.kind.is_none_or(|v| v == kind)
+            && self.names.iter().all(|v| {
+                if v.case_insensitive {
+                    glob_match(
+                        v.pattern.to_lowercase().as_bytes(),
+                        name.to_lowercase().as_bytes(),
+                    )
+                } else {
+                    glob_match(v.pattern.as_bytes(), name.as_bytes())
+                }
+            })
+    }
+}
+
+/// Walks each of the starting points given, returning stdout, stderr and the exit status. Only
+/// tests are supported, so every expression is implicitly `-print`.
+fn find(fs: &FileSystem, params: &[String]) -> (String, String, u32) {
+    let start = params.iter().take_while(|v| !v.starts_with('-')).count();
+    let (paths, mut rest) = params.split_at(start);
+
+    let mut expression = Expression::default();
+
+    while let Some((predicate, tail)) = rest.split_first() {
+        rest = tail;
+
+        let mut argument = || {
+            let (value, tail) = rest.split_first()?;
+            rest = tail;
+            Some(value.as_str())
+        };
+
+        match predicate.as_str() {
+            "-print" | "-writable" | "-readable" => continue,
+            "-name" | "-iname" | "-type" | "-maxdepth" | "-mindepth" => {}
+            _ => {
+                return (
+                    String::new(),
+                    format!("find: unknown predicate `{predicate}'\n"),
+                    1,
+                )
+            }
+        }
+
+        let Some(value) = argument() else {
+            return (
+                String::new(),
+                format!("find: missing argument to `{predicate}'\n"),
+                1,
+            );
+        };
+
+        match predicate.as_str() {
+            "-name" | "-iname" => expression.names.push(Name {
+                pattern: value,
+                case_insensitive: predicate == "-iname",
+            }),
+            "-type" => match value {
+                "f" | "d" | "c" | "l" | "b" | "p" | "s" => expression.kind = value.chars().next(),
+                _ => {
+                    return (
+                        String::new(),
+                        format!("find: Unknown argument to -type: {value}\n"),
+                        1,
+                    )
+                }
+            },
+            _ => {
+                let Ok(depth) = value.parse() else {
+                    return (
+                        String::new(),
+                        format!(
+                            "find: Expected a positive decimal integer argument to {predicate}, but got `{value}'\n"
+                        ),
+                        1,
+                    );
+                };
+
+                if predicate == "-maxdepth" {
+                    expression.max_depth = Some(depth);
+                } else {
+                    expression.min_depth = depth;
+                }
+            }
+        }
+    }
+
+    let paths = if paths.is_empty() {
+        &[".".to_string()][..]
+    } else {
+        paths
+    };
+
+    let mut out = String::new();
+    let mut err = String::new();
+    let mut exit_code = 0;
+
+    for path in paths {
+        match fs.get(path.as_ref()) {
+            Ok(tree) => walk(&mut out, path, path, tree, 0, &expression),
+            Err(e) => {
+                writeln!(err, "find: ‘{path}’: {e}").unwrap();
+                exit_code = 1;
+            }
+        }
+    }
+
+    (out, err, exit_code)
+}
+
+fn walk(
+    out: &mut String,
+    display: &str,
+    name: &str,
+    tree: &Tree,
+    depth: usize,
+    expression: &Expression<'_>,
+) {
+    // starting points are matched on their final component, as `find` does
+    let name = name
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(name);
+
+    if expression.matches(if name.is_empty() { "/" } else { name }, tree, depth) {
+        writeln!(out, "{display}").unwrap();
+    }
+
+    let Tree::Directory(children) = tree else {
+        return;
+    };
+
+    if expression.max_depth.is_some_and(|v| depth >= v) {
+        return;
+    }
+
+    for (child_name, child) in children {
+        let child_display = if display.ends_with('/') {
+            format!("{display}{child_name}")
+        } else {
+            format!("{display}/{child_name}")
+        };
+
+        walk(
+            out,
+            &child_display,
+            child_name,
+            child,
+            depth + 1,
+            expression,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{command::find::find, file_system::FileSystem};
+
+    fn file_system() -> FileSystem {
+        let mut fs = FileSystem::new("root");
+        fs.mkdirall(Path::new("/root/.ssh")).unwrap();
+        fs.mkdirall(Path::new("/tmp/.x")).unwrap();
+        fs.write(Path::new("/root/.ssh/authorized_keys"), Box::default())
+            .unwrap();
+        fs.write(Path::new("/root/run.sh"), Box::default()).unwrap();
+        fs.write(Path::new("/tmp/.x/Miner.SH"), Box::default())
+            .unwrap();
+        fs
+    }
+
+    #[test_case("", ".\n./.ssh\n./.ssh/authorized_keys\n./run.sh\n"; "default")]
+    #[test_case("/tmp", "/tmp\n/tmp/.x\n/tmp/.x/Miner.SH\n"; "absolute")]
+    #[test_case("/ -name '*.sh'", "/root/run.sh\n"; "name")]
+    #[test_case("/ -iname '*.sh'", "/root/run.sh\n/tmp/.x/Miner.SH\n"; "case insensitive name")]
+    #[test_case("/tmp -type d", "/tmp\n/tmp/.x\n"; "directories")]
+    #[test_case("/ -maxdepth 1 -type d", "/\n/dev\n/root\n/tmp\n"; "max depth")]
+    #[test_case("/tmp -mindepth 1 -maxdepth 1", "/tmp/.x\n"; "min depth")]
+    #[test_case("/dev -type c -name null", "/dev/null\n"; "devices")]
+    #[test_case("/ -writable -type f -name 'a*'", "/root/.ssh/authorized_keys\n"; "writable")]
+    fn finds(input: &str, expected: &str) {
+        let (out, err, exit_code) = find(&file_system(), &shlex::split(input).unwrap());
+
+        assert_eq!(out, expected);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test_case("missing", "find: ‘missing’: No such file or directory\n"; "missing")]
+    #[test_case(". -exec", "find: unknown predicate `-exec'\n"; "unknown predicate")]
+    #[test_case(". -name", "find: missing argument to `-name'\n"; "missing argument")]
+    #[test_case(". -type x", "find: Unknown argument to -type: x\n"; "bad type")]
+    fn fails(input: &str, expected: &str) {
+        let (_out, err, exit_code) = find(&file_system(), &shlex::split(input).unwrap());
+
+        assert_eq!(err, expected);
+        assert_eq!(exit_code, 1);
+    }
+}
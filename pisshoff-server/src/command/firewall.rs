@@ -0,0 +1,344 @@
+//! Firewall management. The fake system has no rules worth hiding so listing always shows an open
+//! policy, but anything that would modify the firewall is recorded as an attempt to weaken it.
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::macros::format_description;
+
+use crate::{
+    command::{service::record, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const IPTABLES_LIST: &str = "Chain INPUT (policy ACCEPT)
+target     prot opt source               destination
+
+Chain FORWARD (policy ACCEPT)
+target     prot opt source               destination
+
+Chain OUTPUT (policy ACCEPT)
+target     prot opt source               destination
+";
+
+const IPTABLES_RULES: &str = "-P INPUT ACCEPT
+-P FORWARD ACCEPT
+-P OUTPUT ACCEPT
+";
+
+/// Flags that change the ruleset rather than just looking at it.
+const IPTABLES_MODIFY: &[&str] = &[
+    "-A",
+    "--append",
+    "-D",
+    "--delete",
+    "-I",
+    "--insert",
+    "-R",
+    "--replace",
+    "-F",
+    "--flush",
+    "-X",
+    "--delete-chain",
+    "-P",
+    "--policy",
+    "-N",
+    "--new-chain",
+    "-Z",
+    "--zero",
+];
+
+#[derive(Debug, Clone)]
+pub struct Iptables {}
+
+#[async_trait]
+impl Command for Iptables {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        if params.iter().any(|v| v == "-V" || v == "--version") {
+            session.data(channel, "iptables v1.8.7 (nf_tables)\n".into());
+            return CommandResult::Exit(0);
+        }
+
+        if let Some(action) = params
+            .iter()
+            .find(|v| IPTABLES_MODIFY.contains(&v.as_str()))
+        {
+            record(connection, "iptables", action, params.to_vec());
+        }
+
+        if connection.username() != "root" {
+            session.stderr(
+                channel,
+                "iptables v1.8.7 (nf_tables): Could not fetch rule set generation id: \
+                 Permission denied (you must be root)\n\n"
+                    .into(),
+            );
+            return CommandResult::Exit(4);
+        }
+
+        if params.iter().any(|v| v == "-L" || v == "--list") {
+            session.data(channel, IPTABLES_LIST.into());
+        } else if params.iter().any(|v| v == "-S" || v == "--list-rules") {
+            session.data(channel, IPTABLES_RULES.into());
+        } else if params.is_empty() {
+            session.stderr(
+                channel,
+                "iptables v1.8.7 (nf_tables): no command specified\n\
+                 Try `iptables -h' or 'iptables --help' for more information.\n"
+                    .into(),
+            );
+            return CommandResult::Exit(2);
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ufw {}
+
+impl Ufw {
+    fn status(connection: &ConnectionState) -> &'static str {
+        if connection.is_service_running("ufw") {
+            "Status: active
+
+To                         Action      From
+--                         ------      ----
+22/tcp                     ALLOW       Anywhere
+22/tcp (v6)                ALLOW       Anywhere (v6)
+"
+        } else {
+            "Status: inactive\n"
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Ufw {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut args = params
+            .iter()
+            .map(String::as_str)
+            .filter(|v| !v.starts_with('-'));
+
+        let Some(action) = args.next() else {
+            session.stderr(channel, "ERROR: not enough args\n".into());
+            return CommandResult::Exit(1);
+        };
+
+        if matches!(
+            action,
+            "disable" | "reset" | "allow" | "delete" | "default" | "insert"
+        ) {
+            record(
+                connection,
+                "ufw",
+                action,
+                args.map(ToString::to_string).collect(),
+            );
+        }
+
+        if action == "version" {
+            session.data(
+                channel,
+                "ufw 0.36.1\nCopyright 2008-2021 Canonical Ltd.\n".into(),
+            );
+            return CommandResult::Exit(0);
+        }
+
+        if connection.username() != "root" {
+            session.stderr(
+                channel,
+                "ERROR: You need to be root to run this script\n".into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        let out = match action {
+            "status" => Self::status(connection),
+            "disable" => {
+                connection.set_service_running("ufw", false);
+                "Firewall stopped and disabled on system startup\n"
+            }
+            "enable" => {
+                connection.set_service_running("ufw", true);
+                "Firewall is active and enabled on system startup\n"
+            }
+            "reset" => {
+                connection.set_service_running("ufw", false);
+
+                let suffix = connection
+                    .now()
+                    .format(format_description!(
+                        "[year][month][day]_[hour][minute][second]"
+                    ))
+                    .unwrap_or_default();
+
+                session.data(
+                    channel,
+                    format!(
+                        "Resetting all rules to installed defaults. Proceed with operation \
+                         (y|n)? Backing up 'user.rules' to '/etc/ufw/user.rules.{suffix}'\n\
+                         Backing up 'user6.rules' to '/etc/ufw/user6.rules.{suffix}'\n"
+                    )
+                    .into(),
+                );
+                return CommandResult::Exit(0);
+            }
+            "allow" | "deny" | "reject" | "limit" | "insert" => "Rule added\nRule added (v6)\n",
+            "delete" => "Rule deleted\nRule deleted (v6)\n",
+            "default" => {
+                "Default incoming policy changed to 'allow'\n\
+                 (be sure to update your rules accordingly)\n"
+            }
+            "reload" => "Firewall reloaded\n",
+            other => {
+                session.stderr(
+                    channel,
+                    format!("ERROR: Invalid syntax\n\nUnknown command '{other}'\n").into(),
+                );
+                return CommandResult::Exit(1);
+            }
+        };
+
+        session.data(channel, out.into());
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, DefenseEvasionEvent};
+
+    use crate::{
+        command::{
+            firewall::{Iptables, Ufw},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn flush_is_recorded() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        let out = Iptables::new(
+            &mut state,
+            ["-F".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let AuditLogAction::DefenseEvasion(DefenseEvasionEvent {
+            command,
+            action,
+            targets,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected defense evasion event");
+        };
+
+        assert_eq!(command, "iptables");
+        assert_eq!(&**action, "-F");
+        assert_eq!(&**targets, ["-F"]);
+    }
+
+    #[tokio::test]
+    async fn iptables_requires_root() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string(
+                    "iptables v1.8.7 (nf_tables): Could not fetch rule set generation id: \
+                     Permission denied (you must be root)\n\n",
+                ),
+            )
+            .returning(|_, _| ());
+
+        let out = Iptables::new(
+            &mut state,
+            ["-L".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(4)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn ufw_disable() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string("Firewall stopped and disabled on system startup\n"),
+            )
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("Status: inactive\n"))
+            .returning(|_, _| ());
+
+        for args in ["disable", "status"] {
+            let out = Ufw::new(
+                &mut state,
+                [args.to_string()].as_slice(),
+                fake_channel_id(),
+                &mut session,
+            )
+            .await;
+            assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        }
+
+        assert!(!state.is_service_running("ufw"));
+        assert_eq!(state.audit_log().events.len(), 1);
+    }
+}
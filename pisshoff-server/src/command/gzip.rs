@@ -0,0 +1,366 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use thrussh::ChannelId;
+
+use crate::{
+    archive,
+    audit::HoneytokenAccess,
+    command::{Arg, Command, CommandResult},
+    file_system::LsError,
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Suffixes `gzip -d` recognises, alongside the suffix the decompressed file is given.
+const SUFFIXES: &[(&str, &str)] = &[
+    (".tgz", ".tar"),
+    (".taz", ".tar"),
+    (".gz", ""),
+    ("-gz", ""),
+    (".z", ""),
+];
+
+#[derive(Debug, Clone)]
+pub struct Gzip {}
+
+#[async_trait]
+impl Command for Gzip {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection, params, false);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Gunzip {}
+
+#[async_trait]
+impl Command for Gunzip {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection, params, true);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// Exit status of `gzip`, errors take precedence over warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok = 0,
+    Warning = 2,
+    Error = 1,
+}
+
+impl Status {
+    fn worst(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Error, _) | (_, Self::Error) => Self::Error,
+            (Self::Warning, _) | (_, Self::Warning) => Self::Warning,
+            _ => Self::Ok,
+        }
+    }
+}
+
+fn execute(
+    connection: &mut ConnectionState,
+    params: &[String],
+    mut decompress: bool,
+) -> (Vec<u8>, String, u32) {
+    let mut to_stdout = false;
+    let mut keep = false;
+    let mut verbose = false;
+    let mut files = Vec::new();
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('d') | Arg::Long("decompress" | "uncompress") => decompress = true,
+            Arg::Short('c') | Arg::Long("stdout" | "to-stdout") => to_stdout = true,
+            Arg::Short('k') | Arg::Long("keep") => keep = true,
+            Arg::Short('v') | Arg::Long("verbose") => verbose = true,
+            Arg::Short('f' | 'q' | 'n' | 'N' | '1'..='9')
+            | Arg::Long("force" | "quiet" | "no-name" | "name" | "best" | "fast") => {}
+            Arg::Operand(v) => files.push(v),
+            Arg::Short(c) => {
+                return (
+                    Vec::new(),
+                    format!(
+                        "gzip: invalid option -- '{c}'\nTry `gzip --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    Vec::new(),
+                    format!("gzip: unrecognized option '--{v}'\nTry `gzip --help' for more information.\n"),
+                    1,
+                );
+            }
+        }
+    }
+
+    if files.is_empty() {
+        let err = if decompress {
+            "gzip: compressed data not read from a terminal. Use -f to force decompression.\n"
+        } else {
+            "gzip: compressed data not written to a terminal. Use -f to force compression.\n"
+        };
+
+        return (Vec::new(), format!("{err}For help, type: gzip -h\n"), 1);
+    }
+
+    let mut out = Vec::new();
+    let mut err = String::new();
+    let mut status = Status::Ok;
+
+    for file in files {
+        let result = if decompress {
+            gunzip_file(connection, file, to_stdout, &mut out)
+        } else {
+            gzip_file(connection, file, to_stdout, &mut out)
+        };
+
+        match result {
+            Ok(Some((destination, ratio))) => {
+                if !to_stdout && !keep {
+                    let _res = connection.file_system().remove(Path::new(file));
+                }
+
+                if verbose && !to_stdout {
+                    let action = if keep { "created" } else { "replaced with" };
+                    writeln!(err, "{file}:\t{ratio:>5.1}% -- {action} {destination}").unwrap();
+                }
+            }
+            Ok(None) => {}
+            Err((message, s)) => {
+                writeln!(err, "gzip: {message}").unwrap();
+                status = status.worst(s);
+            }
+        }
+    }
+
+    (out, err, status as u32)
+}
+
+type FileResult = Result<Option<(String, f64)>, (String, Status)>;
+
+fn gunzip_file(
+    connection: &mut ConnectionState,
+    file: &str,
+    to_stdout: bool,
+    out: &mut Vec<u8>,
+) -> FileResult {
+    let destination = SUFFIXES
+        .iter()
+        .find_map(|(suffix, replacement)| {
+            file.strip_suffix(suffix)
+                .map(|v| format!("{v}{replacement}"))
+        })
+        .filter(|v| !v.is_empty());
+
+    let Some(destination) = destination.or_else(|| to_stdout.then(String::new)) else {
+        return Err((
+            format!("{file}: unknown suffix -- ignored"),
+            Status::Warning,
+        ));
+    };
+
+    let data = read(connection, file)?;
+
+    let content = archive::gunzip(data)
+        .ok_or_else(|| (format!("{file}: not in gzip format"), Status::Error))?;
+    let ratio = ratio(data.len(), content.len());
+
+    if to_stdout {
+        out.extend_from_slice(&content);
+        return Ok(None);
+    }
+
+    write(connection, &destination, content)?;
+    Ok(Some((destination, ratio)))
+}
+
+fn gzip_file(
+    connection: &mut ConnectionState,
+    file: &str,
+    to_stdout: bool,
+    out: &mut Vec<u8>,
+) -> FileResult {
+    if !to_stdout && SUFFIXES.iter().any(|(suffix, _)| file.ends_with(suffix)) {
+        return Err((
+            format!("{file} already has .gz suffix -- unchanged"),
+            Status::Warning,
+        ));
+    }
+
+    let data = read(connection, file)?;
+    let content = archive::gzip(data);
+    let ratio = ratio(content.len(), data.len());
+
+    if to_stdout {
+        out.extend_from_slice(&content);
+        return Ok(None);
+    }
+
+    let destination = format!("{file}.gz");
+    write(connection, &destination, content)?;
+    Ok(Some((destination, ratio)))
+}
+
+fn read<'a>(connection: &'a mut ConnectionState, file: &str) -> Result<&'a [u8], (String, Status)> {
+    connection.touch_file(Path::new(file), HoneytokenAccess::Read);
+
+    match connection.file_system().read(Path::new(file)) {
+        Ok(data) => Ok(data),
+        Err(LsError::IsADirectory) => {
+            Err((format!("{file} is a directory -- ignored"), Status::Warning))
+        }
+        Err(e) => Err((format!("{file}: {e}"), Status::Error)),
+    }
+}
+
+fn write(
+    connection: &mut ConnectionState,
+    destination: &str,
+    content: Vec<u8>,
+) -> Result<(), (String, Status)> {
+    connection.record_file_write(destination, Bytes::copy_from_slice(&content));
+
+    connection
+        .file_system()
+        .write(Path::new(destination), content.into_boxed_slice())
+        .map_err(|e| (format!("{destination}: {e}"), Status::Error))
+}
+
+/// Percentage of space saved by compression, as reported by `gzip -v`.
+#[allow(clippy::cast_precision_loss)]
+fn ratio(compressed: usize, uncompressed: usize) -> f64 {
+    if uncompressed == 0 {
+        0.0
+    } else {
+        100.0 - (compressed as f64 * 100.0 / uncompressed as f64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{archive, command::gzip::execute, server::ConnectionState};
+
+    fn state() -> ConnectionState {
+        let mut state = ConnectionState::mock();
+        let fs = state.file_system();
+        fs.write(
+            Path::new("run.sh"),
+            b"#!/bin/sh\n".to_vec().into_boxed_slice(),
+        )
+        .unwrap();
+        fs.write(
+            Path::new("bot.gz"),
+            archive::gzip(b"payload").into_boxed_slice(),
+        )
+        .unwrap();
+        fs.write(Path::new("fake.gz"), b"payload".to_vec().into_boxed_slice())
+            .unwrap();
+        state
+    }
+
+    #[test_case("", false, "", "gzip: compressed data not written to a terminal. Use -f to force compression.\nFor help, type: gzip -h\n", 1; "no files")]
+    #[test_case("-x run.sh", false, "", "gzip: invalid option -- 'x'\nTry `gzip --help' for more information.\n", 1; "invalid option")]
+    #[test_case("missing", false, "", "gzip: missing: No such file or directory\n", 1; "missing file")]
+    #[test_case("bot.gz", false, "", "gzip: bot.gz already has .gz suffix -- unchanged\n", 2; "already compressed")]
+    #[test_case("-d run.sh", false, "", "gzip: run.sh: unknown suffix -- ignored\n", 2; "unknown suffix")]
+    #[test_case("fake.gz", true, "", "gzip: fake.gz: not in gzip format\n", 1; "not gzip")]
+    #[test_case("-c bot.gz", true, "payload", "", 0; "stdout")]
+    #[test_case("bot.gz missing.gz", true, "", "gzip: missing.gz: No such file or directory\n", 1; "partial failure")]
+    fn output(
+        input: &str,
+        decompress: bool,
+        expected_output: &str,
+        expected_error: &str,
+        expected_exit_code: u32,
+    ) {
+        let mut state = state();
+
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = execute(&mut state, &input, decompress);
+
+        assert_eq!(String::from_utf8(out).unwrap(), expected_output);
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut state = state();
+
+        let input = shlex::split("run.sh").unwrap();
+        assert_eq!(
+            execute(&mut state, &input, false),
+            (vec![], String::new(), 0)
+        );
+        assert!(state.file_system().read(Path::new("run.sh")).is_err());
+
+        let input = shlex::split("-dk run.sh.gz").unwrap();
+        assert_eq!(
+            execute(&mut state, &input, false),
+            (vec![], String::new(), 0)
+        );
+        assert!(state.file_system().read(Path::new("run.sh.gz")).is_ok());
+        assert_eq!(
+            state.file_system().read(Path::new("run.sh")).unwrap(),
+            b"#!/bin/sh\n"
+        );
+    }
+}
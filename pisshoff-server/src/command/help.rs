@@ -0,0 +1,518 @@
+use crate::{
+    command::{insmod, uname},
+    config::Personality,
+};
+
+/// Banner printed by busybox before the usage of any of its applets.
+pub const BUSYBOX_BANNER: &str = "BusyBox v1.36.1 (2023-07-27 17:12:24 UTC) multi-call binary.\n\n";
+
+/// Help text and manual page metadata for an emulated command.
+pub struct Manual {
+    pub name: &'static str,
+    /// Manual section the command is documented in, ie. `1` for user commands or `8` for
+    /// administration commands.
+    pub section: u8,
+    /// One line description of the command, shown in the `NAME` section of the manual page.
+    pub summary: &'static str,
+    /// Package the command is provided by, shown in the footer of the manual page.
+    pub source: &'static str,
+    /// Release date of the package, shown in the footer of the manual page.
+    pub date: &'static str,
+    /// Output of `--help` on a GNU userland.
+    pub gnu: &'static str,
+    /// Output of `--help` on busybox, excluding the banner, or `None` if the applet doesn't
+    /// exist.
+    pub busybox: Option<&'static str>,
+}
+
+pub const MANUALS: &[Manual] = &[
+    Manual {
+        name: "cat",
+        section: 1,
+        summary: "concatenate files and print on the standard output",
+        source: "GNU coreutils 8.32",
+        date: "February 2022",
+        gnu: CAT_GNU,
+        busybox: Some(CAT_BUSYBOX),
+    },
+    Manual {
+        name: "dmesg",
+        section: 1,
+        summary: "print or control the kernel ring buffer",
+        source: "util-linux 2.37.2",
+        date: "July 2020",
+        gnu: DMESG_GNU,
+        busybox: Some(DMESG_BUSYBOX),
+    },
+    Manual {
+        name: "insmod",
+        section: 8,
+        summary: "Simple program to insert a module into the Linux Kernel",
+        source: "kmod",
+        date: "02/21/2022",
+        gnu: insmod::USAGE,
+        busybox: Some(INSMOD_BUSYBOX),
+    },
+    Manual {
+        name: "ls",
+        section: 1,
+        summary: "list directory contents",
+        source: "GNU coreutils 8.32",
+        date: "February 2022",
+        gnu: LS_GNU,
+        busybox: Some(LS_BUSYBOX),
+    },
+    Manual {
+        name: "modprobe",
+        section: 8,
+        summary: "Add and remove modules from the Linux Kernel",
+        source: "kmod",
+        date: "02/21/2022",
+        gnu: MODPROBE_GNU,
+        busybox: Some(MODPROBE_BUSYBOX),
+    },
+    Manual {
+        name: "uname",
+        section: 1,
+        summary: "print system information",
+        source: "GNU coreutils 8.32",
+        date: "February 2022",
+        gnu: uname::HELP_STRING,
+        busybox: Some(UNAME_BUSYBOX),
+    },
+    Manual {
+        name: "whoami",
+        section: 1,
+        summary: "print effective userid",
+        source: "GNU coreutils 8.32",
+        date: "February 2022",
+        gnu: WHOAMI_GNU,
+        busybox: Some(WHOAMI_BUSYBOX),
+    },
+];
+
+/// Looks up the manual for the given command.
+pub fn manual(command: &str) -> Option<&'static Manual> {
+    MANUALS.iter().find(|v| v.name == command)
+}
+
+/// Returns the output of `command --help` for the given personality, or `None` if the command
+/// doesn't have any help text and should handle `--help` itself.
+pub fn help(command: &[u8], personality: Personality) -> Option<String> {
+    let manual = manual(std::str::from_utf8(command).ok()?)?;
+
+    match personality {
+        Personality::Gnu => Some(manual.gnu.to_string()),
+        Personality::Busybox => manual.busybox.map(|v| format!("{BUSYBOX_BANNER}{v}")),
+    }
+}
+
+/// Returns true if the parameters contain a `--help` flag before the end of the options.
+pub fn requested(params: &[String]) -> bool {
+    params
+        .iter()
+        .take_while(|v| *v != "--")
+        .any(|v| v == "--help")
+}
+
+const CAT_GNU: &str = "Usage: cat [OPTION]... [FILE]...
+Concatenate FILE(s) to standard output.
+
+With no FILE, or when FILE is -, read standard input.
+
+  -A, --show-all           equivalent to -vET
+  -b, --number-nonblank    number nonempty output lines, overrides -n
+  -e                       equivalent to -vE
+  -E, --show-ends          display $ at end of each line
+  -n, --number             number all output lines
+  -s, --squeeze-blank      suppress repeated empty output lines
+  -t                       equivalent to -vT
+  -T, --show-tabs          display TAB characters as ^I
+  -u                       (ignored)
+  -v, --show-nonprinting   use ^ and M- notation, except for LFD and TAB
+      --help     display this help and exit
+      --version  output version information and exit
+
+Examples:
+  cat f - g  Output f's contents, then standard input, then g's contents.
+  cat        Copy standard input to standard output.
+
+GNU coreutils online help: <https://www.gnu.org/software/coreutils/>
+Report any translation bugs to <https://translationproject.org/team/>
+Full documentation <https://www.gnu.org/software/coreutils/cat>
+or available locally via: info '(coreutils) cat invocation'
+";
+
+const CAT_BUSYBOX: &str = "Usage: cat [-nbvteA] [FILE]...
+
+Print FILEs to stdout
+
+\t-n\tNumber output lines
+\t-b\tNumber nonempty lines
+\t-v\tShow nonprinting characters as ^x or M-x
+\t-t\t...and tabs as ^I
+\t-e\t...and end lines with $
+\t-A\tSame as -vte
+";
+
+const DMESG_GNU: &str = "
+Usage:
+ dmesg [options]
+
+Display or control the kernel ring buffer.
+
+Options:
+ -C, --clear                 clear the kernel ring buffer
+ -c, --read-clear            read and clear all messages
+ -D, --console-off           disable printing messages to console
+ -E, --console-on            enable printing messages to console
+ -F, --file <file>           use the file instead of the kernel log buffer
+ -f, --facility <list>       restrict output to defined facilities
+ -H, --human                 human readable output
+ -J, --json                  use JSON output format
+ -k, --kernel                display kernel messages
+ -L, --color[=<when>]        colorize messages (auto, always or never)
+                               colors are enabled by default
+ -l, --level <list>          restrict output to defined levels
+ -n, --console-level <level> set level of messages printed to console
+ -P, --nopager               do not pipe output into a pager
+ -p, --force-prefix          force timestamp output on each line of multi-line messages
+ -r, --raw                   print the raw message buffer
+     --noescape              don't escape unprintable character
+ -S, --syslog                force to use syslog(2) rather than /dev/kmsg
+ -s, --buffer-size <size>    buffer size to query the kernel ring buffer
+ -u, --userspace             display userspace messages
+ -w, --follow                wait for new messages
+ -W, --follow-new            wait and print only new messages
+ -x, --decode                decode facility and level to readable string
+ -d, --show-delta            show time delta between printed messages
+ -e, --reltime               show local time and time delta in readable format
+ -T, --ctime                 show human-readable timestamp (may be inaccurate!)
+ -t, --notime                don't show any timestamp with messages
+     --time-format <format>  show timestamp using the given format:
+                               [delta|reltime|ctime|notime|iso]
+Suspending/resume will make ctime and iso timestamps inaccurate.
+
+ -h, --help                  display this help
+ -V, --version               display version
+
+Supported log facilities:
+    kern - kernel messages
+    user - random user-level messages
+    mail - mail system
+  daemon - system daemons
+    auth - security/authorization messages
+  syslog - messages generated internally by syslogd
+     lpr - line printer subsystem
+    news - network news subsystem
+
+Supported log levels (priorities):
+   emerg - system is unusable
+   alert - action must be taken immediately
+    crit - critical conditions
+     err - error conditions
+    warn - warning conditions
+  notice - normal but significant condition
+    info - informational
+   debug - debug-level messages
+
+For more details see dmesg(1).
+";
+
+const DMESG_BUSYBOX: &str = "Usage: dmesg [-cr] [-n LEVEL] [-s SIZE]
+
+Print or control the kernel ring buffer
+
+\t-c\t\tClear ring buffer after printing
+\t-n LEVEL\tSet console logging level
+\t-s SIZE\t\tBuffer size
+\t-r\t\tPrint raw message buffer
+";
+
+const INSMOD_BUSYBOX: &str = "Usage: insmod FILE [SYMBOL=VALUE]...
+
+Load kernel module
+";
+
+const LS_GNU: &str = "Usage: ls [OPTION]... [FILE]...
+List information about the FILEs (the current directory by default).
+Sort entries alphabetically if none of -cftuvSUX nor --sort is specified.
+
+Mandatory arguments to long options are mandatory for short options too.
+  -a, --all                  do not ignore entries starting with .
+  -A, --almost-all           do not list implied . and ..
+      --author               with -l, print the author of each file
+  -b, --escape               print C-style escapes for nongraphic characters
+      --block-size=SIZE      with -l, scale sizes by SIZE when printing them;
+                               e.g., '--block-size=M'; see SIZE format below
+  -B, --ignore-backups       do not list implied entries ending with ~
+  -c                         with -lt: sort by, and show, ctime (time of last
+                               modification of file status information);
+                               with -l: show ctime and sort by name;
+                               otherwise: sort by ctime, newest first
+  -C                         list entries by columns
+      --color[=WHEN]         colorize the output; WHEN can be 'always' (default
+                               if omitted), 'auto', or 'never'; more info below
+  -d, --directory            list directories themselves, not their contents
+  -D, --dired                generate output designed for Emacs' dired mode
+  -f                         do not sort, enable -aU, disable -ls --color
+  -F, --classify             append indicator (one of */=>@|) to entries
+      --file-type            likewise, except do not append '*'
+      --format=WORD          across -x, commas -m, horizontal -x, long -l,
+                               single-column -1, verbose -l, vertical -C
+      --full-time            like -l --time-style=full-iso
+  -g                         like -l, but do not list owner
+      --group-directories-first
+                             group directories before files;
+                               can be augmented with a --sort option, but any
+                               use of --sort=none (-U) disables grouping
+  -G, --no-group             in a long listing, don't print group names
+  -h, --human-readable       with -l and -s, print sizes like 1K 234M 2G etc.
+      --si                   likewise, but use powers of 1000 not 1024
+  -H, --dereference-command-line
+                             follow symbolic links listed on the command line
+      --dereference-command-line-symlink-to-dir
+                             follow each command line symbolic link
+                               that points to a directory
+      --hide=PATTERN         do not list implied entries matching shell PATTERN
+                               (overridden by -a or -A)
+      --hyperlink[=WHEN]     hyperlink file names; WHEN can be 'always'
+                               (default if omitted), 'auto', or 'never'
+      --indicator-style=WORD  append indicator with style WORD to entry names:
+                               none (default), slash (-p),
+                               file-type (--file-type), classify (-F)
+  -i, --inode                print the index number of each file
+  -I, --ignore=PATTERN       do not list implied entries matching shell PATTERN
+  -k, --kibibytes            default to 1024-byte blocks for disk usage;
+                               used only with -s and per directory totals
+  -l                         use a long listing format
+  -L, --dereference          when showing file information for a symbolic
+                               link, show information for the file the link
+                               references rather than for the link itself
+  -m                         fill width with a comma separated list of entries
+  -n, --numeric-uid-gid      like -l, but list numeric user and group IDs
+  -N, --literal              print entry names without quoting
+  -o                         like -l, but do not list group information
+  -p, --indicator-style=slash
+                             append / indicator to directories
+  -q, --hide-control-chars   print ? instead of nongraphic characters
+      --show-control-chars   show nongraphic characters as-is (the default,
+                               unless program is 'ls' and output is a terminal)
+  -Q, --quote-name           enclose entry names in double quotes
+      --quoting-style=WORD   use quoting style WORD for entry names:
+                               literal, locale, shell, shell-always,
+                               shell-escape, shell-escape-always, c, escape
+                               (overrides QUOTING_STYLE environment variable)
+  -r, --reverse              reverse order while sorting
+  -R, --recursive            list subdirectories recursively
+  -s, --size                 print the allocated size of each file, in blocks
+  -S                         sort by file size, largest first
+      --sort=WORD            sort by WORD instead of name: none (-U), size (-S),
+                               time (-t), version (-v), extension (-X)
+      --time=WORD            change the default of using modification times;
+                               access time (-u): atime, access, use;
+                               change time (-c): ctime, status;
+                               birth time: birth, creation;
+                             with -l, WORD determines which time to show;
+                             with --sort=time, sort by WORD (newest first)
+      --time-style=TIME_STYLE  time/date format with -l; see TIME_STYLE below
+  -t                         sort by time, newest first; see --time
+  -T, --tabsize=COLS         assume tab stops at each COLS instead of 8
+  -u                         with -lt: sort by, and show, access time;
+                               with -l: show access time and sort by name;
+                               otherwise: sort by access time, newest first
+  -U                         do not sort; list entries in directory order
+  -v                         natural sort of (version) numbers within text
+  -w, --width=COLS           set output width to COLS.  0 means no limit
+  -x                         list entries by lines instead of by columns
+  -X                         sort alphabetically by entry extension
+  -Z, --context              print any security context of each file
+  -1                         list one file per line.  Avoid '\\n' with -q or -b
+      --help     display this help and exit
+      --version  output version information and exit
+
+The SIZE argument is an integer and optional unit (example: 10K is 10*1024).
+Units are K,M,G,T,P,E,Z,Y (powers of 1024) or KB,MB,... (powers of 1000).
+Binary prefixes can be used, too: KiB=K, MiB=M, and so on.
+
+The TIME_STYLE argument can be full-iso, long-iso, iso, locale, or +FORMAT.
+FORMAT is interpreted like in date(1).  If FORMAT is FORMAT1<newline>FORMAT2,
+then FORMAT1 applies to non-recent files and FORMAT2 to recent files.
+TIME_STYLE prefixed with 'posix-' takes effect only outside the POSIX locale.
+Also the TIME_STYLE environment variable sets the default style to use.
+
+Using color to distinguish file types is disabled both by default and
+with --color=never.  With --color=auto, ls emits color codes only when
+standard output is connected to a terminal.  The LS_COLORS environment
+variable can change the settings.  Use the dircolors command to set it.
+
+Exit status:
+ 0  if OK,
+ 1  if minor problems (e.g., cannot access subdirectory),
+ 2  if serious trouble (e.g., cannot access command-line argument).
+
+GNU coreutils online help: <https://www.gnu.org/software/coreutils/>
+Report any translation bugs to <https://translationproject.org/team/>
+Full documentation <https://www.gnu.org/software/coreutils/ls>
+or available locally via: info '(coreutils) ls invocation'
+";
+
+const LS_BUSYBOX: &str = "Usage: ls [-1AaCxdLHRFplinshrSXvctu] [-w WIDTH] [FILE]...
+
+List directory contents
+
+\t-1\tOne column output
+\t-a\tInclude names starting with .
+\t-A\tLike -a, but exclude . and ..
+\t-x\tList by lines
+\t-d\tList directory names, not contents
+\t-L\tFollow symlinks
+\t-H\tFollow symlinks on command line
+\t-R\tRecurse
+\t-p\tAppend / to directory names
+\t-F\tAppend indicator (one of */=@|) to names
+\t-l\tLong format
+\t-i\tList inode numbers
+\t-n\tList numeric UIDs and GIDs instead of names
+\t-s\tList allocated blocks
+\t-lc\tList ctime
+\t-lu\tList atime
+\t--full-time\tList full date/time
+\t-h\tHuman readable sizes (1K 243M 2G)
+\t--group-directories-first
+\t-S\tSort by size
+\t-X\tSort by extension
+\t-v\tSort by version
+\t-t\tSort by mtime
+\t-tc\tSort by ctime
+\t-tu\tSort by atime
+\t-r\tReverse sort order
+\t-w N\tFormat N columns wide
+\t--color[={always,never,auto}]
+";
+
+const MODPROBE_GNU: &str = "Usage:
+\tmodprobe [options] [-i] [-b] modulename
+\tmodprobe [options] -a [-i] [-b] modulename [modulename...]
+\tmodprobe [options] -r [-i] modulename
+\tmodprobe [options] -r -a [-i] modulename [modulename...]
+\tmodprobe [options] -c
+\tmodprobe [options] --dump-modversions filename
+Management Options:
+\t-a, --all                   Consider every non-argument to
+\t                            be a module name to be inserted
+\t                            or removed (-r)
+\t-r, --remove                Remove modules instead of inserting
+\t    --remove-dependencies   Also remove modules depending on it
+\t-R, --resolve-alias         Only lookup and print alias and exit
+\t    --first-time            Fail if module already inserted or removed
+\t-i, --ignore-install        Ignore install commands
+\t-i, --ignore-remove         Ignore remove commands
+\t-b, --use-blacklist         Apply blacklist to resolved alias.
+\t-f, --force                 Force module insertion or removal.
+\t                            implies --force-modversions and
+\t                            --force-vermagic
+\t    --force-modversion      Ignore module's version
+\t    --force-vermagic        Ignore module's version magic
+
+Query Options:
+\t-D, --show-depends          Only print module dependencies and exit
+\t-c, --showconfig            Print out known configuration and exit
+\t-c, --show-config           Same as --showconfig
+\t    --show-modversions      Dump module symbol version and exit
+\t    --dump-modversions      Same as --show-modversions
+\t    --show-exports          Only print module exported symbol versions and exit
+
+General Options:
+\t-n, --dry-run               Do not execute operations, just print out
+\t-n, --show                  Same as --dry-run
+\t-C, --config=FILE           Use FILE instead of default search paths
+\t-d, --dirname=DIR           Use DIR as filesystem root for /lib/modules
+\t-S, --set-version=VERSION   Use VERSION instead of `uname -r`
+\t-s, --syslog                print to syslog, not stderr
+\t-q, --quiet                 disable messages
+\t-v, --verbose               enables more messages
+\t-V, --version               show version
+\t-h, --help                  show this help
+";
+
+const MODPROBE_BUSYBOX: &str = "Usage: modprobe [-alrqvsD] MODULE [SYMBOL=VALUE]...
+
+\t-a\tLoad multiple MODULEs
+\t-l\tList (MODULE is a pattern)
+\t-r\tRemove MODULE (stacks) or do autoclean
+\t-q\tQuiet
+\t-v\tVerbose
+\t-s\tLog to syslog
+\t-D\tShow dependencies
+";
+
+const UNAME_BUSYBOX: &str = "Usage: uname [-amnrspvio]
+
+Print system information
+
+\t-a\tPrint all
+\t-m\tThe machine (hardware) type
+\t-n\tHostname
+\t-r\tKernel release
+\t-s\tKernel name (default)
+\t-p\tProcessor type
+\t-v\tKernel version
+\t-i\tThe hardware platform
+\t-o\tOS name
+";
+
+const WHOAMI_GNU: &str = "Usage: whoami [OPTION]...
+Print the user name associated with the current effective user ID.
+Same as id -un.
+
+      --help     display this help and exit
+      --version  output version information and exit
+
+GNU coreutils online help: <https://www.gnu.org/software/coreutils/>
+Report any translation bugs to <https://translationproject.org/team/>
+Full documentation <https://www.gnu.org/software/coreutils/whoami>
+or available locally via: info '(coreutils) whoami invocation'
+";
+
+const WHOAMI_BUSYBOX: &str = "Usage: whoami
+
+Print the user name associated with the current effective user id
+";
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{
+        command::help::{help, requested},
+        config::Personality,
+    };
+
+    #[test_case("--help", true; "help")]
+    #[test_case("-a --help", true; "after other flags")]
+    #[test_case("-- --help", false; "after end of options")]
+    #[test_case("-h", false; "short flag")]
+    fn requested_help(input: &str, expected: bool) {
+        let input = shlex::split(input).unwrap();
+        assert_eq!(requested(&input), expected);
+    }
+
+    #[test]
+    fn personality() {
+        let gnu = help(b"cat", Personality::Gnu).unwrap();
+        assert!(
+            gnu.starts_with("Usage: cat [OPTION]... [FILE]...\n"),
+            "{gnu}"
+        );
+
+        let busybox = help(b"cat", Personality::Busybox).unwrap();
+        assert!(busybox.starts_with("BusyBox v1.36.1"), "{busybox}");
+        assert!(
+            busybox.contains("\nUsage: cat [-nbvteA] [FILE]...\n"),
+            "{busybox}"
+        );
+
+        assert_eq!(help(b"echo", Personality::Gnu), None);
+    }
+}
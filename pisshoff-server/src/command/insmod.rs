@@ -0,0 +1,113 @@
+use std::{borrow::Cow, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, KernelModuleAttemptEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+pub const USAGE: &str = "Usage:\n\tinsmod [options] filename [args]\nOptions:\n\t-V, --version     show version\n\t-h, --help        show this help\n";
+
+#[derive(Debug, Clone)]
+pub struct Insmod {}
+
+#[async_trait]
+impl Command for Insmod {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (err, exit_code) = execute(connection, params);
+
+        session.stderr(channel, err.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let Some((path, module_params)) = params.split_first() else {
+        return (USAGE.to_string(), 1);
+    };
+
+    connection.push_action(AuditLogAction::KernelModuleAttempt(
+        KernelModuleAttemptEvent {
+            command: Cow::Borrowed("insmod"),
+            module: Box::from(path.as_str()),
+            params: Box::from(module_params),
+        },
+    ));
+
+    if let Err(e) = connection.file_system().read(Path::new(path)) {
+        return (
+            format!("insmod: ERROR: could not load module {path}: {e}\n"),
+            1,
+        );
+    }
+
+    // loading an object file the client has uploaded themselves is almost certainly a rootkit
+    connection.tag("rootkit", "1");
+
+    let reason = if connection.username() == "root" {
+        // any module uploaded by an attacker won't have been built against our fake kernel
+        "Invalid module format"
+    } else {
+        "Operation not permitted"
+    };
+
+    (
+        format!("insmod: ERROR: could not insert module {path}: {reason}\n"),
+        1,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::{command::insmod::execute, server::ConnectionState};
+
+    #[test]
+    fn missing_file() {
+        let (err, exit_code) = execute(&mut ConnectionState::mock(), &["rootkit.ko".to_string()]);
+
+        assert_eq!(
+            err,
+            "insmod: ERROR: could not load module rootkit.ko: No such file or directory\n"
+        );
+        assert_eq!(exit_code, 1);
+    }
+
+    #[test]
+    fn uploaded_file() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("rootkit.ko"), b"\x7fELF".to_vec().into())
+            .unwrap();
+
+        let (err, exit_code) = execute(&mut state, &["rootkit.ko".to_string()]);
+
+        assert_eq!(
+            err,
+            "insmod: ERROR: could not insert module rootkit.ko: Invalid module format\n"
+        );
+        assert_eq!(exit_code, 1);
+        assert_eq!(state.audit_log().events.len(), 1);
+        assert!(state.audit_log().tags.contains_key("rootkit"));
+    }
+}
@@ -0,0 +1,413 @@
+use std::{borrow::Cow, fmt::Debug, marker::PhantomData, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::{
+    audit::{AuditLogAction, ScriptExecutionEvent},
+    byte_str::ByteStr,
+};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Describes how an interpreter is invoked and how it responds when it can't run a script.
+#[derive(Debug)]
+pub struct Language {
+    /// Name of the binary, used in audit events and error messages.
+    name: &'static str,
+    /// Flag taking the script inline, ie. `-c` for python.
+    inline_flag: char,
+    /// Whether the inline flag may be given multiple times, each being a line of the script.
+    repeatable: bool,
+    /// Flag printing the version, `--version` is always accepted too.
+    version_flag: char,
+    version: &'static str,
+    /// Flag taking the path to the script, for interpreters that don't just take it as the first
+    /// operand.
+    file_flag: Option<char>,
+    /// Flag running a module that ships with the interpreter rather than a script.
+    module_flag: Option<char>,
+    /// Flags that take a value which isn't the script.
+    takes_value: &'static [char],
+    /// Error printed when the inline flag isn't followed by a script.
+    missing_script: (&'static str, u32),
+    /// Formats the error printed when the script file can't be read.
+    unreadable_file: fn(name: &str, file: &str, error: &str) -> String,
+    unreadable_file_exit_code: u32,
+}
+
+pub trait Interpreted: Debug + Clone + Send + Sync {
+    const LANGUAGE: Language;
+}
+
+#[derive(Debug, Clone)]
+pub struct Python;
+
+impl Interpreted for Python {
+    const LANGUAGE: Language = Language {
+        name: "python",
+        ..Python3::LANGUAGE
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct Python3;
+
+impl Interpreted for Python3 {
+    const LANGUAGE: Language = Language {
+        name: "python3",
+        inline_flag: 'c',
+        repeatable: false,
+        version_flag: 'V',
+        version: "Python 3.9.2\n",
+        file_flag: None,
+        module_flag: Some('m'),
+        takes_value: &['W', 'X'],
+        missing_script: (
+            "Argument expected for the -c option\nusage: python3 [option] ... [-c cmd | -m mod | file | -] [arg] ...\nTry `python -h' for more information.\n",
+            2,
+        ),
+        unreadable_file: |name, file, e| format!("{name}: can't open file '{file}': [Errno 2] {e}\n"),
+        unreadable_file_exit_code: 2,
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct Perl;
+
+impl Interpreted for Perl {
+    const LANGUAGE: Language = Language {
+        name: "perl",
+        inline_flag: 'e',
+        repeatable: true,
+        version_flag: 'v',
+        version: "
+This is perl 5, version 32, subversion 1 (v5.32.1) built for x86_64-linux-gnu-thread-multi
+(with 48 registered patches, see perl -V for more detail)
+
+Copyright 1987-2021, Larry Wall
+
+Perl may be copied only under the terms of either the Artistic License or the
+GNU General Public License, which may be found in the Perl 5 source kit.
+
+Complete documentation for Perl, including FAQ lists, should be found on
+this system using \"man perl\" or \"perldoc perl\".  If you have access to the
+Internet, point your browser at http://www.perl.org/, the Perl Home Page.
+
+",
+        file_flag: None,
+        module_flag: None,
+        takes_value: &['I', 'M', 'm'],
+        missing_script: ("No code specified for -e.\n", 29),
+        unreadable_file: |_name, file, e| format!("Can't open perl script \"{file}\": {e}\n"),
+        unreadable_file_exit_code: 2,
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct Php;
+
+impl Interpreted for Php {
+    const LANGUAGE: Language = Language {
+        name: "php",
+        inline_flag: 'r',
+        repeatable: false,
+        version_flag: 'v',
+        version: "PHP 7.4.33 (cli) (built: Feb 22 2023 20:07:47) ( NTS )
+Copyright (c) The PHP Group
+Zend Engine v3.4.0, Copyright (c) Zend Technologies
+    with Zend OPcache v7.4.33, Copyright (c), by Zend Technologies
+",
+        file_flag: Some('f'),
+        module_flag: None,
+        takes_value: &['c', 'd', 'z'],
+        missing_script: ("Error in argument 1, char 2: no argument for option r\n", 1),
+        unreadable_file: |_name, file, _e| format!("Could not open input file: {file}\n"),
+        unreadable_file_exit_code: 1,
+    };
+}
+
+/// A stub interpreter that doesn't run anything, but records every script it's handed.
+#[derive(Debug, Clone)]
+pub struct Interpreter<L> {
+    args: Vec<String>,
+    /// Script received on stdin so far.
+    script: Vec<u8>,
+    language: PhantomData<L>,
+}
+
+#[async_trait]
+impl<L: Interpreted> Command for Interpreter<L> {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        match execute(&L::LANGUAGE, connection, params) {
+            Outcome::ReadStdin(args) => CommandResult::ReadStdin(Self {
+                args,
+                script: Vec::new(),
+                language: PhantomData,
+            }),
+            Outcome::Exit(out, exit_code) => {
+                if !out.is_empty() {
+                    session.data(channel, out.into());
+                }
+
+                CommandResult::Exit(exit_code)
+            }
+            Outcome::Fail(err, exit_code) => {
+                session.stderr(channel, err.into());
+                CommandResult::Exit(exit_code)
+            }
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        _channel: ChannelId,
+        data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        // ^D ends the script
+        if let Some(i) = data.iter().position(|c| *c == 0x04) {
+            self.script.extend_from_slice(&data[..i]);
+            self.abort(connection);
+            CommandResult::Exit(0)
+        } else {
+            self.script.extend_from_slice(data);
+            CommandResult::ReadStdin(self)
+        }
+    }
+
+    fn abort(self, connection: &mut ConnectionState) {
+        if self.script.is_empty() {
+            return;
+        }
+
+        record(
+            &L::LANGUAGE,
+            connection,
+            Some("-"),
+            &String::from_utf8_lossy(&self.script),
+            self.args,
+        );
+    }
+}
+
+/// Where the script to be executed comes from.
+#[derive(Debug, PartialEq, Eq)]
+enum Invocation<'a> {
+    Version,
+    Inline(String),
+    File(&'a str),
+    Module,
+    Stdin,
+}
+
+/// Parses the interpreter's arguments, returning how the script was given followed by the
+/// arguments to be passed through to it.
+fn parse_args<'a>(
+    language: &Language,
+    params: &'a [String],
+) -> Result<(Invocation<'a>, &'a [String]), (String, u32)> {
+    let mut inline: Option<Vec<&str>> = None;
+    let mut i = 0;
+
+    while let Some(param) = params.get(i) {
+        if param == "--version" {
+            return Ok((Invocation::Version, &[]));
+        }
+
+        let Some(flags) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+            break;
+        };
+
+        i += 1;
+
+        if flags == "-" {
+            break;
+        }
+
+        for (pos, c) in flags.char_indices() {
+            if c == language.version_flag {
+                return Ok((Invocation::Version, &[]));
+            } else if language.module_flag == Some(c) {
+                return Ok((Invocation::Module, &[]));
+            } else if c != language.inline_flag
+                && language.file_flag != Some(c)
+                && !language.takes_value.contains(&c)
+            {
+                continue;
+            }
+
+            let rest = &flags[pos + c.len_utf8()..];
+            let value = if rest.is_empty() {
+                i += 1;
+                params.get(i - 1).map(String::as_str)
+            } else {
+                Some(rest)
+            };
+
+            if c == language.inline_flag {
+                let Some(value) = value else {
+                    let (message, exit_code) = language.missing_script;
+                    return Err((message.to_string(), exit_code));
+                };
+
+                inline.get_or_insert_with(Vec::new).push(value);
+            } else if let Some(file) = value.filter(|_| language.file_flag == Some(c)) {
+                return Ok((Invocation::File(file), params.get(i..).unwrap_or_default()));
+            }
+
+            break;
+        }
+
+        if inline.is_some() && !language.repeatable {
+            break;
+        }
+    }
+
+    let rest = params.get(i..).unwrap_or_default();
+
+    Ok(match (inline, rest.split_first()) {
+        (Some(lines), _) => (Invocation::Inline(lines.join("\n")), rest),
+        (None, Some((file, args))) if file != "-" => (Invocation::File(file), args),
+        (None, Some((_, args))) => (Invocation::Stdin, args),
+        (None, None) => (Invocation::Stdin, rest),
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    /// The script is to be read from stdin, and passed the given arguments.
+    ReadStdin(Vec<String>),
+    Exit(String, u32),
+    /// The interpreter refused to run, printing the given error.
+    Fail(String, u32),
+}
+
+fn execute(language: &Language, connection: &mut ConnectionState, params: &[String]) -> Outcome {
+    let (invocation, args) = match parse_args(language, params) {
+        Ok(v) => v,
+        Err((err, exit_code)) => return Outcome::Fail(err, exit_code),
+    };
+
+    match invocation {
+        Invocation::Version => Outcome::Exit(language.version.to_string(), 0),
+        Invocation::Inline(script) => {
+            record(language, connection, None, &script, args.to_vec());
+            Outcome::Exit(String::new(), 0)
+        }
+        Invocation::File(file) => match connection.file_system().read(Path::new(file)) {
+            Ok(script) => {
+                let script = String::from_utf8_lossy(script).to_string();
+                record(language, connection, Some(file), &script, args.to_vec());
+                Outcome::Exit(String::new(), 0)
+            }
+            Err(e) => Outcome::Fail(
+                (language.unreadable_file)(language.name, file, &e.to_string()),
+                language.unreadable_file_exit_code,
+            ),
+        },
+        // running a module doesn't involve a script we'd be able to capture
+        Invocation::Module => Outcome::Exit(String::new(), 0),
+        Invocation::Stdin => Outcome::ReadStdin(args.to_vec()),
+    }
+}
+
+fn record(
+    language: &Language,
+    connection: &mut ConnectionState,
+    source: Option<&str>,
+    script: &str,
+    args: Vec<String>,
+) {
+    connection.push_action(AuditLogAction::ScriptExecution(ScriptExecutionEvent {
+        interpreter: Cow::Borrowed(language.name),
+        source: source.map(Box::from),
+        script: ByteStr::from(script),
+        args: args.into_boxed_slice(),
+    }));
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use insta::assert_debug_snapshot;
+    use test_case::test_case;
+
+    use crate::{
+        command::interpreter::{
+            execute, parse_args, Interpreted, Invocation, Outcome, Perl, Php, Python3,
+        },
+        server::ConnectionState,
+    };
+
+    #[test_case(&Python3::LANGUAGE, "-c 'import os' a b", &Invocation::Inline("import os".to_string()), &["a", "b"]; "python inline")]
+    #[test_case(&Python3::LANGUAGE, "-uc 'import os'", &Invocation::Inline("import os".to_string()), &[]; "python bundled")]
+    #[test_case(&Python3::LANGUAGE, "-W ignore x.py a", &Invocation::File("x.py"), &["a"]; "python file")]
+    #[test_case(&Python3::LANGUAGE, "- a", &Invocation::Stdin, &["a"]; "python stdin")]
+    #[test_case(&Python3::LANGUAGE, "-m http.server 80", &Invocation::Module, &[]; "python module")]
+    #[test_case(&Perl::LANGUAGE, "-le 'print 1' -e 'print 2' a", &Invocation::Inline("print 1\nprint 2".to_string()), &["a"]; "perl repeated inline")]
+    #[test_case(&Perl::LANGUAGE, "-I lib x.pl", &Invocation::File("x.pl"), &[]; "perl file")]
+    #[test_case(&Perl::LANGUAGE, "--version", &Invocation::Version, &[]; "perl version")]
+    #[test_case(&Php::LANGUAGE, "-r 'echo 1;'", &Invocation::Inline("echo 1;".to_string()), &[]; "php inline")]
+    #[test_case(&Php::LANGUAGE, "-f x.php -- a", &Invocation::File("x.php"), &["--", "a"]; "php file")]
+    #[test_case(&Php::LANGUAGE, "", &Invocation::Stdin, &[]; "php stdin")]
+    fn parses(
+        language: &super::Language,
+        input: &str,
+        expected: &Invocation<'_>,
+        expected_args: &[&str],
+    ) {
+        let input = shlex::split(input).unwrap();
+        let (invocation, args) = parse_args(language, &input).unwrap();
+
+        assert_eq!(&invocation, expected);
+        assert_eq!(args, expected_args);
+    }
+
+    #[test_case(&Python3::LANGUAGE, "-V", Outcome::Exit("Python 3.9.2\n".into(), 0); "python version")]
+    #[test_case(&Python3::LANGUAGE, "-c", Outcome::Fail("Argument expected for the -c option\nusage: python3 [option] ... [-c cmd | -m mod | file | -] [arg] ...\nTry `python -h' for more information.\n".into(), 2); "python missing script")]
+    #[test_case(&Python3::LANGUAGE, "x.py", Outcome::Fail("python3: can't open file 'x.py': [Errno 2] No such file or directory\n".into(), 2); "python missing file")]
+    #[test_case(&Perl::LANGUAGE, "x.pl", Outcome::Fail("Can't open perl script \"x.pl\": No such file or directory\n".into(), 2); "perl missing file")]
+    #[test_case(&Php::LANGUAGE, "x.php", Outcome::Fail("Could not open input file: x.php\n".into(), 1); "php missing file")]
+    #[test_case(&Php::LANGUAGE, "-r 'system(\"id\");'", Outcome::Exit(String::new(), 0); "php inline")]
+    fn output(language: &super::Language, input: &str, expected: Outcome) {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(execute(language, &mut state, &input), expected);
+    }
+
+    #[test]
+    fn records_scripts() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(
+                Path::new("x.pl"),
+                b"system('id');\n".to_vec().into_boxed_slice(),
+            )
+            .unwrap();
+
+        let input = shlex::split("-c 'import os; os.system(\"id\")' arg").unwrap();
+        execute(&Python3::LANGUAGE, &mut state, &input);
+
+        let input = shlex::split("x.pl").unwrap();
+        execute(&Perl::LANGUAGE, &mut state, &input);
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            assert_debug_snapshot!(state.audit_log());
+        });
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, Duration, OffsetDateTime};
+
+use crate::{
+    command::{dmesg::syslog_timestamp, Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Userspace journal entries, as (microseconds since the kernel finished booting, unit, message).
+const USERSPACE_LOG: &[(i64, &str, &str)] = &[
+    (
+        12_004,
+        "systemd-journald.service",
+        "systemd-journald[231]: Journal started",
+    ),
+    (98_112, "init.scope", "systemd[1]: Started Journal Service."),
+    (
+        402_771,
+        "init.scope",
+        "systemd[1]: Starting OpenBSD Secure Shell server...",
+    ),
+    (
+        408_213,
+        "cron.service",
+        "cron[598]: (CRON) INFO (pidfile fd = 3)",
+    ),
+    (
+        409_530,
+        "cron.service",
+        "cron[598]: (CRON) INFO (Running @reboot jobs)",
+    ),
+    (
+        611_094,
+        "ssh.service",
+        "sshd[612]: Server listening on 0.0.0.0 port 22.",
+    ),
+    (
+        611_250,
+        "ssh.service",
+        "sshd[612]: Server listening on :: port 22.",
+    ),
+    (
+        612_877,
+        "init.scope",
+        "systemd[1]: Started OpenBSD Secure Shell server.",
+    ),
+    (
+        1_004_213,
+        "init.scope",
+        "systemd[1]: Reached target Multi-User System.",
+    ),
+    (
+        1_004_980,
+        "init.scope",
+        "systemd[1]: Reached target Graphical Interface.",
+    ),
+    (
+        1_120_337,
+        "init.scope",
+        "systemd[1]: Startup finished in 3.004s (kernel) + 5.112s (userspace) = 8.116s.",
+    ),
+];
+
+const NOT_PERMITTED: &str =
+    "Hint: You are currently not seeing messages from other users and the system.
+      Users in groups 'adm', 'systemd-journal' can see all messages.
+      Pass -q to turn off this notice.
+-- No entries --
+";
+
+#[derive(Debug, Clone)]
+pub struct Journalctl {}
+
+#[async_trait]
+impl Command for Journalctl {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code, follow) = execute(connection, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        if follow && exit_code == 0 {
+            CommandResult::ReadStdin(Self {})
+        } else {
+            CommandResult::Exit(exit_code)
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn execute(connection: &ConnectionState, params: &[String]) -> (String, String, u32, bool) {
+    let mut kernel_only = false;
+    let mut follow = false;
+    let mut lines = None;
+    let mut unit = None;
+
+    let mut args = super::argparse(params);
+
+    while let Some(param) = args.next() {
+        match param {
+            Arg::Short('k') | Arg::Long("dmesg") => kernel_only = true,
+            Arg::Short('f') | Arg::Long("follow") => follow = true,
+            Arg::Short('n') | Arg::Long("lines") => match args.next() {
+                Some(Arg::Operand(n)) => match n.parse::<usize>() {
+                    Ok(n) => lines = Some(n),
+                    Err(_) => {
+                        return (
+                            String::new(),
+                            format!("Failed to parse lines '{n}'.\n"),
+                            1,
+                            false,
+                        );
+                    }
+                },
+                _ => lines = Some(10),
+            },
+            Arg::Short('u') | Arg::Long("unit") => match args.next() {
+                Some(Arg::Operand(u)) => unit = Some(u),
+                _ => {
+                    return (
+                        String::new(),
+                        "journalctl: option requires an argument -- 'u'\n".to_string(),
+                        1,
+                        false,
+                    );
+                }
+            },
+            Arg::Long(v) if v.starts_with("lines=") => {
+                match v.trim_start_matches("lines=").parse::<usize>() {
+                    Ok(n) => lines = Some(n),
+                    Err(_) => {
+                        return (
+                            String::new(),
+                            format!("Failed to parse lines '{v}'.\n"),
+                            1,
+                            false,
+                        );
+                    }
+                }
+            }
+            Arg::Short('b' | 'e' | 'x' | 'a' | 'q')
+            | Arg::Long("no-pager" | "boot" | "pager-end")
+            | Arg::Operand(_) => {}
+            Arg::Short(s) => {
+                return (
+                    String::new(),
+                    format!("journalctl: invalid option -- '{s}'\n"),
+                    1,
+                    false,
+                );
+            }
+            Arg::Long(s) => {
+                return (
+                    String::new(),
+                    format!("journalctl: unrecognized option '--{s}'\n"),
+                    1,
+                    false,
+                );
+            }
+        }
+    }
+
+    if connection.username() != "root" {
+        return (NOT_PERMITTED.to_string(), String::new(), 0, follow);
+    }
+
+    let boot_time = connection.boot_time();
+
+    let profile = connection.profile();
+    let kernel_log_end = Duration::microseconds(profile.kernel_log_end());
+
+    let kernel = profile.kernel_log().map(|(offset, message)| {
+        (
+            Duration::microseconds(offset),
+            "kernel",
+            format!("kernel: {message}"),
+        )
+    });
+
+    let userspace = USERSPACE_LOG
+        .iter()
+        .filter(|_| !kernel_only)
+        .map(|(offset, unit, message)| {
+            (
+                kernel_log_end + Duration::microseconds(*offset),
+                *unit,
+                (*message).to_string(),
+            )
+        });
+
+    let entries = kernel
+        .chain(userspace)
+        .filter(|(_, entry_unit, _)| match unit {
+            Some(u) => *entry_unit == u || entry_unit.strip_suffix(".service") == Some(u),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() {
+        return ("-- No entries --\n".to_string(), String::new(), 0, follow);
+    }
+
+    let skip = lines.map_or(0, |n| entries.len().saturating_sub(n));
+
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "-- Logs begin at {}, end at {}. --",
+        long_timestamp(boot_time),
+        long_timestamp(boot_time + entries.last().map_or(Duration::ZERO, |(v, _, _)| *v)),
+    )
+    .unwrap();
+
+    for (offset, _, message) in entries.into_iter().skip(skip) {
+        writeln!(
+            out,
+            "{} {} {message}",
+            syslog_timestamp(boot_time, offset),
+            connection.node_name(),
+        )
+        .unwrap();
+    }
+
+    (out, String::new(), 0, follow)
+}
+
+fn long_timestamp(ts: OffsetDateTime) -> String {
+    ts.format(format_description!(
+        "[weekday repr:short] [year]-[month]-[day] [hour]:[minute]:[second] UTC"
+    ))
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::journalctl::execute, server::ConnectionState};
+
+    #[test_case("", 43; "all")]
+    #[test_case("-k", 32; "kernel only")]
+    #[test_case("-n 3", 4; "lines")]
+    #[test_case("--lines=5 --no-pager", 6; "long lines")]
+    #[test_case("-u ssh", 3; "unit")]
+    #[test_case("-u ssh.service -k", 1; "unit kernel only")]
+    fn lines(input: &str, expected_lines: usize) {
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code, follow) = execute(&ConnectionState::mock(), &input);
+
+        assert_eq!(exit_code, 0);
+        assert_eq!(err, "");
+        assert!(!follow);
+        assert_eq!(out.lines().count(), expected_lines, "{out}");
+    }
+
+    #[test]
+    fn unknown_arg() {
+        let input = shlex::split("-z").unwrap();
+        let (out, err, exit_code, _follow) = execute(&ConnectionState::mock(), &input);
+        assert_eq!(out, "");
+        assert_eq!(err, "journalctl: invalid option -- 'z'\n");
+        assert_eq!(exit_code, 1);
+    }
+}
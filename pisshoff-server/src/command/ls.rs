@@ -1,13 +1,29 @@
-use std::{fmt::Write, path::Path};
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
 
 use async_trait::async_trait;
 use thrussh::ChannelId;
+use time::{format_description::FormatItem, macros::format_description, Duration, OffsetDateTime};
 
 use crate::{
-    command::{Command, CommandResult},
+    command::{argparse, chmod::render_mode, sysinfo::human_size, Arg, Command, CommandResult},
+    file_system::{FileSystem, Tree},
     server::{ConnectionState, ThrusshSession},
 };
 
+/// How old a file can be for `ls -l` to show the time it was modified rather than the year, which
+/// is six months as in coreutils.
+const RECENT: Duration = Duration::seconds(31_556_952 / 2);
+
+const RECENT_FORMAT: &[FormatItem<'static>] =
+    format_description!("[month repr:short] [day padding:space] [hour]:[minute]");
+const OLD_FORMAT: &[FormatItem<'static>] =
+    format_description!("[month repr:short] [day padding:space]  [year]");
+
 #[derive(Debug, Clone)]
 pub struct Ls {}
 
@@ -19,55 +35,21 @@ impl Command for Ls {
         channel: ChannelId,
         session: &mut S,
     ) -> CommandResult<Self> {
-        let mut error = false;
-
-        let resp = if params.is_empty() {
-            match connection.file_system().ls(None) {
-                Ok(v) => v.join("  "),
-                Err(e) => {
-                    error = true;
-                    format!("ls: {}: {e}", connection.file_system().pwd().display())
-                }
-            }
-        } else if params.len() == 1 {
-            match connection
-                .file_system()
-                .ls(Some(Path::new(params.first().unwrap())))
-            {
-                Ok(v) => v.join("  "),
-                Err(e) => {
-                    error = true;
-                    format!("ls: {}: {e}", params.first().unwrap())
-                }
-            }
-        } else {
-            let mut out = String::new();
-
-            for dir in params {
-                if !out.is_empty() {
-                    out.push('\n');
-                }
+        // directories don't keep track of when they were modified, so claim they've been
+        // untouched since boot
+        let boot_time = connection.boot_time();
+        let now = connection.now();
+        let (out, err, exit_code) = ls(connection.file_system(), params, boot_time, now);
 
-                match connection.file_system().ls(Some(Path::new(dir))) {
-                    Ok(v) => {
-                        write!(out, "{dir}:\n{}", v.join("  ")).unwrap();
-                    }
-                    Err(e) => {
-                        error = true;
-                        write!(out, "ls: {dir}: {e}").unwrap();
-                    }
-                }
-            }
-
-            out
-        };
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
 
-        if !resp.is_empty() {
-            let resp = resp.trim();
-            session.data(channel, format!("{resp}\n").into());
+        if !out.is_empty() {
+            session.data(channel, out.into());
         }
 
-        CommandResult::Exit(u32::from(error))
+        CommandResult::Exit(exit_code)
     }
 
     async fn stdin<S: ThrusshSession + Send>(
@@ -81,14 +63,312 @@ impl Command for Ls {
     }
 }
 
+#[derive(Default)]
+struct Options {
+    long: bool,
+    all: bool,
+    human: bool,
+    sort: Sort,
+}
+
+#[derive(Default)]
+enum Sort {
+    #[default]
+    Name,
+    /// Most recently modified first.
+    Time,
+}
+
+/// A single file or directory in the listing.
+struct Entry<'a> {
+    name: Cow<'a, str>,
+    path: PathBuf,
+    tree: &'a Tree,
+}
+
+/// Lists the given files and directories, returning stdout, stderr and the exit status.
+fn ls(
+    fs: &FileSystem,
+    params: &[String],
+    dir_mtime: OffsetDateTime,
+    now: OffsetDateTime,
+) -> (String, String, u32) {
+    let mut options = Options::default();
+    let mut operands = Vec::new();
+
+    for param in argparse(params) {
+        match param {
+            Arg::Short('l') => options.long = true,
+            Arg::Short('a') | Arg::Long("all") => options.all = true,
+            Arg::Short('h') | Arg::Long("human-readable") => options.human = true,
+            Arg::Short('t') => options.sort = Sort::Time,
+            // commonly aliased in, and there's no colour to add anyway
+            Arg::Long(v) if v == "color" || v.starts_with("color=") => {}
+            Arg::Operand(v) => operands.push(v),
+            Arg::Short(c) => {
+                return (
+                    String::new(),
+                    format!("ls: invalid option -- '{c}'\nTry 'ls --help' for more information.\n"),
+                    2,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    String::new(),
+                    format!(
+                        "ls: unrecognized option '--{v}'\nTry 'ls --help' for more information.\n"
+                    ),
+                    2,
+                );
+            }
+        }
+    }
+
+    let show_headers = operands.len() > 1;
+
+    if operands.is_empty() {
+        operands.push(".");
+    }
+
+    let mut err = String::new();
+    let mut exit_code = 0;
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+
+    for operand in operands {
+        let path = fs.canonicalise(Path::new(operand));
+
+        match fs.get(&path) {
+            Ok(tree @ (Tree::File(..) | Tree::Device(..))) => files.push(Entry {
+                name: Cow::Borrowed(operand),
+                path,
+                tree,
+            }),
+            Ok(Tree::Directory(_)) => directories.push((operand, path)),
+            Err(e) => {
+                writeln!(err, "ls: cannot access '{operand}': {e}").unwrap();
+                exit_code = 2;
+            }
+        }
+    }
+
+    let mut blocks = Vec::new();
+
+    if !files.is_empty() {
+        sort(&mut files, &options, dir_mtime);
+        blocks.push(render(fs, &files, &options, dir_mtime, now, false));
+    }
+
+    for (operand, path) in directories {
+        let mut entries = directory_entries(fs, &path, &options);
+        sort(&mut entries, &options, dir_mtime);
+
+        let mut block = if show_headers {
+            format!("{operand}:\n")
+        } else {
+            String::new()
+        };
+        block.push_str(&render(fs, &entries, &options, dir_mtime, now, true));
+        blocks.push(block);
+    }
+
+    (blocks.join("\n"), err, exit_code)
+}
+
+fn directory_entries<'a>(fs: &'a FileSystem, path: &Path, options: &Options) -> Vec<Entry<'a>> {
+    let Ok(dir @ Tree::Directory(children)) = fs.get(path) else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+
+    if options.all {
+        let parent = path.parent().unwrap_or(path);
+
+        entries.push(Entry {
+            name: Cow::Borrowed("."),
+            path: path.to_path_buf(),
+            tree: dir,
+        });
+        entries.push(Entry {
+            name: Cow::Borrowed(".."),
+            path: parent.to_path_buf(),
+            tree: fs.get(parent).unwrap_or(dir),
+        });
+    }
+
+    entries.extend(
+        children
+            .iter()
+            .filter(|(name, _)| options.all || !name.starts_with('.'))
+            .map(|(name, tree)| Entry {
+                name: Cow::Borrowed(name.as_str()),
+                path: path.join(name),
+                tree,
+            }),
+    );
+
+    entries
+}
+
+fn sort(entries: &mut [Entry<'_>], options: &Options, dir_mtime: OffsetDateTime) {
+    match options.sort {
+        Sort::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+        Sort::Time => entries.sort_by_key(|v| Reverse(mtime(v.tree, dir_mtime))),
+    }
+}
+
+fn mtime(tree: &Tree, dir_mtime: OffsetDateTime) -> OffsetDateTime {
+    match tree {
+        Tree::Directory(_) => dir_mtime,
+        Tree::File(_, metadata) | Tree::Device(_, metadata) => metadata.mtime,
+    }
+}
+
+/// Renders the entries, either as a list of names or as the columns shown by `ls -l`. `total`
+/// is only shown when listing the contents of a directory.
+fn render(
+    fs: &FileSystem,
+    entries: &[Entry<'_>],
+    options: &Options,
+    dir_mtime: OffsetDateTime,
+    now: OffsetDateTime,
+    total: bool,
+) -> String {
+    if !options.long {
+        if entries.is_empty() {
+            return String::new();
+        }
+
+        let names: Vec<_> = entries.iter().map(|v| v.name.as_ref()).collect();
+        return format!("{}\n", names.join("  "));
+    }
+
+    let rows: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let (kind, mode, owner, group) = match entry.tree {
+                Tree::Directory(_) => {
+                    let owner = directory_owner(fs, &entry.path);
+                    ('d', directory_mode(&entry.path), owner, owner)
+                }
+                Tree::File(_, metadata) => (
+                    '-',
+                    metadata.mode,
+                    metadata.owner.as_str(),
+                    metadata.group.as_str(),
+                ),
+                Tree::Device(_, metadata) => (
+                    'c',
+                    metadata.mode,
+                    metadata.owner.as_str(),
+                    metadata.group.as_str(),
+                ),
+            };
+
+            let size = if let Tree::Device(device, _) = entry.tree {
+                let (major, minor) = device.numbers(&entry.path);
+                format!("{major}, {minor}")
+            } else if options.human {
+                human_bytes(entry.tree.size())
+            } else {
+                entry.tree.size().to_string()
+            };
+
+            [
+                format!("{kind}{}", render_mode(mode)),
+                entry.tree.links().to_string(),
+                owner.to_string(),
+                group.to_string(),
+                size,
+                timestamp(mtime(entry.tree, dir_mtime), now),
+            ]
+        })
+        .collect();
+
+    let width = |column: usize| rows.iter().map(|v| v[column].len()).max().unwrap_or(0);
+    let (links, owner, group, size) = (width(1), width(2), width(3), width(4));
+
+    let mut out = String::new();
+
+    if total {
+        // sizes are counted in 1K units, with every file taking up at least one 4K block
+        let blocks: u64 = entries
+            .iter()
+            .map(|v| v.tree.size().div_ceil(4096) * 4)
+            .sum();
+
+        if options.human {
+            writeln!(out, "total {}", human_size(blocks, &["K", "M", "G", "T"])).unwrap();
+        } else {
+            writeln!(out, "total {blocks}").unwrap();
+        }
+    }
+
+    for (row, entry) in rows.iter().zip(entries) {
+        writeln!(
+            out,
+            "{} {:>links$} {:<owner$} {:<group$} {:>size$} {} {}",
+            row[0], row[1], row[2], row[3], row[4], row[5], entry.name,
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+/// Directories don't have metadata of their own, so they're owned by the user within their home
+/// directory and by root everywhere else.
+fn directory_owner<'a>(fs: &'a FileSystem, path: &Path) -> &'a str {
+    if path.starts_with(fs.home()) {
+        fs.user()
+    } else {
+        "root"
+    }
+}
+
+fn directory_mode(path: &Path) -> u32 {
+    match path.to_str() {
+        Some("/tmp" | "/var/tmp" | "/dev/shm") => 0o1777,
+        Some("/root") => 0o700,
+        _ if path.ends_with(".ssh") => 0o700,
+        _ => 0o755,
+    }
+}
+
+/// Formats a size in bytes the way `ls -h` does.
+fn human_bytes(size: u64) -> String {
+    if size < 1024 {
+        size.to_string()
+    } else {
+        human_size(size, &["", "K", "M", "G", "T"])
+    }
+}
+
+fn timestamp(mtime: OffsetDateTime, now: OffsetDateTime) -> String {
+    let format = if mtime <= now && now - mtime < RECENT {
+        RECENT_FORMAT
+    } else {
+        OLD_FORMAT
+    };
+
+    mtime.format(format).unwrap_or_default()
+}
+
 #[cfg(test)]
 mod test {
     use std::path::Path;
 
     use mockall::predicate::always;
+    use test_case::test_case;
+    use time::macros::datetime;
 
     use crate::{
-        command::{ls::Ls, Command, CommandResult},
+        command::{
+            ls::{ls, Ls},
+            Command, CommandResult,
+        },
+        file_system::FileSystem,
         server::{
             test::{fake_channel_id, predicate::eq_string},
             ConnectionState, MockThrusshSession,
@@ -134,4 +414,79 @@ mod test {
 
         assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
     }
+
+    fn file_system() -> FileSystem {
+        let mut fs = FileSystem::new("root");
+        fs.mkdirall(Path::new("/root/.ssh")).unwrap();
+        fs.mkdirall(Path::new("/root/backup")).unwrap();
+
+        for (path, size, mtime) in [
+            (".bashrc", 3106, datetime!(2022-10-15 06:51:12 UTC)),
+            (
+                ".ssh/authorized_keys",
+                0,
+                datetime!(2023-07-27 22:03:41 UTC),
+            ),
+            (
+                "backup/db.sql",
+                2_483_201,
+                datetime!(2023-07-28 03:00:02 UTC),
+            ),
+            ("run.sh", 58, datetime!(2023-07-28 14:02:19 UTC)),
+        ] {
+            fs.write(Path::new(path), vec![0; size].into_boxed_slice())
+                .unwrap();
+            fs.metadata_mut(Path::new(path)).unwrap().mtime = mtime;
+        }
+
+        fs.metadata_mut(Path::new("run.sh")).unwrap().mode = 0o755;
+
+        for device in ["null", "random", "urandom", "zero"] {
+            fs.metadata_mut(&Path::new("/dev").join(device))
+                .unwrap()
+                .mtime = datetime!(2023-07-25 09:12:51 UTC);
+        }
+
+        fs
+    }
+
+    #[test_case(""; "none")]
+    #[test_case("-a"; "all")]
+    #[test_case("-l"; "long")]
+    #[test_case("-la"; "long all")]
+    #[test_case("-lh backup"; "human")]
+    #[test_case("-lt"; "sorted by time")]
+    #[test_case("-l run.sh /tmp backup"; "operands")]
+    #[test_case("-l /dev"; "devices")]
+    fn ls_snapshot(input: &str) {
+        let mut fs = file_system();
+        fs.mkdirall(Path::new("/tmp")).unwrap();
+
+        let (out, err, exit_code) = ls(
+            &fs,
+            &shlex::split(input).unwrap(),
+            datetime!(2023-07-25 09:12:44 UTC),
+            datetime!(2023-07-28 14:30:12 UTC),
+        );
+
+        insta::assert_snapshot!(format!("ls {input}").trim(), out);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test_case("missing", "ls: cannot access 'missing': No such file or directory\n", 2; "missing")]
+    #[test_case("-x", "ls: invalid option -- 'x'\nTry 'ls --help' for more information.\n", 2; "invalid option")]
+    #[test_case("--bogus", "ls: unrecognized option '--bogus'\nTry 'ls --help' for more information.\n", 2; "unrecognized option")]
+    fn ls_error(input: &str, expected: &str, expected_exit_code: u32) {
+        let (out, err, exit_code) = ls(
+            &file_system(),
+            &shlex::split(input).unwrap(),
+            datetime!(2023-07-25 09:12:44 UTC),
+            datetime!(2023-07-28 14:30:12 UTC),
+        );
+
+        assert_eq!(out, "");
+        assert_eq!(err, expected);
+        assert_eq!(exit_code, expected_exit_code);
+    }
 }
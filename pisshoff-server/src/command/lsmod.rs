@@ -0,0 +1,135 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Modules loaded into the fake kernel, as (name, size, used by).
+pub const MODULES: &[(&str, u32, &[&str])] = &[
+    ("xt_conntrack", 16_384, &[]),
+    ("nf_conntrack", 172_032, &["xt_conntrack"]),
+    ("nf_defrag_ipv6", 24_576, &["nf_conntrack"]),
+    ("nf_defrag_ipv4", 16_384, &["nf_conntrack"]),
+    ("iptable_filter", 16_384, &[]),
+    ("bpfilter", 16_384, &[]),
+    ("intel_rapl_msr", 20_480, &[]),
+    ("intel_rapl_common", 28_672, &["intel_rapl_msr"]),
+    ("skx_edac", 24_576, &[]),
+    ("x86_pkg_temp_thermal", 20_480, &[]),
+    ("intel_powerclamp", 20_480, &[]),
+    ("coretemp", 20_480, &[]),
+    ("kvm_intel", 327_680, &[]),
+    ("kvm", 1_032_192, &["kvm_intel"]),
+    ("irqbypass", 16_384, &["kvm"]),
+    ("ipmi_ssif", 36_864, &[]),
+    ("ipmi_si", 69_632, &[]),
+    ("ipmi_devintf", 20_480, &[]),
+    (
+        "ipmi_msghandler",
+        122_880,
+        &["ipmi_devintf", "ipmi_si", "ipmi_ssif"],
+    ),
+    ("ixgbe", 364_544, &[]),
+    ("mdio", 16_384, &["ixgbe"]),
+    ("ahci", 45_056, &[]),
+    ("libahci", 45_056, &["ahci"]),
+    ("megaraid_sas", 176_128, &[]),
+    ("ext4", 925_696, &[]),
+    ("mbcache", 16_384, &["ext4"]),
+    ("jbd2", 167_936, &["ext4"]),
+];
+
+/// Modules that aren't loaded but exist under `/lib/modules` and can be loaded by `modprobe`.
+pub const AVAILABLE_MODULES: &[&str] = &[
+    "br_netfilter",
+    "bridge",
+    "dummy",
+    "ip_tables",
+    "ip6_tables",
+    "loop",
+    "msr",
+    "overlay",
+    "tun",
+    "veth",
+    "wireguard",
+    "xfs",
+];
+
+#[derive(Debug, Clone)]
+pub struct Lsmod {}
+
+#[async_trait]
+impl Command for Lsmod {
+    async fn new<S: ThrusshSession + Send>(
+        _connection: &mut ConnectionState,
+        _params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        session.data(channel, execute().into());
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute() -> String {
+    let mut out = String::from("Module                  Size  Used by\n");
+
+    for (name, size, used_by) in MODULES {
+        write!(out, "{name:<19} {size:>8}  {}", used_by.len()).unwrap();
+
+        if !used_by.is_empty() {
+            write!(out, " {}", used_by.join(",")).unwrap();
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Returns true if the given module name is known to the fake kernel, module names are treated
+/// the same regardless of whether they use dashes or underscores.
+pub fn is_known_module(name: &str) -> bool {
+    let name = name.replace('-', "_");
+
+    MODULES.iter().any(|(module, _, _)| *module == name) || AVAILABLE_MODULES.contains(&&*name)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command::lsmod::{execute, is_known_module};
+
+    #[test]
+    fn output() {
+        let out = execute();
+        let mut lines = out.lines();
+
+        assert_eq!(lines.next(), Some("Module                  Size  Used by"));
+        assert_eq!(lines.next(), Some("xt_conntrack           16384  0"));
+        assert_eq!(
+            lines.next(),
+            Some("nf_conntrack          172032  1 xt_conntrack")
+        );
+    }
+
+    #[test]
+    fn known_modules() {
+        assert!(is_known_module("kvm"));
+        assert!(is_known_module("br-netfilter"));
+        assert!(!is_known_module("diamorphine"));
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{
+        help::{manual, Manual, BUSYBOX_BANNER},
+        Arg, Command, CommandResult,
+    },
+    config::Personality,
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Width manual pages are formatted to, as if the client's terminal was 80 columns wide.
+const WIDTH: usize = 80;
+
+/// Indentation of the body of each section of a manual page.
+const INDENT: &str = "       ";
+
+const BUSYBOX_USAGE: &str = "Usage: man [-aw] [SECTION] MANPAGE[.EXT]...
+
+Display manual page
+
+\t-a\tDisplay all pages
+\t-w\tShow page locations
+
+$COLUMNS overrides output width
+";
+
+#[derive(Debug, Clone)]
+pub struct Man {}
+
+#[async_trait]
+impl Command for Man {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection.config().personality, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(personality: Personality, params: &[String]) -> (String, String, u32) {
+    let mut pages = super::argparse(params)
+        .filter_map(|v| match v {
+            Arg::Operand(v) => Some(v),
+            Arg::Short(_) | Arg::Long(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    if personality == Personality::Busybox {
+        // BusyBox systems don't ship with any manual pages
+        return match pages.first() {
+            None => (String::new(), format!("{BUSYBOX_BANNER}{BUSYBOX_USAGE}"), 1),
+            Some(page) => (
+                String::new(),
+                format!("man: no manual entry for {page}\n"),
+                1,
+            ),
+        };
+    }
+
+    // a leading number restricts the lookup to a single section, ie. `man 8 modprobe`
+    let section = match pages.as_slice() {
+        [section, _, ..] if section.bytes().all(|c| c.is_ascii_digit()) => {
+            let section = section.parse::<u8>().ok();
+            pages.remove(0);
+            section
+        }
+        _ => None,
+    };
+
+    if pages.is_empty() {
+        return (
+            String::new(),
+            "What manual page do you want?\nFor example, try 'man man'.\n".to_string(),
+            1,
+        );
+    }
+
+    let mut out = String::new();
+    let mut err = String::new();
+    let mut exit_code = 0;
+
+    for page in pages {
+        let found = manual(page).filter(|v| section.is_none() || section == Some(v.section));
+
+        if let Some(manual) = found {
+            out.push_str(&render(manual));
+            continue;
+        }
+
+        // man-db returns 16 if at least one page couldn't be found
+        exit_code = 16;
+
+        if let Some(section) = section {
+            writeln!(err, "No manual entry for {page} in section {section}").unwrap();
+        } else {
+            writeln!(err, "No manual entry for {page}").unwrap();
+        }
+    }
+
+    (out, err, exit_code)
+}
+
+/// Renders the manual page for a command in the style of `help2man`, which builds the page
+/// from the command's `--help` output.
+fn render(manual: &Manual) -> String {
+    let title = format!("{}({})", manual.name.to_uppercase(), manual.section);
+    let heading = if manual.section == 8 {
+        "System Administration"
+    } else {
+        "User Commands"
+    };
+
+    let (synopsis, description) = split_usage(manual.gnu);
+
+    let mut out = String::new();
+    writeln!(out, "{}\n", columns(&title, heading, &title)).unwrap();
+    writeln!(out, "NAME\n{INDENT}{} - {}\n", manual.name, manual.summary).unwrap();
+
+    out.push_str("SYNOPSIS\n");
+    for line in synopsis {
+        writeln!(out, "{INDENT}{line}").unwrap();
+    }
+
+    out.push_str("\nDESCRIPTION\n");
+    for line in description {
+        let line = line.replace('\t', "        ");
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            writeln!(out, "{INDENT}{line}").unwrap();
+        }
+    }
+
+    writeln!(out, "\n{}", columns(manual.source, manual.date, &title)).unwrap();
+
+    out
+}
+
+/// Splits `--help` output into the lines of its usage, and the rest of the text.
+fn split_usage(help: &str) -> (Vec<&str>, Vec<&str>) {
+    let mut lines = help.trim_start_matches('\n').lines().peekable();
+    let mut usage = Vec::new();
+
+    if let Some(rest) = lines.next().and_then(|v| v.strip_prefix("Usage:")) {
+        if !rest.trim().is_empty() {
+            usage.push(rest.trim());
+        }
+    }
+
+    // further forms of the command are listed on indented lines
+    while let Some(line) = lines.next_if(|v| v.starts_with([' ', '\t'])) {
+        usage.push(line.trim());
+    }
+
+    while lines.next_if(|v| v.is_empty()).is_some() {}
+
+    (usage, lines.collect())
+}
+
+/// Lays out a header or footer line, with `center` in the middle of the page.
+fn columns(left: &str, center: &str, right: &str) -> String {
+    let padding = WIDTH.saturating_sub(left.len() + center.len() + right.len());
+    let before = padding / 2;
+    let after = padding - before;
+
+    format!("{left}{:before$}{center}{:after$}{right}", "", "")
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::man::execute, config::Personality};
+
+    #[test_case("", "What manual page do you want?\nFor example, try 'man man'.\n", 1; "no page")]
+    #[test_case("nmap", "No manual entry for nmap\n", 16; "unknown page")]
+    #[test_case("1 modprobe", "No manual entry for modprobe in section 1\n", 16; "wrong section")]
+    fn output(input: &str, expected_error: &str, expected_exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = execute(Personality::Gnu, &input);
+
+        assert_eq!(out, "");
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test_case("uname"; "uname")]
+    #[test_case("8 modprobe"; "modprobe")]
+    #[test_case("dmesg"; "dmesg")]
+    fn page(input: &str) {
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = execute(Personality::Gnu, &input);
+
+        insta::assert_snapshot!(input.join(" "), out);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test]
+    fn busybox() {
+        let input = ["ls".to_string()];
+        let (out, err, exit_code) = execute(Personality::Busybox, &input);
+
+        assert_eq!(out, "");
+        assert_eq!(err, "man: no manual entry for ls\n");
+        assert_eq!(exit_code, 1);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{borrow::Cow, fmt::Write};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, KernelModuleAttemptEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{lsmod::is_known_module, Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Modprobe {}
+
+#[async_trait]
+impl Command for Modprobe {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (err, exit_code) = execute(connection, params);
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut remove = false;
+    let mut quiet = false;
+    let mut all = false;
+    let mut operands = Vec::new();
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('r') | Arg::Long("remove") => remove = true,
+            Arg::Short('q') | Arg::Long("quiet") => quiet = true,
+            Arg::Short('a') | Arg::Long("all") => all = true,
+            Arg::Short('v' | 'f' | 'b') | Arg::Long("verbose" | "force" | "use-blacklist") => {}
+            Arg::Operand(v) => operands.push(v),
+            Arg::Short(s) => {
+                return (format!("modprobe: invalid option -- '{s}'\n"), 1);
+            }
+            Arg::Long(s) => {
+                return (format!("modprobe: unrecognized option '--{s}'\n"), 1);
+            }
+        }
+    }
+
+    if operands.is_empty() {
+        return (
+            "modprobe: ERROR: missing parameters. See -h.\n".to_string(),
+            1,
+        );
+    }
+
+    // without `-a` only the first operand is a module, the rest are its parameters
+    let (modules, module_params) = if all || remove {
+        (operands.as_slice(), [].as_slice())
+    } else {
+        operands.split_at(1)
+    };
+
+    let mut err = String::new();
+    let mut exit_code = 0;
+
+    for module in modules {
+        if !remove {
+            connection.push_action(AuditLogAction::KernelModuleAttempt(
+                KernelModuleAttemptEvent {
+                    command: Cow::Borrowed("modprobe"),
+                    module: Box::from(*module),
+                    params: module_params.iter().map(ToString::to_string).collect(),
+                },
+            ));
+        }
+
+        if !is_known_module(module) {
+            if !quiet {
+                writeln!(
+                    err,
+                    "modprobe: FATAL: Module {module} not found in directory /lib/modules/{}",
+                    connection.profile().kernel_release,
+                )
+                .unwrap();
+            }
+
+            exit_code = 1;
+            break;
+        }
+
+        if connection.username() != "root" {
+            let verb = if remove { "remove" } else { "insert" };
+            writeln!(
+                err,
+                "modprobe: ERROR: could not {verb} '{module}': Operation not permitted"
+            )
+            .unwrap();
+            exit_code = 1;
+            break;
+        }
+    }
+
+    (err, exit_code)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::modprobe::execute, server::ConnectionState};
+
+    #[test_case("overlay", "", 0; "available module")]
+    #[test_case("-r kvm", "", 0; "remove module")]
+    #[test_case("diamorphine", "modprobe: FATAL: Module diamorphine not found in directory /lib/modules/5.15.49\n", 1; "unknown module")]
+    #[test_case("-q diamorphine", "", 1; "quiet unknown module")]
+    #[test_case("", "modprobe: ERROR: missing parameters. See -h.\n", 1; "no module")]
+    fn output(input: &str, expected_error: &str, expected_exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (err, exit_code) = execute(&mut ConnectionState::mock(), &input);
+
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test]
+    fn audits() {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split("diamorphine foo=bar").unwrap();
+        let _res = execute(&mut state, &input);
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            insta::assert_debug_snapshot!(state.audit_log());
+        });
+    }
+}
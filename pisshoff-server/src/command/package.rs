@@ -0,0 +1,924 @@
+//! Package managers. Nothing is ever downloaded, but the requested packages are logged and the
+//! commands they provide become runnable for the rest of the session, so scripts that install
+//! their dependencies first carry on to the interesting part.
+
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    fmt::Write,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, PackageInstallEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// The longest a package manager will pretend to be downloading for.
+const MAX_DOWNLOAD_TIME: Duration = Duration::from_secs(30);
+
+/// Packages that come with the fake system as they provide commands that are already emulated.
+const PREINSTALLED: &[&str] = &[
+    "bash",
+    "coreutils",
+    "gzip",
+    "openssh-client",
+    "perl",
+    "python3",
+    "sudo",
+    "tar",
+    "unzip",
+];
+
+/// Commands provided by packages that aren't named after them. Anything not listed provides a
+/// command of the same name, unless it looks like a library.
+const PROVIDES: &[(&str, &[&str])] = &[
+    ("bind-utils", &["dig", "host", "nslookup"]),
+    ("build-essential", &["gcc", "g++", "make"]),
+    ("dnsutils", &["dig", "host", "nslookup"]),
+    ("gcc-c++", &["g++"]),
+    ("iproute2", &["ip", "ss"]),
+    ("iputils-ping", &["ping"]),
+    ("net-tools", &["arp", "ifconfig", "netstat", "route"]),
+    ("netcat", &["nc"]),
+    ("netcat-openbsd", &["nc"]),
+    ("nmap-ncat", &["nc", "ncat"]),
+    ("procps", &["free", "ps", "top", "uptime"]),
+    ("procps-ng", &["free", "ps", "top", "uptime"]),
+    ("py3-pip", &["pip3"]),
+    ("python3-pip", &["pip3"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Manager {
+    Apt,
+    Yum,
+    Apk,
+}
+
+impl Manager {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Apt => "apt-get",
+            Self::Yum => "yum",
+            Self::Apk => "apk",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Package {
+    name: String,
+    version: String,
+    /// Download size in kB.
+    size: u64,
+    /// Whether the package was already installed before it was requested.
+    installed: bool,
+}
+
+impl Package {
+    /// Looks up a package, the version and size are generated from its name so they're the same
+    /// every time it's installed.
+    fn new(connection: &ConnectionState, manager: Manager, name: &str) -> Self {
+        // ignore version and architecture constraints, ie. `curl=7.81.0` or `curl:amd64`
+        let name = name.split(['=', ':']).next().unwrap_or_default();
+
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        let rng = fastrand::Rng::with_seed(hasher.finish());
+
+        let (major, minor, patch, release) =
+            (rng.u8(0..10), rng.u8(0..30), rng.u8(0..20), rng.u8(1..6));
+
+        let version = match manager {
+            Manager::Apt => format!("{major}.{minor}.{patch}-{release}"),
+            Manager::Yum => format!("{major}.{minor}.{patch}-{release}.el8"),
+            Manager::Apk => format!("{major}.{minor}.{patch}-r{release}"),
+        };
+
+        let provides = provides(name);
+
+        Self {
+            name: name.to_string(),
+            version,
+            size: rng.u64(40..2400),
+            installed: PREINSTALLED.contains(&name)
+                || (!provides.is_empty() && provides.iter().all(|v| connection.is_installed(v))),
+        }
+    }
+}
+
+/// Returns the commands a package provides.
+fn provides(package: &str) -> Vec<&str> {
+    if let Some((_, commands)) = PROVIDES.iter().find(|(name, _)| *name == package) {
+        commands.to_vec()
+    } else if package.starts_with("lib")
+        || ["-dev", "-devel", "-headers", "-doc"]
+            .iter()
+            .any(|v| package.ends_with(v))
+    {
+        Vec::new()
+    } else {
+        vec![package]
+    }
+}
+
+/// Records the packages requested by the client and looks them up.
+fn resolve(connection: &mut ConnectionState, manager: Manager, names: &[&str]) -> Vec<Package> {
+    if !names.is_empty() {
+        connection.push_action(AuditLogAction::PackageInstall(PackageInstallEvent {
+            manager: Cow::Borrowed(manager.name()),
+            packages: names.iter().map(ToString::to_string).collect(),
+        }));
+    }
+
+    names
+        .iter()
+        .map(|v| Package::new(connection, manager, v))
+        .collect()
+}
+
+/// Makes the commands provided by the packages available, returning how long downloading them
+/// should appear to take.
+fn install(connection: &mut ConnectionState, packages: &[&Package]) -> Duration {
+    for package in packages {
+        for command in provides(&package.name) {
+            connection.install_command(command);
+        }
+    }
+
+    download_time(connection, packages.iter().map(|v| v.size).sum())
+}
+
+fn download_time(connection: &ConnectionState, size: u64) -> Duration {
+    let speed = connection.config().package_download_speed.max(1);
+    Duration::from_millis(size.saturating_mul(1000) / speed).min(MAX_DOWNLOAD_TIME)
+}
+
+/// Formats a number with thousands separators, as `apt` does for sizes.
+fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+
+    digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|v| std::str::from_utf8(v).unwrap())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn apt_install(connection: &ConnectionState, packages: &[Package]) -> String {
+    let mut out = String::from(
+        "Reading package lists... Done\n\
+         Building dependency tree... Done\n\
+         Reading state information... Done\n",
+    );
+
+    for package in packages.iter().filter(|v| v.installed) {
+        writeln!(
+            out,
+            "{} is already the newest version ({}).",
+            package.name, package.version
+        )
+        .unwrap();
+    }
+
+    let new: Vec<_> = packages.iter().filter(|v| !v.installed).collect();
+
+    if !new.is_empty() {
+        out.push_str("The following NEW packages will be installed:\n ");
+
+        for package in &new {
+            write!(out, " {}", package.name).unwrap();
+        }
+
+        out.push('\n');
+    }
+
+    writeln!(
+        out,
+        "0 upgraded, {} newly installed, 0 to remove and 37 not upgraded.",
+        new.len()
+    )
+    .unwrap();
+
+    if new.is_empty() {
+        return out;
+    }
+
+    let size = new.iter().map(|v| v.size).sum::<u64>();
+    let speed = connection.config().package_download_speed;
+
+    writeln!(out, "Need to get {} kB of archives.", thousands(size)).unwrap();
+    writeln!(
+        out,
+        "After this operation, {} kB of additional disk space will be used.",
+        thousands(size * 3)
+    )
+    .unwrap();
+
+    for (i, package) in new.iter().enumerate() {
+        writeln!(
+            out,
+            "Get:{} http://archive.ubuntu.com/ubuntu jammy/universe amd64 {} amd64 {} [{} kB]",
+            i + 1,
+            package.name,
+            package.version,
+            thousands(package.size)
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        out,
+        "Fetched {} kB in {}s ({} kB/s)",
+        thousands(size),
+        download_time(connection, size).as_secs(),
+        thousands(speed)
+    )
+    .unwrap();
+
+    for package in &new {
+        writeln!(
+            out,
+            "Selecting previously unselected package {name}.\n\
+             (Reading database ... 71234 files and directories currently installed.)\n\
+             Preparing to unpack .../{name}_{version}_amd64.deb ...\n\
+             Unpacking {name} ({version}) ...",
+            name = package.name,
+            version = package.version,
+        )
+        .unwrap();
+    }
+
+    for package in &new {
+        writeln!(out, "Setting up {} ({}) ...", package.name, package.version).unwrap();
+    }
+
+    out.push_str("Processing triggers for man-db (2.10.2-1) ...\n");
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct AptGet {}
+
+#[async_trait]
+impl Command for AptGet {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut args = params
+            .iter()
+            .map(String::as_str)
+            .filter(|v| !v.starts_with('-'));
+
+        let Some(action) = args.next() else {
+            session.data(
+                channel,
+                "apt 2.4.10 (amd64)\n\
+                 Usage: apt-get [options] command\n       \
+                 apt-get [options] install|remove pkg1 [pkg2 ...]\n"
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        };
+
+        let names: Vec<_> = args.collect();
+        let packages = if action == "install" {
+            resolve(connection, Manager::Apt, &names)
+        } else {
+            Vec::new()
+        };
+
+        if connection.username() != "root" {
+            session.stderr(
+                channel,
+                "E: Could not open lock file /var/lib/dpkg/lock-frontend - open (13: Permission \
+                 denied)\n\
+                 E: Unable to acquire the dpkg frontend lock (/var/lib/dpkg/lock-frontend), are \
+                 you root?\n"
+                    .into(),
+            );
+            return CommandResult::Exit(100);
+        }
+
+        match action {
+            "install" => {
+                let out = apt_install(connection, &packages);
+                let new: Vec<_> = packages.iter().filter(|v| !v.installed).collect();
+                tokio::time::sleep(install(connection, &new)).await;
+
+                session.data(channel, out.into());
+            }
+            "update" => {
+                tokio::time::sleep(download_time(connection, 1800)).await;
+
+                session.data(
+                    channel,
+                    "Hit:1 http://archive.ubuntu.com/ubuntu jammy InRelease\n\
+                     Get:2 http://archive.ubuntu.com/ubuntu jammy-updates InRelease [119 kB]\n\
+                     Get:3 http://security.ubuntu.com/ubuntu jammy-security InRelease [110 kB]\n\
+                     Get:4 http://archive.ubuntu.com/ubuntu jammy-updates/main amd64 Packages [1,571 kB]\n\
+                     Reading package lists... Done\n"
+                        .into(),
+                );
+            }
+            "upgrade" | "dist-upgrade" | "full-upgrade" => {
+                session.data(
+                    channel,
+                    "Reading package lists... Done\n\
+                     Building dependency tree... Done\n\
+                     Reading state information... Done\n\
+                     Calculating upgrade... Done\n\
+                     0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.\n"
+                        .into(),
+                );
+            }
+            other => {
+                session.stderr(channel, format!("E: Invalid operation {other}\n").into());
+                return CommandResult::Exit(100);
+            }
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// Formats a size in kB the way `yum` does.
+fn yum_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{size} k")
+    } else {
+        #[allow(clippy::cast_precision_loss)]
+        let size = size as f64 / 1024.0;
+        format!("{size:.1} M")
+    }
+}
+
+fn yum_transaction(packages: &[&Package]) -> String {
+    let rule = "=".repeat(80);
+    let mut out = String::from("Dependencies resolved.\n");
+
+    writeln!(out, "{rule}").unwrap();
+    writeln!(
+        out,
+        " {:<24} {:<12} {:<20} {:<12} {:>6}",
+        "Package", "Architecture", "Version", "Repository", "Size"
+    )
+    .unwrap();
+    writeln!(out, "{rule}\nInstalling:").unwrap();
+
+    for package in packages {
+        writeln!(
+            out,
+            " {:<24} {:<12} {:<20} {:<12} {:>6}",
+            package.name,
+            "x86_64",
+            package.version,
+            "appstream",
+            yum_size(package.size)
+        )
+        .unwrap();
+    }
+
+    let size = packages.iter().map(|v| v.size).sum::<u64>();
+
+    writeln!(
+        out,
+        "\nTransaction Summary\n{rule}\nInstall  {} Package{}\n",
+        packages.len(),
+        if packages.len() == 1 { "" } else { "s" }
+    )
+    .unwrap();
+    writeln!(out, "Total download size: {}", yum_size(size)).unwrap();
+    writeln!(out, "Installed size: {}", yum_size(size * 3)).unwrap();
+
+    out
+}
+
+fn yum_install(packages: &[&Package]) -> String {
+    let mut out = String::from("Downloading Packages:\n");
+    let total = packages.len();
+
+    for (i, package) in packages.iter().enumerate() {
+        writeln!(
+            out,
+            "({}/{total}): {:<48} {:>6}     00:00",
+            i + 1,
+            format!("{}-{}.x86_64.rpm", package.name, package.version),
+            yum_size(package.size),
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "{}", "-".repeat(80)).unwrap();
+    out.push_str(
+        "Running transaction check\n\
+         Transaction check succeeded.\n\
+         Running transaction test\n\
+         Transaction test succeeded.\n\
+         Running transaction\n",
+    );
+
+    for step in ["Installing", "Verifying"] {
+        for (i, package) in packages.iter().enumerate() {
+            writeln!(
+                out,
+                "  {step:<17}: {:<52} {}/{total}",
+                format!("{}-{}.x86_64", package.name, package.version),
+                i + 1,
+            )
+            .unwrap();
+        }
+    }
+
+    out.push_str("\nInstalled:\n");
+
+    for package in packages {
+        writeln!(out, "  {}-{}.x86_64", package.name, package.version).unwrap();
+    }
+
+    out.push_str("\nComplete!\n");
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct Yum {
+    /// Packages waiting on the client to confirm the transaction.
+    pending: Vec<Package>,
+    input: Vec<u8>,
+}
+
+#[async_trait]
+impl Command for Yum {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let assume_yes = params
+            .iter()
+            .any(|v| v == "-y" || v == "--assumeyes" || v == "--yes");
+        let mut args = params
+            .iter()
+            .map(String::as_str)
+            .filter(|v| !v.starts_with('-'));
+
+        let Some(action) = args.next() else {
+            session.data(
+                channel,
+                "usage: yum [options] COMMAND\n\nList of Main Commands:\n\n\
+                 install                   Install a package or packages on your system\n\
+                 update                    Upgrade a package or packages on your system\n"
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        };
+
+        let names: Vec<_> = args.collect();
+        let packages = if action == "install" {
+            resolve(connection, Manager::Yum, &names)
+        } else {
+            Vec::new()
+        };
+
+        if connection.username() != "root" {
+            session.stderr(
+                channel,
+                "Error: This command has to be run with superuser privileges (under the root user \
+                 on most systems).\n"
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        match action {
+            "install" => {
+                let mut out = String::new();
+
+                for package in packages.iter().filter(|v| v.installed) {
+                    writeln!(
+                        out,
+                        "Package {}-{}.x86_64 is already installed.",
+                        package.name, package.version
+                    )
+                    .unwrap();
+                }
+
+                let new: Vec<_> = packages.iter().filter(|v| !v.installed).collect();
+
+                if new.is_empty() {
+                    out.push_str("Dependencies resolved.\nNothing to do.\nComplete!\n");
+                    session.data(channel, out.into());
+                    return CommandResult::Exit(0);
+                }
+
+                out.push_str(&yum_transaction(&new));
+
+                if !assume_yes {
+                    out.push_str("Is this ok [y/N]: ");
+                    session.data(channel, out.into());
+
+                    return CommandResult::ReadStdin(Self {
+                        pending: packages,
+                        input: Vec::new(),
+                    });
+                }
+
+                out.push_str(&yum_install(&new));
+                tokio::time::sleep(install(connection, &new)).await;
+
+                session.data(channel, out.into());
+            }
+            "update" | "upgrade" => {
+                session.data(
+                    channel,
+                    "Dependencies resolved.\nNothing to do.\nComplete!\n".into(),
+                );
+            }
+            "makecache" => {
+                tokio::time::sleep(download_time(connection, 1800)).await;
+                session.data(channel, "Metadata cache created.\n".into());
+            }
+            other => {
+                session.stderr(
+                    channel,
+                    format!("No such command: {other}. Please use /usr/bin/yum --help\n").into(),
+                );
+                return CommandResult::Exit(1);
+            }
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        self.input.extend_from_slice(data);
+
+        let Some(end) = self.input.iter().position(|v| *v == b'\n' || *v == b'\r') else {
+            return CommandResult::ReadStdin(self);
+        };
+
+        let answer = String::from_utf8_lossy(&self.input[..end])
+            .trim()
+            .to_lowercase();
+
+        if answer != "y" && answer != "yes" {
+            session.data(channel, "Operation aborted.\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        let new: Vec<_> = self.pending.iter().filter(|v| !v.installed).collect();
+        let out = yum_install(&new);
+        tokio::time::sleep(install(connection, &new)).await;
+
+        session.data(channel, out.into());
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Apk {}
+
+#[async_trait]
+impl Command for Apk {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let update_cache = params
+            .iter()
+            .any(|v| v == "-U" || v == "--update-cache" || v == "--no-cache");
+        let mut args = params
+            .iter()
+            .map(String::as_str)
+            .filter(|v| !v.starts_with('-'));
+
+        let Some(action) = args.next() else {
+            session.data(
+                channel,
+                "apk-tools 2.14.0, compiled for x86_64.\n\n\
+                 usage: apk [<OPTIONS>...] COMMAND [<ARGUMENTS>...]\n"
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        };
+
+        let names: Vec<_> = args.collect();
+        let packages = if action == "add" {
+            resolve(connection, Manager::Apk, &names)
+        } else {
+            Vec::new()
+        };
+
+        if connection.username() != "root" {
+            session.stderr(
+                channel,
+                "ERROR: Unable to lock database: Permission denied\n\
+                 ERROR: Failed to open apk database: Permission denied\n"
+                    .into(),
+            );
+            return CommandResult::Exit(99);
+        }
+
+        let index = "fetch https://dl-cdn.alpinelinux.org/alpine/v3.18/main/x86_64/APKINDEX.tar.gz\n\
+                     fetch https://dl-cdn.alpinelinux.org/alpine/v3.18/community/x86_64/APKINDEX.tar.gz\n";
+
+        match action {
+            "add" => {
+                let mut out = String::new();
+
+                if update_cache {
+                    out.push_str(index);
+                }
+
+                let new: Vec<_> = packages.iter().filter(|v| !v.installed).collect();
+
+                for (i, package) in new.iter().enumerate() {
+                    writeln!(
+                        out,
+                        "({}/{}) Installing {} ({})",
+                        i + 1,
+                        new.len(),
+                        package.name,
+                        package.version
+                    )
+                    .unwrap();
+                }
+
+                if !new.is_empty() {
+                    out.push_str("Executing busybox-1.36.1-r2.trigger\n");
+                }
+
+                let size = new.iter().map(|v| v.size * 3).sum::<u64>() / 1024;
+                writeln!(out, "OK: {} MiB in {} packages", 9 + size, 15 + new.len()).unwrap();
+
+                tokio::time::sleep(install(connection, &new)).await;
+                session.data(channel, out.into());
+            }
+            "update" => {
+                tokio::time::sleep(download_time(connection, 1800)).await;
+                session.data(
+                    channel,
+                    format!(
+                        "{index}v3.18.4-110-g5a4d8bc4d35 [https://dl-cdn.alpinelinux.org/alpine/v3.18/main]\n\
+                         v3.18.4-111-g8c0d1ad8b3e [https://dl-cdn.alpinelinux.org/alpine/v3.18/community]\n\
+                         OK: 20071 distinct packages available\n"
+                    )
+                    .into(),
+                );
+            }
+            other => {
+                session.stderr(
+                    channel,
+                    format!("apk: unrecognized command '{other}'\n").into(),
+                );
+                return CommandResult::Exit(1);
+            }
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, PackageInstallEvent};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            package::{thousands, Apk, AptGet, Yum},
+            Command, CommandResult, ConcreteCommand,
+        },
+        config::Config,
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    fn state() -> ConnectionState {
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            package_download_speed: u64::MAX,
+            ..Config::default()
+        });
+        state
+    }
+
+    fn capture(session: &mut MockThrusshSession) -> Arc<Mutex<String>> {
+        let out = Arc::new(Mutex::new(String::new()));
+        let captured = out.clone();
+
+        session.expect_data().returning(move |_, data| {
+            captured
+                .lock()
+                .unwrap()
+                .push_str(std::str::from_utf8(&data).unwrap());
+        });
+
+        out
+    }
+
+    fn capture_stderr(session: &mut MockThrusshSession) -> Arc<Mutex<String>> {
+        let err = Arc::new(Mutex::new(String::new()));
+        let captured = err.clone();
+
+        session.expect_stderr().returning(move |_, data| {
+            captured
+                .lock()
+                .unwrap()
+                .push_str(std::str::from_utf8(&data).unwrap());
+        });
+
+        err
+    }
+
+    #[test_case(0, "0")]
+    #[test_case(999, "999")]
+    #[test_case(1000, "1,000")]
+    #[test_case(1_234_567, "1,234,567")]
+    fn formats_thousands(n: u64, expected: &str) {
+        assert_eq!(thousands(n), expected);
+    }
+
+    #[tokio::test]
+    async fn installs_commands() {
+        let mut session = MockThrusshSession::default();
+        let mut state = state();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string("bash: xmrig: command not found\n"))
+            .returning(|_, _| ());
+        let out = capture(&mut session);
+
+        let xmrig = ["xmrig".to_string()];
+        let result = ConcreteCommand::new(
+            &mut state,
+            Some(b"xmrig".as_slice()),
+            &[],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(result, CommandResult::Exit(127)), "{result:?}");
+
+        let result = AptGet::new(
+            &mut state,
+            ["install", "-y", "xmrig", "libuv1-dev", "tar"]
+                .map(ToString::to_string)
+                .as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(result, CommandResult::Exit(0)), "{result:?}");
+
+        let result = ConcreteCommand::new(
+            &mut state,
+            Some(b"xmrig".as_slice()),
+            &xmrig,
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(result, CommandResult::Exit(0)), "{result:?}");
+
+        let out = out.lock().unwrap();
+        assert!(out.contains("tar is already the newest version"), "{out}");
+        assert!(
+            out.contains("The following NEW packages will be installed:\n  xmrig libuv1-dev\n"),
+            "{out}"
+        );
+        assert!(out.contains("Setting up xmrig ("), "{out}");
+        assert!(state.file_system().read("/usr/bin/xmrig".as_ref()).is_ok());
+
+        let AuditLogAction::PackageInstall(PackageInstallEvent { manager, packages }) =
+            &state.audit_log().events[0].action
+        else {
+            panic!("expected package install event");
+        };
+
+        assert_eq!(manager, "apt-get");
+        assert_eq!(&**packages, ["xmrig", "libuv1-dev", "tar"]);
+    }
+
+    #[tokio::test]
+    async fn apt_requires_root() {
+        let mut session = MockThrusshSession::default();
+        let mut state = state();
+        state.set_username("ubuntu");
+        let err = capture_stderr(&mut session);
+
+        let result = AptGet::new(
+            &mut state,
+            ["install", "masscan"].map(ToString::to_string).as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(result, CommandResult::Exit(100)), "{result:?}");
+        assert!(err.lock().unwrap().contains("are you root?"));
+        assert!(!state.is_installed("masscan"));
+        assert_eq!(state.audit_log().events.len(), 1);
+    }
+
+    #[test_case("y\n", 0; "confirmed")]
+    #[test_case("n\n", 1; "aborted")]
+    #[tokio::test]
+    async fn yum_confirms(input: &'static str, expected: u32) {
+        let mut session = MockThrusshSession::default();
+        let mut state = state();
+        let out = capture(&mut session);
+
+        let result = Yum::new(
+            &mut state,
+            ["install", "screen"].map(ToString::to_string).as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin()
+        .stdin(
+            &mut state,
+            fake_channel_id(),
+            input.as_bytes(),
+            &mut session,
+        )
+        .await;
+
+        assert!(
+            matches!(result, CommandResult::Exit(v) if v == expected),
+            "{result:?}"
+        );
+        assert!(out.lock().unwrap().contains("Is this ok [y/N]: "));
+        assert_eq!(state.is_installed("screen"), expected == 0);
+    }
+
+    #[tokio::test]
+    async fn apk_add() {
+        let mut session = MockThrusshSession::default();
+        let mut state = state();
+        let out = capture(&mut session);
+
+        let result = Apk::new(
+            &mut state,
+            ["add", "--no-cache", "curl", "py3-pip"]
+                .map(ToString::to_string)
+                .as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(result, CommandResult::Exit(0)), "{result:?}");
+        assert!(out.lock().unwrap().contains("(2/2) Installing py3-pip ("));
+        assert!(state.is_installed("curl"));
+        assert!(state.is_installed("pip3"));
+    }
+}
@@ -0,0 +1,727 @@
+use std::{borrow::Cow, fmt::Write};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{echo, variables::is_valid_identifier, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "bash: printf: usage: printf [-v var] format [arguments]\n";
+
+/// Flags, width and precision given to a single conversion, ie. `%-08.3d`.
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Pads `body` out to the width, with `sign` and `prefix` ahead of any zeroes added.
+    fn pad(&self, out: &mut Vec<u8>, sign: &str, prefix: &str, body: &[u8], zeroes: bool) {
+        let len = sign.len() + prefix.len() + body.len();
+        let padding = self.width.saturating_sub(len);
+
+        if self.left {
+            out.extend_from_slice(sign.as_bytes());
+            out.extend_from_slice(prefix.as_bytes());
+            out.extend_from_slice(body);
+            out.resize(out.len() + padding, b' ');
+        } else if zeroes && self.zero {
+            out.extend_from_slice(sign.as_bytes());
+            out.extend_from_slice(prefix.as_bytes());
+            out.resize(out.len() + padding, b'0');
+            out.extend_from_slice(body);
+        } else {
+            out.resize(out.len() + padding, b' ');
+            out.extend_from_slice(sign.as_bytes());
+            out.extend_from_slice(prefix.as_bytes());
+            out.extend_from_slice(body);
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+}
+
+/// The arguments still to be formatted, which are taken as empty or zero once they run out.
+struct Arguments<'a> {
+    args: std::slice::Iter<'a, String>,
+    errors: Vec<String>,
+}
+
+impl<'a> Arguments<'a> {
+    fn string(&mut self) -> &'a str {
+        self.args.next().map_or("", String::as_str)
+    }
+
+    fn integer(&mut self) -> i64 {
+        let arg = self.string();
+        let (value, valid) = parse_integer(arg);
+
+        if !valid {
+            self.errors.push(format!("{arg}: invalid number"));
+        }
+
+        value
+    }
+
+    fn float(&mut self) -> f64 {
+        let arg = self.string();
+        let (value, valid) = parse_float(arg);
+
+        if !valid {
+            self.errors.push(format!("{arg}: invalid number"));
+        }
+
+        value
+    }
+}
+
+/// The value of a leading `'` or `"` quoted character, which numeric conversions take the
+/// character code of.
+fn character_code(arg: &str) -> Option<u32> {
+    let rest = arg.strip_prefix(['\'', '"'])?;
+    Some(rest.chars().next().map_or(0, u32::from))
+}
+
+/// Parses a numeric argument the same way as bash, accepting hex with a leading `0x` and octal
+/// with a leading `0`. Returns the value parsed before anything invalid, and whether there was
+/// anything invalid.
+fn parse_integer(arg: &str) -> (i64, bool) {
+    if let Some(code) = character_code(arg) {
+        return (i64::from(code), true);
+    }
+
+    let trimmed = arg.trim_start();
+    if trimmed.is_empty() {
+        return (0, arg.is_empty());
+    }
+
+    let (negative, rest) = match trimmed.as_bytes()[0] {
+        b'-' => (true, &trimmed[1..]),
+        b'+' => (false, &trimmed[1..]),
+        _ => (false, trimmed),
+    };
+
+    let (radix, digits) = if let Some(rest) = rest.strip_prefix("0x").or(rest.strip_prefix("0X")) {
+        (16, rest)
+    } else if rest.len() > 1 && rest.starts_with('0') {
+        (8, &rest[1..])
+    } else {
+        (10, rest)
+    };
+
+    let len = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let value = digits[..len].chars().fold(0_i64, |acc, c| {
+        acc.saturating_mul(i64::from(radix))
+            .saturating_add(i64::from(c.to_digit(radix).unwrap_or_default()))
+    });
+
+    let valid = (len > 0 || radix == 8) && len == digits.len();
+    (if negative { -value } else { value }, valid)
+}
+
+/// Parses a numeric argument for the floating point conversions, returning the value parsed
+/// before anything invalid, and whether there was anything invalid.
+fn parse_float(arg: &str) -> (f64, bool) {
+    if let Some(code) = character_code(arg) {
+        return (f64::from(code), true);
+    }
+
+    let trimmed = arg.trim_start();
+    if trimmed.is_empty() {
+        return (0.0, arg.is_empty());
+    }
+
+    if let Ok(value) = trimmed.parse() {
+        return (value, true);
+    }
+
+    let value = (1..trimmed.len())
+        .rev()
+        .filter(|i| trimmed.is_char_boundary(*i))
+        .find_map(|i| trimmed[..i].parse().ok())
+        .unwrap_or_default();
+    (value, false)
+}
+
+/// Takes up to `max` digits in `radix` from the start of `rest`, returning their value added on
+/// to `value` and how many there were.
+fn take_digits(rest: &mut &[u8], max: usize, radix: u32, value: u32) -> (u32, usize) {
+    let len = rest
+        .iter()
+        .take(max)
+        .take_while(|v| char::from(**v).is_digit(radix))
+        .count();
+    let value = rest[..len].iter().fold(value, |acc, v| {
+        acc.wrapping_mul(radix)
+            .wrapping_add(char::from(*v).to_digit(radix).unwrap_or_default())
+    });
+
+    *rest = &rest[len..];
+    (value, len)
+}
+
+/// Interprets a backslash escape in the format string, with `rest` starting just after the
+/// backslash. Unlike `echo -e`, octal escapes don't need a leading zero and `\c` is printed
+/// as is.
+#[allow(clippy::cast_possible_truncation)]
+fn escape(rest: &mut &[u8], out: &mut Vec<u8>) {
+    let Some((&c, tail)) = rest.split_first() else {
+        out.push(b'\\');
+        return;
+    };
+    *rest = tail;
+
+    match c {
+        b'a' => out.push(0x07),
+        b'b' => out.push(0x08),
+        b'e' | b'E' => out.push(0x1b),
+        b'f' => out.push(0x0c),
+        b'n' => out.push(b'\n'),
+        b'r' => out.push(b'\r'),
+        b't' => out.push(b'\t'),
+        b'v' => out.push(0x0b),
+        b'\\' | b'"' | b'\'' | b'?' => out.push(c),
+        b'0'..=b'7' => out.push(take_digits(rest, 2, 8, u32::from(c - b'0')).0 as u8),
+        b'x' => match take_digits(rest, 2, 16, 0) {
+            (_, 0) => out.extend_from_slice(b"\\x"),
+            (value, _) => out.push(value as u8),
+        },
+        b'u' | b'U' => match take_digits(rest, if c == b'u' { 4 } else { 8 }, 16, 0) {
+            (_, 0) => out.extend_from_slice(&[b'\\', c]),
+            (value, _) => {
+                let c = char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER);
+                out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        },
+        other => out.extend_from_slice(&[b'\\', other]),
+    }
+}
+
+/// Quotes `arg` so it can be reused as shell input, as `%q` does.
+fn quote(arg: &str) -> String {
+    if arg.is_empty() {
+        return "''".to_string();
+    }
+
+    if arg.chars().any(char::is_control) {
+        let mut out = String::from("$'");
+        for c in arg.chars() {
+            match c {
+                '\x07' => out.push_str("\\a"),
+                '\x08' => out.push_str("\\b"),
+                '\x1b' => out.push_str("\\E"),
+                '\x0c' => out.push_str("\\f"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '\x0b' => out.push_str("\\v"),
+                '\\' | '\'' => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                c if c.is_control() => {
+                    let _ = write!(out, "\\{:03o}", u32::from(c));
+                }
+                c => out.push(c),
+            }
+        }
+        out.push('\'');
+        return out;
+    }
+
+    let mut out = String::with_capacity(arg.len());
+    for (i, c) in arg.chars().enumerate() {
+        if " !\"$&'()*,;<>?[\\]^`{|}".contains(c) || (i == 0 && "#~".contains(c)) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Formats `value` as C's `%e` would, with `precision` digits after the decimal point and at
+/// least two digits in the exponent.
+fn scientific(value: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{value:.precision$e}");
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or_default();
+    let e = if upper { 'E' } else { 'e' };
+    let sign = if exponent < 0 { '-' } else { '+' };
+
+    format!("{mantissa}{e}{sign}{:02}", exponent.abs())
+}
+
+/// Formats `value` as C's `%g` would, using `%e` for very large or small exponents and `%f`
+/// otherwise, and dropping trailing zeroes unless `alternate` is set.
+fn shortest(value: f64, precision: usize, upper: bool, alternate: bool) -> String {
+    let precision = precision.max(1);
+    let exponent: i32 = format!("{:.*e}", precision - 1, value)
+        .split_once('e')
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or_default();
+
+    let mut out = match usize::try_from(exponent) {
+        Ok(exponent) if exponent >= precision => scientific(value, precision - 1, upper),
+        Ok(exponent) => format!("{value:.*}", precision - 1 - exponent),
+        Err(_) if exponent < -4 => scientific(value, precision - 1, upper),
+        Err(_) => format!(
+            "{value:.*}",
+            precision - 1 + exponent.unsigned_abs() as usize
+        ),
+    };
+
+    if !alternate && out.contains('.') {
+        let end = out.find(['e', 'E']).unwrap_or(out.len());
+        let trimmed = out[..end].trim_end_matches('0').trim_end_matches('.').len();
+        out.replace_range(trimmed..end, "");
+    }
+
+    out
+}
+
+/// Reads the flags, width and precision of a conversion from the start of `rest`, taking the
+/// width and precision from the arguments if given as `*`.
+fn spec(rest: &mut &[u8], args: &mut Arguments<'_>) -> Spec {
+    let mut spec = Spec::default();
+
+    while let Some((&c, tail)) = rest.split_first() {
+        match c {
+            b'-' => spec.left = true,
+            b'+' => spec.plus = true,
+            b' ' => spec.space = true,
+            b'#' => spec.alternate = true,
+            b'0' => spec.zero = true,
+            _ => break,
+        }
+        *rest = tail;
+    }
+
+    if let Some(tail) = rest.strip_prefix(b"*") {
+        *rest = tail;
+        let width = args.integer();
+        spec.left |= width < 0;
+        spec.width = usize::try_from(width.unsigned_abs()).unwrap_or(usize::MAX);
+    } else {
+        spec.width = take_digits(rest, usize::MAX, 10, 0).0 as usize;
+    }
+
+    if let Some(tail) = rest.strip_prefix(b".") {
+        *rest = tail;
+
+        spec.precision = if let Some(tail) = rest.strip_prefix(b"*") {
+            *rest = tail;
+            // a negative precision is taken as if none was given
+            usize::try_from(args.integer()).ok()
+        } else {
+            Some(take_digits(rest, usize::MAX, 10, 0).0 as usize)
+        };
+    }
+
+    // length modifiers are accepted but make no difference
+    while let Some((_, tail)) = rest.split_first().filter(|(c, _)| b"hlLjzt".contains(c)) {
+        *rest = tail;
+    }
+
+    spec
+}
+
+/// Formats a single conversion, with `rest` starting just after the `%`. Returns false if the
+/// rest of the output should be dropped, either because the conversion isn't valid or because
+/// a `%b` argument contained `\c`.
+fn conversion(rest: &mut &[u8], args: &mut Arguments<'_>, out: &mut Vec<u8>) -> bool {
+    if let Some(tail) = rest.strip_prefix(b"%") {
+        *rest = tail;
+        out.push(b'%');
+        return true;
+    }
+
+    let spec = spec(rest, args);
+
+    let Some((&c, tail)) = rest.split_first() else {
+        args.errors
+            .push("`%': missing format character".to_string());
+        return false;
+    };
+    *rest = tail;
+
+    match c {
+        b's' => {
+            let arg = args.string().as_bytes();
+            let len = spec.precision.map_or(arg.len(), |v| v.min(arg.len()));
+            spec.pad(out, "", "", &arg[..len], false);
+        }
+        b'b' => {
+            let (arg, stop) = echo::unescape(args.string());
+            let len = spec.precision.map_or(arg.len(), |v| v.min(arg.len()));
+            spec.pad(out, "", "", &arg[..len], false);
+
+            if stop {
+                return false;
+            }
+        }
+        b'q' => spec.pad(out, "", "", quote(args.string()).as_bytes(), false),
+        b'c' => {
+            let arg = args.string();
+            let len = arg.chars().next().map_or(0, char::len_utf8);
+            spec.pad(out, "", "", &arg.as_bytes()[..len], false);
+        }
+        b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+            let value = args.integer();
+            #[allow(clippy::cast_sign_loss)]
+            let (negative, digits) = match c {
+                b'd' | b'i' => (value < 0, value.unsigned_abs().to_string()),
+                b'o' => (false, format!("{:o}", value as u64)),
+                b'u' => (false, (value as u64).to_string()),
+                b'x' => (false, format!("{:x}", value as u64)),
+                _ => (false, format!("{:X}", value as u64)),
+            };
+
+            let digits = match spec.precision {
+                Some(0) if value == 0 => String::new(),
+                Some(precision) => format!("{digits:0>precision$}"),
+                None => digits,
+            };
+
+            let prefix = match c {
+                b'o' if spec.alternate && !digits.starts_with('0') => "0",
+                b'x' if spec.alternate && value != 0 => "0x",
+                b'X' if spec.alternate && value != 0 => "0X",
+                _ => "",
+            };
+            let sign = if matches!(c, b'd' | b'i') {
+                spec.sign(negative)
+            } else {
+                ""
+            };
+
+            // zero padding is ignored once there's a precision
+            let zeroes = spec.precision.is_none();
+            spec.pad(out, sign, prefix, digits.as_bytes(), zeroes);
+        }
+        b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+            let value = args.float();
+            let upper = c.is_ascii_uppercase();
+            let precision = spec.precision.unwrap_or(6);
+            let magnitude = value.abs();
+
+            let body = if magnitude.is_nan() || magnitude.is_infinite() {
+                let body = if magnitude.is_nan() { "nan" } else { "inf" };
+                if upper {
+                    body.to_uppercase()
+                } else {
+                    body.to_string()
+                }
+            } else {
+                match c {
+                    b'e' | b'E' => scientific(magnitude, precision, upper),
+                    b'f' | b'F' => format!("{magnitude:.precision$}"),
+                    _ => shortest(magnitude, precision, upper, spec.alternate),
+                }
+            };
+
+            let zeroes = value.is_finite();
+            spec.pad(
+                out,
+                spec.sign(value.is_sign_negative() && !value.is_nan()),
+                "",
+                body.as_bytes(),
+                zeroes,
+            );
+        }
+        other => {
+            args.errors
+                .push(format!("`{}': invalid format character", char::from(other)));
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Writes `args` out according to `format`, reusing the format until every argument has been
+/// used. Returns the output, and any problems with the arguments, which bash reports without
+/// stopping.
+fn printf(format: &str, args: &[String]) -> (Vec<u8>, Vec<String>) {
+    let mut out = Vec::new();
+    let mut args = Arguments {
+        args: args.iter(),
+        errors: Vec::new(),
+    };
+
+    'outer: loop {
+        let remaining = args.args.len();
+        let mut rest = format.as_bytes();
+
+        while let Some((&c, tail)) = rest.split_first() {
+            rest = tail;
+
+            match c {
+                b'\\' => escape(&mut rest, &mut out),
+                b'%' => {
+                    if !conversion(&mut rest, &mut args, &mut out) {
+                        break 'outer;
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+
+        // the format is only reused if it actually uses any of the arguments
+        if args.args.len() == 0 || args.args.len() == remaining {
+            break;
+        }
+    }
+
+    (out, args.errors)
+}
+
+#[derive(Debug, Clone)]
+pub struct Printf {}
+
+#[async_trait]
+impl Command for Printf {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut params = params;
+        let mut variable = None;
+
+        while let Some((first, rest)) = params.split_first() {
+            match first.as_str() {
+                "--" => {
+                    params = rest;
+                    break;
+                }
+                "-v" => {
+                    let Some((name, rest)) = rest.split_first() else {
+                        session.stderr(
+                            channel,
+                            format!("bash: printf: -v: option requires an argument\n{USAGE}")
+                                .into(),
+                        );
+                        return CommandResult::Exit(2);
+                    };
+
+                    variable = Some(name.as_str());
+                    params = rest;
+                }
+                v if v.len() > 1 && v.starts_with('-') => {
+                    session.stderr(
+                        channel,
+                        format!("bash: printf: {v}: invalid option\n{USAGE}").into(),
+                    );
+                    return CommandResult::Exit(2);
+                }
+                _ => break,
+            }
+        }
+
+        let Some((format, args)) = params.split_first() else {
+            session.stderr(channel, USAGE.into());
+            return CommandResult::Exit(2);
+        };
+
+        if let Some(name) = variable.filter(|v| !is_valid_identifier(v)) {
+            session.stderr(
+                channel,
+                format!("bash: printf: `{name}': not a valid identifier\n").into(),
+            );
+            return CommandResult::Exit(2);
+        }
+
+        let (out, errors) = printf(format, args);
+
+        match variable {
+            Some(name) => {
+                connection.set_variable(Cow::Owned(name.as_bytes().to_vec()), Cow::Owned(out));
+            }
+            None if !out.is_empty() => session.data(channel, out.into()),
+            None => {}
+        }
+
+        for error in &errors {
+            session.stderr(channel, format!("bash: printf: {error}\n").into());
+        }
+
+        CommandResult::Exit(u32::from(!errors.is_empty()))
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        command::{printf::Printf, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    fn params(params: &[&str]) -> Vec<String> {
+        params.iter().map(ToString::to_string).collect()
+    }
+
+    #[test_case(&["hello"], "hello"; "plain format")]
+    #[test_case(&["%s\\n", "a", "b", "c"], "a\nb\nc\n"; "format reused")]
+    #[test_case(&["%s=%s\\n", "a", "1", "b"], "a=1\nb=\n"; "missing arguments")]
+    #[test_case(&["%s"], ""; "no arguments")]
+    #[test_case(&["no conversions\\n", "a", "b"], "no conversions\n"; "unused arguments")]
+    #[test_case(&["%5s|%-5s|%.2s", "ab", "cd", "hello"], "   ab|cd   |he"; "string width")]
+    #[test_case(&["%d %i %u", "42", "-7", "3"], "42 -7 3"; "integers")]
+    #[test_case(&["%05d|%-5d|%+d|% d", "42", "42", "42", "42"], "00042|42   |+42| 42"; "integer flags")]
+    #[test_case(&["%x %X %o %#x %#o", "255", "255", "8", "255", "8"], "ff FF 10 0xff 010"; "bases")]
+    #[test_case(&["%d %d %d %d", "0x1f", "010", "'A", " 5"], "31 8 65 5"; "integer arguments")]
+    #[test_case(&["%.3d|%5.3d|%.0d|", "7", "-7", "0"], "007| -007||"; "integer precision")]
+    #[test_case(&["%*d|%-*d|%.*s", "4", "1", "3", "2", "2", "hello"], "   1|2  |he"; "star width")]
+    #[test_case(&["%f %.2f %5.1f", "3.14159", "2.5", "-2.26"], "3.141590 2.50  -2.3"; "fixed")]
+    #[test_case(&["%e %E %.0e", "1234.5", "0.00012", "5"], "1.234500e+03 1.200000E-04 5e+00"; "scientific")]
+    #[test_case(&["%g %g %g %G", "0.0001", "1234567", "100", "0.00001"], "0.0001 1.23457e+06 100 1E-05"; "shortest")]
+    #[test_case(&["%c%c", "hello", "é"], "hé"; "characters")]
+    #[test_case(&["%b|%b", "a\\tb\\0101", "c\\cdropped", "never"], "a\tbA|c"; "escaped arguments")]
+    #[test_case(&["a\\tb\\101\\x42\\u00e9\\c\\n"], "a\tbAB\u{e9}\\c\n"; "format escapes")]
+    #[test_case(&["%q %q %q", "it's here", "", "a\nb"], "it\\'s\\ here '' $'a\\nb'"; "quoted")]
+    #[test_case(&["100%%\\n"], "100%\n"; "literal percent")]
+    #[test_case(&["--", "-x"], "-x"; "end of options")]
+    #[tokio::test]
+    async fn test(args: &[&str], output: &'static str) {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_data()
+            .times(usize::from(!output.is_empty()))
+            .with(always(), eq_string(output))
+            .returning(|_, _| ());
+
+        let out = Printf::new(
+            &mut ConnectionState::mock(),
+            &params(args),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+
+    #[test_case(&["%d|%d\\n", "12abc", "xyz"], "12|0\n", &["12abc: invalid number", "xyz: invalid number"], 1; "invalid numbers")]
+    #[test_case(&["a%kb"], "a", &["`k': invalid format character"], 1; "invalid conversion")]
+    #[test_case(&["a%"], "a", &["`%': missing format character"], 1; "missing conversion")]
+    #[tokio::test]
+    async fn errors(args: &[&str], output: &'static str, errors: &[&'static str], code: u32) {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(output))
+            .returning(|_, _| ());
+
+        for error in errors {
+            session
+                .expect_stderr()
+                .once()
+                .with(
+                    always(),
+                    eq_string(format!("bash: printf: {error}\n").leak()),
+                )
+                .returning(|_, _| ());
+        }
+
+        let out = Printf::new(
+            &mut ConnectionState::mock(),
+            &params(args),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(
+            matches!(out, CommandResult::Exit(v) if v == code),
+            "{out:?}"
+        );
+    }
+
+    #[test_case(&[], "bash: printf: usage: printf [-v var] format [arguments]\n"; "no format")]
+    #[test_case(&["-x", "%s"], "bash: printf: -x: invalid option\nbash: printf: usage: printf [-v var] format [arguments]\n"; "invalid option")]
+    #[test_case(&["-v", "1x", "%s"], "bash: printf: `1x': not a valid identifier\n"; "invalid variable")]
+    #[tokio::test]
+    async fn usage(args: &[&str], error: &'static str) {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string(error))
+            .returning(|_, _| ());
+
+        let out = Printf::new(
+            &mut ConnectionState::mock(),
+            &params(args),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(2)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn assigns_variable() {
+        let mut state = ConnectionState::mock();
+        let mut session = MockThrusshSession::default();
+
+        let out = Printf::new(
+            &mut state,
+            &params(&["-v", "line", "%s=%d\\n", "port", "22"]),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(
+            state
+                .environment()
+                .get(b"line".as_slice())
+                .map(AsRef::as_ref),
+            Some(b"port=22\n".as_slice())
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    anti_forensics,
+    audit::AntiForensicsTechnique,
+    command::{argparse, Arg, Command, CommandResult},
+    file_system::{LsError, Tree},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Records an anti-forensics event for each kind of file in `removed` that would hide what the
+/// client has been up to.
+fn record_removals(connection: &mut ConnectionState, removed: &[&str]) {
+    let mut techniques: Vec<(AntiForensicsTechnique, Vec<String>)> = Vec::new();
+
+    for path in removed {
+        let canonical = connection.file_system().canonicalise(Path::new(path));
+        let Some(technique) = anti_forensics::file_technique(&canonical) else {
+            continue;
+        };
+
+        match techniques.iter_mut().find(|(v, _)| *v == technique) {
+            Some((_, targets)) => targets.push((*path).to_string()),
+            None => techniques.push((technique, vec![(*path).to_string()])),
+        }
+    }
+
+    for (technique, targets) in techniques {
+        connection.record_anti_forensics(technique, targets.into_boxed_slice());
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rm {}
+
+#[async_trait]
+impl Command for Rm {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut recursive = false;
+        let mut force = false;
+        let mut verbose = false;
+        let mut preserve_root = true;
+        let mut operands = Vec::new();
+
+        for param in argparse(params) {
+            match param {
+                Arg::Short('r' | 'R') | Arg::Long("recursive") => recursive = true,
+                Arg::Short('f') | Arg::Long("force") => force = true,
+                Arg::Short('v') | Arg::Long("verbose") => verbose = true,
+                Arg::Short('i' | 'I' | 'd') | Arg::Long("dir" | "one-file-system") => {}
+                Arg::Long("no-preserve-root") => preserve_root = false,
+                Arg::Long("preserve-root") => preserve_root = true,
+                Arg::Operand(v) => operands.push(v),
+                Arg::Short(c) => {
+                    session.stderr(
+                        channel,
+                        format!(
+                            "rm: invalid option -- '{c}'\nTry 'rm --help' for more information.\n"
+                        )
+                        .into(),
+                    );
+                    return CommandResult::Exit(1);
+                }
+                Arg::Long(v) => {
+                    session.stderr(
+                        channel,
+                        format!(
+                            "rm: unrecognized option '--{v}'\nTry 'rm --help' for more information.\n"
+                        )
+                        .into(),
+                    );
+                    return CommandResult::Exit(1);
+                }
+            }
+        }
+
+        if operands.is_empty() && !force {
+            session.stderr(
+                channel,
+                "rm: missing operand\nTry 'rm --help' for more information.\n".into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        let mut out = String::new();
+        let mut err = String::new();
+        let mut exit_code = 0;
+        let mut removed = Vec::new();
+
+        for operand in operands {
+            let fs = connection.file_system();
+            let path = fs.canonicalise(Path::new(operand));
+
+            if recursive && preserve_root && path == Path::new("/") {
+                err.push_str(
+                    "rm: it is dangerous to operate recursively on '/'\nrm: use --no-preserve-root to override this failsafe\n",
+                );
+                exit_code = 1;
+                continue;
+            }
+
+            let res = match fs.get(&path) {
+                Ok(Tree::Directory(_)) if !recursive => Err(LsError::IsADirectory),
+                Ok(Tree::Directory(_)) => fs.remove_all(&path),
+                Ok(_) => fs.remove(&path),
+                Err(e) => Err(e),
+            };
+
+            match res {
+                Ok(()) => {
+                    if verbose {
+                        writeln!(out, "removed '{operand}'").unwrap();
+                    }
+
+                    removed.push(operand);
+                }
+                Err(LsError::NoSuchFileOrDirectory) if force => {}
+                Err(e) => {
+                    writeln!(err, "rm: cannot remove '{operand}': {e}").unwrap();
+                    exit_code = 1;
+                }
+            }
+        }
+
+        record_removals(connection, &removed);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Shred {}
+
+#[async_trait]
+impl Command for Shred {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut remove = false;
+        let mut operands = Vec::new();
+        let mut params = params.iter();
+
+        while let Some(param) = params.next() {
+            if let Some(long) = param.strip_prefix("--") {
+                match long {
+                    "remove" => remove = true,
+                    // the number of passes and size to overwrite take a value
+                    "iterations" | "size" | "random-source" => {
+                        params.next();
+                    }
+                    _ => {}
+                }
+            } else if let Some(short) = param.strip_prefix('-').filter(|v| !v.is_empty()) {
+                remove |= short.contains('u');
+
+                if short.ends_with(['n', 's']) {
+                    params.next();
+                }
+            } else {
+                operands.push(param.as_str());
+            }
+        }
+
+        if operands.is_empty() {
+            session.stderr(
+                channel,
+                "shred: missing file operand\nTry 'shred --help' for more information.\n".into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        let mut err = String::new();
+        let mut exit_code = 0;
+        let mut shredded = Vec::new();
+
+        for operand in operands {
+            let len = match connection.file_system().read(Path::new(operand)) {
+                Ok(content) => content.len(),
+                Err(e) => {
+                    writeln!(err, "shred: {operand}: failed to open for writing: {e}").unwrap();
+                    exit_code = 1;
+                    continue;
+                }
+            };
+
+            let content = std::iter::repeat_with(|| connection.rng().u8(..))
+                .take(len)
+                .collect();
+
+            let fs = connection.file_system();
+            let res = if remove {
+                fs.remove(Path::new(operand))
+            } else {
+                fs.write(Path::new(operand), content)
+            };
+
+            match res {
+                Ok(()) => shredded.push(operand),
+                Err(e) => {
+                    writeln!(err, "shred: {operand}: {e}").unwrap();
+                    exit_code = 1;
+                }
+            }
+        }
+
+        if !shredded.is_empty() {
+            connection.record_anti_forensics(
+                AntiForensicsTechnique::Shred,
+                shredded.iter().map(ToString::to_string).collect(),
+            );
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        audit::{AntiForensicsEvent, AntiForensicsTechnique, AuditLogAction},
+        command::{
+            rm::{Rm, Shred},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    fn state() -> ConnectionState {
+        let mut state = ConnectionState::mock();
+        let fs = state.file_system();
+        fs.mkdirall(Path::new("/var/log/nginx")).unwrap();
+        fs.write(Path::new("/var/log/nginx/access.log"), Box::default())
+            .unwrap();
+        fs.write(Path::new("/var/log/wtmp"), Box::default())
+            .unwrap();
+        fs.write(Path::new(".bash_history"), Box::from(*b"ls\n"))
+            .unwrap();
+        fs.write(Path::new("run.sh"), Box::default()).unwrap();
+        state
+    }
+
+    fn anti_forensics(state: &mut ConnectionState) -> Vec<(AntiForensicsTechnique, Vec<String>)> {
+        state
+            .audit_log()
+            .events
+            .iter()
+            .filter_map(|v| match &v.action {
+                AuditLogAction::AntiForensics(AntiForensicsEvent { technique, targets }) => {
+                    Some((*technique, targets.to_vec()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn removes_files() {
+        let mut session = MockThrusshSession::default();
+        let mut state = state();
+
+        let params = [
+            "-rf",
+            "run.sh",
+            "/var/log/nginx",
+            "missing",
+            ".bash_history",
+        ]
+        .map(ToString::to_string);
+        let out = Rm::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let fs = state.file_system();
+        assert!(fs.get(Path::new("run.sh")).is_err());
+        assert!(fs.get(Path::new("/var/log/nginx")).is_err());
+        assert!(fs.get(Path::new("/var/log/wtmp")).is_ok());
+
+        assert_eq!(
+            anti_forensics(&mut state),
+            [
+                (
+                    AntiForensicsTechnique::TamperLogs,
+                    vec!["/var/log/nginx".to_string()]
+                ),
+                (
+                    AntiForensicsTechnique::DeleteHistory,
+                    vec![".bash_history".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test_case(&["/var/log"], "rm: cannot remove '/var/log': Is a directory\n"; "directory")]
+    #[test_case(&["missing"], "rm: cannot remove 'missing': No such file or directory\n"; "missing")]
+    #[test_case(&[], "rm: missing operand\nTry 'rm --help' for more information.\n"; "no operands")]
+    #[test_case(&["-rf", "/"], "rm: it is dangerous to operate recursively on '/'\nrm: use --no-preserve-root to override this failsafe\n"; "root")]
+    #[tokio::test]
+    async fn fails(params: &[&str], expected: &'static str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = state();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let params: Vec<_> = params.iter().map(ToString::to_string).collect();
+        let out = Rm::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+        assert!(anti_forensics(&mut state).is_empty());
+    }
+
+    #[tokio::test]
+    async fn shreds_files() {
+        let mut session = MockThrusshSession::default();
+        let mut state = state();
+
+        let params =
+            ["-n", "3", "-z", ".bash_history", "-u", "/var/log/wtmp"].map(ToString::to_string);
+        let out = Shred::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(state.file_system().get(Path::new(".bash_history")).is_err());
+        assert_eq!(
+            anti_forensics(&mut state),
+            [(
+                AntiForensicsTechnique::Shred,
+                vec![".bash_history".to_string(), "/var/log/wtmp".to_string()]
+            )]
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use async_trait::async_trait;
 use bytes::{Buf, BytesMut};
@@ -8,12 +12,18 @@ use nom::{
     combinator::{map, map_res},
     IResult,
 };
-use pisshoff_types::audit::{AuditLogAction, WriteFileEvent};
+use pisshoff_types::audit::{AuditLogAction, MkdirEvent, PartialUploadEvent};
 use thrussh::ChannelId;
+use time::OffsetDateTime;
 use tracing::warn;
 
 use crate::{
-    command::{Arg, Command, CommandResult},
+    command::{
+        ssh::{self, Remote, SSH_TAKES_VALUE},
+        Arg, Command, CommandResult,
+    },
+    file_system::LsError,
+    memory::Reservation,
     server::{ConnectionState, ThrusshSession},
 };
 
@@ -28,32 +38,49 @@ const SUCCESS: &str = "\0";
 // https://web.archive.org/web/20170215184048/https://blogs.oracle.com/janp/entry/how_the_scp_protocol_works
 #[derive(Debug, Clone)]
 pub struct Scp {
-    path: PathBuf,
+    destination: Destination,
     pending_data: BytesMut,
+    /// Accounts for `pending_data` in the connection's memory budget.
+    reservation: Reservation,
     state: State,
 }
 
 #[async_trait]
 impl Command for Scp {
     async fn new<S: ThrusshSession + Send>(
-        _connection: &mut ConnectionState,
+        connection: &mut ConnectionState,
         params: &[String],
         channel: ChannelId,
         session: &mut S,
     ) -> CommandResult<Self> {
-        let mut path = None;
+        let mut operands = Vec::new();
+        let mut port = None;
         let mut transfer = false;
+        let mut target_should_be_directory = false;
+        let mut args = super::argparse(params);
 
-        for param in super::argparse(params) {
+        while let Some(param) = args.next() {
             match param {
                 Arg::Short('t') => {
                     transfer = true;
                 }
-                Arg::Short('r' | 'v') => {
+                Arg::Short('d') => {
+                    target_should_be_directory = true;
+                }
+                Arg::Short(
+                    'r' | 'v' | 'p' | '3' | '4' | '6' | 'A' | 'B' | 'C' | 'O' | 'q' | 'T',
+                ) => {
                     // this is an allowed param, do nothing
                 }
+                Arg::Short(c) if matches!(c, 'P' | 'X') || SSH_TAKES_VALUE.contains(&c) => {
+                    let value = args.next();
+
+                    if let (Some(Arg::Operand(v)), 'P') = (value, c) {
+                        port = v.parse().ok();
+                    }
+                }
                 Arg::Operand(p) => {
-                    path = Some(p);
+                    operands.push(p);
                 }
                 _ => {
                     session.data(channel, HELP.to_string().into());
@@ -62,26 +89,39 @@ impl Command for Scp {
             }
         }
 
-        let Some(path) = path else {
+        if !transfer {
+            return connect_out(connection, &operands, port, channel, session);
+        }
+
+        let Some(path) = operands.last() else {
             session.data(channel, AMBIGUOUS_TARGET.to_string().into());
             return CommandResult::Exit(1);
         };
 
-        if !transfer {
-            session.data(channel, HELP.to_string().into());
-            return CommandResult::Exit(1);
-        }
+        let target = PathBuf::from(path);
+        let target_is_directory = target_should_be_directory
+            || matches!(
+                connection.file_system().metadata(&target),
+                Err(LsError::IsADirectory)
+            );
 
         // signal to the client we've started listening
         session.data(channel, SUCCESS.to_string().into());
 
         CommandResult::ReadStdin(Self {
-            path: PathBuf::new().join(path),
+            destination: Destination {
+                target,
+                target_is_directory,
+                directories: Vec::new(),
+                modified: None,
+            },
             pending_data: BytesMut::new(),
+            reservation: Reservation::default(),
             state: State::Waiting,
         })
     }
 
+    #[allow(clippy::too_many_lines)]
     async fn stdin<S: ThrusshSession + Send>(
         mut self,
         connection: &mut ConnectionState,
@@ -89,6 +129,13 @@ impl Command for Scp {
         data: &[u8],
         session: &mut S,
     ) -> CommandResult<Self> {
+        let pending = self.pending_data.len().saturating_add(data.len());
+        if !self.reservation.try_grow(connection.memory(), pending) {
+            warn!("scp exceeded the connection's memory budget");
+            self.abort(connection);
+            return CommandResult::Exit(1);
+        }
+
         self.pending_data.extend_from_slice(data);
 
         let mut exit = false;
@@ -98,20 +145,77 @@ impl Command for Scp {
                     match Receive::parse(&self.pending_data) {
                         Ok((rest, res)) => {
                             let mut state = State::Waiting;
+                            let mut reply = Cow::Borrowed(SUCCESS);
 
                             match res {
                                 Receive::FileCopy {
-                                    length, file_name, ..
+                                    mode,
+                                    length,
+                                    file_name,
                                 } => {
-                                    state = State::ReceivingFile(length, self.path.join(file_name));
+                                    let Some(path) = self.destination.resolve(file_name) else {
+                                        return unexpected_filename(file_name, channel, session);
+                                    };
+
+                                    // the fake filesystem doesn't keep modification times
+                                    self.destination.modified = None;
+
+                                    if connection.reserve_upload(length as u64, length as u64) {
+                                        state =
+                                            State::ReceivingFile(length, path, parse_mode(mode));
+                                    } else {
+                                        // refuse the file the same way scp does when it can't be
+                                        // opened, the client will move onto the next one
+                                        reply = Cow::Owned(format!(
+                                            "\x01scp: {}: No space left on device\n",
+                                            path.display()
+                                        ));
+                                        connection.push_action(AuditLogAction::PartialUpload(
+                                            PartialUploadEvent {
+                                                protocol: Cow::Borrowed("scp"),
+                                                path: path.to_string_lossy().into(),
+                                                bytes_received: 0,
+                                                expected_bytes: Some(length as u64),
+                                                content: None,
+                                                sha256: None,
+                                            },
+                                        ));
+                                    }
                                 }
-                                Receive::DirectoryCopy { directory_name, .. } => {
-                                    self.path.push(directory_name);
+                                Receive::DirectoryCopy {
+                                    mode,
+                                    directory_name,
+                                    ..
+                                } => {
+                                    let Some(path) = self.destination.resolve(directory_name)
+                                    else {
+                                        return unexpected_filename(
+                                            directory_name,
+                                            channel,
+                                            session,
+                                        );
+                                    };
+
+                                    let fs = connection.file_system();
+                                    let absolute = fs.pwd().join(&path);
+                                    let _res = fs.mkdirall(&absolute);
+
+                                    connection.push_action(AuditLogAction::Mkdir(MkdirEvent {
+                                        path: path.to_string_lossy().into(),
+                                        mode: Some(Box::from(mode)),
+                                        modified: self.destination.modified.take(),
+                                    }));
+
+                                    self.destination.directories.push(path);
                                 }
                                 Receive::EndDirectory => {
-                                    self.path.pop();
+                                    self.destination.directories.pop();
+                                }
+                                Receive::AccessTime { modified_time, .. } => {
+                                    self.destination.modified = i64::try_from(modified_time)
+                                        .ok()
+                                        .and_then(|v| OffsetDateTime::from_unix_timestamp(v).ok());
                                 }
-                                Receive::AccessTime { .. } => {}
                             }
 
                             self.pending_data
@@ -119,7 +223,7 @@ impl Command for Scp {
 
                             // signal to the client we received their message and we're now
                             // listening for more data
-                            session.data(channel, SUCCESS.to_string().into());
+                            session.data(channel, reply.into_owned().into());
 
                             state
                         }
@@ -129,21 +233,17 @@ impl Command for Scp {
                         }
                     }
                 }
-                State::ReceivingFile(length, path) => {
+                State::ReceivingFile(length, path, mode) => {
                     if self.pending_data.len() < length {
                         // keep waiting for more data...
                         exit = true;
-                        State::ReceivingFile(length, path)
+                        State::ReceivingFile(length, path, mode)
                     } else {
                         // we've received the whole file, lets print and start waiting again
-                        let data = self.pending_data.split_to(length);
+                        let data = self.pending_data.split_to(length).freeze();
 
-                        connection
-                            .audit_log()
-                            .push_action(AuditLogAction::WriteFile(WriteFileEvent {
-                                path: Box::from(path.to_string_lossy().into_owned()),
-                                content: data.freeze(),
-                            }));
+                        connection.record_file_write(&path.to_string_lossy(), data.clone());
+                        write_file(connection, &path, &data, mode);
 
                         State::AwaitingSeparator
                     }
@@ -164,17 +264,142 @@ impl Command for Scp {
             self.state = next_state;
         }
 
+        self.reservation
+            .sync(connection.memory(), self.pending_data.capacity());
+
         CommandResult::ReadStdin(self)
     }
+
+    fn abort(self, connection: &mut ConnectionState) {
+        let State::ReceivingFile(length, path, _mode) = self.state else {
+            return;
+        };
+
+        let bytes_received = self.pending_data.len() as u64;
+        let (content, sha256) = connection.truncate_upload(self.pending_data.freeze());
+
+        connection.push_action(AuditLogAction::PartialUpload(PartialUploadEvent {
+            protocol: Cow::Borrowed("scp"),
+            path: path.to_string_lossy().into(),
+            bytes_received,
+            expected_bytes: Some(length as u64),
+            content: Some(content),
+            sha256,
+        }));
+    }
 }
 
 #[derive(Clone, Debug)]
 enum State {
     Waiting,
-    ReceivingFile(usize, PathBuf),
+    ReceivingFile(usize, PathBuf, u32),
     AwaitingSeparator,
 }
 
+/// Tracks where incoming files should be written, as `D` and `E` messages descend into and climb
+/// back out of directories.
+#[derive(Clone, Debug)]
+struct Destination {
+    /// The path given on the command line.
+    target: PathBuf,
+    /// Whether `target` is a directory to copy into, rather than the name to copy to.
+    target_is_directory: bool,
+    /// Directories entered by `D` messages that haven't yet been left by an `E`.
+    directories: Vec<PathBuf>,
+    /// Modification time sent by the last `T` message, which applies to the next file or
+    /// directory.
+    modified: Option<OffsetDateTime>,
+}
+
+impl Destination {
+    /// Resolves the name from a `C` or `D` message to the path it should be written to, returning
+    /// `None` if the name would escape the current directory.
+    fn resolve(&self, name: &str) -> Option<PathBuf> {
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return None;
+        }
+
+        Some(match self.directories.last() {
+            Some(directory) => directory.join(name),
+            None if self.target_is_directory => self.target.join(name),
+            None => self.target.clone(),
+        })
+    }
+}
+
+fn parse_mode(mode: &str) -> u32 {
+    u32::from_str_radix(mode, 8).map_or(0o644, |v| v & 0o7777)
+}
+
+/// Writes a received file into the fake filesystem, so it shows up to the client afterwards.
+fn write_file(connection: &mut ConnectionState, path: &Path, data: &[u8], mode: u32) {
+    let fs = connection.file_system();
+
+    if fs.write(path, Box::from(data)).is_ok() {
+        if let Ok(metadata) = fs.metadata_mut(path) {
+            metadata.mode = mode;
+        }
+    }
+}
+
+/// Handles `scp` being run by the client to copy files to or from another host, which is
+/// recorded before failing to connect.
+fn connect_out<S: ThrusshSession + Send>(
+    connection: &mut ConnectionState,
+    operands: &[&str],
+    port: Option<u16>,
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<Scp> {
+    let remote = if operands.len() < 2 {
+        None
+    } else {
+        operands.iter().find_map(|v| Remote::parse_scp(v))
+    };
+
+    let Some((remote, path)) = remote else {
+        session.data(channel, HELP.to_string().into());
+        return CommandResult::Exit(1);
+    };
+
+    let port = remote.port.or(port).unwrap_or(22);
+    let username = remote
+        .user
+        .map_or_else(|| connection.username().to_string(), ToString::to_string);
+
+    ssh::record(
+        connection,
+        "scp",
+        remote.host,
+        port,
+        Some(&username),
+        None,
+        Some(path),
+    );
+
+    session.data(
+        channel,
+        format!(
+            "{}lost connection\n",
+            ssh::ssh_connection_error(remote.host, port)
+        )
+        .into(),
+    );
+    CommandResult::Exit(1)
+}
+
+fn unexpected_filename<S: ThrusshSession>(
+    name: &str,
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<Scp> {
+    session.data(
+        channel,
+        format!("\x01scp: error: unexpected filename: {name}\n").into(),
+    );
+    CommandResult::Exit(1)
+}
+
 #[derive(Debug, PartialEq, Eq)]
 #[allow(dead_code)]
 enum Receive<'a> {
@@ -283,11 +508,15 @@ impl<'a> Receive<'a> {
 
 #[cfg(test)]
 mod test {
+    use std::path::Path;
+
     use insta::assert_debug_snapshot;
     use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
 
     use crate::{
-        command::{scp::Scp, Command},
+        command::{scp::Scp, Command, CommandResult},
+        config::Config,
         server::{
             test::{fake_channel_id, predicate::eq_string},
             ConnectionState, MockThrusshSession,
@@ -373,9 +602,291 @@ mod test {
             .unwrap_stdin();
 
         insta::with_settings!({filters => vec![
-            (r"\bstart_offset: [^,]+", "start_offset: [stripped]")
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            assert_debug_snapshot!(state.audit_log());
+        });
+    }
+
+    #[tokio::test]
+    async fn accepts_openssh_sink_flags() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .with(always(), eq_string("\0"))
+            .returning(|_, _| ());
+
+        let out = Scp::new(
+            &mut state,
+            ["-d", "-p", "-t", "--", "/tmp"]
+                .map(String::from)
+                .as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        assert!(out.destination.target_is_directory);
+        assert_eq!(out.destination.target, Path::new("/tmp"));
+    }
+
+    #[tokio::test]
+    async fn audits_interrupted_upload() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .with(always(), eq_string("\0"))
+            .returning(|_, _| ());
+
+        let out = Scp::new(
+            &mut state,
+            ["-t".to_string(), "hello".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"C0777 11 hello.txt\nhello",
+                &mut session,
+            )
+            .await
+            .unwrap_stdin();
+
+        out.abort(&mut state);
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            assert_debug_snapshot!(state.audit_log());
+        });
+    }
+
+    #[tokio::test]
+    async fn enforces_upload_limits() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            max_file_upload: 11,
+            max_audited_content: 5,
+            ..Config::default()
+        });
+
+        session
+            .expect_data()
+            .with(always(), eq_string("\0"))
+            .returning(|_, _| ());
+        session
+            .expect_data()
+            .with(
+                always(),
+                eq_string("\x01scp: hello/big.txt: No space left on device\n"),
+            )
+            .once()
+            .returning(|_, _| ());
+
+        let out = Scp::new(
+            &mut state,
+            ["-d".to_string(), "-t".to_string(), "hello".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let _out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"C0777 12 big.txt\nC0777 11 hello.txt\nhello world\0",
+                &mut session,
+            )
+            .await
+            .unwrap_stdin();
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
         ]}, {
             assert_debug_snapshot!(state.audit_log());
         });
     }
+
+    #[tokio::test]
+    async fn enforces_memory_budget() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            max_connection_memory: 32,
+            ..Config::default()
+        });
+
+        session
+            .expect_data()
+            .with(always(), eq_string("\0"))
+            .returning(|_, _| ());
+
+        let out = Scp::new(
+            &mut state,
+            ["-t".to_string(), "hello".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"C0777 64 hello.txt\nhello",
+                &mut session,
+            )
+            .await
+            .unwrap_stdin();
+        assert!(state.memory().used() <= 32);
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), &[b'a'; 32], &mut session)
+            .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)));
+        assert_eq!(state.memory().used(), 0);
+
+        let Some(AuditLogAction::PartialUpload(upload)) =
+            state.audit_log().events.last().map(|v| &v.action)
+        else {
+            panic!("expected a partial upload");
+        };
+        assert_eq!(upload.bytes_received, 5);
+    }
+
+    #[tokio::test]
+    async fn recursive() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .with(always(), eq_string("\0"))
+            .returning(|_, _| ());
+
+        let out = Scp::new(
+            &mut state,
+            ["-r".to_string(), "-t".to_string(), "/root".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let _out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"D0755 0 toolkit\nC0755 3 run\nabc\0T1700000000 0 1700000000 0\nD0700 0 lib\n\
+                  C0644 2 a.so\nhi\0E\nE\nC0644 1 top\nx\0",
+                &mut session,
+            )
+            .await
+            .unwrap_stdin();
+
+        let fs = state.file_system();
+        assert_eq!(
+            fs.read(Path::new("/root/toolkit/lib/a.so")).ok(),
+            Some(&b"hi"[..])
+        );
+        assert_eq!(fs.read(Path::new("/root/top")).ok(), Some(&b"x"[..]));
+        assert_eq!(
+            fs.metadata(Path::new("/root/toolkit/run"))
+                .ok()
+                .map(|v| v.mode),
+            Some(0o755)
+        );
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            assert_debug_snapshot!(state.audit_log());
+        });
+    }
+
+    #[tokio::test]
+    async fn rejects_unexpected_filenames() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .with(always(), eq_string("\0"))
+            .returning(|_, _| ());
+        session
+            .expect_data()
+            .with(
+                always(),
+                eq_string("\x01scp: error: unexpected filename: ..\n"),
+            )
+            .once()
+            .returning(|_, _| ());
+
+        let out = Scp::new(
+            &mut state,
+            ["-r".to_string(), "-t".to_string(), "/root".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"D0755 0 ..\n", &mut session)
+            .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)));
+        assert!(state.audit_log().events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn records_outbound_copies() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .with(
+                always(),
+                eq_string(
+                    "ssh: connect to host 10.0.0.5 port 2222: Connection timed out\nlost connection\n",
+                ),
+            )
+            .once()
+            .returning(|_, _| ());
+
+        let params = shlex::split("-P 2222 -i key payload.sh admin@10.0.0.5:/tmp/").unwrap();
+        let out = Scp::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(1)));
+
+        let AuditLogAction::LateralMovement(event) = &state.audit_log().events[0].action else {
+            panic!("expected lateral movement event");
+        };
+
+        assert_eq!(event.command, "scp");
+        assert_eq!(&*event.host, "10.0.0.5");
+        assert_eq!(event.port, 2222);
+        assert_eq!(event.username.as_deref(), Some("admin"));
+        assert_eq!(event.path.as_deref(), Some("/tmp/"));
+    }
 }
@@ -0,0 +1,638 @@
+//! Service management, stopping and disabling services is pretended to work and recorded since
+//! attackers commonly try to kill anything that might be watching them.
+
+use std::{borrow::Cow, fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, DefenseEvasionEvent};
+use thrussh::ChannelId;
+use time::{macros::format_description, Duration, OffsetDateTime};
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// A unit installed on the fake system.
+struct Unit {
+    name: &'static str,
+    description: &'static str,
+    /// The main process of the unit as `(pid, command line)`, or `None` for oneshot units that
+    /// exit once they've run.
+    process: Option<(u32, &'static str)>,
+    /// Microseconds after boot the unit was started, matching the journal.
+    started: i64,
+}
+
+const UNITS: &[Unit] = &[
+    Unit {
+        name: "apparmor",
+        description: "Load AppArmor profiles",
+        process: None,
+        started: 321_104,
+    },
+    Unit {
+        name: "cron",
+        description: "Regular background program processing daemon",
+        process: Some((598, "/usr/sbin/cron -f")),
+        started: 408_213,
+    },
+    Unit {
+        name: "rsyslog",
+        description: "System Logging Service",
+        process: Some((401, "/usr/sbin/rsyslogd -n -iNONE")),
+        started: 388_950,
+    },
+    Unit {
+        name: "ssh",
+        description: "OpenBSD Secure Shell server",
+        process: Some((
+            612,
+            "sshd: /usr/sbin/sshd -D [listener] 0 of 10-100 startups",
+        )),
+        started: 612_877,
+    },
+    Unit {
+        name: "systemd-journald",
+        description: "Journal Service",
+        process: Some((231, "/lib/systemd/systemd-journald")),
+        started: 98_112,
+    },
+    Unit {
+        name: "ufw",
+        description: "Uncomplicated firewall",
+        process: None,
+        started: 330_871,
+    },
+];
+
+/// Actions that stop or weaken a service, and so are recorded.
+const EVASIVE_ACTIONS: &[&str] = &["stop", "disable", "mask", "kill"];
+
+/// Records an attempt to stop or weaken a security control.
+pub fn record(
+    connection: &mut ConnectionState,
+    command: &'static str,
+    action: &str,
+    targets: Vec<String>,
+) {
+    connection.push_action(AuditLogAction::DefenseEvasion(DefenseEvasionEvent {
+        command: Cow::Borrowed(command),
+        action: Box::from(action),
+        targets: targets.into_boxed_slice(),
+    }));
+}
+
+/// Strips the `.service` suffix from a unit name, resolving aliases to the unit they refer to.
+fn unit_name(name: &str) -> &str {
+    match name.strip_suffix(".service").unwrap_or(name) {
+        "sshd" => "ssh",
+        "syslog" => "rsyslog",
+        name => name,
+    }
+}
+
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    UNITS.iter().find(|v| v.name == name)
+}
+
+/// Returns true if the client has written a unit file for `name` themselves.
+fn user_unit_exists(connection: &mut ConnectionState, name: &str) -> bool {
+    let path = format!("/etc/systemd/system/{name}.service");
+    connection.file_system().read(Path::new(&path)).is_ok()
+}
+
+/// Formats `systemctl status` for a unit, returning the exit code `systemctl` would.
+fn status(
+    connection: &mut ConnectionState,
+    name: &str,
+    now: OffsetDateTime,
+) -> (String, String, u32) {
+    let Some(unit) = find_unit(name) else {
+        return if user_unit_exists(connection, name) {
+            (
+                format!(
+                    "○ {name}.service\n     Loaded: loaded (/etc/systemd/system/{name}.service; \
+                     disabled; vendor preset: enabled)\n     Active: inactive (dead)\n"
+                ),
+                String::new(),
+                3,
+            )
+        } else {
+            (
+                String::new(),
+                format!("Unit {name}.service could not be found.\n"),
+                4,
+            )
+        };
+    };
+
+    let running = connection.is_service_running(name);
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "{} {name}.service - {}",
+        if running { '●' } else { '○' },
+        unit.description
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "     Loaded: loaded (/lib/systemd/system/{name}.service; {}; vendor preset: enabled)",
+        if running { "enabled" } else { "disabled" }
+    )
+    .unwrap();
+
+    if !running {
+        out.push_str("     Active: inactive (dead)\n");
+        return (out, String::new(), 3);
+    }
+
+    let since = connection.boot_time() + Duration::microseconds(unit.started);
+    let ago = now - since;
+    let ago = if ago.whole_days() > 0 {
+        format!("{} days ago", ago.whole_days())
+    } else {
+        format!("{}h {}min ago", ago.whole_hours(), ago.whole_minutes() % 60)
+    };
+
+    writeln!(
+        out,
+        "     Active: {} since {}; {ago}",
+        if unit.process.is_some() {
+            "active (running)"
+        } else {
+            "active (exited)"
+        },
+        since
+            .format(format_description!(
+                "[weekday repr:short] [year]-[month]-[day] [hour]:[minute]:[second] UTC"
+            ))
+            .unwrap_or_default(),
+    )
+    .unwrap();
+
+    if let Some((pid, command)) = unit.process {
+        let binary = command
+            .split_whitespace()
+            .next()
+            .and_then(|v| v.rsplit('/').next())
+            .unwrap_or_default()
+            .trim_end_matches(':');
+
+        writeln!(out, "   Main PID: {pid} ({binary})").unwrap();
+        writeln!(out, "     CGroup: /system.slice/{name}.service").unwrap();
+        writeln!(out, "             └─{pid} {command}").unwrap();
+    }
+
+    (out, String::new(), 0)
+}
+
+/// Performs a `systemctl` action on a single unit, returning the output, errors and exit code.
+fn control(
+    connection: &mut ConnectionState,
+    command: &'static str,
+    action: &str,
+    name: &str,
+) -> (String, String, u32) {
+    if EVASIVE_ACTIONS.contains(&action) {
+        record(connection, command, action, vec![name.to_string()]);
+    }
+
+    let exists = find_unit(name).is_some() || user_unit_exists(connection, name);
+
+    if !exists && action != "mask" {
+        return if matches!(action, "enable" | "disable" | "unmask") {
+            (
+                String::new(),
+                format!("Failed to {action} unit: Unit file {name}.service does not exist.\n"),
+                1,
+            )
+        } else {
+            (
+                String::new(),
+                format!("Failed to {action} {name}.service: Unit {name}.service not found.\n"),
+                5,
+            )
+        };
+    }
+
+    if connection.username() != "root" {
+        return (
+            String::new(),
+            format!(
+                "Failed to {action} {name}.service: Interactive authentication required.\n\
+                 See system logs and 'systemctl status {name}.service' for details.\n"
+            ),
+            1,
+        );
+    }
+
+    let out = match action {
+        "stop" | "kill" => {
+            connection.set_service_running(name, false);
+            String::new()
+        }
+        "start" | "restart" | "reload" => {
+            connection.set_service_running(name, true);
+            String::new()
+        }
+        "disable" => {
+            connection.set_service_running(name, false);
+            format!("Removed /etc/systemd/system/multi-user.target.wants/{name}.service.\n")
+        }
+        "mask" => {
+            connection.set_service_running(name, false);
+            format!("Created symlink /etc/systemd/system/{name}.service → /dev/null.\n")
+        }
+        "enable" => {
+            let source = if find_unit(name).is_some() {
+                "/lib/systemd/system"
+            } else {
+                "/etc/systemd/system"
+            };
+
+            format!(
+                "Created symlink /etc/systemd/system/multi-user.target.wants/{name}.service → \
+                 {source}/{name}.service.\n"
+            )
+        }
+        "unmask" => format!("Removed /etc/systemd/system/{name}.service.\n"),
+        _ => String::new(),
+    };
+
+    (out, String::new(), 0)
+}
+
+fn list_units(connection: &ConnectionState) -> String {
+    let mut out = String::from("  UNIT                      LOAD   ACTIVE   SUB     DESCRIPTION\n");
+    let mut listed = 0;
+
+    for unit in UNITS {
+        if !connection.is_service_running(unit.name) {
+            continue;
+        }
+
+        listed += 1;
+        writeln!(
+            out,
+            "  {:<25} loaded active   {:<7} {}",
+            format!("{}.service", unit.name),
+            if unit.process.is_some() {
+                "running"
+            } else {
+                "exited"
+            },
+            unit.description,
+        )
+        .unwrap();
+    }
+
+    write!(
+        out,
+        "\nLOAD   = Reflects whether the unit definition was properly loaded.\n\
+         ACTIVE = The high-level unit activation state, i.e. generalization of SUB.\n\
+         SUB    = The low-level unit activation state, values depend on unit type.\n\
+         {listed} loaded units listed.\n"
+    )
+    .unwrap();
+
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct Systemctl {}
+
+#[async_trait]
+impl Command for Systemctl {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut args = params
+            .iter()
+            .map(String::as_str)
+            .filter(|v| !v.starts_with('-'));
+
+        let action = args.next().unwrap_or("list-units");
+        let units: Vec<_> = args.map(unit_name).collect();
+
+        let (out, err, exit_code) = match action {
+            "list-units" => (list_units(connection), String::new(), 0),
+            "daemon-reload" => (String::new(), String::new(), 0),
+            "status" if units.is_empty() => (
+                format!(
+                    "● {}\n    State: running\n     Jobs: 0 queued\n   Failed: 0 units\n",
+                    connection.node_name()
+                ),
+                String::new(),
+                0,
+            ),
+            "is-active" | "is-enabled" => {
+                let mut out = String::new();
+                let mut exit_code = 0;
+
+                for unit in &units {
+                    let running = find_unit(unit).is_some() && connection.is_service_running(unit);
+                    exit_code = if running { exit_code } else { 3 };
+
+                    out.push_str(match (action, running) {
+                        ("is-active", true) => "active\n",
+                        ("is-active", false) => "inactive\n",
+                        (_, true) => "enabled\n",
+                        (_, false) => "disabled\n",
+                    });
+                }
+
+                (out, String::new(), exit_code)
+            }
+            "status" | "stop" | "start" | "restart" | "reload" | "enable" | "disable" | "mask"
+            | "unmask" | "kill"
+                if units.is_empty() =>
+            {
+                (String::new(), "Too few arguments.\n".to_string(), 1)
+            }
+            "status" => {
+                let now = connection.now();
+                let mut out = String::new();
+                let mut err = String::new();
+                let mut exit_code = 0;
+
+                for unit in &units {
+                    let (status, error, code) = status(connection, unit, now);
+                    out.push_str(&status);
+                    err.push_str(&error);
+                    exit_code = exit_code.max(code);
+                }
+
+                (out, err, exit_code)
+            }
+            "stop" | "start" | "restart" | "reload" | "enable" | "disable" | "mask" | "unmask"
+            | "kill" => {
+                let mut out = String::new();
+                let mut err = String::new();
+                let mut exit_code = 0;
+
+                for unit in &units {
+                    let (result, error, code) = control(connection, "systemctl", action, unit);
+                    out.push_str(&result);
+                    err.push_str(&error);
+                    exit_code = exit_code.max(code);
+                }
+
+                (out, err, exit_code)
+            }
+            other => (String::new(), format!("Unknown command verb {other}.\n"), 1),
+        };
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Service {}
+
+#[async_trait]
+impl Command for Service {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = match params {
+            [flag] if flag == "--status-all" => {
+                let mut out = String::new();
+
+                for unit in UNITS {
+                    let state = if connection.is_service_running(unit.name) {
+                        '+'
+                    } else {
+                        '-'
+                    };
+
+                    writeln!(out, " [ {state} ]  {}", unit.name).unwrap();
+                }
+
+                (out, String::new(), 0)
+            }
+            [name, action, ..] => {
+                let name = unit_name(name);
+
+                match action.as_str() {
+                    "status" => {
+                        let now = connection.now();
+                        status(connection, name, now)
+                    }
+                    "stop" | "start" | "restart" | "reload" => {
+                        control(connection, "service", action, name)
+                    }
+                    _ => (
+                        String::new(),
+                        format!(
+                            "Usage: /etc/init.d/{name} \
+                             {{start|stop|reload|force-reload|restart|try-restart|status}}\n"
+                        ),
+                        1,
+                    ),
+                }
+            }
+            [name] => (String::new(), format!("{name}: unrecognized service\n"), 1),
+            [] => (
+                String::new(),
+                "Usage: service < option > | --status-all | \
+                 [ service_name [ command | --full-restart ] ]\n"
+                    .to_string(),
+                1,
+            ),
+        };
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, DefenseEvasionEvent};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            service::{Service, Systemctl},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    async fn run(state: &mut ConnectionState, input: &str) -> (String, String, u32) {
+        let mut session = MockThrusshSession::default();
+        let out = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+        let err = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+
+        let captured = out.clone();
+        session.expect_data().returning(move |_, data| {
+            captured
+                .lock()
+                .unwrap()
+                .push_str(std::str::from_utf8(&data).unwrap());
+        });
+
+        let captured = err.clone();
+        session.expect_stderr().returning(move |_, data| {
+            captured
+                .lock()
+                .unwrap()
+                .push_str(std::str::from_utf8(&data).unwrap());
+        });
+
+        let params = shlex::split(input).unwrap();
+        let CommandResult::Exit(exit_code) =
+            Systemctl::new(state, &params, fake_channel_id(), &mut session).await
+        else {
+            panic!("expected exit");
+        };
+
+        let out = out.lock().unwrap().clone();
+        let err = err.lock().unwrap().clone();
+        (out, err, exit_code)
+    }
+
+    #[tokio::test]
+    async fn stops_services() {
+        let mut state = ConnectionState::mock();
+
+        assert_eq!(
+            run(&mut state, "is-active ufw").await,
+            ("active\n".into(), String::new(), 0)
+        );
+        assert_eq!(
+            run(&mut state, "stop ufw.service").await,
+            (String::new(), String::new(), 0)
+        );
+        assert_eq!(
+            run(&mut state, "is-active ufw").await,
+            ("inactive\n".into(), String::new(), 3)
+        );
+
+        let (out, _, exit_code) = run(&mut state, "status ufw").await;
+        assert!(out.contains("inactive (dead)"), "{out}");
+        assert_eq!(exit_code, 3);
+
+        let AuditLogAction::DefenseEvasion(DefenseEvasionEvent {
+            command,
+            action,
+            targets,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected defense evasion event");
+        };
+
+        assert_eq!(command, "systemctl");
+        assert_eq!(&**action, "stop");
+        assert_eq!(&**targets, ["ufw"]);
+    }
+
+    #[test_case("status ssh", 0; "running")]
+    #[test_case("status auditd", 4; "missing")]
+    #[test_case("stop auditd", 5; "stop missing")]
+    #[test_case("disable auditd", 1; "disable missing")]
+    #[test_case("frobnicate", 1; "unknown verb")]
+    #[test_case("", 0; "list units")]
+    #[tokio::test]
+    async fn exit_codes(input: &str, expected: u32) {
+        let (out, err, exit_code) = run(&mut ConnectionState::mock(), input).await;
+        assert_eq!(exit_code, expected, "{out}{err}");
+    }
+
+    #[tokio::test]
+    async fn requires_root() {
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+
+        let (_, err, exit_code) = run(&mut state, "stop ssh").await;
+
+        assert_eq!(exit_code, 1);
+        assert!(err.contains("Interactive authentication required"), "{err}");
+        assert!(state.is_service_running("ssh"));
+        assert!(matches!(
+            state.audit_log().events[0].action,
+            AuditLogAction::DefenseEvasion(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn service() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string(" [ + ]  apparmor\n [ + ]  cron\n [ - ]  rsyslog\n [ + ]  ssh\n [ + ]  systemd-journald\n [ + ]  ufw\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Service::new(
+            &mut state,
+            ["rsyslog".to_string(), "stop".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let out = Service::new(
+            &mut state,
+            ["--status-all".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+}
@@ -0,0 +1,435 @@
+//! Clients for connecting out to other hosts. No connection is ever made, the destination is
+//! recorded to reveal where the attacker is trying to pivot to and the connection then fails.
+
+use std::{borrow::Cow, net::IpAddr};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, LateralMovementEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const SSH_USAGE: &str = "usage: ssh [-46AaCfGgKkMNnqsTtVvXxYy] [-B bind_interface]
+           [-b bind_address] [-c cipher_spec] [-D [bind_address:]port]
+           [-E log_file] [-e escape_char] [-F configfile] [-I pkcs11]
+           [-i identity_file] [-J [user@]host[:port]] [-L address]
+           [-l login_name] [-m mac_spec] [-O ctl_cmd] [-o option] [-p port]
+           [-Q query_option] [-R address] [-S ctl_path] [-W host:port]
+           [-w local_tun[:remote_tun]] destination [command [argument ...]]
+";
+
+const TELNET_USAGE: &str = "usage: telnet [-4] [-6] [-8] [-E] [-L] [-a] [-d] [-e char] [-l user]
+\t[-n tracefile] [ -b addr ] [-r] [host-name [port]]
+";
+
+/// Flags taking a value. `scp` accepts most of the same flags, but with a few differences such as
+/// the port being given by `-P` rather than `-p`.
+pub const SSH_TAKES_VALUE: &[char] = &[
+    'B', 'b', 'c', 'D', 'E', 'e', 'F', 'I', 'i', 'J', 'L', 'l', 'm', 'O', 'o', 'p', 'Q', 'R', 'S',
+    'W', 'w',
+];
+
+/// A host being connected to, in the `[user@]host[:port]` form or as an `ssh://` URI.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Remote<'a> {
+    pub user: Option<&'a str>,
+    pub host: &'a str,
+    pub port: Option<u16>,
+}
+
+impl<'a> Remote<'a> {
+    /// Parses an `ssh` destination, `[user@]host` or `ssh://[user@]host[:port]`.
+    pub fn parse(destination: &'a str) -> Self {
+        if let Some(uri) = destination.strip_prefix("ssh://") {
+            let authority = uri.split('/').next().unwrap_or_default();
+            let (user, host_port) = split_user(authority);
+            let (host, port) = split_port(host_port);
+            return Self { user, host, port };
+        }
+
+        let (user, host) = split_user(destination);
+        Self {
+            user,
+            host,
+            port: None,
+        }
+    }
+
+    /// Parses an `scp` operand if it refers to a remote host, returning the host along with the
+    /// remote path. Local paths, including those containing a colon after a slash, return `None`.
+    pub fn parse_scp(operand: &'a str) -> Option<(Self, &'a str)> {
+        if let Some(uri) = operand.strip_prefix("scp://") {
+            let (authority, path) = uri.split_once('/').unwrap_or((uri, ""));
+            let (user, host_port) = split_user(authority);
+            let (host, port) = split_port(host_port);
+            return Some((Self { user, host, port }, path));
+        }
+
+        // colons within brackets are part of an IPv6 address, ie. `[::1]:/tmp`
+        let mut in_brackets = false;
+        let (split, _) = operand.char_indices().find(|&(_, c)| {
+            match c {
+                '[' => in_brackets = true,
+                ']' => in_brackets = false,
+                _ => {}
+            }
+
+            c == ':' && !in_brackets
+        })?;
+        let (remote, path) = (&operand[..split], &operand[split + 1..]);
+
+        if remote.is_empty() || remote.contains('/') {
+            return None;
+        }
+
+        let (user, host) = split_user(remote);
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        Some((
+            Self {
+                user,
+                host,
+                port: None,
+            },
+            path,
+        ))
+    }
+}
+
+fn split_user(v: &str) -> (Option<&str>, &str) {
+    match v.rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, v),
+    }
+}
+
+fn split_port(v: &str) -> (&str, Option<u16>) {
+    if let Some(rest) = v.strip_prefix('[') {
+        let (host, port) = rest.split_once(']').unwrap_or((rest, ""));
+        return (host, port.strip_prefix(':').and_then(|v| v.parse().ok()));
+    }
+
+    match v.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()),
+        None => (v, None),
+    }
+}
+
+/// Records the connection the client attempted.
+pub fn record(
+    connection: &mut ConnectionState,
+    command: &'static str,
+    host: &str,
+    port: u16,
+    username: Option<&str>,
+    remote_command: Option<String>,
+    path: Option<&str>,
+) {
+    connection.push_action(AuditLogAction::LateralMovement(LateralMovementEvent {
+        command: Cow::Borrowed(command),
+        host: Box::from(host),
+        port,
+        username: username.map(Box::from),
+        remote_command: remote_command.map(String::into_boxed_str),
+        path: path.map(Box::from),
+    }));
+}
+
+/// The error `ssh` gives when it fails to connect, connections to the local machine are refused
+/// and hostnames never resolve, everything else times out.
+pub fn ssh_connection_error(host: &str, port: u16) -> String {
+    if host == "localhost" || host.parse::<IpAddr>().is_ok_and(|v| v.is_loopback()) {
+        format!("ssh: connect to host {host} port {port}: Connection refused\n")
+    } else if host.parse::<IpAddr>().is_ok() {
+        format!("ssh: connect to host {host} port {port}: Connection timed out\n")
+    } else {
+        format!("ssh: Could not resolve hostname {host}: Temporary failure in name resolution\n")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ssh {}
+
+#[async_trait]
+impl Command for Ssh {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut port = None;
+        let mut login = None;
+        let mut iter = params.iter();
+        let mut destination = None;
+
+        while let Some(param) = iter.next() {
+            let Some(flags) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+                destination = Some(param.as_str());
+                break;
+            };
+
+            for (pos, c) in flags.char_indices() {
+                if c == 'V' {
+                    session.data(
+                        channel,
+                        "OpenSSH_8.4p1 Debian-5+deb11u1, OpenSSL 1.1.1w  11 Sep 2023\n".into(),
+                    );
+                    return CommandResult::Exit(0);
+                } else if !SSH_TAKES_VALUE.contains(&c) {
+                    continue;
+                }
+
+                let attached = &flags[pos + c.len_utf8()..];
+                let Some(value) = (if attached.is_empty() {
+                    iter.next().map(String::as_str)
+                } else {
+                    Some(attached)
+                }) else {
+                    session.stderr(
+                        channel,
+                        format!("option requires an argument -- {c}\n{SSH_USAGE}").into(),
+                    );
+                    return CommandResult::Exit(255);
+                };
+
+                match c {
+                    'p' => port = value.parse().ok(),
+                    'l' => login = Some(value),
+                    _ => {}
+                }
+
+                break;
+            }
+        }
+
+        let Some(destination) = destination else {
+            session.stderr(channel, SSH_USAGE.into());
+            return CommandResult::Exit(255);
+        };
+
+        let remote = Remote::parse(destination);
+        let port = remote.port.or(port).unwrap_or(22);
+        let remote_command = iter.as_slice().join(" ");
+        let username = remote
+            .user
+            .or(login)
+            .map_or_else(|| connection.username().to_string(), ToString::to_string);
+
+        record(
+            connection,
+            "ssh",
+            remote.host,
+            port,
+            Some(&username),
+            Some(remote_command).filter(|v| !v.is_empty()),
+            None,
+        );
+
+        session.stderr(channel, ssh_connection_error(remote.host, port).into());
+        CommandResult::Exit(255)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Telnet {}
+
+#[async_trait]
+impl Command for Telnet {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut login = None;
+        let mut operands = Vec::new();
+        let mut iter = params.iter();
+
+        while let Some(param) = iter.next() {
+            match param.strip_prefix('-').filter(|v| !v.is_empty()) {
+                Some("l") => login = iter.next().map(String::as_str),
+                Some("e" | "n" | "b") => {
+                    iter.next();
+                }
+                Some(_) => {}
+                None => operands.push(param.as_str()),
+            }
+        }
+
+        let (host, port) = match operands.as_slice() {
+            [host] => (*host, Ok(23)),
+            [host, port] => (*host, port.parse::<u16>().map_err(|_| *port)),
+            _ => {
+                session.stderr(channel, TELNET_USAGE.into());
+                return CommandResult::Exit(1);
+            }
+        };
+
+        let port = match port {
+            Ok(port) => port,
+            Err(port) => {
+                session.stderr(
+                    channel,
+                    format!(
+                        "telnet: could not resolve {host}/{port}: Servname not supported for \
+                         ai_socktype\n"
+                    )
+                    .into(),
+                );
+                return CommandResult::Exit(1);
+            }
+        };
+
+        record(connection, "telnet", host, port, login, None, None);
+
+        let err = if let Ok(addr) = host.parse::<IpAddr>() {
+            let error = if addr.is_loopback() {
+                "Connection refused"
+            } else {
+                "Connection timed out"
+            };
+
+            session.data(channel, format!("Trying {addr}...\n").into());
+            format!("telnet: Unable to connect to remote host: {error}\n")
+        } else {
+            format!(
+                "telnet: could not resolve {host}/{port}: Temporary failure in name resolution\n"
+            )
+        };
+
+        session.stderr(channel, err.into());
+        CommandResult::Exit(1)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, LateralMovementEvent};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            ssh::{Remote, Ssh, Telnet},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("host", None, "host", None; "host")]
+    #[test_case("admin@10.0.0.1", Some("admin"), "10.0.0.1", None; "user")]
+    #[test_case("ssh://admin@host:2222", Some("admin"), "host", Some(2222); "uri")]
+    #[test_case("ssh://[::1]:2222", None, "::1", Some(2222); "uri ipv6")]
+    fn parses_destination(input: &str, user: Option<&str>, host: &str, port: Option<u16>) {
+        assert_eq!(Remote::parse(input), Remote { user, host, port });
+    }
+
+    #[test_case("admin@host:/tmp/a", Some(("host", "/tmp/a")); "remote")]
+    #[test_case("host:", Some(("host", "")); "remote home")]
+    #[test_case("scp://admin@host:2222/tmp/a", Some(("host", "tmp/a")); "uri")]
+    #[test_case("[::1]:/tmp/a", Some(("::1", "/tmp/a")); "ipv6")]
+    #[test_case("./a:b", None; "local with colon")]
+    #[test_case("/tmp/a", None; "local")]
+    fn parses_scp_operand(input: &str, expected: Option<(&str, &str)>) {
+        assert_eq!(
+            Remote::parse_scp(input).map(|(remote, path)| (remote.host, path)),
+            expected
+        );
+    }
+
+    #[test_case("10.0.0.5", "ssh: connect to host 10.0.0.5 port 2222: Connection timed out\n"; "timeout")]
+    #[test_case("127.0.0.1", "ssh: connect to host 127.0.0.1 port 2222: Connection refused\n"; "refused")]
+    #[test_case("db01", "ssh: Could not resolve hostname db01: Temporary failure in name resolution\n"; "unresolvable")]
+    #[tokio::test]
+    async fn ssh(host: &str, expected: &'static str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let params = shlex::split(&format!("-i key -p 2222 deploy@{host} uname -a")).unwrap();
+        let out = Ssh::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(255)), "{out:?}");
+
+        let AuditLogAction::LateralMovement(LateralMovementEvent {
+            command,
+            host: actual_host,
+            port,
+            username,
+            remote_command,
+            path,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected lateral movement event");
+        };
+
+        assert_eq!(command, "ssh");
+        assert_eq!(&**actual_host, host);
+        assert_eq!(*port, 2222);
+        assert_eq!(username.as_deref(), Some("deploy"));
+        assert_eq!(remote_command.as_deref(), Some("uname -a"));
+        assert_eq!(*path, None);
+    }
+
+    #[tokio::test]
+    async fn telnet() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("Trying 10.0.0.5...\n"))
+            .returning(|_, _| ());
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string("telnet: Unable to connect to remote host: Connection timed out\n"),
+            )
+            .returning(|_, _| ());
+
+        let params = shlex::split("10.0.0.5 2323").unwrap();
+        let out = Telnet::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+        assert!(matches!(
+            &state.audit_log().events[0].action,
+            AuditLogAction::LateralMovement(LateralMovementEvent { port: 2323, .. })
+        ));
+    }
+}
@@ -0,0 +1,371 @@
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, SwitchUserEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{
+        sudo::{read_password, PasswordInput, RunAs},
+        Command, CommandResult,
+    },
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Su {
+    state: State,
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    /// Waiting on the client to finish typing the target user's password.
+    Password { options: Options, password: Vec<u8> },
+    /// A command given with `-c` is running as the target user and waiting on stdin.
+    Running(RunAs),
+}
+
+impl From<RunAs> for Su {
+    fn from(value: RunAs) -> Self {
+        Self {
+            state: State::Running(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Options {
+    user: String,
+    /// Whether to start a login shell, changing into the user's home directory.
+    login: bool,
+    /// Command to run as the user instead of switching to them for the rest of the session.
+    command: Option<String>,
+}
+
+fn parse_args(params: &[String]) -> Result<Options, String> {
+    let mut user = None;
+    let mut login = false;
+    let mut command = None;
+    let mut iter = params.iter();
+
+    while let Some(param) = iter.next() {
+        match param.as_str() {
+            "-" | "-l" | "--login" => login = true,
+            "-c" | "--command" => {
+                command = Some(iter.next().cloned().ok_or_else(|| {
+                    "su: option requires an argument -- 'c'\n\
+                     Try 'su --help' for more information.\n"
+                        .to_string()
+                })?);
+            }
+            "-s" | "--shell" => {
+                iter.next();
+            }
+            "-m" | "-p" | "--preserve-environment" => {}
+            v if v.starts_with("--command=") => {
+                command = Some(v.trim_start_matches("--command=").to_string());
+            }
+            v if v.starts_with('-') => {
+                return Err(format!(
+                    "su: invalid option -- '{}'\nTry 'su --help' for more information.\n",
+                    v.trim_start_matches('-')
+                ));
+            }
+            // anything after the user is passed on to the shell
+            v if user.is_none() => user = Some(v.to_string()),
+            _ => {}
+        }
+    }
+
+    Ok(Options {
+        user: user.unwrap_or_else(|| "root".to_string()),
+        login,
+        command,
+    })
+}
+
+impl Su {
+    fn record(connection: &mut ConnectionState, to: &str, password: Option<&[u8]>, success: bool) {
+        let from = Box::from(connection.username());
+
+        connection.push_action(AuditLogAction::SwitchUser(SwitchUserEvent {
+            from,
+            to: Box::from(to),
+            password: password.map(|v| String::from_utf8_lossy(v).into()),
+            success,
+        }));
+    }
+
+    /// Switches to the requested user, either for the rest of the session or just for the
+    /// duration of the command given with `-c`.
+    async fn switch<S: ThrusshSession + Send>(
+        options: Options,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let Some(command) = options.command else {
+            connection.switch_user(&options.user, options.login);
+            return CommandResult::Exit(0);
+        };
+
+        let Some(command) = shlex::split(&command) else {
+            session.stderr(
+                channel,
+                "bash: -c: line 1: unexpected EOF while looking for matching `''\n".into(),
+            );
+            return CommandResult::Exit(2);
+        };
+
+        RunAs::start(&options.user, &command, connection, channel, session)
+            .await
+            .map(Self::from)
+    }
+}
+
+#[async_trait]
+impl Command for Su {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = match parse_args(params) {
+            Ok(v) => v,
+            Err(e) => {
+                session.stderr(channel, e.into());
+                return CommandResult::Exit(1);
+            }
+        };
+
+        // root can become anyone without a password
+        if connection.username() == "root" {
+            Self::record(connection, &options.user, None, true);
+            return Self::switch(options, connection, channel, session).await;
+        }
+
+        if !connection.has_pty(channel) {
+            session.stderr(channel, "su: must be run from a terminal\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        session.data(channel, "Password: ".into());
+
+        CommandResult::ReadStdin(Self {
+            state: State::Password {
+                options,
+                password: Vec::new(),
+            },
+        })
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (options, mut password) = match self.state {
+            State::Password { options, password } => (options, password),
+            State::Running(cmd) => {
+                return cmd
+                    .stdin(connection, channel, data, session)
+                    .await
+                    .map(Self::from);
+            }
+        };
+
+        match read_password(&mut password, data) {
+            PasswordInput::Incomplete => {
+                return CommandResult::ReadStdin(Self {
+                    state: State::Password { options, password },
+                });
+            }
+            PasswordInput::Interrupted => {
+                session.data(channel, "\n".into());
+                Self::record(connection, &options.user, Some(&password), false);
+                return CommandResult::Exit(1);
+            }
+            PasswordInput::Entered(_) => {}
+        }
+
+        session.data(channel, "\n".into());
+
+        let success = connection.check_password(&options.user, &String::from_utf8_lossy(&password));
+        Self::record(connection, &options.user, Some(&password), success);
+
+        if !success {
+            session.stderr(channel, "su: Authentication failure\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        Self::switch(options, connection, channel, session).await
+    }
+
+    fn abort(self, connection: &mut ConnectionState) {
+        match self.state {
+            State::Password { options, password } if !password.is_empty() => {
+                Self::record(connection, &options.user, Some(&password), false);
+            }
+            State::Password { .. } => {}
+            State::Running(cmd) => cmd.abort(connection),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, SwitchUserEvent};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            pwd::Pwd,
+            su::{parse_args, Su},
+            whoami::Whoami,
+            Command, CommandResult,
+        },
+        config::Config,
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("", "root", false, None; "no args")]
+    #[test_case("-", "root", true, None; "login")]
+    #[test_case("- ubuntu", "ubuntu", true, None; "login user")]
+    #[test_case("-c id ubuntu", "ubuntu", false, Some("id"); "command")]
+    #[test_case("--command=id -s /bin/sh", "root", false, Some("id"); "long command")]
+    fn parses_args(input: &str, user: &str, login: bool, command: Option<&str>) {
+        let input = shlex::split(input).unwrap();
+        let options = parse_args(&input).unwrap();
+
+        assert_eq!(options.user, user);
+        assert_eq!(options.login, login);
+        assert_eq!(options.command.as_deref(), command);
+    }
+
+    #[tokio::test]
+    async fn switches_user() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+        state.set_pty(fake_channel_id());
+        state.set_config(Config {
+            access_probability: 1.0,
+            ..Config::default()
+        });
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("Password: "))
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("\n"))
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("root\n"))
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("/root\n"))
+            .returning(|_, _| ());
+
+        assert_eq!(state.file_system().pwd().to_str(), Some("/home/ubuntu"));
+
+        let out = Su::new(
+            &mut state,
+            ["-".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"toor\r", &mut session)
+            .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        Whoami::new(&mut state, [].as_slice(), fake_channel_id(), &mut session).await;
+        Pwd::new(&mut state, [].as_slice(), fake_channel_id(), &mut session).await;
+
+        let AuditLogAction::SwitchUser(SwitchUserEvent {
+            from,
+            to,
+            password,
+            success,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected switch user event");
+        };
+
+        assert_eq!(&**from, "ubuntu");
+        assert_eq!(&**to, "root");
+        assert_eq!(password.as_deref(), Some("toor"));
+        assert!(success);
+    }
+
+    #[tokio::test]
+    async fn authentication_failure() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+        state.set_pty(fake_channel_id());
+        state.set_config(Config {
+            access_probability: 0.0,
+            ..Config::default()
+        });
+
+        session.expect_data().times(2).returning(|_, _| ());
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string("su: Authentication failure\n"))
+            .returning(|_, _| ());
+
+        let out = Su::new(&mut state, [].as_slice(), fake_channel_id(), &mut session)
+            .await
+            .unwrap_stdin()
+            .stdin(&mut state, fake_channel_id(), b"password\n", &mut session)
+            .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+        assert_eq!(state.username(), "ubuntu");
+    }
+
+    #[tokio::test]
+    async fn runs_command() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("ubuntu\n"))
+            .returning(|_, _| ());
+
+        let out = Su::new(
+            &mut state,
+            ["-c".to_string(), "whoami".to_string(), "ubuntu".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(state.username(), "root");
+    }
+}
@@ -0,0 +1,582 @@
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, SudoPasswordEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult, ConcreteCommand},
+    config::SudoMode,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "usage: sudo -h | -K | -k | -V
+usage: sudo -v [-AknS] [-g group] [-h host] [-p prompt] [-u user]
+usage: sudo -l [-AknS] [-g group] [-h host] [-p prompt] [-U user] [-u user] [command]
+usage: sudo [-AbEHknPS] [-C num] [-D directory] [-g group] [-h host] [-p prompt] [-R directory]
+            [-T timeout] [-u user] [VAR=value] [-i|-s] [<command>]
+usage: sudo -e [-AknS] [-C num] [-D directory] [-g group] [-h host] [-p prompt] [-R directory]
+            [-T timeout] [-u user] file ...
+";
+
+const LECTURE: &str = "
+We trust you have received the usual lecture from the local System
+Administrator. It usually boils down to these three things:
+
+    #1) Respect the privacy of others.
+    #2) Think before you type.
+    #3) With great power comes great responsibility.
+
+";
+
+/// Options taking a value, which may either be attached (`-uroot`) or the next parameter.
+const TAKES_VALUE: &[char] = &['u', 'g', 'p', 'C', 'D', 'h', 'R', 'T', 'U'];
+
+#[derive(Debug, Clone)]
+pub struct Sudo {
+    state: State,
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    /// Waiting on the client to finish typing their password, after which `command` is run.
+    Password {
+        command: Vec<String>,
+        target_user: Box<str>,
+        pty: bool,
+        password: Vec<u8>,
+    },
+    /// The wrapped command is running and waiting on stdin.
+    Running(RunAs),
+}
+
+impl From<RunAs> for Sudo {
+    fn from(value: RunAs) -> Self {
+        Self {
+            state: State::Running(value),
+        }
+    }
+}
+
+/// A command being run as another user, switching back to the original user once it exits.
+#[derive(Debug, Clone)]
+pub struct RunAs {
+    command: Box<ConcreteCommand>,
+    previous_user: Box<str>,
+}
+
+impl RunAs {
+    pub async fn start<S: ThrusshSession + Send>(
+        target_user: &str,
+        command: &[String],
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let previous_user = Box::from(connection.username());
+        connection.set_username(target_user);
+
+        let Some((exec, params)) = command.split_first() else {
+            return Self::finish(CommandResult::Exit(0), previous_user, connection);
+        };
+
+        let result =
+            ConcreteCommand::new(connection, Some(exec.as_bytes()), params, channel, session).await;
+
+        Self::finish(result, previous_user, connection)
+    }
+
+    pub async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let result = self.command.stdin(connection, channel, data, session).await;
+        Self::finish(result, self.previous_user, connection)
+    }
+
+    pub fn abort(self, connection: &mut ConnectionState) {
+        self.command.abort(connection);
+        connection.set_username(&self.previous_user);
+    }
+
+    fn finish(
+        result: CommandResult<ConcreteCommand>,
+        previous_user: Box<str>,
+        connection: &mut ConnectionState,
+    ) -> CommandResult<Self> {
+        match result {
+            CommandResult::ReadStdin(cmd) => CommandResult::ReadStdin(Self {
+                command: Box::new(cmd),
+                previous_user,
+            }),
+            CommandResult::Exit(status) => {
+                connection.set_username(&previous_user);
+                CommandResult::Exit(status)
+            }
+            CommandResult::Close(status) => {
+                connection.set_username(&previous_user);
+                CommandResult::Close(status)
+            }
+        }
+    }
+}
+
+/// Progress reading a password typed into a prompt.
+pub enum PasswordInput<'a> {
+    /// The password hasn't been completed yet, more input is needed.
+    Incomplete,
+    /// Enter was pressed, followed by `rest`.
+    Entered(&'a [u8]),
+    /// The prompt was cancelled with ^C.
+    Interrupted,
+}
+
+/// Adds the keys typed in `data` to `password`, handling backspaces as a terminal would.
+pub fn read_password<'a>(password: &mut Vec<u8>, data: &'a [u8]) -> PasswordInput<'a> {
+    for (i, c) in data.iter().copied().enumerate() {
+        match c {
+            b'\r' | b'\n' => return PasswordInput::Entered(&data[i + 1..]),
+            // ^C
+            0x03 => return PasswordInput::Interrupted,
+            // backspace
+            0x7f | 0x08 => {
+                password.pop();
+            }
+            c => password.push(c),
+        }
+    }
+
+    PasswordInput::Incomplete
+}
+
+/// Arguments to `sudo` preceding the command to run.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    user: Option<String>,
+    non_interactive: bool,
+    stdin: bool,
+}
+
+/// Splits `sudo`'s own options from the command it's been asked to run.
+fn parse_args(params: &[String]) -> Result<(Options, &[String]), String> {
+    let mut options = Options::default();
+    let mut i = 0;
+
+    while let Some(param) = params.get(i) {
+        let Some(flags) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+            break;
+        };
+
+        i += 1;
+
+        if flags == "-" {
+            break;
+        }
+
+        if let Some(long) = flags.strip_prefix('-') {
+            let (name, value) = long
+                .split_once('=')
+                .map_or((long, None), |(k, v)| (k, Some(v)));
+
+            match name {
+                "non-interactive" => options.non_interactive = true,
+                "stdin" => options.stdin = true,
+                "user" => {
+                    options.user = value.map(ToString::to_string).or_else(|| {
+                        i += 1;
+                        params.get(i - 1).cloned()
+                    });
+                }
+                "preserve-env" | "login" | "shell" | "set-home" | "background" => {}
+                _ => return Err(format!("sudo: unrecognized option '--{name}'\n{USAGE}")),
+            }
+
+            continue;
+        }
+
+        for (pos, c) in flags.char_indices() {
+            match c {
+                'n' => options.non_interactive = true,
+                'S' => options.stdin = true,
+                'A' | 'b' | 'E' | 'H' | 'i' | 'k' | 'P' | 's' => {}
+                c if TAKES_VALUE.contains(&c) => {
+                    let attached = &flags[pos + c.len_utf8()..];
+
+                    let value = if attached.is_empty() {
+                        i += 1;
+                        params.get(i - 1).cloned().ok_or_else(|| {
+                            format!("sudo: option requires an argument -- '{c}'\n{USAGE}")
+                        })?
+                    } else {
+                        attached.to_string()
+                    };
+
+                    if c == 'u' {
+                        options.user = Some(value);
+                    }
+
+                    break;
+                }
+                c => return Err(format!("sudo: invalid option -- '{c}'\n{USAGE}")),
+            }
+        }
+    }
+
+    Ok((options, &params[i..]))
+}
+
+impl Sudo {
+    fn record_password(connection: &mut ConnectionState, password: &[u8], command: Vec<String>) {
+        let username = Box::from(connection.username());
+
+        connection.push_action(AuditLogAction::SudoPassword(SudoPasswordEvent {
+            username,
+            password: String::from_utf8_lossy(password).into(),
+            command: command.into_boxed_slice(),
+        }));
+    }
+}
+
+#[async_trait]
+impl Command for Sudo {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (options, command) = match parse_args(params) {
+            Ok((_, [])) => {
+                session.stderr(channel, USAGE.to_string().into());
+                return CommandResult::Exit(1);
+            }
+            Ok(v) => v,
+            Err(e) => {
+                session.stderr(channel, e.into());
+                return CommandResult::Exit(1);
+            }
+        };
+
+        let target_user = options.user.unwrap_or_else(|| "root".to_string());
+
+        // root is never asked for its password
+        if connection.username() == "root" {
+            return RunAs::start(&target_user, command, connection, channel, session)
+                .await
+                .map(Self::from);
+        }
+
+        let pty = connection.has_pty(channel);
+
+        if options.non_interactive {
+            session.stderr(channel, "sudo: a password is required\n".into());
+            return CommandResult::Exit(1);
+        } else if !pty && !options.stdin {
+            session.stderr(
+                channel,
+                "sudo: a terminal is required to read the password; either use the -S option to \
+                 read from standard input or configure an askpass helper\n\
+                 sudo: a password is required\n"
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        if connection.config().sudo == SudoMode::Deny {
+            session.data(channel, LECTURE.into());
+        }
+
+        session.data(
+            channel,
+            format!("[sudo] password for {}: ", connection.username()).into(),
+        );
+
+        CommandResult::ReadStdin(Self {
+            state: State::Password {
+                command: command.to_vec(),
+                target_user: target_user.into_boxed_str(),
+                pty,
+                password: Vec::new(),
+            },
+        })
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (command, target_user, pty, mut password) = match self.state {
+            State::Password {
+                command,
+                target_user,
+                pty,
+                password,
+            } => (command, target_user, pty, password),
+            State::Running(cmd) => {
+                return cmd
+                    .stdin(connection, channel, data, session)
+                    .await
+                    .map(Self::from);
+            }
+        };
+
+        let rest = match read_password(&mut password, data) {
+            PasswordInput::Incomplete => {
+                return CommandResult::ReadStdin(Self {
+                    state: State::Password {
+                        command,
+                        target_user,
+                        pty,
+                        password,
+                    },
+                });
+            }
+            PasswordInput::Interrupted => {
+                if pty {
+                    session.data(channel, "\n".into());
+                }
+
+                Self::record_password(connection, &password, command);
+                return CommandResult::Exit(1);
+            }
+            PasswordInput::Entered(rest) => rest,
+        };
+
+        if pty {
+            session.data(channel, "\n".into());
+        }
+
+        Self::record_password(connection, &password, command.clone());
+
+        if connection.config().sudo == SudoMode::Deny {
+            session.stderr(
+                channel,
+                format!(
+                    "{} is not in the sudoers file.  This incident will be reported.\n",
+                    connection.username()
+                )
+                .into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        let result = RunAs::start(&target_user, &command, connection, channel, session).await;
+
+        // anything typed after the password is input for the command
+        match result {
+            CommandResult::ReadStdin(cmd) if !rest.is_empty() => {
+                cmd.stdin(connection, channel, rest, session).await
+            }
+            result => result,
+        }
+        .map(Self::from)
+    }
+
+    fn abort(self, connection: &mut ConnectionState) {
+        match self.state {
+            State::Password {
+                command, password, ..
+            } if !password.is_empty() => {
+                Self::record_password(connection, &password, command);
+            }
+            State::Password { .. } => {}
+            State::Running(cmd) => cmd.abort(connection),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, SudoPasswordEvent};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            sudo::{parse_args, Sudo},
+            Command, CommandResult,
+        },
+        config::{Config, SudoMode},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("whoami", None, (false, false), 0; "command only")]
+    #[test_case("-u admin whoami", Some("admin"), (false, false), 2; "user")]
+    #[test_case("-uadmin -S whoami", Some("admin"), (true, false), 2; "attached user")]
+    #[test_case("--user=admin -n -- whoami", Some("admin"), (false, true), 3; "long")]
+    #[test_case("-E whoami -n", None, (false, false), 1; "command options")]
+    fn parses_args(
+        input: &str,
+        user: Option<&str>,
+        (stdin, non_interactive): (bool, bool),
+        command_start: usize,
+    ) {
+        let input = shlex::split(input).unwrap();
+        let (options, command) = parse_args(&input).unwrap();
+
+        assert_eq!(options.user.as_deref(), user);
+        assert_eq!(options.stdin, stdin);
+        assert_eq!(options.non_interactive, non_interactive);
+        assert_eq!(command, &input[command_start..]);
+    }
+
+    #[test]
+    fn rejects_unknown_option() {
+        let input = shlex::split("-z whoami").unwrap();
+        assert!(parse_args(&input).is_err());
+    }
+
+    #[tokio::test]
+    async fn runs_as_root() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+        state.set_pty(fake_channel_id());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("[sudo] password for ubuntu: "))
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("\n"))
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("root\n"))
+            .returning(|_, _| ());
+
+        let out = Sudo::new(
+            &mut state,
+            ["whoami".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"hunt", &mut session)
+            .await
+            .unwrap_stdin();
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"er3\x7f2\r", &mut session)
+            .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(state.username(), "ubuntu");
+
+        let AuditLogAction::SudoPassword(SudoPasswordEvent {
+            username,
+            password,
+            command,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected sudo password event");
+        };
+
+        assert_eq!(&**username, "ubuntu");
+        assert_eq!(&**password, "hunter2");
+        assert_eq!(&**command, ["whoami"]);
+    }
+
+    #[tokio::test]
+    async fn denied() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+        state.set_config(Config {
+            sudo: SudoMode::Deny,
+            ..Config::default()
+        });
+
+        session.expect_data().times(2).returning(|_, _| ());
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string("ubuntu is not in the sudoers file.  This incident will be reported.\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Sudo::new(
+            &mut state,
+            ["-S".to_string(), "whoami".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"hunter2\n", &mut session)
+            .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+        assert!(matches!(
+            state.audit_log().events[0].action,
+            AuditLogAction::SudoPassword(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn requires_terminal() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_username("ubuntu");
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string("sudo: a terminal is required to read the password; either use the -S option to read from standard input or configure an askpass helper\nsudo: a password is required\n"))
+            .returning(|_, _| ());
+
+        let out = Sudo::new(
+            &mut state,
+            ["whoami".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn root_skips_password() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("root\n"))
+            .returning(|_, _| ());
+
+        let out = Sudo::new(
+            &mut state,
+            ["whoami".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(state.audit_log().events.is_empty());
+    }
+}
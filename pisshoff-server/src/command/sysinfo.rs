@@ -0,0 +1,654 @@
+//! Commands reporting on the machine's resources, all generated from the active system profile.
+
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    profile::{Disk, Profile, ARM_FEATURES, X86_FLAGS},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Formats a size in the unit of the first of `suffixes` the way `df -h` does, rounding up to one
+/// decimal place for values below 10 and to a whole number otherwise.
+#[allow(clippy::cast_precision_loss)]
+pub fn human_size(kib: u64, suffixes: &[&str]) -> String {
+    let mut value = kib as f64;
+    let mut suffix = suffixes[0];
+
+    for next in &suffixes[1..] {
+        if value < 1024.0 {
+            break;
+        }
+
+        value /= 1024.0;
+        suffix = next;
+    }
+
+    if value == 0.0 {
+        "0".to_string()
+    } else if value < 10.0 {
+        format!("{:.1}{suffix}", (value * 10.0).ceil() / 10.0)
+    } else {
+        format!("{:.0}{suffix}", value.ceil())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Df {}
+
+#[async_trait]
+impl Command for Df {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = df(connection.profile(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn df(profile: &Profile, params: &[String]) -> (String, String, u32) {
+    let mut human = false;
+    let mut print_type = false;
+    let mut operands = Vec::new();
+
+    let mut args = super::argparse(params);
+
+    while let Some(param) = args.next() {
+        match param {
+            Arg::Short('h' | 'H') | Arg::Long("human-readable" | "si") => human = true,
+            Arg::Short('T') | Arg::Long("print-type") => print_type = true,
+            Arg::Short('k' | 'a' | 'l' | 'P') | Arg::Long("all" | "local" | "portability") => {}
+            Arg::Short('t' | 'x') | Arg::Long("type" | "exclude-type") => {
+                let _type = args.next();
+            }
+            Arg::Long(v) if v.starts_with("type=") || v.starts_with("exclude-type=") => {}
+            Arg::Operand(v) => operands.push(v),
+            Arg::Short(s) => {
+                return (
+                    String::new(),
+                    format!("df: invalid option -- '{s}'\nTry 'df --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Long(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "df: unrecognized option '--{s}'\nTry 'df --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let mut err = String::new();
+    let mut exit_code = 0;
+
+    let mut disks = Vec::new();
+
+    if operands.is_empty() {
+        disks.extend(profile.disks);
+    }
+
+    for operand in operands {
+        // the disk an absolute path lives on is the one with the longest mount point containing
+        // it, anything else is assumed to be relative to the home directory on the root disk
+        let disk = profile
+            .disks
+            .iter()
+            .filter(|disk| {
+                !operand.starts_with('/')
+                    || operand.strip_prefix(disk.mount).is_some_and(|rest| {
+                        disk.mount == "/" || rest.is_empty() || rest.starts_with('/')
+                    })
+            })
+            .max_by_key(|disk| disk.mount.len());
+
+        if let Some(disk) = disk {
+            disks.push(disk);
+        } else {
+            writeln!(err, "df: {operand}: No such file or directory").unwrap();
+            exit_code = 1;
+        }
+    }
+
+    out.push_str(&df_table(&disks, human, print_type));
+
+    (out, err, exit_code)
+}
+
+fn df_table(disks: &[&Disk], human: bool, print_type: bool) -> String {
+    let mut rows = vec![[
+        "Filesystem".to_string(),
+        "Type".to_string(),
+        if human { "Size" } else { "1K-blocks" }.to_string(),
+        "Used".to_string(),
+        if human { "Avail" } else { "Available" }.to_string(),
+        "Use%".to_string(),
+        "Mounted on".to_string(),
+    ]];
+
+    for disk in disks {
+        // ext file systems keep 5% of the disk back for root
+        let reserved = if disk.fs_type.starts_with("ext") {
+            disk.size_kib / 20
+        } else {
+            0
+        };
+        let available = disk.size_kib.saturating_sub(disk.used_kib + reserved);
+        let percent = (disk.used_kib * 100).div_ceil((disk.used_kib + available).max(1));
+
+        let size = |v: u64| {
+            if human {
+                human_size(v, &["K", "M", "G", "T"])
+            } else {
+                v.to_string()
+            }
+        };
+
+        rows.push([
+            disk.device.to_string(),
+            disk.fs_type.to_string(),
+            size(disk.size_kib),
+            size(disk.used_kib),
+            size(available),
+            format!("{percent}%"),
+            disk.mount.to_string(),
+        ]);
+    }
+
+    let widths: Vec<_> = (0..6)
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+
+    let mut out = String::new();
+
+    for row in rows {
+        write!(out, "{:<w$} ", row[0], w = widths[0]).unwrap();
+
+        if print_type {
+            write!(out, "{:<w$} ", row[1], w = widths[1]).unwrap();
+        }
+
+        writeln!(
+            out,
+            "{:>w2$} {:>w3$} {:>w4$} {:>w5$} {}",
+            row[2],
+            row[3],
+            row[4],
+            row[5],
+            row[6],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+            w5 = widths[5],
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct Free {}
+
+#[async_trait]
+impl Command for Free {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = free(connection.profile(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn free(profile: &Profile, params: &[String]) -> (String, String, u32) {
+    let mut human = false;
+    let mut shift = 0;
+    let mut total = false;
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('h') | Arg::Long("human") => human = true,
+            Arg::Short('k') | Arg::Long("kibi" | "kilo") => shift = 0,
+            Arg::Short('m') | Arg::Long("mebi" | "mega") => shift = 10,
+            Arg::Short('g') | Arg::Long("gibi" | "giga") => shift = 20,
+            Arg::Short('t') | Arg::Long("total") => total = true,
+            Arg::Short('w' | 'l') | Arg::Long("wide" | "lohi") => {}
+            Arg::Short(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "free: invalid option -- '{s}'\n\nUsage:\n free [options]\n\nFor more details see free(1).\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "free: unrecognized option '--{s}'\n\nUsage:\n free [options]\n\nFor more details see free(1).\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Operand(_) => {
+                return (
+                    String::new(),
+                    "Usage:\n free [options]\n\nFor more details see free(1).\n".to_string(),
+                    1,
+                );
+            }
+        }
+    }
+
+    let memory = profile.memory();
+    let buff_cache = memory.buffers + memory.cached;
+    let used = memory.total - memory.free - buff_cache;
+
+    let size = |v: u64| {
+        if human {
+            human_size(v, &["Ki", "Mi", "Gi", "Ti"])
+        } else {
+            (v >> shift).to_string()
+        }
+    };
+
+    let mut out = format!(
+        "{:>15}{:>12}{:>12}{:>12}{:>12}{:>12}\n",
+        "total", "used", "free", "shared", "buff/cache", "available"
+    );
+
+    writeln!(
+        out,
+        "Mem:  {:>9}{:>12}{:>12}{:>12}{:>12}{:>12}",
+        size(memory.total),
+        size(used),
+        size(memory.free),
+        size(memory.shared),
+        size(buff_cache),
+        size(memory.available),
+    )
+    .unwrap();
+
+    writeln!(out, "Swap: {:>9}{:>12}{:>12}", size(0), size(0), size(0)).unwrap();
+
+    if total {
+        writeln!(
+            out,
+            "Total:{:>9}{:>12}{:>12}",
+            size(memory.total),
+            size(used),
+            size(memory.free),
+        )
+        .unwrap();
+    }
+
+    (out, String::new(), 0)
+}
+
+#[derive(Debug, Clone)]
+pub struct Uptime {}
+
+#[async_trait]
+impl Command for Uptime {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let boot_time = connection.boot_time();
+        let (out, err, exit_code) = uptime(boot_time, connection.now(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn uptime(
+    boot_time: OffsetDateTime,
+    now: OffsetDateTime,
+    params: &[String],
+) -> (String, String, u32) {
+    // every option prints something different, so only the first is looked at
+    if let Some(param) = super::argparse(params).next() {
+        match param {
+            Arg::Short('p') | Arg::Long("pretty") => {
+                let uptime = now - boot_time;
+                let mut parts = Vec::new();
+
+                for (value, unit) in [
+                    (uptime.whole_weeks(), "week"),
+                    (uptime.whole_days() % 7, "day"),
+                    (uptime.whole_hours() % 24, "hour"),
+                    (uptime.whole_minutes() % 60, "minute"),
+                ] {
+                    match value {
+                        0 => {}
+                        1 => parts.push(format!("1 {unit}")),
+                        n => parts.push(format!("{n} {unit}s")),
+                    }
+                }
+
+                return (format!("up {}\n", parts.join(", ")), String::new(), 0);
+            }
+            Arg::Short('s') | Arg::Long("since") => {
+                let since = boot_time
+                    .format(format_description!(
+                        "[year]-[month]-[day] [hour]:[minute]:[second]"
+                    ))
+                    .unwrap_or_default();
+
+                return (format!("{since}\n"), String::new(), 0);
+            }
+            Arg::Short(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "uptime: invalid option -- '{s}'\n\nUsage:\n uptime [options]\n\nFor more details see uptime(1).\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "uptime: unrecognized option '--{s}'\n\nUsage:\n uptime [options]\n\nFor more details see uptime(1).\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Operand(_) => {
+                return (
+                    String::new(),
+                    "\nUsage:\n uptime [options]\n\nFor more details see uptime(1).\n".to_string(),
+                    1,
+                );
+            }
+        }
+    }
+
+    (format!("{}\n", summary(boot_time, now)), String::new(), 0)
+}
+
+/// The line printed by `uptime`, also used as the header of `w`.
+pub fn summary(boot_time: OffsetDateTime, now: OffsetDateTime) -> String {
+    let uptime = now - boot_time;
+    let days = uptime.whole_days();
+    let hours = uptime.whole_hours() % 24;
+    let minutes = uptime.whole_minutes() % 60;
+
+    let mut out = format!(
+        " {} up ",
+        now.format(format_description!("[hour]:[minute]:[second]"))
+            .unwrap_or_default()
+    );
+
+    match days {
+        0 => {}
+        1 => out.push_str("1 day, "),
+        days => write!(out, "{days} days, ").unwrap(),
+    }
+
+    write!(
+        out,
+        "{hours:>2}:{minutes:02},  1 user,  load average: 0.00, 0.01, 0.05"
+    )
+    .unwrap();
+
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct Lscpu {}
+
+#[async_trait]
+impl Command for Lscpu {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = lscpu(connection.profile(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn lscpu(profile: &Profile, params: &[String]) -> (String, String, u32) {
+    if let Some(param) = super::argparse(params).next() {
+        match param {
+            Arg::Short(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "lscpu: invalid option -- '{s}'\nTry 'lscpu --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(s) => {
+                return (
+                    String::new(),
+                    format!(
+                        "lscpu: unrecognized option '--{s}'\nTry 'lscpu --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Operand(_) => {
+                return (
+                    String::new(),
+                    "lscpu: bad usage\nTry 'lscpu --help' for more information.\n".to_string(),
+                    1,
+                );
+            }
+        }
+    }
+
+    let arm = profile.machine == "aarch64";
+
+    let mut fields = vec![
+        ("Architecture:", profile.machine.to_string()),
+        ("  CPU op-mode(s):", "32-bit, 64-bit".to_string()),
+    ];
+
+    if !arm {
+        fields.push((
+            "  Address sizes:",
+            "46 bits physical, 48 bits virtual".to_string(),
+        ));
+    }
+
+    fields.extend([
+        ("  Byte Order:", "Little Endian".to_string()),
+        ("CPU(s):", profile.cpus.to_string()),
+        (
+            "  On-line CPU(s) list:",
+            format!("0-{}", profile.cpus.saturating_sub(1)),
+        ),
+        (
+            "Vendor ID:",
+            if arm { "ARM" } else { "GenuineIntel" }.to_string(),
+        ),
+        (
+            "  Model name:",
+            if arm { "Cortex-A72" } else { profile.cpu_model }.to_string(),
+        ),
+        ("    Thread(s) per core:", "1".to_string()),
+        ("    Core(s) per socket:", profile.cpus.to_string()),
+        ("    Socket(s):", "1".to_string()),
+    ]);
+
+    if arm {
+        fields.push(("    BogoMIPS:", "108.00".to_string()));
+        fields.push(("    Flags:", ARM_FEATURES.to_string()));
+    } else {
+        fields.push(("    Flags:", X86_FLAGS.to_string()));
+    }
+
+    let mut out = String::new();
+
+    for (name, value) in fields {
+        writeln!(out, "{name:<32}{value}").unwrap();
+    }
+
+    (out, String::new(), 0)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{
+        command::sysinfo::{df, free, human_size, lscpu, uptime},
+        profile,
+    };
+
+    #[test_case(""; "none")]
+    #[test_case("-h"; "human")]
+    #[test_case("-hT"; "human with type")]
+    #[test_case("-h /etc/hosts /tmp"; "paths")]
+    fn df_snapshot(input: &str) {
+        let (out, err, exit_code) = df(&profile::CENTOS_7, &shlex::split(input).unwrap());
+
+        insta::assert_snapshot!(format!("df {input}").trim(), out);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test_case(""; "none")]
+    #[test_case("-h"; "human")]
+    #[test_case("-mt"; "mebibytes with total")]
+    fn free_snapshot(input: &str) {
+        let (out, err, exit_code) = free(&profile::UBUNTU_2204, &shlex::split(input).unwrap());
+
+        insta::assert_snapshot!(format!("free {input}").trim(), out);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test_case("lscpu x86", &profile::UBUNTU_2204; "x86")]
+    #[test_case("lscpu arm", &profile::RASPBERRY_PI; "arm")]
+    fn lscpu_snapshot(name: &str, profile: &profile::Profile) {
+        let (out, err, exit_code) = lscpu(profile, &[]);
+
+        insta::assert_snapshot!(name, out);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test_case("", " 14:30:12 up 3 days,  5:17,  1 user,  load average: 0.00, 0.01, 0.05\n"; "none")]
+    #[test_case("-p", "up 3 days, 5 hours, 17 minutes\n"; "pretty")]
+    #[test_case("-s", "2023-07-25 09:12:44\n"; "since")]
+    fn uptime_output(input: &str, expected: &str) {
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = uptime(
+            datetime!(2023-07-25 09:12:44 UTC),
+            datetime!(2023-07-28 14:30:12 UTC),
+            &input,
+        );
+
+        assert_eq!(out, expected);
+        assert_eq!(err, "");
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test_case(0, "0")]
+    #[test_case(6_186, "6.1M")]
+    #[test_case(106_858, "105M")]
+    #[test_case(81_106_868, "78G")]
+    fn human(kib: u64, expected: &str) {
+        assert_eq!(human_size(kib, &["K", "M", "G", "T"]), expected);
+    }
+}
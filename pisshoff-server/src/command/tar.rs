@@ -0,0 +1,415 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::macros::format_description;
+
+use crate::{
+    archive::{self, Entry},
+    audit::HoneytokenAccess,
+    command::{chmod::render_mode, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const TRY_HELP: &str = "Try 'tar --help' or 'tar --usage' for more information.\n";
+
+const NOT_RECOVERABLE: &str = "tar: Error is not recoverable: exiting now\n";
+
+const PREVIOUS_ERRORS: &str = "tar: Exiting with failure status due to previous errors\n";
+
+#[derive(Debug, Clone)]
+pub struct Tar {}
+
+#[async_trait]
+impl Command for Tar {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Extract,
+    List,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    mode: Option<Mode>,
+    verbose: bool,
+    file: Option<String>,
+    directory: Option<String>,
+    strip_components: usize,
+    members: Vec<String>,
+}
+
+fn parse_args(params: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut params = params.iter().map(String::as_str).enumerate().peekable();
+
+    while let Some((i, param)) = params.next() {
+        // the first argument can be a bundle of options without a leading dash, ie. `tar xzf`
+        let short = match param.strip_prefix('-') {
+            Some(rest) if !rest.starts_with('-') && !rest.is_empty() => rest,
+            None if i == 0 => param,
+            _ => "",
+        };
+
+        if let Some(long) = param.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let mut value = || {
+                value
+                    .map(ToString::to_string)
+                    .or_else(|| params.next().map(|(_, v)| v.to_string()))
+                    .ok_or_else(|| {
+                        format!("tar: option '--{name}' requires an argument\n{TRY_HELP}")
+                    })
+            };
+
+            match name {
+                "extract" | "get" => options.mode = Some(Mode::Extract),
+                "list" => options.mode = Some(Mode::List),
+                "verbose" => options.verbose = true,
+                "file" => options.file = Some(value()?),
+                "directory" => options.directory = Some(value()?),
+                "strip-components" => {
+                    options.strip_components = value()?
+                        .parse()
+                        .map_err(|_| format!("tar: Invalid number of elements\n{TRY_HELP}"))?;
+                }
+                "gzip"
+                | "gunzip"
+                | "ungzip"
+                | "bzip2"
+                | "xz"
+                | "auto-compress"
+                | "no-same-owner"
+                | "same-owner"
+                | "no-same-permissions"
+                | "same-permissions"
+                | "preserve-permissions"
+                | "overwrite"
+                | "keep-old-files"
+                | "touch"
+                | "warning" => {}
+                _ => return Err(format!("tar: unrecognized option '{param}'\n{TRY_HELP}")),
+            }
+
+            continue;
+        }
+
+        if short.is_empty() {
+            options.members.push(param.to_string());
+            continue;
+        }
+
+        for (pos, c) in short.char_indices() {
+            match c {
+                'x' => options.mode = Some(Mode::Extract),
+                't' => options.mode = Some(Mode::List),
+                'v' => options.verbose = true,
+                // compression is detected automatically when reading archives
+                'z' | 'j' | 'J' | 'a' | 'p' | 'o' | 'k' | 'm' => {}
+                'f' | 'C' => {
+                    let rest = &short[pos + 1..];
+                    let value = if rest.is_empty() {
+                        params.next().map(|(_, v)| v.to_string()).ok_or_else(|| {
+                            format!("tar: option requires an argument -- '{c}'\n{TRY_HELP}")
+                        })?
+                    } else {
+                        rest.to_string()
+                    };
+
+                    if c == 'f' {
+                        options.file = Some(value);
+                    } else {
+                        options.directory = Some(value);
+                    }
+
+                    break;
+                }
+                c => return Err(format!("tar: invalid option -- '{c}'\n{TRY_HELP}")),
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, String, u32) {
+    let options = match parse_args(params) {
+        Ok(v) => v,
+        Err(e) => return (String::new(), e, 2),
+    };
+
+    let Some(mode) = options.mode else {
+        return (
+            String::new(),
+            format!(
+                "tar: You must specify one of the '-Acdtrux', '--delete' or '--test-label' options\n{TRY_HELP}"
+            ),
+            2,
+        );
+    };
+
+    let Some(file) = options.file.as_deref() else {
+        return (
+            String::new(),
+            format!(
+                "tar: Refusing to read archive contents from terminal (missing -f option?)\n{NOT_RECOVERABLE}"
+            ),
+            2,
+        );
+    };
+
+    connection.touch_file(Path::new(file), HoneytokenAccess::Read);
+
+    let data = match connection.file_system().read(Path::new(file)) {
+        Ok(data) => data,
+        Err(e) => {
+            return (
+                String::new(),
+                format!("tar: {file}: Cannot open: {e}\n{NOT_RECOVERABLE}"),
+                2,
+            )
+        }
+    };
+
+    let entries = archive::gunzip(data)
+        .as_deref()
+        .map_or_else(|| archive::untar(data), archive::untar);
+
+    let base = Path::new(options.directory.as_deref().unwrap_or("."));
+
+    let Some(entries) = entries else {
+        // we couldn't make sense of the archive, so pretend it unpacked something believable
+        return (
+            pretend(connection, file, base, mode, options.verbose),
+            String::new(),
+            0,
+        );
+    };
+
+    let mut out = String::new();
+    let mut err = String::new();
+    let mut failed = false;
+    let mut warned_absolute = false;
+
+    for entry in &entries {
+        if !options.members.is_empty() && !options.members.iter().any(|v| is_member(v, entry)) {
+            continue;
+        }
+
+        // leading components are stripped before anything else happens to the entry
+        let path = entry
+            .path
+            .split('/')
+            .filter(|v| !v.is_empty())
+            .skip(options.strip_components)
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if path.is_empty() {
+            continue;
+        }
+
+        match mode {
+            Mode::List if options.verbose => {
+                writeln!(out, "{}", long_listing(entry)).unwrap();
+            }
+            Mode::List => writeln!(out, "{}", entry.path).unwrap(),
+            Mode::Extract => {
+                if entry.path.starts_with('/') && !warned_absolute {
+                    err.push_str("tar: Removing leading `/' from member names\n");
+                    warned_absolute = true;
+                }
+
+                if options.verbose {
+                    writeln!(out, "{}", entry.path).unwrap();
+                }
+
+                let Some(destination) = archive::destination(base, &path) else {
+                    writeln!(err, "tar: {}: Member name contains '..'", entry.path).unwrap();
+                    failed = true;
+                    continue;
+                };
+
+                if let Err(e) = archive::extract(connection, &destination, entry) {
+                    writeln!(err, "tar: {path}: Cannot open: {e}").unwrap();
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    for member in &options.members {
+        if !entries.iter().any(|v| is_member(member, v)) {
+            writeln!(err, "tar: {member}: Not found in archive").unwrap();
+            failed = true;
+        }
+    }
+
+    if failed {
+        err.push_str(PREVIOUS_ERRORS);
+        (out, err, 2)
+    } else {
+        (out, err, 0)
+    }
+}
+
+/// Lists or "extracts" the plausible contents of an archive we couldn't parse, only the
+/// directories are created.
+fn pretend(
+    connection: &mut ConnectionState,
+    file: &str,
+    base: &Path,
+    mode: Mode,
+    verbose: bool,
+) -> String {
+    let mut out = String::new();
+
+    for path in archive::plausible_contents(file) {
+        if mode == Mode::Extract && path.ends_with('/') {
+            let fs = connection.file_system();
+            let dir = fs.pwd().join(base).join(&path);
+            let _res = fs.mkdirall(&dir);
+        }
+
+        if verbose || mode == Mode::List {
+            writeln!(out, "{path}").unwrap();
+        }
+    }
+
+    out
+}
+
+/// Returns true if the member given on the command line refers to the entry, or a directory
+/// containing it.
+fn is_member(member: &str, entry: &Entry) -> bool {
+    let member = member.trim_end_matches('/');
+    let path = entry.path.trim_end_matches('/');
+
+    path == member
+        || path
+            .strip_prefix(member)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Formats an entry as shown by `tar -tv`.
+fn long_listing(entry: &Entry) -> String {
+    let kind = if entry.content.is_some() { '-' } else { 'd' };
+    let owner = format!("{}/{}", entry.owner, entry.group);
+    let size = entry.len().to_string();
+    let width = 19_usize.saturating_sub(owner.len()).max(size.len() + 1);
+    let modified = entry
+        .modified
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap_or_default();
+
+    format!(
+        "{kind}{} {owner}{size:>width$} {modified} {}",
+        render_mode(entry.mode),
+        entry.path
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{
+        archive::{self, test::tar},
+        command::tar::execute,
+        server::ConnectionState,
+    };
+
+    fn state() -> ConnectionState {
+        let mut state = ConnectionState::mock();
+        let fs = state.file_system();
+        fs.write(
+            Path::new("x.tar"),
+            tar("x/run.sh", b"#!/bin/sh\n").into_boxed_slice(),
+        )
+        .unwrap();
+        fs.write(
+            Path::new("x.tar.gz"),
+            archive::gzip(&tar("x/run.sh", b"#!/bin/sh\n")).into_boxed_slice(),
+        )
+        .unwrap();
+        fs.write(Path::new("bot.tgz"), b"garbage".to_vec().into_boxed_slice())
+            .unwrap();
+        state
+    }
+
+    #[test_case("", "", "tar: You must specify one of the '-Acdtrux', '--delete' or '--test-label' options\nTry 'tar --help' or 'tar --usage' for more information.\n", 2; "no mode")]
+    #[test_case("-x", "", "tar: Refusing to read archive contents from terminal (missing -f option?)\ntar: Error is not recoverable: exiting now\n", 2; "no file")]
+    #[test_case("-xq", "", "tar: invalid option -- 'q'\nTry 'tar --help' or 'tar --usage' for more information.\n", 2; "invalid option")]
+    #[test_case("xf missing.tar", "", "tar: missing.tar: Cannot open: No such file or directory\ntar: Error is not recoverable: exiting now\n", 2; "missing archive")]
+    #[test_case("tf x.tar", "x/\nx/run.sh\n", "", 0; "list")]
+    #[test_case("-tvf x.tar", "drwxr-xr-x root/root         0 2023-06-01 12:00 x/\n-rwxr-xr-x root/root        10 2023-06-01 12:00 x/run.sh\n", "", 0; "list verbose")]
+    #[test_case("-xzvf x.tar.gz", "x/\nx/run.sh\n", "", 0; "extract gzip")]
+    #[test_case("xf x.tar x/missing", "", "tar: x/missing: Not found in archive\ntar: Exiting with failure status due to previous errors\n", 2; "missing member")]
+    #[test_case("xvf bot.tgz", "bot/\nbot/README\nbot/config.json\nbot/install.sh\nbot/bot\n", "", 0; "plausible")]
+    fn output(input: &str, expected_output: &str, expected_error: &str, expected_exit_code: u32) {
+        let mut state = state();
+
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = execute(&mut state, &input);
+
+        assert_eq!(out, expected_output);
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test]
+    fn extracts() {
+        let mut state = state();
+
+        let input = shlex::split("-xf x.tar.gz --strip-components=1 -C /tmp").unwrap();
+        assert_eq!(
+            execute(&mut state, &input),
+            (String::new(), String::new(), 0)
+        );
+
+        let fs = state.file_system();
+        assert_eq!(fs.read(Path::new("/tmp/run.sh")).unwrap(), b"#!/bin/sh\n");
+        assert_eq!(fs.metadata(Path::new("/tmp/run.sh")).unwrap().mode, 0o755);
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            insta::assert_debug_snapshot!(state.audit_log());
+        });
+    }
+}
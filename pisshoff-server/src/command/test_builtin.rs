@@ -0,0 +1,392 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    audit::HoneytokenAccess,
+    command::{Command, CommandResult},
+    file_system::{LsError, Tree},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// The `test` builtin, evaluating a conditional expression.
+#[derive(Debug, Clone)]
+pub struct Test {}
+
+#[async_trait]
+impl Command for Test {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (err, exit_code) = execute(connection, "test", params);
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// The `[` builtin, identical to `test` except it requires a closing `]`.
+#[derive(Debug, Clone)]
+pub struct Bracket {}
+
+#[async_trait]
+impl Command for Bracket {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (err, exit_code) = match params.split_last() {
+            Some((last, params)) if last == "]" => execute(connection, "[", params),
+            _ => ("bash: [: missing `]'\n".to_string(), 2),
+        };
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// Evaluates the expression, returning any error alongside the exit code.
+fn execute(connection: &mut ConnectionState, name: &str, params: &[String]) -> (String, u32) {
+    let mut evaluator = Evaluator {
+        connection,
+        args: params,
+        pos: 0,
+    };
+
+    match evaluator.evaluate() {
+        Ok(true) => (String::new(), 0),
+        Ok(false) => (String::new(), 1),
+        Err(e) => (format!("bash: {name}: {e}\n"), 2),
+    }
+}
+
+/// Recursive descent evaluator over the arguments given to `test`, in order of precedence:
+/// `-o`, `-a`, `!`, then parenthesised expressions and primaries.
+struct Evaluator<'a> {
+    connection: &'a mut ConnectionState,
+    args: &'a [String],
+    pos: usize,
+}
+
+impl Evaluator<'_> {
+    fn evaluate(&mut self) -> Result<bool, String> {
+        // an empty expression is false
+        if self.args.is_empty() {
+            return Ok(false);
+        }
+
+        let result = self.or()?;
+
+        match self.peek() {
+            None => Ok(result),
+            Some(_) => Err("too many arguments".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.args.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let v = self.args.get(self.pos).map(String::as_str);
+        self.pos += 1;
+        v
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+
+        while self.peek() == Some("-o") {
+            self.pos += 1;
+            let rhs = self.and()?;
+            result = result || rhs;
+        }
+
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+
+        while self.peek() == Some("-a") {
+            self.pos += 1;
+            let rhs = self.not()?;
+            result = result && rhs;
+        }
+
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // a lone `!` is just a non-empty string
+        if self.peek() == Some("!") && self.pos + 1 < self.args.len() {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let args = self.args;
+
+        let Some(first) = args.get(self.pos).map(String::as_str) else {
+            return Err("argument expected".to_string());
+        };
+        self.pos += 1;
+
+        if first == "(" && self.pos < args.len() {
+            let result = self.or()?;
+
+            return match self.next() {
+                Some(")") => Ok(result),
+                _ => Err("`)' expected".to_string()),
+            };
+        }
+
+        if let (Some(op), Some(rhs)) = (args.get(self.pos), args.get(self.pos + 1)) {
+            if is_binary_operator(op) {
+                self.pos += 2;
+                return binary(first, op, rhs);
+            }
+        }
+
+        if let Some(operand) = args.get(self.pos) {
+            if is_unary_operator(first) {
+                self.pos += 1;
+                return Ok(unary(self.connection, first, operand));
+            }
+        }
+
+        Ok(!first.is_empty())
+    }
+}
+
+fn is_unary_operator(op: &str) -> bool {
+    matches!(
+        op,
+        "-e" | "-f"
+            | "-d"
+            | "-s"
+            | "-r"
+            | "-w"
+            | "-x"
+            | "-z"
+            | "-n"
+            | "-L"
+            | "-h"
+            | "-p"
+            | "-S"
+            | "-b"
+            | "-c"
+            | "-t"
+    )
+}
+
+fn is_binary_operator(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "=="
+            | "!="
+            | "<"
+            | ">"
+            | "-eq"
+            | "-ne"
+            | "-lt"
+            | "-le"
+            | "-gt"
+            | "-ge"
+            | "-nt"
+            | "-ot"
+            | "-ef"
+    )
+}
+
+fn unary(connection: &mut ConnectionState, op: &str, operand: &str) -> bool {
+    if !matches!(op, "-z" | "-n") {
+        connection.touch_file(Path::new(operand), HoneytokenAccess::Stat);
+    }
+
+    let fs = connection.file_system();
+    let file = fs.get(Path::new(operand));
+
+    match op {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        // everything in the fake file system is readable and writable by the user
+        "-e" | "-r" | "-w" => file.is_ok(),
+        "-x" => match fs.metadata(Path::new(operand)) {
+            Ok(metadata) => metadata.mode & 0o111 != 0,
+            Err(e) => matches!(e, LsError::IsADirectory),
+        },
+        "-f" => matches!(file, Ok(Tree::File(..))),
+        "-d" => matches!(file, Ok(Tree::Directory(_))),
+        "-c" => matches!(file, Ok(Tree::Device(..))),
+        "-s" => matches!(file, Ok(tree) if tree.size() > 0),
+        // there are no symlinks, pipes, sockets or terminals in the fake file system
+        _ => false,
+    }
+}
+
+fn binary(lhs: &str, op: &str, rhs: &str) -> Result<bool, String> {
+    let integer = |v: &str| {
+        v.trim()
+            .parse::<i64>()
+            .map_err(|_| format!("{v}: integer expression expected"))
+    };
+
+    Ok(match op {
+        "=" | "==" => lhs == rhs,
+        "!=" => lhs != rhs,
+        "<" => lhs < rhs,
+        ">" => lhs > rhs,
+        "-eq" => integer(lhs)? == integer(rhs)?,
+        "-ne" => integer(lhs)? != integer(rhs)?,
+        "-lt" => integer(lhs)? < integer(rhs)?,
+        "-le" => integer(lhs)? <= integer(rhs)?,
+        "-gt" => integer(lhs)? > integer(rhs)?,
+        "-ge" => integer(lhs)? >= integer(rhs)?,
+        // files in the fake file system have no timestamps or inodes
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            test_builtin::{execute, Bracket},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("", 1; "empty")]
+    #[test_case("abc", 0; "non-empty string")]
+    #[test_case("''", 1; "empty string")]
+    #[test_case("-n", 0; "lone operator")]
+    #[test_case("-n abc", 0; "non-zero length")]
+    #[test_case("-z abc", 1; "zero length")]
+    #[test_case("abc = abc", 0; "string equal")]
+    #[test_case("abc == abd", 1; "string not equal")]
+    #[test_case("abc != abd", 0; "string inequality")]
+    #[test_case("1 -eq 1", 0; "integer equal")]
+    #[test_case("2 -lt 10", 0; "integer less than")]
+    #[test_case("2 -ge 10", 1; "integer greater or equal")]
+    #[test_case("a -eq 1", 2; "not an integer")]
+    #[test_case("! abc", 1; "negated")]
+    #[test_case("!", 0; "lone negation")]
+    #[test_case("abc -a ''", 1; "and")]
+    #[test_case("abc -o ''", 0; "or")]
+    #[test_case("( abc -o '' ) -a def", 0; "parenthesised")]
+    #[test_case("( abc", 2; "unterminated parenthesis")]
+    #[test_case("a b c d", 2; "too many arguments")]
+    #[test_case("-f /etc/passwd", 1; "missing file")]
+    #[test_case("-d /", 0; "root directory")]
+    #[test_case("-f /", 1; "directory is not a file")]
+    #[test_case("-c /dev/null", 0; "character device")]
+    #[test_case("-f /dev/null", 1; "device is not a file")]
+    #[test_case("-s /dev/urandom", 1; "device is empty")]
+    fn exit_code(input: &str, expected_exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (_err, exit_code) = execute(&mut ConnectionState::mock(), "test", &input);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test_case("-f payload", 0; "is file")]
+    #[test_case("-e payload", 0; "exists")]
+    #[test_case("-s payload", 0; "non-empty file")]
+    #[test_case("-d payload", 1; "is not directory")]
+    #[test_case("-s empty", 1; "empty file")]
+    fn uploaded_file(input: &str, expected_exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("payload"), b"#!/bin/sh".to_vec().into())
+            .unwrap();
+        state
+            .file_system()
+            .write(Path::new("empty"), Vec::new().into())
+            .unwrap();
+
+        let input = shlex::split(input).unwrap();
+        let (_err, exit_code) = execute(&mut state, "test", &input);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[tokio::test]
+    async fn missing_closing_bracket() {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(always(), eq_string("bash: [: missing `]'\n"))
+            .returning(|_, _| ());
+
+        let out = Bracket::new(
+            &mut ConnectionState::mock(),
+            ["-n".to_string(), "abc".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(2)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn closing_bracket() {
+        let mut session = MockThrusshSession::default();
+
+        let out = Bracket::new(
+            &mut ConnectionState::mock(),
+            ["-n".to_string(), "abc".to_string(), "]".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+}
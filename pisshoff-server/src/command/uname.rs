@@ -4,6 +4,7 @@ use thrussh::ChannelId;
 
 use crate::{
     command::{Arg, Command, CommandResult},
+    profile::Profile,
     server::{ConnectionState, ThrusshSession},
 };
 
@@ -58,14 +59,21 @@ pub struct Uname {}
 #[async_trait]
 impl Command for Uname {
     async fn new<S: ThrusshSession + Send>(
-        _connection: &mut ConnectionState,
+        connection: &mut ConnectionState,
         params: &[String],
         channel: ChannelId,
         session: &mut S,
     ) -> CommandResult<Self> {
-        let (out, exit_code) = execute(params);
+        let (out, err, exit_code) = execute(params, connection.profile(), &connection.node_name());
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
 
-        session.data(channel, out.into());
         CommandResult::Exit(exit_code)
     }
 
@@ -80,7 +88,7 @@ impl Command for Uname {
     }
 }
 
-pub fn execute(params: &[String]) -> (String, u32) {
+pub fn execute(params: &[String], profile: &Profile, node_name: &str) -> (String, String, u32) {
     let mut to_print = ToPrint::empty();
     let mut filter_unknown = false;
 
@@ -98,10 +106,11 @@ pub fn execute(params: &[String]) -> (String, u32) {
             Arg::Short('p') | Arg::Long("processor") => ToPrint::PROCESSOR,
             Arg::Short('i') | Arg::Long("hardware-platform") => ToPrint::PLATFORM,
             Arg::Short('o') | Arg::Long("operating-system") => ToPrint::OPERATING_SYSTEM,
-            Arg::Long("help") => return (HELP_STRING.to_string(), 0),
-            Arg::Long("version") => return (VERSION_STRING.to_string(), 0),
+            Arg::Long("help") => return (HELP_STRING.to_string(), String::new(), 0),
+            Arg::Long("version") => return (VERSION_STRING.to_string(), String::new(), 0),
             Arg::Operand(operand) => {
                 return (
+                    String::new(),
                     format!(
                     "uname: extra operand '{operand}'\nTry 'uname --help' for more information.\n"
                 ),
@@ -110,6 +119,7 @@ pub fn execute(params: &[String]) -> (String, u32) {
             }
             Arg::Short(s) => {
                 return (
+                    String::new(),
                     format!(
                     "uname: invalid option -- '{s}'\nTry 'uname --help' for more information.\n"
                 ),
@@ -118,6 +128,7 @@ pub fn execute(params: &[String]) -> (String, u32) {
             }
             Arg::Long(s) => {
                 return (
+                    String::new(),
                     format!(
                     "uname: unrecognized option '--{s}'\nTry 'uname --help' for more information.\n"
                 ),
@@ -148,19 +159,19 @@ pub fn execute(params: &[String]) -> (String, u32) {
     }
 
     if to_print.contains(ToPrint::NODE_NAME) {
-        write!("cd5079c0d642");
+        write!(node_name);
     }
 
     if to_print.contains(ToPrint::KERNEL_RELEASE) {
-        write!("5.15.49");
+        write!(profile.kernel_release);
     }
 
     if to_print.contains(ToPrint::KERNEL_VERSION) {
-        write!("#1 SMP PREEMPT Tue Sep 13 07:51:32 UTC 2022");
+        write!(profile.kernel_version);
     }
 
     if to_print.contains(ToPrint::MACHINE) {
-        write!("x86_64");
+        write!(profile.machine);
     }
 
     if to_print.contains(ToPrint::PROCESSOR) && !filter_unknown {
@@ -177,14 +188,14 @@ pub fn execute(params: &[String]) -> (String, u32) {
 
     out.push('\n');
 
-    (out, 0)
+    (out, String::new(), 0)
 }
 
 #[cfg(test)]
 mod test {
     use test_case::test_case;
 
-    use crate::command::uname::execute;
+    use crate::{command::uname::execute, profile, server::NODE_NAME};
 
     #[test_case("", 0; "none")]
     #[test_case("-a", 0; "all")]
@@ -196,9 +207,32 @@ mod test {
     #[test_case("-sn oper", 1; "unknown operand")]
     fn snapshot(input: &str, expected_exit_code: u32) {
         let input_parsed = shlex::split(input).unwrap();
-        let (output, actual_exit_code) = execute(&input_parsed);
+        let (out, err, actual_exit_code) = execute(&input_parsed, &profile::CONTAINER, NODE_NAME);
+
+        // errors are snapshotted in place of the output they replace
+        let output = if actual_exit_code == 0 {
+            assert_eq!(err, "");
+            out
+        } else {
+            assert_eq!(out, "");
+            err
+        };
 
         insta::assert_display_snapshot!(input, output);
         assert_eq!(actual_exit_code, expected_exit_code);
     }
+
+    #[test]
+    fn profile() {
+        let (output, _, _) = execute(
+            &["-a".to_string()],
+            &profile::RASPBERRY_PI,
+            profile::RASPBERRY_PI.hostname.unwrap(),
+        );
+
+        assert_eq!(
+            output,
+            "Linux raspberrypi 6.1.21-v8+ #1642 SMP PREEMPT Mon Apr  3 17:24:16 BST 2023 aarch64 GNU/Linux\n"
+        );
+    }
 }
@@ -0,0 +1,279 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::macros::format_description;
+
+use crate::{
+    archive,
+    audit::HoneytokenAccess,
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "UnZip 6.00 of 20 April 2009, by Debian. Original by Info-ZIP.
+
+Usage: unzip [-Z] [-opts[modifiers]] file[.zip] [list] [-x xlist] [-d exdir]
+  Default action is to extract files in list, except those in xlist, to exdir;
+  file[.zip] may be a wildcard.  -Z => ZipInfo mode (\"unzip -Z\" for usage).
+
+  -p  extract files to pipe, no messages     -l  list files (short format)
+  -f  freshen existing files, create none    -t  test compressed archive data
+  -u  update files, create if necessary      -z  display archive comment only
+  -v  list verbosely/show version info       -T  timestamp archive to latest
+  -x  exclude files that follow (in xlist)   -d  extract files into exdir
+";
+
+#[derive(Debug, Clone)]
+pub struct Unzip {}
+
+#[async_trait]
+impl Command for Unzip {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, err, exit_code) = execute(connection, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        if !err.is_empty() {
+            session.stderr(channel, err.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Options<'a> {
+    list: bool,
+    quiet: bool,
+    directory: Option<&'a str>,
+    operands: Vec<&'a str>,
+}
+
+fn parse_args(params: &[String]) -> Result<Options<'_>, String> {
+    let mut options = Options::default();
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        let Some(flags) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+            options.operands.push(param.as_str());
+            continue;
+        };
+
+        for (pos, c) in flags.char_indices() {
+            match c {
+                'l' => options.list = true,
+                'q' => options.quiet = true,
+                'd' => {
+                    let rest = &flags[pos + 1..];
+                    options.directory = if rest.is_empty() {
+                        params.next().map(String::as_str)
+                    } else {
+                        Some(rest)
+                    };
+                    break;
+                }
+                'o' | 'n' | 'j' | 'a' | 'L' | 'C' | 'X' => {}
+                _ => return Err(format!("{USAGE}\nunzip:  error in command line ({c})\n")),
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, String, u32) {
+    let Options {
+        list,
+        quiet,
+        directory,
+        operands,
+    } = match parse_args(params) {
+        Ok(v) => v,
+        Err(e) => return (String::new(), e, 10),
+    };
+
+    let Some(file) = operands.first() else {
+        return (USAGE.to_string(), String::new(), 0);
+    };
+
+    connection.touch_file(Path::new(file), HoneytokenAccess::Read);
+
+    let Ok(data) = connection.file_system().read(Path::new(file)) else {
+        return (
+            String::new(),
+            format!("unzip:  cannot find or open {file}, {file}.zip or {file}.ZIP.\n"),
+            9,
+        );
+    };
+
+    let mut out = String::new();
+    let mut err = String::new();
+
+    if !quiet {
+        writeln!(out, "Archive:  {file}").unwrap();
+    }
+
+    let Some(entries) = archive::unzip(data) else {
+        // we couldn't make sense of the archive, so pretend it unpacked something believable
+        let base = Path::new(directory.unwrap_or("."));
+
+        for path in archive::plausible_contents(file) {
+            if path.ends_with('/') {
+                let fs = connection.file_system();
+                let dir = fs.pwd().join(base).join(&path);
+                let _res = fs.mkdirall(&dir);
+
+                if !quiet {
+                    writeln!(out, "   creating: {path}").unwrap();
+                }
+            } else if !quiet {
+                writeln!(out, "  inflating: {path}").unwrap();
+            }
+        }
+
+        return (out, err, 0);
+    };
+
+    if list {
+        render_list(&mut out, &entries);
+        return (out, err, 0);
+    }
+
+    let base = Path::new(directory.unwrap_or("."));
+    let mut exit_code = 0;
+
+    for entry in &entries {
+        let Some(destination) = archive::destination(base, &entry.path) else {
+            writeln!(
+                err,
+                "warning:  skipped \"../\" path component(s) in {}",
+                entry.path
+            )
+            .unwrap();
+            exit_code = 1;
+            continue;
+        };
+
+        if !quiet {
+            let action = match &entry.content {
+                None => "   creating",
+                Some(v) if v.is_empty() => " extracting",
+                Some(_) => "  inflating",
+            };
+
+            writeln!(out, "{action}: {}", destination.display()).unwrap();
+        }
+
+        if let Err(e) = archive::extract(connection, &destination, entry) {
+            writeln!(
+                err,
+                "error:  cannot create {}\n        {e}",
+                destination.display()
+            )
+            .unwrap();
+            exit_code = 50;
+        }
+    }
+
+    (out, err, exit_code)
+}
+
+/// Renders the output of `unzip -l`.
+fn render_list(out: &mut String, entries: &[archive::Entry]) {
+    out.push_str("  Length      Date    Time    Name\n");
+    out.push_str("---------  ---------- -----   ----\n");
+
+    let mut total = 0;
+
+    for entry in entries {
+        let modified = entry
+            .modified
+            .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+            .unwrap_or_default();
+
+        writeln!(out, "{:>9}  {modified}   {}", entry.len(), entry.path).unwrap();
+        total += entry.len();
+    }
+
+    let files = if entries.len() == 1 { "file" } else { "files" };
+
+    out.push_str("---------                     -------\n");
+    writeln!(
+        out,
+        "{total:>9}                     {} {files}",
+        entries.len()
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{archive::test::zip, command::unzip::execute, server::ConnectionState};
+
+    fn state() -> ConnectionState {
+        let mut state = ConnectionState::mock();
+        let fs = state.file_system();
+        fs.write(
+            Path::new("x.zip"),
+            zip("run.sh", b"#!/bin/sh\n").into_boxed_slice(),
+        )
+        .unwrap();
+        fs.write(Path::new("bot.zip"), b"garbage".to_vec().into_boxed_slice())
+            .unwrap();
+        state
+    }
+
+    #[test_case("missing.zip", "", "unzip:  cannot find or open missing.zip, missing.zip.zip or missing.zip.ZIP.\n", 9; "missing archive")]
+    #[test_case("-l x.zip", "Archive:  x.zip\n  Length      Date    Time    Name\n---------  ---------- -----   ----\n       10  2023-01-01 12:00   run.sh\n---------                     -------\n       10                     1 file\n", "", 0; "list")]
+    #[test_case("x.zip -d /tmp", "Archive:  x.zip\n  inflating: /tmp/run.sh\n", "", 0; "extract")]
+    #[test_case("-q x.zip", "", "", 0; "quiet")]
+    #[test_case("bot.zip", "Archive:  bot.zip\n   creating: bot/\n  inflating: bot/README\n  inflating: bot/config.json\n  inflating: bot/install.sh\n  inflating: bot/bot\n", "", 0; "plausible")]
+    fn output(input: &str, expected_output: &str, expected_error: &str, expected_exit_code: u32) {
+        let mut state = state();
+
+        let input = shlex::split(input).unwrap();
+        let (out, err, exit_code) = execute(&mut state, &input);
+
+        assert_eq!(out, expected_output);
+        assert_eq!(err, expected_error);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test]
+    fn extracts() {
+        let mut state = state();
+
+        let input = shlex::split("-q x.zip").unwrap();
+        assert_eq!(
+            execute(&mut state, &input),
+            (String::new(), String::new(), 0)
+        );
+
+        assert_eq!(
+            state.file_system().read(Path::new("run.sh")).unwrap(),
+            b"#!/bin/sh\n"
+        );
+    }
+}
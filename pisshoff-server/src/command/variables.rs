@@ -0,0 +1,249 @@
+use std::{borrow::Cow, fmt::Write};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    anti_forensics,
+    audit::AntiForensicsTechnique,
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+pub fn is_valid_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone)]
+pub struct Export {}
+
+#[async_trait]
+impl Command for Export {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let operands: Vec<_> = params.iter().filter(|v| !v.starts_with('-')).collect();
+
+        if operands.is_empty() {
+            let mut variables: Vec<_> = connection.environment().iter().collect();
+            variables.sort();
+
+            let out = variables
+                .into_iter()
+                .fold(String::new(), |mut out, (k, v)| {
+                    let _ = writeln!(
+                        out,
+                        "declare -x {}=\"{}\"",
+                        String::from_utf8_lossy(k),
+                        String::from_utf8_lossy(v),
+                    );
+                    out
+                });
+
+            session.data(channel, out.into());
+            return CommandResult::Exit(0);
+        }
+
+        let mut exit_code = 0;
+        let mut disabled = Vec::new();
+
+        for operand in operands {
+            // exporting a variable without a value only changes whether it's passed on to
+            // children, which isn't tracked
+            let (name, value) = operand.split_once('=').unwrap_or((operand, ""));
+
+            if !is_valid_identifier(name) {
+                session.stderr(
+                    channel,
+                    format!("bash: export: `{operand}': not a valid identifier\n").into(),
+                );
+                exit_code = 1;
+                continue;
+            }
+
+            if !operand.contains('=') {
+                continue;
+            }
+
+            if anti_forensics::disables_history(name.as_bytes(), Some(value.as_bytes())) {
+                disabled.push(operand.clone());
+            }
+
+            connection.set_variable(
+                Cow::Owned(name.as_bytes().to_vec()),
+                Cow::Owned(value.as_bytes().to_vec()),
+            );
+        }
+
+        if !disabled.is_empty() {
+            connection.record_anti_forensics(
+                AntiForensicsTechnique::DisableHistory,
+                disabled.into_boxed_slice(),
+            );
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Unset {}
+
+#[async_trait]
+impl Command for Unset {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut exit_code = 0;
+        let mut disabled = Vec::new();
+
+        for name in params.iter().filter(|v| !v.starts_with('-')) {
+            if !is_valid_identifier(name) {
+                session.stderr(
+                    channel,
+                    format!("bash: unset: `{name}': not a valid identifier\n").into(),
+                );
+                exit_code = 1;
+                continue;
+            }
+
+            if anti_forensics::disables_history(name.as_bytes(), None) {
+                disabled.push(name.clone());
+            }
+
+            connection.unset_variable(name.as_bytes());
+        }
+
+        if !disabled.is_empty() {
+            connection.record_anti_forensics(
+                AntiForensicsTechnique::DisableHistory,
+                disabled.into_boxed_slice(),
+            );
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{
+        audit::{AntiForensicsEvent, AntiForensicsTechnique, AuditLogAction},
+        command::{
+            variables::{Export, Unset},
+            Command, CommandResult,
+        },
+        server::{test::fake_channel_id, ConnectionState, MockThrusshSession},
+    };
+
+    fn disabled(state: &mut ConnectionState) -> Vec<String> {
+        state
+            .audit_log()
+            .events
+            .iter()
+            .filter_map(|v| match &v.action {
+                AuditLogAction::AntiForensics(AntiForensicsEvent {
+                    technique: AntiForensicsTechnique::DisableHistory,
+                    targets,
+                }) => Some(targets.to_vec()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    #[test_case(&["HISTFILE=/dev/null", "HISTSIZE=0"], &["HISTFILE=/dev/null", "HISTSIZE=0"]; "disables history")]
+    #[test_case(&["HISTSIZE=1000"], &[]; "keeps history")]
+    #[tokio::test]
+    async fn export(params: &[&str], expected: &[&str]) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        let params: Vec<_> = params.iter().map(ToString::to_string).collect();
+        let out = Export::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(
+            state
+                .environment()
+                .get(b"HISTSIZE".as_slice())
+                .map(AsRef::as_ref),
+            Some(if expected.is_empty() {
+                &b"1000"[..]
+            } else {
+                b"0"
+            })
+        );
+        assert_eq!(disabled(&mut state), expected);
+    }
+
+    #[tokio::test]
+    async fn export_lists_environment_only() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.init_environment();
+        state.set_last_exit_status(1);
+
+        session
+            .expect_data()
+            .once()
+            .withf(|_, data| {
+                let out = String::from_utf8_lossy(data);
+                out.contains("declare -x USER=\"root\"\n")
+                    && !out.contains("declare -x ?")
+                    && !out.contains("declare -x $")
+            })
+            .returning(|_, _| ());
+
+        let params = ["-p".to_string()];
+        let out = Export::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(state.variable(b"?").as_deref(), Some(b"1".as_slice()));
+        assert!(state.variable(b"$").is_some());
+    }
+
+    #[tokio::test]
+    async fn unset() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.init_environment();
+
+        let params = ["HISTFILE", "PATH"].map(ToString::to_string);
+        let out = Unset::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(!state.environment().contains_key(b"PATH".as_slice()));
+        assert_eq!(disabled(&mut state), ["HISTFILE"]);
+    }
+}
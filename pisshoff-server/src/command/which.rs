@@ -0,0 +1,171 @@
+use std::{fmt::Write, path::PathBuf};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{resolve, Command, CommandResult, Resolved},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Commands that are built into the shell rather than being binaries, so have no path.
+const SHELL_BUILTINS: &[&str] = &["cd", "exit", "export", "history", "unset"];
+
+/// Where the binary for `name` lives, if it can be found.
+fn locate(connection: &mut ConnectionState, name: &str) -> Option<PathBuf> {
+    if SHELL_BUILTINS.contains(&name) {
+        return None;
+    }
+
+    match resolve(connection, name.as_bytes())? {
+        Resolved::File(path) => Some(path),
+        Resolved::Canned(_) | Resolved::Builtin(..) if name.contains('/') => Some(name.into()),
+        Resolved::Canned(_) | Resolved::Builtin(..) => Some(PathBuf::from("/usr/bin").join(name)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Which {}
+
+#[async_trait]
+impl Command for Which {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut out = String::new();
+        let mut exit_code = 0;
+
+        for name in params.iter().filter(|v| !v.starts_with('-')) {
+            match locate(connection, name) {
+                Some(path) => writeln!(out, "{}", path.display()).unwrap(),
+                None => exit_code = 1,
+            }
+        }
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Whereis {}
+
+#[async_trait]
+impl Command for Whereis {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut out = String::new();
+
+        for name in params.iter().filter(|v| !v.starts_with('-')) {
+            match locate(connection, name) {
+                Some(path) => writeln!(out, "{name}: {}", path.display()).unwrap(),
+                None => writeln!(out, "{name}:").unwrap(),
+            }
+        }
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{borrow::Cow, path::Path};
+
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            which::{Whereis, Which},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case(&["ls", "miner"], "/usr/bin/ls\n/opt/bin/miner\n", 0; "found")]
+    #[test_case(&["ls", "cd", "masscan"], "/usr/bin/ls\n", 1; "missing")]
+    #[test_case(&["/bin/uname"], "/bin/uname\n", 0; "path")]
+    #[tokio::test]
+    async fn which(params: &[&str], expected: &'static str, expected_exit_code: u32) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/opt/bin")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("/opt/bin/miner"), Box::default())
+            .unwrap();
+        state.set_variable(Cow::Borrowed(b"PATH"), Cow::Borrowed(b"/usr/bin:/opt/bin"));
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let params: Vec<_> = params.iter().map(ToString::to_string).collect();
+        let out = Which::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(
+            matches!(out, CommandResult::Exit(v) if v == expected_exit_code),
+            "{out:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn whereis() {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("uname: /usr/bin/uname\nmasscan:\n"))
+            .returning(|_, _| ());
+
+        let out = Whereis::new(
+            &mut ConnectionState::mock(),
+            &["uname".to_string(), "masscan".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+}
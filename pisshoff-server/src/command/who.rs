@@ -0,0 +1,427 @@
+//! Login accounting commands. The history is generated from what we know about the client's
+//! address, so returning attackers can find their previous visits in `last`, alongside a few
+//! sessions from a fake administrator. Other attackers' addresses are never shown.
+
+use std::{cmp::Reverse, fmt::Write};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, Duration, OffsetDateTime};
+
+use crate::{
+    command::{sysinfo, Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+    state::AttackerProfile,
+};
+
+/// Number of logins from the fake administrator shown in `last`.
+const ADMIN_LOGINS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Login {
+    user: Box<str>,
+    tty: &'static str,
+    from: Box<str>,
+    start: OffsetDateTime,
+    /// When the session ended, or `None` if it's still ongoing.
+    end: Option<OffsetDateTime>,
+}
+
+impl Login {
+    /// The client's own session.
+    fn current(connection: &mut ConnectionState) -> Self {
+        let user = Box::from(connection.username());
+        let audit_log = connection.audit_log();
+
+        Self {
+            user,
+            tty: "pts/0",
+            from: audit_log
+                .peer_address
+                .map(|v| v.ip().to_string().into_boxed_str())
+                .unwrap_or_default(),
+            start: audit_log.ts,
+            end: None,
+        }
+    }
+}
+
+/// Builds the login history, most recent first, consisting of the current session, the client's
+/// last visit if they've been here before and some logins by an administrator since boot. Anything
+/// random is seeded from the attacker's profile so the history is the same each time it's viewed.
+fn history(
+    current: Login,
+    attacker: Option<&AttackerProfile>,
+    boot_time: OffsetDateTime,
+) -> Vec<Login> {
+    let rng = fastrand::Rng::with_seed(attacker.map_or(0, |v| v.seed));
+    let mut logins = Vec::with_capacity(ADMIN_LOGINS + 2);
+
+    if let Some(attacker) = attacker.filter(|v| v.connections > 0) {
+        let end = attacker.last_seen;
+        let start = (end - Duration::seconds(rng.i64(60..1800))).max(attacker.first_seen);
+
+        logins.push(Login {
+            user: attacker
+                .credentials
+                .last()
+                .map_or_else(|| current.user.clone(), |v| v.username.clone()),
+            tty: "pts/0",
+            from: current.from.clone(),
+            start,
+            end: Some(end),
+        });
+    }
+
+    let admin_address = format!("10.0.{}.{}", rng.u8(0..16), rng.u8(2..255)).into_boxed_str();
+    let window = (current.start - boot_time).whole_seconds().max(1);
+
+    for _ in 0..ADMIN_LOGINS {
+        let start = boot_time + Duration::seconds(rng.i64(0..window));
+
+        logins.push(Login {
+            user: Box::from("root"),
+            tty: "pts/0",
+            from: admin_address.clone(),
+            start,
+            end: Some((start + Duration::seconds(rng.i64(120..5400))).min(current.start)),
+        });
+    }
+
+    logins.push(current);
+    logins.sort_by_key(|v| Reverse(v.start));
+    logins
+}
+
+fn last(
+    history: &[Login],
+    boot_time: OffsetDateTime,
+    kernel_release: &str,
+    limit: Option<usize>,
+) -> String {
+    let short = format_description!(
+        "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]"
+    );
+
+    let mut out = String::new();
+
+    for login in history.iter().take(limit.unwrap_or(usize::MAX)) {
+        write!(
+            out,
+            "{:<8} {:<12} {:<16} {}",
+            login.user,
+            login.tty,
+            login.from,
+            login.start.format(short).unwrap_or_default(),
+        )
+        .unwrap();
+
+        match login.end {
+            Some(end) => {
+                let duration = end - login.start;
+                let days = duration.whole_days();
+                let hours = duration.whole_hours() % 24;
+                let minutes = duration.whole_minutes() % 60;
+
+                write!(
+                    out,
+                    " - {}  (",
+                    end.format(format_description!("[hour]:[minute]"))
+                        .unwrap_or_default()
+                )
+                .unwrap();
+
+                if days > 0 {
+                    write!(out, "{days}+").unwrap();
+                }
+
+                writeln!(out, "{hours:02}:{minutes:02})").unwrap();
+            }
+            None => out.push_str("   still logged in\n"),
+        }
+    }
+
+    if history.len() < limit.unwrap_or(usize::MAX) {
+        writeln!(
+            out,
+            "{:<8} {:<12} {:<16} {}   still running",
+            "reboot",
+            "system boot",
+            kernel_release,
+            boot_time.format(short).unwrap_or_default(),
+        )
+        .unwrap();
+    }
+
+    let begins = history
+        .iter()
+        .map(|v| v.start)
+        .chain(std::iter::once(boot_time))
+        .min()
+        .unwrap_or(boot_time);
+
+    writeln!(
+        out,
+        "\nwtmp begins {}",
+        begins
+            .format(format_description!(
+                "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]:[second] [year]"
+            ))
+            .unwrap_or_default()
+    )
+    .unwrap();
+
+    out
+}
+
+fn who(current: &Login) -> String {
+    format!(
+        "{:<8} {:<12} {} ({})\n",
+        current.user,
+        current.tty,
+        current
+            .start
+            .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+            .unwrap_or_default(),
+        current.from,
+    )
+}
+
+fn w(current: &Login, boot_time: OffsetDateTime, now: OffsetDateTime, header: bool) -> String {
+    let mut out = String::new();
+
+    if header {
+        writeln!(out, "{}", sysinfo::summary(boot_time, now)).unwrap();
+        out.push_str("USER     TTY      FROM             LOGIN@   IDLE   JCPU   PCPU WHAT\n");
+    }
+
+    writeln!(
+        out,
+        "{:<8} {:<8} {:<16} {}    0.00s  0.01s  0.00s w",
+        current.user,
+        current.tty,
+        current.from,
+        current
+            .start
+            .format(format_description!("[hour]:[minute]"))
+            .unwrap_or_default(),
+    )
+    .unwrap();
+
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct Last {}
+
+#[async_trait]
+impl Command for Last {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut limit = None;
+        let mut args = super::argparse(params);
+
+        while let Some(arg) = args.next() {
+            match arg {
+                Arg::Short('n') | Arg::Long("limit") => {
+                    if let Some(Arg::Operand(n)) = args.next() {
+                        limit = n.parse().ok();
+                    }
+                }
+                Arg::Long(v) if v.starts_with("limit=") => {
+                    limit = v.trim_start_matches("limit=").parse().ok();
+                }
+                _ => {}
+            }
+        }
+
+        let boot_time = connection.boot_time();
+        let current = Login::current(connection);
+        let history = history(current, connection.attacker(), boot_time);
+
+        session.data(
+            channel,
+            last(
+                &history,
+                boot_time,
+                connection.profile().kernel_release,
+                limit,
+            )
+            .into(),
+        );
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Who {}
+
+#[async_trait]
+impl Command for Who {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        _params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        session.data(channel, who(&Login::current(connection)).into());
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct W {}
+
+#[async_trait]
+impl Command for W {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let header =
+            !super::argparse(params).any(|v| matches!(v, Arg::Short('h') | Arg::Long("no-header")));
+
+        let boot_time = connection.boot_time();
+        let current = Login::current(connection);
+
+        session.data(
+            channel,
+            w(&current, boot_time, connection.now(), header).into(),
+        );
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{HashMap, VecDeque},
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use time::{macros::datetime, Duration};
+
+    use crate::{
+        command::who::{history, last, w, who, Login},
+        state::{AttackerProfile, Credential},
+    };
+
+    fn current() -> Login {
+        Login {
+            user: Box::from("root"),
+            tty: "pts/0",
+            from: Box::from("203.0.113.5"),
+            start: datetime!(2023-08-10 20:46:09 UTC),
+            end: None,
+        }
+    }
+
+    fn attacker(connections: u64) -> AttackerProfile {
+        AttackerProfile {
+            address: IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5)),
+            first_seen: datetime!(2023-08-08 11:02:54 UTC),
+            last_seen: datetime!(2023-08-09 23:31:12 UTC),
+            connections,
+            credentials: vec![Credential {
+                username: Box::from("admin"),
+                password: Box::from("admin"),
+            }],
+            failed_logins: HashMap::new(),
+            commands: VecDeque::new(),
+            seed: 1234,
+        }
+    }
+
+    #[test]
+    fn returning_attacker() {
+        let boot_time = datetime!(2023-07-28 09:12:44 UTC);
+        let history = history(current(), Some(&attacker(2)), boot_time);
+
+        assert_eq!(history.len(), 5);
+        assert_eq!(history[0], current());
+        assert_eq!(&*history[1].user, "admin");
+        assert_eq!(&*history[1].from, "203.0.113.5");
+        assert_eq!(history[1].end, Some(attacker(2).last_seen));
+
+        insta::assert_snapshot!(last(&history, boot_time, "5.15.49", None));
+    }
+
+    #[test]
+    fn consistent_history() {
+        let boot_time = datetime!(2023-07-28 09:12:44 UTC);
+
+        assert_eq!(
+            history(current(), Some(&attacker(2)), boot_time),
+            history(current(), Some(&attacker(2)), boot_time)
+        );
+        assert_eq!(history(current(), Some(&attacker(0)), boot_time).len(), 4);
+    }
+
+    #[test]
+    fn limit() {
+        let boot_time = datetime!(2023-07-28 09:12:44 UTC);
+        let history = history(current(), None, boot_time);
+
+        assert_eq!(
+            last(&history, boot_time, "5.15.49", Some(2))
+                .lines()
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn who_output() {
+        assert_eq!(
+            who(&current()),
+            "root     pts/0        2023-08-10 20:46 (203.0.113.5)\n"
+        );
+    }
+
+    #[test]
+    fn w_output() {
+        let now = datetime!(2023-08-10 20:50:01 UTC);
+
+        insta::assert_snapshot!(w(&current(), now - Duration::hours(303), now, true));
+        assert_eq!(
+            w(&current(), now, now, false),
+            "root     pts/0    203.0.113.5      20:46    0.00s  0.01s  0.00s w\n"
+        );
+    }
+}
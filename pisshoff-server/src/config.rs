@@ -1,15 +1,20 @@
-use std::{io::ErrorKind, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, io::ErrorKind, net::SocketAddr, path::PathBuf, sync::Arc};
 
 use clap::Parser;
-use serde::{de::DeserializeOwned, Deserialize};
+use pisshoff_types::audit::{AuditLogAction, GeoLocation};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use strum::VariantNames;
+use tracing::warn;
+
+use crate::profile::{self, Profile};
 
 /// Parser for command line arguments, these arguments can also be passed via capitalised env vars
 /// of the same name.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    #[arg(short, long, env, value_parser = load_config::<Config>)]
-    pub config: Arc<Config>,
+    #[arg(short, long = "config", env = "CONFIG", value_parser = ConfigFile::load)]
+    pub config_file: ConfigFile,
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 }
@@ -25,34 +30,450 @@ impl Args {
     }
 }
 
+/// The config along with the path it was loaded from, so it can be re-read when the server is
+/// sent a SIGHUP.
+#[derive(Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub config: Arc<Config>,
+}
+
+impl ConfigFile {
+    fn load(path: &str) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            path: PathBuf::from(path),
+            config: Arc::new(load_config(path)?),
+        })
+    }
+
+    /// Re-reads the config file, returning the settings that should now be used. See
+    /// [`Config::reload`].
+    pub fn reload(&self, current: &Config) -> Result<Config, std::io::Error> {
+        let path = self.path.to_str().ok_or(ErrorKind::InvalidInput)?;
+        Ok(current.reload(load_config(path)?))
+    }
+}
+
 #[derive(Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
-    /// Address for the server to listen on.
-    #[serde(default = "Config::default_listen_address")]
-    pub listen_address: SocketAddr,
+    /// Addresses for the server to listen on, given in the config file as either a single
+    /// address or a list of them.
+    #[serde(
+        default = "Config::default_listen_address",
+        deserialize_with = "one_or_many"
+    )]
+    pub listen_address: Vec<SocketAddr>,
     /// The probability that an authentication attempt will succeed, once a given password
     /// has been accepted once - it will be accepted for the rest of the lifetime of the
     /// instance.
     #[serde(default = "Config::default_access_probability")]
     pub access_probability: f64,
+    /// Number of times a client must fail to log in as a user from the same address before any
+    /// password for that user can be accepted, as a weak password is usually only found part way
+    /// through a brute force.
+    #[serde(default)]
+    pub min_failed_logins: u64,
+    /// How long rejections of each authentication method are held back for.
+    #[serde(default)]
+    pub rejection_delay: RejectionDelay,
+    /// Banner sent to clients before they authenticate, such as a legal notice. Templated once at
+    /// startup, so only `{{hostname}}`, `{{date}}` and random ranges are available.
+    #[serde(default)]
+    pub auth_banner: Option<String>,
+    /// Message of the day printed when the client starts an interactive shell, templated for each
+    /// connection.
+    #[serde(default)]
+    pub motd: Option<String>,
+    /// Prompts shown to clients authenticating with keyboard-interactive, all sent in a single
+    /// round. The answer to the first is checked as the password.
+    #[serde(default = "Config::default_keyboard_interactive_prompts")]
+    pub keyboard_interactive_prompts: Vec<String>,
     /// Path of the file to write audit logs to.
     #[serde(default = "Config::default_audit_output_file")]
     pub audit_output_file: PathBuf,
+    /// Path of an additional file to write audit logs to in the format used by the Linux audit
+    /// daemon, for testing detection rules written against `auditd` logs.
+    #[serde(default)]
+    pub auditd_output_file: Option<PathBuf>,
+    /// Rolls the audit logs over once they grow too large or old, for deployments that aren't
+    /// running logrotate.
+    #[serde(default)]
+    pub audit_rotation: Option<AuditRotation>,
+    /// Compresses the audit log as it's written, which doesn't apply to the auditd log.
+    #[serde(default)]
+    pub audit_compression: LogCompression,
+    /// Unix socket to send audit logs to, such as the one the exporter listens on.
+    #[serde(default)]
+    pub audit_socket: Option<AuditSocket>,
+    /// Elasticsearch or OpenSearch cluster to bulk index audit events into.
+    #[serde(default)]
+    pub elasticsearch: Option<Elasticsearch>,
+    /// OTLP gRPC endpoint to export connections to as traces, ie. `http://localhost:4317`.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// Address to serve counters of the commands clients have run on, in the Prometheus text
+    /// format.
+    #[serde(default)]
+    pub metrics_listen_address: Option<SocketAddr>,
+    /// Address to serve liveness and readiness checks on over HTTP.
+    #[serde(default)]
+    pub health_listen_address: Option<SocketAddr>,
+    /// Streams audit events over HTTP as they're recorded.
+    #[serde(default)]
+    pub live_tail: Option<LiveTail>,
+    /// The most audit logs that can be waiting to be written before the server reports itself as
+    /// not ready.
+    #[serde(default = "Config::default_max_audit_backlog")]
+    pub max_audit_backlog: usize,
+    /// Number of seconds between each summary of the commands run written to the audit log, or
+    /// `None` to not write summaries.
+    #[serde(default)]
+    pub command_summary_interval: Option<u64>,
     /// The server ID string sent at the beginning of the SSH connection.
     #[serde(default = "Config::default_server_id")]
     pub server_id: String,
+    /// The maximum number of iterations a single `for` or `while` loop in the shell may run
+    /// before it is terminated.
+    #[serde(default = "Config::default_max_loop_iterations")]
+    pub max_loop_iterations: usize,
+    /// The maximum number of loop iterations a script may run in total, across every loop within
+    /// it and any scripts it runs, before all of its loops are terminated.
+    #[serde(default = "Config::default_max_script_loop_iterations")]
+    pub max_script_loop_iterations: usize,
+    /// The maximum number of bytes loops within a single script may write to the client, any
+    /// further output is discarded and the loops are terminated.
+    #[serde(default = "Config::default_max_loop_output")]
+    pub max_loop_output: usize,
+    /// How deeply command substitutions and compound statements may be nested within a single
+    /// command or script before it's rejected as a syntax error.
+    #[serde(default = "Config::default_max_parse_depth")]
+    pub max_parse_depth: usize,
+    /// The most words, quoted strings, expansions and redirections a single command or script may
+    /// contain before it's rejected as a syntax error.
+    #[serde(default = "Config::default_max_parse_tokens")]
+    pub max_parse_tokens: usize,
+    /// The largest script in bytes that will be run when executed from the fake file system or
+    /// passed to `sh`, anything bigger fails to execute.
+    #[serde(default = "Config::default_max_script_size")]
+    pub max_script_size: usize,
+    /// The maximum size in bytes of a single file uploaded over SCP or SFTP, larger uploads are
+    /// refused as if the disk were full.
+    #[serde(default = "Config::default_max_file_upload")]
+    pub max_file_upload: u64,
+    /// The maximum number of bytes a single connection may upload over SCP or SFTP in total.
+    #[serde(default = "Config::default_max_connection_upload")]
+    pub max_connection_upload: u64,
+    /// The most bytes of file content a single connection's fake file system may hold, writes
+    /// beyond this fail with `No space left on device`.
+    #[serde(default = "Config::default_max_file_system_size")]
+    pub max_file_system_size: u64,
+    /// The most files and directories a single connection's fake file system may hold.
+    #[serde(default = "Config::default_max_file_system_inodes")]
+    pub max_file_system_inodes: u64,
+    /// The most bytes of file content held by every connection's fake file system combined.
+    #[serde(default = "Config::default_max_total_file_system_size")]
+    pub max_total_file_system_size: u64,
+    /// The most files and directories held by every connection's fake file system combined.
+    #[serde(default = "Config::default_max_total_file_system_inodes")]
+    pub max_total_file_system_inodes: u64,
+    /// The most bytes a single connection may hold in buffers the client controls the size of,
+    /// such as SCP and SFTP transfers that are yet to complete. Transfers that would go over it
+    /// are refused.
+    #[serde(default = "Config::default_max_connection_memory")]
+    pub max_connection_memory: usize,
+    /// The most bytes held in such buffers by every connection combined.
+    #[serde(default = "Config::default_max_total_connection_memory")]
+    pub max_total_connection_memory: usize,
+    /// The number of bytes of each written file kept in the audit log, anything beyond this is
+    /// discarded and a SHA-256 of the full content is logged instead.
+    #[serde(default = "Config::default_max_audited_content")]
+    pub max_audited_content: usize,
+    /// Directory to store each unique written file in, named after the SHA-256 of its content
+    /// alongside a `<sha256>.jsonl` file recording every time it was uploaded. When set, only
+    /// the hash of each file is written to the audit log.
+    #[serde(default)]
+    pub payload_directory: Option<PathBuf>,
+    /// File to persist what's known about each attacker to, so returning attackers are shown the
+    /// same fake system after the server is restarted.
+    #[serde(default)]
+    pub attacker_profiles: Option<PathBuf>,
+    /// The most bytes per second accepted from a client across all of its channels, anything
+    /// sent faster than this is held back until the client is within the limit. 0 disables the
+    /// limit.
+    #[serde(default = "Config::default_max_data_rate")]
+    pub max_data_rate: u64,
+    /// The most commands per second a client may run, including those run by scripts and loops,
+    /// further commands are delayed until the client is within the limit. 0 disables the limit.
+    #[serde(default = "Config::default_max_command_rate")]
+    pub max_command_rate: u64,
+    /// The most bytes a single connection may send, including SSH framing, before it's
+    /// disconnected.
+    #[serde(default = "Config::default_max_connection_bytes_in")]
+    pub max_connection_bytes_in: u64,
+    /// The most bytes sent to a single connection, including SSH framing, before it's
+    /// disconnected.
+    #[serde(default = "Config::default_max_connection_bytes_out")]
+    pub max_connection_bytes_out: u64,
+    /// The most channels a single connection may open before it's disconnected.
+    #[serde(default = "Config::default_max_connection_channels")]
+    pub max_connection_channels: u64,
+    /// The most times a single connection may try to authenticate before it's disconnected.
+    #[serde(default = "Config::default_max_connection_auth_attempts")]
+    pub max_connection_auth_attempts: u64,
+    /// The most commands a single connection may run, including those run by scripts and loops,
+    /// before it's disconnected.
+    #[serde(default = "Config::default_max_connection_commands")]
+    pub max_connection_commands: u64,
+    /// Controls how much of each connection is captured and written to the audit log.
+    #[serde(default)]
+    pub logging_preset: LoggingPreset,
+    /// The fraction of each type of event to keep, keyed by the event's `type`, for thinning out
+    /// high volume events on busy sensors. Events not listed are always kept, and those dropped
+    /// are counted in the connection's summary.
+    #[serde(default, deserialize_with = "sample_rates")]
+    pub event_sample_rates: HashMap<String, f64>,
+    /// How sensitive fields are written to the audit log, for operators that can't store them as
+    /// they were captured.
+    #[serde(default)]
+    pub redaction: Redaction,
+    /// The userland the emulated commands imitate, affecting help text and manual pages.
+    #[serde(default)]
+    pub personality: Personality,
+    /// The machine the server describes itself as, keeping the hostname, kernel, hardware and
+    /// installed packages reported by each command consistent.
+    #[serde(default)]
+    pub profile: SystemProfile,
+    /// Seed for anything made up for each connection, such as process IDs and how long the
+    /// machine has been up. By default it's taken from the connection ID so it differs between
+    /// connections, fixing it makes every connection see the same values.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// How requests from the client to forward a connection through the server are handled.
+    #[serde(default)]
+    pub direct_tcpip: DirectTcpIpMode,
+    /// How `sudo` responds once the client has entered a password.
+    #[serde(default)]
+    pub sudo: SudoMode,
+    /// The speed in kB/s packages appear to download at, package managers take as long to finish
+    /// as the download would have (up to 30 seconds) before showing their output.
+    #[serde(default = "Config::default_package_download_speed")]
+    pub package_download_speed: u64,
+    /// What happens when the client runs a binary it's uploaded.
+    #[serde(default)]
+    pub binary_execution: BinaryExecutionMode,
+    /// Commands that print canned output, templated each time they're run, taking precedence over
+    /// the built in commands so their output can be overridden.
+    #[serde(default)]
+    pub commands: HashMap<String, CannedCommand>,
+    /// Fake files that raise an alert whenever the client reads, writes or stats them.
+    #[serde(default)]
+    pub honeytokens: Vec<Honeytoken>,
+    /// URL each honeytoken and canary credential alert is `POST`ed to as JSON.
+    #[serde(default)]
+    pub honeytoken_webhook: Option<String>,
+    /// Credentials that are always accepted, raising an alert the first time each connection
+    /// uses one.
+    #[serde(default)]
+    pub canary_credentials: Vec<CanaryCredential>,
+    /// Database of the country and autonomous system each address is registered to, in the
+    /// format published by iptoasn.com, looked up as each client connects.
+    #[serde(default)]
+    pub geoip_database: Option<PathBuf>,
+    /// Rules deciding how logins go for clients connecting from particular countries or
+    /// autonomous systems, the first rule matching the client is used.
+    #[serde(default)]
+    pub auth_rules: Vec<AuthRule>,
+    /// The fake cloud instance metadata service `curl` and `wget` answer requests to
+    /// `169.254.169.254` with.
+    #[serde(default)]
+    pub cloud_metadata: CloudMetadata,
+    /// Reports the addresses of clients to AbuseIPDB and DShield in batches.
+    #[serde(default)]
+    pub abuse_reports: Option<AbuseReports>,
+    /// User to switch to once the listening sockets are bound, so the server only needs to be
+    /// started as root to bind to a privileged port.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Group to switch to alongside `user`, defaulting to the user's primary group.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_address: Self::default_listen_address(),
+            access_probability: Self::default_access_probability(),
+            min_failed_logins: 0,
+            rejection_delay: RejectionDelay::default(),
+            auth_banner: None,
+            motd: None,
+            keyboard_interactive_prompts: Self::default_keyboard_interactive_prompts(),
+            audit_output_file: Self::default_audit_output_file(),
+            auditd_output_file: None,
+            audit_rotation: None,
+            audit_compression: LogCompression::default(),
+            audit_socket: None,
+            elasticsearch: None,
+            otlp_endpoint: None,
+            metrics_listen_address: None,
+            health_listen_address: None,
+            live_tail: None,
+            max_audit_backlog: Self::default_max_audit_backlog(),
+            command_summary_interval: None,
+            server_id: Self::default_server_id(),
+            max_loop_iterations: Self::default_max_loop_iterations(),
+            max_script_loop_iterations: Self::default_max_script_loop_iterations(),
+            max_loop_output: Self::default_max_loop_output(),
+            max_parse_depth: Self::default_max_parse_depth(),
+            max_parse_tokens: Self::default_max_parse_tokens(),
+            max_script_size: Self::default_max_script_size(),
+            max_file_upload: Self::default_max_file_upload(),
+            max_connection_upload: Self::default_max_connection_upload(),
+            max_file_system_size: Self::default_max_file_system_size(),
+            max_file_system_inodes: Self::default_max_file_system_inodes(),
+            max_total_file_system_size: Self::default_max_total_file_system_size(),
+            max_total_file_system_inodes: Self::default_max_total_file_system_inodes(),
+            max_connection_memory: Self::default_max_connection_memory(),
+            max_total_connection_memory: Self::default_max_total_connection_memory(),
+            max_audited_content: Self::default_max_audited_content(),
+            max_data_rate: Self::default_max_data_rate(),
+            max_command_rate: Self::default_max_command_rate(),
+            max_connection_bytes_in: Self::default_max_connection_bytes_in(),
+            max_connection_bytes_out: Self::default_max_connection_bytes_out(),
+            max_connection_channels: Self::default_max_connection_channels(),
+            max_connection_auth_attempts: Self::default_max_connection_auth_attempts(),
+            max_connection_commands: Self::default_max_connection_commands(),
+            payload_directory: None,
+            attacker_profiles: None,
+            logging_preset: LoggingPreset::default(),
+            event_sample_rates: HashMap::new(),
+            redaction: Redaction::default(),
+            personality: Personality::default(),
+            profile: SystemProfile::default(),
+            seed: None,
+            direct_tcpip: DirectTcpIpMode::default(),
+            sudo: SudoMode::default(),
+            package_download_speed: Self::default_package_download_speed(),
+            binary_execution: BinaryExecutionMode::default(),
+            commands: HashMap::new(),
+            honeytokens: Vec::new(),
+            honeytoken_webhook: None,
+            canary_credentials: Vec::new(),
+            geoip_database: None,
+            auth_rules: Vec::new(),
+            cloud_metadata: CloudMetadata::default(),
+            abuse_reports: None,
+            user: None,
+            group: None,
+        }
+    }
 }
 
 impl Config {
-    fn default_listen_address() -> SocketAddr {
-        "0.0.0.0:22".parse().unwrap()
+    /// Takes the settings from `new` that are safe to change while the server is running, keeping
+    /// the ones that are only read at startup as they were. Settings are captured when each
+    /// connection is opened, so changes only apply to new connections.
+    #[must_use]
+    pub fn reload(&self, mut new: Config) -> Config {
+        let startup_only = [
+            ("listen-address", self.listen_address != new.listen_address),
+            (
+                "audit-output-file",
+                self.audit_output_file != new.audit_output_file,
+            ),
+            (
+                "auditd-output-file",
+                self.auditd_output_file != new.auditd_output_file,
+            ),
+            ("audit-rotation", self.audit_rotation != new.audit_rotation),
+            (
+                "audit-compression",
+                self.audit_compression != new.audit_compression,
+            ),
+            ("audit-socket", self.audit_socket != new.audit_socket),
+            ("elasticsearch", self.elasticsearch != new.elasticsearch),
+            ("otlp-endpoint", self.otlp_endpoint != new.otlp_endpoint),
+            (
+                "metrics-listen-address",
+                self.metrics_listen_address != new.metrics_listen_address,
+            ),
+            (
+                "health-listen-address",
+                self.health_listen_address != new.health_listen_address,
+            ),
+            ("live-tail", self.live_tail != new.live_tail),
+            (
+                "command-summary-interval",
+                self.command_summary_interval != new.command_summary_interval,
+            ),
+            ("auth-banner", self.auth_banner != new.auth_banner),
+            ("server-id", self.server_id != new.server_id),
+            ("logging-preset", self.logging_preset != new.logging_preset),
+            (
+                "event-sample-rates",
+                self.event_sample_rates != new.event_sample_rates,
+            ),
+            ("redaction", self.redaction != new.redaction),
+            (
+                "payload-directory",
+                self.payload_directory != new.payload_directory,
+            ),
+            (
+                "attacker-profiles",
+                self.attacker_profiles != new.attacker_profiles,
+            ),
+            ("geoip-database", self.geoip_database != new.geoip_database),
+            ("abuse-reports", self.abuse_reports != new.abuse_reports),
+            ("user", self.user != new.user),
+            ("group", self.group != new.group),
+        ];
+
+        for (name, changed) in startup_only {
+            if changed {
+                warn!("Ignoring change to {name}, the server must be restarted to apply it");
+            }
+        }
+
+        new.listen_address.clone_from(&self.listen_address);
+        new.audit_output_file.clone_from(&self.audit_output_file);
+        new.auditd_output_file.clone_from(&self.auditd_output_file);
+        new.audit_rotation.clone_from(&self.audit_rotation);
+        new.audit_compression = self.audit_compression;
+        new.audit_socket.clone_from(&self.audit_socket);
+        new.elasticsearch.clone_from(&self.elasticsearch);
+        new.otlp_endpoint.clone_from(&self.otlp_endpoint);
+        new.metrics_listen_address = self.metrics_listen_address;
+        new.health_listen_address = self.health_listen_address;
+        new.live_tail.clone_from(&self.live_tail);
+        new.command_summary_interval = self.command_summary_interval;
+        new.auth_banner.clone_from(&self.auth_banner);
+        new.server_id.clone_from(&self.server_id);
+        new.logging_preset = self.logging_preset;
+        new.event_sample_rates.clone_from(&self.event_sample_rates);
+        new.redaction.clone_from(&self.redaction);
+        new.payload_directory.clone_from(&self.payload_directory);
+        new.attacker_profiles.clone_from(&self.attacker_profiles);
+        new.geoip_database.clone_from(&self.geoip_database);
+        new.abuse_reports.clone_from(&self.abuse_reports);
+        new.user.clone_from(&self.user);
+        new.group.clone_from(&self.group);
+        new
+    }
+
+    fn default_listen_address() -> Vec<SocketAddr> {
+        vec!["0.0.0.0:22".parse().unwrap()]
     }
 
     fn default_access_probability() -> f64 {
         0.2
     }
 
+    fn default_keyboard_interactive_prompts() -> Vec<String> {
+        vec!["Password: ".to_string()]
+    }
+
     fn default_audit_output_file() -> PathBuf {
         "/var/log/pisshoff/audit.log".parse().unwrap()
     }
@@ -60,12 +481,1033 @@ impl Config {
     fn default_server_id() -> String {
         "SSH-2.0-OpenSSH_9.3".to_string()
     }
+
+    fn default_max_loop_iterations() -> usize {
+        10_000
+    }
+
+    fn default_max_script_loop_iterations() -> usize {
+        100_000
+    }
+
+    fn default_max_loop_output() -> usize {
+        1024 * 1024
+    }
+
+    fn default_max_parse_depth() -> usize {
+        64
+    }
+
+    fn default_max_parse_tokens() -> usize {
+        64 * 1024
+    }
+
+    fn default_max_script_size() -> usize {
+        256 * 1024
+    }
+
+    fn default_max_file_upload() -> u64 {
+        64 * 1024 * 1024
+    }
+
+    fn default_max_connection_upload() -> u64 {
+        256 * 1024 * 1024
+    }
+
+    fn default_max_file_system_size() -> u64 {
+        256 * 1024 * 1024
+    }
+
+    fn default_max_file_system_inodes() -> u64 {
+        16 * 1024
+    }
+
+    fn default_max_total_file_system_size() -> u64 {
+        2 * 1024 * 1024 * 1024
+    }
+
+    fn default_max_total_file_system_inodes() -> u64 {
+        1024 * 1024
+    }
+
+    fn default_max_connection_memory() -> usize {
+        128 * 1024 * 1024
+    }
+
+    fn default_max_total_connection_memory() -> usize {
+        1024 * 1024 * 1024
+    }
+
+    fn default_max_audited_content() -> usize {
+        64 * 1024
+    }
+
+    fn default_max_data_rate() -> u64 {
+        1024 * 1024
+    }
+
+    fn default_max_command_rate() -> u64 {
+        100
+    }
+
+    fn default_max_connection_bytes_in() -> u64 {
+        512 * 1024 * 1024
+    }
+
+    fn default_max_connection_bytes_out() -> u64 {
+        256 * 1024 * 1024
+    }
+
+    fn default_max_connection_channels() -> u64 {
+        256
+    }
+
+    fn default_max_connection_auth_attempts() -> u64 {
+        100
+    }
+
+    fn default_max_connection_commands() -> u64 {
+        10_000
+    }
+
+    fn default_max_audit_backlog() -> usize {
+        10_000
+    }
+
+    fn default_package_download_speed() -> u64 {
+        2048
+    }
+}
+
+/// When the audit logs are rotated, and how many old logs are kept around. Rotated logs are named
+/// after the log with a number appended, ie. `audit.jsonl.1.gz` being the most recent.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditRotation {
+    /// Size in bytes a log may grow to before it's rotated.
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// Number of seconds after a log was started that it's rotated, checked as events are
+    /// written.
+    #[serde(default)]
+    pub max_age: Option<u64>,
+    /// Number of rotated logs to keep, the oldest is deleted once there are more than this.
+    #[serde(default = "AuditRotation::default_keep")]
+    pub keep: usize,
+    /// How rotated logs are compressed, logs already compressed as they're written are kept
+    /// as they are.
+    #[serde(default = "AuditRotation::default_compression")]
+    pub compression: LogCompression,
+}
+
+impl AuditRotation {
+    fn default_keep() -> usize {
+        7
+    }
+
+    fn default_compression() -> LogCompression {
+        LogCompression::Gzip
+    }
+}
+
+/// Milliseconds to wait before rejecting an authentication attempt, for each method.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RejectionDelay {
+    #[serde(default = "RejectionDelay::default_delay")]
+    pub password: u64,
+    #[serde(default = "RejectionDelay::default_delay")]
+    pub keyboard_interactive: u64,
+    #[serde(default = "RejectionDelay::default_delay")]
+    pub publickey: u64,
+    #[serde(default = "RejectionDelay::default_delay")]
+    pub none: u64,
+    /// Up to this many milliseconds are added to each delay at random, so rejections don't all
+    /// take exactly as long.
+    #[serde(default)]
+    pub jitter: u64,
+}
+
+impl RejectionDelay {
+    fn default_delay() -> u64 {
+        1000
+    }
+}
+
+impl Default for RejectionDelay {
+    fn default() -> Self {
+        Self {
+            password: Self::default_delay(),
+            keyboard_interactive: Self::default_delay(),
+            publickey: Self::default_delay(),
+            none: Self::default_delay(),
+            jitter: 0,
+        }
+    }
+}
+
+/// A Unix socket audit logs are sent to as newline-delimited JSON, reconnecting with a backoff
+/// whenever the connection is lost.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditSocket {
+    pub path: PathBuf,
+    /// Whether audit logs are still written to `audit-output-file` as well as the socket.
+    #[serde(default = "AuditSocket::default_write_file")]
+    pub write_file: bool,
+    /// The most audit logs held on to while the socket is unavailable, the newest are dropped
+    /// once this is reached.
+    #[serde(default = "AuditSocket::default_max_buffered")]
+    pub max_buffered: usize,
+}
+
+impl AuditSocket {
+    fn default_write_file() -> bool {
+        true
+    }
+
+    fn default_max_buffered() -> usize {
+        10_000
+    }
+}
+
+/// An HTTP listener streaming each audit event as it's recorded, over Server-Sent Events or a
+/// WebSocket.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct LiveTail {
+    pub listen_address: SocketAddr,
+    /// Token clients have to give as a bearer token or a `token` query parameter, anyone who can
+    /// reach the listener can watch if this isn't set.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Origin of a dashboard allowed to read the stream from a browser, ie.
+    /// `https://dashboard.example.com`, or `*` for any.
+    #[serde(default)]
+    pub allow_origin: Option<String>,
+    /// The most clients watching at once, any more are turned away.
+    #[serde(default = "LiveTail::default_max_clients")]
+    pub max_clients: usize,
+}
+
+impl LiveTail {
+    fn default_max_clients() -> usize {
+        16
+    }
+}
+
+/// An Elasticsearch or OpenSearch cluster each audit event is indexed into as its own document, in
+/// daily indices named after `index`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Elasticsearch {
+    /// URL of the cluster.
+    pub url: String,
+    /// PEM file of CA certificates to trust for the cluster, on top of the usual public ones, for
+    /// clusters using a self-signed certificate.
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+    #[serde(default)]
+    pub distribution: SearchDistribution,
+    /// Prefix of the daily indices, also used to name the index template and lifecycle policy.
+    #[serde(default = "Elasticsearch::default_index")]
+    pub index: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Encoded API key, used in place of `username` and `password`.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Whether to create or update the index template and lifecycle policy on startup, rather
+    /// than leaving them to be managed elsewhere.
+    #[serde(default = "Elasticsearch::default_manage_template")]
+    pub manage_template: bool,
+    /// Days before an index is deleted by the lifecycle policy, or 0 to keep them forever.
+    #[serde(default = "Elasticsearch::default_retention_days")]
+    pub retention_days: u32,
+    /// The most events sent in a single bulk request.
+    #[serde(default = "Elasticsearch::default_batch_size")]
+    pub batch_size: usize,
+    /// Seconds to wait for a batch to fill before sending what there is.
+    #[serde(default = "Elasticsearch::default_flush_interval")]
+    pub flush_interval: u64,
+    /// The most events held on to while the cluster is unavailable, the newest are dropped once
+    /// this is reached.
+    #[serde(default = "Elasticsearch::default_max_buffered")]
+    pub max_buffered: usize,
+}
+
+impl Elasticsearch {
+    fn default_index() -> String {
+        "pisshoff".to_string()
+    }
+
+    fn default_manage_template() -> bool {
+        true
+    }
+
+    fn default_retention_days() -> u32 {
+        90
+    }
+
+    fn default_batch_size() -> usize {
+        500
+    }
+
+    fn default_flush_interval() -> u64 {
+        5
+    }
+
+    fn default_max_buffered() -> usize {
+        100_000
+    }
+}
+
+/// Which search engine the cluster runs, as they manage the lifecycle of indices differently.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchDistribution {
+    /// Deletes old indices with an ILM policy.
+    #[default]
+    Elasticsearch,
+    /// Deletes old indices with an ISM policy.
+    Opensearch,
+}
+
+/// Batches of client addresses reported to abuse databases, along with what each got up to.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AbuseReports {
+    /// Seconds between each batch of reports.
+    #[serde(default = "AbuseReports::default_interval")]
+    pub interval: u64,
+    /// The most addresses reported to AbuseIPDB in each batch, any more are held on to until the
+    /// next.
+    #[serde(default = "AbuseReports::default_max_reports")]
+    pub max_reports: usize,
+    /// Seconds before an address that's been reported can be reported again.
+    #[serde(default = "AbuseReports::default_cooldown")]
+    pub cooldown: u64,
+    /// Logs each report rather than sending it.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub abuseipdb: Option<AbuseIpDb>,
+    #[serde(default)]
+    pub dshield: Option<DShield>,
+}
+
+impl AbuseReports {
+    fn default_interval() -> u64 {
+        3600
+    }
+
+    fn default_max_reports() -> usize {
+        // AbuseIPDB's free plan allows 1000 reports a day
+        40
+    }
+
+    fn default_cooldown() -> u64 {
+        86400
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AbuseIpDb {
+    /// URL of the report endpoint.
+    #[serde(default = "AbuseIpDb::default_url")]
+    pub url: String,
+    pub api_key: String,
+}
+
+impl AbuseIpDb {
+    fn default_url() -> String {
+        "https://api.abuseipdb.com/api/v2/report".to_string()
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DShield {
+    /// URL of the SSH log endpoint.
+    #[serde(default = "DShield::default_url")]
+    pub url: String,
+    pub user_id: String,
+    /// The base64-encoded key from the DShield account page.
+    pub api_key: String,
 }
 
-fn load_config<T: DeserializeOwned>(path: &str) -> Result<Arc<T>, std::io::Error> {
+impl DShield {
+    fn default_url() -> String {
+        "https://secure.dshield.org/api/file/sshlog".to_string()
+    }
+}
+
+/// How audit logs are compressed.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl LogCompression {
+    /// The extension appended to the name of rotated logs.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => ".gz",
+            Self::Zstd => ".zst",
+        }
+    }
+}
+
+/// How each kind of sensitive field is written to the audit log, everything being written as it
+/// was captured by default.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Redaction {
+    /// Passwords from login attempts, keyboard-interactive responses, `sudo`, `su` and SOCKS
+    /// proxy requests.
+    #[serde(default)]
+    pub passwords: RedactionMode,
+    /// The content of uploaded files, including partial uploads.
+    #[serde(default)]
+    pub file_contents: RedactionMode,
+    /// Prepended to passwords before they're hashed, so the hashes can't be looked up in tables
+    /// of common passwords' hashes.
+    #[serde(default)]
+    pub salt: String,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RedactionMode {
+    /// Written as captured.
+    #[default]
+    Raw,
+    /// Replaced by its SHA-256, so values can still be told apart and matched against known
+    /// ones. Passwords are salted, file contents are left out in favour of the file's unsalted
+    /// hash so it still matches the payload store.
+    Hash,
+    /// Left out entirely.
+    Redact,
+}
+
+/// Named sets of events to capture and write to the audit log, so operators don't need to
+/// configure each type of event individually.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoggingPreset {
+    /// Only authentication attempts (including `sudo` and `su` passwords) and their outcomes,
+    /// clients disconnecting before authenticating, executed commands and scripts, input the
+    /// shell couldn't parse, uploaded files, including partial uploads, and alerts such as
+    /// honeytokens being touched, canary credentials being used, attempts to cover tracks,
+    /// suspected miners, probes trying to detect the honeypot, container and cluster management
+    /// commands or requests to the cloud metadata service.
+    Quiet,
+    /// Every event other than raw transcripts.
+    #[default]
+    Standard,
+    /// Every event, including a raw transcript of all data sent by the client.
+    Forensic,
+}
+
+impl LoggingPreset {
+    /// Returns true if the given event should be written to the audit log.
+    pub fn includes(self, action: &AuditLogAction) -> bool {
+        match self {
+            Self::Quiet => matches!(
+                action,
+                AuditLogAction::LoginAttempt(_)
+                    | AuditLogAction::AuthCheck(_)
+                    | AuditLogAction::PreAuthDisconnect(_)
+                    | AuditLogAction::ExecCommand(_)
+                    | AuditLogAction::ScriptExecution(_)
+                    | AuditLogAction::ShellParseError(_)
+                    | AuditLogAction::BinaryExecution(_)
+                    | AuditLogAction::SudoPassword(_)
+                    | AuditLogAction::SwitchUser(_)
+                    | AuditLogAction::WriteFile(_)
+                    | AuditLogAction::PartialUpload(_)
+                    | AuditLogAction::HoneytokenAccessed(_)
+                    | AuditLogAction::CanaryCredentialUsed(_)
+                    | AuditLogAction::AntiForensics(_)
+                    | AuditLogAction::SuspectedMiner(_)
+                    | AuditLogAction::DetectionAttempt(_)
+                    | AuditLogAction::ContainerCommand(_)
+                    | AuditLogAction::MetadataAccess(_)
+            ),
+            Self::Standard => !matches!(action, AuditLogAction::Transcript(_)),
+            Self::Forensic => true,
+        }
+    }
+
+    /// Returns true if all data sent by the client should be captured verbatim.
+    pub fn captures_transcripts(self) -> bool {
+        self == Self::Forensic
+    }
+}
+
+/// The style of userland presented to the client.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Personality {
+    /// A typical distribution with GNU coreutils, util-linux and manual pages installed.
+    #[default]
+    Gnu,
+    /// An embedded device with a single busybox binary providing the userland.
+    Busybox,
+}
+
+/// The built in descriptions of the machine presented to the client.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SystemProfile {
+    /// A Debian container on a dedicated server, with a hostname that differs for each attacker.
+    #[default]
+    Container,
+    /// A small Ubuntu 22.04 cloud VPS.
+    #[serde(rename = "ubuntu-22.04")]
+    Ubuntu2204,
+    /// A `CentOS` 7 database server.
+    #[serde(rename = "centos-7")]
+    Centos7,
+    /// A Raspberry Pi 4 running Raspberry Pi OS.
+    RaspberryPi,
+}
+
+impl SystemProfile {
+    pub fn profile(self) -> &'static Profile {
+        match self {
+            Self::Container => &profile::CONTAINER,
+            Self::Ubuntu2204 => &profile::UBUNTU_2204,
+            Self::Centos7 => &profile::CENTOS_7,
+            Self::RaspberryPi => &profile::RASPBERRY_PI,
+        }
+    }
+}
+
+/// How `direct-tcpip` channels, used by clients for port forwarding and SOCKS proxying, are
+/// handled. No connections are ever actually made to the requested host.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirectTcpIpMode {
+    /// The channel is refused.
+    #[default]
+    Reject,
+    /// The channel is accepted and everything the client sends through it is recorded, but
+    /// nothing is ever sent back.
+    Record,
+    /// As with `Record`, but the remote host is imitated for a few well-known ports, such as
+    /// sending an SMTP greeting or responding to HTTP requests, and clients expecting a SOCKS
+    /// proxy have their handshake answered.
+    Emulate,
+}
+
+/// How `sudo` responds to a password being entered, which it always asks for unless the client
+/// is logged in as root.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SudoMode {
+    /// Any password is accepted and the command is run as root.
+    #[default]
+    Allow,
+    /// The usual lecture is given before the password prompt, and the user is then told they
+    /// aren't in the sudoers file.
+    Deny,
+}
+
+/// How running an ELF binary from the fake file system appears to the client. Binaries installed
+/// by a package manager always succeed silently.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BinaryExecutionMode {
+    /// The binary crashes immediately.
+    #[default]
+    Segfault,
+    /// The binary is built for a different architecture to the one the server claims to be.
+    ExecFormatError,
+    /// The binary prints the given output and exits.
+    Canned(CannedCommand),
+}
+
+/// A command defined in the config file, which prints its output templated with the details of the
+/// connection every time it's run.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CannedCommand {
+    #[serde(default)]
+    pub output: String,
+    #[serde(default)]
+    pub exit_code: u32,
+}
+
+/// A fake file written to every connection's file system, which raises an alert when touched.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Honeytoken {
+    /// Where the file is written, relative paths are within the user's home directory.
+    pub path: PathBuf,
+    /// Content of the file, with `{token}` replaced by a token unique to the connection and
+    /// `{connection-id}` by the connection's ID.
+    #[serde(default)]
+    pub content: String,
+}
+
+/// A username and password seeded somewhere they might be leaked from, such as a credential list
+/// handed to a canary service, so any client logging in with them must have learnt them there.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CanaryCredential {
+    pub username: String,
+    pub password: String,
+    /// Where the credential was seeded, included in the alert so leaks can be told apart.
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Decides how logins go for clients connecting from any of the given countries or autonomous
+/// systems, as looked up in the `geoip-database`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuthRule {
+    /// Recorded in the audit log against each authentication check the rule matches.
+    pub id: String,
+    /// ISO 3166-1 alpha-2 country codes, ie. `NL`.
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub asns: Vec<u32>,
+    pub action: AuthRuleAction,
+    /// Milliseconds to hold back each rejection for when the action is `tarpit`.
+    #[serde(default = "AuthRule::default_tarpit_delay")]
+    pub tarpit_delay: u64,
+}
+
+impl AuthRule {
+    /// Whether the rule applies to clients connecting from `location`.
+    pub fn matches(&self, location: &GeoLocation) -> bool {
+        self.asns.contains(&location.asn)
+            || self
+                .countries
+                .iter()
+                .any(|v| v.eq_ignore_ascii_case(&location.country))
+    }
+
+    fn default_tarpit_delay() -> u64 {
+        30_000
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthRuleAction {
+    /// Password and keyboard-interactive logins always succeed.
+    Accept,
+    /// Every login is rejected.
+    Reject,
+    /// Every login is rejected, each after `tarpit-delay` to slow the client down.
+    Tarpit,
+}
+
+/// The identity and credentials handed out by the fake AWS instance metadata service. Each value
+/// has `{token}` and `{connection-id}` filled in as honeytokens do, so credentials turning up
+/// elsewhere can be traced back to the connection they were taken by.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CloudMetadata {
+    /// The IAM role the instance runs as.
+    #[serde(default = "CloudMetadata::default_role")]
+    pub role: String,
+    #[serde(default = "CloudMetadata::default_access_key_id")]
+    pub access_key_id: String,
+    #[serde(default = "CloudMetadata::default_secret_access_key")]
+    pub secret_access_key: String,
+    #[serde(default = "CloudMetadata::default_session_token")]
+    pub session_token: String,
+    #[serde(default = "CloudMetadata::default_account_id")]
+    pub account_id: String,
+    #[serde(default = "CloudMetadata::default_region")]
+    pub region: String,
+    #[serde(default = "CloudMetadata::default_instance_id")]
+    pub instance_id: String,
+}
+
+impl Default for CloudMetadata {
+    fn default() -> Self {
+        Self {
+            role: Self::default_role(),
+            access_key_id: Self::default_access_key_id(),
+            secret_access_key: Self::default_secret_access_key(),
+            session_token: Self::default_session_token(),
+            account_id: Self::default_account_id(),
+            region: Self::default_region(),
+            instance_id: Self::default_instance_id(),
+        }
+    }
+}
+
+impl CloudMetadata {
+    fn default_role() -> String {
+        "ec2-app-server".to_string()
+    }
+
+    fn default_access_key_id() -> String {
+        "ASIA{token}".to_string()
+    }
+
+    fn default_secret_access_key() -> String {
+        "q8Nf2rLx7TWbVZ1dKc0sPj5hYgE3mUoR{token}".to_string()
+    }
+
+    fn default_session_token() -> String {
+        "IQoJb3JpZ2luX2VjEJr//////////wEaCXVzLWVhc3QtMSJHMEUCIQDx{token}".to_string()
+    }
+
+    fn default_account_id() -> String {
+        "417352908126".to_string()
+    }
+
+    fn default_region() -> String {
+        "us-east-1".to_string()
+    }
+
+    fn default_instance_id() -> String {
+        "i-0a3f9c27d5e81b46f".to_string()
+    }
+}
+
+/// Deserializes either a single value or a list of them.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(v) => vec![v],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+/// Deserializes the rate each type of event is sampled at, rejecting types of event that don't
+/// exist and rates that aren't a fraction.
+fn sample_rates<'de, D>(deserializer: D) -> Result<HashMap<String, f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rates = HashMap::<String, f64>::deserialize(deserializer)?;
+
+    for (event, rate) in &rates {
+        if !AuditLogAction::VARIANTS.contains(&event.as_str()) {
+            return Err(serde::de::Error::unknown_variant(
+                event,
+                AuditLogAction::VARIANTS,
+            ));
+        }
+
+        if !(0.0..=1.0).contains(rate) {
+            return Err(serde::de::Error::custom(format!(
+                "sample rate for {event} must be between 0 and 1, got {rate}"
+            )));
+        }
+    }
+
+    Ok(rates)
+}
+
+fn load_config<T: DeserializeOwned>(path: &str) -> Result<T, std::io::Error> {
     let file = std::fs::read_to_string(path)?;
 
-    toml::from_str(&file)
-        .map(Arc::new)
-        .map_err(|e| std::io::Error::new(ErrorKind::Other, e))
+    toml::from_str(&file).map_err(std::io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, path::Path};
+
+    use pisshoff_types::audit::{AuditLogAction, TranscriptEvent};
+    use test_case::test_case;
+
+    use crate::config::{
+        AbuseIpDb, AuthRule, AuthRuleAction, BinaryExecutionMode, CannedCommand, Config,
+        DirectTcpIpMode, Honeytoken, LoggingPreset, Personality, SearchDistribution, SystemProfile,
+    };
+
+    #[test_case("", LoggingPreset::Standard; "default")]
+    #[test_case("logging-preset = \"quiet\"", LoggingPreset::Quiet; "quiet")]
+    #[test_case("logging-preset = \"forensic\"", LoggingPreset::Forensic; "forensic")]
+    fn parses_preset(input: &str, expected: LoggingPreset) {
+        let config: Config = toml::from_str(input).unwrap();
+        assert_eq!(config.logging_preset, expected);
+    }
+
+    #[test_case("", Personality::Gnu; "default")]
+    #[test_case("personality = \"busybox\"", Personality::Busybox; "busybox")]
+    fn parses_personality(input: &str, expected: Personality) {
+        let config: Config = toml::from_str(input).unwrap();
+        assert_eq!(config.personality, expected);
+    }
+
+    #[test_case("", SystemProfile::Container; "default")]
+    #[test_case("profile = \"ubuntu-22.04\"", SystemProfile::Ubuntu2204; "ubuntu")]
+    #[test_case("profile = \"centos-7\"", SystemProfile::Centos7; "centos")]
+    #[test_case("profile = \"raspberry-pi\"", SystemProfile::RaspberryPi; "raspberry pi")]
+    fn parses_profile(input: &str, expected: SystemProfile) {
+        let config: Config = toml::from_str(input).unwrap();
+        assert_eq!(config.profile, expected);
+    }
+
+    #[test]
+    fn parses_honeytokens() {
+        let config: Config = toml::from_str(
+            "honeytoken-webhook = \"http://127.0.0.1:8080/alert\"\n\
+             [[honeytokens]]\n\
+             path = \".aws/credentials\"\n\
+             content = \"AKIA{token}\"\n\
+             [[honeytokens]]\n\
+             path = \"/root/wallet.dat\"",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.honeytoken_webhook.as_deref(),
+            Some("http://127.0.0.1:8080/alert")
+        );
+        assert_eq!(
+            config.honeytokens,
+            [
+                Honeytoken {
+                    path: ".aws/credentials".into(),
+                    content: "AKIA{token}".to_string(),
+                },
+                Honeytoken {
+                    path: "/root/wallet.dat".into(),
+                    content: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test_case("event-sample-rates = { window-adjusted = 0, transcript = 0.5 }", None; "valid")]
+    #[test_case("event-sample-rates = { keystroke = 0.5 }", Some("unknown variant `keystroke`"); "unknown event")]
+    #[test_case("event-sample-rates = { transcript = 2 }", Some("must be between 0 and 1"); "out of range")]
+    fn parses_sample_rates(input: &str, error: Option<&str>) {
+        match (toml::from_str::<Config>(input), error) {
+            (Ok(config), None) => {
+                assert_eq!(config.event_sample_rates.len(), 2);
+                assert!(config.event_sample_rates["window-adjusted"].abs() < f64::EPSILON);
+            }
+            (Err(e), Some(error)) => assert!(e.to_string().contains(error), "{e}"),
+            (res, _) => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parses_abuse_reports() {
+        let config: Config = toml::from_str(
+            "[abuse-reports]\n\
+             dry-run = true\n\
+             [abuse-reports.abuseipdb]\n\
+             url = \"http://127.0.0.1:8080/api/v2/report\"\n\
+             api-key = \"key\"",
+        )
+        .unwrap();
+
+        let abuse_reports = config.abuse_reports.unwrap();
+        assert_eq!(abuse_reports.interval, 3600);
+        assert_eq!(abuse_reports.max_reports, 40);
+        assert!(abuse_reports.dry_run);
+        assert_eq!(
+            abuse_reports.abuseipdb,
+            Some(AbuseIpDb {
+                url: "http://127.0.0.1:8080/api/v2/report".to_string(),
+                api_key: "key".to_string(),
+            })
+        );
+        assert_eq!(abuse_reports.dshield, None);
+    }
+
+    #[test]
+    fn defaults_abuse_report_urls() {
+        let config: Config = toml::from_str(
+            "[abuse-reports]\n\
+             [abuse-reports.abuseipdb]\n\
+             api-key = \"key\"\n\
+             [abuse-reports.dshield]\n\
+             user-id = \"1234\"\n\
+             api-key = \"a2V5\"",
+        )
+        .unwrap();
+
+        let abuse_reports = config.abuse_reports.unwrap();
+        assert_eq!(
+            abuse_reports.abuseipdb.unwrap().url,
+            "https://api.abuseipdb.com/api/v2/report"
+        );
+        assert_eq!(
+            abuse_reports.dshield.unwrap().url,
+            "https://secure.dshield.org/api/file/sshlog"
+        );
+    }
+
+    #[test]
+    fn parses_elasticsearch() {
+        let config: Config = toml::from_str(
+            "[elasticsearch]\n\
+             url = \"https://127.0.0.1:9200\"\n\
+             ca-file = \"http_ca.crt\"\n\
+             distribution = \"opensearch\"\n\
+             retention-days = 0",
+        )
+        .unwrap();
+
+        let elasticsearch = config.elasticsearch.unwrap();
+        assert_eq!(elasticsearch.distribution, SearchDistribution::Opensearch);
+        assert_eq!(elasticsearch.index, "pisshoff");
+        assert_eq!(elasticsearch.retention_days, 0);
+        assert_eq!(elasticsearch.batch_size, 500);
+        assert!(elasticsearch.manage_template);
+        assert_eq!(elasticsearch.api_key, None);
+        assert_eq!(
+            elasticsearch.ca_file.as_deref(),
+            Some(Path::new("http_ca.crt"))
+        );
+    }
+
+    #[test]
+    fn parses_auth_rules() {
+        let config: Config = toml::from_str(
+            "geoip-database = \"ip2asn-combined.tsv\"\n\
+             [[auth-rules]]\n\
+             id = \"scanners\"\n\
+             asns = [14061, 16276]\n\
+             action = \"accept\"\n\
+             [[auth-rules]]\n\
+             id = \"slow\"\n\
+             countries = [\"NL\"]\n\
+             action = \"tarpit\"\n\
+             tarpit-delay = 5000",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.geoip_database.as_deref(),
+            Some(Path::new("ip2asn-combined.tsv"))
+        );
+        assert_eq!(
+            config.auth_rules,
+            [
+                AuthRule {
+                    id: "scanners".to_string(),
+                    countries: Vec::new(),
+                    asns: vec![14061, 16276],
+                    action: AuthRuleAction::Accept,
+                    tarpit_delay: 30_000,
+                },
+                AuthRule {
+                    id: "slow".to_string(),
+                    countries: vec!["NL".to_string()],
+                    asns: Vec::new(),
+                    action: AuthRuleAction::Tarpit,
+                    tarpit_delay: 5000,
+                },
+            ]
+        );
+    }
+
+    #[test_case("", &BinaryExecutionMode::Segfault; "default")]
+    #[test_case(
+        "binary-execution = \"exec-format-error\"",
+        &BinaryExecutionMode::ExecFormatError;
+        "exec format error"
+    )]
+    #[test_case(
+        "[binary-execution.canned]\noutput = \"hi\\n\"\nexit-code = 2",
+        &BinaryExecutionMode::Canned(CannedCommand { output: "hi\n".to_string(), exit_code: 2 });
+        "canned"
+    )]
+    fn parses_binary_execution(input: &str, expected: &BinaryExecutionMode) {
+        let config: Config = toml::from_str(input).unwrap();
+        assert_eq!(&config.binary_execution, expected);
+    }
+
+    #[test_case("", &["Password: "]; "default")]
+    #[test_case(
+        "keyboard-interactive-prompts = [\"Password: \", \"Verification code: \"]",
+        &["Password: ", "Verification code: "];
+        "multiple"
+    )]
+    fn parses_keyboard_interactive_prompts(input: &str, expected: &[&str]) {
+        let config: Config = toml::from_str(input).unwrap();
+        assert_eq!(config.keyboard_interactive_prompts, expected);
+    }
+
+    #[test_case("", DirectTcpIpMode::Reject; "default")]
+    #[test_case("direct-tcpip = \"record\"", DirectTcpIpMode::Record; "record")]
+    #[test_case("direct-tcpip = \"emulate\"", DirectTcpIpMode::Emulate; "emulate")]
+    fn parses_direct_tcpip(input: &str, expected: DirectTcpIpMode) {
+        let config: Config = toml::from_str(input).unwrap();
+        assert_eq!(config.direct_tcpip, expected);
+    }
+
+    #[test_case("", &["0.0.0.0:22"]; "default")]
+    #[test_case("listen-address = \"127.0.0.1:2222\"", &["127.0.0.1:2222"]; "single")]
+    #[test_case(
+        "listen-address = [\"0.0.0.0:22\", \"[::]:2222\"]",
+        &["0.0.0.0:22", "[::]:2222"];
+        "list"
+    )]
+    fn parses_listen_address(input: &str, expected: &[&str]) {
+        let config: Config = toml::from_str(input).unwrap();
+        let expected: Vec<SocketAddr> = expected.iter().map(|v| v.parse().unwrap()).collect();
+        assert_eq!(config.listen_address, expected);
+    }
+
+    #[test]
+    fn reload_keeps_startup_settings() {
+        let current = Config::default();
+        let new: Config = toml::from_str(
+            "listen-address = \"127.0.0.1:2222\"\n\
+             server-id = \"SSH-2.0-dropbear\"\n\
+             access-probability = 0.5\n\
+             personality = \"busybox\"",
+        )
+        .unwrap();
+
+        let reloaded = current.reload(new);
+
+        assert_eq!(reloaded.listen_address, current.listen_address);
+        assert_eq!(reloaded.server_id, current.server_id);
+        assert!((reloaded.access_probability - 0.5).abs() < f64::EPSILON);
+        assert_eq!(reloaded.personality, Personality::Busybox);
+    }
+
+    #[test_case(LoggingPreset::Quiet, false, false; "quiet")]
+    #[test_case(LoggingPreset::Standard, true, false; "standard")]
+    #[test_case(LoggingPreset::Forensic, true, true; "forensic")]
+    fn includes(preset: LoggingPreset, shell_requested: bool, transcript: bool) {
+        assert_eq!(
+            preset.includes(&AuditLogAction::ShellRequested),
+            shell_requested
+        );
+        assert_eq!(
+            preset.includes(&AuditLogAction::Transcript(TranscriptEvent {
+                data: "ls\n".into()
+            })),
+            transcript
+        );
+        assert_eq!(preset.captures_transcripts(), transcript);
+    }
 }
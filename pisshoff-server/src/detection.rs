@@ -0,0 +1,117 @@
+//! Recognises the probes clients use to tell honeypots apart from real servers, so the attempt
+//! can be flagged.
+
+use crate::audit::DetectionProbe;
+
+/// Files listing what's mounted, which honeypots often leave empty or missing.
+const MOUNTS: &[&str] = &[
+    "/proc/mounts",
+    "/proc/self/mounts",
+    "/proc/self/mountinfo",
+    "/etc/mtab",
+];
+
+/// Files and commands giving away whether the system is a container or virtual machine.
+const VIRTUALISATION: &[&str] = &[
+    "/proc/1/cgroup",
+    "/proc/self/cgroup",
+    "/.dockerenv",
+    "/sys/class/dmi/id/product_name",
+    "systemd-detect-virt",
+    "virt-what",
+];
+
+/// Hostnames, users and names that well known honeypots ship with by default, matched as whole
+/// words.
+const HONEYPOT_DEFAULTS: &[&str] = &["svr04", "phil", "richard", "cowrie", "kippo"];
+
+/// The probe, if any, that the command line looks to be.
+pub fn probe(command: &str) -> Option<DetectionProbe> {
+    if command
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| HONEYPOT_DEFAULTS.contains(&word))
+    {
+        return Some(DetectionProbe::HoneypotDefaults);
+    }
+
+    if VIRTUALISATION.iter().any(|v| command.contains(v)) {
+        return Some(DetectionProbe::Virtualisation);
+    }
+
+    if MOUNTS.iter().any(|v| command.contains(v)) {
+        return Some(DetectionProbe::Mounts);
+    }
+
+    let echoes_escapes = command.split([';', '&', '|', '\n']).any(|statement| {
+        let mut words = statement.split_whitespace();
+
+        words.next() == Some("echo")
+            && words
+                .next()
+                .and_then(|v| v.strip_prefix('-'))
+                .is_some_and(|v| v.contains('e'))
+            && statement.contains("\\x")
+    });
+
+    echoes_escapes.then_some(DetectionProbe::EchoEscapes)
+}
+
+/// Returns true if `name`, which didn't resolve to a command, looks like gibberish typed to see
+/// how the shell reports a missing command rather than a real command that isn't installed.
+pub fn is_nonsense_command(name: &str) -> bool {
+    if name.len() < 5 || !name.bytes().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+
+    let lowercase = name.to_ascii_lowercase();
+    let has_vowels = lowercase.bytes().any(|c| b"aeiouy".contains(&c));
+
+    // letters and digits jumbled together, ie. `a8f3k2`, rather than a version suffix
+    let switches = name
+        .as_bytes()
+        .windows(2)
+        .filter(|v| v[0].is_ascii_digit() != v[1].is_ascii_digit())
+        .count();
+
+    let mut distinct = lowercase.into_bytes();
+    distinct.sort_unstable();
+    distinct.dedup();
+
+    !has_vowels || switches >= 3 || distinct.len() <= 2
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{
+        audit::DetectionProbe,
+        detection::{is_nonsense_command, probe},
+    };
+
+    #[test_case("cat /proc/mounts", Some(DetectionProbe::Mounts); "mounts")]
+    #[test_case("cat /proc/1/cgroup | grep docker", Some(DetectionProbe::Virtualisation); "cgroup")]
+    #[test_case("ls -la /.dockerenv", Some(DetectionProbe::Virtualisation); "dockerenv")]
+    #[test_case("grep phil /etc/passwd", Some(DetectionProbe::HoneypotDefaults); "cowrie user")]
+    #[test_case("hostname | grep -q svr04 && exit", Some(DetectionProbe::HoneypotDefaults); "cowrie hostname")]
+    #[test_case("echo -e \"\\x6F\\x6B\"", Some(DetectionProbe::EchoEscapes); "echo escapes")]
+    #[test_case("cd /tmp; echo -en '\\x31\\x33' > .x", Some(DetectionProbe::EchoEscapes); "echo escapes in list")]
+    #[test_case("echo -n hello", None; "plain echo")]
+    #[test_case("cat /etc/philosophy", None; "partial word")]
+    #[test_case("uname -a", None; "benign")]
+    fn detects_probes(command: &str, expected: Option<DetectionProbe>) {
+        assert_eq!(probe(command), expected);
+    }
+
+    #[test_case("xkcdqz", true; "no vowels")]
+    #[test_case("a8f3k2", true; "jumbled digits")]
+    #[test_case("aaaaaaa", true; "repeated")]
+    #[test_case("nvidia-smi", false; "hyphenated")]
+    #[test_case("masscan", false; "real command")]
+    #[test_case("python3", false; "version suffix")]
+    #[test_case("sha256sum", false; "digits within")]
+    #[test_case("xyz", false; "short")]
+    fn detects_nonsense_commands(name: &str, expected: bool) {
+        assert_eq!(is_nonsense_command(name), expected);
+    }
+}
@@ -1,65 +1,385 @@
 #![allow(dead_code)]
+// every error is an `LsError`, named for what the client would see from a real file system
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
 
 use std::{
-    borrow::Cow,
-    collections::{btree_map::Entry, BTreeMap},
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
     fmt::{Display, Formatter},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
 };
 
+use time::OffsetDateTime;
+
+/// The most that's returned by a single read of a device that would otherwise produce data
+/// forever, such as `/dev/zero`.
+pub const DEVICE_READ_LIMIT: usize = 64 * 1024;
+
+static ZEROES: [u8; DEVICE_READ_LIMIT] = [0; DEVICE_READ_LIMIT];
+
 /// A fake file system, stored in memory only active for the current session.
 pub struct FileSystem {
     pwd: PathBuf,
     home: PathBuf,
+    /// The user new files are owned by.
+    user: String,
     data: Tree,
+    /// Served for reads of `/dev/random` and `/dev/urandom`, only generated once either is read
+    /// as most connections never touch them.
+    random: OnceLock<Box<[u8]>>,
+    usage: Usage,
+}
+
+/// The most bytes of file content, and the most files and directories, that can be stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub bytes: u64,
+    pub inodes: u64,
+}
+
+impl Quota {
+    pub const UNLIMITED: Self = Self {
+        bytes: u64::MAX,
+        inodes: u64::MAX,
+    };
+}
+
+/// Space used by every connection's file system combined, so the server as a whole can be held
+/// to a quota too.
+#[derive(Debug, Default)]
+pub struct SharedUsage {
+    bytes: AtomicU64,
+    inodes: AtomicU64,
+}
+
+impl SharedUsage {
+    /// Bytes and inodes currently in use.
+    pub fn get(&self) -> (u64, u64) {
+        (
+            self.bytes.load(Ordering::Relaxed),
+            self.inodes.load(Ordering::Relaxed),
+        )
+    }
+
+    fn reserve(&self, bytes: u64, inodes: u64, quota: Quota) -> Result<(), LsError> {
+        let add = |counter: &AtomicU64, n: u64, max: u64| {
+            counter
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                    v.checked_add(n).filter(|v| *v <= max)
+                })
+                .is_ok()
+        };
+
+        if !add(&self.bytes, bytes, quota.bytes) {
+            return Err(LsError::NoSpaceLeftOnDevice);
+        }
+
+        if !add(&self.inodes, inodes, quota.inodes) {
+            self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+            return Err(LsError::NoSpaceLeftOnDevice);
+        }
+
+        Ok(())
+    }
+
+    fn release(&self, bytes: u64, inodes: u64) {
+        self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+        self.inodes.fetch_sub(inodes, Ordering::Relaxed);
+    }
+}
+
+/// Space used by a single file system, which is given back to the [`SharedUsage`] once the file
+/// system is dropped.
+struct Usage {
+    bytes: u64,
+    inodes: u64,
+    quota: Quota,
+    shared: Option<(Arc<SharedUsage>, Quota)>,
+}
+
+impl Usage {
+    fn reserve(&mut self, bytes: u64, inodes: u64) -> Result<(), LsError> {
+        let within = |used: u64, n: u64, max: u64| used.checked_add(n).is_some_and(|v| v <= max);
+
+        if !within(self.bytes, bytes, self.quota.bytes)
+            || !within(self.inodes, inodes, self.quota.inodes)
+        {
+            return Err(LsError::NoSpaceLeftOnDevice);
+        }
+
+        if let Some((shared, quota)) = &self.shared {
+            shared.reserve(bytes, inodes, *quota)?;
+        }
+
+        self.bytes += bytes;
+        self.inodes += inodes;
+        Ok(())
+    }
+
+    fn release(&mut self, bytes: u64, inodes: u64) {
+        self.bytes -= bytes;
+        self.inodes -= inodes;
+
+        if let Some((shared, _)) = &self.shared {
+            shared.release(bytes, inodes);
+        }
+    }
+}
+
+impl Drop for Usage {
+    fn drop(&mut self) {
+        if let Some((shared, _)) = &self.shared {
+            shared.release(self.bytes, self.inodes);
+        }
+    }
 }
 
 pub enum Tree {
     Directory(BTreeMap<String, Box<Tree>>),
-    File(Box<[u8]>),
+    File(Box<[u8]>, Metadata),
+    Device(Device, Metadata),
+}
+
+/// Character devices under `/dev`, writes to all of them are discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    /// Reads as empty.
+    Null,
+    /// Reads as zeroes.
+    Zero,
+    /// Reads as random bytes.
+    Random,
+}
+
+impl Device {
+    /// The paths each device is created at.
+    const PATHS: [(&'static str, Device); 4] = [
+        ("/dev/null", Device::Null),
+        ("/dev/zero", Device::Zero),
+        ("/dev/random", Device::Random),
+        ("/dev/urandom", Device::Random),
+    ];
+
+    /// Major and minor device numbers, as shown in place of the size by `ls -l`.
+    pub fn numbers(self, path: &Path) -> (u32, u32) {
+        match self {
+            Self::Null => (1, 3),
+            Self::Zero => (1, 5),
+            Self::Random if path.ends_with("urandom") => (1, 9),
+            Self::Random => (1, 8),
+        }
+    }
+}
+
+impl Tree {
+    /// Size of the entry as reported by `stat`, directories are always a single block.
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Directory(_) => 4096,
+            Self::File(content, _) => content.len() as u64,
+            Self::Device(..) => 0,
+        }
+    }
+
+    /// Number of hard links to the entry, which for a directory is its own entry, its `.` and the
+    /// `..` of each subdirectory.
+    pub fn links(&self) -> u64 {
+        match self {
+            Self::Directory(entries) => {
+                2 + entries
+                    .values()
+                    .filter(|v| matches!(v.as_ref(), Self::Directory(_)))
+                    .count() as u64
+            }
+            Self::File(..) | Self::Device(..) => 1,
+        }
+    }
+
+    /// Bytes of file content and number of inodes used by the entry and everything within it.
+    fn usage(&self) -> (u64, u64) {
+        match self {
+            Self::Directory(entries) => entries
+                .values()
+                .map(|v| v.usage())
+                .fold((0, 1), |(bytes, inodes), (b, i)| (bytes + b, inodes + i)),
+            Self::File(..) | Self::Device(..) => (self.size(), 1),
+        }
+    }
+
+    /// Returns true if the entry, or anything within it, is immutable or append only.
+    fn is_protected(&self) -> bool {
+        match self {
+            Self::Directory(entries) => entries.values().any(|v| v.is_protected()),
+            Self::File(_, metadata) | Self::Device(_, metadata) => {
+                metadata.is_immutable() || metadata.is_append_only()
+            }
+        }
+    }
+}
+
+/// Ownership, permissions and modification time of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// Permission bits, including the setuid, setgid and sticky bits.
+    pub mode: u32,
+    pub owner: String,
+    pub group: String,
+    /// Attributes set using `chattr`, ie. `i` for immutable or `a` for append only.
+    pub attributes: BTreeSet<char>,
+    pub mtime: OffsetDateTime,
+}
+
+impl Metadata {
+    /// Metadata for a file newly created by the given user, with the default umask of `022`.
+    pub fn new(user: &str) -> Self {
+        Self {
+            mode: 0o644,
+            owner: user.to_string(),
+            group: user.to_string(),
+            attributes: BTreeSet::new(),
+            mtime: OffsetDateTime::now_utc(),
+        }
+    }
+
+    /// Returns true if the file can't be modified or removed, even by root.
+    pub fn is_immutable(&self) -> bool {
+        self.attributes.contains(&'i')
+    }
+
+    /// Returns true if the file can only be appended to and can't be removed, even by root.
+    pub fn is_append_only(&self) -> bool {
+        self.attributes.contains(&'a')
+    }
+}
+
+/// Returns the home directory for the given user.
+pub fn home_directory(user: &str) -> PathBuf {
+    if user == "root" {
+        PathBuf::from("/root")
+    } else {
+        PathBuf::from("/home").join(user)
+    }
 }
 
 impl FileSystem {
+    #[must_use]
     pub fn new(user: &str) -> Self {
-        let pwd = if user == "root" {
-            PathBuf::from("/root")
-        } else {
-            PathBuf::from("/home").join(user)
-        };
+        Self::with_quota(user, Quota::UNLIMITED, None)
+    }
+
+    /// Creates a file system that refuses writes with `No space left on device` once `quota` is
+    /// used up, or once `shared` is if given.
+    #[must_use]
+    pub fn with_quota(user: &str, quota: Quota, shared: Option<(Arc<SharedUsage>, Quota)>) -> Self {
+        let pwd = home_directory(user);
 
         let mut this = Self {
             home: pwd.clone(),
             pwd,
+            user: user.to_string(),
             data: Tree::Directory(BTreeMap::new()),
+            random: OnceLock::new(),
+            usage: Usage {
+                bytes: 0,
+                inodes: 0,
+                quota,
+                shared,
+            },
         };
 
         let _res = this.mkdirall(&this.pwd.clone());
+        let _res = this.mkdirall(Path::new("/dev"));
+
+        let reserved = this.usage.reserve(0, Device::PATHS.len() as u64).is_ok();
+
+        if let (Ok(Tree::Directory(dev)), true) = (this.get_mut(Path::new("/dev")), reserved) {
+            for (path, device) in Device::PATHS {
+                let metadata = Metadata {
+                    mode: 0o666,
+                    ..Metadata::new("root")
+                };
+
+                dev.insert(
+                    path.trim_start_matches("/dev/").to_string(),
+                    Box::new(Tree::Device(device, metadata)),
+                );
+            }
+        }
+
         this
     }
 
     pub fn mkdirall(&mut self, path: &Path) -> Result<(), LsError> {
+        let canonical = self.canonicalise(path);
         let mut tree = &mut self.data;
 
-        for c in path {
+        for c in &canonical {
             match tree {
                 Tree::Directory(d) => {
+                    let name = c.to_str().unwrap();
+
+                    if !d.contains_key(name) {
+                        self.usage.reserve(0, 1)?;
+                    }
+
                     tree = d
-                        .entry(c.to_str().unwrap().to_string())
+                        .entry(name.to_string())
                         .or_insert_with(|| Box::new(Tree::Directory(BTreeMap::new())));
                 }
-                Tree::File(_) => return Err(LsError::FileExists),
+                Tree::File(..) | Tree::Device(..) => return Err(LsError::FileExists),
             }
         }
 
         Ok(())
     }
 
-    pub fn cd(&mut self, v: Option<&str>) {
-        if let Some(v) = v {
-            self.pwd.push(v);
-        } else {
+    /// Makes `user` the owner of newly created files and moves their home directory, changing
+    /// into it too if `login` is set, as `su -` would.
+    pub fn switch_user(&mut self, user: &str, login: bool) {
+        self.user = user.to_string();
+        self.home = home_directory(user);
+        let _res = self.mkdirall(&self.home.clone());
+
+        if login {
+            self.pwd = self.home.clone();
+        }
+    }
+
+    /// Changes the working directory to `v`, or to the home directory if not given.
+    pub fn cd(&mut self, v: Option<&str>) -> Result<(), LsError> {
+        let Some(v) = v else {
             self.pwd = self.home.clone();
+            return Ok(());
+        };
+
+        match self.get(Path::new(v))? {
+            Tree::Directory(_) => {
+                self.pwd = self.canonicalise(Path::new(v));
+                Ok(())
+            }
+            Tree::File(..) | Tree::Device(..) => Err(LsError::NotDirectory),
+        }
+    }
+
+    /// Resolves `path` against the working directory into an absolute path without any `.` or
+    /// `..` components. As on Linux, the parent of the root directory is the root directory.
+    pub fn canonicalise(&self, path: &Path) -> PathBuf {
+        let mut canonical = PathBuf::from("/");
+
+        for c in self.pwd.join(path).components() {
+            match c {
+                Component::Normal(v) => canonical.push(v),
+                Component::ParentDir => {
+                    canonical.pop();
+                }
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            }
         }
+
+        canonical
     }
 
     pub fn pwd(&self) -> &Path {
@@ -67,7 +387,89 @@ impl FileSystem {
     }
 
     pub fn read(&self, path: &Path) -> Result<&[u8], LsError> {
-        let canonical = self.pwd().join(path);
+        let canonical = self.canonicalise(path);
+        let mut tree = &self.data;
+
+        for c in &canonical {
+            match tree {
+                Tree::Directory(d) => {
+                    tree = d
+                        .get(c.to_str().unwrap())
+                        .ok_or(LsError::NoSuchFileOrDirectory)?;
+                }
+                Tree::File(..) | Tree::Device(..) => {
+                    return Err(LsError::NotDirectory);
+                }
+            }
+        }
+
+        match tree {
+            Tree::Directory(_) => Err(LsError::IsADirectory),
+            Tree::File(content, _) => Ok(content),
+            Tree::Device(Device::Null, _) => Ok(&[]),
+            Tree::Device(Device::Zero, _) => Ok(&ZEROES),
+            Tree::Device(Device::Random, _) => Ok(self.random.get_or_init(|| {
+                std::iter::repeat_with(|| fastrand::u8(..))
+                    .take(DEVICE_READ_LIMIT)
+                    .collect()
+            })),
+        }
+    }
+
+    /// Looks up the file or directory at the given path.
+    pub fn get(&self, path: &Path) -> Result<&Tree, LsError> {
+        let canonical = self.canonicalise(path);
+        let mut tree = &self.data;
+
+        for c in &canonical {
+            match tree {
+                Tree::Directory(d) => {
+                    tree = d
+                        .get(c.to_str().unwrap())
+                        .ok_or(LsError::NoSuchFileOrDirectory)?;
+                }
+                Tree::File(..) | Tree::Device(..) => {
+                    return Err(LsError::NotDirectory);
+                }
+            }
+        }
+
+        Ok(tree)
+    }
+
+    fn get_mut(&mut self, path: &Path) -> Result<&mut Tree, LsError> {
+        let canonical = self.canonicalise(path);
+        let mut tree = &mut self.data;
+
+        for c in &canonical {
+            match tree {
+                Tree::Directory(d) => {
+                    tree = d
+                        .get_mut(c.to_str().unwrap())
+                        .ok_or(LsError::NoSuchFileOrDirectory)?;
+                }
+                Tree::File(..) | Tree::Device(..) => {
+                    return Err(LsError::NotDirectory);
+                }
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// The user new files are owned by.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// The current user's home directory.
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// Returns the metadata of the file at the given path, directories don't have any metadata.
+    pub fn metadata(&self, path: &Path) -> Result<&Metadata, LsError> {
+        let canonical = self.canonicalise(path);
         let mut tree = &self.data;
 
         for c in &canonical {
@@ -77,7 +479,30 @@ impl FileSystem {
                         .get(c.to_str().unwrap())
                         .ok_or(LsError::NoSuchFileOrDirectory)?;
                 }
-                Tree::File(_) => {
+                Tree::File(..) | Tree::Device(..) => {
+                    return Err(LsError::NotDirectory);
+                }
+            }
+        }
+
+        match tree {
+            Tree::Directory(_) => Err(LsError::IsADirectory),
+            Tree::File(_, metadata) | Tree::Device(_, metadata) => Ok(metadata),
+        }
+    }
+
+    pub fn metadata_mut(&mut self, path: &Path) -> Result<&mut Metadata, LsError> {
+        let canonical = self.canonicalise(path);
+        let mut tree = &mut self.data;
+
+        for c in &canonical {
+            match tree {
+                Tree::Directory(d) => {
+                    tree = d
+                        .get_mut(c.to_str().unwrap())
+                        .ok_or(LsError::NoSuchFileOrDirectory)?;
+                }
+                Tree::File(..) | Tree::Device(..) => {
                     return Err(LsError::NotDirectory);
                 }
             }
@@ -85,12 +510,28 @@ impl FileSystem {
 
         match tree {
             Tree::Directory(_) => Err(LsError::IsADirectory),
-            Tree::File(content) => Ok(content),
+            Tree::File(_, metadata) | Tree::Device(_, metadata) => Ok(metadata),
         }
     }
 
     pub fn write(&mut self, path: &Path, content: Box<[u8]>) -> Result<(), LsError> {
-        let canonical = self.pwd().join(path);
+        self.write_inner(path, content, false)
+    }
+
+    /// Appends `content` to the end of the file at the given path, creating it if it doesn't
+    /// exist. Unlike [`FileSystem::write`] this is allowed on append only files.
+    pub fn append(&mut self, path: &Path, content: Box<[u8]>) -> Result<(), LsError> {
+        self.write_inner(path, content, true)
+    }
+
+    fn write_inner(
+        &mut self,
+        path: &Path,
+        content: Box<[u8]>,
+        append: bool,
+    ) -> Result<(), LsError> {
+        let canonical = self.canonicalise(path);
+        let metadata = Metadata::new(&self.user);
         let mut tree = &mut self.data;
 
         if let Some(parents) = canonical.parent() {
@@ -101,7 +542,7 @@ impl FileSystem {
                             .get_mut(c.to_str().unwrap())
                             .ok_or(LsError::NoSuchFileOrDirectory)?;
                     }
-                    Tree::File(_) => {
+                    Tree::File(..) | Tree::Device(..) => {
                         return Err(LsError::NotDirectory);
                     }
                 }
@@ -121,38 +562,216 @@ impl FileSystem {
                         .to_string(),
                 ) {
                     Entry::Vacant(v) => {
-                        v.insert(Box::new(Tree::File(content)));
+                        self.usage.reserve(content.len() as u64, 1)?;
+                        v.insert(Box::new(Tree::File(content, metadata)));
                         Ok(())
                     }
-                    Entry::Occupied(mut o) if matches!(o.get().as_ref(), Tree::File(_)) => {
-                        o.insert(Box::new(Tree::File(content)));
+                    Entry::Occupied(mut o) => match o.get_mut().as_mut() {
+                        Tree::File(_, metadata)
+                            if metadata.is_immutable()
+                                || (metadata.is_append_only() && !append) =>
+                        {
+                            Err(LsError::OperationNotPermitted)
+                        }
+                        Tree::File(existing, metadata) => {
+                            let old = existing.len() as u64;
+                            let new = if append { old } else { 0 } + content.len() as u64;
+                            if new > old {
+                                self.usage.reserve(new - old, 0)?;
+                            } else {
+                                self.usage.release(old - new, 0);
+                            }
+
+                            *existing = if append {
+                                [&existing[..], &content[..]].concat().into()
+                            } else {
+                                content
+                            };
+                            metadata.mtime = OffsetDateTime::now_utc();
+                            Ok(())
+                        }
+                        Tree::Device(..) => Ok(()),
+                        Tree::Directory(_) => Err(LsError::IsADirectory),
+                    },
+                }
+            }
+            Tree::File(..) | Tree::Device(..) => Err(LsError::NotDirectory),
+        }
+    }
+
+    /// Removes the file at the given path, directories can't be removed.
+    pub fn remove(&mut self, path: &Path) -> Result<(), LsError> {
+        let canonical = self.canonicalise(path);
+        let mut tree = &mut self.data;
+
+        let Some(name) = canonical.file_name().and_then(|v| v.to_str()) else {
+            return Err(LsError::IsADirectory);
+        };
+
+        if let Some(parents) = canonical.parent() {
+            for c in parents {
+                match tree {
+                    Tree::Directory(d) => {
+                        tree = d
+                            .get_mut(c.to_str().unwrap())
+                            .ok_or(LsError::NoSuchFileOrDirectory)?;
+                    }
+                    Tree::File(..) | Tree::Device(..) => {
+                        return Err(LsError::NotDirectory);
+                    }
+                }
+            }
+        }
+
+        match tree {
+            Tree::Directory(v) => match v.entry(name.to_string()) {
+                Entry::Occupied(o) => match o.get().as_ref() {
+                    Tree::File(_, metadata) | Tree::Device(_, metadata)
+                        if metadata.is_immutable() || metadata.is_append_only() =>
+                    {
+                        Err(LsError::OperationNotPermitted)
+                    }
+                    tree @ (Tree::File(..) | Tree::Device(..)) => {
+                        self.usage.release(tree.size(), 1);
+                        o.remove();
                         Ok(())
                     }
-                    Entry::Occupied(_) => Err(LsError::IsADirectory),
+                    Tree::Directory(_) => Err(LsError::IsADirectory),
+                },
+                Entry::Vacant(_) => Err(LsError::NoSuchFileOrDirectory),
+            },
+            Tree::File(..) | Tree::Device(..) => Err(LsError::NotDirectory),
+        }
+    }
+
+    /// Removes the file or directory at the given path along with everything within it. Nothing is
+    /// removed if any file within can't be.
+    pub fn remove_all(&mut self, path: &Path) -> Result<(), LsError> {
+        let canonical = self.canonicalise(path);
+        let mut tree = &mut self.data;
+
+        let Some(name) = canonical.file_name().and_then(|v| v.to_str()) else {
+            return Err(LsError::OperationNotPermitted);
+        };
+
+        if let Some(parents) = canonical.parent() {
+            for c in parents {
+                match tree {
+                    Tree::Directory(d) => {
+                        tree = d
+                            .get_mut(c.to_str().unwrap())
+                            .ok_or(LsError::NoSuchFileOrDirectory)?;
+                    }
+                    Tree::File(..) | Tree::Device(..) => {
+                        return Err(LsError::NotDirectory);
+                    }
                 }
             }
-            Tree::File(_) => Err(LsError::NotDirectory),
+        }
+
+        match tree {
+            Tree::Directory(v) => match v.entry(name.to_string()) {
+                Entry::Occupied(o) if o.get().is_protected() => Err(LsError::OperationNotPermitted),
+                Entry::Occupied(o) => {
+                    let (bytes, inodes) = o.get().usage();
+                    self.usage.release(bytes, inodes);
+                    o.remove();
+                    Ok(())
+                }
+                Entry::Vacant(_) => Err(LsError::NoSuchFileOrDirectory),
+            },
+            Tree::File(..) | Tree::Device(..) => Err(LsError::NotDirectory),
         }
     }
 
+    /// Expands a shell wildcard pattern (`*`, `?`, `[...]`) against the file system, returning the
+    /// matching paths in the same form as the pattern was given (ie. relative or absolute).
+    pub fn glob(&self, pattern: &str) -> Vec<String> {
+        // the directories leading up to the first wildcard are looked up as a path and kept as
+        // they were written, so `../*.sh` expands to `../a.sh`
+        let literal = pattern
+            .match_indices('/')
+            .map(|(i, _)| i + 1)
+            .take_while(|&i| !is_glob(&pattern[..i]))
+            .last()
+            .unwrap_or(0);
+        let (prefix, rest) = pattern.split_at(literal);
+
+        let mut tree = &self.data;
+
+        for c in &self.canonicalise(Path::new(prefix)) {
+            match tree {
+                Tree::Directory(d) => match d.get(c.to_str().unwrap()) {
+                    Some(v) => tree = v,
+                    None => return vec![],
+                },
+                Tree::File(..) | Tree::Device(..) => return vec![],
+            }
+        }
+
+        let mut candidates = vec![(prefix.to_string(), tree)];
+
+        for component in rest.split('/').filter(|v| !v.is_empty()) {
+            let mut next = Vec::new();
+
+            for (prefix, tree) in candidates {
+                let Tree::Directory(entries) = tree else {
+                    continue;
+                };
+
+                let join = |name: &str| {
+                    if prefix.is_empty() || prefix.ends_with('/') {
+                        format!("{prefix}{name}")
+                    } else {
+                        format!("{prefix}/{name}")
+                    }
+                };
+
+                if !is_glob(component) {
+                    if component == "." {
+                        next.push((join(component), tree));
+                    } else if let Some(child) = entries.get(component) {
+                        next.push((join(component), child.as_ref()));
+                    }
+
+                    continue;
+                }
+
+                for (name, child) in entries {
+                    // hidden files are only matched if the pattern explicitly asks for them
+                    if name.starts_with('.') && !component.starts_with('.') {
+                        continue;
+                    }
+
+                    if glob_match(component.as_bytes(), name.as_bytes()) {
+                        next.push((join(name), child.as_ref()));
+                    }
+                }
+            }
+
+            candidates = next;
+        }
+
+        candidates
+            .into_iter()
+            .map(|(path, _)| path)
+            .filter(|path| !path.is_empty())
+            .collect()
+    }
+
     #[allow(clippy::unused_self)]
     pub fn ls<'a>(&'a self, dir: Option<&'a Path>) -> Result<Vec<&'a str>, LsError> {
-        let canonical = if let Some(dir) = dir {
-            Cow::Owned(self.pwd().join(dir))
-        } else {
-            Cow::Borrowed(self.pwd())
-        };
-
+        let canonical = self.canonicalise(dir.unwrap_or(Path::new(".")));
         let mut tree = &self.data;
 
-        for c in canonical.as_ref() {
+        for c in &canonical {
             match tree {
                 Tree::Directory(d) => {
                     tree = d
                         .get(c.to_str().unwrap())
                         .ok_or(LsError::NoSuchFileOrDirectory)?;
                 }
-                Tree::File(_) => {
+                Tree::File(..) | Tree::Device(..) => {
                     return Err(LsError::NotDirectory);
                 }
             }
@@ -160,8 +779,93 @@ impl FileSystem {
 
         match tree {
             Tree::Directory(v) => Ok(v.keys().map(String::as_str).collect()),
-            Tree::File(_) => Ok(vec![dir.unwrap_or(self.pwd()).to_str().unwrap()]),
+            Tree::File(..) | Tree::Device(..) => {
+                Ok(vec![dir.unwrap_or(self.pwd()).to_str().unwrap()])
+            }
+        }
+    }
+}
+
+/// Returns true if the given path component contains any wildcard characters.
+pub fn is_glob(v: &str) -> bool {
+    v.contains(['*', '?', '['])
+}
+
+/// Matches a single path component against a wildcard pattern, supporting `*`, `?` and bracket
+/// expressions such as `[abc]`, `[a-z]` and `[!abc]`.
+///
+/// Only the most recent `*` is ever backtracked to, so matching takes at most
+/// `pattern.len() * name.len()` steps however many stars the client puts in the pattern.
+pub fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // the position in the pattern just after the last `*`, and how far into the name it has
+    // been stretched to so far
+    let mut star = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, n));
+                continue;
+            }
+            Some(_) => {
+                if let Some(len) = match_one(&pattern[p..], name[n]) {
+                    p += len;
+                    n += 1;
+                    continue;
+                }
+            }
+            None => {}
+        }
+
+        // the rest of the pattern didn't match, so have the last `*` swallow one more character
+        let Some((star_p, star_n)) = star else {
+            return false;
+        };
+
+        p = star_p;
+        n = star_n + 1;
+        star = Some((star_p, n));
+    }
+
+    pattern[p..].iter().all(|v| *v == b'*')
+}
+
+/// Matches `c` against the token at the start of `pattern`, which can't be a `*`, returning the
+/// length of the token if it matched.
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern[0] {
+        b'?' => Some(1),
+        b'[' => {
+            let (negate, class) = match &pattern[1..] {
+                [b'!' | b'^', rest @ ..] => (true, rest),
+                rest => (false, rest),
+            };
+
+            // a `]` directly after the opening bracket is treated as a literal
+            let Some(end) = class.iter().skip(1).position(|v| *v == b']').map(|v| v + 1) else {
+                // unterminated bracket, treat the `[` as a literal
+                return (c == b'[').then_some(1);
+            };
+
+            let class = &class[..end];
+            let mut matched = false;
+            let mut i = 0;
+
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    matched |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+
+            (matched != negate).then_some(1 + usize::from(negate) + end + 1)
         }
+        literal => (literal == c).then_some(1),
     }
 }
 
@@ -171,6 +875,8 @@ pub enum LsError {
     NoSuchFileOrDirectory,
     IsADirectory,
     FileExists,
+    OperationNotPermitted,
+    NoSpaceLeftOnDevice,
 }
 
 impl Display for LsError {
@@ -180,6 +886,187 @@ impl Display for LsError {
             LsError::NotDirectory => "Not a directory",
             LsError::IsADirectory => "Is a directory",
             LsError::FileExists => "File exists",
+            LsError::OperationNotPermitted => "Operation not permitted",
+            LsError::NoSpaceLeftOnDevice => "No space left on device",
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, sync::Arc};
+
+    use test_case::test_case;
+
+    use crate::file_system::{
+        glob_match, FileSystem, LsError, Quota, SharedUsage, DEVICE_READ_LIMIT,
+    };
+
+    #[test_case("*", "hello", true; "star")]
+    #[test_case("*.sh", "run.sh", true; "star with suffix")]
+    #[test_case("*.sh", "run.py", false; "star with wrong suffix")]
+    #[test_case("h?llo", "hello", true; "question mark")]
+    #[test_case("h?llo", "hllo", false; "question mark requires char")]
+    #[test_case("[a-c]at", "bat", true; "range")]
+    #[test_case("[!a-c]at", "bat", false; "negated range")]
+    #[test_case("[xyz]", "y", true; "class")]
+    #[test_case("[abc", "[abc", true; "unterminated class")]
+    #[test_case("*a*b*c", "xaybzc", true; "several stars")]
+    #[test_case("*a*b*c", "xaybzcd", false; "several stars with trailing char")]
+    #[test_case("a**", "a", true; "trailing stars")]
+    #[test_case("[]]x", "]x", true; "literal bracket in class")]
+    #[test_case(
+        "*a*a*a*a*a*a*a*a*a*a*a*a*b",
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        false;
+        "pathological"
+    )]
+    fn glob_matching(pattern: &str, name: &str, expected: bool) {
+        assert_eq!(glob_match(pattern.as_bytes(), name.as_bytes()), expected);
+    }
+
+    #[test]
+    fn glob() {
+        let mut fs = FileSystem::new("root");
+        fs.mkdirall(Path::new("/etc/cron.d")).unwrap();
+        fs.write(Path::new("/etc/cron.d/a"), Box::default())
+            .unwrap();
+        fs.write(Path::new("/etc/cron.d/b"), Box::default())
+            .unwrap();
+        fs.write(Path::new("/etc/cron.d/.hidden"), Box::default())
+            .unwrap();
+        fs.write(Path::new("test.sh"), Box::default()).unwrap();
+
+        assert_eq!(fs.glob("/etc/cron.d/*"), ["/etc/cron.d/a", "/etc/cron.d/b"]);
+        assert_eq!(fs.glob("/etc/*/.*"), ["/etc/cron.d/.hidden"]);
+        assert_eq!(fs.glob("*.sh"), ["test.sh"]);
+        assert_eq!(fs.glob("./*.sh"), ["./test.sh"]);
+        assert_eq!(
+            fs.glob("../etc/cron.d/[ab]"),
+            ["../etc/cron.d/a", "../etc/cron.d/b"]
+        );
+        assert!(fs.glob("/tmp/*").is_empty());
+    }
+
+    #[test_case("/root", "../../etc/passwd", "/etc/passwd"; "parent")]
+    #[test_case("/root", "./foo/../bar", "/root/bar"; "current and parent")]
+    #[test_case("/", "..", "/"; "above root")]
+    #[test_case("/root", "/tmp/./x/", "/tmp/x"; "absolute")]
+    fn canonicalise(pwd: &str, path: &str, expected: &str) {
+        let mut fs = FileSystem::new("root");
+        fs.cd(Some(pwd)).unwrap();
+
+        assert_eq!(fs.canonicalise(Path::new(path)), Path::new(expected));
+    }
+
+    #[test]
+    fn relative_parents() {
+        let mut fs = FileSystem::new("root");
+        fs.mkdirall(Path::new("/etc")).unwrap();
+        fs.write(Path::new("../etc/passwd"), Box::from(*b"root:x:0:0"))
+            .unwrap();
+
+        assert_eq!(fs.read(Path::new("/etc/passwd")).unwrap(), b"root:x:0:0");
+        assert_eq!(
+            fs.ls(Some(Path::new("./../etc/../etc"))).unwrap(),
+            ["passwd"]
+        );
+    }
+
+    #[test]
+    fn append_only() {
+        let mut fs = FileSystem::new("root");
+        fs.write(Path::new("log"), Box::from(*b"a\n")).unwrap();
+        fs.metadata_mut(Path::new("log"))
+            .unwrap()
+            .attributes
+            .insert('a');
+
+        assert!(matches!(
+            fs.write(Path::new("log"), Box::from(*b"b\n")),
+            Err(LsError::OperationNotPermitted)
+        ));
+        fs.append(Path::new("log"), Box::from(*b"b\n")).unwrap();
+        assert_eq!(fs.read(Path::new("log")).unwrap(), b"a\nb\n");
+        assert!(matches!(
+            fs.remove(Path::new("log")),
+            Err(LsError::OperationNotPermitted)
+        ));
+    }
+
+    #[test]
+    fn remove() {
+        let mut fs = FileSystem::new("root");
+        fs.write(Path::new("test.sh"), Box::default()).unwrap();
+
+        fs.remove(Path::new("/root/test.sh")).unwrap();
+        assert!(fs.read(Path::new("test.sh")).is_err());
+        assert!(fs.remove(Path::new("test.sh")).is_err());
+        assert!(fs.remove(Path::new("/root")).is_err());
+    }
+
+    #[test]
+    fn devices() {
+        let mut fs = FileSystem::new("root");
+
+        fs.write(Path::new("/dev/null"), b"hello".to_vec().into())
+            .unwrap();
+        assert_eq!(fs.read(Path::new("/dev/null")).unwrap(), b"");
+
+        let zero = fs.read(Path::new("/dev/zero")).unwrap();
+        assert_eq!(zero.len(), DEVICE_READ_LIMIT);
+        assert!(zero.iter().all(|v| *v == 0));
+
+        let random = fs.read(Path::new("/dev/urandom")).unwrap();
+        assert_eq!(random.len(), DEVICE_READ_LIMIT);
+        assert!(random.iter().any(|v| *v != 0));
+
+        assert_eq!(
+            fs.ls(Some(Path::new("/dev"))).unwrap(),
+            ["null", "random", "urandom", "zero"]
+        );
+    }
+
+    #[test]
+    fn quota() {
+        let quota = Quota {
+            bytes: 10,
+            inodes: 64,
+        };
+        let mut fs = FileSystem::with_quota("root", quota, None);
+
+        fs.write(Path::new("a"), Box::from(*b"12345678")).unwrap();
+        assert!(matches!(
+            fs.write(Path::new("b"), Box::from(*b"123")),
+            Err(LsError::NoSpaceLeftOnDevice)
+        ));
+        assert!(fs.read(Path::new("b")).is_err());
+
+        // overwriting only counts the difference in size
+        fs.write(Path::new("a"), Box::from(*b"1234567890")).unwrap();
+        fs.remove(Path::new("a")).unwrap();
+        fs.write(Path::new("b"), Box::from(*b"123")).unwrap();
+    }
+
+    #[test]
+    fn shared_quota() {
+        let shared = Arc::new(SharedUsage::default());
+        let total = Quota {
+            bytes: 10,
+            inodes: u64::MAX,
+        };
+
+        let mut a = FileSystem::with_quota("root", Quota::UNLIMITED, Some((shared.clone(), total)));
+        let mut b = FileSystem::with_quota("root", Quota::UNLIMITED, Some((shared.clone(), total)));
+
+        a.write(Path::new("a"), Box::from(*b"12345678")).unwrap();
+        assert!(b.write(Path::new("b"), Box::from(*b"123")).is_err());
+
+        drop(a);
+        b.write(Path::new("b"), Box::from(*b"123")).unwrap();
+        assert_eq!(shared.get().0, 3);
+
+        drop(b);
+        assert_eq!(shared.get(), (0, 0));
+    }
+}
@@ -0,0 +1,134 @@
+//! Looks up the country and autonomous system each client connects from, in a database using the
+//! tab separated format published by [iptoasn](https://iptoasn.com), such as
+//! `ip2asn-combined.tsv`.
+
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, Ipv6Addr},
+    path::Path,
+};
+
+use pisshoff_types::audit::GeoLocation;
+
+/// Address ranges and where they're registered, sorted by the start of each range. IPv4
+/// addresses are stored as IPv4-mapped IPv6 addresses so both can be searched together.
+#[derive(Default)]
+pub struct GeoIp {
+    ranges: Vec<(u128, u128, GeoLocation)>,
+}
+
+impl GeoIp {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses lines of `range_start`, `range_end`, `AS_number`, `country_code` and
+    /// `AS_description`. Ranges that aren't routed have an AS number of 0 and are left out.
+    fn parse(content: &str) -> std::io::Result<Self> {
+        let mut ranges = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || {
+                std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid GeoIP database entry on line {}", i + 1),
+                )
+            };
+
+            let mut fields = line.split('\t');
+            let mut next = || fields.next().ok_or_else(invalid);
+
+            let start = next()?.parse().map_err(|_| invalid())?;
+            let end = next()?.parse().map_err(|_| invalid())?;
+            let asn = next()?.parse().map_err(|_| invalid())?;
+            let country = next()?;
+            let as_name = next()?;
+
+            if asn == 0 {
+                continue;
+            }
+
+            ranges.push((
+                key(start),
+                key(end),
+                GeoLocation {
+                    country: Box::from(country),
+                    asn,
+                    as_name: Box::from(as_name),
+                },
+            ));
+        }
+
+        ranges.sort_unstable_by_key(|(start, _, _)| *start);
+
+        Ok(Self { ranges })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Where `addr` is registered, if it's within one of the ranges in the database.
+    pub fn lookup(&self, addr: IpAddr) -> Option<&GeoLocation> {
+        let addr = key(addr);
+        let i = self.ranges.partition_point(|(start, _, _)| *start <= addr);
+        let (_, end, location) = self.ranges.get(i.checked_sub(1)?)?;
+
+        (addr <= *end).then_some(location)
+    }
+}
+
+fn key(addr: IpAddr) -> u128 {
+    let addr: Ipv6Addr = match addr {
+        IpAddr::V4(v) => v.to_ipv6_mapped(),
+        IpAddr::V6(v) => v,
+    };
+
+    addr.into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use test_case::test_case;
+
+    use crate::geoip::GeoIp;
+
+    const DATABASE: &str = "\
+        1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+        1.0.1.0\t1.0.3.255\t0\tNone\tNot routed\n\
+        1.0.4.0\t1.0.7.255\t38803\tAU\tWPL-AS-AP Wirefreebroadband Pty Ltd\n\
+        2001:200::\t2001:200:5ff:ffff:ffff:ffff:ffff:ffff\t2500\tJP\tWIDE-BB WIDE Project\n";
+
+    #[test_case("1.0.0.1", Some(("US", 13335)); "start of database")]
+    #[test_case("1.0.0.255", Some(("US", 13335)); "end of range")]
+    #[test_case("1.0.2.1", None; "not routed")]
+    #[test_case("1.0.5.1", Some(("AU", 38803)); "within range")]
+    #[test_case("1.0.8.0", None; "after range")]
+    #[test_case("0.255.255.255", None; "before database")]
+    #[test_case("::ffff:1.0.0.1", Some(("US", 13335)); "ipv4 mapped")]
+    #[test_case("2001:200:1::1", Some(("JP", 2500)); "ipv6")]
+    #[test_case("2001:201::1", None; "after ipv6 range")]
+    fn looks_up(addr: &str, expected: Option<(&str, u32)>) {
+        let geoip = GeoIp::parse(DATABASE).unwrap();
+        assert_eq!(geoip.len(), 3);
+
+        let actual = geoip
+            .lookup(addr.parse::<IpAddr>().unwrap())
+            .map(|v| (&*v.country, v.asn));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        let err = GeoIp::parse("1.0.0.0\t1.0.0.255\tAS13335\tUS\tCLOUDFLARENET\n")
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "invalid GeoIP database entry on line 1");
+    }
+}
@@ -0,0 +1,365 @@
+//! Fingerprinting of the client's SSH implementation from the identification string and key
+//! exchange proposal it sends in the clear at the start of every connection. thrussh doesn't
+//! expose either, so they're picked out of the stream as thrussh reads it.
+
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::map_res,
+    multi::count,
+    number::complete::{be_u32, be_u8},
+    IResult,
+};
+use parking_lot::Mutex;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::audit::NegotiatedAlgorithms;
+
+/// How much of the stream to hold onto looking for the key exchange proposal, which is
+/// comfortably more than the 255 byte identification string and 35000 byte packet the protocol
+/// allows for.
+const MAX_CAPTURE: usize = 64 * 1024;
+
+const SSH_MSG_KEXINIT: u8 = 20;
+
+/// The algorithms the server offers, in the order thrussh has been configured to prefer them.
+#[derive(Debug)]
+pub struct ServerAlgorithms {
+    kex: Vec<&'static str>,
+    host_key: Vec<&'static str>,
+    cipher: Vec<&'static str>,
+    mac: Vec<&'static str>,
+    compression: Vec<&'static str>,
+}
+
+impl ServerAlgorithms {
+    pub fn new(config: &thrussh::server::Config) -> Self {
+        let preferred = &config.preferred;
+
+        Self {
+            kex: preferred.kex.iter().map(AsRef::as_ref).collect(),
+            // thrussh only offers the host key algorithms it has a key for
+            host_key: preferred
+                .key
+                .iter()
+                .map(AsRef::as_ref)
+                .filter(|name| config.keys.iter().any(|key| key.name() == *name))
+                .collect(),
+            cipher: preferred.cipher.iter().map(AsRef::as_ref).collect(),
+            mac: preferred.mac.to_vec(),
+            compression: preferred.compression.to_vec(),
+        }
+    }
+}
+
+/// What was learnt about the client from the start of the connection.
+#[derive(Debug, Default)]
+pub struct ClientHello {
+    pub version: Option<Box<str>>,
+    pub algorithms: Option<NegotiatedAlgorithms>,
+    pub hassh: Option<Box<str>>,
+}
+
+impl ClientHello {
+    fn parse(data: &[u8], server: &ServerAlgorithms) -> Self {
+        let mut hello = Self::default();
+        let mut rest = data;
+
+        // other lines are only allowed before the server's identification string, but skip over
+        // them from the client too in case it's confused about which side it is
+        loop {
+            let Some(end) = rest.iter().position(|v| *v == b'\n') else {
+                return hello;
+            };
+
+            let (line, tail) = rest.split_at(end + 1);
+            rest = tail;
+
+            if line.starts_with(b"SSH-") {
+                let line = line.trim_ascii_end();
+                hello.version = Some(String::from_utf8_lossy(line).into());
+                break;
+            }
+        }
+
+        if let Ok((_, proposal)) = KexInit::parse(rest) {
+            hello.algorithms = Some(proposal.negotiate(server));
+            hello.hassh = Some(proposal.hassh());
+        }
+
+        hello
+    }
+}
+
+/// The algorithms proposed in the client's `SSH_MSG_KEXINIT`, each a comma separated list in
+/// order of preference.
+struct KexInit<'a> {
+    kex: &'a str,
+    host_key: &'a str,
+    cipher: &'a str,
+    mac: &'a str,
+    compression: &'a str,
+}
+
+impl<'a> KexInit<'a> {
+    fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
+        let (rest, length) = be_u32(rest)?;
+        let (rest, padding) = be_u8(rest)?;
+        let (rest, payload) = take(length.saturating_sub(u32::from(padding) + 1))(rest)?;
+
+        let (payload, _) = tag([SSH_MSG_KEXINIT])(payload)?;
+        let (payload, _cookie) = take(16_usize)(payload)?;
+        let (_, lists) = count(name_list, 10)(payload)?;
+
+        // client to server and server to client algorithms are negotiated separately, but only
+        // the ones used by the client to send to us are recorded
+        let [kex, host_key, cipher, _, mac, _, compression, ..] = <[_; 10]>::try_from(lists)
+            .unwrap_or_else(|_| unreachable!("count always returns 10 lists"));
+
+        Ok((
+            rest,
+            Self {
+                kex,
+                host_key,
+                cipher,
+                mac,
+                compression,
+            },
+        ))
+    }
+
+    fn negotiate(&self, server: &ServerAlgorithms) -> NegotiatedAlgorithms {
+        let cipher = pick(self.cipher, &server.cipher);
+
+        // an AEAD cipher authenticates the packets itself, so whatever MAC is agreed on goes
+        // unused and there's no need for the lists to have one in common
+        let mac = pick(self.mac, &server.mac).or_else(|| {
+            cipher
+                .as_deref()
+                .is_some_and(is_aead)
+                .then(|| Box::from("none"))
+        });
+
+        NegotiatedAlgorithms {
+            kex: pick(self.kex, &server.kex),
+            host_key: pick(self.host_key, &server.host_key),
+            cipher,
+            mac,
+            compression: pick(self.compression, &server.compression),
+        }
+    }
+
+    /// The [HASSH](https://github.com/salesforce/hassh) fingerprint of the client, which depends
+    /// only on the SSH library it's using and how that's been configured.
+    fn hassh(&self) -> Box<str> {
+        let algorithms = format!(
+            "{};{};{};{}",
+            self.kex, self.cipher, self.mac, self.compression
+        );
+
+        format!("{:x}", md5::compute(algorithms)).into_boxed_str()
+    }
+}
+
+fn name_list(rest: &[u8]) -> IResult<&[u8], &str> {
+    let (rest, length) = be_u32(rest)?;
+    map_res(take(length), std::str::from_utf8)(rest)
+}
+
+/// Picks the first of the client's algorithms that the server also supports, as described in
+/// RFC 4253 section 7.1.
+fn pick(client: &str, server: &[&str]) -> Option<Box<str>> {
+    client
+        .split(',')
+        .find(|v| server.contains(v))
+        .map(Box::from)
+}
+
+fn is_aead(cipher: &str) -> bool {
+    matches!(
+        cipher,
+        "chacha20-poly1305@openssh.com" | "aes128-gcm@openssh.com" | "aes256-gcm@openssh.com"
+    )
+}
+
+/// Wraps the client's stream, picking out the identification string and key exchange proposal
+/// as they're read.
+pub struct Sniffer<S> {
+    inner: S,
+    captured: Vec<u8>,
+    /// Set once the key exchange proposal has been found, or too much has been read to keep
+    /// looking for it.
+    done: bool,
+    server: Arc<ServerAlgorithms>,
+    hello: Arc<Mutex<ClientHello>>,
+}
+
+impl<S> Sniffer<S> {
+    /// Wraps `inner`, returning a handle to whatever is learnt about the client as the stream is
+    /// read. The client's proposal is negotiated against `server`.
+    pub fn new(inner: S, server: Arc<ServerAlgorithms>) -> (Self, Arc<Mutex<ClientHello>>) {
+        let hello = Arc::new(Mutex::new(ClientHello::default()));
+
+        (
+            Self {
+                inner,
+                captured: Vec::new(),
+                done: false,
+                server,
+                hello: hello.clone(),
+            },
+            hello,
+        )
+    }
+
+    fn capture(&mut self, data: &[u8]) {
+        if self.done || data.is_empty() {
+            return;
+        }
+
+        self.captured.extend_from_slice(data);
+
+        let hello = ClientHello::parse(&self.captured, &self.server);
+        self.done = hello.algorithms.is_some() || self.captured.len() >= MAX_CAPTURE;
+        *self.hello.lock() = hello;
+
+        if self.done {
+            self.captured = Vec::new();
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Sniffer<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = res {
+            self.capture(&buf.filled()[filled..]);
+        }
+
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Sniffer<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use thrussh::server::Config;
+    use tokio::io::AsyncReadExt;
+
+    use crate::{
+        audit::NegotiatedAlgorithms,
+        handshake::{ServerAlgorithms, Sniffer},
+    };
+
+    fn server_algorithms() -> Arc<ServerAlgorithms> {
+        Arc::new(ServerAlgorithms::new(&Config {
+            keys: vec![thrussh_keys::key::KeyPair::generate_ed25519().unwrap()],
+            ..Config::default()
+        }))
+    }
+
+    fn kex_init(lists: [&str; 10]) -> Vec<u8> {
+        let mut payload = vec![super::SSH_MSG_KEXINIT];
+        payload.extend_from_slice(&[0; 16]);
+
+        for list in lists {
+            payload.extend_from_slice(&u32::try_from(list.len()).unwrap().to_be_bytes());
+            payload.extend_from_slice(list.as_bytes());
+        }
+
+        // first_kex_packet_follows and the reserved field
+        payload.extend_from_slice(&[0; 5]);
+
+        let padding = 4;
+        let mut packet = Vec::new();
+        packet.extend_from_slice(
+            &u32::try_from(payload.len() + padding + 1)
+                .unwrap()
+                .to_be_bytes(),
+        );
+        packet.push(u8::try_from(padding).unwrap());
+        packet.extend_from_slice(&payload);
+        packet.extend_from_slice(&[0; 4]);
+        packet
+    }
+
+    #[tokio::test]
+    async fn captures_client_hello() {
+        let mut stream = b"SSH-2.0-libssh_0.8.1\r\n".to_vec();
+        stream.extend_from_slice(&kex_init([
+            "diffie-hellman-group14-sha1,curve25519-sha256@libssh.org,ext-info-c",
+            "ssh-rsa,ssh-ed25519",
+            "aes128-ctr,chacha20-poly1305@openssh.com",
+            "aes128-ctr",
+            "hmac-sha1",
+            "hmac-sha1",
+            "none",
+            "none",
+            "",
+            "",
+        ]));
+
+        // read a few bytes at a time, so the packet arrives split across several reads
+        let (mut sniffer, hello) = Sniffer::new(stream.as_slice(), server_algorithms());
+        let mut buf = [0; 7];
+        while sniffer.read(&mut buf).await.unwrap() > 0 {}
+
+        let hello = hello.lock();
+        assert_eq!(hello.version.as_deref(), Some("SSH-2.0-libssh_0.8.1"));
+        assert_eq!(
+            hello.algorithms,
+            Some(NegotiatedAlgorithms {
+                kex: Some("curve25519-sha256@libssh.org".into()),
+                host_key: Some("ssh-ed25519".into()),
+                cipher: Some("chacha20-poly1305@openssh.com".into()),
+                mac: Some("none".into()),
+                compression: Some("none".into()),
+            })
+        );
+        assert_eq!(
+            hello.hassh.as_deref(),
+            Some("b7fb6030187106d1b83238a3ce1c99c5")
+        );
+    }
+
+    #[tokio::test]
+    async fn captures_version_without_kex() {
+        let (mut sniffer, hello) = Sniffer::new(b"SSH-2.0-Go\r\n".as_slice(), server_algorithms());
+        sniffer.read_to_end(&mut Vec::new()).await.unwrap();
+
+        let hello = hello.lock();
+        assert_eq!(hello.version.as_deref(), Some("SSH-2.0-Go"));
+        assert_eq!(hello.algorithms, None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Liveness and readiness checks served over HTTP, for service managers, orchestrators and uptime
+//! monitors to tell whether the server is up and keeping on top of its audit log.
+
+use std::{
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::watch,
+};
+use tracing::{debug, info};
+
+use crate::{audit::AuditSender, config::Config, state::State, tcp};
+
+/// How long a client has to send its request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Binds the health check listener, if one is configured. This is done alongside the SSH
+/// listeners so it can be on a privileged port.
+pub async fn bind(addr: Option<SocketAddr>) -> std::io::Result<Option<TcpListener>> {
+    let Some(addr) = addr else {
+        return Ok(None);
+    };
+
+    info!("Serving health checks on {addr}");
+    TcpListener::bind(addr).await.map(Some)
+}
+
+/// Answers each request on `listener` with the server's health. Never returns.
+pub async fn serve(
+    listener: Option<TcpListener>,
+    state: Arc<State>,
+    audit_send: AuditSender,
+    config: watch::Receiver<Arc<Config>>,
+) -> std::io::Result<()> {
+    let Some(listener) = listener else {
+        return futures::future::pending().await;
+    };
+
+    loop {
+        let (mut stream, remote) = tcp::accept(&listener).await;
+
+        let status = Status {
+            listeners: state.listeners.load(Ordering::Relaxed),
+            audit_backlog: audit_send.backlog(),
+            max_audit_backlog: config.borrow().max_audit_backlog,
+        };
+
+        tokio::spawn(async move {
+            let mut request = [0; 1024];
+            let Ok(Ok(read)) =
+                tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut request)).await
+            else {
+                debug!(%remote, "Health check request timed out");
+                return;
+            };
+
+            let path = std::str::from_utf8(&request[..read])
+                .ok()
+                .and_then(|request| request.split_whitespace().nth(1))
+                .unwrap_or_default();
+
+            let (status, body) = respond(path, &status);
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+
+            let _res = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// The state of the server at the time of a request.
+#[derive(Serialize)]
+struct Status {
+    listeners: usize,
+    audit_backlog: usize,
+    #[serde(skip)]
+    max_audit_backlog: usize,
+}
+
+impl Status {
+    /// Whether the server is accepting connections and writing audit logs as fast as they
+    /// come in.
+    fn ready(&self) -> bool {
+        self.listeners > 0 && self.audit_backlog <= self.max_audit_backlog
+    }
+}
+
+#[derive(Serialize)]
+struct Response<'a> {
+    status: &'static str,
+    #[serde(flatten)]
+    details: &'a Status,
+}
+
+/// Returns the HTTP status line and body to answer a request for `path` with.
+fn respond(path: &str, status: &Status) -> (&'static str, String) {
+    let ok = match path.split('?').next().unwrap_or_default() {
+        "/health" => true,
+        "/ready" => status.ready(),
+        _ => return ("404 Not Found", String::new()),
+    };
+
+    let body = serde_json::to_string(&Response {
+        status: if ok { "ok" } else { "unavailable" },
+        details: status,
+    })
+    .unwrap_or_default();
+
+    if ok {
+        ("200 OK", body)
+    } else {
+        ("503 Service Unavailable", body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::health::{respond, Status};
+
+    #[test_case("/health", 1, 0, "200 OK"; "healthy")]
+    #[test_case("/health", 0, 20, "200 OK"; "alive while not ready")]
+    #[test_case("/ready", 2, 10, "200 OK"; "ready")]
+    #[test_case("/ready?verbose", 2, 10, "200 OK"; "query string")]
+    #[test_case("/ready", 0, 0, "503 Service Unavailable"; "no listeners")]
+    #[test_case("/ready", 1, 11, "503 Service Unavailable"; "audit backlog")]
+    #[test_case("/metrics", 1, 0, "404 Not Found"; "unknown path")]
+    fn responds(path: &str, listeners: usize, audit_backlog: usize, expected: &str) {
+        let status = Status {
+            listeners,
+            audit_backlog,
+            max_audit_backlog: 10,
+        };
+
+        assert_eq!(respond(path, &status).0, expected);
+    }
+
+    #[test]
+    fn reports_details() {
+        let status = Status {
+            listeners: 0,
+            audit_backlog: 3,
+            max_audit_backlog: 10,
+        };
+
+        assert_eq!(
+            respond("/ready", &status).1,
+            r#"{"status":"unavailable","listeners":0,"audit_backlog":3}"#
+        );
+    }
+}
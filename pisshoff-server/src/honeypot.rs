@@ -0,0 +1,597 @@
+//! Runs the honeypot, either from the `pisshoff-server` binary or from another application
+//! embedding it.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use clap::Parser;
+use futures::FutureExt;
+use itertools::Itertools;
+use thrussh::MethodSet;
+use time::OffsetDateTime;
+use tokio::{
+    net::TcpListener,
+    signal::unix::SignalKind,
+    sync::{oneshot, watch},
+};
+use tracing::{error, info};
+
+use crate::{
+    audit::{self, AuditSink},
+    clock::Clock,
+    command::{self, Command},
+    config::{Args, Config, ConfigFile},
+    geoip::GeoIp,
+    health, live_tail, metrics, payload, privileges, server,
+    state::{Attackers, State},
+    tcp, telemetry, template,
+};
+
+/// Collects everything the honeypot is run with: the commands on top of those built in, and, when
+/// embedding it, its configuration, where it listens and where its audit logs go.
+#[derive(Default)]
+pub struct Builder {
+    commands: command::Registry,
+    config: Option<Config>,
+    listeners: Vec<TcpListener>,
+    audit_sink: Option<Box<dyn AuditSink>>,
+    clock: Clock,
+}
+
+impl Builder {
+    /// Makes `T` available to clients as `name`, replacing any command already available by that
+    /// name, including those built in.
+    #[must_use]
+    pub fn command<T: Command + Debug + Clone + Send + Sync + 'static>(
+        mut self,
+        name: &str,
+    ) -> Self {
+        self.commands.register::<T>(name);
+        self
+    }
+
+    /// Runs the honeypot with `config`, rather than the defaults.
+    #[must_use]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Accepts clients on an already bound `listener`. Once any are given, the `listen-address`
+    /// from the config is ignored.
+    #[must_use]
+    pub fn listener(mut self, listener: TcpListener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// Hands each audit log to `sink` instead of writing it to the audit log file, auditd log and
+    /// audit socket from the config.
+    #[must_use]
+    pub fn audit_sink(mut self, sink: impl AuditSink) -> Self {
+        self.audit_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Has connections take the time from `clock` rather than the system clock, so tests can
+    /// control the time clients see.
+    #[must_use]
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> Server {
+        Server {
+            commands: self.commands,
+            config: self.config,
+            listeners: self.listeners,
+            audit_sink: self.audit_sink,
+            clock: self.clock,
+        }
+    }
+
+    /// Builds the server and runs it, see [`Server::run`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Server::run`].
+    pub async fn run(self) -> anyhow::Result<()> {
+        self.build().run().await
+    }
+}
+
+/// A honeypot ready to be run.
+pub struct Server {
+    commands: command::Registry,
+    config: Option<Config>,
+    listeners: Vec<TcpListener>,
+    audit_sink: Option<Box<dyn AuditSink>>,
+    clock: Clock,
+}
+
+impl Server {
+    #[must_use]
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Runs the honeypot the same way the `pisshoff-server` binary does, configured by the
+    /// command line arguments and the config file they point to rather than any config given to
+    /// the builder. Only returns once the server has been shut down with ctrl-c, or has failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration can't be loaded, the listeners can't be bound, or
+    /// the audit log can't be written to.
+    pub async fn run_from_args(self) -> anyhow::Result<()> {
+        let args = Args::parse();
+        let config = args.config_file.config.clone();
+
+        std::env::set_var("RUST_LOG", args.verbosity());
+
+        let telemetry = telemetry::init(&config)?;
+
+        let listeners = Listeners {
+            ssh: if self.listeners.is_empty() {
+                bind(&config)?
+            } else {
+                self.listeners
+            },
+            metrics: metrics::bind(config.metrics_listen_address).await?,
+            health: health::bind(config.health_listen_address).await?,
+            live_tail: live_tail::bind(config.live_tail.as_ref()).await?,
+        };
+
+        if let Some(user) = &config.user {
+            privileges::drop_privileges(user, config.group.as_deref())?;
+        }
+
+        let shutdown = async {
+            tokio::signal::ctrl_c().await?;
+            info!("Received ctrl-c, initiating shutdown");
+            Ok(())
+        };
+
+        serve(
+            config,
+            listeners,
+            self.commands,
+            self.audit_sink,
+            self.clock,
+            Some(&args.config_file),
+            shutdown,
+        )
+        .await?;
+
+        telemetry.shutdown();
+
+        Ok(())
+    }
+
+    /// Runs the honeypot until it fails, see [`Server::run_until`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Server::run_until`].
+    pub async fn run(self) -> anyhow::Result<()> {
+        self.run_until(futures::future::pending()).await
+    }
+
+    /// Runs the honeypot inside of an application embedding it, until `shutdown` resolves. Unlike
+    /// [`Server::run_from_args`], this leaves tracing, signal handling and the process' user to
+    /// the application.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listeners can't be bound, or the audit log can't be written to.
+    pub async fn run_until(self, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+        let config = Arc::new(self.config.unwrap_or_default());
+
+        let listeners = Listeners {
+            ssh: if self.listeners.is_empty() {
+                listen(&config)?
+            } else {
+                self.listeners
+            },
+            metrics: metrics::bind(config.metrics_listen_address).await?,
+            health: health::bind(config.health_listen_address).await?,
+            live_tail: live_tail::bind(config.live_tail.as_ref()).await?,
+        };
+
+        let shutdown = async {
+            shutdown.await;
+            info!("Shutdown requested, stopping {}", env!("CARGO_CRATE_NAME"));
+            Ok(())
+        };
+
+        serve(
+            config,
+            listeners,
+            self.commands,
+            self.audit_sink,
+            self.clock,
+            None,
+            shutdown,
+        )
+        .await
+    }
+}
+
+/// Everything the honeypot listens on, bound before it starts serving so privileges can be
+/// dropped in between.
+struct Listeners {
+    ssh: Vec<TcpListener>,
+    metrics: Option<TcpListener>,
+    health: Option<TcpListener>,
+    live_tail: Option<TcpListener>,
+}
+
+/// Serves clients on `listeners` until `shutdown` resolves, then finishes writing the audit logs.
+/// The config is reloaded from `config_file` on SIGHUP, if one is given.
+#[allow(clippy::too_many_lines)]
+async fn serve(
+    config: Arc<Config>,
+    listeners: Listeners,
+    commands: command::Registry,
+    audit_sink: Option<Box<dyn AuditSink>>,
+    clock: Clock,
+    config_file: Option<&ConfigFile>,
+    shutdown: impl Future<Output = anyhow::Result<()>>,
+) -> anyhow::Result<()> {
+    let hostname = Box::leak(
+        nix::unistd::gethostname()?
+            .into_string()
+            .map_err(|_| anyhow!("invalid hostname"))?
+            .into_boxed_str(),
+    );
+    let keys = vec![thrussh_keys::key::KeyPair::generate_ed25519().unwrap()];
+
+    let thrussh_config = Arc::new(thrussh::server::Config {
+        server_id: config.server_id.clone(),
+        methods: server::AUTH_METHODS
+            .iter()
+            .fold(MethodSet::empty(), |methods, (_, method)| methods | *method),
+        keys,
+        // rejections are delayed by the handlers instead, so each method can take its own time
+        auth_rejection_time: Duration::ZERO,
+        // thrussh only takes a static banner, this is only built once so it can be leaked
+        auth_banner: config.auth_banner.as_deref().map(|banner| {
+            let hostname = config
+                .profile
+                .profile()
+                .hostname
+                .unwrap_or(server::NODE_NAME);
+            let context = template::Context {
+                hostname,
+                username: "",
+                ip: None,
+                now: OffsetDateTime::now_utc(),
+                rng: &fastrand::Rng::new(),
+            };
+            &*template::render(banner, &context).leak()
+        }),
+        ..thrussh::server::Config::default()
+    });
+
+    let (reload_send, reload_recv) = watch::channel(());
+    let (shutdown_send, shutdown_recv) = oneshot::channel();
+
+    let (audit_send, audit_handle) =
+        audit::start_audit_writer(config.clone(), audit_sink, reload_recv, shutdown_recv);
+    let mut audit_handle = audit_handle.fuse();
+
+    let payload_send = config
+        .payload_directory
+        .clone()
+        .map(payload::start_payload_writer);
+
+    let state = Arc::new(State {
+        commands,
+        attackers: match &config.attacker_profiles {
+            Some(path) => Attackers::load(path)?,
+            None => Attackers::default(),
+        },
+        geoip: match &config.geoip_database {
+            Some(path) => load_geoip(path)?,
+            None => GeoIp::default(),
+        },
+        clock,
+        ..State::default()
+    });
+
+    let (config_send, config_recv) = watch::channel(config.clone());
+
+    let command_summariser = metrics::summarise_commands(
+        state.clone(),
+        hostname,
+        config.command_summary_interval,
+        audit_send.clone(),
+    );
+    let metrics_server = metrics::serve(listeners.metrics, state.clone());
+    let health_server = health::serve(
+        listeners.health,
+        state.clone(),
+        audit_send.clone(),
+        config_recv.clone(),
+    );
+
+    let live_tail_server = live_tail::serve(listeners.live_tail, state.clone(), config.clone());
+
+    let server = server::Server::new(
+        hostname,
+        config_recv,
+        state.clone(),
+        audit_send,
+        payload_send,
+    );
+
+    // TODO: needs clean shutdowns on clients
+    let fut = futures::future::try_join_all(
+        listeners
+            .ssh
+            .into_iter()
+            .map(|listener| server::run(thrussh_config.clone(), listener, server.clone())),
+    );
+
+    let shutdown_watcher = watch_for_shutdown(shutdown, shutdown_send);
+    let reload_watcher = async {
+        if let Some(config_file) = config_file {
+            watch_for_reloads(reload_send, config_file, config_send).await
+        } else {
+            // keep the senders alive, so the config and audit writer don't see them closed
+            let _senders = (reload_send, config_send);
+            futures::future::pending().await
+        }
+    };
+    let profile_saver = save_attacker_profiles(&state, config.attacker_profiles.clone());
+
+    tokio::select! {
+        res = fut => {
+            res?;
+        }
+        res = &mut audit_handle => res??,
+        res = shutdown_watcher => res?,
+        res = reload_watcher => res?,
+        () = profile_saver => {}
+        () = command_summariser => {}
+        res = metrics_server => res?,
+        res = health_server => res?,
+        res = live_tail_server => res?,
+    }
+
+    info!("Finishing audit log writes");
+    audit_handle.await??;
+    info!("Audit log writes finished");
+
+    if let Some(path) = &config.attacker_profiles {
+        state.attackers.save(path).await?;
+        info!("Attacker profiles saved");
+    }
+
+    Ok(())
+}
+
+/// Takes the listening sockets passed to us by systemd if we were socket activated, otherwise
+/// binds to each configured address.
+fn bind(config: &Config) -> anyhow::Result<Vec<TcpListener>> {
+    if let Some(listeners) = tcp::inherited()? {
+        info!(
+            "{} listening on {} socket(s) passed by systemd",
+            env!("CARGO_CRATE_NAME"),
+            listeners.len()
+        );
+        return Ok(listeners);
+    }
+
+    listen(config)
+}
+
+/// Binds to each address in the config's `listen-address`.
+fn listen(config: &Config) -> anyhow::Result<Vec<TcpListener>> {
+    if config.listen_address.is_empty() {
+        return Err(anyhow!("listen-address must contain at least one address"));
+    }
+
+    info!(
+        "{} listening on {}",
+        env!("CARGO_CRATE_NAME"),
+        config.listen_address.iter().join(", ")
+    );
+
+    config
+        .listen_address
+        .iter()
+        .map(|addr| {
+            // when listening on both IPv4 and IPv6 on the same port, the IPv6 socket needs to stop
+            // claiming IPv4 connections for itself or the two binds will conflict
+            let v6_only = addr.is_ipv6()
+                && config
+                    .listen_address
+                    .iter()
+                    .any(|other| other.is_ipv4() && other.port() == addr.port());
+
+            Ok(tcp::listen(*addr, v6_only)?)
+        })
+        .collect()
+}
+
+async fn watch_for_shutdown(
+    signal: impl Future<Output = anyhow::Result<()>>,
+    send: oneshot::Sender<()>,
+) -> Result<(), anyhow::Error> {
+    signal.await?;
+
+    let _res = send.send(());
+
+    Ok(())
+}
+
+fn load_geoip(path: &Path) -> anyhow::Result<GeoIp> {
+    let geoip = GeoIp::load(path)
+        .map_err(|e| anyhow!("failed to load GeoIP database {}: {e}", path.display()))?;
+    info!("Loaded {} ranges from {}", geoip.len(), path.display());
+    Ok(geoip)
+}
+
+/// Periodically writes the attacker profiles to disk so they survive a crash, never returning.
+async fn save_attacker_profiles(state: &State, path: Option<PathBuf>) {
+    let Some(path) = path else {
+        return futures::future::pending().await;
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_mins(1));
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = state.attackers.save(&path).await {
+            error!(
+                "Failed to save attacker profiles to {}: {e}",
+                path.display()
+            );
+        }
+    }
+}
+
+async fn watch_for_reloads(
+    send: watch::Sender<()>,
+    config_file: &ConfigFile,
+    config_send: watch::Sender<Arc<Config>>,
+) -> Result<(), anyhow::Error> {
+    let mut signal = tokio::signal::unix::signal(SignalKind::hangup())?;
+
+    while let Some(()) = signal.recv().await {
+        info!("Received SIGHUP, reloading {}", config_file.path.display());
+
+        let current = config_send.borrow().clone();
+        match config_file.reload(&current) {
+            Ok(config) => {
+                config_send.send_replace(Arc::new(config));
+                info!("Configuration reloaded, changes will apply to new connections");
+            }
+            Err(e) => {
+                error!("Failed to reload configuration, keeping the current one: {e}");
+            }
+        }
+
+        info!("Broadcasting reload");
+        let _res = send.send(());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use time::macros::datetime;
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, oneshot},
+        time::timeout,
+    };
+
+    use crate::{
+        command::{
+            async_trait, ChannelId, Command, CommandResult, ConnectionState, ThrusshSession,
+        },
+        server::{test::fake_channel_id, MockThrusshSession},
+        Clock, Config, Server,
+    };
+
+    #[derive(Debug, Clone)]
+    struct Custom;
+
+    #[async_trait]
+    impl Command for Custom {
+        async fn new<S: ThrusshSession + Send>(
+            _connection: &mut ConnectionState,
+            _params: &[String],
+            _channel: ChannelId,
+            _session: &mut S,
+        ) -> CommandResult<Self> {
+            CommandResult::Exit(7)
+        }
+
+        async fn stdin<S: ThrusshSession + Send>(
+            self,
+            _connection: &mut ConnectionState,
+            _channel: ChannelId,
+            _data: &[u8],
+            _session: &mut S,
+        ) -> CommandResult<Self> {
+            CommandResult::Exit(7)
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_pre_bound_listeners_until_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (log_send, mut log_recv) = mpsc::unbounded_channel();
+        let (shutdown_send, shutdown_recv) = oneshot::channel::<()>();
+
+        let server = tokio::spawn(
+            Server::builder()
+                .config(Config::default())
+                .listener(listener)
+                .audit_sink(move |log| {
+                    let _res = log_send.send(log);
+                })
+                .clock(Clock::fixed(datetime!(2024-05-02 13:37:00 UTC)))
+                .build()
+                .run_until(async {
+                    let _res = shutdown_recv.await;
+                }),
+        );
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"SSH-2.0-test\r\n").await.unwrap();
+        drop(client);
+
+        let log = timeout(Duration::from_secs(5), log_recv.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(log.client_version.as_deref(), Some("SSH-2.0-test"));
+        assert_eq!(log.ts, datetime!(2024-05-02 13:37:00 UTC));
+
+        shutdown_send.send(()).unwrap();
+        timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn registers_commands_alongside_builtins() {
+        let server = Server::builder()
+            .command::<Custom>("custom")
+            .command::<Custom>("echo")
+            .build();
+
+        let mut state = ConnectionState::mock();
+        let mut session = MockThrusshSession::default();
+
+        for name in ["custom", "echo"] {
+            let factory = server.commands.get(name.as_bytes()).unwrap();
+            let out = factory(&mut state, &[], fake_channel_id(), &mut session).await;
+            assert!(matches!(out, CommandResult::Exit(7)), "{name}");
+        }
+
+        assert!(server.commands.get(b"ls").is_some());
+    }
+}
@@ -0,0 +1,110 @@
+//! Fake files that raise an alert when the client touches them, such as cloud credentials or
+//! cryptocurrency wallets. Each connection gets its own token templated into the files' content,
+//! so if the content turns up again elsewhere it can be traced back to where it was taken from.
+
+use std::{fmt::Write as _, time::Duration};
+
+use pisshoff_http as http;
+use tracing::warn;
+use uuid::Uuid;
+
+/// How long the webhook has to accept the alert before it's given up on.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The token for a connection, in the style of the random part of an AWS access key ID.
+pub fn token(connection_id: Uuid) -> String {
+    connection_id.as_bytes()[..8]
+        .iter()
+        .fold(String::new(), |mut out, v| {
+            let _ = write!(out, "{v:02X}");
+            out
+        })
+}
+
+/// Fills in the placeholders in a honeytoken's configured content.
+pub fn render(content: &str, token: &str, connection_id: Uuid) -> String {
+    content
+        .replace("{token}", token)
+        .replace("{connection-id}", &connection_id.to_string())
+}
+
+/// POSTs `body` as JSON to `url`, logging rather than returning any failure since there's
+/// nothing the connection can do about it.
+pub async fn send_webhook(url: String, body: serde_json::Value) {
+    let res = tokio::time::timeout(WEBHOOK_TIMEOUT, post(&url, &body.to_string())).await;
+
+    match res {
+        Ok(Ok(status)) if http::is_success(&status) => {}
+        Ok(Ok(status)) => warn!(url, status, "Honeytoken webhook rejected alert"),
+        Ok(Err(error)) => warn!(url, %error, "Failed to send honeytoken webhook"),
+        Err(_) => warn!(url, "Honeytoken webhook timed out"),
+    }
+}
+
+/// Sends the request, returning the status line of the response.
+async fn post(url: &str, body: &str) -> std::io::Result<String> {
+    http::Client::shared()
+        .request("POST", url, &[("Content-Type", "application/json")], body)
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use uuid::Uuid;
+
+    use crate::honeytoken::{post, render, token};
+
+    #[test]
+    fn renders_content() {
+        let connection_id = Uuid::from_u128(0x0123_4567_89ab_cdef_0000_0000_0000_0000);
+        let token = token(connection_id);
+
+        assert_eq!(token, "0123456789ABCDEF");
+        assert_eq!(
+            render(
+                "aws_access_key_id = AKIA{token}\n# {connection-id}\n",
+                &token,
+                connection_id
+            ),
+            "aws_access_key_id = AKIA0123456789ABCDEF\n# 01234567-89ab-cdef-0000-000000000000\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/alert", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"{}") {
+                let mut buf = [0; 1024];
+                let len = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let status = post(&url, "{}").await.unwrap();
+        let request = server.await.unwrap();
+
+        assert_eq!(status, "HTTP/1.1 204 No Content");
+        assert!(request.starts_with("POST /alert HTTP/1.1\r\n"), "{request}");
+        assert!(request.ends_with("\r\n\r\n{}"), "{request}");
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_scheme() {
+        assert!(post("ftp://127.0.0.1/", "{}").await.is_err());
+    }
+}
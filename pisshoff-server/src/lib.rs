@@ -0,0 +1,50 @@
+//! An SSH honeypot that accepts logins, emulates enough of a Linux shell to keep the client
+//! interested, and audits everything they do.
+//!
+//! The server can be run with commands of its own on top of the built-in ones, by implementing
+//! [`command::Command`] and registering them with [`Server::builder`]. The same builder lets it be
+//! embedded in another application, listening on sockets the application has already bound and
+//! handing the audit logs to an [`AuditSink`] of its choosing.
+
+#![deny(clippy::pedantic)]
+#![allow(clippy::module_name_repetitions)]
+
+mod anti_forensics;
+mod archive;
+mod audit;
+mod authorized_keys;
+mod clock;
+pub mod command;
+mod config;
+mod detection;
+mod file_system;
+mod geoip;
+mod handshake;
+mod health;
+mod honeypot;
+mod honeytoken;
+mod live_tail;
+mod memory;
+mod metadata;
+mod metrics;
+mod miner;
+mod payload;
+mod privileges;
+mod profile;
+mod rate_limit;
+mod server;
+mod state;
+mod subsystem;
+mod tcp;
+mod telemetry;
+mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod traffic;
+
+pub use crate::{
+    audit::{AuditLog, AuditSink},
+    clock::Clock,
+    config::Config,
+    honeypot::{Builder, Server},
+};
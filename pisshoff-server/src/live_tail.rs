@@ -0,0 +1,650 @@
+//! Streams audit events over HTTP as they're recorded, rather than once the connection closes,
+//! for live dashboards and watching clients as they type. `GET /events` is answered with
+//! Server-Sent Events, or with a WebSocket if the client asks to upgrade to one.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use pisshoff_types::audit::{AuditLog, AuditLogEvent};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{tcp::OwnedReadHalf, TcpListener, TcpStream},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc, Semaphore,
+    },
+};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    audit::redact,
+    config::{Config, LiveTail},
+    state::State,
+    tcp,
+};
+
+/// Events held on to for each client, a client falling further behind than this misses events
+/// and is told how many.
+const BUFFERED_EVENTS: usize = 1024;
+
+/// How long a client has to send its request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest request accepted, which only needs room for the request line and a few headers.
+const MAX_REQUEST_LENGTH: usize = 8192;
+
+/// How often something is sent to idle clients, so connections that have gone away are noticed
+/// and proxies don't time them out.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The largest WebSocket frame accepted from a client, which should only ever send control
+/// frames.
+const MAX_FRAME_LENGTH: u64 = 65536;
+
+/// Appended to the client's key to prove the server understood the WebSocket handshake, as
+/// fixed by RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// Events recorded by every connection, serialised once for however many clients are watching.
+pub struct LiveEvents(broadcast::Sender<Arc<str>>);
+
+impl Default for LiveEvents {
+    fn default() -> Self {
+        Self(broadcast::channel(BUFFERED_EVENTS).0)
+    }
+}
+
+impl LiveEvents {
+    /// Whether any clients are watching, so events aren't serialised for nobody.
+    pub fn is_watched(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    /// Sends `event` from `log` to every client watching, unless it's left out by the logging
+    /// preset. Passwords and uploads are redacted the same as they are in the audit log.
+    pub fn publish(&self, log: &AuditLog, event: &AuditLogEvent, config: &Config) {
+        if !config.logging_preset.includes(&event.action) {
+            return;
+        }
+
+        let mut event = event.clone();
+        redact::redact_action(&mut event.action, &config.redaction);
+
+        let event = LiveEvent {
+            connection_id: log.connection_id,
+            peer_address: log.peer_address,
+            host: &log.host,
+            ts: log.ts + event.start_offset,
+            event: &event,
+        };
+
+        if let Ok(event) = serde_json::to_string(&event) {
+            let _res = self.0.send(event.into());
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Arc<str>> {
+        self.0.subscribe()
+    }
+}
+
+/// A single event, along with enough of its connection to tell it apart from the others.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct LiveEvent<'a> {
+    connection_id: Uuid,
+    peer_address: Option<SocketAddr>,
+    host: &'a str,
+    #[serde(with = "time::serde::rfc3339")]
+    ts: OffsetDateTime,
+    #[serde(flatten)]
+    event: &'a AuditLogEvent,
+}
+
+/// Binds the live tail listener, if one is configured. This is done alongside the SSH listeners
+/// so it can be on a privileged port.
+pub async fn bind(config: Option<&LiveTail>) -> std::io::Result<Option<TcpListener>> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+
+    info!("Serving live audit events on {}", config.listen_address);
+    TcpListener::bind(config.listen_address).await.map(Some)
+}
+
+/// Streams events to each client connecting to `listener`. Never returns.
+pub async fn serve(
+    listener: Option<TcpListener>,
+    state: Arc<State>,
+    config: Arc<Config>,
+) -> std::io::Result<()> {
+    let (Some(listener), Some(config)) = (listener, config.live_tail.clone()) else {
+        return futures::future::pending().await;
+    };
+
+    let config = Arc::new(config);
+    let clients = Arc::new(Semaphore::new(config.max_clients));
+
+    loop {
+        let (stream, remote) = tcp::accept(&listener).await;
+        tokio::spawn(handle(
+            stream,
+            remote,
+            state.clone(),
+            config.clone(),
+            clients.clone(),
+        ));
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    remote: SocketAddr,
+    state: Arc<State>,
+    config: Arc<LiveTail>,
+    clients: Arc<Semaphore>,
+) {
+    let Ok(Some(request)) = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .map(Result::ok)
+        .map(Option::flatten)
+    else {
+        debug!(%remote, "Live tail request timed out or was malformed");
+        return;
+    };
+
+    let cors = config
+        .allow_origin
+        .as_deref()
+        .map(|origin| format!("Access-Control-Allow-Origin: {origin}\r\n"))
+        .unwrap_or_default();
+
+    if request.method != "GET" || request.path() != "/events" {
+        let _res = stream.write_all(&error("404 Not Found", &cors)).await;
+        return;
+    }
+
+    if !request.is_authorised(config.token.as_deref()) {
+        let _res = stream.write_all(&error("401 Unauthorized", &cors)).await;
+        return;
+    }
+
+    let Ok(_permit) = clients.try_acquire_owned() else {
+        let _res = stream
+            .write_all(&error("503 Service Unavailable", &cors))
+            .await;
+        return;
+    };
+
+    // subscribed before the response is sent, so the client doesn't miss anything recorded as
+    // soon as it sees the response
+    let events = state.live_events.subscribe();
+
+    let websocket_key = request
+        .header("upgrade")
+        .filter(|v| v.eq_ignore_ascii_case("websocket"))
+        .and_then(|_| request.header("sec-websocket-key"));
+
+    debug!(%remote, websocket = websocket_key.is_some(), "Live tail client connected");
+
+    if let Some(key) = websocket_key {
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: \
+             Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+
+        if stream.write_all(response.as_bytes()).await.is_ok() {
+            stream_websocket(stream, events).await;
+        }
+    } else {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: \
+             no-cache\r\n{cors}Connection: close\r\n\r\n"
+        );
+
+        if stream.write_all(response.as_bytes()).await.is_ok() {
+            stream_server_sent_events(stream, events).await;
+        }
+    }
+
+    debug!(%remote, "Live tail client disconnected");
+}
+
+/// A request's line and headers, with header names lowercased.
+struct Request {
+    method: String,
+    target: String,
+    headers: HashMap<String, String>,
+}
+
+impl Request {
+    fn parse(head: &str) -> Option<Self> {
+        let mut lines = head.lines();
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let target = request_line.next()?.to_string();
+
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+
+        Some(Self {
+            method,
+            target,
+            headers,
+        })
+    }
+
+    fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// Whether the request carries `token` as a bearer token or in the `token` query parameter,
+    /// since browsers can't set headers on an `EventSource` or WebSocket.
+    fn is_authorised(&self, token: Option<&str>) -> bool {
+        let Some(token) = token else {
+            return true;
+        };
+
+        let bearer = self
+            .header("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let query = self
+            .target
+            .split_once('?')
+            .into_iter()
+            .flat_map(|(_, query)| query.split('&'))
+            .find_map(|pair| pair.strip_prefix("token="));
+
+        bearer == Some(token) || query == Some(token)
+    }
+}
+
+/// Reads the request line and headers, returning `None` if they don't fit in
+/// [`MAX_REQUEST_LENGTH`] or the connection is closed first.
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<Request>> {
+    let mut head = Vec::new();
+
+    loop {
+        let mut buf = [0; 1024];
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        head.extend_from_slice(&buf[..read]);
+
+        if let Some(end) = head.windows(4).position(|v| v == b"\r\n\r\n") {
+            return Ok(std::str::from_utf8(&head[..end])
+                .ok()
+                .and_then(Request::parse));
+        }
+
+        if head.len() > MAX_REQUEST_LENGTH {
+            return Ok(None);
+        }
+    }
+}
+
+fn error(status: &str, cors: &str) -> Vec<u8> {
+    format!("HTTP/1.1 {status}\r\n{cors}Content-Length: 0\r\nConnection: close\r\n\r\n")
+        .into_bytes()
+}
+
+/// Sends each event as a message, until the client goes away. Clients that fall behind are sent
+/// a `dropped` event with the number of events they missed.
+async fn stream_server_sent_events(
+    mut stream: TcpStream,
+    mut events: broadcast::Receiver<Arc<str>>,
+) {
+    let mut keepalive = keepalive();
+
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => format!("data: {event}\n\n"),
+                Err(RecvError::Lagged(dropped)) => format!("event: dropped\ndata: {dropped}\n\n"),
+                Err(RecvError::Closed) => return,
+            },
+            _ = keepalive.tick() => ":\n\n".to_string(),
+        };
+
+        if stream.write_all(message.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Ticks every [`KEEPALIVE_INTERVAL`], starting one interval from now so a client doesn't get a
+/// keepalive before the first event.
+fn keepalive() -> tokio::time::Interval {
+    tokio::time::interval_at(
+        tokio::time::Instant::now() + KEEPALIVE_INTERVAL,
+        KEEPALIVE_INTERVAL,
+    )
+}
+
+/// What the client has asked for over the WebSocket.
+enum Control {
+    Ping(Vec<u8>),
+    Close,
+}
+
+/// Sends each event as a text message, until the client closes the WebSocket. Clients that fall
+/// behind are sent `{"dropped":n}` with the number of events they missed.
+async fn stream_websocket(stream: TcpStream, mut events: broadcast::Receiver<Arc<str>>) {
+    let (mut read, mut write) = stream.into_split();
+
+    let (control_send, mut control_recv) = mpsc::channel(4);
+    let reader = tokio::spawn(async move {
+        loop {
+            let control = match read_frame(&mut read).await {
+                Ok((OPCODE_PING, payload)) => Control::Ping(payload),
+                Ok((OPCODE_CLOSE, _)) | Err(_) => Control::Close,
+                Ok(_) => continue,
+            };
+
+            let close = matches!(control, Control::Close);
+            if control_send.send(control).await.is_err() || close {
+                return;
+            }
+        }
+    });
+
+    let mut keepalive = keepalive();
+
+    loop {
+        let frame = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => frame(OPCODE_TEXT, event.as_bytes()),
+                Err(RecvError::Lagged(dropped)) => {
+                    frame(OPCODE_TEXT, format!("{{\"dropped\":{dropped}}}").as_bytes())
+                }
+                Err(RecvError::Closed) => frame(OPCODE_CLOSE, &[]),
+            },
+            control = control_recv.recv() => match control {
+                Some(Control::Ping(payload)) => frame(OPCODE_PONG, &payload),
+                Some(Control::Close) | None => frame(OPCODE_CLOSE, &[]),
+            },
+            _ = keepalive.tick() => frame(OPCODE_PING, &[]),
+        };
+
+        if write.write_all(&frame).await.is_err() || frame[0] & 0x0f == OPCODE_CLOSE {
+            break;
+        }
+    }
+
+    reader.abort();
+}
+
+/// Reads a single frame sent by the client, returning its opcode and unmasked payload.
+async fn read_frame(read: &mut OwnedReadHalf) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    read.read_exact(&mut head).await?;
+
+    let len = match head[1] & 0x7f {
+        126 => u64::from(read.read_u16().await?),
+        127 => read.read_u64().await?,
+        len => u64::from(len),
+    };
+
+    if len > MAX_FRAME_LENGTH {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "websocket frame too long",
+        ));
+    }
+
+    let mut mask = [0; 4];
+    if head[1] & 0x80 != 0 {
+        read.read_exact(&mut mask).await?;
+    }
+
+    let mut payload = vec![0; usize::try_from(len).unwrap_or_default()];
+    read.read_exact(&mut payload).await?;
+
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok((head[0] & 0x0f, payload))
+}
+
+/// A single unmasked frame, as servers send them.
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+
+    let len = payload.len();
+    if len < 126 {
+        frame.push(u8::try_from(len).unwrap_or_default());
+    } else if let Ok(len) = u16::try_from(len) {
+        frame.push(126);
+        frame.extend_from_slice(&len.to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&u64::try_from(len).unwrap_or_default().to_be_bytes());
+    }
+
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// The `Sec-WebSocket-Accept` answering a client's `Sec-WebSocket-Key`.
+fn accept_key(key: &str) -> String {
+    BASE64.encode(
+        sha1_smol::Sha1::from(format!("{key}{WEBSOCKET_GUID}"))
+            .digest()
+            .bytes(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use pisshoff_types::audit::{AuditLog, AuditLogAction, ExecCommandEvent, LoginAttemptEvent};
+    use test_case::test_case;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use crate::{
+        config::Config,
+        live_tail::{accept_key, bind, frame, serve},
+        state::State,
+    };
+
+    #[test]
+    fn accepts_websocket_key() {
+        // the example handshake from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test_case(0, &[0x81, 0]; "empty")]
+    #[test_case(125, &[0x81, 125]; "short")]
+    #[test_case(126, &[0x81, 126, 0, 126]; "medium")]
+    #[test_case(65536, &[0x81, 127, 0, 0, 0, 0, 0, 1, 0, 0]; "long")]
+    fn frames_payload(len: usize, head: &[u8]) {
+        let frame = frame(0x1, &vec![b'a'; len]);
+        assert_eq!(&frame[..head.len()], head);
+        assert_eq!(frame.len(), head.len() + len);
+    }
+
+    /// Starts serving the live tail for `state`, returning the address it's listening on.
+    async fn start(state: Arc<State>) -> String {
+        let config: Config = toml::from_str(
+            "[live-tail]\n\
+             listen-address = \"127.0.0.1:0\"\n\
+             token = \"secret\"\n\
+             [redaction]\n\
+             passwords = \"redact\"",
+        )
+        .unwrap();
+
+        let listener = bind(config.live_tail.as_ref()).await.unwrap().unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(Some(listener), state, Arc::new(config)));
+        addr
+    }
+
+    /// Records `action` on a connection, as soon as the client is watching.
+    async fn publish(state: &State, action: AuditLogAction) {
+        while !state.live_events.is_watched() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let config: Config = toml::from_str("[redaction]\npasswords = \"redact\"").unwrap();
+        let mut log = AuditLog {
+            peer_address: Some("192.0.2.1:1234".parse().unwrap()),
+            ..AuditLog::default()
+        };
+        log.push_action(action);
+        state
+            .live_events
+            .publish(&log, log.events.last().unwrap(), &config);
+    }
+
+    /// Reads from `stream` until `expected` has been received, returning everything read.
+    async fn read_until(stream: &mut TcpStream, expected: &str) -> String {
+        let mut received = Vec::new();
+
+        while !String::from_utf8_lossy(&received).contains(expected) {
+            let mut buf = [0; 4096];
+            let len = stream.read(&mut buf).await.unwrap();
+            assert_ne!(len, 0, "{}", String::from_utf8_lossy(&received));
+            received.extend_from_slice(&buf[..len]);
+        }
+
+        String::from_utf8_lossy(&received).into_owned()
+    }
+
+    #[tokio::test]
+    async fn streams_server_sent_events() {
+        let state = Arc::new(State::default());
+        let addr = start(state.clone()).await;
+
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        stream
+            .write_all(b"GET /events?token=secret HTTP/1.1\r\nHost: pisshoff\r\n\r\n")
+            .await
+            .unwrap();
+
+        publish(
+            &state,
+            AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword {
+                username: "root".into(),
+                password: "hunter2".into(),
+            }),
+        )
+        .await;
+
+        let received = read_until(&mut stream, "}}\n\n").await;
+        assert!(received.starts_with("HTTP/1.1 200 OK\r\n"), "{received}");
+        assert!(
+            received.contains("\r\nContent-Type: text/event-stream\r\n"),
+            "{received}"
+        );
+
+        let event = received
+            .lines()
+            .find_map(|line| line.strip_prefix("data: "))
+            .unwrap();
+        assert!(
+            event.contains("\"peer-address\":\"192.0.2.1:1234\""),
+            "{event}"
+        );
+        assert!(event.contains("\"password\":\"[redacted]\""), "{event}");
+    }
+
+    #[test_case("GET /events HTTP/1.1\r\n\r\n", "401 Unauthorized"; "no token")]
+    #[test_case("GET /events?token=wrong HTTP/1.1\r\n\r\n", "401 Unauthorized"; "wrong token")]
+    #[test_case("GET /logs HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n", "404 Not Found"; "unknown path")]
+    #[tokio::test]
+    async fn refuses_requests(request: &str, status: &str) {
+        let addr = start(Arc::new(State::default())).await;
+
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(
+            response.starts_with(&format!("HTTP/1.1 {status}\r\n")),
+            "{response}"
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_websocket() {
+        let state = Arc::new(State::default());
+        let addr = start(state.clone()).await;
+
+        let mut stream = TcpStream::connect(&addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET /events HTTP/1.1\r\nHost: pisshoff\r\nAuthorization: Bearer secret\r\n\
+                  Upgrade: websocket\r\nConnection: Upgrade\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let response = read_until(&mut stream, "\r\n\r\n").await;
+        assert!(
+            response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"),
+            "{response}"
+        );
+        assert!(
+            response.contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"),
+            "{response}"
+        );
+
+        publish(
+            &state,
+            AuditLogAction::ExecCommand(ExecCommandEvent {
+                command: "uname -a".into(),
+                args: None,
+                interactive: true,
+                pty: true,
+            }),
+        )
+        .await;
+
+        let mut head = [0; 2];
+        stream.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[0], 0x81);
+        let len = match head[1] {
+            126 => usize::from(stream.read_u16().await.unwrap()),
+            len => usize::from(len),
+        };
+
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).await.unwrap();
+        let payload = String::from_utf8(payload).unwrap();
+        assert!(payload.contains("\"command\":\"uname -a\""), "{payload}");
+
+        // a masked close frame, which should be answered with one of our own
+        stream.write_all(&[0x88, 0x80, 1, 2, 3, 4]).await.unwrap();
+        let mut close = Vec::new();
+        stream.read_to_end(&mut close).await.unwrap();
+        assert_eq!(close, [0x88, 0]);
+    }
+}
@@ -0,0 +1,170 @@
+//! Accounting for memory held in buffers the client decides the size of, such as partially
+//! received SCP and SFTP transfers, so neither a single connection nor thousands of them at once
+//! can grow the server without bound.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// The memory a connection's buffers may hold, counted against both the connection's own limit
+/// and the limit shared by every connection. Clones share the same counts.
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    used: Arc<AtomicUsize>,
+    max: usize,
+    total: Arc<AtomicUsize>,
+    max_total: usize,
+}
+
+impl MemoryBudget {
+    /// Creates a budget for a new connection, sharing `total` with every other connection.
+    pub fn new(max: usize, total: Arc<AtomicUsize>, max_total: usize) -> Self {
+        Self {
+            used: Arc::default(),
+            max,
+            total,
+            max_total,
+        }
+    }
+
+    /// Bytes currently held by the connection's buffers.
+    #[cfg(test)]
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn reserve(&self, bytes: usize) -> bool {
+        let add = |counter: &AtomicUsize, max: usize| {
+            counter
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                    v.checked_add(bytes).filter(|v| *v <= max)
+                })
+                .is_ok()
+        };
+
+        if !add(&self.used, self.max) {
+            return false;
+        }
+
+        if !add(&self.total, self.max_total) {
+            self.used.fetch_sub(bytes, Ordering::Relaxed);
+            return false;
+        }
+
+        true
+    }
+
+    /// Accounts for `bytes` regardless of the limits, for growth that's already happened.
+    fn force(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+        self.total.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+        self.total.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// The share of a [`MemoryBudget`] held by a single buffer, given back once it's dropped so
+/// buffers thrown away with whatever owns them are never leaked from the budget.
+#[derive(Debug, Default)]
+pub struct Reservation {
+    budget: Option<MemoryBudget>,
+    bytes: usize,
+}
+
+impl Reservation {
+    /// Grows the reservation to cover a buffer that's about to hold `bytes`, returning false if
+    /// the connection or the server as a whole can't afford it, in which case the buffer
+    /// shouldn't grow.
+    pub fn try_grow(&mut self, budget: &MemoryBudget, bytes: usize) -> bool {
+        let budget = self.budget.get_or_insert_with(|| budget.clone());
+
+        if bytes <= self.bytes {
+            return true;
+        }
+
+        if !budget.reserve(bytes - self.bytes) {
+            return false;
+        }
+
+        self.bytes = bytes;
+        true
+    }
+
+    /// Resizes the reservation to match what the buffer actually holds, such as its capacity
+    /// once it's grown, or to give back what it no longer needs.
+    pub fn sync(&mut self, budget: &MemoryBudget, bytes: usize) {
+        let budget = self.budget.get_or_insert_with(|| budget.clone());
+
+        if bytes > self.bytes {
+            budget.force(bytes - self.bytes);
+        } else {
+            budget.release(self.bytes - bytes);
+        }
+
+        self.bytes = bytes;
+    }
+}
+
+impl Clone for Reservation {
+    /// Reserves the same again, as the buffer it's cloned alongside will be too.
+    fn clone(&self) -> Self {
+        if let Some(budget) = &self.budget {
+            budget.force(self.bytes);
+        }
+
+        Self {
+            budget: self.budget.clone(),
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.release(self.bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{atomic::Ordering, Arc};
+
+    use crate::memory::{MemoryBudget, Reservation};
+
+    #[test]
+    fn holds_connections_to_their_budget() {
+        let total = Arc::default();
+        let budget = MemoryBudget::new(100, Arc::clone(&total), 150);
+        let other = MemoryBudget::new(100, Arc::clone(&total), 150);
+
+        let mut first = Reservation::default();
+        assert!(first.try_grow(&budget, 60));
+        assert!(!first.try_grow(&budget, 101));
+        assert_eq!(budget.used(), 60);
+
+        let mut second = Reservation::default();
+        assert!(second.try_grow(&budget, 40));
+        assert!(!second.try_grow(&budget, 41));
+
+        // the other connection is within its own budget, but not the server's
+        let mut third = Reservation::default();
+        assert!(third.try_grow(&other, 50));
+        assert!(!third.try_grow(&other, 51));
+        assert_eq!(other.used(), 50);
+
+        second.sync(&budget, 10);
+        assert_eq!(budget.used(), 70);
+        assert!(third.try_grow(&other, 80));
+
+        drop(first);
+        drop(second);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(total.load(Ordering::Relaxed), 80);
+    }
+}
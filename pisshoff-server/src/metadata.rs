@@ -0,0 +1,207 @@
+//! A fake of the AWS instance metadata service, answered when `curl` or `wget` are pointed at it
+//! so clients hunting for cloud credentials are handed canaries rather than a connection error.
+
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{config::CloudMetadata, honeytoken};
+
+/// Hosts the metadata service is reachable at from within an instance.
+const HOSTS: &[&str] = &[
+    "169.254.169.254",
+    "[fd00:ec2::254]",
+    "instance-data",
+    "instance-data.ec2.internal",
+];
+
+const INSTANCE_TYPE: &str = "t3.medium";
+const AMI_ID: &str = "ami-0c7217cdde317cfec";
+const LOCAL_IPV4: &str = "172.31.24.7";
+
+const NOT_FOUND: &str = r#"<?xml version="1.0" encoding="iso-8859-1"?>
+<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN"
+         "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en" lang="en">
+ <head>
+  <title>404 - Not Found</title>
+ </head>
+ <body>
+  <h1>404 - Not Found</h1>
+ </body>
+</html>
+"#;
+
+/// Returns true if `host`, as given in a URL, is the metadata service.
+pub fn is_metadata_host(host: &str) -> bool {
+    HOSTS.iter().any(|v| v.eq_ignore_ascii_case(host))
+}
+
+/// An HTTP response from the metadata service.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+    /// Whether the role's credentials were handed out.
+    pub credentials: bool,
+}
+
+impl Response {
+    fn ok(body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/plain",
+            body: body.into(),
+            credentials: false,
+        }
+    }
+
+    fn not_found() -> Self {
+        Self {
+            status: 404,
+            content_type: "text/html",
+            body: NOT_FOUND.to_string(),
+            credentials: false,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        if self.status == 200 {
+            "OK"
+        } else {
+            "Not Found"
+        }
+    }
+}
+
+/// Answers a request for `path`, as the service does regardless of the method or whether an
+/// `IMDSv2` token was sent.
+pub fn respond(
+    path: &str,
+    config: &CloudMetadata,
+    connection_id: Uuid,
+    now: OffsetDateTime,
+) -> Response {
+    let token = honeytoken::token(connection_id);
+    let render = |v: &str| honeytoken::render(v, &token, connection_id);
+
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let path = path.trim_end_matches('/');
+    let role = render(&config.role);
+    let region = &config.region;
+    let instance_id = render(&config.instance_id);
+
+    let Some(rest) = path.strip_prefix("/latest") else {
+        return if path.is_empty() {
+            Response::ok("latest")
+        } else {
+            Response::not_found()
+        };
+    };
+
+    match rest {
+        "" => Response::ok("dynamic\nmeta-data\nuser-data"),
+        "/api/token" => Response::ok(format!("AQAEA{token}{}==", connection_id.simple())),
+        "/meta-data" => Response::ok(
+            "ami-id\nhostname\niam/\ninstance-id\ninstance-type\nlocal-hostname\nlocal-ipv4\nplacement/",
+        ),
+        "/meta-data/ami-id" => Response::ok(AMI_ID),
+        "/meta-data/hostname" | "/meta-data/local-hostname" => Response::ok(format!(
+            "ip-{}.{region}.compute.internal",
+            LOCAL_IPV4.replace('.', "-")
+        )),
+        "/meta-data/instance-id" => Response::ok(instance_id),
+        "/meta-data/instance-type" => Response::ok(INSTANCE_TYPE),
+        "/meta-data/local-ipv4" => Response::ok(LOCAL_IPV4),
+        "/meta-data/placement" => Response::ok("availability-zone\nregion"),
+        "/meta-data/placement/availability-zone" => Response::ok(format!("{region}a")),
+        "/meta-data/placement/region" => Response::ok(region.as_str()),
+        "/meta-data/iam" => Response::ok("info\nsecurity-credentials/"),
+        "/meta-data/iam/info" => Response::ok(format!(
+            "{{\n  \"Code\" : \"Success\",\n  \"LastUpdated\" : \"{}\",\n  \"InstanceProfileArn\" : \"arn:aws:iam::{}:instance-profile/{role}\",\n  \"InstanceProfileId\" : \"AIPA{token}\"\n}}",
+            timestamp(now - Duration::minutes(23)),
+            render(&config.account_id),
+        )),
+        "/meta-data/iam/security-credentials" => Response::ok(role),
+        "/dynamic/instance-identity/document" => Response::ok(format!(
+            "{{\n  \"accountId\" : \"{}\",\n  \"architecture\" : \"x86_64\",\n  \"availabilityZone\" : \"{region}a\",\n  \"imageId\" : \"{AMI_ID}\",\n  \"instanceId\" : \"{instance_id}\",\n  \"instanceType\" : \"{INSTANCE_TYPE}\",\n  \"pendingTime\" : \"{}\",\n  \"privateIp\" : \"{LOCAL_IPV4}\",\n  \"region\" : \"{region}\",\n  \"version\" : \"2017-09-30\"\n}}",
+            render(&config.account_id),
+            timestamp(now - Duration::days(41)),
+        )),
+        rest if rest.strip_prefix("/meta-data/iam/security-credentials/") == Some(&role) => {
+            Response {
+                credentials: true,
+                ..Response::ok(format!(
+                    "{{\n  \"Code\" : \"Success\",\n  \"LastUpdated\" : \"{}\",\n  \"Type\" : \"AWS-HMAC\",\n  \"AccessKeyId\" : \"{}\",\n  \"SecretAccessKey\" : \"{}\",\n  \"Token\" : \"{}\",\n  \"Expiration\" : \"{}\"\n}}",
+                    timestamp(now - Duration::minutes(23)),
+                    render(&config.access_key_id),
+                    render(&config.secret_access_key),
+                    render(&config.session_token),
+                    timestamp(now + Duration::hours(6)),
+                ))
+            }
+        }
+        _ => Response::not_found(),
+    }
+}
+
+/// Formats a time the way the service does, to the second.
+fn timestamp(time: OffsetDateTime) -> String {
+    time.replace_nanosecond(0)
+        .unwrap_or(time)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+    use uuid::Uuid;
+
+    use crate::{
+        config::CloudMetadata,
+        metadata::{is_metadata_host, respond},
+    };
+
+    #[test_case("169.254.169.254", true; "ipv4")]
+    #[test_case("[FD00:EC2::254]", true; "ipv6")]
+    #[test_case("instance-data", true; "hostname")]
+    #[test_case("169.254.169.253", false; "other address")]
+    fn detects_host(host: &str, expected: bool) {
+        assert_eq!(is_metadata_host(host), expected);
+    }
+
+    #[test_case("/latest/meta-data/", 200, false; "listing")]
+    #[test_case("/latest/meta-data/iam/security-credentials", 200, false; "roles")]
+    #[test_case("/latest/meta-data/iam/security-credentials/ec2-app-server", 200, true; "credentials")]
+    #[test_case("/latest/meta-data/iam/security-credentials/admin", 404, false; "other role")]
+    #[test_case("/latest/dynamic/instance-identity/document", 200, false; "identity")]
+    #[test_case("/latest/user-data", 404, false; "no user data")]
+    #[test_case("/computeMetadata/v1/", 404, false; "gcp")]
+    fn responds(path: &str, status: u16, credentials: bool) {
+        let response = respond(
+            path,
+            &CloudMetadata::default(),
+            Uuid::from_u128(0x0102_0304_0506_0708_0000_0000_0000_0000),
+            datetime!(2023-06-05 09:03:00.5 UTC),
+        );
+
+        assert_eq!(response.status, status);
+        assert_eq!(response.credentials, credentials);
+    }
+
+    #[test]
+    fn credentials_are_traceable() {
+        let response = respond(
+            "/latest/meta-data/iam/security-credentials/ec2-app-server",
+            &CloudMetadata::default(),
+            Uuid::from_u128(0x0102_0304_0506_0708_0000_0000_0000_0000),
+            datetime!(2023-06-05 09:03:00.5 UTC),
+        );
+
+        let credentials: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(credentials["AccessKeyId"], "ASIA0102030405060708");
+        assert_eq!(credentials["Expiration"], "2023-06-05T15:03:00Z");
+    }
+}
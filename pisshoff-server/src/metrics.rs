@@ -0,0 +1,179 @@
+//! Counters for operators to watch what clients are up to without trawling through the audit log,
+//! served over HTTP in the Prometheus text format and optionally summarised into the audit log
+//! itself.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::{debug, info};
+
+use crate::{
+    audit::{AuditLog, AuditLogAction, AuditSender, CommandSummaryEvent},
+    state::State,
+    tcp,
+};
+
+/// How long a scraper has to send its request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Binds the metrics listener, if one is configured. This is done alongside the SSH listeners so
+/// it can be on a privileged port.
+pub async fn bind(addr: Option<SocketAddr>) -> std::io::Result<Option<TcpListener>> {
+    let Some(addr) = addr else {
+        return Ok(None);
+    };
+
+    info!("Serving metrics on {addr}");
+    TcpListener::bind(addr).await.map(Some)
+}
+
+/// Answers every request on `listener` with the current metrics, whatever the path. This only
+/// needs to be good enough for a scraper, so the request itself isn't parsed. Never returns.
+pub async fn serve(listener: Option<TcpListener>, state: Arc<State>) -> std::io::Result<()> {
+    let Some(listener) = listener else {
+        return futures::future::pending().await;
+    };
+
+    loop {
+        let (mut stream, remote) = tcp::accept(&listener).await;
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let mut request = [0; 1024];
+            if tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut request))
+                .await
+                .is_err()
+            {
+                debug!(%remote, "Metrics request timed out");
+                return;
+            }
+
+            let body = render(&state);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+
+            let _res = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+fn render(state: &State) -> String {
+    let mut out = String::new();
+
+    out.push_str(
+        "# HELP pisshoff_command_executions_total Commands run by clients, by the name they were run as.\n",
+    );
+    out.push_str("# TYPE pisshoff_command_executions_total counter\n");
+
+    for (name, found, count) in state.command_counts.snapshot() {
+        writeln!(
+            out,
+            "pisshoff_command_executions_total{{command=\"{}\",found=\"{found}\"}} {count}",
+            escape_label(&name),
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+fn escape_label(value: &str) -> Cow<'_, str> {
+    if !value.contains(['\\', '"', '\n']) {
+        return Cow::Borrowed(value);
+    }
+
+    Cow::Owned(
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n"),
+    )
+}
+
+/// Writes a [`CommandSummaryEvent`] to the audit log every `interval` seconds, covering the
+/// commands run since the previous one. Nothing is written for periods without any commands.
+/// Never returns.
+pub async fn summarise_commands(
+    state: Arc<State>,
+    host: &'static str,
+    interval: Option<u64>,
+    audit_send: AuditSender,
+) {
+    let Some(interval) = interval.map(Duration::from_secs) else {
+        return futures::future::pending().await;
+    };
+
+    let mut previous = HashMap::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let mut commands = BTreeMap::new();
+        let mut unknown_commands = BTreeMap::new();
+
+        for (name, found, count) in state.command_counts.snapshot() {
+            let last = previous.insert((name.clone(), found), count).unwrap_or(0);
+
+            if count > last {
+                let counts = if found {
+                    &mut commands
+                } else {
+                    &mut unknown_commands
+                };
+                counts.insert(name, count - last);
+            }
+        }
+
+        if commands.is_empty() && unknown_commands.is_empty() {
+            continue;
+        }
+
+        let mut log = AuditLog {
+            connection_id: uuid::Uuid::new_v4(),
+            host: Cow::Borrowed(host),
+            ..AuditLog::default()
+        };
+        log.push_action(AuditLogAction::CommandSummary(CommandSummaryEvent {
+            period: interval,
+            commands,
+            unknown_commands,
+        }));
+
+        audit_send.send(log);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{metrics::render, state::State};
+
+    #[test]
+    fn renders_command_counts() {
+        let state = State::default();
+        state.command_counts.record("uname", true);
+        state.command_counts.record("uname", true);
+        state.command_counts.record("echo \"hi\"", false);
+
+        assert_eq!(
+            render(&state),
+            "# HELP pisshoff_command_executions_total Commands run by clients, by the name they were run as.\n\
+             # TYPE pisshoff_command_executions_total counter\n\
+             pisshoff_command_executions_total{command=\"echo \\\"hi\\\"\",found=\"false\"} 1\n\
+             pisshoff_command_executions_total{command=\"uname\",found=\"true\"} 2\n"
+        );
+    }
+}
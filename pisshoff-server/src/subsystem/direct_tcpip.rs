@@ -0,0 +1,452 @@
+mod socks;
+
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pisshoff_types::audit::{AuditLogAction, ForwardedDataEvent, SocksConnectEvent};
+use thrussh::{server::Session, ChannelId};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    config::DirectTcpIpMode,
+    server::ConnectionState,
+    subsystem::{
+        direct_tcpip::socks::{Outcome, Socks},
+        Subsystem,
+    },
+};
+
+/// Maximum amount of data buffered while waiting for a complete request from the client.
+const MAX_PENDING: usize = 64 * 1024;
+
+const HTTP_BODY: &str = "<!DOCTYPE html>
+<html>
+<head>
+<title>Welcome to nginx!</title>
+<style>
+    body {
+        width: 35em;
+        margin: 0 auto;
+        font-family: Tahoma, Verdana, Arial, sans-serif;
+    }
+</style>
+</head>
+<body>
+<h1>Welcome to nginx!</h1>
+<p>If you see this page, the nginx web server is successfully installed and
+working. Further configuration is required.</p>
+
+<p><em>Thank you for using nginx.</em></p>
+</body>
+</html>
+";
+
+/// Extensions advertised in response to `EHLO`, following the line greeting the client.
+const EHLO_EXTENSIONS: &str = "250-PIPELINING\r\n250-SIZE 10240000\r\n250-VRFY\r\n250-ETRN\r\n250-ENHANCEDSTATUSCODES\r\n250-8BITMIME\r\n250-DSN\r\n250-SMTPUTF8\r\n250 CHUNKING\r\n";
+
+/// A well-known service the remote end of a forwarded connection can imitate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    Http,
+    Smtp {
+        /// Whether the client is sending the body of a message, following a `DATA` command.
+        receiving_message: bool,
+    },
+}
+
+impl Service {
+    fn for_port(port: u32) -> Option<Self> {
+        match port {
+            80 | 8000 | 8080 => Some(Self::Http),
+            25 | 587 => Some(Self::Smtp {
+                receiving_message: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A connection the client asked us to forward to a remote host, which is never actually made.
+#[derive(Debug)]
+pub struct DirectTcpIp {
+    /// The host the client wants to reach, updated if the client asks a SOCKS proxy to connect
+    /// elsewhere.
+    host: String,
+    port: u32,
+    emulate: bool,
+    service: Option<Service>,
+    /// The SOCKS handshake in progress, if the client is treating the remote end as a proxy.
+    socks: Option<Socks>,
+    /// Whether any data has been received from the client yet.
+    started: bool,
+    /// Data received that doesn't yet make up a complete request.
+    pending: Vec<u8>,
+}
+
+impl DirectTcpIp {
+    pub fn new(
+        host: &str,
+        port: u32,
+        mode: DirectTcpIpMode,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Self {
+        let this = Self::build(host, port, mode);
+
+        if let Some(greeting) = this.greeting() {
+            session.data(channel, greeting.into());
+        }
+
+        this
+    }
+
+    fn build(host: &str, port: u32, mode: DirectTcpIpMode) -> Self {
+        let emulate = mode == DirectTcpIpMode::Emulate;
+
+        Self {
+            host: host.to_string(),
+            port,
+            emulate,
+            service: emulate.then(|| Service::for_port(port)).flatten(),
+            socks: None,
+            started: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Banner sent by the emulated service as soon as the connection opens.
+    fn greeting(&self) -> Option<String> {
+        match self.service? {
+            Service::Smtp { .. } => {
+                Some(format!("220 {} ESMTP Postfix (Debian/GNU)\r\n", self.host))
+            }
+            Service::Http => None,
+        }
+    }
+
+    /// Returns the emulated service's response to `data`, and whether the connection should be
+    /// closed once the response has been sent.
+    fn respond(
+        &mut self,
+        connection: &mut ConnectionState,
+        data: &[u8],
+        now: OffsetDateTime,
+    ) -> (Vec<u8>, bool) {
+        if !self.emulate {
+            return (Vec::new(), false);
+        }
+
+        // whatever port was requested, a client that opens with a SOCKS handshake wants a proxy
+        if !std::mem::replace(&mut self.started, true) && socks::detect(data) {
+            self.socks = Some(Socks::default());
+        }
+
+        self.pending.extend_from_slice(data);
+
+        if let Some(socks) = self.socks.take() {
+            self.respond_socks(socks, connection, now)
+        } else {
+            self.respond_service(connection.rng(), now)
+        }
+    }
+
+    fn respond_service(&mut self, rng: &fastrand::Rng, now: OffsetDateTime) -> (Vec<u8>, bool) {
+        match self.service {
+            Some(Service::Http) => self.respond_http(now),
+            Some(Service::Smtp { receiving_message }) => self.respond_smtp(rng, receiving_message),
+            None => {
+                self.pending.clear();
+                (Vec::new(), false)
+            }
+        }
+    }
+
+    fn respond_socks(
+        &mut self,
+        mut socks: Socks,
+        connection: &mut ConnectionState,
+        now: OffsetDateTime,
+    ) -> (Vec<u8>, bool) {
+        let mut out = Vec::new();
+
+        loop {
+            match socks.advance(&mut self.pending) {
+                Outcome::Incomplete => {
+                    self.socks = Some(socks);
+                    return (out, false);
+                }
+                Outcome::Reply(reply) => out.extend_from_slice(&reply),
+                Outcome::Refuse(reply) => {
+                    out.extend_from_slice(&reply);
+                    return (out, true);
+                }
+                Outcome::Connect(reply, request) => {
+                    out.extend_from_slice(&reply);
+
+                    connection.push_action(AuditLogAction::SocksConnect(SocksConnectEvent {
+                        version: request.version,
+                        host: Box::from(request.host.as_str()),
+                        port: request.port,
+                        username: request.username.map(Box::from),
+                        password: request.password.map(Box::from),
+                    }));
+
+                    // from here on, everything is destined for the host the proxy "connected" to
+                    self.host = request.host;
+                    self.port = request.port.into();
+                    self.service = Service::for_port(self.port);
+
+                    if let Some(greeting) = self.greeting() {
+                        out.extend_from_slice(greeting.as_bytes());
+                    }
+
+                    let (response, close) = self.respond_service(connection.rng(), now);
+                    out.extend_from_slice(&response);
+                    return (out, close);
+                }
+            }
+        }
+    }
+
+    fn respond_http(&mut self, now: OffsetDateTime) -> (Vec<u8>, bool) {
+        let Some(end) = self.pending.windows(4).position(|v| v == b"\r\n\r\n") else {
+            if self.pending.len() > MAX_PENDING {
+                self.pending.clear();
+                return (
+                    b"HTTP/1.1 413 Request Entity Too Large\r\nServer: nginx/1.18.0\r\nConnection: close\r\n\r\n".to_vec(),
+                    true,
+                );
+            }
+
+            return (Vec::new(), false);
+        };
+
+        let head = self.pending.starts_with(b"HEAD ");
+        self.pending.drain(..end + 4);
+
+        let date = now
+            .format(format_description!(
+                "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+            ))
+            .unwrap_or_default();
+
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nServer: nginx/1.18.0\r\nDate: {date}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            HTTP_BODY.len()
+        );
+
+        if !head {
+            response.push_str(HTTP_BODY);
+        }
+
+        (response.into_bytes(), true)
+    }
+
+    fn respond_smtp(
+        &mut self,
+        rng: &fastrand::Rng,
+        mut receiving_message: bool,
+    ) -> (Vec<u8>, bool) {
+        let mut out = String::new();
+        let mut close = false;
+
+        while let Some(end) = self.pending.iter().position(|c| *c == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if receiving_message {
+                if line == "." {
+                    receiving_message = false;
+                    write!(
+                        out,
+                        "250 2.0.0 Ok: queued as {:010X}\r\n",
+                        rng.u64(..0x00FF_FFFF_FFFF)
+                    )
+                    .unwrap();
+                }
+
+                continue;
+            }
+
+            let verb = line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_ascii_uppercase();
+
+            let response = match verb.as_str() {
+                "HELO" => {
+                    write!(out, "250 {}\r\n", self.host).unwrap();
+                    continue;
+                }
+                "EHLO" => {
+                    write!(out, "250-{}\r\n{EHLO_EXTENSIONS}", self.host).unwrap();
+                    continue;
+                }
+                "MAIL" => "250 2.1.0 Ok\r\n",
+                "RCPT" => "250 2.1.5 Ok\r\n",
+                "DATA" => {
+                    receiving_message = true;
+                    "354 End data with <CR><LF>.<CR><LF>\r\n"
+                }
+                "RSET" | "NOOP" => "250 2.0.0 Ok\r\n",
+                "QUIT" => {
+                    close = true;
+                    "221 2.0.0 Bye\r\n"
+                }
+                "" => "500 5.5.2 Error: bad syntax\r\n",
+                _ => "502 5.5.2 Error: command not recognized\r\n",
+            };
+
+            out.push_str(response);
+
+            if close {
+                break;
+            }
+        }
+
+        if self.pending.len() > MAX_PENDING {
+            self.pending.clear();
+        }
+
+        self.service = Some(Service::Smtp { receiving_message });
+        (out.into_bytes(), close)
+    }
+}
+
+#[async_trait]
+impl Subsystem for DirectTcpIp {
+    async fn data(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) {
+        connection.push_action(AuditLogAction::ForwardedData(ForwardedDataEvent {
+            host_to_connect: Box::from(self.host.as_str()),
+            port_to_connect: self.port,
+            data: Bytes::copy_from_slice(data),
+        }));
+
+        let now = connection.now();
+        let (response, close) = self.respond(connection, data, now);
+
+        if !response.is_empty() {
+            session.data(channel, response.into());
+        }
+
+        if close {
+            session.eof(channel);
+            session.close(channel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use insta::assert_debug_snapshot;
+    use time::macros::datetime;
+
+    use crate::{
+        config::DirectTcpIpMode, server::ConnectionState, subsystem::direct_tcpip::DirectTcpIp,
+    };
+
+    #[test]
+    fn record_only() {
+        let mut state = ConnectionState::mock();
+        let mut forward = DirectTcpIp::build("example.com", 80, DirectTcpIpMode::Record);
+
+        assert_eq!(forward.greeting(), None);
+        assert_eq!(
+            forward.respond(
+                &mut state,
+                b"GET / HTTP/1.1\r\n\r\n",
+                datetime!(2023-01-01 12:00 UTC)
+            ),
+            (vec![], false)
+        );
+    }
+
+    #[test]
+    fn http() {
+        let mut state = ConnectionState::mock();
+        let mut forward = DirectTcpIp::build("example.com", 80, DirectTcpIpMode::Emulate);
+        let now = datetime!(2023-01-01 12:00 UTC);
+
+        assert_eq!(forward.greeting(), None);
+        assert_eq!(
+            forward.respond(&mut state, b"HEAD / HTTP/1.1\r\n", now),
+            (vec![], false)
+        );
+
+        let (response, close) = forward.respond(&mut state, b"Host: example.com\r\n\r\n", now);
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "HTTP/1.1 200 OK\r\nServer: nginx/1.18.0\r\nDate: Sun, 01 Jan 2023 12:00:00 GMT\r\nContent-Type: text/html\r\nContent-Length: 425\r\nConnection: close\r\n\r\n"
+        );
+        assert!(close);
+    }
+
+    #[test]
+    fn smtp() {
+        let mut state = ConnectionState::mock();
+        let mut forward = DirectTcpIp::build("mx.example.com", 25, DirectTcpIpMode::Emulate);
+        let now = datetime!(2023-01-01 12:00 UTC);
+
+        assert_eq!(
+            forward.greeting().as_deref(),
+            Some("220 mx.example.com ESMTP Postfix (Debian/GNU)\r\n")
+        );
+
+        let (response, close) = forward.respond(
+            &mut state,
+            b"HELO spam\r\nMAIL FROM:<a@b.c>\r\nRCPT TO:<d@e.f>\r\nDATA\r\nSubject: hi\r\n",
+            now,
+        );
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "250 mx.example.com\r\n250 2.1.0 Ok\r\n250 2.1.5 Ok\r\n354 End data with <CR><LF>.<CR><LF>\r\n"
+        );
+        assert!(!close);
+
+        let (response, close) = forward.respond(&mut state, b"\r\nbuy now\r\n.\r\nQUIT\r\n", now);
+        let response = String::from_utf8(response).unwrap();
+        assert!(
+            response.starts_with("250 2.0.0 Ok: queued as "),
+            "{response}"
+        );
+        assert!(response.ends_with("\r\n221 2.0.0 Bye\r\n"), "{response}");
+        assert!(close);
+    }
+
+    #[test]
+    fn socks() {
+        let mut state = ConnectionState::mock();
+        let mut forward = DirectTcpIp::build("127.0.0.1", 1080, DirectTcpIpMode::Emulate);
+        let now = datetime!(2023-01-01 12:00 UTC);
+
+        assert_eq!(
+            forward.respond(&mut state, b"\x05\x01\x00", now),
+            (vec![5, 0], false)
+        );
+
+        let (response, close) = forward.respond(
+            &mut state,
+            b"\x05\x01\x00\x03\x0bexample.com\x00\x50HEAD / HTTP/1.1\r\n\r\n",
+            now,
+        );
+        assert!(response.starts_with(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]));
+        assert!(response[10..].starts_with(b"HTTP/1.1 200 OK\r\n"));
+        assert!(close);
+
+        insta::with_settings!({filters => vec![
+            (r"\bstart_offset: [^,]+", "start_offset: [stripped]"),
+            (r"\bsequence: Some\(\s*\d+,\s*\)", "sequence: [stripped]")
+        ]}, {
+            assert_debug_snapshot!(state.audit_log());
+        });
+    }
+}
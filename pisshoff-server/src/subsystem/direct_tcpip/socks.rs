@@ -0,0 +1,280 @@
+//! Server side of the SOCKS4(a) and SOCKS5 handshakes, for clients expecting a proxy on the other
+//! end of a forwarded connection.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const SOCKS4_GRANTED: u8 = 0x5a;
+const SOCKS4_REJECTED: u8 = 0x5b;
+
+const SOCKS5_NO_AUTHENTICATION: u8 = 0x00;
+const SOCKS5_USERNAME_PASSWORD: u8 = 0x02;
+const SOCKS5_NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const SOCKS5_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+
+/// Returns true if `data` looks like the first message of a SOCKS handshake.
+pub fn detect(data: &[u8]) -> bool {
+    matches!(data, [4, 1 | 2, ..] | [5, 1..=255, ..])
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting on the SOCKS4 request, or the SOCKS5 method selection.
+    #[default]
+    Greeting,
+    /// Waiting on the SOCKS5 username/password subnegotiation.
+    Authenticating,
+    /// Waiting on the SOCKS5 request.
+    Request,
+}
+
+/// Where the client asked the proxy to connect to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConnectRequest {
+    pub version: u8,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// More data is needed before the next message can be parsed.
+    Incomplete,
+    /// Send the reply and continue with the handshake.
+    Reply(Vec<u8>),
+    /// Send the reply and close the connection.
+    Refuse(Vec<u8>),
+    /// The handshake is complete, send the reply and start treating data as being destined for
+    /// the requested host.
+    Connect(Vec<u8>, ConnectRequest),
+}
+
+#[derive(Debug, Default)]
+pub struct Socks {
+    state: State,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Socks {
+    /// Consumes the next complete message from `buf`, returning what should happen next.
+    pub fn advance(&mut self, buf: &mut Vec<u8>) -> Outcome {
+        let parsed = match (self.state, buf.first()) {
+            (State::Greeting, Some(4)) => self.socks4(buf),
+            (State::Greeting, Some(5)) => self.greeting(buf),
+            (State::Authenticating, Some(1)) => self.authenticate(buf),
+            (State::Request, Some(5)) => self.request(buf),
+            (_, None) => return Outcome::Incomplete,
+            _ => return Outcome::Refuse(Vec::new()),
+        };
+
+        match parsed {
+            Some((consumed, outcome)) => {
+                buf.drain(..consumed);
+                outcome
+            }
+            None => Outcome::Incomplete,
+        }
+    }
+
+    fn socks4(&mut self, buf: &[u8]) -> Option<(usize, Outcome)> {
+        let [4, command, port_hi, port_lo, a, b, c, d, rest @ ..] = buf else {
+            return None;
+        };
+
+        let user_end = rest.iter().position(|v| *v == 0)?;
+        self.username =
+            Some(String::from_utf8_lossy(&rest[..user_end]).to_string()).filter(|v| !v.is_empty());
+        let mut consumed = 8 + user_end + 1;
+
+        let port = u16::from_be_bytes([*port_hi, *port_lo]);
+        let mut reply = vec![0, SOCKS4_GRANTED, *port_hi, *port_lo, *a, *b, *c, *d];
+
+        // SOCKS4a signals the hostname follows the user ID with an address of 0.0.0.x
+        let host = if [*a, *b, *c] == [0, 0, 0] && *d != 0 {
+            let rest = &rest[user_end + 1..];
+            let host_end = rest.iter().position(|v| *v == 0)?;
+            consumed += host_end + 1;
+            String::from_utf8_lossy(&rest[..host_end]).to_string()
+        } else {
+            Ipv4Addr::new(*a, *b, *c, *d).to_string()
+        };
+
+        if *command != 1 {
+            reply[1] = SOCKS4_REJECTED;
+            return Some((consumed, Outcome::Refuse(reply)));
+        }
+
+        Some((
+            consumed,
+            Outcome::Connect(reply, self.connect_request(4, host, port)),
+        ))
+    }
+
+    fn greeting(&mut self, buf: &[u8]) -> Option<(usize, Outcome)> {
+        let [5, count, rest @ ..] = buf else {
+            return None;
+        };
+
+        let methods = rest.get(..usize::from(*count))?;
+        let consumed = 2 + methods.len();
+
+        // prefer whichever gets the client to their request quickest, but take credentials if
+        // that's all they're offering
+        let outcome = if methods.contains(&SOCKS5_NO_AUTHENTICATION) {
+            self.state = State::Request;
+            Outcome::Reply(vec![5, SOCKS5_NO_AUTHENTICATION])
+        } else if methods.contains(&SOCKS5_USERNAME_PASSWORD) {
+            self.state = State::Authenticating;
+            Outcome::Reply(vec![5, SOCKS5_USERNAME_PASSWORD])
+        } else {
+            Outcome::Refuse(vec![5, SOCKS5_NO_ACCEPTABLE_METHODS])
+        };
+
+        Some((consumed, outcome))
+    }
+
+    fn authenticate(&mut self, buf: &[u8]) -> Option<(usize, Outcome)> {
+        let [1, username_len, rest @ ..] = buf else {
+            return None;
+        };
+
+        let username = rest.get(..usize::from(*username_len))?;
+        let rest = &rest[username.len()..];
+        let password_len = usize::from(*rest.first()?);
+        let password = rest.get(1..=password_len)?;
+
+        self.username = Some(String::from_utf8_lossy(username).to_string());
+        self.password = Some(String::from_utf8_lossy(password).to_string());
+        self.state = State::Request;
+
+        Some((
+            3 + username.len() + password.len(),
+            Outcome::Reply(vec![1, 0]),
+        ))
+    }
+
+    fn request(&mut self, buf: &[u8]) -> Option<(usize, Outcome)> {
+        let [5, command, _reserved, address_type, rest @ ..] = buf else {
+            return None;
+        };
+
+        let (host, address_len) = match address_type {
+            1 => {
+                let octets: [u8; 4] = rest.get(..4)?.try_into().ok()?;
+                (Ipv4Addr::from(octets).to_string(), 4)
+            }
+            3 => {
+                let len = usize::from(*rest.first()?);
+                let host = rest.get(1..=len)?;
+                (String::from_utf8_lossy(host).to_string(), len + 1)
+            }
+            4 => {
+                let octets: [u8; 16] = rest.get(..16)?.try_into().ok()?;
+                (Ipv6Addr::from(octets).to_string(), 16)
+            }
+            _ => return Some((buf.len(), Outcome::Refuse(Vec::new()))),
+        };
+
+        let port = rest.get(address_len..address_len + 2)?;
+        let port = u16::from_be_bytes([port[0], port[1]]);
+        let consumed = 4 + address_len + 2;
+
+        // bound address and port, which aren't meaningful since no connection is ever made
+        let mut reply = vec![5, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+
+        if *command != 1 {
+            reply[1] = SOCKS5_COMMAND_NOT_SUPPORTED;
+            return Some((consumed, Outcome::Refuse(reply)));
+        }
+
+        Some((
+            consumed,
+            Outcome::Connect(reply, self.connect_request(5, host, port)),
+        ))
+    }
+
+    fn connect_request(&mut self, version: u8, host: String, port: u16) -> ConnectRequest {
+        ConnectRequest {
+            version,
+            host,
+            port,
+            username: self.username.take(),
+            password: self.password.take(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::subsystem::direct_tcpip::socks::{detect, ConnectRequest, Outcome, Socks};
+
+    #[test_case(b"\x04\x01\x00\x50\x01\x02\x03\x04\x00", true; "socks4")]
+    #[test_case(b"\x05\x01\x00", true; "socks5")]
+    #[test_case(b"\x05\x00", false; "socks5 without methods")]
+    #[test_case(b"GET / HTTP/1.1\r\n", false; "http")]
+    fn detects(input: &[u8], expected: bool) {
+        assert_eq!(detect(input), expected);
+    }
+
+    #[test]
+    fn socks4a() {
+        let mut socks = Socks::default();
+        let mut buf = b"\x04\x01\x01\xbb\x00\x00\x00\x01bot\x00example.com\x00rest".to_vec();
+
+        assert_eq!(
+            socks.advance(&mut buf),
+            Outcome::Connect(
+                vec![0, 0x5a, 0x01, 0xbb, 0, 0, 0, 1],
+                ConnectRequest {
+                    version: 4,
+                    host: "example.com".to_string(),
+                    port: 443,
+                    username: Some("bot".to_string()),
+                    password: None,
+                }
+            )
+        );
+        assert_eq!(buf, b"rest");
+    }
+
+    #[test]
+    fn socks5_with_credentials() {
+        let mut socks = Socks::default();
+        let mut buf = b"\x05\x01\x02".to_vec();
+
+        assert_eq!(socks.advance(&mut buf), Outcome::Reply(vec![5, 2]));
+        assert_eq!(socks.advance(&mut buf), Outcome::Incomplete);
+
+        buf.extend_from_slice(b"\x01\x04user\x04pass\x05\x01\x00\x01\x0a\x00\x00\x01\x00");
+        assert_eq!(socks.advance(&mut buf), Outcome::Reply(vec![1, 0]));
+        assert_eq!(socks.advance(&mut buf), Outcome::Incomplete);
+
+        buf.push(0x19);
+        assert_eq!(
+            socks.advance(&mut buf),
+            Outcome::Connect(
+                vec![5, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+                ConnectRequest {
+                    version: 5,
+                    host: "10.0.0.1".to_string(),
+                    port: 25,
+                    username: Some("user".to_string()),
+                    password: Some("pass".to_string()),
+                }
+            )
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn socks5_no_acceptable_methods() {
+        let mut socks = Socks::default();
+        let mut buf = b"\x05\x01\x01".to_vec();
+
+        assert_eq!(socks.advance(&mut buf), Outcome::Refuse(vec![5, 0xff]));
+    }
+}
@@ -1,15 +1,16 @@
+use std::{collections::HashMap, fmt::Debug};
+
 use async_trait::async_trait;
 use thrussh::{server::Session, ChannelId};
 
 use crate::server::ConnectionState;
 
+pub mod direct_tcpip;
 pub mod sftp;
 pub mod shell;
 
 #[async_trait]
-pub trait Subsystem {
-    const NAME: &'static str;
-
+pub trait Subsystem: Debug + Send {
     async fn data(
         &mut self,
         connection: &mut ConnectionState,
@@ -17,4 +18,60 @@ pub trait Subsystem {
         data: &[u8],
         session: &mut Session,
     );
+
+    /// Called when the channel the subsystem is running on goes away, either due to the client
+    /// closing it or disconnecting entirely, so any in-progress work can be audited.
+    fn abort(&mut self, _connection: &mut ConnectionState) {}
+}
+
+/// Starts a subsystem for a channel the client has requested it on.
+pub type SubsystemFactory = fn() -> Box<dyn Subsystem>;
+
+fn start<T: Subsystem + Default + 'static>() -> Box<dyn Subsystem> {
+    Box::<T>::default()
+}
+
+/// The subsystems clients can request by name, shared between every connection.
+pub struct Registry {
+    subsystems: HashMap<Box<str>, SubsystemFactory>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut this = Self {
+            subsystems: HashMap::new(),
+        };
+
+        this.register::<sftp::Sftp>(sftp::Sftp::NAME);
+
+        this
+    }
+}
+
+impl Registry {
+    /// Makes `T` available as `name`, replacing any subsystem already registered with that name.
+    pub fn register<T: Subsystem + Default + 'static>(&mut self, name: &str) {
+        self.register_factory(name, start::<T>);
+    }
+
+    pub fn register_factory(&mut self, name: &str, factory: SubsystemFactory) {
+        self.subsystems.insert(Box::from(name), factory);
+    }
+
+    pub fn get(&self, name: &str) -> Option<SubsystemFactory> {
+        self.subsystems.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::subsystem::{sftp::Sftp, Registry};
+
+    #[test]
+    fn registers_sftp() {
+        let registry = Registry::default();
+
+        assert!(registry.get(Sftp::NAME).is_some());
+        assert!(registry.get("netconf").is_none());
+    }
 }
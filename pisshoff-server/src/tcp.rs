@@ -0,0 +1,369 @@
+//! Passive fingerprinting of the client's TCP stack, using the SYN packet the kernel saves for
+//! us and the round trip time it measured during the handshake.
+
+use std::{
+    borrow::Cow,
+    io,
+    net::SocketAddr,
+    ops::Range,
+    os::fd::{FromRawFd, RawFd},
+    time::Duration,
+};
+
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tracing::warn;
+
+use crate::audit::ConnectionMetadataEvent;
+
+/// Initial TTLs commonly used by operating systems, alongside the family they belong to.
+const INITIAL_TTLS: &[(u8, &str)] = &[(64, "unix"), (128, "windows"), (255, "network-device")];
+
+/// How long to wait before accepting again once accepting a connection has failed.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Accepts the next connection on `listener`. Failures such as running out of file descriptors
+/// during a flood of connections pass once some are closed, so they're logged and retried after a
+/// short wait rather than taking the listener down.
+pub async fn accept(listener: &TcpListener) -> (TcpStream, SocketAddr) {
+    loop {
+        match listener.accept().await {
+            Ok(accepted) => return accepted,
+            Err(error) => {
+                warn!(%error, "Failed to accept connection");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+            }
+        }
+    }
+}
+
+/// Binds to `addr`, asking the kernel to hold onto the SYN of each accepted connection.
+///
+/// If `v6_only` is set, an IPv6 socket won't also accept IPv4 connections, allowing a separate
+/// listener to bind to the same port for IPv4.
+pub fn listen(addr: SocketAddr, v6_only: bool) -> io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+
+    socket.set_reuseaddr(true)?;
+    if v6_only {
+        sys::set_v6_only(&socket)?;
+    }
+    sys::save_syn(&socket);
+    socket.bind(addr)?;
+    socket.listen(1024)
+}
+
+/// The first file descriptor passed by systemd socket activation, following stdin, stdout and
+/// stderr.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Takes ownership of the listening sockets passed to us by systemd socket activation, returning
+/// `None` if the server wasn't socket activated.
+pub fn inherited() -> io::Result<Option<Vec<TcpListener>>> {
+    let Some(fds) = listen_fds(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    ) else {
+        return Ok(None);
+    };
+
+    // stop the sockets being claimed again by anything we spawn
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    fds.map(|fd| {
+        // SAFETY: systemd hands over ownership of these descriptors to us, and we only take them
+        // once since the variables pointing at them are removed above
+        let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        listener.set_nonblocking(true)?;
+        sys::save_syn_fd(fd);
+        TcpListener::from_std(listener)
+    })
+    .collect::<io::Result<_>>()
+    .map(Some)
+}
+
+/// Works out which file descriptors were passed to us from the `LISTEN_PID` and `LISTEN_FDS`
+/// variables, following `sd_listen_fds(3)`.
+fn listen_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> Option<Range<RawFd>> {
+    if listen_pid?.parse::<u32>().ok()? != pid {
+        return None;
+    }
+
+    let count = listen_fds?.parse::<RawFd>().ok().filter(|v| *v > 0)?;
+    Some(SD_LISTEN_FDS_START..SD_LISTEN_FDS_START.checked_add(count)?)
+}
+
+/// Gathers whatever details of the client's TCP stack are available for a newly accepted
+/// connection.
+pub fn metadata(stream: &TcpStream) -> ConnectionMetadataEvent {
+    let mut event = sys::saved_syn(stream)
+        .map(|syn| parse_syn(&syn))
+        .unwrap_or_default();
+    event.handshake_rtt = sys::rtt(stream);
+    event
+}
+
+/// Parses the IP and TCP headers of a SYN packet.
+fn parse_syn(syn: &[u8]) -> ConnectionMetadataEvent {
+    let (ttl, tcp) = match syn.first().map(|v| v >> 4) {
+        Some(4) if syn.len() >= 20 => (syn[8], syn.get(usize::from(syn[0] & 0xf) * 4..)),
+        // extension headers are rare enough on a SYN that we don't bother walking them
+        Some(6) if syn.len() >= 40 => (syn[7], syn.get(40..).filter(|_| syn[6] == 6)),
+        _ => return ConnectionMetadataEvent::default(),
+    };
+
+    let initial_ttl = INITIAL_TTLS.iter().find(|(initial, _)| *initial >= ttl);
+
+    let mut event = ConnectionMetadataEvent {
+        ttl: Some(ttl),
+        initial_ttl: initial_ttl.map(|(v, _)| *v),
+        os_guess: initial_ttl.map(|(_, os)| Cow::Borrowed(*os)),
+        ..ConnectionMetadataEvent::default()
+    };
+
+    let Some(tcp) = tcp.filter(|v| v.len() >= 20) else {
+        return event;
+    };
+
+    event.window_size = Some(u16::from_be_bytes([tcp[14], tcp[15]]));
+
+    let options_end = (usize::from(tcp[12] >> 4) * 4).min(tcp.len());
+    let mut options = tcp.get(20..options_end).unwrap_or_default();
+    let mut layout = Vec::new();
+
+    while let Some(&kind) = options.first() {
+        let len = match kind {
+            0 | 1 => 1,
+            _ => match options.get(1) {
+                Some(&len) if len >= 2 && usize::from(len) <= options.len() => usize::from(len),
+                _ => break,
+            },
+        };
+
+        let (option, rest) = options.split_at(len);
+        options = rest;
+
+        layout.push(match (kind, option) {
+            (0, _) => Cow::Borrowed("eol"),
+            (1, _) => Cow::Borrowed("nop"),
+            (2, [_, _, a, b]) => {
+                event.mss = Some(u16::from_be_bytes([*a, *b]));
+                Cow::Borrowed("mss")
+            }
+            (3, [_, _, shift]) => {
+                event.window_scale = Some(*shift);
+                Cow::Borrowed("ws")
+            }
+            (4, _) => Cow::Borrowed("sok"),
+            (5, _) => Cow::Borrowed("sack"),
+            (8, _) => Cow::Borrowed("ts"),
+            (kind, _) => Cow::Owned(format!("?{kind}")),
+        });
+
+        if kind == 0 {
+            break;
+        }
+    }
+
+    event.tcp_options = Some(layout.join(",").into_boxed_str());
+    event
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{
+        mem::size_of,
+        os::fd::{AsRawFd, RawFd},
+        time::Duration,
+    };
+
+    use tokio::net::{TcpSocket, TcpStream};
+    use tracing::debug;
+
+    /// Enough for the largest IP and TCP headers.
+    const MAX_SYN_LEN: usize = 60 + 60;
+
+    pub fn set_v6_only(socket: &TcpSocket) -> std::io::Result<()> {
+        let enable: libc::c_int = 1;
+
+        // SAFETY: the pointer and length describe a valid c_int for the duration of the call
+        let res = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                libc::IPV6_V6ONLY,
+                std::ptr::addr_of!(enable).cast(),
+                socklen::<libc::c_int>(),
+            )
+        };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+
+    pub fn save_syn(socket: &TcpSocket) {
+        save_syn_fd(socket.as_raw_fd());
+    }
+
+    pub fn save_syn_fd(fd: RawFd) {
+        let enable: libc::c_int = 1;
+
+        // SAFETY: the pointer and length describe a valid c_int for the duration of the call
+        let res = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::IPPROTO_TCP,
+                libc::TCP_SAVE_SYN,
+                std::ptr::addr_of!(enable).cast(),
+                socklen::<libc::c_int>(),
+            )
+        };
+
+        if res != 0 {
+            debug!(
+                "Unable to save SYNs, connection metadata will be limited: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    pub fn saved_syn(stream: &TcpStream) -> Option<Vec<u8>> {
+        let mut syn = vec![0_u8; MAX_SYN_LEN];
+        let len = getsockopt(stream.as_raw_fd(), libc::TCP_SAVED_SYN, &mut syn)?;
+        syn.truncate(len);
+        Some(syn)
+    }
+
+    pub fn rtt(stream: &TcpStream) -> Option<Duration> {
+        // SAFETY: tcp_info is plain old data, so all zeroes is a valid value
+        let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+
+        // SAFETY: the buffer is exactly the size of a tcp_info
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(
+                std::ptr::addr_of_mut!(info).cast::<u8>(),
+                size_of::<libc::tcp_info>(),
+            )
+        };
+        getsockopt(stream.as_raw_fd(), libc::TCP_INFO, buf)?;
+
+        (info.tcpi_rtt > 0).then(|| Duration::from_micros(info.tcpi_rtt.into()))
+    }
+
+    /// Reads a TCP-level socket option into `buf`, returning the number of bytes written.
+    fn getsockopt(fd: RawFd, option: libc::c_int, buf: &mut [u8]) -> Option<usize> {
+        let mut len = libc::socklen_t::try_from(buf.len()).ok()?;
+
+        // SAFETY: the pointer and length describe `buf`, which outlives the call
+        let res = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::IPPROTO_TCP,
+                option,
+                buf.as_mut_ptr().cast(),
+                std::ptr::addr_of_mut!(len),
+            )
+        };
+
+        if res != 0 {
+            return None;
+        }
+
+        usize::try_from(len).ok().filter(|v| *v > 0)
+    }
+
+    fn socklen<T>() -> libc::socklen_t {
+        libc::socklen_t::try_from(size_of::<T>()).unwrap()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::time::Duration;
+
+    use tokio::net::{TcpSocket, TcpStream};
+
+    pub fn set_v6_only(_socket: &TcpSocket) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    pub fn save_syn(_socket: &TcpSocket) {}
+
+    pub fn save_syn_fd(_fd: std::os::fd::RawFd) {}
+
+    pub fn saved_syn(_stream: &TcpStream) -> Option<Vec<u8>> {
+        None
+    }
+
+    pub fn rtt(_stream: &TcpStream) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::tcp::{listen_fds, parse_syn};
+
+    /// IPv4 header of a SYN that arrived with a TTL of 52.
+    const IPV4_HEADER: &[u8] = &[
+        0x45, 0x00, 0x00, 0x3c, 0x12, 0x34, 0x40, 0x00, 0x34, 0x06, 0x00, 0x00, 10, 0, 0, 1, 10, 0,
+        0, 2,
+    ];
+
+    /// TCP header of a SYN sent by Linux, with a window of 64240 and 20 bytes of options.
+    const TCP_HEADER: &[u8] = &[
+        0xd4, 0x31, 0x00, 0x16, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0xa0, 0x02, 0xfa,
+        0xf0, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// MSS 1460, SACK permitted, timestamps, NOP and window scale 7.
+    const TCP_OPTIONS: &[u8] = &[
+        0x02, 0x04, 0x05, 0xb4, 0x04, 0x02, 0x08, 0x0a, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x03, 0x03, 0x07,
+    ];
+
+    #[test]
+    fn parses_linux_syn() {
+        let event = parse_syn(&[IPV4_HEADER, TCP_HEADER, TCP_OPTIONS].concat());
+
+        assert_eq!(event.ttl, Some(52));
+        assert_eq!(event.initial_ttl, Some(64));
+        assert_eq!(event.os_guess.as_deref(), Some("unix"));
+        assert_eq!(event.window_size, Some(64240));
+        assert_eq!(event.window_scale, Some(7));
+        assert_eq!(event.mss, Some(1460));
+        assert_eq!(event.tcp_options.as_deref(), Some("mss,sok,ts,nop,ws"));
+    }
+
+    #[test_case(Some("42"), Some("2"), Some((3, 5)); "activated")]
+    #[test_case(Some("41"), Some("2"), None; "other process")]
+    #[test_case(Some("42"), Some("0"), None; "no sockets")]
+    #[test_case(None, None, None; "not activated")]
+    fn parses_listen_fds(pid: Option<&str>, fds: Option<&str>, expected: Option<(i32, i32)>) {
+        assert_eq!(listen_fds(pid, fds, 42).map(|v| (v.start, v.end)), expected);
+    }
+
+    #[test]
+    fn truncated_syn() {
+        let event = parse_syn(&[IPV4_HEADER, &TCP_HEADER[..10]].concat());
+
+        assert_eq!(event.ttl, Some(52));
+        assert_eq!(event.window_size, None);
+        assert_eq!(parse_syn(&[]).ttl, None);
+    }
+}
@@ -0,0 +1,61 @@
+//! Sets up logging, and optionally exports spans to an OpenTelemetry collector so each
+//! connection can be viewed as a trace, with its authentication attempts, channels and commands as
+//! child spans.
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::warn;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::Config;
+
+/// Handle to the trace exporter, which should be shut down before exiting so any spans still
+/// waiting to be exported are sent.
+pub struct Telemetry(Option<SdkTracerProvider>);
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.0 {
+            if let Err(error) = provider.shutdown() {
+                warn!(%error, "Failed to export remaining spans");
+            }
+        }
+    }
+}
+
+pub fn init(config: &Config) -> anyhow::Result<Telemetry> {
+    let provider = config
+        .otlp_endpoint
+        .as_ref()
+        .map(|endpoint| {
+            let exporter = SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?;
+
+            Ok::<_, anyhow::Error>(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(
+                        Resource::builder()
+                            .with_service_name(env!("CARGO_PKG_NAME"))
+                            .build(),
+                    )
+                    .build(),
+            )
+        })
+        .transpose()?;
+
+    let otel = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+    });
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .init();
+
+    Ok(Telemetry(provider))
+}
@@ -0,0 +1,128 @@
+//! Placeholders filled into text from the config file each time it's shown, so canned output
+//! isn't identical byte-for-byte across every deployment and connection.
+//!
+//! Placeholders are wrapped in double braces, ie. `{{hostname}}`, and are one of:
+//!
+//! - `hostname`: the hostname presented to the client
+//! - `username`: the user the client is logged in as
+//! - `ip`: the client's address
+//! - `date`: the current time, formatted like `date`'s default output
+//! - `random A-B`: a number picked between `A` and `B` inclusive
+//!
+//! Anything else between braces is left as is. The single brace `{hostname}` and `{date}`
+//! accepted by older configs are still filled in.
+
+use std::net::IpAddr;
+
+use time::{macros::format_description, OffsetDateTime};
+
+/// Values available to a template.
+pub struct Context<'a> {
+    pub hostname: &'a str,
+    /// Empty if there's no user yet, ie. in the banner shown before authentication.
+    pub username: &'a str,
+    pub ip: Option<IpAddr>,
+    pub now: OffsetDateTime,
+    pub rng: &'a fastrand::Rng,
+}
+
+impl Context<'_> {
+    /// The value of a placeholder, or `None` if it isn't one we know about.
+    fn evaluate(&self, expression: &str) -> Option<String> {
+        let expression = expression.trim();
+
+        if let Some(range) = expression.strip_prefix("random ") {
+            let (start, end) = range.trim().split_once('-')?;
+            let (start, end): (u64, u64) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+            return (start <= end).then(|| self.rng.u64(start..=end).to_string());
+        }
+
+        match expression {
+            "hostname" => Some(self.hostname.to_string()),
+            "username" => Some(self.username.to_string()),
+            "ip" => Some(self.ip.map(|v| v.to_string()).unwrap_or_default()),
+            "date" => Some(
+                self.now
+                    .format(format_description!(
+                        "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]:[second] UTC [year]"
+                    ))
+                    .unwrap_or_default(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Fills in every placeholder in `template`.
+pub fn render(template: &str, context: &Context<'_>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let (placeholder, value) = if let Some(inner) = rest.strip_prefix("{{") {
+            let Some(end) = inner.find("}}") else {
+                break;
+            };
+            (&rest[..end + 4], context.evaluate(&inner[..end]))
+        } else if let Some(legacy) = ["{hostname}", "{date}"]
+            .into_iter()
+            .find(|v| rest.starts_with(v))
+        {
+            (legacy, context.evaluate(&legacy[1..legacy.len() - 1]))
+        } else {
+            ("{", None)
+        };
+
+        out.push_str(value.as_deref().unwrap_or(placeholder));
+        rest = &rest[placeholder.len()..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::template::{render, Context};
+
+    fn context(rng: &fastrand::Rng) -> Context<'_> {
+        Context {
+            hostname: "web-01",
+            username: "admin",
+            ip: Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+            now: datetime!(2023-06-05 09:03:00 UTC),
+            rng,
+        }
+    }
+
+    #[test_case("Welcome to {{hostname}}, it's {{ date }}\n", "Welcome to web-01, it's Mon Jun  5 09:03:00 UTC 2023\n"; "variables")]
+    #[test_case("Last login: {{date}} from {{ip}} as {{username}}", "Last login: Mon Jun  5 09:03:00 UTC 2023 from 192.0.2.1 as admin"; "client")]
+    #[test_case("Welcome to {hostname}, it's {date}\n", "Welcome to web-01, it's Mon Jun  5 09:03:00 UTC 2023\n"; "legacy")]
+    #[test_case("{{unknown}} {\"a\": 1} {{random 9-1}} {{", "{{unknown}} {\"a\": 1} {{random 9-1}} {{"; "left alone")]
+    fn renders(template: &str, expected: &str) {
+        assert_eq!(
+            render(template, &context(&fastrand::Rng::with_seed(0))),
+            expected
+        );
+    }
+
+    #[test]
+    fn renders_random_ranges() {
+        let rng = fastrand::Rng::with_seed(0);
+
+        for _ in 0..100 {
+            let value: u64 = render("{{random 10-20}}", &context(&rng)).parse().unwrap();
+            assert!((10..=20).contains(&value), "{value}");
+        }
+
+        assert_eq!(render("{{random 7-7}}", &context(&rng)), "7");
+    }
+}
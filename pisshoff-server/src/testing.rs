@@ -0,0 +1,35 @@
+//! Helpers for testing commands outside of a running server, enabled by the `testing` feature.
+//!
+//! Commands are given a [`ConnectionState::mock`] connection and a [`MockThrusshSession`] with
+//! expectations set for the output they should write:
+//!
+//! ```ignore
+//! let mut connection = ConnectionState::mock();
+//! let mut session = MockThrusshSession::default();
+//! session
+//!     .expect_data()
+//!     .once()
+//!     .with(mockall::predicate::always(), testing::eq_string("hello\n"))
+//!     .returning(|_, _| ());
+//!
+//! MyCommand::new(&mut connection, &[], testing::channel_id(), &mut session).await;
+//! ```
+
+use mockall::Predicate;
+use thrussh::{ChannelId, CryptoVec};
+
+pub use crate::server::{ConnectionState, MockThrusshSession};
+
+/// A channel for commands to write their output to, which a [`MockThrusshSession`] doesn't
+/// otherwise care about.
+#[must_use]
+pub fn channel_id() -> ChannelId {
+    // SAFETY: ChannelId is a plain u32 newtype that thrussh doesn't give a way to construct
+    unsafe { std::mem::transmute(0_u32) }
+}
+
+/// Matches output written to a [`MockThrusshSession`] against the string it's expected to be.
+#[must_use]
+pub fn eq_string(s: &str) -> impl Predicate<CryptoVec> + '_ {
+    mockall::predicate::function(|v: &CryptoVec| &**v == s.as_bytes())
+}
@@ -0,0 +1,147 @@
+//! Counts the bytes exchanged with the client at the socket, so the totals include everything
+//! thrussh sends and receives, and cuts the connection off once it goes over its caps.
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Bytes read from and written to the client so far.
+#[derive(Debug, Default)]
+pub struct Traffic {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl Traffic {
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+}
+
+/// Wraps the client's stream, counting the bytes passing through it. Once either direction has
+/// gone over its cap, every further read and write fails, which has thrussh close the
+/// connection.
+pub struct Metered<S> {
+    inner: S,
+    traffic: Arc<Traffic>,
+    max_in: u64,
+    max_out: u64,
+}
+
+impl<S> Metered<S> {
+    /// Wraps `inner`, returning a handle to the running totals.
+    pub fn new(inner: S, max_in: u64, max_out: u64) -> (Self, Arc<Traffic>) {
+        let traffic = Arc::new(Traffic::default());
+
+        (
+            Self {
+                inner,
+                traffic: traffic.clone(),
+                max_in,
+                max_out,
+            },
+            traffic,
+        )
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.traffic.bytes_in() > self.max_in || self.traffic.bytes_out() > self.max_out {
+            Err(io::Error::other("connection exceeded its traffic limit"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Metered<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.check()?;
+
+        let filled = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = res {
+            let read = (buf.filled().len() - filled) as u64;
+            self.traffic.bytes_in.fetch_add(read, Ordering::Relaxed);
+        }
+
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Metered<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.check()?;
+
+        let res = Pin::new(&mut self.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(written)) = res {
+            self.traffic
+                .bytes_out
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::traffic::Metered;
+
+    #[tokio::test]
+    async fn counts_traffic() {
+        let (mut metered, traffic) = Metered::new(Vec::new(), 100, 100);
+        metered.write_all(b"hello").await.unwrap();
+        metered.write_all(b" world").await.unwrap();
+        assert_eq!(traffic.bytes_out(), 11);
+
+        let (mut metered, traffic) = Metered::new(b"hello world".as_slice(), 100, 100);
+        metered.read_to_end(&mut Vec::new()).await.unwrap();
+        assert_eq!(traffic.bytes_in(), 11);
+    }
+
+    #[tokio::test]
+    async fn fails_once_over_limit() {
+        let (mut metered, traffic) = Metered::new(b"hello world".as_slice(), 4, 100);
+
+        let mut buf = [0; 5];
+        assert_eq!(metered.read(&mut buf).await.unwrap(), 5);
+        metered.read(&mut buf).await.unwrap_err();
+        assert_eq!(traffic.bytes_in(), 5);
+
+        let (mut metered, _traffic) = Metered::new(Vec::new(), 100, 4);
+        metered.write_all(b"hello").await.unwrap();
+        metered.write_all(b"hello").await.unwrap_err();
+    }
+}
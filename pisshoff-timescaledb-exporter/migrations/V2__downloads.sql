@@ -0,0 +1,13 @@
+CREATE TABLE audit_downloads (
+    timestamp TIMESTAMPTZ NOT NULL,
+    connection_id UUID NOT NULL,
+    command TEXT NOT NULL,
+    url TEXT NOT NULL,
+    destination TEXT,
+    user_agent TEXT
+);
+
+SELECT create_hypertable('audit_downloads', 'timestamp');
+
+CREATE INDEX audit_downloads_connection_id ON audit_downloads USING HASH (connection_id);
+CREATE INDEX audit_downloads_url ON audit_downloads USING HASH (url);
@@ -0,0 +1,8 @@
+CREATE TABLE audit_tags (
+    connection_id uuid NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX audit_tags_connection_id ON audit_tags USING HASH (connection_id);
+CREATE INDEX audit_tags_name ON audit_tags USING HASH (name);
@@ -0,0 +1,5 @@
+-- sequence numbers give the order events were recorded in across concurrent connections, events
+-- exported before this migration have no sequence number
+ALTER TABLE audit_events ADD COLUMN sequence BIGINT;
+
+CREATE INDEX audit_events_sequence ON audit_events (sequence);
@@ -0,0 +1,14 @@
+CREATE TABLE audit_authorized_keys (
+    timestamp TIMESTAMPTZ NOT NULL,
+    connection_id UUID NOT NULL,
+    path TEXT NOT NULL,
+    key_type TEXT NOT NULL,
+    key TEXT NOT NULL,
+    fingerprint TEXT,
+    comment TEXT
+);
+
+SELECT create_hypertable('audit_authorized_keys', 'timestamp');
+
+CREATE INDEX audit_authorized_keys_connection_id ON audit_authorized_keys USING HASH (connection_id);
+CREATE INDEX audit_authorized_keys_key ON audit_authorized_keys USING HASH (key);
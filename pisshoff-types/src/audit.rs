@@ -1,18 +1,31 @@
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     fmt::{Debug, Formatter},
     net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
     time::{Duration, Instant},
 };
 
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
-use strum::IntoStaticStr;
+use strum::{EnumVariantNames, IntoStaticStr};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+use crate::byte_str::ByteStr;
+
+/// The version of the format audit logs are currently written in. This is bumped whenever a
+/// change is made that collectors written against the previous version would misread or fail to
+/// parse, such as an event or field being renamed, so they can tell which format a log is in.
+pub const AUDIT_LOG_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize)]
 pub struct AuditLog {
+    /// The format the log was written in, see [`AUDIT_LOG_VERSION`]. Logs written before the
+    /// format was versioned don't have one, and are version 0.
+    #[serde(default)]
+    pub version: u32,
     pub connection_id: Uuid,
     #[serde(with = "time::serde::rfc3339")]
     pub ts: OffsetDateTime,
@@ -20,6 +33,28 @@ pub struct AuditLog {
     pub host: Cow<'static, str>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub environment_variables: Vec<(Box<str>, Box<str>)>,
+    /// Arbitrary key/value pairs attached to the connection by the emulation, ie. `miner=1`
+    /// when a known cryptocurrency miner is downloaded.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub tags: BTreeMap<Cow<'static, str>, Cow<'static, str>>,
+    /// The identification string the client sent at the start of the connection, ie.
+    /// `SSH-2.0-OpenSSH_9.6` or `SSH-2.0-Go`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub client_version: Option<Box<str>>,
+    /// The algorithms agreed on from the client's key exchange proposal.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub algorithms: Option<NegotiatedAlgorithms>,
+    /// The [HASSH](https://github.com/salesforce/hassh) fingerprint of the client's key exchange
+    /// proposal, which identifies the tool connecting regardless of where it connects from.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub hassh: Option<Box<str>>,
+    /// Where the client's address is registered, if the server has a database of addresses with
+    /// an entry for it.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub location: Option<GeoLocation>,
+    /// Totals for the connection, filled in once it closes.
+    #[serde(default)]
+    pub summary: SessionSummary,
     pub events: Vec<AuditLogEvent>,
     #[serde(skip, default = "Instant::now")]
     pub start: Instant,
@@ -28,11 +63,18 @@ pub struct AuditLog {
 impl Default for AuditLog {
     fn default() -> Self {
         Self {
+            version: AUDIT_LOG_VERSION,
             connection_id: Uuid::default(),
             ts: OffsetDateTime::now_utc(),
             host: Cow::Borrowed(""),
             peer_address: None,
             environment_variables: vec![],
+            tags: BTreeMap::new(),
+            client_version: None,
+            algorithms: None,
+            hassh: None,
+            location: None,
+            summary: SessionSummary::default(),
             events: vec![],
             start: Instant::now(),
         }
@@ -46,35 +88,67 @@ impl Debug for AuditLog {
             .field("connection_id", &self.connection_id)
             .field("peer_address", &self.peer_address)
             .field("environment_variables", &self.environment_variables)
+            .field("tags", &self.tags)
             .field("events", &self.events)
             .finish()
     }
 }
 
+/// Source of event sequence numbers, shared between every connection within the process so
+/// events from concurrent connections can be totally ordered against each other.
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
 impl AuditLog {
+    /// Attaches a tag to the connection, overwriting any previous value for the same key.
+    pub fn tag(&mut self, key: impl Into<Cow<'static, str>>, value: impl Into<Cow<'static, str>>) {
+        self.tags.insert(key.into(), value.into());
+    }
+
     pub fn push_action(&mut self, action: AuditLogAction) {
+        self.push_action_at(action, self.start.elapsed());
+    }
+
+    /// Records `action` as having happened `start_offset` into the connection, for callers
+    /// keeping time themselves.
+    pub fn push_action_at(&mut self, action: AuditLogAction, start_offset: Duration) {
         self.events.push(AuditLogEvent {
-            start_offset: self.start.elapsed(),
+            sequence: Some(NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed)),
+            start_offset,
             action,
         });
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A single action taken by the client.
+///
+/// Events within a single [`AuditLog`] are always stored in the order they occurred. Across
+/// connections, `sequence` gives the order events were recorded in by the server process and
+/// should be used over `start_offset` when reconstructing how concurrent connections (or
+/// channels) interleaved, as the offsets of two connections are only as precise as the clock.
+/// Sequence numbers restart from zero when the server is restarted and are not contiguous within
+/// a single connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditLogEvent {
+    /// Monotonically increasing sequence number, unique within the lifetime of the server process.
+    /// `None` for events recorded by servers that predate sequence numbers.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sequence: Option<u64>,
     pub start_offset: Duration,
     pub action: AuditLogAction,
 }
 
-#[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
+#[derive(Debug, Clone, Serialize, Deserialize, IntoStaticStr, EnumVariantNames)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 #[strum(serialize_all = "kebab-case")]
 pub enum AuditLogAction {
+    ConnectionMetadata(ConnectionMetadataEvent),
     LoginAttempt(LoginAttemptEvent),
     PtyRequest(PtyRequestEvent),
     X11Request(X11RequestEvent),
     OpenX11(OpenX11Event),
     OpenDirectTcpIp(OpenDirectTcpIpEvent),
+    ForwardedData(ForwardedDataEvent),
+    SocksConnect(SocksConnectEvent),
     ExecCommand(ExecCommandEvent),
     WindowAdjusted(WindowAdjustedEvent),
     ShellRequested,
@@ -85,40 +159,500 @@ pub enum AuditLogAction {
     CancelTcpIpForward(TcpIpForwardEvent),
     Mkdir(MkdirEvent),
     WriteFile(WriteFileEvent),
+    PartialUpload(PartialUploadEvent),
+    KernelModuleAttempt(KernelModuleAttemptEvent),
+    DownloadAttempt(DownloadAttemptEvent),
+    CronInstalled(CronInstalledEvent),
+    AuthorizedKeyAdded(AuthorizedKeyAddedEvent),
+    Chmod(ChmodEvent),
+    Chown(ChownEvent),
+    Chattr(ChattrEvent),
+    ScriptExecution(ScriptExecutionEvent),
+    BinaryExecution(BinaryExecutionEvent),
+    SudoPassword(SudoPasswordEvent),
+    SwitchUser(SwitchUserEvent),
+    LateralMovement(LateralMovementEvent),
+    DefenseEvasion(DefenseEvasionEvent),
+    PackageInstall(PackageInstallEvent),
+    Transcript(TranscriptEvent),
+    CommandSummary(CommandSummaryEvent),
+    HoneytokenAccessed(HoneytokenAccessedEvent),
+    AntiForensics(AntiForensicsEvent),
+    SuspectedMiner(SuspectedMinerEvent),
+    DetectionAttempt(DetectionAttemptEvent),
+    ContainerCommand(ContainerCommandEvent),
+    MetadataAccess(MetadataAccessEvent),
+    AuthCheck(AuthCheckEvent),
+    PreAuthDisconnect(PreAuthDisconnectEvent),
+    ShellParseError(ShellParseErrorEvent),
+    CanaryCredentialUsed(CanaryCredentialUsedEvent),
+}
+
+/// The country and autonomous system an address is registered to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoLocation {
+    /// ISO 3166-1 alpha-2 country code, ie. `NL`.
+    pub country: Box<str>,
+    pub asn: u32,
+    /// The name the autonomous system is registered under, ie. `DIGITALOCEAN-ASN`.
+    pub as_name: Box<str>,
+}
+
+/// The algorithms picked for the connection, being the first in each of the client's lists that
+/// the server also supports. Each is missing if there were none in common, in which case the
+/// connection failed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NegotiatedAlgorithms {
+    pub kex: Option<Box<str>>,
+    pub host_key: Option<Box<str>>,
+    pub cipher: Option<Box<str>>,
+    pub mac: Option<Box<str>>,
+    pub compression: Option<Box<str>>,
+}
+
+/// Counts of what the client got up to over the whole connection.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// Bytes read from the client, including SSH framing and encryption overhead.
+    pub bytes_in: u64,
+    /// Bytes written to the client, including SSH framing and encryption overhead.
+    pub bytes_out: u64,
+    pub channels: u64,
+    pub auth_attempts: u64,
+    pub commands: u64,
+    /// The cap the connection was terminated for going over, if any.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub limit_exceeded: Option<SessionLimit>,
+    /// How many events of each type were left out of the log by the server's sampling, keyed by
+    /// the events' `type`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub dropped_events: BTreeMap<Cow<'static, str>, u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A per-connection cap on one of the counts in [`SessionSummary`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, IntoStaticStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum SessionLimit {
+    BytesIn,
+    BytesOut,
+    Channels,
+    AuthAttempts,
+    Commands,
+}
+
+/// Details of the TCP connection as seen when it was accepted, useful for passively
+/// fingerprinting the client's network stack.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConnectionMetadataEvent {
+    /// TTL (or hop limit) of the client's SYN packet as it arrived.
+    pub ttl: Option<u8>,
+    /// The TTL the client most likely sent the SYN with, before it was decremented by each hop.
+    pub initial_ttl: Option<u8>,
+    /// Operating system family typically using `initial_ttl`, ie. `unix` or `windows`.
+    pub os_guess: Option<Cow<'static, str>>,
+    /// Receive window advertised in the client's SYN.
+    pub window_size: Option<u16>,
+    pub window_scale: Option<u8>,
+    /// Maximum segment size advertised in the client's SYN.
+    pub mss: Option<u16>,
+    /// Layout of the TCP options in the client's SYN, in the same format as p0f, ie.
+    /// `mss,sok,ts,nop,ws`.
+    pub tcp_options: Option<Box<str>>,
+    /// Round trip time measured by the kernel during the TCP handshake.
+    pub handshake_rtt: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MkdirEvent {
     pub path: Box<str>,
+    /// The mode the directory was created with as given by the client, ie. `0755`, if the
+    /// protocol sends one.
+    #[serde(default)]
+    pub mode: Option<Box<str>>,
+    /// The modification time the client asked to be preserved on the directory, if any.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub modified: Option<OffsetDateTime>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WriteFileEvent {
     pub path: Box<str>,
+    /// The data written, truncated to the server's configured limit. Empty if the server is
+    /// keeping written files in its payload store instead.
     pub content: Bytes,
+    /// The total number of bytes written, regardless of how much of it was kept in `content`.
+    pub size: u64,
+    /// The hex-encoded SHA-256 of the full data written, which is also the name the file is kept
+    /// under in the payload store. Missing from logs written by older versions, which only set it
+    /// if `content` was truncated.
+    pub sha256: Option<Box<str>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExecCommandEvent {
+/// A file transfer that was interrupted before it completed, such as by the client
+/// disconnecting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialUploadEvent {
+    /// The protocol the file was being transferred over, ie. `scp` or `sftp`.
+    pub protocol: Cow<'static, str>,
+    pub path: Box<str>,
+    /// The number of bytes of the file received before the transfer was interrupted.
+    pub bytes_received: u64,
+    /// The size of the file the client announced, if the protocol sends it upfront.
+    pub expected_bytes: Option<u64>,
+    /// The data received before the transfer was interrupted, truncated to the server's
+    /// configured limit, or `None` if the transfer was refused before any data was accepted.
+    pub content: Option<Bytes>,
+    /// The hex-encoded SHA-256 of the data received, set only if `content` was truncated.
+    pub sha256: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelModuleAttemptEvent {
+    /// The command used to load the module, ie. `modprobe` or `insmod`.
+    pub command: Cow<'static, str>,
+    /// The name of the module, or the path to the object file in the case of `insmod`.
+    pub module: Box<str>,
+    /// Any module parameters passed alongside the module.
+    pub params: Box<[String]>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadAttemptEvent {
+    /// The command used to fetch the resource, ie. `wget`, `curl` or `git`.
+    pub command: Cow<'static, str>,
+    /// The URL the client attempted to fetch.
+    pub url: Box<str>,
+    /// The path the resource would have been written to, or `None` if it was written to stdout.
+    pub destination: Option<Box<str>>,
+    /// The user agent explicitly requested by the client, if any.
+    pub user_agent_flag: Option<Box<str>>,
+}
+
+/// A request to the cloud instance metadata service, usually made to steal the credentials of
+/// the role the instance runs as.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataAccessEvent {
+    /// The command used to make the request, ie. `curl` or `wget`.
+    pub command: Cow<'static, str>,
+    pub url: Box<str>,
+    /// Whether the response handed out the role's credentials.
+    pub credentials: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CronInstalledEvent {
+    /// The user the crontab was installed for.
+    pub user: Box<str>,
+    /// The file the crontab was read from, or `None` if it was read from stdin.
+    pub source: Option<Box<str>>,
+    /// The entries being installed, verbatim.
+    pub content: Box<str>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizedKeyAddedEvent {
+    /// The `authorized_keys` file the key was written to.
+    pub path: Box<str>,
+    /// The type of the key, ie. `ssh-rsa` or `ssh-ed25519`.
+    pub key_type: Box<str>,
+    /// The base64-encoded public key, as written to the file.
+    pub key: Box<str>,
+    /// The SHA256 fingerprint of the key, or `None` if the key couldn't be decoded.
+    pub fingerprint: Option<Box<str>>,
+    /// The comment trailing the key, often identifying the botnet or operator that added it.
+    pub comment: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChmodEvent {
+    pub path: Box<str>,
+    /// The mode as given by the client, ie. `+x` or `755`.
+    pub mode: Box<str>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChownEvent {
+    pub path: Box<str>,
+    /// The new owner of the file, or `None` if only the group is being changed.
+    pub owner: Option<Box<str>>,
+    /// The new group of the file, or `None` if only the owner is being changed.
+    pub group: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChattrEvent {
+    pub path: Box<str>,
+    /// The attribute changes as given by the client, ie. `+i` or `-ia`.
+    pub attributes: Box<str>,
+}
+
+/// A script handed to an interpreter such as `python` or `perl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptExecutionEvent {
+    /// The interpreter the script was passed to, ie. `python3` or `php`.
+    pub interpreter: Cow<'static, str>,
+    /// The file the script was read from, `-` if it was read from stdin, or `None` if it was
+    /// passed inline such as with `python -c`.
+    pub source: Option<Box<str>>,
+    /// The full text of the script.
+    pub script: ByteStr,
+    /// Arguments passed through to the script.
+    pub args: Box<[String]>,
+}
+
+/// A binary in the fake file system being run, such as an uploaded payload. Scripts are recorded
+/// as a [`ScriptExecutionEvent`] instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinaryExecutionEvent {
+    pub path: Box<str>,
+    /// The hex-encoded SHA-256 of the file, matching the name it's kept under in the payload
+    /// store.
+    pub sha256: Box<str>,
+    pub size: u64,
     pub args: Box<[String]>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A password typed into the `sudo` password prompt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SudoPasswordEvent {
+    /// The user the password was requested for, ie. the user the client is logged in as.
+    pub username: Box<str>,
+    pub password: Box<str>,
+    /// The command the client was trying to run with `sudo`.
+    pub command: Box<[String]>,
+}
+
+/// An attempt to switch to another user with `su`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchUserEvent {
+    pub from: Box<str>,
+    pub to: Box<str>,
+    /// The password typed into the prompt, or `None` if one wasn't asked for, such as when
+    /// switching from root.
+    pub password: Option<Box<str>>,
+    pub success: bool,
+}
+
+/// An attempt to connect out from the honeypot to another host, ie. with `ssh` or `scp`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LateralMovementEvent {
+    /// The client used to make the connection, ie. `ssh`, `scp` or `telnet`.
+    pub command: Cow<'static, str>,
+    pub host: Box<str>,
+    pub port: u16,
+    /// The user the client would have logged in as, if the protocol has one.
+    pub username: Option<Box<str>>,
+    /// The command the client asked to be run on the remote host, if any.
+    pub remote_command: Option<Box<str>>,
+    /// The remote file being copied to or from, if any.
+    pub path: Option<Box<str>>,
+}
+
+/// An attempt to stop or weaken a security control, such as stopping a service or flushing the
+/// firewall rules.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefenseEvasionEvent {
+    /// The command used, ie. `systemctl`, `service`, `iptables` or `ufw`.
+    pub command: Cow<'static, str>,
+    /// What was done, ie. `stop`, `disable` or `-F`.
+    pub action: Box<str>,
+    /// The services, chains or rules the action was applied to.
+    pub targets: Box<[String]>,
+}
+
+/// A container or cluster management command run by the client, often the first step in trying
+/// to escape a container or abuse the cloud account the server runs in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerCommandEvent {
+    /// The command used, ie. `docker` or `kubectl`.
+    pub command: Cow<'static, str>,
+    /// Every argument passed to the command, in order.
+    pub args: Box<[String]>,
+}
+
+/// Packages the client asked a package manager to install.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageInstallEvent {
+    /// The package manager used, ie. `apt-get`, `yum` or `apk`.
+    pub manager: Cow<'static, str>,
+    pub packages: Box<[String]>,
+}
+
+/// How many times each command was run across every connection since the previous summary,
+/// written periodically in its own log entry with no peer address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandSummaryEvent {
+    /// Length of time the counts cover.
+    pub period: Duration,
+    /// Commands that were found, by name.
+    pub commands: BTreeMap<Box<str>, u64>,
+    /// Names that the shell couldn't find a command for.
+    pub unknown_commands: BTreeMap<Box<str>, u64>,
+}
+
+/// A file configured as a honeytoken was touched by the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoneytokenAccessedEvent {
+    pub path: Box<str>,
+    pub access: HoneytokenAccess,
+    /// The token templated into the file's content for this connection, so credentials later
+    /// used elsewhere can be traced back to the connection they were taken from.
+    pub token: Box<str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HoneytokenAccess {
+    Read,
+    Write,
+    Stat,
+}
+
+/// The client logged in with one of the configured canary credentials, which are only ever
+/// seeded somewhere they could be leaked from, so the client learnt them from that leak.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryCredentialUsedEvent {
+    pub method: Cow<'static, str>,
+    pub username: Box<str>,
+    /// The label configured for the credential, to tell apart where each was seeded.
+    pub label: Option<Box<str>>,
+}
+
+/// The client tried to cover its tracks, such as by clearing the shell history or wiping logs.
+/// The server pretends to comply, so the attempt itself is all that's recorded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AntiForensicsEvent {
+    pub technique: AntiForensicsTechnique,
+    /// The files or variables that were targeted.
+    pub targets: Box<[String]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AntiForensicsTechnique {
+    /// The shell's history was cleared with `history -c`.
+    ClearHistory,
+    /// History was stopped from being saved, ie. `unset HISTFILE` or `export HISTSIZE=0`.
+    DisableHistory,
+    /// A history file such as `~/.bash_history` was removed or overwritten.
+    DeleteHistory,
+    /// A log under `/var/log` was removed, truncated or overwritten.
+    TamperLogs,
+    /// Files were overwritten with `shred` to stop them being recovered.
+    Shred,
+}
+
+/// Commands run or files uploaded by the client look like they're setting up a cryptocurrency
+/// miner. Raised again each time a new indicator is seen on the connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuspectedMinerEvent {
+    pub confidence: MinerConfidence,
+    /// Every indicator seen on the connection so far, in the order they were first seen.
+    pub indicators: Box<[MinerIndicator]>,
+    /// The command or path of the uploaded file that revealed the latest indicator.
+    pub source: Box<str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MinerConfidence {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MinerIndicator {
+    /// A well known miner such as `xmrig` was named in a command, file name or file content.
+    KnownMiner,
+    /// A `stratum+tcp://` (or `+ssl`/`+tls`) mining pool URL.
+    StratumUrl,
+    /// The CPU count was checked with `nproc`, and a file downloaded and made executable, the
+    /// usual steps of a script sizing a miner's thread count to the host.
+    DownloadAndExecute,
+    /// A JSON config file with a list of pools, as read by `xmrig` and its forks.
+    PoolConfig,
+}
+
+/// The client ran something known to be used to tell honeypots apart from real servers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectionAttemptEvent {
+    pub probe: DetectionProbe,
+    /// The command line, or for nonsense commands the name, that gave the probe away.
+    pub command: Box<str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectionProbe {
+    /// `/proc/mounts` was read, which is often missing or sparse on honeypots.
+    Mounts,
+    /// The client checked whether it's running in a container or virtual machine, ie. by reading
+    /// `/proc/1/cgroup` or looking for `/.dockerenv`.
+    Virtualisation,
+    /// Defaults shipped with well known honeypots were looked for, such as Cowrie's `svr04`
+    /// hostname or `phil` user.
+    HoneypotDefaults,
+    /// `echo -e` was used to print escaped bytes, checking the shell interprets them like bash
+    /// does.
+    EchoEscapes,
+    /// A made up command was run to check the error matches bash's.
+    NonsenseCommand,
+}
+
+/// Raw data sent by the client on a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEvent {
+    pub data: Bytes,
+}
+
+/// Input the shell couldn't parse, which is often the most interesting input there is and is
+/// kept so the parser can be taught to handle it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellParseErrorEvent {
+    /// The script or line that failed to parse, including any earlier lines of a statement
+    /// spanning several, truncated to the server's configured limit.
+    pub input: Bytes,
+    /// Why it couldn't be parsed, with positions given as byte offsets into `input`.
+    pub error: Box<str>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecCommandEvent {
+    /// The command exactly as sent by the client, either in an exec request (what `sshd` would
+    /// expose as `SSH_ORIGINAL_COMMAND`) or as input to an interactive shell.
+    #[serde(default)]
+    pub command: ByteStr,
+    /// `command` split into words the way a shell would, or `None` if it couldn't be, ie. due to
+    /// an unterminated quote.
+    pub args: Option<Box<[ByteStr]>>,
+    /// Whether the command was typed into an interactive shell rather than sent in an exec
+    /// request.
+    #[serde(default)]
+    pub interactive: bool,
+    /// Whether the client requested a PTY on the channel before sending the command.
+    #[serde(default)]
+    pub pty: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WindowAdjustedEvent {
     pub new_size: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubsystemRequestEvent {
     pub name: Box<str>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignalEvent {
     pub name: Box<str>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "credential-type", rename_all = "kebab-case")]
 pub enum LoginAttemptEvent {
     UsernamePassword {
@@ -129,9 +663,55 @@ pub enum LoginAttemptEvent {
         kind: Cow<'static, str>,
         fingerprint: Box<str>,
     },
+    /// The client tried the `none` method, usually to find out which methods are accepted
+    /// before trying any of them.
+    None { username: Box<str> },
+    /// The answers given to each keyboard-interactive prompt. The first answer is also checked
+    /// as a password, and logged as a username-password attempt.
+    KeyboardInteractive {
+        username: Box<str>,
+        submethods: Box<str>,
+        responses: Vec<KeyboardInteractiveResponse>,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The outcome of an authentication attempt, recorded as it's checked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthCheckEvent {
+    /// The method used, as named in the protocol, ie. `password`.
+    pub method: Cow<'static, str>,
+    pub username: Box<str>,
+    pub accepted: bool,
+    /// Number of authentication attempts made on the connection so far, including this one.
+    pub attempt: u64,
+    /// Each method the client has tried on the connection so far, in the order they were first
+    /// tried.
+    pub methods_tried: Vec<Cow<'static, str>>,
+    /// The methods offered to the client.
+    pub methods_advertised: Vec<Cow<'static, str>>,
+    /// The ID of the auth rule matching where the client connected from, which decides the
+    /// outcome of password logins in place of the usual checks.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub rule: Option<Box<str>>,
+}
+
+/// The client disconnected without authenticating, as most brute forcing and scanning does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreAuthDisconnectEvent {
+    /// How long the client was connected for.
+    pub duration: Duration,
+    pub auth_attempts: u64,
+    /// Each method the client tried, in the order they were first tried.
+    pub methods_tried: Vec<Cow<'static, str>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyboardInteractiveResponse {
+    pub prompt: Box<str>,
+    pub response: Box<str>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PtyRequestEvent {
     pub term: Box<str>,
     pub col_width: u32,
@@ -141,13 +721,13 @@ pub struct PtyRequestEvent {
     pub modes: Box<[(u8, u32)]>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenX11Event {
     pub originator_address: Box<str>,
     pub originator_port: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct X11RequestEvent {
     pub single_connection: bool,
     pub x11_auth_protocol: Box<str>,
@@ -155,7 +735,7 @@ pub struct X11RequestEvent {
     pub x11_screen_number: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenDirectTcpIpEvent {
     pub host_to_connect: Box<str>,
     pub port_to_connect: u32,
@@ -163,7 +743,29 @@ pub struct OpenDirectTcpIpEvent {
     pub originator_port: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Data sent by the client through a `direct-tcpip` channel, destined for the remote host it
+/// asked us to connect to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardedDataEvent {
+    pub host_to_connect: Box<str>,
+    pub port_to_connect: u32,
+    pub data: Bytes,
+}
+
+/// A request to connect somewhere, sent by a client that believed it was talking to a SOCKS proxy
+/// on the other end of a `direct-tcpip` channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocksConnectEvent {
+    /// The SOCKS protocol version used, either 4 or 5.
+    pub version: u8,
+    pub host: Box<str>,
+    pub port: u16,
+    /// The SOCKS4 user ID or SOCKS5 username, if the client sent one.
+    pub username: Option<Box<str>>,
+    pub password: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WindowChangeRequestEvent {
     pub col_width: u32,
     pub row_height: u32,
@@ -171,7 +773,7 @@ pub struct WindowChangeRequestEvent {
     pub pix_height: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TcpIpForwardEvent {
     pub address: Box<str>,
     pub port: u32,
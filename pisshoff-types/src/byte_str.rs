@@ -0,0 +1,187 @@
+//! A string backed by [`Bytes`], so text captured from a connection, such as a command or a
+//! script, can be shared between events and the buffers it was read from rather than copied
+//! into each of them.
+
+use std::{
+    borrow::{Borrow, Cow},
+    fmt::{Debug, Display, Formatter},
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// UTF-8 text backed by [`Bytes`]. Cloning it, or taking a part of it with
+/// [`ByteStr::slice_ref`], only bumps a reference count.
+///
+/// It's serialised as a plain string, so it's indistinguishable from a `String` in audit logs.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteStr(Bytes);
+
+impl ByteStr {
+    #[must_use]
+    pub const fn from_static(value: &'static str) -> Self {
+        Self(Bytes::from_static(value.as_bytes()))
+    }
+
+    /// Takes `bytes` without copying them if they're valid UTF-8, otherwise copies them with any
+    /// invalid sequences replaced, like [`String::from_utf8_lossy`].
+    #[must_use]
+    pub fn from_utf8_lossy(bytes: Bytes) -> Self {
+        match String::from_utf8_lossy(&bytes) {
+            Cow::Borrowed(_) => Self(bytes),
+            Cow::Owned(replaced) => Self(Bytes::from(replaced)),
+        }
+    }
+
+    /// Returns the part of this string `subset` refers to, sharing its buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `subset` isn't contained within this string.
+    #[must_use]
+    pub fn slice_ref(&self, subset: &str) -> Self {
+        Self(self.0.slice_ref(subset.as_bytes()))
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        // SAFETY: every constructor only accepts valid UTF-8, and `slice_ref` can only be given
+        // a `&str`, so always splits on a character boundary
+        unsafe { std::str::from_utf8_unchecked(&self.0) }
+    }
+
+    #[must_use]
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for ByteStr {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for ByteStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for ByteStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// hashed as a `str` rather than as bytes, so it can be looked up by one through `Borrow`
+impl Hash for ByteStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl Debug for ByteStr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for ByteStr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl From<String> for ByteStr {
+    fn from(value: String) -> Self {
+        Self(Bytes::from(value))
+    }
+}
+
+impl From<&str> for ByteStr {
+    fn from(value: &str) -> Self {
+        Self(Bytes::copy_from_slice(value.as_bytes()))
+    }
+}
+
+impl From<Box<str>> for ByteStr {
+    fn from(value: Box<str>) -> Self {
+        Self::from(String::from(value))
+    }
+}
+
+impl From<Cow<'_, str>> for ByteStr {
+    fn from(value: Cow<'_, str>) -> Self {
+        match value {
+            Cow::Borrowed(value) => Self::from(value),
+            Cow::Owned(value) => Self::from(value),
+        }
+    }
+}
+
+impl PartialEq<str> for ByteStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ByteStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for ByteStr {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl Serialize for ByteStr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::ByteStr;
+
+    #[test]
+    fn shares_buffer() {
+        let bytes = Bytes::from("uname -a");
+        let command = ByteStr::from_utf8_lossy(bytes.clone());
+        assert_eq!(command.as_ptr(), bytes.as_ptr());
+
+        let arg = command.slice_ref(&command[6..]);
+        assert_eq!(arg, "-a");
+        assert_eq!(arg.as_ptr(), bytes[6..].as_ptr());
+    }
+
+    #[test]
+    fn replaces_invalid_utf8() {
+        let command = ByteStr::from_utf8_lossy(Bytes::from_static(b"echo \xff"));
+        assert_eq!(command, "echo \u{fffd}");
+    }
+
+    #[test]
+    fn serialises_as_string() {
+        let command = ByteStr::from_static("cat /etc/passwd");
+        let json = serde_json::to_string(&command).unwrap();
+        assert_eq!(json, r#""cat /etc/passwd""#);
+        assert_eq!(serde_json::from_str::<ByteStr>(&json).unwrap(), command);
+    }
+}
@@ -0,0 +1,63 @@
+//! Serializes audit logs as newline-delimited JSON, the format they're written to disk and sent
+//! to the audit socket in.
+
+use crate::audit::AuditLog;
+
+/// The most space to keep hold of between logs. Logs containing large uploads can be several
+/// megabytes, which isn't worth holding on to for the rest of the process' lifetime.
+const MAX_RETAINED: usize = 64 * 1024;
+
+/// Serializes logs into a single buffer that's reused between them, rather than allocating and
+/// growing a new one for each log.
+#[derive(Default)]
+pub struct AuditEncoder {
+    buf: Vec<u8>,
+}
+
+impl AuditEncoder {
+    /// Serializes `log`, followed by a newline. The returned slice is only valid until the next
+    /// log is encoded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `log` can't be serialized.
+    pub fn encode(&mut self, log: &AuditLog) -> serde_json::Result<&[u8]> {
+        self.buf.clear();
+        self.buf.shrink_to(MAX_RETAINED);
+
+        serde_json::to_writer(&mut self.buf, log)?;
+        self.buf.push(b'\n');
+
+        Ok(&self.buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::{
+        audit::{AuditLog, AuditLogAction, TranscriptEvent},
+        encode::AuditEncoder,
+    };
+
+    #[test]
+    fn reused_buffer_matches_serializing_each_log() {
+        let mut encoder = AuditEncoder::default();
+
+        // big enough that the buffer is shrunk between some of them
+        for i in [0, 8, 64, 4096, 8, 0] {
+            let mut log = AuditLog::default();
+            for _ in 0..i {
+                log.push_action(AuditLogAction::Transcript(TranscriptEvent {
+                    data: Bytes::from(vec![b'a'; i]),
+                }));
+            }
+
+            let mut expected = serde_json::to_vec(&log).unwrap();
+            expected.push(b'\n');
+
+            assert_eq!(encoder.encode(&log).unwrap(), expected);
+        }
+    }
+}
@@ -2,3 +2,6 @@
 #![allow(clippy::module_name_repetitions)]
 
 pub mod audit;
+pub mod byte_str;
+pub mod encode;
+pub mod upgrade;
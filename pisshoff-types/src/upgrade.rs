@@ -0,0 +1,155 @@
+//! Upgrades audit logs written in older versions of the format to the current one before they're
+//! parsed, so logs already on disk, or still being sent by servers that haven't been updated yet,
+//! can be ingested alongside new ones.
+
+use std::fmt::{Display, Formatter};
+
+use serde_json::Value;
+
+use crate::audit::{AuditLog, AUDIT_LOG_VERSION};
+
+/// Upgrades from each version to the next, indexed by the version being upgraded from.
+const UPGRADES: &[fn(&mut Value)] = &[v0_to_v1];
+
+// every version needs a way up to the one after it
+const _: () = assert!(UPGRADES.len() == AUDIT_LOG_VERSION as usize);
+
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// The line isn't JSON, or doesn't match the current format once upgraded.
+    Json(serde_json::Error),
+    /// The log's version isn't a number.
+    InvalidVersion(Value),
+    /// The log was written by a newer server than this one understands.
+    Unsupported(u64),
+}
+
+impl Display for UpgradeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(e) => e.fmt(f),
+            Self::InvalidVersion(version) => write!(f, "invalid audit log version {version}"),
+            Self::Unsupported(version) => write!(
+                f,
+                "audit log version {version} is newer than the latest supported, {AUDIT_LOG_VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpgradeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            Self::InvalidVersion(_) | Self::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for UpgradeError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+/// Parses a single line of an audit log, in any version of the format up to the current one.
+///
+/// # Errors
+///
+/// Returns an error if the line isn't an audit log, or is one from a newer version of the format.
+pub fn parse(line: &str) -> Result<AuditLog, UpgradeError> {
+    let mut log: Value = serde_json::from_str(line)?;
+
+    let version = match log.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| UpgradeError::InvalidVersion(version.clone()))?,
+        None => 0,
+    };
+
+    let version = usize::try_from(version)
+        .ok()
+        .filter(|v| *v <= UPGRADES.len())
+        .ok_or(UpgradeError::Unsupported(version))?;
+
+    for upgrade in &UPGRADES[version..] {
+        upgrade(&mut log);
+    }
+
+    log["version"] = AUDIT_LOG_VERSION.into();
+
+    Ok(serde_json::from_value(log)?)
+}
+
+/// Every action of type `ty` in the log.
+fn actions<'a>(log: &'a mut Value, ty: &'a str) -> impl Iterator<Item = &'a mut Value> + 'a {
+    log.get_mut("events")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|event| event.get_mut("action"))
+        .filter(move |action| action.get("type").and_then(Value::as_str) == Some(ty))
+}
+
+/// Executed commands were only recorded as the words they were split into, before the command
+/// exactly as sent was added alongside them. Joining the words back up is the closest there is.
+fn v0_to_v1(log: &mut Value) {
+    for action in actions(log, "exec-command") {
+        if action.get("command").is_some() {
+            continue;
+        }
+
+        let command = action
+            .get("args")
+            .and_then(Value::as_array)
+            .map(|args| {
+                args.iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+
+        action["command"] = command.into();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        audit::{AuditLogAction, AUDIT_LOG_VERSION},
+        upgrade::parse,
+    };
+
+    const V0: &str = r#"{"connection_id":"00000000-0000-0000-0000-000000000000","ts":"2023-06-01T12:00:00Z","peer_address":"127.0.0.1:1234","host":"test","events":[{"start_offset":{"secs":1,"nanos":0},"action":{"type":"exec-command","args":["uname","-a"]}}]}"#;
+
+    #[test]
+    fn upgrades_v0() {
+        let log = parse(V0).unwrap();
+        assert_eq!(log.version, AUDIT_LOG_VERSION);
+
+        let AuditLogAction::ExecCommand(event) = &log.events[0].action else {
+            panic!("expected exec-command, got {:?}", log.events[0].action);
+        };
+        assert_eq!(&*event.command, "uname -a");
+    }
+
+    #[test]
+    fn keeps_current_logs() {
+        let line = V0.replace(r#""args""#, r#""command":"uname  -a","args""#);
+        let line = line.replacen('{', &format!(r#"{{"version":{AUDIT_LOG_VERSION},"#), 1);
+
+        let log = parse(&line).unwrap();
+
+        let AuditLogAction::ExecCommand(event) = &log.events[0].action else {
+            panic!("expected exec-command, got {:?}", log.events[0].action);
+        };
+        assert_eq!(&*event.command, "uname  -a");
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let line = V0.replacen('{', r#"{"version":4294967295,"#, 1);
+        assert!(parse(&line).is_err());
+    }
+}